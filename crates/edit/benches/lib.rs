@@ -70,6 +70,19 @@ fn bench_buffer(c: &mut Criterion) {
         buf
     };
 
+    let bench_piece_tree = || {
+        let mut buf = buffer::PieceTree::new().unwrap();
+        buf.replace(0..usize::MAX, data.start_content.as_bytes());
+
+        for t in &data.txns {
+            for p in &t.patches {
+                buf.replace(p.0..p.0 + p.1, p.2.as_bytes());
+            }
+        }
+
+        buf
+    };
+
     let bench_text_buffer = || {
         let mut tb = buffer::TextBuffer::new(false).unwrap();
         tb.set_crlf(false);
@@ -91,6 +104,12 @@ fn bench_buffer(c: &mut Criterion) {
         buf.extract_raw(0..usize::MAX, &mut actual, 0);
         assert_eq!(actual, data.end_content.as_bytes());
     }
+    {
+        let buf = bench_piece_tree();
+        let mut actual = Vec::new();
+        buf.extract_raw(0..usize::MAX, &mut actual, 0);
+        assert_eq!(actual, data.end_content.as_bytes());
+    }
     {
         let mut tb = bench_text_buffer();
         let mut actual = String::new();
@@ -102,6 +121,9 @@ fn bench_buffer(c: &mut Criterion) {
         .bench_function(BenchmarkId::new("GapBuffer", "rustcode"), |b| {
             b.iter(bench_gap_buffer);
         })
+        .bench_function(BenchmarkId::new("PieceTree", "rustcode"), |b| {
+            b.iter(bench_piece_tree);
+        })
         .bench_function(BenchmarkId::new("TextBuffer", "rustcode"), |b| {
             b.iter(bench_text_buffer);
         });
@@ -144,7 +144,7 @@ pub fn generate(definitions: &str) -> String {
 pub enum LocId {{",
         );
 
-        for (k, _) in translations.iter() {
+        for k in translations.keys() {
             _ = writeln!(out, "    {k},");
         }
 
@@ -181,7 +181,7 @@ const LANGUAGES: &[(&str, LangId)] = &[
             "\
 ];
 
-const TRANSLATIONS: [[&str; {}]; {}] = [
+static TRANSLATIONS: [[&str; {}]; {}] = [
 ",
             translations.len(),
             languages.len(),
@@ -189,7 +189,7 @@ const TRANSLATIONS: [[&str; {}]; {}] = [
 
         for lang in &languages {
             _ = writeln!(out, "    [");
-            for (_, v) in translations.iter() {
+            for v in translations.values() {
                 const DEFAULT: &String = &String::new();
                 let v = v.get(lang).or_else(|| v.get("en")).unwrap_or(DEFAULT);
                 _ = writeln!(out, "        {v:?},");
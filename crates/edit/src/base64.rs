@@ -3,8 +3,6 @@
 
 //! Base64 facilities.
 
-use stdext::arena::ArenaString;
-
 const CHARSET: [u8; 64] = *b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
 
 /// One aspect of base64 is that the encoded length can be
@@ -15,7 +13,7 @@ pub fn encode_len(src_len: usize) -> usize {
 }
 
 /// Encodes the given bytes as base64 and appends them to the destination string.
-pub fn encode(dst: &mut ArenaString, src: &[u8]) {
+pub fn encode(dst: &mut String, src: &[u8]) {
     unsafe {
         let mut inp = src.as_ptr();
         let mut remaining = src.len();
@@ -77,17 +75,56 @@ pub fn encode(dst: &mut ArenaString, src: &[u8]) {
     }
 }
 
+/// Decodes standard base64 with optional padding.
+/// Returns `None` if `src` contains anything but base64 characters.
+pub fn decode(src: &[u8]) -> Option<Vec<u8>> {
+    let src = src.strip_suffix(b"==").or_else(|| src.strip_suffix(b"=")).unwrap_or(src);
+    let mut dst = Vec::with_capacity(src.len() / 4 * 3 + 2);
+    let mut acc = 0u32;
+    let mut bits = 0;
+
+    for &ch in src {
+        let val = match ch {
+            b'A'..=b'Z' => ch - b'A',
+            b'a'..=b'z' => ch - b'a' + 26,
+            b'0'..=b'9' => ch - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            _ => return None,
+        };
+        acc = (acc << 6) | val as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            dst.push((acc >> bits) as u8);
+        }
+    }
+
+    Some(dst)
+}
+
 #[cfg(test)]
 mod tests {
-    use stdext::arena::{Arena, ArenaString};
+    use super::{decode, encode};
 
-    use super::encode;
+    #[test]
+    fn test_decode() {
+        assert_eq!(decode(b"").as_deref(), Some(&b""[..]));
+        assert_eq!(decode(b"YQ==").as_deref(), Some(&b"a"[..]));
+        assert_eq!(decode(b"YWI=").as_deref(), Some(&b"ab"[..]));
+        assert_eq!(decode(b"YWJj").as_deref(), Some(&b"abc"[..]));
+        assert_eq!(decode(b"YWJjZA").as_deref(), Some(&b"abcd"[..]));
+        assert_eq!(
+            decode(b"YWJjZGVmZ2hpamtsbU5PUFFSU1RVVldYWVo=").as_deref(),
+            Some(&b"abcdefghijklmNOPQRSTUVWXYZ"[..])
+        );
+        assert_eq!(decode(b"YW?j"), None);
+    }
 
     #[test]
     fn test_basic() {
-        let arena = Arena::new(4 * 1024).unwrap();
         let enc = |s: &[u8]| {
-            let mut dst = ArenaString::new_in(&arena);
+            let mut dst = String::new();
             encode(&mut dst, s);
             dst
         };
@@ -0,0 +1,136 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Saves dirty documents after a period of inactivity.
+//!
+//! Autosave only ever writes to a file the user already chose to write to
+//! (i.e. it has a path). If the file was changed by someone else since we
+//! last read or wrote it, we don't overwrite it. Instead, the contents go
+//! into a recovery swap file next to it (`.name.swp`), which is removed again
+//! once the document gets saved normally.
+//!
+//! Backups are made as configured (see [`crate::backup`]), but only by the first
+//! autosave after the file was opened or saved by the user.
+
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use edit::apperr;
+
+use crate::backup::Backup;
+use crate::documents::Document;
+use crate::save::{SaveError, SaveStage};
+use crate::state::State;
+
+/// How much longer to wait while power saving.
+const POWER_SAVING_DELAY_FACTOR: u32 = 4;
+
+pub struct Autosave {
+    /// How long to wait after the last input. Zero disables autosave.
+    pub delay: Duration,
+    /// Set while the battery runs low. See [`crate::power`].
+    pub power_saving: bool,
+    last_input: Instant,
+    pending: bool,
+}
+
+impl Autosave {
+    pub fn new() -> Self {
+        Self {
+            delay: Duration::ZERO,
+            power_saving: false,
+            last_input: Instant::now(),
+            pending: false,
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.delay.is_zero()
+    }
+
+    /// Restarts the idle timer. Call this whenever the user does something.
+    pub fn notify_input(&mut self) {
+        self.last_input = Instant::now();
+        self.pending = true;
+    }
+
+    /// How long the main loop may block waiting for input before an autosave is due.
+    pub fn read_timeout(&self) -> Duration {
+        if self.is_enabled() && self.pending {
+            self.effective_delay().saturating_sub(self.last_input.elapsed())
+        } else {
+            Duration::MAX
+        }
+    }
+
+    pub fn is_due(&self) -> bool {
+        self.is_enabled() && self.pending && self.last_input.elapsed() >= self.effective_delay()
+    }
+
+    fn effective_delay(&self) -> Duration {
+        if self.power_saving {
+            self.delay.saturating_mul(POWER_SAVING_DELAY_FACTOR)
+        } else {
+            self.delay
+        }
+    }
+}
+
+/// Saves all dirty documents that have autosave enabled.
+/// Returns the errors that occurred, if any.
+pub fn autosave_documents(state: &mut State) -> Vec<SaveError> {
+    state.autosave.pending = false;
+
+    let mut errors = Vec::new();
+    for doc in state.documents.iter_mut() {
+        if let Err(err) = autosave_document(doc, &state.backup) {
+            errors.push(err);
+        }
+    }
+    errors
+}
+
+fn autosave_document(doc: &mut Document, backup: &Backup) -> Result<(), SaveError> {
+    {
+        let tb = doc.buffer.borrow();
+        if !doc.autosave || tb.is_read_only() || !tb.is_dirty() {
+            return Ok(());
+        }
+    }
+
+    // Untitled documents have no file that we could write to.
+    let Some(path) = doc.path.clone() else {
+        return Ok(());
+    };
+
+    if doc.is_changed_on_disk() {
+        return write_swap_file(doc, &path).map_err(SaveError::at(SaveStage::Write));
+    }
+
+    // The first autosave backs up the version that the user opened or saved themselves.
+    // The ones after it would only back up other autosaves and rotate that backup away.
+    let no_backup = Backup::default();
+    let backup = if doc.autosave_backed_up { &no_backup } else { backup };
+    doc.save(None, backup)?;
+    doc.autosave_backed_up = true;
+    remove_swap_file(&path);
+    Ok(())
+}
+
+fn write_swap_file(doc: &Document, path: &Path) -> apperr::Result<()> {
+    let mut file = File::create(swap_path(path))?;
+    doc.buffer.borrow().write_file_copy(&mut file)
+}
+
+/// Removes the recovery swap file for `path`, if there's one.
+pub fn remove_swap_file(path: &Path) {
+    _ = fs::remove_file(swap_path(path));
+}
+
+fn swap_path(path: &Path) -> PathBuf {
+    let mut name = std::ffi::OsString::from(".");
+    name.push(path.file_name().unwrap_or_default());
+    name.push(".swp");
+    path.with_file_name(name)
+}
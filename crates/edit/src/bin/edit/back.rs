@@ -0,0 +1,113 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Lets Esc act like Android's back button, which Termux sends as Esc.
+//!
+//! Esc first cancels whatever is going on: A dialog, the menu, a selection,
+//! the search bar, a pending chord or Vim command. Only when there's nothing
+//! left to cancel does it quit, depending on the [`BackPolicy`]. Quitting
+//! goes through the usual prompt for documents with unsaved changes.
+
+use std::time::{Duration, Instant};
+
+/// How long the second press of [`BackPolicy::Double`] may take.
+pub const BACK_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BackPolicy {
+    /// Esc never quits.
+    Off,
+    /// A single Esc quits.
+    Single,
+    /// Esc quits when pressed twice in a row, so that a stray back gesture does nothing.
+    Double,
+}
+
+pub struct Back {
+    policy: BackPolicy,
+    /// Set if the user chose the policy.
+    configured: bool,
+    /// When the first press of a double press happened.
+    armed: Option<Instant>,
+}
+
+impl Back {
+    pub fn new() -> Self {
+        Self { policy: BackPolicy::Off, configured: false, armed: None }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.policy != BackPolicy::Off
+    }
+
+    pub fn set_policy(&mut self, policy: BackPolicy) {
+        self.policy = policy;
+        self.configured = true;
+    }
+
+    /// Quits on a double press, unless the user chose otherwise.
+    pub fn enable_by_default(&mut self) {
+        if !self.configured {
+            self.policy = BackPolicy::Double;
+        }
+    }
+
+    /// Call this when Esc had nothing left to cancel. Returns whether to quit.
+    pub fn press(&mut self) -> bool {
+        match self.policy {
+            BackPolicy::Off => false,
+            BackPolicy::Single => true,
+            BackPolicy::Double => {
+                if self.is_armed() {
+                    self.armed = None;
+                    true
+                } else {
+                    self.armed = Some(Instant::now());
+                    false
+                }
+            }
+        }
+    }
+
+    /// Whether the next press quits. The UI should say so.
+    pub fn is_armed(&self) -> bool {
+        self.armed.is_some_and(|t| t.elapsed() < BACK_TIMEOUT)
+    }
+
+    /// How long the main loop may block waiting for input before the hint has to go away.
+    pub fn read_timeout(&self) -> Duration {
+        match self.armed {
+            Some(t) if self.is_armed() => BACK_TIMEOUT.saturating_sub(t.elapsed()),
+            _ => Duration::MAX,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_press() {
+        let mut back = Back::new();
+        assert!(!back.press());
+
+        back.enable_by_default();
+        assert_eq!(back.policy, BackPolicy::Double);
+        assert!(!back.press());
+        assert!(back.is_armed());
+        assert!(back.press());
+        assert!(!back.is_armed());
+
+        // The second press came too late.
+        assert!(!back.press());
+        back.armed = Some(Instant::now() - BACK_TIMEOUT);
+        assert!(!back.is_armed());
+        assert_eq!(back.read_timeout(), Duration::MAX);
+        assert!(!back.press());
+
+        back.set_policy(BackPolicy::Single);
+        back.enable_by_default();
+        assert!(back.press());
+    }
+}
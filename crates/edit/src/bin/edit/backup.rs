@@ -0,0 +1,226 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Copies the previous version of a file aside before it gets overwritten.
+
+use std::ffi::OsString;
+use std::fs::{self, File, FileTimes};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use edit::{apperr, hash};
+
+const DATE_PLACEHOLDER: &str = "{date}";
+const DIR_PLACEHOLDER: &str = "{dir}";
+const NAME_PLACEHOLDER: &str = "{name}";
+/// The length of the timestamps written by [`format_utc_timestamp`], e.g. `20251015-123456-789`.
+const DATE_LEN: usize = 19;
+
+#[derive(Default, Clone, PartialEq, Eq)]
+pub enum BackupMode {
+    #[default]
+    None,
+    /// Copy `file` to `file~`.
+    Tilde,
+    /// Copy `file` into the given directory using [`Backup::pattern`].
+    Directory(PathBuf),
+}
+
+pub struct Backup {
+    pub mode: BackupMode,
+    /// The filename used in [`BackupMode::Directory`].
+    /// `{name}` expands to the original filename, `{dir}` to a hash of its parent directory
+    /// and `{date}` to the current UTC time, down to the millisecond.
+    pub pattern: String,
+    /// How many backups of each file to keep in [`BackupMode::Directory`]. 0 keeps all of them.
+    pub keep: usize,
+}
+
+impl Default for Backup {
+    fn default() -> Self {
+        Self {
+            mode: BackupMode::None,
+            pattern: format!("{NAME_PLACEHOLDER}.{DIR_PLACEHOLDER}.{DATE_PLACEHOLDER}"),
+            keep: 10,
+        }
+    }
+}
+
+impl Backup {
+    /// Copies `path` aside according to the configured mode.
+    /// Does nothing if the file doesn't exist yet.
+    pub fn create(&self, path: &Path) -> apperr::Result<()> {
+        let target = match &self.mode {
+            BackupMode::None => return Ok(()),
+            _ if !path.is_file() => return Ok(()),
+            BackupMode::Tilde => {
+                let mut target = path.as_os_str().to_owned();
+                target.push("~");
+                PathBuf::from(target)
+            }
+            BackupMode::Directory(dir) => {
+                fs::create_dir_all(dir)?;
+                let expanded = self.expand_name(path);
+                let mut time = SystemTime::now();
+                loop {
+                    let date = format_utc_timestamp(time);
+                    let target = dir.join(expanded.replace(DATE_PLACEHOLDER, &date));
+                    // Two saves within the same millisecond (or a pattern without `{date}`)
+                    // would otherwise overwrite the previous backup.
+                    if !target.exists() || !self.pattern.contains(DATE_PLACEHOLDER) {
+                        break target;
+                    }
+                    time += Duration::from_millis(1);
+                }
+            }
+        };
+
+        copy_preserving_times(path, &target)?;
+
+        if let BackupMode::Directory(dir) = &self.mode {
+            self.prune(dir, path);
+        }
+
+        Ok(())
+    }
+
+    /// Expands all placeholders in [`Backup::pattern`] except for `{date}`.
+    fn expand_name(&self, path: &Path) -> String {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        // Files with the same name in different directories must not share their backups.
+        let parent = path.parent().unwrap_or(Path::new(""));
+        let parent = fs::canonicalize(parent).unwrap_or_else(|_| parent.to_path_buf());
+        let dir = format!("{:016x}", hash::hash(0, parent.as_os_str().as_encoded_bytes()));
+        self.pattern.replace(NAME_PLACEHOLDER, &name).replace(DIR_PLACEHOLDER, &dir)
+    }
+
+    /// Deletes the oldest backups of `path` in `dir`, keeping the newest [`Backup::keep`].
+    fn prune(&self, dir: &Path, path: &Path) {
+        if self.keep == 0 || !self.pattern.contains(DATE_PLACEHOLDER) {
+            return;
+        }
+
+        let expanded = self.expand_name(path);
+        let Some((prefix, suffix)) = expanded.split_once(DATE_PLACEHOLDER) else {
+            return;
+        };
+
+        let Ok(entries) = fs::read_dir(dir) else {
+            return;
+        };
+        let mut backups: Vec<OsString> = entries
+            .flatten()
+            .map(|e| e.file_name())
+            .filter(|n| {
+                // Only names that are exactly `prefix + date + suffix` belong to `path`.
+                // Otherwise, saving `foo` would prune the backups of `foo.bar`.
+                n.to_str()
+                    .and_then(|n| n.strip_prefix(prefix))
+                    .and_then(|n| n.strip_suffix(suffix))
+                    .is_some_and(is_timestamp)
+            })
+            .collect();
+
+        // The timestamps sort lexicographically, so the oldest backups come first.
+        backups.sort();
+
+        let excess = backups.len().saturating_sub(self.keep);
+        for name in &backups[..excess] {
+            _ = fs::remove_file(dir.join(name));
+        }
+    }
+}
+
+/// Copies `src` to `dst`, including permissions and modification time.
+/// Unlike a rename this works across filesystems.
+fn copy_preserving_times(src: &Path, dst: &Path) -> apperr::Result<()> {
+    let metadata = fs::metadata(src)?;
+    fs::copy(src, dst)?;
+
+    let mut times = FileTimes::new();
+    if let Ok(t) = metadata.accessed() {
+        times = times.set_accessed(t);
+    }
+    if let Ok(t) = metadata.modified() {
+        times = times.set_modified(t);
+    }
+    // Not all filesystems support this (e.g. some FUSE mounts). It's not worth failing over.
+    _ = File::options().write(true).open(dst).and_then(|f| f.set_times(times));
+
+    Ok(())
+}
+
+/// Formats `time` as `YYYYMMDD-hhmmss-mmm` in UTC.
+fn format_utc_timestamp(time: SystemTime) -> String {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = since_epoch.as_secs();
+    let millis = since_epoch.subsec_millis();
+    let days = (secs / 86400) as i64;
+    let rem = secs % 86400;
+
+    // Converts days since 1970-01-01 into a civil date.
+    // See: https://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + (month <= 2) as i64;
+
+    format!(
+        "{year:04}{month:02}{day:02}-{:02}{:02}{:02}-{millis:03}",
+        rem / 3600,
+        rem / 60 % 60,
+        rem % 60
+    )
+}
+
+/// Returns `true` if `s` has the exact shape of a [`format_utc_timestamp`] result.
+fn is_timestamp(s: &str) -> bool {
+    s.len() == DATE_LEN
+        && s.bytes().enumerate().all(|(i, b)| match i {
+            8 | 15 => b == b'-',
+            _ => b.is_ascii_digit(),
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_utc_timestamp() {
+        let t = |millis| format_utc_timestamp(UNIX_EPOCH + Duration::from_millis(millis));
+        assert_eq!(t(0), "19700101-000000-000");
+        assert_eq!(t(951_782_400_000), "20000229-000000-000");
+        assert_eq!(t(1_760_531_696_789), "20251015-123456-789");
+    }
+
+    #[test]
+    fn test_prune_keeps_other_files() {
+        let root = std::env::temp_dir().join(format!("edit-test-backup-{}", std::process::id()));
+        let dir = root.join("backups");
+        let (a, b) = (root.join("a"), root.join("b"));
+        for d in [&dir, &a, &b] {
+            fs::create_dir_all(d).unwrap();
+        }
+        for p in [a.join("foo"), b.join("foo"), a.join("foo.bar")] {
+            fs::write(p, "x").unwrap();
+        }
+
+        let backup =
+            Backup { mode: BackupMode::Directory(dir.clone()), keep: 2, ..Default::default() };
+        backup.create(&b.join("foo")).unwrap();
+        backup.create(&a.join("foo.bar")).unwrap();
+        for _ in 0..3 {
+            backup.create(&a.join("foo")).unwrap();
+        }
+
+        // 2 of `a/foo` (none lost to name collisions), 1 each of `b/foo` and `a/foo.bar`.
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 4);
+        _ = fs::remove_dir_all(&root);
+    }
+}
@@ -0,0 +1,253 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! `edit --batch SCRIPT FILE...` runs a script on each of the files, without
+//! the terminal. The files are opened as they would be otherwise, so `--encoding=`,
+//! `+line` and the like apply, and the commands work on them the way the
+//! editor's own do:
+//!
+//! ```text
+//! # Comments and empty lines are skipped.
+//! open FILE               # Continue with FILE instead, relative to the working directory.
+//! find /PATTERN/FLAGS     # Select the next match. It's an error if there's none.
+//! replace /PATTERN/TEXT/FLAGS
+//!                         # Replace all matches.
+//! sort                    # Sort the lines.
+//! save [FILE]             # Save the file, or save it as FILE.
+//! ```
+//!
+//! Any character can delimit the pattern, as in `sed`. The flags are `i` to ignore
+//! case, `w` for whole words and `r` for a regex. Nothing is saved unless the
+//! script says so, except with `-` and a redirected stdout, which gets the result.
+
+use std::io::{self, Write as _};
+use std::path::Path;
+use std::process::ExitCode;
+use std::{env, fs};
+
+use edit::buffer::{RcTextBuffer, SearchOptions};
+use edit::sys;
+
+use crate::state::{FormatApperr, State};
+
+#[derive(Debug, PartialEq, Eq)]
+enum Command<'a> {
+    Open(&'a str),
+    Find(&'a str, SearchOptions),
+    Replace(&'a str, &'a str, SearchOptions),
+    Sort,
+    Save(Option<&'a str>),
+}
+
+/// Runs the script at `path` on each of the documents from the command line.
+pub fn run(state: &mut State, path: &Path) -> ExitCode {
+    match run_script(state, path) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(msg) => {
+            sys::write_stdout(&format!("{msg}\n"));
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run_script(state: &mut State, path: &Path) -> Result<(), String> {
+    let script = fs::read_to_string(path).map_err(|err| format!("{}: {err}", path.display()))?;
+    let commands = script
+        .lines()
+        .enumerate()
+        .filter_map(|(i, line)| {
+            let line = line.trim();
+            let cmd = (!line.is_empty() && !line.starts_with('#')).then(|| parse(line))?;
+            Some(cmd.map(|cmd| (i + 1, cmd)).map_err(|msg| (i + 1, msg)))
+        })
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|(line, msg)| format!("{}:{line}: {msg}", path.display()))?;
+
+    // In the order they were given. Without any, `open` must pick one.
+    let mut buffers: Vec<RcTextBuffer> =
+        state.documents.iter().map(|doc| doc.buffer.clone()).collect();
+    buffers.reverse();
+    if buffers.is_empty() {
+        buffers.push(
+            state.documents.add_untitled().map_err(|err| format_apperr(path, err))?.buffer.clone(),
+        );
+    }
+
+    for buffer in buffers {
+        state.documents.update_active(|doc| RcTextBuffer::ptr_eq(&doc.buffer, &buffer));
+        for (line, cmd) in &commands {
+            execute(state, cmd).map_err(|msg| format!("{}:{line}: {msg}", path.display()))?;
+        }
+    }
+
+    if let Some(buffer) = state.stdout_buffer.take() {
+        let mut stdout = io::stdout().lock();
+        buffer
+            .borrow()
+            .write_file_copy(&mut stdout)
+            .and_then(|()| stdout.flush().map_err(Into::into))
+            .map_err(|err| format_apperr(path, err))?;
+    }
+    Ok(())
+}
+
+fn format_apperr(path: &Path, err: edit::apperr::Error) -> String {
+    format!("{}: {}", path.display(), FormatApperr::from(err))
+}
+
+fn parse(line: &str) -> Result<Command<'_>, String> {
+    let (name, arg) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+    let arg = arg.trim();
+    let cmd = match name {
+        "open" if !arg.is_empty() => Command::Open(arg),
+        "find" => {
+            let [pattern, flags] = split_delimited(arg).ok_or("expected `find /PATTERN/FLAGS`")?;
+            Command::Find(pattern, parse_flags(flags)?)
+        }
+        "replace" => {
+            let [pattern, text, flags] =
+                split_delimited(arg).ok_or("expected `replace /PATTERN/TEXT/FLAGS`")?;
+            Command::Replace(pattern, text, parse_flags(flags)?)
+        }
+        "sort" if arg.is_empty() => Command::Sort,
+        "save" => Command::Save((!arg.is_empty()).then_some(arg)),
+        "open" | "sort" => return Err(format!("wrong arguments for `{name}`")),
+        _ => return Err(format!("unknown command `{name}`")),
+    };
+    Ok(cmd)
+}
+
+/// Splits `/a/b/flags` into `["a", "b", "flags"]`. The first character is the delimiter.
+fn split_delimited<const N: usize>(arg: &str) -> Option<[&str; N]> {
+    let delim = arg.chars().next().filter(|c| !c.is_alphanumeric())?;
+    let mut parts = arg[delim.len_utf8()..].splitn(N, delim);
+    let parts = [(); N].map(|()| parts.next());
+    if parts.contains(&None) || parts[0] == Some("") {
+        return None;
+    }
+    Some(parts.map(Option::unwrap))
+}
+
+fn parse_flags(flags: &str) -> Result<SearchOptions, String> {
+    let mut options = SearchOptions { match_case: true, ..Default::default() };
+    for flag in flags.chars() {
+        match flag {
+            'i' => options.match_case = false,
+            'w' => options.whole_word = true,
+            'r' => options.use_regex = true,
+            _ => return Err(format!("unknown flag `{flag}`")),
+        }
+    }
+    Ok(options)
+}
+
+fn execute(state: &mut State, cmd: &Command) -> Result<(), String> {
+    if let Command::Open(file) = cmd {
+        let path = env::current_dir().map_err(|err| err.to_string())?.join(file);
+        let doc = state.documents.add_file_path(&path).map_err(apperr_string)?;
+        doc.buffer.borrow_mut().set_read_only(state.open_read_only);
+        return Ok(());
+    }
+
+    let Some(doc) = state.documents.active_mut() else {
+        return Err("no file".to_string());
+    };
+    let mut tb = doc.buffer.borrow_mut();
+    if tb.is_read_only() && !matches!(cmd, Command::Find(..)) {
+        return Err(apperr_string(edit::apperr::APP_READ_ONLY));
+    }
+
+    match *cmd {
+        Command::Open(_) => unreachable!(),
+        Command::Find(pattern, options) => match tb.find_and_select(pattern, options) {
+            Ok(Some((n, _))) if n > 0 => {}
+            Ok(_) => return Err(format!("`{pattern}` not found")),
+            Err(err) => return Err(apperr_string(err)),
+        },
+        Command::Replace(pattern, text, options) => {
+            tb.find_and_replace_all(pattern, options, text.as_bytes()).map_err(apperr_string)?;
+        }
+        Command::Sort => {
+            tb.select_all();
+            let text = tb.extract_user_selection(false).unwrap_or_default();
+            tb.write_raw(&sort_lines(&text));
+        }
+        Command::Save(file) => {
+            drop(tb);
+            // It's written to stdout at the end.
+            let to_stdout =
+                state.stdout_buffer.as_ref().is_some_and(|b| RcTextBuffer::ptr_eq(b, &doc.buffer));
+            let path = match file {
+                None if to_stdout => return Ok(()),
+                Some(file) => Some(env::current_dir().map_err(|err| err.to_string())?.join(file)),
+                None if doc.path.is_none() => return Err("no file name to save to".to_string()),
+                None => None,
+            };
+            doc.save(path, &state.backup).map_err(|err| err.to_string())?;
+        }
+    }
+    Ok(())
+}
+
+fn apperr_string(err: edit::apperr::Error) -> String {
+    FormatApperr::from(err).to_string()
+}
+
+/// Sorts the lines of `text`, whatever their line endings. A final newline stays at the end.
+fn sort_lines(text: &[u8]) -> Vec<u8> {
+    let trailing = text.ends_with(b"\n");
+    let text = if trailing { &text[..text.len() - 1] } else { text };
+    let mut lines: Vec<&[u8]> =
+        text.split(|&b| b == b'\n').map(|line| line.strip_suffix(b"\r").unwrap_or(line)).collect();
+    lines.sort();
+
+    let mut sorted = lines.join(&b'\n');
+    if trailing {
+        sorted.push(b'\n');
+    }
+    sorted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let exact = SearchOptions { match_case: true, ..Default::default() };
+        assert_eq!(parse("open a b.txt"), Ok(Command::Open("a b.txt")));
+        assert_eq!(parse("find /foo/"), Ok(Command::Find("foo", exact)));
+        assert_eq!(
+            parse("replace |a/b|c|iw"),
+            Ok(Command::Replace(
+                "a/b",
+                "c",
+                SearchOptions { match_case: false, whole_word: true, use_regex: false }
+            ))
+        );
+        assert_eq!(parse("replace /a//"), Ok(Command::Replace("a", "", exact)));
+        assert_eq!(parse("save"), Ok(Command::Save(None)));
+        assert_eq!(parse("save out.txt"), Ok(Command::Save(Some("out.txt"))));
+        assert_eq!(parse("sort"), Ok(Command::Sort));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(parse("frobnicate"), Err("unknown command `frobnicate`".to_string()));
+        assert_eq!(parse("open"), Err("wrong arguments for `open`".to_string()));
+        assert_eq!(
+            parse("replace /a/b"),
+            Err("expected `replace /PATTERN/TEXT/FLAGS`".to_string())
+        );
+        assert_eq!(parse("find //"), Err("expected `find /PATTERN/FLAGS`".to_string()));
+        assert_eq!(parse("find /a/x"), Err("unknown flag `x`".to_string()));
+        assert!(parse("find abc").is_err());
+    }
+
+    #[test]
+    fn test_sort_lines() {
+        assert_eq!(sort_lines(b"c\nA\nb\n"), b"A\nb\nc\n");
+        assert_eq!(sort_lines(b"b\r\na"), b"a\nb");
+        assert_eq!(sort_lines(b""), b"");
+    }
+}
@@ -0,0 +1,245 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Multi-key shortcuts ("chords") such as Ctrl+K followed by W.
+//!
+//! On-screen keyboards offer few modifier keys, so chords let a single prefix
+//! shortcut unlock a whole set of commands. After the prefix, the next key
+//! (with or without Ctrl) picks the command. Pressing Escape, an unknown key,
+//! or waiting for [`CHORD_TIMEOUT`] cancels the chord.
+
+use std::time::{Duration, Instant};
+
+use edit::input::{InputKey, kbmod, vk};
+
+use crate::localization::{LocId, loc};
+
+pub const CHORD_TIMEOUT: Duration = Duration::from_secs(3);
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ChordAction {
+    SaveAs,
+    Close,
+    Goto,
+    GoToFile,
+    DeleteLines,
+    OpenInApp,
+    GotoThis,
+    Registers,
+    InsertCharacter,
+    Digraph,
+    InsertCodepoint,
+    JoinLines,
+    Share,
+    FocusStatusbar,
+    FocusFileTree,
+    FocusTerminal,
+    RunInTerminal,
+    Notifications,
+    ToggleWordWrap,
+    ToggleReadOnly,
+    ToggleAutosave,
+    ToggleVim,
+    ToggleMarkdownPreview,
+}
+
+pub struct ChordBinding {
+    /// The key that completes the chord. Always an uppercase ASCII letter.
+    pub key: char,
+    pub action: ChordAction,
+    pub label: LocId,
+}
+
+pub struct ChordPrefix {
+    /// The prefix is Ctrl plus this key. Always an uppercase ASCII letter.
+    pub key: char,
+    pub bindings: &'static [ChordBinding],
+}
+
+pub const CHORDS: &[ChordPrefix] = &[ChordPrefix {
+    key: 'K',
+    bindings: &[
+        ChordBinding { key: 'A', action: ChordAction::SaveAs, label: LocId::FileSaveAs },
+        ChordBinding {
+            key: 'B',
+            action: ChordAction::FocusStatusbar,
+            label: LocId::ViewFocusStatusbar,
+        },
+        ChordBinding {
+            key: 'C',
+            action: ChordAction::InsertCharacter,
+            label: LocId::EditInsertCharacter,
+        },
+        ChordBinding {
+            key: 'D',
+            action: ChordAction::DeleteLines,
+            label: LocId::SelectionDeleteLines,
+        },
+        ChordBinding {
+            key: 'E',
+            action: ChordAction::FocusFileTree,
+            label: LocId::ViewFocusFileTree,
+        },
+        ChordBinding { key: 'G', action: ChordAction::Goto, label: LocId::FileGoto },
+        ChordBinding {
+            key: 'I',
+            action: ChordAction::InsertCodepoint,
+            label: LocId::EditInsertCodepoint,
+        },
+        ChordBinding { key: 'J', action: ChordAction::JoinLines, label: LocId::SelectionJoinLines },
+        ChordBinding { key: 'K', action: ChordAction::Digraph, label: LocId::EditDigraph },
+        ChordBinding { key: 'L', action: ChordAction::GotoThis, label: LocId::ViewGotoThis },
+        ChordBinding {
+            key: 'M',
+            action: ChordAction::ToggleMarkdownPreview,
+            label: LocId::ViewMarkdownPreview,
+        },
+        ChordBinding {
+            key: 'N',
+            action: ChordAction::Notifications,
+            label: LocId::ViewNotifications,
+        },
+        ChordBinding { key: 'O', action: ChordAction::OpenInApp, label: LocId::FileOpenInApp },
+        ChordBinding { key: 'P', action: ChordAction::GoToFile, label: LocId::ViewGoToFile },
+        ChordBinding { key: 'R', action: ChordAction::ToggleReadOnly, label: LocId::ViewReadOnly },
+        ChordBinding { key: 'S', action: ChordAction::Share, label: LocId::FileShare },
+        ChordBinding {
+            key: 'T',
+            action: ChordAction::FocusTerminal,
+            label: LocId::ViewFocusTerminal,
+        },
+        ChordBinding { key: 'U', action: ChordAction::ToggleAutosave, label: LocId::FileAutosave },
+        ChordBinding { key: 'V', action: ChordAction::ToggleVim, label: LocId::ViewVimMode },
+        ChordBinding { key: 'W', action: ChordAction::Close, label: LocId::FileClose },
+        ChordBinding {
+            key: 'X',
+            action: ChordAction::RunInTerminal,
+            label: LocId::EditRunInTerminal,
+        },
+        ChordBinding { key: 'Y', action: ChordAction::Registers, label: LocId::EditRegisters },
+        ChordBinding { key: 'Z', action: ChordAction::ToggleWordWrap, label: LocId::ViewWordWrap },
+    ],
+}];
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ChordResult {
+    /// The key isn't part of a chord.
+    Unhandled,
+    /// The key started a chord.
+    Started,
+    /// The pending chord was aborted.
+    Cancelled,
+    Action(ChordAction),
+}
+
+pub struct Chords {
+    pending: Option<&'static ChordPrefix>,
+    started: Instant,
+}
+
+impl Chords {
+    pub fn new() -> Self {
+        Self { pending: None, started: Instant::now() }
+    }
+
+    /// Returns the prefix that was pressed, if a chord is in progress.
+    pub fn pending(&self) -> Option<&'static ChordPrefix> {
+        self.pending
+    }
+
+    pub fn cancel(&mut self) {
+        self.pending = None;
+    }
+
+    /// How long the main loop may block waiting for input before the pending chord expires.
+    pub fn read_timeout(&self) -> Duration {
+        match self.pending {
+            Some(_) => CHORD_TIMEOUT.saturating_sub(self.started.elapsed()),
+            None => Duration::MAX,
+        }
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.pending.is_some() && self.started.elapsed() >= CHORD_TIMEOUT
+    }
+
+    /// Starts a chord if `key` is one of the prefixes.
+    pub fn start(&mut self, key: InputKey) -> ChordResult {
+        match CHORDS.iter().find(|c| ctrl_key(c.key) == Some(key)) {
+            Some(prefix) => {
+                self.pending = Some(prefix);
+                self.started = Instant::now();
+                ChordResult::Started
+            }
+            None => ChordResult::Unhandled,
+        }
+    }
+
+    /// Completes the pending chord with `key`.
+    pub fn complete(&mut self, key: InputKey) -> ChordResult {
+        let Some(prefix) = self.pending.take() else {
+            return ChordResult::Unhandled;
+        };
+        if key == vk::ESCAPE {
+            return ChordResult::Cancelled;
+        }
+
+        // Ctrl+K Ctrl+W is the same as Ctrl+K W, because it's easy to keep holding Ctrl.
+        let key = key.key();
+        prefix
+            .bindings
+            .iter()
+            .find(|b| InputKey::from_ascii(b.key.to_ascii_lowercase()) == Some(key))
+            .map_or(ChordResult::Cancelled, |b| ChordResult::Action(b.action))
+    }
+}
+
+/// Spells out the chord that runs `action`, like "Ctrl+K W", for the menus.
+/// Empty if there's none.
+pub fn hint(action: ChordAction) -> String {
+    CHORDS
+        .iter()
+        .find_map(|prefix| {
+            let binding = prefix.bindings.iter().find(|b| b.action == action)?;
+            Some(format!("{}+{} {}", loc(LocId::Ctrl), prefix.key, binding.key))
+        })
+        .unwrap_or_default()
+}
+
+fn ctrl_key(ch: char) -> Option<InputKey> {
+    InputKey::from_ascii(ch.to_ascii_lowercase()).map(|k| k | kbmod::CTRL)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chords() {
+        let mut chords = Chords::new();
+        assert_eq!(chords.complete(vk::W), ChordResult::Unhandled);
+        assert_eq!(chords.start(kbmod::CTRL | vk::W), ChordResult::Unhandled);
+
+        assert_eq!(chords.start(kbmod::CTRL | vk::K), ChordResult::Started);
+        assert!(chords.pending().is_some());
+        assert_eq!(chords.complete(vk::W), ChordResult::Action(ChordAction::Close));
+        assert!(chords.pending().is_none());
+
+        chords.start(kbmod::CTRL | vk::K);
+        assert_eq!(
+            chords.complete(kbmod::CTRL | vk::Z),
+            ChordResult::Action(ChordAction::ToggleWordWrap)
+        );
+
+        chords.start(kbmod::CTRL | vk::K);
+        assert_eq!(chords.complete(vk::ESCAPE), ChordResult::Cancelled);
+        chords.start(kbmod::CTRL | vk::K);
+        assert_eq!(chords.complete(vk::F1), ChordResult::Cancelled);
+    }
+
+    #[test]
+    fn test_hint() {
+        assert!(hint(ChordAction::Close).ends_with("+K W"));
+        assert!(hint(ChordAction::SaveAs).ends_with("+K A"));
+    }
+}
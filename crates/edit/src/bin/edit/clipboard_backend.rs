@@ -0,0 +1,260 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Synchronizes the editor's internal clipboard with the system clipboard.
+//!
+//! The only backend so far uses the Termux:API add-on (`termux-clipboard-set`
+//! and `termux-clipboard-get`), so that copy and paste work with other Android
+//! apps. The commands run on a background thread, because they take a while
+//! and hang forever if the Termux:API app isn't installed. If one of them
+//! fails or times out, the backend disables itself and the editor keeps using
+//! its internal clipboard.
+//!
+//! Changes made in other apps are picked up when the terminal regains focus
+//! and, since not every terminal reports that, every few seconds while focused.
+
+use std::io::{Read, Write as _};
+use std::process::{Command, Stdio};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+use std::{env, iter, thread};
+
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(3);
+/// How often the system clipboard is read while the window has focus.
+const REQUEST_INTERVAL: Duration = Duration::from_secs(5);
+/// How often the main loop checks for the result of a request.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+pub trait ClipboardBackend {
+    /// Sends `data` to the system clipboard without blocking.
+    fn write(&mut self, data: &[u8]);
+
+    /// Asks for the contents of the system clipboard without blocking.
+    /// They can be retrieved with [`ClipboardBackend::poll`] once they arrive.
+    fn request(&mut self);
+
+    /// Like [`ClipboardBackend::request`], but only if the last one was a while ago.
+    fn request_if_due(&mut self);
+
+    /// Returns the contents of the system clipboard, if a request completed since the last call.
+    fn poll(&mut self) -> Option<Vec<u8>>;
+
+    /// How long the main loop may block waiting for input before checking for results
+    /// or, while `focused`, before the next [`ClipboardBackend::request_if_due`].
+    fn read_timeout(&self, focused: bool) -> Duration;
+}
+
+/// Returns the backend that fits the environment we're running in, if any.
+pub fn detect() -> Option<Box<dyn ClipboardBackend>> {
+    if env::var_os("TERMUX_VERSION").is_some() && find_in_path("termux-clipboard-get") {
+        Some(Box::new(TermuxClipboard::new()))
+    } else {
+        None
+    }
+}
+
+enum Job {
+    Set(Vec<u8>),
+    Get,
+}
+
+struct TermuxClipboard {
+    jobs: Sender<Job>,
+    results: Receiver<Vec<u8>>,
+    /// Cleared by the worker thread once a command failed.
+    available: Arc<AtomicBool>,
+    /// What we last sent, so that we neither send it twice nor read it back as a change.
+    last_written: Vec<u8>,
+    /// When the last request was sent.
+    last_request: Instant,
+    /// Whether a request hasn't been answered yet.
+    pending: bool,
+}
+
+impl TermuxClipboard {
+    fn new() -> Self {
+        let (jobs, job_receiver) = mpsc::channel();
+        let (result_sender, results) = mpsc::channel();
+        let available = Arc::new(AtomicBool::new(true));
+
+        let worker_available = available.clone();
+        thread::spawn(move || {
+            while let Ok(job) = job_receiver.recv() {
+                // Only the latest write matters, if several piled up.
+                let mut set = None;
+                let mut get = false;
+                for job in iter::once(job).chain(job_receiver.try_iter()) {
+                    match job {
+                        Job::Set(data) => set = Some(data),
+                        Job::Get => get = true,
+                    }
+                }
+
+                if let Some(data) = set {
+                    let mut cmd = Command::new("termux-clipboard-set");
+                    if run_with_timeout(&mut cmd, &data, COMMAND_TIMEOUT).is_none() {
+                        worker_available.store(false, Ordering::Relaxed);
+                        break;
+                    }
+                }
+                if get {
+                    let mut cmd = Command::new("termux-clipboard-get");
+                    let Some(output) = run_with_timeout(&mut cmd, &[], COMMAND_TIMEOUT) else {
+                        worker_available.store(false, Ordering::Relaxed);
+                        break;
+                    };
+                    if result_sender.send(output).is_err() {
+                        break;
+                    }
+                }
+            }
+        });
+
+        Self {
+            jobs,
+            results,
+            available,
+            last_written: Vec::new(),
+            last_request: Instant::now(),
+            pending: false,
+        }
+    }
+
+    fn is_available(&self) -> bool {
+        self.available.load(Ordering::Relaxed)
+    }
+}
+
+impl ClipboardBackend for TermuxClipboard {
+    fn write(&mut self, data: &[u8]) {
+        if self.is_available() && data != self.last_written {
+            self.last_written = data.to_vec();
+            _ = self.jobs.send(Job::Set(self.last_written.clone()));
+        }
+    }
+
+    fn request(&mut self) {
+        self.last_request = Instant::now();
+        if self.is_available() && !self.pending {
+            self.pending = self.jobs.send(Job::Get).is_ok();
+        }
+    }
+
+    fn request_if_due(&mut self) {
+        if self.last_request.elapsed() >= REQUEST_INTERVAL {
+            self.request();
+        }
+    }
+
+    fn poll(&mut self) -> Option<Vec<u8>> {
+        if !self.is_available() {
+            self.pending = false;
+        }
+        let data = self.results.try_iter().last()?;
+        self.pending = false;
+        if data.is_empty() || data == self.last_written {
+            return None;
+        }
+        self.last_written = data.clone();
+        Some(data)
+    }
+
+    fn read_timeout(&self, focused: bool) -> Duration {
+        if self.pending {
+            POLL_INTERVAL
+        } else if focused && self.is_available() {
+            REQUEST_INTERVAL.saturating_sub(self.last_request.elapsed())
+        } else {
+            Duration::MAX
+        }
+    }
+}
+
+/// Runs `cmd`, feeds it `input` and returns its output.
+/// Returns `None` if it couldn't be started, failed, or didn't finish within `timeout`.
+pub fn run_with_timeout(cmd: &mut Command, input: &[u8], timeout: Duration) -> Option<Vec<u8>> {
+    run_capturing_stderr(cmd, input, timeout).ok()
+}
+
+/// Like [`run_with_timeout`], but if `cmd` fails, returns what it wrote to stderr.
+/// That's empty if it wrote nothing or took too long.
+pub fn run_capturing_stderr(
+    cmd: &mut Command,
+    input: &[u8],
+    timeout: Duration,
+) -> Result<Vec<u8>, Vec<u8>> {
+    let mut child = cmd
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|err| err.to_string().into_bytes())?;
+
+    // All of them are done on separate threads, because any could block indefinitely.
+    let mut stdin = child.stdin.take().ok_or_else(Vec::new)?;
+    let input = input.to_vec();
+    thread::spawn(move || _ = stdin.write_all(&input));
+
+    let reader = read_on_thread(child.stdout.take());
+    let errors = read_on_thread(child.stderr.take());
+
+    let deadline = Instant::now() + timeout;
+    let status = loop {
+        match child.try_wait() {
+            Ok(Some(status)) => break status,
+            Ok(None) if Instant::now() < deadline => thread::sleep(Duration::from_millis(10)),
+            _ => {
+                _ = child.kill();
+                _ = child.wait();
+                return Err(Vec::new());
+            }
+        }
+    };
+
+    let output = reader.join().map_err(|_| Vec::new())?;
+    if status.success() { Ok(output) } else { Err(errors.join().unwrap_or_default()) }
+}
+
+fn read_on_thread(pipe: Option<impl Read + Send + 'static>) -> JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut output = Vec::new();
+        if let Some(mut pipe) = pipe {
+            _ = pipe.read_to_end(&mut output);
+        }
+        output
+    })
+}
+
+/// Starts `cmd` without waiting for it. It's reaped on a thread, so that it doesn't linger as a zombie.
+pub fn spawn_detached(cmd: &mut Command) {
+    let child = cmd.stdin(Stdio::null()).stdout(Stdio::null()).stderr(Stdio::null()).spawn();
+    if let Ok(mut child) = child {
+        thread::spawn(move || _ = child.wait());
+    }
+}
+
+pub fn find_in_path(program: &str) -> bool {
+    env::var_os("PATH")
+        .is_some_and(|path| env::split_paths(&path).any(|dir| dir.join(program).is_file()))
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_run_with_timeout() {
+        let timeout = Duration::from_secs(5);
+        let output = run_with_timeout(&mut Command::new("cat"), b"hello", timeout);
+        assert_eq!(output.as_deref(), Some(&b"hello"[..]));
+        assert_eq!(run_with_timeout(&mut Command::new("false"), b"", timeout), None);
+        assert_eq!(run_with_timeout(&mut Command::new("does-not-exist"), b"", timeout), None);
+
+        let mut sleep = Command::new("sleep");
+        sleep.arg("5");
+        assert_eq!(run_with_timeout(&mut sleep, b"", Duration::from_millis(50)), None);
+    }
+}
@@ -0,0 +1,75 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Figures out whether the terminal supports true color.
+//!
+//! Environment variables only tell half the story: `COLORTERM` usually doesn't
+//! survive SSH, and over mosh or inside a multiplexer the answer can differ
+//! from session to session. So we also ask the terminal itself via XTGETTCAP,
+//! which modern terminals (xterm, kitty, foot, WezTerm, ...) answer. The
+//! query is sent before the DA1 request that ends the startup probing, so a
+//! terminal that ignores it doesn't cost us any time.
+
+use edit::framebuffer::ColorMode;
+
+/// Parses the DCS response to the XTGETTCAP request for `Tc` and `RGB`
+/// (`ESC P + q 5463;524742 ESC \`): `1+r<names>...` if a capability is
+/// supported and `0+r...` otherwise.
+/// Returns `None` if it's a different DCS response.
+pub fn parse_xtgettcap(data: &str) -> Option<bool> {
+    match data.get(..3)? {
+        "1+r" => Some(true),
+        "0+r" => Some(false),
+        _ => None,
+    }
+}
+
+/// The environment variables that hint at the color support.
+#[derive(Default)]
+pub struct ColorEnv<'a> {
+    pub colorterm: Option<&'a str>,
+    pub term: Option<&'a str>,
+    pub termux: bool,
+}
+
+/// Picks the color mode based on the XTGETTCAP response (if any) and the environment.
+pub fn detect(tcap: Option<bool>, env: &ColorEnv) -> ColorMode {
+    if tcap == Some(true)
+        || env.colorterm.is_some_and(|v| v == "truecolor" || v == "24bit")
+        || env.term.is_some_and(|v| v.ends_with("-direct") || v.contains("truecolor"))
+    {
+        return ColorMode::TrueColor;
+    }
+
+    // We always used 256 colors in Termux and true color elsewhere.
+    // Without better information, that's still the safest bet.
+    if tcap == Some(false) || env.termux { ColorMode::Palette256 } else { ColorMode::TrueColor }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_xtgettcap() {
+        assert_eq!(parse_xtgettcap("1+r5463"), Some(true));
+        assert_eq!(parse_xtgettcap("1+r524742=382F382F38"), Some(true));
+        assert_eq!(parse_xtgettcap("0+r"), Some(false));
+        assert_eq!(parse_xtgettcap("$r0m"), None);
+        assert_eq!(parse_xtgettcap(""), None);
+    }
+
+    #[test]
+    fn test_detect() {
+        let env = |colorterm, term, termux| ColorEnv { colorterm, term, termux };
+        let none = ColorEnv::default();
+
+        assert_eq!(detect(Some(true), &env(None, None, true)), ColorMode::TrueColor);
+        assert_eq!(detect(None, &env(Some("truecolor"), None, true)), ColorMode::TrueColor);
+        assert_eq!(detect(None, &env(None, Some("xterm-direct"), false)), ColorMode::TrueColor);
+        assert_eq!(detect(None, &env(None, None, true)), ColorMode::Palette256);
+        assert_eq!(detect(None, &env(None, Some("xterm-256color"), false)), ColorMode::TrueColor);
+        assert_eq!(detect(Some(false), &none), ColorMode::Palette256);
+        assert_eq!(detect(None, &none), ColorMode::TrueColor);
+    }
+}
@@ -0,0 +1,851 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Reads the settings from `config.toml` in our config directory, which
+//! `--paths` shows. All of them are optional and command line options win:
+//!
+//! ```toml
+//! [editor]
+//! tab_width = 4
+//! indent_with_tabs = false
+//! word_wrap = false
+//! line_numbers = true
+//! # Shows tabs, trailing spaces and line ends outside of selections, too.
+//! show_whitespace = false
+//! indent_guides = true
+//! # Typing `(`, `[`, `{`, `"` or `'` inserts the closing one, too.
+//! auto_close = true
+//! # The columns left of the text, in this order. Leave one out to hide it.
+//! gutter = ["bookmarks", "line_numbers", "git_signs"]
+//! # Columns with a thin vertical line.
+//! rulers = [80, 120]
+//!
+//! [ui]
+//! color = "auto"      # or "truecolor" or "256"
+//! # Cells taken by characters like "…" or "±", which fonts disagree about.
+//! ambiguous_width = "auto" # or "1" or "2"
+//! keymap = "keymap"   # relative to the config directory
+//! theme = "solarized" # one of the themes in View > Theme
+//! # Remaps colors of the theme: "high_contrast", "deuteranopia", "protanopia"
+//! # or "tritanopia".
+//! palette = "default"
+//! # Without it, F10 or Alt plus a menu's letter shows it until a command was picked.
+//! menubar = true
+//! # A row above the editor with the file's path and, given a ctags `tags` file,
+//! # the symbol at the cursor.
+//! breadcrumbs = false
+//!
+//! # What the status bar shows, aligned to either side. On narrow screens
+//! # the segments are left out from the least important on, see `statusbar.rs`.
+//! # "character", the codepoints under the cursor, isn't shown by default.
+//! [statusbar]
+//! left = ["mode", "filetype", "line_ending", "encoding", "indentation", "position",
+//!         "overtype", "read_only", "dirty"]
+//! right = ["git_branch", "filename"]
+//!
+//! [hooks]
+//! # Runs after the file was saved with Ctrl+S or "Save As".
+//! # The file's path is passed as $1.
+//! after_save = "git -C \"$(dirname \"$1\")\" add \"$1\""
+//!
+//! # More digraphs for Ctrl+K K, or ones that replace the built-in ones, see `digraph.rs`.
+//! [digraphs]
+//! "sh" = "ʃ"
+//!
+//! # Overrides for a file type, named as in the status bar.
+//! [filetype.python]
+//! tab_width = 4
+//! indent_with_tabs = false
+//! word_wrap = false
+//! wrap_column = 88              # where the ruler goes and Rewrap wraps, 0 for none
+//! rulers = [72]                 # instead of those of the editor
+//! line_comment = "#"
+//! block_comment = ["\"\"\"", "\"\"\""]
+//! formatter = "black -q -"      # reads the file on stdin, prints it formatted
+//! formatter_input = "stdin"     # or "file": formats the temporary file passed as $1 in place
+//! formatter_timeout = 10        # in seconds
+//! format_on_save = false
+//! ```
+//!
+//! Mistakes are reported at startup with their position and what would be
+//! accepted instead, since a setting that silently doesn't apply is worse.
+//!
+//! Changes to this file, the keymap or the theme are picked up while running,
+//! or on File > Reload Settings (`:reload-config` in Vim mode).
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{Duration, Instant, SystemTime};
+use std::{fs, io};
+
+use edit::buffer::GutterColumn;
+use edit::framebuffer::ColorMode;
+use edit::helpers::CoordType;
+use toml_span::Value;
+
+use crate::clipboard_backend::{run_capturing_stderr, run_with_timeout, spawn_detached};
+use crate::digraph::Digraph;
+use crate::documents::DocumentDefaults;
+use crate::filetype::{self, FILETYPES, FileTypeSettings, FormatterInput};
+use crate::gutter::GUTTER_COLUMNS;
+use crate::palette::{PALETTES, Palette};
+use crate::paths::{self, Kind};
+use crate::statusbar::{SEGMENTS, StatusbarLayout};
+
+const SECTIONS: [(&str, &[&str]); 4] = [
+    (
+        "editor",
+        &[
+            "tab_width",
+            "indent_with_tabs",
+            "word_wrap",
+            "line_numbers",
+            "show_whitespace",
+            "indent_guides",
+            "auto_close",
+            "gutter",
+            "rulers",
+        ],
+    ),
+    ("ui", &["color", "ambiguous_width", "keymap", "theme", "palette", "menubar", "breadcrumbs"]),
+    ("statusbar", &["left", "right"]),
+    ("hooks", &["after_save"]),
+];
+/// The choices for `ui.color`. `None` detects it.
+pub const COLOR_MODES: [(&str, Option<ColorMode>); 3] = [
+    ("auto", None),
+    ("truecolor", Some(ColorMode::TrueColor)),
+    ("256", Some(ColorMode::Palette256)),
+];
+/// The choices for `ui.ambiguous_width`. `None` asks the terminal.
+pub const AMBIGUOUS_WIDTHS: [(&str, Option<CoordType>); 3] =
+    [("auto", None), ("1", Some(1)), ("2", Some(2))];
+/// The choices for `filetype.NAME.formatter_input`.
+const FORMATTER_INPUTS: [(&str, FormatterInput); 2] =
+    [("stdin", FormatterInput::Stdin), ("file", FormatterInput::File)];
+/// How often [`ConfigWatcher`] looks at the files.
+const WATCH_INTERVAL: Duration = Duration::from_secs(1);
+/// Formatters are run while the UI waits, so they must be quick, unless `formatter_timeout` says so.
+const FORMATTER_TIMEOUT: Duration = Duration::from_secs(10);
+/// How long Edit > Filter Through Command waits for the command.
+const FILTER_TIMEOUT: Duration = Duration::from_secs(30);
+const FILETYPE_KEYS: [&str; 12] = [
+    "tab_width",
+    "indent_with_tabs",
+    "word_wrap",
+    "auto_close",
+    "wrap_column",
+    "rulers",
+    "line_comment",
+    "block_comment",
+    "formatter",
+    "formatter_input",
+    "formatter_timeout",
+    "format_on_save",
+];
+
+#[derive(Default)]
+pub struct Config {
+    pub document: DocumentDefaults,
+    /// `None` for [`crate::gutter::default_columns`].
+    pub gutter: Option<Vec<GutterColumn>>,
+    pub rulers: Vec<CoordType>,
+    /// `Some(None)` to detect it.
+    pub color_mode: Option<Option<ColorMode>>,
+    /// `Some(None)` to detect it.
+    pub ambiguous_width: Option<Option<CoordType>>,
+    pub keymap: Option<PathBuf>,
+    pub theme: Option<String>,
+    pub palette: Palette,
+    /// `ui.menubar = false`.
+    pub hide_menubar: bool,
+    pub breadcrumbs: bool,
+    pub statusbar: StatusbarLayout,
+    pub after_save: Option<String>,
+    pub digraphs: Vec<Digraph>,
+    pub filetypes: Vec<(&'static str, FileTypeSettings)>,
+}
+
+/// A problem with the config file: The byte offset where it is, and what it is.
+type ParseError = (usize, String);
+
+/// Loads the config file. It's fine if there's none.
+/// Errors are formatted as `path:line:column: message`.
+pub fn load() -> Result<Config, String> {
+    let Some(path) = path() else {
+        return Ok(Config::default());
+    };
+    let dir = path.parent().unwrap_or(&path);
+
+    match fs::read_to_string(&path) {
+        Ok(text) => parse(&text, dir).map_err(|(offset, msg)| {
+            let (line, column) = position(&text, offset);
+            format!("{}:{line}:{column}: {msg}", path.display())
+        }),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Config::default()),
+        Err(err) => Err(format!("{}: {}", path.display(), err)),
+    }
+}
+
+/// Where the config file is, if there's a config directory at all.
+pub fn path() -> Option<PathBuf> {
+    Some(paths::dir(Kind::Config)?.join("config.toml"))
+}
+
+/// Notices when the files that make up the settings were changed, e.g. in another
+/// Termux session, so that they can be reloaded.
+#[derive(Default)]
+pub struct ConfigWatcher {
+    files: Vec<(PathBuf, Option<SystemTime>)>,
+    checked_at: Option<Instant>,
+}
+
+impl ConfigWatcher {
+    /// Watches the given files from now on.
+    pub fn watch(&mut self, paths: Vec<PathBuf>) {
+        self.files = paths
+            .into_iter()
+            .map(|path| {
+                let modified = mtime(&path);
+                (path, modified)
+            })
+            .collect();
+    }
+
+    /// Takes note of changes that we made ourselves, so that [`ConfigWatcher::poll`] skips them.
+    pub fn acknowledge(&mut self) {
+        for (path, modified) in &mut self.files {
+            *modified = mtime(path);
+        }
+    }
+
+    /// Returns `true` if one of the files was changed since the last call.
+    /// Checks at most once every [`WATCH_INTERVAL`], as this runs on input.
+    pub fn poll(&mut self) -> bool {
+        if self.checked_at.is_some_and(|at| at.elapsed() < WATCH_INTERVAL) {
+            return false;
+        }
+        self.checked_at = Some(Instant::now());
+
+        let mut changed = false;
+        for (path, modified) in &mut self.files {
+            let now = mtime(path);
+            changed |= now != *modified;
+            *modified = now;
+        }
+        changed
+    }
+}
+
+fn mtime(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// Changes a setting in the config file, e.g. `editor.tab_width` to `2`.
+/// `value` is in TOML syntax. Everything else in the file, including comments, is kept as is.
+pub fn save_setting(name: &str, value: &str) -> Result<(), String> {
+    let Some(path) = path() else {
+        return Err("there's no config directory, see `--paths`".to_string());
+    };
+    let text = match fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(err) => return Err(format!("{}: {}", path.display(), err)),
+    };
+
+    let text = set_value(&text, name, value);
+    fs::create_dir_all(path.parent().unwrap_or(&path))
+        .and_then(|_| fs::write(&path, text))
+        .map_err(|err| format!("{}: {}", path.display(), err))
+}
+
+/// Quotes a string for TOML.
+pub fn toml_string(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Sets `section.key = value` in the text of a config file. The line with the key is replaced,
+/// or if there's none, one is added to the end of the section, or a new section to the file.
+fn set_value(text: &str, name: &str, value: &str) -> String {
+    let (section, key) = name.split_once('.').unwrap();
+    let line = format!("{key} = {value}");
+    let mut lines: Vec<&str> = text.lines().collect();
+    let mut current = "";
+    // The index after the last line of the section that isn't empty.
+    let mut section_end = None;
+
+    for (i, l) in lines.iter().enumerate() {
+        let trimmed = l.trim();
+        if let Some(header) = trimmed.strip_prefix('[') {
+            current = header.split(']').next().unwrap_or("").trim();
+            if current == section {
+                section_end = Some(i + 1);
+            }
+            continue;
+        }
+        if current != section {
+            continue;
+        }
+        let is_key =
+            trimmed.strip_prefix(key).is_some_and(|rest| rest.trim_start().starts_with('='));
+        if is_key {
+            lines[i] = &line;
+            return join_lines(&lines);
+        }
+        if !trimmed.is_empty() {
+            section_end = Some(i + 1);
+        }
+    }
+
+    let header = format!("[{section}]");
+    match section_end {
+        Some(i) => lines.insert(i, &line),
+        None => {
+            if lines.last().is_some_and(|l| !l.trim().is_empty()) {
+                lines.push("");
+            }
+            lines.push(&header);
+            lines.push(&line);
+        }
+    }
+    join_lines(&lines)
+}
+
+fn join_lines(lines: &[&str]) -> String {
+    let mut text = lines.join("\n");
+    text.push('\n');
+    text
+}
+
+/// Runs the `after_save` hook for the file at `path`, without waiting for it.
+pub fn run_after_save(command: &str, path: &Path) {
+    let mut cmd = shell(command);
+    cmd.arg(path);
+    spawn_detached(&mut cmd);
+}
+
+/// Runs a file type's `formatter` on `text` and returns the formatted text,
+/// or `None` if it failed. A temporary file for it is called `name`,
+/// since formatters may go by the extension.
+pub fn run_formatter(settings: &FileTypeSettings, text: &[u8], name: &str) -> Option<Vec<u8>> {
+    let command = settings.formatter.as_deref()?;
+    let timeout = settings
+        .formatter_timeout
+        .map_or(FORMATTER_TIMEOUT, |secs| Duration::from_secs(secs as u64));
+
+    match settings.formatter_input.unwrap_or_default() {
+        FormatterInput::Stdin => run_with_timeout(&mut shell(command), text, timeout),
+        FormatterInput::File => {
+            let dir = paths::runtime_dir().join(format!("format-{}", std::process::id()));
+            let path = dir.join(name);
+            let formatted = fs::create_dir_all(&dir)
+                .and_then(|_| fs::write(&path, text))
+                .ok()
+                .and_then(|_| run_with_timeout(shell(command).arg(&path), &[], timeout))
+                .and_then(|_| fs::read(&path).ok());
+            _ = fs::remove_dir_all(&dir);
+            formatted
+        }
+    }
+}
+
+/// Pipes `text` through the shell `command` and returns its output,
+/// or if it failed, what it wrote to stderr.
+pub fn run_filter(command: &str, text: &[u8]) -> Result<Vec<u8>, String> {
+    run_capturing_stderr(&mut shell(command), text, FILTER_TIMEOUT)
+        .map_err(|stderr| String::from_utf8_lossy(&stderr).trim().to_string())
+}
+
+/// Returns a command that runs `command` in the shell. Arguments added to it are `$1` and so on.
+fn shell(command: &str) -> Command {
+    let mut cmd;
+    if cfg!(windows) {
+        cmd = Command::new("cmd");
+        cmd.arg("/C").arg(command);
+    } else {
+        cmd = Command::new("sh");
+        cmd.arg("-c").arg(command).arg("sh");
+    }
+    cmd
+}
+
+/// Parses the contents of a config file. Relative paths in it are relative to `dir`.
+fn parse(text: &str, dir: &Path) -> Result<Config, ParseError> {
+    let root = toml_span::parse(text).map_err(|err| (err.span.start, err.to_string()))?;
+    let mut config = Config::default();
+    let Some(root) = root.as_table() else {
+        return Ok(config);
+    };
+
+    for (section, value) in root {
+        let section_name = section.name.as_ref();
+        if section_name == "filetype" {
+            parse_filetypes(value, &mut config)?;
+            continue;
+        }
+        if section_name == "digraphs" {
+            parse_digraphs(value, &mut config)?;
+            continue;
+        }
+        let Some(&(_, keys)) = SECTIONS.iter().find(|(name, _)| *name == section_name) else {
+            let expected = list(
+                SECTIONS
+                    .iter()
+                    .map(|(name, _)| format!("[{name}]"))
+                    .chain(["[digraphs]".to_string(), "[filetype.NAME]".to_string()]),
+            );
+            return Err((
+                section.span.start,
+                format!("unknown section `[{section_name}]`, expected one of {expected}"),
+            ));
+        };
+        let Some(table) = value.as_table() else {
+            return Err((
+                value.span.start,
+                format!("`{section_name}` must be a section like `[{section_name}]`"),
+            ));
+        };
+
+        for (key, value) in table {
+            let key_name = key.name.as_ref();
+            let name = format!("{section_name}.{key_name}");
+            match name.as_str() {
+                "editor.tab_width" => config.document.tab_size = integer(value, &name, 1, 8)?,
+                "editor.indent_with_tabs" => {
+                    config.document.indent_with_tabs = boolean(value, &name)?
+                }
+                "editor.word_wrap" => config.document.word_wrap = boolean(value, &name)?,
+                "editor.line_numbers" => config.document.line_numbers = boolean(value, &name)?,
+                "editor.show_whitespace" => {
+                    config.document.show_whitespace = boolean(value, &name)?
+                }
+                "editor.indent_guides" => config.document.indent_guides = boolean(value, &name)?,
+                "editor.auto_close" => config.document.auto_close = boolean(value, &name)?,
+                "editor.gutter" => {
+                    config.gutter = Some(choice_list(value, &name, &GUTTER_COLUMNS)?)
+                }
+                "editor.rulers" => config.rulers = integer_list(value, &name, 1, 1000)?,
+                "ui.color" => config.color_mode = Some(choice(value, &name, &COLOR_MODES)?),
+                "ui.ambiguous_width" => {
+                    config.ambiguous_width = Some(choice(value, &name, &AMBIGUOUS_WIDTHS)?)
+                }
+                "ui.keymap" => config.keymap = Some(dir.join(string(value, &name)?)),
+                "ui.theme" => {
+                    config.theme =
+                        Some(string(value, &name)?.to_string()).filter(|name| !name.is_empty())
+                }
+                "ui.palette" => config.palette = choice(value, &name, &PALETTES)?,
+                "ui.menubar" => config.hide_menubar = !boolean(value, &name)?,
+                "ui.breadcrumbs" => config.breadcrumbs = boolean(value, &name)?,
+                "statusbar.left" => config.statusbar.left = choice_list(value, &name, &SEGMENTS)?,
+                "statusbar.right" => config.statusbar.right = choice_list(value, &name, &SEGMENTS)?,
+                "hooks.after_save" => {
+                    config.after_save =
+                        Some(string(value, &name)?.to_string()).filter(|cmd| !cmd.is_empty())
+                }
+                _ => {
+                    let expected = list(keys.iter().map(|key| format!("`{key}`")));
+                    return Err((
+                        key.span.start,
+                        format!("unknown setting `{name}`, expected one of {expected}"),
+                    ));
+                }
+            }
+        }
+    }
+
+    Ok(config)
+}
+
+/// Parses the `[digraphs]` section, where each key is two characters
+/// and its value the character they stand for.
+fn parse_digraphs(value: &Value, config: &mut Config) -> Result<(), ParseError> {
+    let Some(table) = value.as_table() else {
+        return Err((value.span.start, "`digraphs` must be a section like `[digraphs]`".into()));
+    };
+
+    for (key, value) in table {
+        let key_name = key.name.as_ref();
+        let name = format!("digraphs.{key_name}");
+        let mut chars = key_name.chars();
+        let (Some(a), Some(b), None) = (chars.next(), chars.next(), chars.next()) else {
+            return Err((key.span.start, format!("`{name}` must be named with two characters")));
+        };
+        let mut chars = string(value, &name)?.chars();
+        let (Some(c), None) = (chars.next(), chars.next()) else {
+            return Err((value.span.start, must_be(&name, "a single character", value)));
+        };
+        config.digraphs.push(([a, b], c));
+    }
+
+    Ok(())
+}
+
+/// Parses the `[filetype.NAME]` sections.
+fn parse_filetypes(value: &Value, config: &mut Config) -> Result<(), ParseError> {
+    let Some(table) = value.as_table() else {
+        return Err((
+            value.span.start,
+            "`filetype` must be a section like `[filetype.rust]`".into(),
+        ));
+    };
+
+    for (filetype, value) in table {
+        let filetype_name = filetype.name.as_ref();
+        let Some(filetype) = filetype::by_name(filetype_name) else {
+            let expected = list(FILETYPES.iter().map(|filetype| filetype.name.to_string()));
+            return Err((
+                filetype.span.start,
+                format!("unknown file type `{filetype_name}`, expected one of {expected}"),
+            ));
+        };
+        let Some(table) = value.as_table() else {
+            return Err((
+                value.span.start,
+                format!(
+                    "`filetype.{filetype_name}` must be a section like `[filetype.{filetype_name}]`"
+                ),
+            ));
+        };
+        let mut settings = FileTypeSettings::default();
+
+        for (key, value) in table {
+            let key_name = key.name.as_ref();
+            let name = format!("filetype.{filetype_name}.{key_name}");
+            match key_name {
+                "tab_width" => settings.tab_size = Some(integer(value, &name, 1, 8)?),
+                "indent_with_tabs" => settings.indent_with_tabs = Some(boolean(value, &name)?),
+                "word_wrap" => settings.word_wrap = Some(boolean(value, &name)?),
+                "auto_close" => settings.auto_close = Some(boolean(value, &name)?),
+                "wrap_column" => settings.wrap_column = Some(integer(value, &name, 0, 1000)?),
+                "rulers" => settings.rulers = Some(integer_list(value, &name, 1, 1000)?),
+                "line_comment" => settings.line_comment = Some(string(value, &name)?.to_string()),
+                "block_comment" => {
+                    let pair = value.as_array().and_then(|items| match items.as_slice() {
+                        [beg, end] => Some((beg.as_str()?.to_string(), end.as_str()?.to_string())),
+                        _ => None,
+                    });
+                    let Some(pair) = pair else {
+                        let expected = "two strings like `[\"/*\", \"*/\"]`";
+                        return Err((value.span.start, must_be(&name, expected, value)));
+                    };
+                    settings.block_comment = Some(pair);
+                }
+                "formatter" => {
+                    settings.formatter =
+                        Some(string(value, &name)?.to_string()).filter(|cmd| !cmd.is_empty())
+                }
+                "formatter_input" => {
+                    settings.formatter_input = Some(choice(value, &name, &FORMATTER_INPUTS)?)
+                }
+                "formatter_timeout" => {
+                    settings.formatter_timeout = Some(integer(value, &name, 1, 600)?)
+                }
+                "format_on_save" => settings.format_on_save = Some(boolean(value, &name)?),
+                _ => {
+                    let expected = list(FILETYPE_KEYS.iter().map(|key| format!("`{key}`")));
+                    return Err((
+                        key.span.start,
+                        format!("unknown setting `{name}`, expected one of {expected}"),
+                    ));
+                }
+            }
+        }
+
+        config.filetypes.push((filetype.name, settings));
+    }
+
+    Ok(())
+}
+
+fn integer(
+    value: &Value,
+    name: &str,
+    min: CoordType,
+    max: CoordType,
+) -> Result<CoordType, ParseError> {
+    value
+        .as_integer()
+        .filter(|&n| n >= min as i64 && n <= max as i64)
+        .map(|n| n as CoordType)
+        .ok_or_else(|| {
+            let msg = format!("an integer between {min} and {max}");
+            (value.span.start, must_be(name, &msg, value))
+        })
+}
+
+fn integer_list(
+    value: &Value,
+    name: &str,
+    min: CoordType,
+    max: CoordType,
+) -> Result<Vec<CoordType>, ParseError> {
+    let Some(items) = value.as_array() else {
+        return Err((value.span.start, must_be(name, "a list of integers", value)));
+    };
+    items
+        .iter()
+        .enumerate()
+        .map(|(i, item)| integer(item, &format!("{name}[{i}]"), min, max))
+        .collect()
+}
+
+fn boolean(value: &Value, name: &str) -> Result<bool, ParseError> {
+    value.as_bool().ok_or_else(|| (value.span.start, must_be(name, "`true` or `false`", value)))
+}
+
+fn string<'a>(value: &'a Value, name: &str) -> Result<&'a str, ParseError> {
+    value.as_str().ok_or_else(|| (value.span.start, must_be(name, "a string", value)))
+}
+
+fn choice<T: Copy>(value: &Value, name: &str, choices: &[(&str, T)]) -> Result<T, ParseError> {
+    value
+        .as_str()
+        .and_then(|s| choices.iter().find(|(choice, _)| *choice == s))
+        .map(|&(_, result)| result)
+        .ok_or_else(|| {
+            let expected = list(choices.iter().map(|(choice, _)| format!("\"{choice}\"")));
+            (value.span.start, must_be(name, &format!("one of {expected}"), value))
+        })
+}
+
+fn choice_list<T: Copy>(
+    value: &Value,
+    name: &str,
+    choices: &[(&str, T)],
+) -> Result<Vec<T>, ParseError> {
+    let Some(items) = value.as_array() else {
+        return Err((value.span.start, must_be(name, "a list of strings", value)));
+    };
+    items
+        .iter()
+        .enumerate()
+        .map(|(i, item)| choice(item, &format!("{name}[{i}]"), choices))
+        .collect()
+}
+
+fn must_be(name: &str, expected: &str, value: &Value) -> String {
+    let found = if let Some(s) = value.as_str() {
+        format!("`\"{s}\"`")
+    } else if let Some(n) = value.as_integer() {
+        format!("`{n}`")
+    } else if let Some(b) = value.as_bool() {
+        format!("`{b}`")
+    } else {
+        format!("a {}", value.as_ref().type_str())
+    };
+    format!("`{name}` must be {expected}, not {found}")
+}
+
+fn list(items: impl Iterator<Item = String>) -> String {
+    items.collect::<Vec<_>>().join(", ")
+}
+
+/// Turns a byte offset into a 1-based line and column.
+pub fn position(text: &str, offset: usize) -> (usize, usize) {
+    let before = &text[..offset.min(text.len())];
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    (before.matches('\n').count() + 1, before[line_start..].chars().count() + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::statusbar::Segment;
+
+    fn parse_err(text: &str) -> String {
+        match parse(text, Path::new("/cfg")) {
+            Ok(_) => panic!("expected an error for {text:?}"),
+            Err((offset, msg)) => {
+                let (line, column) = position(text, offset);
+                format!("{line}:{column}: {msg}")
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse() {
+        let config = parse(
+            "[editor]\ntab_width = 2\nword_wrap = true\nline_numbers = false\nshow_whitespace = true\nindent_guides = false\nauto_close = false\n\
+             rulers = [80, 120]\n\n\
+             [ui]\ncolor = \"256\"\nambiguous_width = \"2\"\nkeymap = \"keys\"\npalette = \"protanopia\"\nmenubar = false\n\
+             breadcrumbs = true\n\n\
+             [hooks]\nafter_save = \"make\"\n",
+            Path::new("/cfg"),
+        )
+        .unwrap();
+        assert_eq!(config.document.tab_size, 2);
+        assert!(!config.document.indent_with_tabs);
+        assert!(config.document.word_wrap);
+        assert!(!config.document.line_numbers);
+        assert!(config.document.show_whitespace);
+        assert!(!config.document.indent_guides);
+        assert!(!config.document.auto_close);
+        assert_eq!(config.rulers, [80, 120]);
+        assert_eq!(config.color_mode, Some(Some(ColorMode::Palette256)));
+        assert_eq!(config.ambiguous_width, Some(Some(2)));
+        assert_eq!(config.keymap.as_deref(), Some(Path::new("/cfg/keys")));
+        assert_eq!(config.palette, Palette::Protanopia);
+        assert!(config.hide_menubar);
+        assert!(config.breadcrumbs);
+        assert_eq!(config.after_save.as_deref(), Some("make"));
+
+        let config = parse(
+            "[filetype.python]\ntab_width = 2\nwrap_column = 88\nrulers = [72]\nformatter = \"black -q -\"\n\
+             formatter_input = \"file\"\nformatter_timeout = 30\nformat_on_save = true\n\
+             [filetype.css]\nblock_comment = [\"/*\", \"*/\"]\nauto_close = false\n",
+            Path::new("/cfg"),
+        )
+        .unwrap();
+        let python = FileTypeSettings {
+            tab_size: Some(2),
+            wrap_column: Some(88),
+            rulers: Some(vec![72]),
+            formatter: Some("black -q -".to_string()),
+            formatter_input: Some(FormatterInput::File),
+            formatter_timeout: Some(30),
+            format_on_save: Some(true),
+            ..Default::default()
+        };
+        let css = FileTypeSettings {
+            block_comment: Some(("/*".to_string(), "*/".to_string())),
+            auto_close: Some(false),
+            ..Default::default()
+        };
+        assert_eq!(config.filetypes, [("css", css), ("python", python)]);
+
+        let config =
+            parse("[statusbar]\nleft = [\"position\", \"dirty\"]\nright = []\n", Path::new("/cfg"))
+                .unwrap();
+        assert_eq!(config.statusbar.left, [Segment::Position, Segment::Dirty]);
+        assert_eq!(config.statusbar.right, []);
+
+        let config = parse("[digraphs]\nsh = \"ʃ\"\n\"<3\" = \"♥\"\n", Path::new("/cfg")).unwrap();
+        assert_eq!(config.digraphs, [(['<', '3'], '♥'), (['s', 'h'], 'ʃ')]);
+
+        let config = parse("", Path::new("/cfg")).unwrap();
+        assert_eq!(config.statusbar, StatusbarLayout::default());
+        assert_eq!(config.gutter, None);
+
+        let config =
+            parse("[editor]\ngutter = [\"git_signs\", \"line_numbers\"]\n", Path::new("/cfg"))
+                .unwrap();
+        assert_eq!(
+            config.gutter,
+            Some(vec![GutterColumn::Marks(crate::gutter::GIT_SIGNS), GutterColumn::LineNumbers])
+        );
+        assert_eq!(config.rulers, []);
+        assert_eq!(config.document.tab_size, 4);
+        assert_eq!(config.color_mode, None);
+        assert_eq!(config.ambiguous_width, None);
+    }
+
+    #[test]
+    fn test_set_value() {
+        let text = "# My settings\n[editor]\ntab_width = 4 # spaces\n\n[ui]\ncolor = \"auto\"\n";
+        assert_eq!(
+            set_value(text, "editor.tab_width", "2"),
+            "# My settings\n[editor]\ntab_width = 2\n\n[ui]\ncolor = \"auto\"\n"
+        );
+        assert_eq!(
+            set_value(text, "editor.word_wrap", "true"),
+            "# My settings\n[editor]\ntab_width = 4 # spaces\nword_wrap = true\n\n\
+             [ui]\ncolor = \"auto\"\n"
+        );
+        assert_eq!(
+            set_value(text, "hooks.after_save", "\"make\""),
+            format!("{text}\n[hooks]\nafter_save = \"make\"\n")
+        );
+        assert_eq!(set_value("", "ui.theme", "\"nord\""), "[ui]\ntheme = \"nord\"\n");
+        // Keys that merely start the same don't count.
+        assert_eq!(
+            set_value("[editor]\nword_wrap_x = 1\n", "editor.word_wrap", "true"),
+            "[editor]\nword_wrap_x = 1\nword_wrap = true\n"
+        );
+        assert_eq!(toml_string("a \"b\" \\c"), r#""a \"b\" \\c""#);
+    }
+
+    #[test]
+    fn test_config_watcher() {
+        let dir = std::env::temp_dir().join(format!("edit-test-watcher-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("config.toml");
+        let touch = |secs| {
+            let file = fs::File::create(&path).unwrap();
+            file.set_modified(SystemTime::UNIX_EPOCH + Duration::from_secs(secs)).unwrap();
+        };
+        touch(1);
+
+        let mut watcher = ConfigWatcher::default();
+        watcher.watch(vec![path.clone(), dir.join("keymap")]);
+        assert!(!watcher.poll());
+
+        // Changes are only looked for once per interval.
+        touch(2);
+        assert!(!watcher.poll());
+        watcher.checked_at = None;
+        assert!(watcher.poll());
+        watcher.checked_at = None;
+        assert!(!watcher.poll());
+
+        // Our own changes don't count.
+        touch(3);
+        watcher.acknowledge();
+        watcher.checked_at = None;
+        assert!(!watcher.poll());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(
+            parse_err("[editor]\ntab_width = 12\n"),
+            "2:13: `editor.tab_width` must be an integer between 1 and 8, not `12`"
+        );
+        assert_eq!(
+            parse_err("[editor]\nword_wrap = \"yes\"\n"),
+            "2:14: `editor.word_wrap` must be `true` or `false`, not `\"yes\"`"
+        );
+        assert_eq!(
+            parse_err("[ui]\ncolor = 256\n"),
+            "2:9: `ui.color` must be one of \"auto\", \"truecolor\", \"256\", not `256`"
+        );
+        assert_eq!(
+            parse_err("[ui]\nfont = \"mono\"\n"),
+            "2:1: unknown setting `ui.font`, expected one of `color`, `ambiguous_width`, \
+             `keymap`, `theme`, `palette`, `menubar`, `breadcrumbs`"
+        );
+        assert_eq!(
+            parse_err("tab_width = 4\n"),
+            "1:1: unknown section `[tab_width]`, expected one of [editor], [ui], [statusbar], \
+             [hooks], [digraphs], [filetype.NAME]"
+        );
+        assert_eq!(
+            parse_err("[digraphs]\nabc = \"x\"\n"),
+            "2:1: `digraphs.abc` must be named with two characters"
+        );
+        assert_eq!(
+            parse_err("[digraphs]\nab = \"xy\"\n"),
+            "2:7: `digraphs.ab` must be a single character, not `\"xy\"`"
+        );
+        assert_eq!(
+            parse_err("[filetype.rust]\nwrap_column = -1\n"),
+            "2:15: `filetype.rust.wrap_column` must be an integer between 0 and 1000, not `-1`"
+        );
+        assert_eq!(
+            parse_err("[filetype.python]\nblock_comment = \"#\"\n"),
+            "2:18: `filetype.python.block_comment` must be two strings like `[\"/*\", \"*/\"]`, \
+             not `\"#\"`"
+        );
+        assert!(parse_err("[statusbar]\nleft = [\"mode\", \"clock\"]\n").starts_with(
+            "2:18: `statusbar.left[1]` must be one of \"character\", \"git_branch\", "
+        ));
+        assert_eq!(
+            parse_err("[editor]\nrulers = [80, 0]\n"),
+            "2:15: `editor.rulers[1]` must be an integer between 1 and 1000, not `0`"
+        );
+        assert!(parse_err("[filetype.rsut]\n").starts_with("1:11: unknown file type `rsut`"));
+        assert!(parse_err("[editor\n").starts_with("1:"));
+    }
+}
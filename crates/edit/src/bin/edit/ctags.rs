@@ -0,0 +1,89 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Reads the symbols of a file from a `tags` file written by ctags.
+//!
+//! The `tags` file is looked for next to the file and in the folders above it.
+//! Only tags with a line number are used, so it should be written with
+//! `ctags -R -n` or `ctags -R --fields=+n`.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use edit::helpers::CoordType;
+
+pub struct Tag {
+    pub name: String,
+    /// 1-based, like ctags writes it.
+    pub line: CoordType,
+}
+
+/// Returns the tags of `path`, sorted by line. Empty if there's no `tags` file.
+pub fn load(path: &Path) -> Vec<Tag> {
+    let Some(tags_path) = find(path) else {
+        return Vec::new();
+    };
+    let Ok(text) = fs::read_to_string(&tags_path) else {
+        return Vec::new();
+    };
+    let base = tags_path.parent().unwrap_or(Path::new(""));
+    parse(&text, base, path)
+}
+
+/// The tag that `line` (1-based) is in, which is the closest one above it.
+pub fn enclosing(tags: &[Tag], line: CoordType) -> Option<usize> {
+    tags.partition_point(|tag| tag.line <= line).checked_sub(1)
+}
+
+fn find(path: &Path) -> Option<PathBuf> {
+    path.ancestors().skip(1).map(|dir| dir.join("tags")).find(|tags| tags.is_file())
+}
+
+/// Picks the lines for `path` out of a `tags` file in `base`. They look like this:
+/// `name<Tab>file<Tab>address;"<Tab>kind<Tab>line:42`, where the address is the
+/// line number itself with `-n`.
+fn parse(text: &str, base: &Path, path: &Path) -> Vec<Tag> {
+    let mut tags: Vec<_> = text
+        .lines()
+        .filter(|line| !line.starts_with("!_"))
+        .filter_map(|line| {
+            let mut fields = line.split('\t');
+            let name = fields.next()?;
+            let file = fields.next()?;
+            if base.join(file) != path {
+                return None;
+            }
+            let rest: Vec<_> = fields.collect();
+            let address = rest.first()?.trim_end_matches(";\"");
+            let line = address.parse().ok().or_else(|| {
+                rest.iter().find_map(|f| f.strip_prefix("line:")).and_then(|n| n.parse().ok())
+            })?;
+            Some(Tag { name: name.to_string(), line })
+        })
+        .collect();
+    tags.sort_by_key(|tag| tag.line);
+    tags
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let text = "!_TAG_FILE_FORMAT\t2\t/extended format/\n\
+                    main\tsrc/main.rs\t/^fn main() {$/;\"\tf\tline:20\n\
+                    Config\tsrc/main.rs\t5;\"\ts\n\
+                    other\tsrc/lib.rs\t/^fn other() {$/;\"\tf\tline:3\n\
+                    nowhere\tsrc/main.rs\t/^nowhere$/;\"\tv\n";
+        let tags = parse(text, Path::new("/p"), Path::new("/p/src/main.rs"));
+
+        let names: Vec<_> = tags.iter().map(|t| (t.name.as_str(), t.line)).collect();
+        assert_eq!(names, [("Config", 5), ("main", 20)]);
+
+        assert_eq!(enclosing(&tags, 1), None);
+        assert_eq!(enclosing(&tags, 5), Some(0));
+        assert_eq!(enclosing(&tags, 19), Some(0));
+        assert_eq!(enclosing(&tags, 100), Some(1));
+    }
+}
@@ -0,0 +1,114 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Types by voice, because long prose is painful on a phone keyboard.
+//!
+//! `termux-speech-to-text` listens until the user stops speaking and prints
+//! what it recognized. Some versions print the partial results as they come
+//! in, one per line, so only the last line counts. It runs on a background
+//! thread, and the text is inserted at the cursor once it's done.
+
+use std::process::Command;
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+use std::time::Duration;
+
+use crate::clipboard_backend::{find_in_path, run_with_timeout};
+
+/// Android stops listening after a few seconds of silence, so this is just a safety net.
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(120);
+/// How often to check for the result while listening.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// What to append to the recognized text.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum DictationSuffix {
+    None,
+    Space,
+    Newline,
+}
+
+pub struct Dictation {
+    pub suffix: DictationSuffix,
+    available: bool,
+    pending: Option<Receiver<Option<String>>>,
+}
+
+impl Dictation {
+    pub fn new() -> Self {
+        Self {
+            suffix: DictationSuffix::None,
+            available: find_in_path("termux-speech-to-text"),
+            pending: None,
+        }
+    }
+
+    /// Whether the Termux:API command is installed.
+    pub fn is_available(&self) -> bool {
+        self.available
+    }
+
+    pub fn is_listening(&self) -> bool {
+        self.pending.is_some()
+    }
+
+    /// Starts listening, unless it already is.
+    pub fn start(&mut self) {
+        if self.pending.is_some() {
+            return;
+        }
+
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let mut cmd = Command::new("termux-speech-to-text");
+            let output = run_with_timeout(&mut cmd, &[], COMMAND_TIMEOUT);
+            _ = sender.send(output.map(|output| parse_output(&output)));
+        });
+        self.pending = Some(receiver);
+    }
+
+    /// Returns the text to insert once listening is done, or `Some(Err)` if it failed.
+    /// The text is empty if nothing was recognized.
+    pub fn poll(&mut self) -> Option<Result<String, ()>> {
+        let result = match self.pending.as_ref()?.try_recv() {
+            Ok(Some(text)) => Ok(text),
+            Ok(None) | Err(TryRecvError::Disconnected) => Err(()),
+            Err(TryRecvError::Empty) => return None,
+        };
+        self.pending = None;
+
+        Some(result.map(|mut text| {
+            if !text.is_empty() {
+                match self.suffix {
+                    DictationSuffix::None => {}
+                    DictationSuffix::Space => text.push(' '),
+                    DictationSuffix::Newline => text.push('\n'),
+                }
+            }
+            text
+        }))
+    }
+
+    /// How long the main loop may block waiting for input before checking for the result.
+    pub fn read_timeout(&self) -> Duration {
+        if self.is_listening() { POLL_INTERVAL } else { Duration::MAX }
+    }
+}
+
+/// Returns the final result, which is the last line.
+fn parse_output(output: &[u8]) -> String {
+    let text = String::from_utf8_lossy(output);
+    text.lines().map(str::trim).rfind(|line| !line.is_empty()).unwrap_or_default().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_output() {
+        assert_eq!(parse_output(b"hello world\n"), "hello world");
+        assert_eq!(parse_output(b"hel\nhello\nhello world\n\n"), "hello world");
+        assert_eq!(parse_output(b""), "");
+    }
+}
@@ -0,0 +1,338 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! `edit -d FILE1 FILE2` compares two files side by side, e.g. as `git difftool`.
+//! The lines are diffed with Myers' algorithm and the removed and added lines of
+//! a hunk are paired up into rows, so that both sides scroll together.
+//! See [`crate::draw_diff`] for the viewer.
+//!
+//! [`line_hunks`] is the same diff for applying a formatter's output as a few small edits.
+
+use std::fs;
+use std::ops::Range;
+use std::path::Path;
+
+use edit::apperr;
+
+/// Beyond this many removed and added lines the diff would take too much memory,
+/// and the rest is shown as a single change.
+const MAX_COST: usize = 2000;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Kind {
+    Same,
+    /// Only on the left.
+    Removed,
+    /// Only on the right.
+    Added,
+    /// A line on the left that became the one on the right.
+    Changed,
+}
+
+/// A row of the viewer with the 0-based line on either side, if any.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Row {
+    pub left: Option<usize>,
+    pub right: Option<usize>,
+    pub kind: Kind,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Op {
+    Same,
+    Delete,
+    Insert,
+}
+
+pub struct Diff {
+    pub left_name: String,
+    pub right_name: String,
+    pub left: Vec<String>,
+    pub right: Vec<String>,
+    pub rows: Vec<Row>,
+    /// The rows where the hunks start.
+    pub hunks: Vec<usize>,
+    /// The first visible row. Both sides share it.
+    pub scroll: usize,
+    /// How many columns both sides are scrolled to the right.
+    pub scroll_x: usize,
+    /// The index of the hunk that was jumped to last.
+    pub hunk: Option<usize>,
+}
+
+impl Diff {
+    pub fn load(left: &Path, right: &Path) -> apperr::Result<Self> {
+        let left_text = fs::read(left)?;
+        let right_text = fs::read(right)?;
+        let mut diff = Self::new(&left_text, &right_text);
+        diff.left_name = left.display().to_string();
+        diff.right_name = right.display().to_string();
+        Ok(diff)
+    }
+
+    pub fn new(left: &[u8], right: &[u8]) -> Self {
+        let left = lines(left);
+        let right = lines(right);
+        let rows = rows(&diff_lines(&left, &right));
+        let hunks = (0..rows.len())
+            .filter(|&i| rows[i].kind != Kind::Same && (i == 0 || rows[i - 1].kind == Kind::Same))
+            .collect();
+        Self {
+            left_name: String::new(),
+            right_name: String::new(),
+            left,
+            right,
+            rows,
+            hunks,
+            scroll: 0,
+            scroll_x: 0,
+            hunk: None,
+        }
+    }
+
+    /// Moves on to the next (or previous) hunk and returns the row where it starts.
+    /// Before the first jump, that's the first hunk below (or above) the scroll position.
+    pub fn goto_hunk(&mut self, forward: bool) -> Option<usize> {
+        let below = self.hunks.partition_point(|&start| start < self.scroll);
+        let index = match (self.hunk, forward) {
+            (Some(hunk), true) => hunk + 1,
+            (Some(hunk), false) => hunk.checked_sub(1)?,
+            (None, true) => below,
+            (None, false) => below.checked_sub(1)?,
+        };
+        let row = *self.hunks.get(index)?;
+        self.hunk = Some(index);
+        Some(row)
+    }
+}
+
+fn lines(text: &[u8]) -> Vec<String> {
+    let text = String::from_utf8_lossy(text);
+    let text = text.strip_suffix('\n').unwrap_or(&text);
+    if text.is_empty() {
+        return Vec::new();
+    }
+    text.split('\n').map(|line| line.strip_suffix('\r').unwrap_or(line).to_string()).collect()
+}
+
+/// The edit script that turns `a` into `b`.
+fn diff_lines<T: PartialEq>(a: &[T], b: &[T]) -> Vec<Op> {
+    let prefix = a.iter().zip(b).take_while(|(a, b)| a == b).count();
+    let suffix =
+        a[prefix..].iter().rev().zip(b[prefix..].iter().rev()).take_while(|(a, b)| a == b).count();
+    let (a_mid, b_mid) = (&a[prefix..a.len() - suffix], &b[prefix..b.len() - suffix]);
+
+    let mut ops = vec![Op::Same; prefix];
+    match myers(a_mid, b_mid) {
+        Some(mid) => ops.extend(mid),
+        None => {
+            ops.extend(std::iter::repeat_n(Op::Delete, a_mid.len()));
+            ops.extend(std::iter::repeat_n(Op::Insert, b_mid.len()));
+        }
+    }
+    ops.extend(std::iter::repeat_n(Op::Same, suffix));
+    ops
+}
+
+/// Myers' O(ND) diff. Returns `None` if more than [`MAX_COST`] lines differ.
+fn myers<T: PartialEq>(a: &[T], b: &[T]) -> Option<Vec<Op>> {
+    let (n, m) = (a.len() as isize, b.len() as isize);
+    let max = (n + m) as usize;
+    if n.abs_diff(m) > MAX_COST {
+        return None;
+    }
+
+    // `v[k + offset]` is the furthest x on diagonal k. `trace[d]` holds the
+    // diagonals -d-1..=d+1 of `v` before step d, for walking the path back.
+    let offset = max as isize + 1;
+    let mut v = vec![0u32; 2 * max + 3];
+    let mut trace: Vec<Vec<u32>> = Vec::new();
+
+    'outer: for d in 0..=(max.min(MAX_COST) as isize) {
+        trace.push(v[(offset - d - 1) as usize..=(offset + d + 1) as usize].to_vec());
+        for k in (-d..=d).step_by(2) {
+            let i = (k + offset) as usize;
+            let mut x = if k == -d || (k != d && v[i - 1] < v[i + 1]) {
+                v[i + 1] as isize
+            } else {
+                v[i - 1] as isize + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && a[x as usize] == b[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[i] = x as u32;
+            if x >= n && y >= m {
+                break 'outer;
+            }
+        }
+        if d as usize == max.min(MAX_COST) {
+            return None;
+        }
+    }
+
+    let mut ops = Vec::new();
+    let (mut x, mut y) = (n, m);
+    for (d, v) in trace.iter().enumerate().rev() {
+        let d = d as isize;
+        if d == 0 {
+            ops.extend(std::iter::repeat_n(Op::Same, x as usize));
+            break;
+        }
+        let get = |k: isize| v[(k + d + 1) as usize] as isize;
+        let k = x - y;
+        let prev_k = if k == -d || (k != d && get(k - 1) < get(k + 1)) { k + 1 } else { k - 1 };
+        let prev_x = get(prev_k);
+        let prev_y = prev_x - prev_k;
+        while x > prev_x && y > prev_y {
+            ops.push(Op::Same);
+            x -= 1;
+            y -= 1;
+        }
+        if x == prev_x {
+            ops.push(Op::Insert);
+            y -= 1;
+        } else {
+            ops.push(Op::Delete);
+            x -= 1;
+        }
+    }
+    ops.reverse();
+    Some(ops)
+}
+
+/// The ranges of lines of `a` that differ from those of `b`, each with the lines it became.
+/// Lines are compared as they are, with their line break.
+pub fn line_hunks<T: PartialEq>(a: &[T], b: &[T]) -> Vec<(Range<usize>, Range<usize>)> {
+    let mut hunks: Vec<(Range<usize>, Range<usize>)> = Vec::new();
+    let (mut x, mut y) = (0, 0);
+    for op in diff_lines(a, b) {
+        if op == Op::Same {
+            x += 1;
+            y += 1;
+            continue;
+        }
+        if !matches!(hunks.last(), Some((left, right)) if left.end == x && right.end == y) {
+            hunks.push((x..x, y..y));
+        }
+        let (left, right) = hunks.last_mut().unwrap();
+        if op == Op::Delete {
+            x += 1;
+            left.end = x;
+        } else {
+            y += 1;
+            right.end = y;
+        }
+    }
+    hunks
+}
+
+/// Lays out the edit script side by side: The removed and added lines between
+/// two unchanged ones are paired up, and the rest of the longer side is left alone.
+fn rows(ops: &[Op]) -> Vec<Row> {
+    let mut rows = Vec::with_capacity(ops.len());
+    let (mut x, mut y) = (0, 0);
+    let mut i = 0;
+    while i < ops.len() {
+        if ops[i] == Op::Same {
+            rows.push(Row { left: Some(x), right: Some(y), kind: Kind::Same });
+            x += 1;
+            y += 1;
+            i += 1;
+            continue;
+        }
+        let end = ops[i..].iter().position(|&op| op == Op::Same).map_or(ops.len(), |n| i + n);
+        let deleted = ops[i..end].iter().filter(|&&op| op == Op::Delete).count();
+        let inserted = end - i - deleted;
+        for j in 0..deleted.max(inserted) {
+            let left = (j < deleted).then_some(x + j);
+            let right = (j < inserted).then_some(y + j);
+            let kind = match (left, right) {
+                (Some(_), Some(_)) => Kind::Changed,
+                (Some(_), None) => Kind::Removed,
+                _ => Kind::Added,
+            };
+            rows.push(Row { left, right, kind });
+        }
+        x += deleted;
+        y += inserted;
+        i = end;
+    }
+    rows
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kinds(left: &str, right: &str) -> Vec<Kind> {
+        Diff::new(left.as_bytes(), right.as_bytes()).rows.iter().map(|row| row.kind).collect()
+    }
+
+    #[test]
+    fn test_rows() {
+        use Kind::*;
+        assert_eq!(kinds("a\nb\nc\n", "a\nb\nc\n"), [Same, Same, Same]);
+        assert_eq!(kinds("a\nb\nc\n", "a\nc\n"), [Same, Removed, Same]);
+        assert_eq!(kinds("a\nc\n", "a\nb\nc\n"), [Same, Added, Same]);
+        assert_eq!(kinds("a\nb\nc\n", "a\nx\ny\nc\n"), [Same, Changed, Added, Same]);
+        assert_eq!(kinds("", "a\n"), [Added]);
+        assert_eq!(kinds("a\r\nb", "a\nb\n"), [Same, Same]);
+
+        let diff = Diff::new(b"a\nb\nc\nd\n", b"x\nb\nd\ne\n");
+        let sides: Vec<_> = diff.rows.iter().map(|row| (row.left, row.right)).collect();
+        assert_eq!(
+            sides,
+            [
+                (Some(0), Some(0)),
+                (Some(1), Some(1)),
+                (Some(2), None),
+                (Some(3), Some(2)),
+                (None, Some(3))
+            ]
+        );
+    }
+
+    #[test]
+    fn test_hunks() {
+        let mut diff = Diff::new(b"a\nb\nc\nd\ne\n", b"x\nb\nc\nd\ny\nz\n");
+        assert_eq!(diff.hunks, [0, 4]);
+        assert_eq!(diff.goto_hunk(true), Some(0));
+        assert_eq!(diff.goto_hunk(true), Some(4));
+        assert_eq!(diff.goto_hunk(true), None);
+        assert_eq!(diff.hunk, Some(1));
+        assert_eq!(diff.goto_hunk(false), Some(0));
+        assert_eq!(diff.goto_hunk(false), None);
+
+        diff.hunk = None;
+        diff.scroll = 2;
+        assert_eq!(diff.goto_hunk(false), Some(0));
+        diff.hunk = None;
+        assert_eq!(diff.goto_hunk(true), Some(4));
+    }
+
+    #[test]
+    fn test_line_hunks() {
+        let hunks = |a: &str, b: &str| {
+            let a: Vec<&str> = a.split_inclusive('\n').collect();
+            let b: Vec<&str> = b.split_inclusive('\n').collect();
+            line_hunks(&a, &b)
+        };
+        assert_eq!(hunks("a\nb\nc\n", "a\nb\nc\n"), []);
+        assert_eq!(hunks("a\nb\nc\nd\n", "a\nx\ny\nc\n"), [(1..2, 1..3), (3..4, 4..4)]);
+        assert_eq!(hunks("a\nb", "a\nb\n"), [(1..2, 1..2)]);
+        assert_eq!(hunks("", "a\n"), [(0..0, 0..1)]);
+    }
+
+    #[test]
+    fn test_myers_matches_lengths() {
+        let a: Vec<String> = "abcabba".chars().map(String::from).collect();
+        let b: Vec<String> = "cbabac".chars().map(String::from).collect();
+        let ops = myers(&a, &b).unwrap();
+        // The classic example from Myers' paper has an edit distance of 5.
+        assert_eq!(ops.iter().filter(|&&op| op != Op::Same).count(), 5);
+        assert_eq!(ops.iter().filter(|&&op| op != Op::Insert).count(), a.len());
+        assert_eq!(ops.iter().filter(|&&op| op != Op::Delete).count(), b.len());
+    }
+}
@@ -0,0 +1,101 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! RFC 1345 digraphs: Two characters that stand for one that's hard to type, like `e'` for `é`,
+//! `->` for `→` or `,.` for `…`. They're typed after Ctrl+K K, or in Vim's insert mode
+//! after just Ctrl+K, like in Vim.
+//!
+//! The letters with an accent are the letter plus `'` (acute), `!` (grave), `>` (circumflex),
+//! `:` (diaeresis), `?` (tilde), `-` (macron), `(` (breve), `.` (dot), `,` (cedilla),
+//! `<` (caron), `0` (ring), `;` (ogonek) or `"` (double acute). Greek letters are the
+//! Latin one plus `*`. The `[digraphs]` section of the config adds more, see `config.rs`.
+
+/// A user-defined digraph: The two characters and the one they stand for.
+pub type Digraph = ([char; 2], char);
+
+#[rustfmt::skip]
+const BUILTIN: &[(&str, char)] = &[
+    ("A'", 'Á'), ("A!", 'À'), ("A>", 'Â'), ("A:", 'Ä'), ("A?", 'Ã'), ("A-", 'Ā'), ("A(", 'Ă'),
+    ("A.", 'Ȧ'), ("A<", 'Ǎ'), ("A;", 'Ą'), ("a'", 'á'), ("a!", 'à'), ("a>", 'â'), ("a:", 'ä'),
+    ("a?", 'ã'), ("a-", 'ā'), ("a(", 'ă'), ("a.", 'ȧ'), ("a<", 'ǎ'), ("a;", 'ą'), ("C'", 'Ć'),
+    ("C>", 'Ĉ'), ("C.", 'Ċ'), ("C,", 'Ç'), ("C<", 'Č'), ("c'", 'ć'), ("c>", 'ĉ'), ("c.", 'ċ'),
+    ("c,", 'ç'), ("c<", 'č'), ("D<", 'Ď'), ("d<", 'ď'), ("E'", 'É'), ("E!", 'È'), ("E>", 'Ê'),
+    ("E:", 'Ë'), ("E-", 'Ē'), ("E(", 'Ĕ'), ("E.", 'Ė'), ("E,", 'Ȩ'), ("E<", 'Ě'), ("E;", 'Ę'),
+    ("e'", 'é'), ("e!", 'è'), ("e>", 'ê'), ("e:", 'ë'), ("e-", 'ē'), ("e(", 'ĕ'), ("e.", 'ė'),
+    ("e,", 'ȩ'), ("e<", 'ě'), ("e;", 'ę'), ("G'", 'Ǵ'), ("G>", 'Ĝ'), ("G(", 'Ğ'), ("G.", 'Ġ'),
+    ("G,", 'Ģ'), ("G<", 'Ǧ'), ("g'", 'ǵ'), ("g>", 'ĝ'), ("g(", 'ğ'), ("g.", 'ġ'), ("g,", 'ģ'),
+    ("g<", 'ǧ'), ("H>", 'Ĥ'), ("H<", 'Ȟ'), ("h>", 'ĥ'), ("h<", 'ȟ'), ("I'", 'Í'), ("I!", 'Ì'),
+    ("I>", 'Î'), ("I:", 'Ï'), ("I?", 'Ĩ'), ("I-", 'Ī'), ("I(", 'Ĭ'), ("I.", 'İ'), ("I<", 'Ǐ'),
+    ("I;", 'Į'), ("i'", 'í'), ("i!", 'ì'), ("i>", 'î'), ("i:", 'ï'), ("i?", 'ĩ'), ("i-", 'ī'),
+    ("i(", 'ĭ'), ("i<", 'ǐ'), ("i;", 'į'), ("J>", 'Ĵ'), ("j>", 'ĵ'), ("j<", 'ǰ'), ("K,", 'Ķ'),
+    ("K<", 'Ǩ'), ("k,", 'ķ'), ("k<", 'ǩ'), ("L'", 'Ĺ'), ("L,", 'Ļ'), ("L<", 'Ľ'), ("l'", 'ĺ'),
+    ("l,", 'ļ'), ("l<", 'ľ'), ("N'", 'Ń'), ("N!", 'Ǹ'), ("N?", 'Ñ'), ("N,", 'Ņ'), ("N<", 'Ň'),
+    ("n'", 'ń'), ("n!", 'ǹ'), ("n?", 'ñ'), ("n,", 'ņ'), ("n<", 'ň'), ("O'", 'Ó'), ("O!", 'Ò'),
+    ("O>", 'Ô'), ("O:", 'Ö'), ("O?", 'Õ'), ("O-", 'Ō'), ("O(", 'Ŏ'), ("O.", 'Ȯ'), ("O<", 'Ǒ'),
+    ("O;", 'Ǫ'), ("O\"", 'Ő'), ("o'", 'ó'), ("o!", 'ò'), ("o>", 'ô'), ("o:", 'ö'), ("o?", 'õ'),
+    ("o-", 'ō'), ("o(", 'ŏ'), ("o.", 'ȯ'), ("o<", 'ǒ'), ("o;", 'ǫ'), ("o\"", 'ő'), ("R'", 'Ŕ'),
+    ("R,", 'Ŗ'), ("R<", 'Ř'), ("r'", 'ŕ'), ("r,", 'ŗ'), ("r<", 'ř'), ("S'", 'Ś'), ("S>", 'Ŝ'),
+    ("S,", 'Ş'), ("S<", 'Š'), ("s'", 'ś'), ("s>", 'ŝ'), ("s,", 'ş'), ("s<", 'š'), ("T,", 'Ţ'),
+    ("T<", 'Ť'), ("t,", 'ţ'), ("t<", 'ť'), ("U'", 'Ú'), ("U!", 'Ù'), ("U>", 'Û'), ("U:", 'Ü'),
+    ("U?", 'Ũ'), ("U-", 'Ū'), ("U(", 'Ŭ'), ("U<", 'Ǔ'), ("U0", 'Ů'), ("U;", 'Ų'), ("U\"", 'Ű'),
+    ("u'", 'ú'), ("u!", 'ù'), ("u>", 'û'), ("u:", 'ü'), ("u?", 'ũ'), ("u-", 'ū'), ("u(", 'ŭ'),
+    ("u<", 'ǔ'), ("u0", 'ů'), ("u;", 'ų'), ("u\"", 'ű'), ("W>", 'Ŵ'), ("w>", 'ŵ'), ("Y'", 'Ý'),
+    ("Y>", 'Ŷ'), ("Y:", 'Ÿ'), ("Y-", 'Ȳ'), ("y'", 'ý'), ("y>", 'ŷ'), ("y:", 'ÿ'), ("y-", 'ȳ'),
+    ("Z'", 'Ź'), ("Z.", 'Ż'), ("Z<", 'Ž'), ("z'", 'ź'), ("z.", 'ż'), ("z<", 'ž'), ("aa", 'å'),
+    ("AA", 'Å'), ("a*", 'α'), ("A*", 'Α'), ("b*", 'β'), ("B*", 'Β'), ("g*", 'γ'), ("G*", 'Γ'),
+    ("d*", 'δ'), ("D*", 'Δ'), ("e*", 'ε'), ("E*", 'Ε'), ("z*", 'ζ'), ("Z*", 'Ζ'), ("y*", 'η'),
+    ("Y*", 'Η'), ("h*", 'θ'), ("H*", 'Θ'), ("i*", 'ι'), ("I*", 'Ι'), ("k*", 'κ'), ("K*", 'Κ'),
+    ("l*", 'λ'), ("L*", 'Λ'), ("m*", 'μ'), ("M*", 'Μ'), ("n*", 'ν'), ("N*", 'Ν'), ("c*", 'ξ'),
+    ("C*", 'Ξ'), ("o*", 'ο'), ("O*", 'Ο'), ("p*", 'π'), ("P*", 'Π'), ("r*", 'ρ'), ("R*", 'Ρ'),
+    ("s*", 'σ'), ("S*", 'Σ'), ("t*", 'τ'), ("T*", 'Τ'), ("u*", 'υ'), ("U*", 'Υ'), ("f*", 'φ'),
+    ("F*", 'Φ'), ("x*", 'χ'), ("X*", 'Χ'), ("q*", 'ψ'), ("Q*", 'Ψ'), ("w*", 'ω'), ("W*", 'Ω'),
+    ("*s", 'ς'), ("NS", '\u{a0}'), ("ss", 'ß'), ("ae", 'æ'), ("AE", 'Æ'), ("oe", 'œ'),
+    ("OE", 'Œ'), ("o/", 'ø'), ("O/", 'Ø'), ("th", 'þ'), ("TH", 'Þ'), ("d-", 'ð'), ("D-", 'Ð'),
+    ("i.", 'ı'), ("!I", '¡'), ("?I", '¿'), ("Ct", '¢'), ("Pd", '£'), ("Cu", '¤'), ("Ye", '¥'),
+    ("Eu", '€'), ("BB", '¦'), ("SE", '§'), ("PI", '¶'), ("Co", '©'), ("Rg", '®'), ("TM", '™'),
+    ("-a", 'ª'), ("-o", 'º'), ("<<", '«'), (">>", '»'), ("'6", '‘'), ("'9", '’'), (".9", '‚'),
+    ("\"6", '“'), ("\"9", '”'), (":9", '„'), ("-N", '–'), ("-M", '—'), (",.", '…'), ("..", '‥'),
+    ("/-", '†'), ("/=", '‡'), ("%0", '‰'), ("1'", '′'), ("2'", '″'), (".M", '·'), ("Sb", '∙'),
+    ("DG", '°'), ("+-", '±'), ("*X", '×'), ("-:", '÷'), ("My", 'µ'), ("1S", '¹'), ("2S", '²'),
+    ("3S", '³'), ("14", '¼'), ("12", '½'), ("34", '¾'), ("NO", '¬'), ("->", '→'), ("<-", '←'),
+    ("-!", '↑'), ("-v", '↓'), ("<>", '↔'), ("UD", '↕'), ("=>", '⇒'), ("==", '⇔'), ("!=", '≠'),
+    ("=<", '≤'), (">=", '≥'), ("?2", '≈'), ("=3", '≡'), ("00", '∞'), ("RT", '√'), ("FA", '∀'),
+    ("TE", '∃'), ("dP", '∂'), ("/0", '∅'), ("DE", '∆'), ("NB", '∇'), ("(-", '∈'), ("-)", '∋'),
+    ("*P", '∏'), ("+Z", '∑'), ("-2", '−'), ("In", '∫'), ("(U", '∩'), (")U", '∪'), ("(C", '⊂'),
+    (")C", '⊃'), ("(_", '⊆'), (")_", '⊇'), ("AN", '∧'), ("OR", '∨'), ("OK", '✓'), ("XX", '✗'),
+];
+
+/// Looks up the digraph `a` `b`, first among the `user` ones, then the built-in ones.
+/// Like in Vim, the two may be typed the other way around, too, so `'e` is `é` as well.
+pub fn lookup(user: &[Digraph], a: char, b: char) -> Option<char> {
+    let find = |a: char, b: char| {
+        user.iter()
+            .find(|(k, _)| *k == [a, b])
+            .map(|&(_, c)| c)
+            .or_else(|| BUILTIN.iter().find(|(k, _)| k.chars().eq([a, b])).map(|&(_, c)| c))
+    };
+    find(a, b).or_else(|| find(b, a))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lookup() {
+        assert_eq!(lookup(&[], 'e', '\''), Some('é'));
+        assert_eq!(lookup(&[], '\'', 'e'), Some('é'));
+        assert_eq!(lookup(&[], 'a', 'a'), Some('å'));
+        assert_eq!(lookup(&[], 'c', ','), Some('ç'));
+        assert_eq!(lookup(&[], '-', '>'), Some('→'));
+        assert_eq!(lookup(&[], '<', '-'), Some('←'));
+        assert_eq!(lookup(&[], ',', '.'), Some('…'));
+        assert_eq!(lookup(&[], 'l', '*'), Some('λ'));
+        assert_eq!(lookup(&[], 'W', '*'), Some('Ω'));
+        assert_eq!(lookup(&[], 'q', 'q'), None);
+
+        let user = [(['e', '\''], 'ė'), (['s', 'h'], 'ʃ')];
+        assert_eq!(lookup(&user, 'e', '\''), Some('ė'));
+        assert_eq!(lookup(&user, 'h', 's'), Some('ʃ'));
+    }
+}
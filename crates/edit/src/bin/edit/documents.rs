@@ -3,14 +3,45 @@
 
 use std::collections::LinkedList;
 use std::ffi::OsStr;
-use std::fs::File;
+use std::fs::{self, File};
+use std::io::{self, Read as _, Seek as _};
 use std::path::{Path, PathBuf};
+use std::rc::{Rc, Weak};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::time::{Duration, Instant, SystemTime};
+use std::{ptr, thread};
 
-use edit::buffer::{RcTextBuffer, TextBuffer};
-use edit::helpers::{CoordType, Point};
+use edit::buffer::{GutterColumn, RcTextBuffer, TextBuffer, TextBufferCell, detect_bom};
+use edit::helpers::{CoordType, KIBI, MEBI, Point};
 use edit::{apperr, path, sys};
 
+use crate::backup::Backup;
+use crate::draw_statusbar::text_width;
+use crate::filetype::{self, FileType, FileTypeSettings};
+use crate::pretty::Language;
+use crate::save::{self, SaveError, SaveStage};
 use crate::state::DisplayablePathBuf;
+use crate::task::Progress;
+use crate::wake_lock::WakeLock;
+use crate::{gutter, recent, syntax};
+
+/// Files at least this large take long enough to load that we hold a wake lock meanwhile.
+const LARGE_FILE_SIZE: u64 = 32 * MEBI as u64;
+/// Files at least this large are shown before they're fully loaded, see [`Loader`],
+/// or if that's not possible get a progress bar while they're loading, see [`ReadAhead`].
+const READ_AHEAD_SIZE: u64 = 4 * MEBI as u64;
+const READ_AHEAD_CHUNK: usize = 256 * KIBI;
+/// What [`Loader`] reads before the document is shown. Enough for the first screenful.
+const LOAD_HEAD: usize = 64 * KIBI;
+const LOAD_CHUNK: usize = MEBI;
+/// How many chunks the loader thread may read ahead of what was appended.
+const LOAD_QUEUE: usize = 8;
+/// How long appending the chunks may take per frame. Short enough for typing not to lag behind.
+const LOAD_SLICE: Duration = Duration::from_millis(20);
+/// How often the main loop checks for new chunks.
+const LOAD_POLL: Duration = Duration::from_millis(10);
+/// How long the background highlighting may take per frame, see [`TextBuffer::highlight_step`].
+const HIGHLIGHT_SLICE: Duration = Duration::from_millis(10);
 
 pub struct Document {
     pub buffer: RcTextBuffer,
@@ -18,25 +49,45 @@ pub struct Document {
     pub dir: Option<DisplayablePathBuf>,
     pub filename: String,
     pub file_id: Option<sys::FileId>,
+    /// The modification time of the file when we last read or wrote it.
+    pub file_mtime: Option<SystemTime>,
+    /// The modification time of the last outside change that we told the user about.
+    reported_mtime: Option<SystemTime>,
     pub new_file_counter: usize,
+    /// Per-document opt-out of autosave.
+    pub autosave: bool,
+    /// Whether an autosave already backed up the version that was last opened or saved.
+    /// Later autosaves skip the backup, so that they don't rotate the older backups away.
+    pub autosave_backed_up: bool,
+    /// The Android document that `path` is a working copy of. See [`crate::saf`].
+    pub saf_uri: Option<String>,
+    pub filetype: Option<&'static FileType>,
+    /// The settings for `filetype`, including those from the config.
+    pub filetype_settings: FileTypeSettings,
+    /// Appends the rest of a large file, while its beginning is already shown.
+    pub loading: Option<Loader>,
 }
 
 impl Document {
-    pub fn save(&mut self, new_path: Option<PathBuf>) -> apperr::Result<()> {
-        let path = new_path.as_deref().unwrap_or_else(|| self.path.as_ref().unwrap().as_path());
-        let mut file = DocumentManager::open_for_writing(path)?;
-
-        {
-            let mut tb = self.buffer.borrow_mut();
-            tb.write_file(&mut file)?;
+    pub fn save(&mut self, new_path: Option<PathBuf>, backup: &Backup) -> Result<(), SaveError> {
+        // Only a part of the file is there, and writing that would cut it off.
+        if self.loading.is_some() {
+            return Err(SaveError { stage: SaveStage::Write, err: apperr::APP_READ_ONLY });
         }
+        let path = new_path.as_deref().unwrap_or_else(|| self.path.as_ref().unwrap().as_path());
+        backup.create(path).map_err(SaveError::at(SaveStage::Backup))?;
+        save::save_file(path, &mut self.buffer.borrow_mut())?;
+        gutter::update_git_signs(&mut self.buffer.borrow_mut(), path);
 
         if let Ok(id) = sys::file_id(None, path) {
             self.file_id = Some(id);
         }
+        self.file_mtime = file_mtime(path);
+        self.autosave_backed_up = false;
 
         if let Some(path) = new_path {
             self.set_path(path);
+            self.saf_uri = None;
         }
 
         Ok(())
@@ -45,37 +96,178 @@ impl Document {
     pub fn reread(&mut self, encoding: Option<&'static str>) -> apperr::Result<()> {
         let path = self.path.as_ref().unwrap().as_path();
         let mut file = DocumentManager::open_for_reading(path)?;
+        self.loading = None;
 
         {
             let mut tb = self.buffer.borrow_mut();
             tb.read_file(&mut file, encoding)?;
+            gutter::update_git_signs(&mut tb, path);
         }
 
         if let Ok(id) = sys::file_id(None, path) {
             self.file_id = Some(id);
         }
+        self.file_mtime = file_mtime(path);
+        self.autosave_backed_up = false;
 
         Ok(())
     }
 
+    /// Returns `true` if the file was modified by someone else since we last read or wrote it.
+    pub fn is_changed_on_disk(&self) -> bool {
+        match &self.path {
+            Some(path) => self.file_mtime.is_some() && file_mtime(path) != self.file_mtime,
+            None => false,
+        }
+    }
+
+    /// Like [`Document::is_changed_on_disk`], but returns `true` only once per outside change.
+    pub fn take_unreported_change_on_disk(&mut self) -> bool {
+        let Some(path) = &self.path else {
+            return false;
+        };
+        let mtime = file_mtime(path);
+        if self.file_mtime.is_none() || mtime == self.file_mtime || mtime == self.reported_mtime {
+            return false;
+        }
+        self.reported_mtime = mtime;
+        true
+    }
+
     fn set_path(&mut self, path: PathBuf) {
         let filename = path.file_name().unwrap_or_default().to_string_lossy().into_owned();
         let dir = path.parent().map(ToOwned::to_owned).unwrap_or_default();
         self.filename = filename;
         self.dir = Some(DisplayablePathBuf::from_path(dir));
+        recent::add(&path);
         self.path = Some(path);
-        self.update_file_mode();
     }
 
-    fn update_file_mode(&mut self) {
-        let mut tb = self.buffer.borrow_mut();
-        tb.set_ruler(if self.filename == "COMMIT_EDITMSG" { 72 } else { 0 });
+    /// Detects the file type from the path and the contents.
+    fn detect_filetype(&self) -> Option<&'static FileType> {
+        let path = self.path.as_deref()?;
+        let tb = self.buffer.borrow();
+        let chunk = tb.read_forward(0);
+        let first_line = chunk.split(|&c| c == b'\n').next().unwrap_or_default();
+        filetype::detect(path, first_line)
+    }
+
+    fn set_filetype(&mut self, filetype: Option<&'static FileType>, settings: FileTypeSettings) {
+        {
+            let mut tb = self.buffer.borrow_mut();
+            tb.set_ruler(0);
+            settings.apply(&mut tb);
+            tb.set_syntax(filetype.and_then(syntax::for_filetype));
+        }
+        self.filetype = filetype;
+        self.filetype_settings = settings;
+    }
+
+    /// For commit messages, the width of the summary line if it's wider than 72 columns.
+    pub fn long_commit_summary(&self) -> Option<CoordType> {
+        if self.filetype?.name != "git-commit" {
+            return None;
+        }
+        let tb = self.buffer.borrow();
+        let chunk = tb.read_forward(0);
+        let summary = chunk.split(|&c| c == b'\n').next().unwrap_or_default();
+        let width = text_width(String::from_utf8_lossy(summary).trim_end());
+        (width > 72).then_some(width)
+    }
+
+    /// The line comment, or if there's none the block comment, for the file type.
+    pub fn comment_strings(&self) -> Option<(&str, &str)> {
+        let settings = &self.filetype_settings;
+        let filetype = self.filetype?;
+        let line = settings.line_comment.as_deref().unwrap_or(filetype.line_comment);
+        let (beg, end) = match &settings.block_comment {
+            Some((beg, end)) => (beg.as_str(), end.as_str()),
+            None => filetype.block_comment,
+        };
+        if !line.is_empty() {
+            Some((line, ""))
+        } else if !beg.is_empty() {
+            Some((beg, end))
+        } else {
+            None
+        }
+    }
+
+    /// Whether Format Document works: With a `formatter` or the built-in one, see [`crate::pretty`].
+    pub fn can_format(&self) -> bool {
+        self.filetype_settings.formatter.is_some() || self.pretty_language().is_some()
+    }
+
+    /// The language the built-in formatter formats the file type as, if any.
+    pub fn pretty_language(&self) -> Option<Language> {
+        self.filetype.and_then(|filetype| Language::for_filetype(filetype.name))
+    }
+
+    /// The line comment for the file type, or an empty string if it has none.
+    pub fn line_comment(&self) -> &str {
+        match self.comment_strings() {
+            Some((prefix, "")) => prefix,
+            _ => "",
+        }
+    }
+}
+
+/// The settings new documents start out with.
+#[derive(Clone, Copy)]
+pub struct DocumentDefaults {
+    pub tab_size: CoordType,
+    pub indent_with_tabs: bool,
+    pub word_wrap: bool,
+    pub line_numbers: bool,
+    pub show_whitespace: bool,
+    pub indent_guides: bool,
+    pub auto_close: bool,
+}
+
+impl DocumentDefaults {
+    fn apply(&self, tb: &mut TextBuffer) {
+        tb.set_tab_size(self.tab_size);
+        tb.set_indent_with_tabs(self.indent_with_tabs);
+        tb.set_word_wrap(self.word_wrap);
+        tb.set_whitespace_visible(self.show_whitespace);
+        tb.set_indent_guides_enabled(self.indent_guides);
+        tb.set_auto_close_enabled(self.auto_close);
+    }
+}
+
+impl Default for DocumentDefaults {
+    fn default() -> Self {
+        Self {
+            tab_size: 4,
+            indent_with_tabs: false,
+            word_wrap: false,
+            line_numbers: true,
+            show_whitespace: false,
+            indent_guides: true,
+            auto_close: true,
+        }
     }
 }
 
+/// How a file given on the command line is to be opened, see [`DocumentManager::add_file_path_with`].
+#[derive(Default, Clone, Copy)]
+pub struct OpenArgs {
+    /// From `+line[:col]`. Wins over a `file:line:col` suffix.
+    pub goto: Option<Point>,
+    /// From `--encoding=`. `None` to detect it.
+    pub encoding: Option<&'static str>,
+}
+
 #[derive(Default)]
 pub struct DocumentManager {
     list: LinkedList<Document>,
+    pub defaults: DocumentDefaults,
+    /// The settings for file types from the config.
+    pub filetypes: Vec<(&'static str, FileTypeSettings)>,
+    /// The columns of the gutter from the config. `None` for the default ones.
+    pub gutter: Option<Vec<GutterColumn>>,
+    /// The columns with a ruler from the config.
+    pub rulers: Vec<CoordType>,
 }
 
 impl DocumentManager {
@@ -108,19 +300,42 @@ impl DocumentManager {
         false
     }
 
+    #[inline]
+    pub fn iter(&self) -> impl Iterator<Item = &Document> {
+        self.list.iter()
+    }
+
+    #[inline]
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut Document> {
+        self.list.iter_mut()
+    }
+
+    /// Whether `buffer` belongs to one of the documents, i.e. wasn't closed yet.
+    pub fn contains(&self, buffer: &Weak<TextBufferCell>) -> bool {
+        self.list.iter().any(|doc| ptr::eq(Rc::as_ptr(&doc.buffer), buffer.as_ptr()))
+    }
+
     pub fn remove_active(&mut self) {
         self.list.pop_front();
     }
 
     pub fn add_untitled(&mut self) -> apperr::Result<&mut Document> {
-        let buffer = Self::create_buffer()?;
+        let buffer = self.create_buffer()?;
         let mut doc = Document {
             buffer,
             path: None,
             dir: Default::default(),
             filename: Default::default(),
             file_id: None,
+            file_mtime: None,
+            reported_mtime: None,
             new_file_counter: 0,
+            autosave: true,
+            autosave_backed_up: false,
+            saf_uri: None,
+            filetype: None,
+            filetype_settings: Default::default(),
+            loading: None,
         };
         self.gen_untitled_name(&mut doc);
 
@@ -140,7 +355,16 @@ impl DocumentManager {
     }
 
     pub fn add_file_path(&mut self, path: &Path) -> apperr::Result<&mut Document> {
+        self.add_file_path_with(path, OpenArgs::default())
+    }
+
+    pub fn add_file_path_with(
+        &mut self,
+        path: &Path,
+        args: OpenArgs,
+    ) -> apperr::Result<&mut Document> {
         let (path, goto) = Self::parse_filename_goto(path);
+        let goto = args.goto.or(goto);
         let path = path::normalize(path);
 
         let mut file = match Self::open_for_reading(&path) {
@@ -150,6 +374,7 @@ impl DocumentManager {
         };
 
         let file_id = if file.is_some() { Some(sys::file_id(file.as_ref(), &path)?) } else { None };
+        let file_mtime = file.as_ref().and_then(|f| f.metadata().ok()?.modified().ok());
 
         // Check if the file is already open.
         if file_id.is_some() && self.update_active(|doc| doc.file_id == file_id) {
@@ -160,18 +385,32 @@ impl DocumentManager {
             return Ok(doc);
         }
 
-        let buffer = Self::create_buffer()?;
-        {
-            if let Some(file) = &mut file {
-                let mut tb = buffer.borrow_mut();
-                tb.read_file(file, None)?;
-
-                if let Some(goto) = goto
-                    && goto != Default::default()
-                {
-                    tb.cursor_move_to_logical(goto);
+        let buffer = self.create_buffer()?;
+        let mut loading = None;
+        if let Some(file) = &mut file {
+            let len = file.metadata().map_or(0, |m| m.len());
+            // The loader only knows UTF-8.
+            if len >= READ_AHEAD_SIZE && args.encoding.is_none() {
+                loading = Loader::start(file, &buffer, len)?;
+            }
+            let mut tb = buffer.borrow_mut();
+            if loading.is_none() {
+                let _wake_lock = (len >= LARGE_FILE_SIZE).then(WakeLock::acquire);
+                tb.read_file(file, args.encoding)?;
+            }
+
+            if let Some(goto) = goto
+                && goto != Default::default()
+            {
+                tb.cursor_move_to_logical(goto);
+                // The line may not have been loaded yet.
+                if let Some(loader) = &mut loading {
+                    loader.goto = Some((goto, tb.cursor_logical_pos()));
                 }
             }
+        } else if let Some(encoding) = args.encoding {
+            // A new file is saved in it.
+            buffer.borrow_mut().set_encoding(encoding);
         }
 
         let mut doc = Document {
@@ -180,9 +419,22 @@ impl DocumentManager {
             dir: None,
             filename: Default::default(),
             file_id,
+            file_mtime,
+            reported_mtime: None,
             new_file_counter: 0,
+            autosave: true,
+            autosave_backed_up: false,
+            saf_uri: None,
+            filetype: None,
+            filetype_settings: Default::default(),
+            loading,
         };
         doc.set_path(path);
+        if let Some(path) = &doc.path {
+            gutter::update_git_signs(&mut doc.buffer.borrow_mut(), path);
+        }
+        let filetype = doc.detect_filetype();
+        doc.set_filetype(filetype, self.filetype_settings(filetype));
 
         if let Some(active) = self.active()
             && active.path.is_none()
@@ -198,6 +450,149 @@ impl DocumentManager {
         Ok(self.list.front_mut().unwrap())
     }
 
+    /// Whether any document is still loading, see [`Loader`].
+    pub fn is_loading(&self) -> bool {
+        self.list.iter().any(|doc| doc.loading.is_some())
+    }
+
+    /// Whether the active document is still being highlighted in the background.
+    pub fn is_highlighting(&self) -> bool {
+        self.active().is_some_and(|doc| doc.buffer.borrow().is_highlighting())
+    }
+
+    /// How long the main loop may block waiting for input: Briefly, while a [`Loader`] runs,
+    /// and not at all while there's highlighting left to do and the window is `focused`.
+    pub fn read_timeout(&self, focused: bool) -> Duration {
+        if focused && self.is_highlighting() {
+            Duration::ZERO
+        } else if self.is_loading() {
+            LOAD_POLL
+        } else {
+            Duration::MAX
+        }
+    }
+
+    /// Highlights the active document for a while. Returns whether it looks different now.
+    pub fn highlight_step(&self) -> bool {
+        let deadline = Instant::now() + HIGHLIGHT_SLICE;
+        self.active().is_some_and(|doc| doc.buffer.borrow_mut().highlight_step(deadline))
+    }
+
+    /// Appends what the [`Loader`]s have read since the last call. Returns whether anything
+    /// changed, and the errors of those that failed. Their documents stay incomplete and read-only.
+    pub fn poll_loading(&mut self) -> (bool, Vec<apperr::Error>) {
+        let deadline = Instant::now() + LOAD_SLICE;
+        let mut changed = false;
+        let mut errors = Vec::new();
+
+        for doc in &mut self.list {
+            let Some(loader) = &mut doc.loading else {
+                continue;
+            };
+            changed |= loader.step(deadline);
+            match loader.result {
+                None => continue,
+                Some(Ok(())) => {}
+                Some(Err(err)) => errors.push(err),
+            }
+            doc.loading = None;
+        }
+
+        (changed, errors)
+    }
+
+    /// Whether the file at `path` is open and has unsaved changes.
+    pub fn is_unsaved(&self, path: &Path) -> bool {
+        let Ok(id) = sys::file_id(None, path) else {
+            return false;
+        };
+        self.list
+            .iter()
+            .any(|doc| doc.file_id.as_ref() == Some(&id) && doc.buffer.borrow().is_dirty())
+    }
+
+    /// Rereads the documents of the files at `paths`, unless they have unsaved changes.
+    pub fn reread_paths(&mut self, paths: &[PathBuf]) -> Vec<apperr::Error> {
+        let ids: Vec<_> = paths.iter().filter_map(|path| sys::file_id(None, path).ok()).collect();
+        let mut errors = Vec::new();
+        for doc in &mut self.list {
+            if doc.file_id.as_ref().is_some_and(|id| ids.contains(id))
+                && !doc.buffer.borrow().is_dirty()
+                && let Err(err) = doc.reread(None)
+            {
+                errors.push(err);
+            }
+        }
+        errors
+    }
+
+    /// Follows a file or directory that was renamed, so that the documents in it
+    /// are saved to where they are now.
+    pub fn rename_path(&mut self, old: &Path, new: &Path) {
+        for doc in &mut self.list {
+            let Some(rest) = doc.path.as_deref().and_then(|path| path.strip_prefix(old).ok())
+            else {
+                continue;
+            };
+            let path = if rest.as_os_str().is_empty() { new.to_path_buf() } else { new.join(rest) };
+            doc.set_path(path);
+        }
+    }
+
+    /// Changes the file type of the active document, e.g. when the user picked one.
+    pub fn set_active_filetype(&mut self, filetype: Option<&'static FileType>) {
+        let settings = self.filetype_settings(filetype);
+        if let Some(doc) = self.list.front_mut() {
+            doc.set_filetype(filetype, settings);
+        }
+    }
+
+    /// Detects the file type of the active document again, as after "Save As".
+    pub fn redetect_active_filetype(&mut self) {
+        if let Some(doc) = self.list.front() {
+            self.set_active_filetype(doc.detect_filetype());
+        }
+    }
+
+    /// The built-in settings for a file type with those from the config applied on top.
+    /// Without rulers of its own, those of the editor are used.
+    fn filetype_settings(&self, filetype: Option<&'static FileType>) -> FileTypeSettings {
+        let mut settings = match filetype {
+            Some(filetype) => filetype::builtin_settings(filetype),
+            None => FileTypeSettings::default(),
+        };
+        if let Some(filetype) = filetype
+            && let Some((_, config)) =
+                self.filetypes.iter().find(|(name, _)| *name == filetype.name)
+        {
+            settings.merge(config);
+        }
+        settings.rulers.get_or_insert_with(|| self.rulers.clone());
+        settings
+    }
+
+    /// Applies changed defaults to the open documents. Their file type's settings still win.
+    pub fn apply_defaults(&self) {
+        for doc in &self.list {
+            let mut tb = doc.buffer.borrow_mut();
+            self.defaults.apply(&mut tb);
+            doc.filetype_settings.apply(&mut tb);
+        }
+    }
+
+    /// Applies a reloaded config to the open documents:
+    /// The defaults, the settings for their file type (with the rulers) and the gutter.
+    pub fn apply_config(&mut self) {
+        let gutter = self.gutter.clone().unwrap_or_else(gutter::default_columns);
+        let settings: Vec<_> =
+            self.list.iter().map(|doc| self.filetype_settings(doc.filetype)).collect();
+        for (doc, settings) in self.list.iter_mut().zip(settings) {
+            self.defaults.apply(&mut doc.buffer.borrow_mut());
+            doc.set_filetype(doc.filetype, settings);
+            doc.buffer.borrow_mut().set_gutter(gutter.clone());
+        }
+    }
+
     pub fn reflow_all(&self) {
         for doc in &self.list {
             let mut tb = doc.buffer.borrow_mut();
@@ -209,16 +604,15 @@ impl DocumentManager {
         File::open(path).map_err(apperr::Error::from)
     }
 
-    pub fn open_for_writing(path: &Path) -> apperr::Result<File> {
-        File::create(path).map_err(apperr::Error::from)
-    }
-
-    fn create_buffer() -> apperr::Result<RcTextBuffer> {
+    fn create_buffer(&self) -> apperr::Result<RcTextBuffer> {
         let buffer = TextBuffer::new_rc(false)?;
         {
             let mut tb = buffer.borrow_mut();
             tb.set_insert_final_newline(!cfg!(windows)); // As mandated by POSIX.
-            tb.set_margin_enabled(true);
+            self.defaults.apply(&mut tb);
+            tb.set_margin_enabled(self.defaults.line_numbers);
+            tb.set_gutter(self.gutter.clone().unwrap_or_else(gutter::default_columns));
+            tb.set_rulers(self.rulers.clone());
             tb.set_line_highlight_enabled(true);
         }
         Ok(buffer)
@@ -282,6 +676,159 @@ impl DocumentManager {
     }
 }
 
+/// Reads a large UTF-8 file on a thread of its own, a chunk at a time. The document
+/// shows the beginning right away and the rest is appended as it arrives, see
+/// [`DocumentManager::poll_loading`]. It can't be edited or saved until then.
+pub struct Loader {
+    buffer: RcTextBuffer,
+    chunks: Receiver<io::Result<Vec<u8>>>,
+    progress: Progress,
+    /// Where the cursor was asked to go, and where it could go before the rest was there.
+    goto: Option<(Point, Point)>,
+    /// `Some` once it's done.
+    result: Option<apperr::Result<()>>,
+    _wake_lock: Option<WakeLock>,
+}
+
+impl Loader {
+    /// Reads the beginning of `file` into `buffer` and starts the thread for the rest.
+    /// Returns `None` with the `file` rewound, if it's not UTF-8.
+    fn start(file: &mut File, buffer: &RcTextBuffer, len: u64) -> apperr::Result<Option<Self>> {
+        let mut head = Vec::new();
+        file.by_ref().take(LOAD_HEAD as u64).read_to_end(&mut head)?;
+        if !buffer.borrow_mut().read_loaded(&head, len as usize)? {
+            file.rewind()?;
+            return Ok(None);
+        }
+
+        let mut file = file.try_clone()?;
+        let (sender, chunks) = mpsc::sync_channel(LOAD_QUEUE);
+        thread::spawn(move || {
+            loop {
+                let mut chunk = vec![0; LOAD_CHUNK];
+                let res = match file.read(&mut chunk) {
+                    Ok(0) => break,
+                    Ok(read) => {
+                        chunk.truncate(read);
+                        Ok(chunk)
+                    }
+                    Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                    Err(err) => Err(err),
+                };
+                let failed = res.is_err();
+                // It's dropped when the document is closed.
+                if sender.send(res).is_err() || failed {
+                    break;
+                }
+            }
+        });
+
+        Ok(Some(Self {
+            buffer: buffer.clone(),
+            chunks,
+            progress: Progress { done: head.len() as u64, total: len },
+            goto: None,
+            result: None,
+            _wake_lock: (len >= LARGE_FILE_SIZE).then(WakeLock::acquire),
+        }))
+    }
+
+    pub fn progress(&self) -> Progress {
+        self.progress
+    }
+
+    /// Appends the chunks that are there until `deadline`. Returns whether it appended any.
+    fn step(&mut self, deadline: Instant) -> bool {
+        let mut tb = self.buffer.borrow_mut();
+        let mut changed = false;
+
+        while self.result.is_none() && Instant::now() < deadline {
+            match self.chunks.try_recv() {
+                Ok(Ok(chunk)) => {
+                    tb.append_loaded(&chunk);
+                    self.progress.done += chunk.len() as u64;
+                    changed = true;
+                }
+                Ok(Err(err)) => self.result = Some(Err(err.into())),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => self.result = Some(Ok(())),
+            }
+        }
+
+        if let Some(result) = &self.result {
+            tb.finish_loading();
+            // Saving what's there would cut the file off.
+            if result.is_err() {
+                tb.set_read_only(true);
+            }
+            // Unless it was moved meanwhile, the cursor can now go where it was asked to.
+            if let Some((goto, pos)) = self.goto
+                && tb.cursor_logical_pos() == pos
+            {
+                tb.cursor_move_to_logical(goto);
+            }
+            changed = true;
+        }
+        changed
+    }
+}
+
+/// Reads a large file once before it's opened, a slice at a time, so that it can
+/// show its progress, see [`crate::task`]. The actual read is then served from
+/// the cache, which matters on slow storage like Android's shared storage.
+pub struct ReadAhead {
+    pub path: PathBuf,
+    file: File,
+    buf: Vec<u8>,
+    progress: Progress,
+}
+
+impl ReadAhead {
+    /// Returns `None` if the file can be opened right away, because it's small,
+    /// because it's UTF-8 and [`Loader`] takes care of it, or because it can't be read.
+    /// Opening it will report that error.
+    pub fn new(path: &Path) -> Option<Self> {
+        let mut file = File::open(path).ok()?;
+        let len = file.metadata().ok()?.len();
+        if len < READ_AHEAD_SIZE {
+            return None;
+        }
+
+        let mut bom = [0; 4];
+        file.read_exact(&mut bom).ok()?;
+        if detect_bom(&bom).is_none_or(|encoding| encoding == "UTF-8") {
+            return None;
+        }
+        file.rewind().ok()?;
+
+        Some(Self {
+            path: path.to_path_buf(),
+            file,
+            buf: vec![0; READ_AHEAD_CHUNK],
+            progress: Progress { done: 0, total: len },
+        })
+    }
+
+    pub fn progress(&self) -> Progress {
+        self.progress
+    }
+
+    /// Reads until `deadline`. Returns whether it's done.
+    pub fn step(&mut self, deadline: Instant) -> bool {
+        while Instant::now() < deadline {
+            match self.file.read(&mut self.buf) {
+                Ok(0) | Err(_) => return true,
+                Ok(read) => self.progress.done += read as u64,
+            }
+        }
+        false
+    }
+}
+
+fn file_mtime(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).ok()?.modified().ok()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -312,4 +859,47 @@ mod tests {
         assert_eq!(parse("file.txt:10"), ("file.txt", Some(Point { x: 0, y: 9 })));
         assert_eq!(parse("file.txt:10:5"), ("file.txt", Some(Point { x: 4, y: 9 })));
     }
+
+    #[test]
+    fn test_loader() {
+        stdext::arena::init(16 * MEBI).unwrap();
+
+        let path = std::env::temp_dir().join(format!("edit-loader-{}.txt", std::process::id()));
+        let line = "0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcde\n";
+        let lines = READ_AHEAD_SIZE as usize / line.len() + 1000;
+        fs::write(&path, line.repeat(lines)).unwrap();
+
+        let mut documents = DocumentManager::default();
+        let goto = Point { x: 2, y: lines as CoordType - 10 };
+        let mut arg = path.clone().into_os_string();
+        arg.push(format!(":{}:{}", goto.y + 1, goto.x + 1));
+        let doc = documents.add_file_path(Path::new(&arg)).unwrap();
+        assert!(doc.loading.is_some());
+        {
+            let tb = doc.buffer.borrow();
+            assert!(tb.is_loading());
+            assert_eq!(tb.text_length(), LOAD_HEAD);
+            assert!(tb.cursor_logical_pos().y < goto.y);
+        }
+
+        // Saving now would cut the file off.
+        let err = doc.save(None, &Backup::default()).unwrap_err();
+        assert_eq!(err.err, apperr::APP_READ_ONLY);
+
+        let deadline = Instant::now() + Duration::from_secs(60);
+        while documents.is_loading() && Instant::now() < deadline {
+            let (_, errors) = documents.poll_loading();
+            assert!(errors.is_empty());
+        }
+
+        let doc = documents.active().unwrap();
+        let tb = doc.buffer.borrow();
+        assert!(!tb.is_loading());
+        assert!(!tb.is_dirty());
+        assert_eq!(tb.text_length(), line.len() * lines);
+        assert_eq!(tb.logical_line_count(), lines as CoordType + 1);
+        assert_eq!(tb.cursor_logical_pos(), goto);
+
+        _ = fs::remove_file(&path);
+    }
 }
@@ -0,0 +1,206 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+use std::env;
+use std::path::{Path, PathBuf};
+
+use edit::framebuffer::IndexedColor;
+use edit::helpers::*;
+use edit::input::vk;
+use edit::tui::*;
+
+use crate::draw_filepicker::{file_picker_set_dir, list_dir};
+use crate::draw_statusbar::text_width;
+use crate::draw_task::open_file;
+use crate::state::*;
+use crate::{ctags, paths};
+
+/// The dropdowns show at most this many rows and scroll the rest.
+const MENU_HEIGHT: CoordType = 12;
+
+/// Whether there's a breadcrumb bar, taking up a row above the editor.
+pub fn breadcrumbs_visible(state: &State) -> bool {
+    state.breadcrumbs && state.documents.active().is_some()
+}
+
+/// Draws the path of the active document, and the symbol at the cursor if there's
+/// a `tags` file, see [`crate::ctags`]. Each segment opens a list of its siblings.
+pub fn draw_breadcrumbs(ctx: &mut Context, state: &mut State) {
+    let Some(doc) = state.documents.active() else {
+        return;
+    };
+    let line = doc.buffer.borrow().cursor_logical_pos().y + 1;
+    let mut segments = match &doc.path {
+        Some(path) => path_segments(path),
+        None => vec![(doc.filename.clone(), None)],
+    };
+
+    if let Some(path) = &doc.path
+        && state.breadcrumb_tags.as_ref().is_none_or(|(p, _)| p != path)
+    {
+        state.breadcrumb_tags = Some((path.clone(), ctags::load(path)));
+    }
+    let tags = match (&doc.path, &state.breadcrumb_tags) {
+        (Some(_), Some((_, tags))) => tags.as_slice(),
+        _ => &[],
+    };
+    let symbol = ctags::enclosing(tags, line).map(|i| {
+        segments.push((tags[i].name.clone(), None));
+        segments.len() - 1
+    });
+
+    let mut clicked = None;
+    let mut chosen = None;
+    let mut close = false;
+
+    ctx.table_begin("breadcrumbs");
+    ctx.attr_background_rgba(ctx.indexed_alpha(IndexedColor::Black, 1, 4));
+    ctx.attr_intrinsic_size(Size { width: COORD_TYPE_SAFE_MAX, height: 1 });
+    ctx.attr_padding(Rect::two(0, 1));
+    {
+        ctx.table_next_row();
+        for (i, (text, _)) in segments.iter().enumerate() {
+            ctx.next_block_id_mixin(i as u64);
+            if i > 0 {
+                ctx.label("separator", " › ");
+                ctx.next_block_id_mixin(i as u64);
+            }
+            if ctx.button("segment", text, ButtonStyle::default().bracketed(false)) {
+                clicked = Some(i);
+            }
+            if let Some(menu) = &state.breadcrumb_menu
+                && menu.segment == i
+            {
+                (chosen, close) = draw_breadcrumb_menu(ctx, menu);
+            }
+        }
+    }
+    ctx.table_end();
+
+    if let Some(i) = clicked {
+        let open = state.breadcrumb_menu.as_ref().is_none_or(|menu| menu.segment != i);
+        state.breadcrumb_menu = open.then(|| {
+            let entries = if symbol == Some(i) {
+                tags.iter().map(|tag| BreadcrumbEntry::Symbol(tag.name.clone(), tag.line)).collect()
+            } else {
+                segments[i].1.as_deref().map_or(Vec::new(), siblings)
+            };
+            BreadcrumbMenu { segment: i, entries }
+        });
+        ctx.needs_rerender();
+    } else if close {
+        state.breadcrumb_menu = None;
+        state.wants_editor_focus = true;
+        ctx.needs_rerender();
+    }
+
+    if let Some(i) = chosen {
+        let entry = state.breadcrumb_menu.take().unwrap().entries.swap_remove(i);
+        state.wants_editor_focus = true;
+        ctx.needs_rerender();
+        match entry {
+            BreadcrumbEntry::File(path) => {
+                if let Err(err) = open_file(state, &path) {
+                    error_log_add(ctx, state, err);
+                }
+            }
+            BreadcrumbEntry::Dir(path) => {
+                file_picker_set_dir(state, path);
+                state.wants_file_picker = StateFilePicker::Open;
+            }
+            BreadcrumbEntry::Symbol(_, line) => {
+                if let Some(doc) = state.documents.active() {
+                    let mut tb = doc.buffer.borrow_mut();
+                    tb.cursor_move_to_logical(Point { x: 0, y: line - 1 });
+                    tb.make_cursor_visible();
+                }
+            }
+        }
+    }
+}
+
+/// Draws the dropdown below the segment that was just drawn.
+/// Returns the entry that was picked, and whether it should close without one.
+fn draw_breadcrumb_menu(ctx: &mut Context, menu: &BreadcrumbMenu) -> (Option<usize>, bool) {
+    let labels: Vec<_> = menu.entries.iter().map(BreadcrumbEntry::label).collect();
+    let width = labels.iter().map(|label| text_width(label)).max().unwrap_or(0) + 4;
+    let height = (labels.len() as CoordType).clamp(1, MENU_HEIGHT);
+    let mut chosen = None;
+
+    ctx.block_begin("menu");
+    ctx.attr_float(FloatSpec {
+        anchor: Anchor::Last,
+        gravity_x: 0.0,
+        gravity_y: 0.0,
+        offset_x: 0.0,
+        offset_y: 1.0,
+    });
+    ctx.attr_border();
+    ctx.attr_focus_well();
+    ctx.focus_on_first_present();
+    ctx.attr_intrinsic_size(Size { width: width.min(ctx.size().width - 2), height });
+    {
+        ctx.scrollarea_begin("scrollarea", Size { width: 0, height });
+        ctx.inherit_focus();
+        {
+            ctx.list_begin("entries");
+            ctx.inherit_focus();
+            for (i, label) in labels.iter().enumerate() {
+                ctx.next_block_id_mixin(i as u64);
+                if ctx.list_item(i == 0, label) == ListSelection::Activated {
+                    chosen = Some(i);
+                }
+                ctx.attr_overflow(Overflow::TruncateTail);
+            }
+            ctx.list_end();
+        }
+        ctx.scrollarea_end();
+    }
+    ctx.block_end();
+
+    // Like the context menu: Clicking elsewhere takes the focus away and closes it.
+    let close = ctx.consume_shortcut(vk::ESCAPE) || !ctx.contains_focus();
+    (chosen, close && chosen.is_none())
+}
+
+/// Splits `path` into what's shown, and the path that each part stands for.
+/// It's relative to the working directory or the home directory, if it's in there.
+fn path_segments(path: &Path) -> Vec<(String, Option<PathBuf>)> {
+    let cwd = env::current_dir().unwrap_or_default();
+    let home = PathBuf::from(paths::expand("~"));
+
+    let (mut segments, base, rest) = if let Ok(rest) = path.strip_prefix(&cwd)
+        && let Some(name) = cwd.file_name()
+    {
+        (vec![(name.to_string_lossy().into_owned(), Some(cwd.clone()))], cwd.clone(), rest)
+    } else if let Ok(rest) = path.strip_prefix(&home) {
+        (vec![("~".to_string(), Some(home.clone()))], home.clone(), rest)
+    } else {
+        let root = PathBuf::from("/");
+        let rest = path.strip_prefix(&root).unwrap_or(path);
+        (Vec::new(), root, rest)
+    };
+
+    let mut current = base;
+    for component in rest.components() {
+        current.push(component);
+        segments
+            .push((component.as_os_str().to_string_lossy().into_owned(), Some(current.clone())));
+    }
+    segments
+}
+
+/// What's next to `path` in its folder: Folders first, then files.
+fn siblings(path: &Path) -> Vec<BreadcrumbEntry> {
+    let Some(dir) = path.parent() else {
+        return Vec::new();
+    };
+    let mut dirs_files = [Vec::new(), Vec::new()];
+    _ = list_dir(dir, &mut dirs_files);
+
+    let [dirs, files] = dirs_files;
+    dirs.iter()
+        .map(|name| BreadcrumbEntry::Dir(dir.join(name.as_path())))
+        .chain(files.iter().map(|name| BreadcrumbEntry::File(dir.join(name.as_path()))))
+        .collect()
+}
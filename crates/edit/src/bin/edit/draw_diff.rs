@@ -0,0 +1,162 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+use edit::framebuffer::{Attributes, IndexedColor};
+use edit::helpers::*;
+use edit::input::{kbmod, vk};
+use edit::oklab::StraightRgba;
+use edit::tui::*;
+
+use crate::diff::Kind;
+use crate::localization::*;
+use crate::state::*;
+
+/// Draws the side by side diff of `edit -d`, `height` rows tall, in place of the editor.
+pub fn draw_diff(ctx: &mut Context, state: &mut State, height: CoordType) {
+    let Some(diff) = &state.diff else {
+        return;
+    };
+    let dim = ctx.indexed_alpha(IndexedColor::Foreground, 1, 2);
+    let width = ctx.size().width;
+    let half = (width - 1) / 2;
+    // Less the names above and the hint below.
+    let page = (height - 2).max(1) as usize;
+    let end = (diff.scroll + page).min(diff.rows.len());
+    // Wide enough for the line numbers of either file.
+    let digits = diff.left.len().max(diff.right.len()).max(1).ilog10() as usize + 1;
+
+    ctx.block_begin("diff");
+    ctx.attr_focusable();
+    ctx.inherit_focus();
+    {
+        ctx.table_begin("rows");
+        ctx.table_set_columns(&[half, width - 1 - half]);
+        ctx.table_set_cell_gap(Size { width: 1, height: 0 });
+
+        ctx.table_next_row();
+        for (classname, name) in [("left", &diff.left_name), ("right", &diff.right_name)] {
+            ctx.styled_label_begin(classname);
+            ctx.styled_label_set_attributes(Attributes::Bold);
+            ctx.styled_label_add_text(name);
+            ctx.styled_label_end();
+            ctx.attr_overflow(Overflow::TruncateHead);
+        }
+
+        for (i, row) in diff.rows[diff.scroll..end].iter().enumerate() {
+            ctx.table_next_row();
+            ctx.next_block_id_mixin(i as u64);
+            let (left_bg, right_bg) = match row.kind {
+                Kind::Same => (StraightRgba::zero(), StraightRgba::zero()),
+                Kind::Removed => (removed(ctx), missing(ctx)),
+                Kind::Added => (missing(ctx), added(ctx)),
+                Kind::Changed => (removed(ctx), added(ctx)),
+            };
+            for (classname, line, lines, bg) in [
+                ("left", row.left, &diff.left, left_bg),
+                ("right", row.right, &diff.right, right_bg),
+            ] {
+                ctx.styled_label_begin(classname);
+                ctx.attr_background_rgba(bg);
+                if let Some(line) = line {
+                    ctx.styled_label_set_foreground(dim);
+                    ctx.styled_label_add_text(&format!("{:>digits$} ", line + 1));
+                    ctx.styled_label_set_foreground(ctx.indexed(IndexedColor::Foreground));
+                    ctx.styled_label_add_text(&visible_text(&lines[line], diff.scroll_x));
+                }
+                ctx.styled_label_end();
+            }
+        }
+        ctx.table_end();
+    }
+    ctx.block_end();
+    let focused = ctx.contains_focus();
+
+    let count = diff.hunks.len().to_string();
+    let hint = match diff.hunk {
+        _ if diff.hunks.is_empty() => loc(LocId::DiffNoChanges).to_string(),
+        Some(hunk) => loc(LocId::DiffHunk)
+            .replace("{index}", &(hunk + 1).to_string())
+            .replace("{count}", &count),
+        None => loc(LocId::DiffHunks).replace("{count}", &count),
+    };
+    ctx.label("hint", &hint);
+    ctx.attr_foreground_rgba(dim);
+    ctx.attr_overflow(Overflow::TruncateTail);
+
+    if focused {
+        draw_handle_diff_input(ctx, state, page);
+    }
+}
+
+fn draw_handle_diff_input(ctx: &mut Context, state: &mut State, page: usize) {
+    let Some(diff) = &mut state.diff else {
+        return;
+    };
+    let last = diff.rows.len().saturating_sub(page);
+    let mut scroll = diff.scroll as isize;
+    let mut scroll_x = diff.scroll_x as isize;
+
+    if let Some(delta) = ctx.scroll_input() {
+        scroll += delta.y;
+        scroll_x += delta.x;
+        ctx.set_input_consumed();
+    } else if let Some(key) = ctx.keyboard_input() {
+        // The hunks are put a few lines down, with some context above them.
+        let mut hunk = |forward| diff.goto_hunk(forward).map(|row| row.saturating_sub(3) as isize);
+        match key {
+            vk::UP => scroll -= 1,
+            vk::DOWN => scroll += 1,
+            vk::LEFT => scroll_x -= 8,
+            vk::RIGHT => scroll_x += 8,
+            vk::PRIOR => scroll -= page as isize,
+            vk::NEXT | vk::SPACE => scroll += page as isize,
+            vk::HOME => scroll = 0,
+            vk::END => scroll = last as isize,
+            vk::N | vk::F7 => scroll = hunk(true).unwrap_or(scroll),
+            key if key == kbmod::SHIFT | vk::N || key == kbmod::SHIFT | vk::F7 => {
+                scroll = hunk(false).unwrap_or(scroll)
+            }
+            vk::P => scroll = hunk(false).unwrap_or(scroll),
+            vk::ESCAPE | vk::Q => {
+                close_diff(state);
+                ctx.set_input_consumed();
+                ctx.needs_rerender();
+                return;
+            }
+            _ => return,
+        }
+        ctx.set_input_consumed();
+    } else {
+        return;
+    }
+
+    diff.scroll = scroll.clamp(0, last as isize) as usize;
+    diff.scroll_x = scroll_x.max(0) as usize;
+    ctx.needs_rerender();
+}
+
+/// Closes the diff and, with nothing else open (as for `git difftool`), edit as well.
+fn close_diff(state: &mut State) {
+    state.diff = None;
+    if state.documents.len() == 0 {
+        state.wants_exit = true;
+    }
+}
+
+/// The text of a line from column `scroll_x` on, with the tabs expanded.
+fn visible_text(line: &str, scroll_x: usize) -> String {
+    line.replace('\t', "    ").chars().skip(scroll_x).collect()
+}
+
+fn removed(ctx: &Context) -> StraightRgba {
+    ctx.indexed_alpha(IndexedColor::Red, 1, 4)
+}
+
+fn added(ctx: &Context) -> StraightRgba {
+    ctx.indexed_alpha(IndexedColor::Green, 1, 4)
+}
+
+/// The side of a row where a line is missing.
+fn missing(ctx: &Context) -> StraightRgba {
+    ctx.indexed_alpha(IndexedColor::BrightBlack, 1, 4)
+}
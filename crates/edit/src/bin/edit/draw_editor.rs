@@ -2,15 +2,33 @@
 // Licensed under the MIT License.
 
 use std::num::ParseIntError;
+use std::path::Path;
 
+use edit::buffer::{Codec, CodecError, FindMatch, SearchOptions, TextBuffer, surround_delimiters};
 use edit::framebuffer::IndexedColor;
 use edit::helpers::*;
 use edit::icu;
-use edit::input::{kbmod, vk};
+use edit::input::{InputKey, kbmod, vk};
 use edit::tui::*;
+use edit::unicode::{char_name, format_codepoint, parse_codepoint, search_char_names};
 
+use crate::documents::OpenArgs;
+use crate::draw_breadcrumbs::breadcrumbs_visible;
+use crate::draw_diff::draw_diff;
+use crate::draw_filetree::draw_filetree;
+use crate::draw_markdown::draw_markdown_preview;
+use crate::draw_runner::runner_height;
+use crate::draw_terminal::terminal_height;
+use crate::draw_welcome::draw_welcome;
+use crate::goto_this::Target;
+use crate::inspect::{grapheme_at_cursor, utf8_bytes};
 use crate::localization::*;
+use crate::notify::Panel;
+use crate::pretty::{self, Language};
+use crate::registers::preview;
 use crate::state::*;
+use crate::vim::VimCommand;
+use crate::{autosave, config, diff, goto_this, open_in_app};
 
 pub fn draw_editor(ctx: &mut Context, state: &mut State) {
     if !matches!(state.wants_search.kind, StateSearchKind::Hidden | StateSearchKind::Disabled) {
@@ -19,21 +37,140 @@ pub fn draw_editor(ctx: &mut Context, state: &mut State) {
 
     let size = ctx.size();
     // TODO: The layout code should be able to just figure out the height on its own.
-    let height_reduction = match state.wants_search.kind {
+    let mut height_reduction = match state.wants_search.kind {
         StateSearchKind::Search => 4,
         StateSearchKind::Replace => 5,
         _ => 2,
     };
+    if state.softkeys.enabled {
+        height_reduction += 1;
+    }
+    if breadcrumbs_visible(state) {
+        height_reduction += 1;
+    }
+    height_reduction += terminal_height(ctx, state);
+    height_reduction += runner_height(ctx, state);
 
-    if let Some(doc) = state.documents.active() {
+    // The file tree goes left of the editor, which takes up the rest of the width.
+    let sidebar = state.filetree.visible;
+    if sidebar {
+        ctx.table_begin("workspace");
+        ctx.table_set_columns(&[state.filetree.width, COORD_TYPE_SAFE_MAX]);
+        ctx.table_set_cell_gap(Size { width: 1, height: 0 });
+        ctx.inherit_focus();
+        ctx.table_next_row();
+        ctx.inherit_focus();
+        draw_filetree(ctx, state, size.height - height_reduction);
+    }
+
+    if state.back.is_enabled() && ctx.is_next_block_focused("textarea") {
+        draw_handle_back(ctx, state);
+    }
+    if state.vim.enabled && ctx.is_next_block_focused("textarea") {
+        draw_handle_vim(ctx, state);
+    }
+
+    if state.diff.is_some() {
+        state.welcome_recent = None;
+        draw_diff(ctx, state, size.height - height_reduction);
+    } else if state.markdown_preview.is_some() && state.documents.active().is_some() {
+        state.welcome_recent = None;
+        draw_markdown_preview(ctx, state, size.height - height_reduction);
+    } else if let Some(doc) = state.documents.active() {
+        // The recent files may have changed by the time the welcome screen shows up again.
+        state.welcome_recent = None;
+        // Line numbers take up too much of a narrow screen.
+        let line_numbers = state.documents.defaults.line_numbers && !is_compact(ctx);
+        doc.buffer.borrow_mut().set_margin_enabled(line_numbers);
         ctx.textarea("textarea", doc.buffer.clone());
         ctx.inherit_focus();
+        if let Some(pos) = ctx.context_menu_input() {
+            state.wants_context_menu = Some(pos);
+            ctx.needs_rerender();
+        }
     } else {
-        ctx.block_begin("empty");
-        ctx.block_end();
+        draw_welcome(ctx, state, size.height - height_reduction);
+    }
+    if state.wants_editor_focus {
+        state.wants_editor_focus = false;
+        ctx.steal_focus();
     }
 
     ctx.attr_intrinsic_size(Size { width: 0, height: size.height - height_reduction });
+
+    if sidebar {
+        ctx.table_end();
+    }
+}
+
+/// Handles Esc in the editor once there's nothing left to cancel. See [`crate::back`].
+fn draw_handle_back(ctx: &mut Context, state: &mut State) {
+    if ctx.keyboard_input() != Some(vk::ESCAPE)
+        || (state.vim.enabled && !state.vim.is_idle())
+        || state.documents.active().is_some_and(|doc| doc.buffer.borrow().has_selection())
+    {
+        return;
+    }
+
+    if matches!(state.wants_search.kind, StateSearchKind::Search | StateSearchKind::Replace) {
+        state.wants_search.kind = StateSearchKind::Hidden;
+    } else if state.back.press() {
+        state.wants_exit = true;
+    }
+    ctx.set_input_consumed();
+    ctx.needs_rerender();
+}
+
+/// Routes the input through the Vim emulation before the textarea gets to see it.
+fn draw_handle_vim(ctx: &mut Context, state: &mut State) {
+    let Some(doc) = state.documents.active() else {
+        return;
+    };
+
+    let consumed = {
+        let mut tb = doc.buffer.borrow_mut();
+        let consumed = if let Some(text) = ctx.text_input() {
+            state.vim.handle_text(&mut tb, ctx.clipboard_mut(), text)
+        } else if let Some(key) = ctx.keyboard_input() {
+            state.vim.handle_key(&mut tb, ctx.clipboard_mut(), key)
+        } else {
+            false
+        };
+        if consumed {
+            tb.make_cursor_visible();
+        }
+        consumed
+    };
+
+    if consumed {
+        ctx.set_input_consumed();
+        ctx.needs_rerender();
+    }
+
+    match state.vim.take_command() {
+        Some(VimCommand::Save) => state.wants_save = true,
+        Some(VimCommand::Close) => state.wants_close = true,
+        Some(VimCommand::ForceClose) => state.documents.remove_active(),
+        Some(VimCommand::SaveClose) => {
+            draw_handle_save(ctx, state);
+            state.wants_close = true;
+        }
+        Some(VimCommand::Search) => {
+            state.wants_search.kind = StateSearchKind::Search;
+            state.wants_search.focus = true;
+        }
+        Some(VimCommand::SearchNext) => search_execute(ctx, state, SearchAction::Search),
+        Some(VimCommand::ReloadConfig) => state.wants_config_reload = true,
+        Some(VimCommand::Digraph) => state.digraph = StateDigraph::First,
+        Some(VimCommand::WriteStdout) => {
+            if let Some(doc) = state.documents.active() {
+                state.stdout_buffer = Some(doc.buffer.clone());
+                let msg = loc(LocId::WriteStdoutPending).to_string();
+                toast_add(ctx, state, crate::toast::Severity::Info, msg);
+            }
+        }
+        None => {}
+    }
 }
 
 fn draw_search(ctx: &mut Context, state: &mut State) {
@@ -213,10 +350,31 @@ pub fn search_execute(ctx: &mut Context, state: &mut State, action: SearchAction
 }
 
 pub fn draw_handle_save(ctx: &mut Context, state: &mut State) {
+    if let Some(doc) = state.documents.active()
+        && doc.path.is_some()
+        && doc.filetype_settings.format_on_save == Some(true)
+    {
+        format(ctx, state, false);
+    }
+
+    let mut long_summary = None;
     if let Some(doc) = state.documents.active_mut() {
-        if doc.path.is_some() {
-            if let Err(err) = doc.save(None) {
-                error_log_add(ctx, state, err);
+        if let Some(path) = doc.path.clone() {
+            match doc.save(None, &state.backup) {
+                Ok(()) => {
+                    autosave::remove_swap_file(&path);
+                    if let Some(uri) = &doc.saf_uri {
+                        state.saf.write_back(uri, &path);
+                    }
+                    if let Some(command) = &state.after_save {
+                        config::run_after_save(command, &path);
+                    }
+                    if state.filetree.visible {
+                        state.filetree.refresh();
+                    }
+                    long_summary = doc.long_commit_summary();
+                }
+                Err(err) => error_log_add_message(ctx, state, err.to_string()),
             }
         } else {
             // No path? Show the file picker.
@@ -226,9 +384,868 @@ pub fn draw_handle_save(ctx: &mut Context, state: &mut State) {
         }
     }
 
+    if let Some(width) = long_summary {
+        let msg = loc(LocId::CommitSummaryTooLong).replace("{width}", &width.to_string());
+        toast_add(ctx, state, crate::toast::Severity::Warning, msg);
+    }
     state.wants_save = false;
 }
 
+/// Runs the file type's formatter on the document and replaces its contents with the result,
+/// or for JSON and XML without one the built-in formatter.
+pub fn draw_handle_format(ctx: &mut Context, state: &mut State) {
+    state.wants_format = false;
+    format(ctx, state, false);
+}
+
+/// Selection > Pretty-Print JSON and XML, on the selection or without one the whole document.
+pub fn draw_handle_pretty_print(ctx: &mut Context, state: &mut State) {
+    let Some(language) = state.wants_pretty_print.take() else {
+        return;
+    };
+    let selection = state.documents.active().is_some_and(|doc| doc.buffer.borrow().has_selection());
+    pretty_print(ctx, state, language, selection);
+}
+
+/// Formats the document, or with `selection` only the selected text.
+fn format(ctx: &mut Context, state: &mut State, selection: bool) {
+    let Some(doc) = state.documents.active() else {
+        return;
+    };
+    if doc.filetype_settings.formatter.is_none() {
+        if let Some(language) = doc.pretty_language() {
+            pretty_print(ctx, state, language, selection);
+        }
+        return;
+    }
+    let mut tb = doc.buffer.borrow_mut();
+    let text = if selection {
+        match tb.extract_user_selection(false) {
+            Some(text) => text,
+            None => return,
+        }
+    } else {
+        open_in_app::buffer_text(&tb)
+    };
+
+    match config::run_formatter(&doc.filetype_settings, &text, &doc.filename) {
+        Some(formatted) if formatted != text => {
+            apply_formatted(&mut tb, &text, formatted, selection);
+            ctx.needs_rerender();
+        }
+        Some(_) => {}
+        None => {
+            drop(tb);
+            error_log_add_message(ctx, state, loc(LocId::FormatFailed).to_string());
+        }
+    }
+}
+
+/// Reindents the selection, or without `selection` everything, as `language` with the
+/// document's indentation. If it's malformed, only the cursor moves: To where it goes wrong.
+fn pretty_print(ctx: &mut Context, state: &mut State, language: Language, selection: bool) {
+    let Some(doc) = state.documents.active() else {
+        return;
+    };
+    let mut tb = doc.buffer.borrow_mut();
+    let (start, text) = if selection {
+        match (tb.selection_range(), tb.extract_user_selection(false)) {
+            (Some((beg, _)), Some(text)) => (beg.offset, text),
+            _ => return,
+        }
+    } else {
+        (0, open_in_app::buffer_text(&tb))
+    };
+    let indent =
+        if tb.indent_with_tabs() { "\t".to_string() } else { " ".repeat(tb.tab_size() as usize) };
+
+    let result = match str::from_utf8(&text) {
+        Ok(s) => pretty::pretty_print(language, s, &indent),
+        Err(err) => Err(err.valid_up_to()),
+    };
+    match result {
+        Ok(output) => {
+            if output.as_bytes() != text {
+                apply_formatted(&mut tb, &text, output.into_bytes(), selection);
+            }
+        }
+        Err(offset) => {
+            tb.clear_selection();
+            tb.cursor_move_to_offset(start + offset);
+            drop(tb);
+            let msg = loc(LocId::PrettyPrintFailed).replace("{language}", language.name());
+            toast_add(ctx, state, crate::toast::Severity::Warning, msg);
+        }
+    }
+    ctx.needs_rerender();
+}
+
+/// Replaces the selection, or without `selection` everything, which was `text`, with the
+/// `output` of a formatter. Only the lines that differ are edited, all as one undo step,
+/// and the cursor stays on its line, or at the start of what that line became.
+fn apply_formatted(tb: &mut TextBuffer, text: &[u8], mut output: Vec<u8>, selection: bool) {
+    let (start, start_y) = match tb.selection_range() {
+        Some((beg, _)) if selection => (beg.offset, beg.logical_pos.y),
+        _ => (0, 0),
+    };
+    // Formatters end the output with a newline, even if the selection ended mid-line.
+    while selection && !text.ends_with(b"\n") && matches!(output.last(), Some(b'\n' | b'\r')) {
+        output.pop();
+    }
+
+    let old: Vec<&[u8]> = text.split_inclusive(|&b| b == b'\n').collect();
+    let new: Vec<&[u8]> = output.split_inclusive(|&b| b == b'\n').collect();
+    let offsets = |lines: &[&[u8]]| {
+        let mut offsets = vec![0];
+        offsets.extend(lines.iter().scan(0, |off, line| {
+            *off += line.len();
+            Some(*off)
+        }));
+        offsets
+    };
+    let (old_offsets, new_offsets) = (offsets(&old), offsets(&new));
+
+    let hunks = diff::line_hunks(&old, &new);
+    let edits: Vec<FindMatch> = hunks
+        .iter()
+        .map(|(left, right)| FindMatch {
+            range: start + old_offsets[left.start]..start + old_offsets[left.end],
+            line: start_y + left.start as CoordType,
+            line_offset: start + old_offsets[left.start],
+            line_text: Vec::new(),
+            replacement: output[new_offsets[right.start]..new_offsets[right.end]].to_vec(),
+        })
+        .collect();
+
+    let cursor = tb.cursor_logical_pos();
+    let y = (cursor.y - start_y).max(0) as usize;
+    let mut new_y = y as CoordType;
+    for (left, right) in &hunks {
+        if y >= left.end {
+            new_y += right.len() as CoordType - left.len() as CoordType;
+        } else {
+            if y >= left.start {
+                new_y = (right.start + (y - left.start).min(right.len().saturating_sub(1)))
+                    as CoordType;
+            }
+            break;
+        }
+    }
+
+    tb.replace_matches(&edits);
+    tb.clear_selection();
+    tb.cursor_move_to_logical(Point { x: cursor.x, y: start_y + new_y });
+}
+
+/// Replaces the selection, or without `selection` everything, with the `output`
+/// of a command that got `text`. As one edit, so that it can be undone.
+fn replace_text(tb: &mut TextBuffer, text: &[u8], mut output: Vec<u8>, selection: bool) {
+    if selection {
+        // Commands end the output with a newline, even if the selection ended mid-line.
+        while !text.ends_with(b"\n") && matches!(output.last(), Some(b'\n' | b'\r')) {
+            output.pop();
+        }
+        tb.write_raw(&output);
+    } else {
+        let pos = tb.cursor_logical_pos();
+        tb.select_all();
+        tb.write_raw(&output);
+        tb.cursor_move_to_logical(pos);
+    }
+}
+
+/// Asks for a shell command and pipes the selection, or without one the whole document,
+/// through it. On failure, what the command wrote to stderr is shown.
+pub fn draw_filter_dialog(ctx: &mut Context, state: &mut State) {
+    let mut done = false;
+    let mut run = false;
+
+    if state.documents.active().is_some() {
+        ctx.modal_begin("filter", loc(LocId::EditFilter));
+        {
+            ctx.editline("command", &mut state.filter_command);
+            ctx.attr_intrinsic_size(Size { width: 40, height: 1 });
+            ctx.steal_focus();
+            if ctx.consume_shortcut(vk::RETURN) {
+                run = !state.filter_command.trim().is_empty();
+                done = run;
+            }
+        }
+        done |= ctx.modal_end();
+    } else {
+        done = true;
+    }
+
+    if run && let Some(doc) = state.documents.active() {
+        let mut tb = doc.buffer.borrow_mut();
+        let selected = tb.extract_user_selection(false);
+        let selection = selected.is_some();
+        let text = selected.unwrap_or_else(|| open_in_app::buffer_text(&tb));
+
+        match config::run_filter(&state.filter_command, &text) {
+            Ok(output) => {
+                if output != text {
+                    replace_text(&mut tb, &text, output, selection);
+                }
+            }
+            Err(stderr) => {
+                drop(tb);
+                let mut msg = loc(LocId::FilterFailed).to_string();
+                if !stderr.is_empty() {
+                    msg = format!("{msg}: {stderr}");
+                }
+                error_log_add_message(ctx, state, msg);
+            }
+        }
+    }
+
+    if done {
+        state.wants_filter = false;
+        ctx.needs_rerender();
+    }
+}
+
+/// Waits for a bracket or quote to be typed for Selection > Surround With…, Change Surrounding…
+/// (which asks a second time, for the replacement) and Delete Surrounding….
+pub fn draw_surround_dialog(ctx: &mut Context, state: &mut State) {
+    let (title, hint) = match state.wants_surround {
+        StateSurround::Add => (LocId::SelectionSurroundWith, LocId::SurroundHint),
+        StateSurround::Change => (LocId::SelectionChangeSurrounding, LocId::SurroundHint),
+        StateSurround::ChangeTo(_) => {
+            (LocId::SelectionChangeSurrounding, LocId::SurroundReplacementHint)
+        }
+        StateSurround::Delete | StateSurround::None => {
+            (LocId::SelectionDeleteSurrounding, LocId::SurroundHint)
+        }
+    };
+    let mut typed = None;
+
+    ctx.modal_begin("surround", loc(title));
+    {
+        ctx.label("hint", loc(hint));
+        ctx.attr_padding(Rect::two(1, 2));
+        if let Some(text) = ctx.text_input() {
+            ctx.set_input_consumed();
+            typed = text.chars().find(|c| !c.is_control() && !c.is_whitespace());
+        }
+    }
+    let mut done = ctx.modal_end();
+
+    if let Some(c) = typed
+        && let Some(doc) = state.documents.active()
+    {
+        let mut tb = doc.buffer.borrow_mut();
+        let mut missing = c;
+        let found = match state.wants_surround {
+            StateSurround::Add => {
+                let (open, close) = surround_delimiters(c);
+                tb.surround(open, close);
+                true
+            }
+            StateSurround::Change => {
+                drop(tb);
+                state.wants_surround = StateSurround::ChangeTo(c);
+                ctx.needs_rerender();
+                return;
+            }
+            StateSurround::ChangeTo(old) => {
+                missing = old;
+                tb.change_surrounding(old, Some(c))
+            }
+            StateSurround::Delete => tb.change_surrounding(c, None),
+            StateSurround::None => true,
+        };
+        drop(tb);
+        if !found {
+            let msg = loc(LocId::SurroundNotFound).replace("{char}", &missing.to_string());
+            toast_add(ctx, state, crate::toast::Severity::Warning, msg);
+        }
+        done = true;
+    }
+
+    if done {
+        state.wants_surround = StateSurround::None;
+        ctx.needs_rerender();
+    }
+}
+
+/// Selection > Encode or Decode…, which offers the [`Codec`]s both ways.
+/// Text that can't be decoded is reported and left as it is.
+pub fn draw_codec_dialog(ctx: &mut Context, state: &mut State) {
+    const ACTIONS: [(LocId, Codec, bool); 8] = [
+        (LocId::CodecBase64Encode, Codec::Base64, false),
+        (LocId::CodecBase64Decode, Codec::Base64, true),
+        (LocId::CodecUrlEncode, Codec::Percent, false),
+        (LocId::CodecUrlDecode, Codec::Percent, true),
+        (LocId::CodecHtmlEscape, Codec::Html, false),
+        (LocId::CodecHtmlUnescape, Codec::Html, true),
+        (LocId::CodecHexDump, Codec::Hex, false),
+        (LocId::CodecHexUndump, Codec::Hex, true),
+    ];
+    let mut activated = None;
+
+    ctx.modal_begin("codec", loc(LocId::SelectionEncodeDecode));
+    {
+        ctx.list_begin("codecs");
+        ctx.inherit_focus();
+        ctx.attr_padding(Rect::two(1, 2));
+        for (i, &(label, codec, decode)) in ACTIONS.iter().enumerate() {
+            if ctx.list_item(i == 0, loc(label)) == ListSelection::Activated {
+                activated = Some((codec, decode));
+            }
+        }
+        ctx.list_end();
+    }
+    let mut done = ctx.modal_end();
+
+    if let Some((codec, decode)) = activated
+        && let Some(doc) = state.documents.active()
+    {
+        let result = doc.buffer.borrow_mut().transcode(codec, decode);
+        if let Err(err) = result {
+            let msg = match (err, codec) {
+                (CodecError::NotUtf8, _) => LocId::CodecNotUtf8,
+                (CodecError::Invalid, Codec::Base64) => LocId::CodecInvalidBase64,
+                (CodecError::Invalid, Codec::Percent) => LocId::CodecInvalidUrl,
+                (CodecError::Invalid, Codec::Html) => LocId::CodecInvalidEntity,
+                (CodecError::Invalid, Codec::Hex) => LocId::CodecInvalidHex,
+            };
+            toast_add(ctx, state, crate::toast::Severity::Warning, loc(msg).to_string());
+        }
+        done = true;
+    }
+
+    if done {
+        state.wants_codec = false;
+        ctx.needs_rerender();
+    }
+}
+
+/// Edit > Insert Character…, which searches the characters by name or codepoint,
+/// and Insert Codepoint…, which asks for just the codepoint. Enter inserts the first match.
+pub fn draw_insert_char_dialog(ctx: &mut Context, state: &mut State) {
+    let picker = state.wants_insert_char == StateInsertChar::Picker;
+    let width = (ctx.size().width - 20).max(10);
+    let height = (ctx.size().height - 10).max(10);
+    let mut insert = None;
+    let mut invalid = false;
+
+    ctx.modal_begin(
+        "insert-char",
+        loc(if picker { LocId::EditInsertCharacter } else { LocId::EditInsertCodepoint }),
+    );
+    {
+        ctx.table_begin("needle-row");
+        ctx.table_set_columns(&[0, COORD_TYPE_SAFE_MAX]);
+        ctx.table_set_cell_gap(Size { width: 1, height: 0 });
+        ctx.inherit_focus();
+        {
+            ctx.table_next_row();
+            ctx.inherit_focus();
+
+            ctx.label(
+                "needle-label",
+                loc(if picker { LocId::SearchNeedleLabel } else { LocId::InsertCodepointLabel }),
+            );
+
+            if ctx.editline("needle", &mut state.insert_char_needle) {
+                state.insert_char_results = if picker {
+                    search_char_names(&state.insert_char_needle, 200)
+                } else {
+                    parse_codepoint(&state.insert_char_needle).into_iter().collect()
+                };
+            }
+            ctx.attr_intrinsic_size(Size { width: 40, height: 1 });
+            ctx.inherit_focus();
+        }
+        ctx.table_end();
+
+        if picker {
+            ctx.scrollarea_begin("scrollarea", Size { width, height });
+            ctx.attr_background_rgba(ctx.indexed_alpha(IndexedColor::Black, 1, 4));
+            {
+                ctx.list_begin("chars");
+                ctx.inherit_focus();
+                for &c in &state.insert_char_results {
+                    if ctx.list_item(false, &char_description(c)) == ListSelection::Activated {
+                        insert = Some(c);
+                    }
+                    ctx.attr_overflow(Overflow::TruncateTail);
+                }
+                ctx.list_end();
+            }
+            ctx.scrollarea_end();
+        } else {
+            let preview = state
+                .insert_char_results
+                .first()
+                .map_or_else(String::new, |&c| char_description(c));
+            ctx.label("preview", &preview);
+            ctx.attr_overflow(Overflow::TruncateTail);
+        }
+
+        if insert.is_none() && ctx.consume_shortcut(vk::RETURN) {
+            insert = state.insert_char_results.first().copied();
+            invalid = insert.is_none() && !picker;
+        }
+    }
+    let mut done = ctx.modal_end();
+
+    if invalid {
+        toast_add(
+            ctx,
+            state,
+            crate::toast::Severity::Warning,
+            loc(LocId::InsertCodepointInvalid).to_string(),
+        );
+    }
+    if let Some(c) = insert
+        && let Some(doc) = state.documents.active()
+    {
+        doc.buffer.borrow_mut().write_canon(c.encode_utf8(&mut [0; 4]).as_bytes());
+        done = true;
+    }
+
+    if done {
+        state.wants_insert_char = StateInsertChar::None;
+        state.insert_char_needle.clear();
+        state.insert_char_results.clear();
+        ctx.needs_rerender();
+    }
+}
+
+/// `é  U+00E9  LATIN SMALL LETTER E WITH ACUTE`, for the list in Insert Character….
+fn char_description(c: char) -> String {
+    let glyph = if c.is_control() { ' ' } else { c };
+    let name = char_name(c).unwrap_or_default();
+    format!("{glyph}  {}  {name}", format_codepoint(c))
+}
+
+/// View > Inspect Character: The codepoints of the grapheme under the cursor,
+/// with their UTF-8 bytes, width and name.
+pub fn draw_char_inspector(ctx: &mut Context, state: &mut State) {
+    let grapheme =
+        state.documents.active().and_then(|doc| grapheme_at_cursor(&doc.buffer.borrow()));
+    let Some(grapheme) = grapheme else {
+        state.wants_char_inspector = false;
+        toast_add(
+            ctx,
+            state,
+            crate::toast::Severity::Info,
+            loc(LocId::InspectEndOfFile).to_string(),
+        );
+        return;
+    };
+    let mut done = false;
+
+    ctx.modal_begin("inspect", loc(LocId::ViewInspectCharacter));
+    {
+        ctx.table_begin("parts");
+        ctx.table_set_columns(&[0, 0, 0, COORD_TYPE_SAFE_MAX]);
+        ctx.table_set_cell_gap(Size { width: 2, height: 0 });
+        ctx.attr_padding(Rect::three(1, 2, 0));
+        {
+            ctx.table_next_row();
+            ctx.label("codepoint", loc(LocId::InspectCodepoint));
+            ctx.label("utf8", loc(LocId::InspectUtf8));
+            ctx.label("width", loc(LocId::InspectWidth));
+            ctx.label("name", loc(LocId::InspectName));
+
+            for (i, part) in grapheme.parts.iter().enumerate() {
+                ctx.next_block_id_mixin(i as u64);
+                ctx.table_next_row();
+                let codepoint = match part.char {
+                    Some(c) => format_codepoint(c),
+                    None => String::new(),
+                };
+                let name = match part.char {
+                    Some(c) => char_name(c).unwrap_or_else(|| {
+                        if c.is_control() {
+                            loc(LocId::InspectControl).to_string()
+                        } else {
+                            String::new()
+                        }
+                    }),
+                    None => loc(LocId::InspectInvalidUtf8).to_string(),
+                };
+                ctx.label("codepoint", &codepoint);
+                ctx.label("utf8", &utf8_bytes(&grapheme, part));
+                ctx.label("width", &part.width.to_string());
+                ctx.label("name", &name);
+                ctx.attr_overflow(Overflow::TruncateTail);
+            }
+        }
+        ctx.table_end();
+
+        if grapheme.parts.len() > 1 {
+            let summary = loc(LocId::InspectCombined)
+                .replace("{count}", &grapheme.parts.len().to_string())
+                .replace("{width}", &grapheme.width.to_string());
+            ctx.label("summary", &summary);
+            ctx.attr_padding(Rect::three(1, 2, 0));
+            ctx.attr_overflow(Overflow::TruncateTail);
+        }
+
+        ctx.block_begin("choices");
+        ctx.inherit_focus();
+        ctx.attr_padding(Rect::three(1, 2, 1));
+        ctx.attr_position(Position::Center);
+        {
+            done |= ctx.button("ok", loc(LocId::Ok), ButtonStyle::default());
+            ctx.inherit_focus();
+        }
+        ctx.block_end();
+    }
+    done |= ctx.modal_end();
+
+    if done {
+        state.wants_char_inspector = false;
+        ctx.needs_rerender();
+    }
+}
+
+/// Opens the URL under the cursor or, if there's none, the file itself in an Android app.
+pub fn draw_handle_open_in_app(ctx: &mut Context, state: &mut State) {
+    state.wants_open_in_app = false;
+
+    let Some(doc) = state.documents.active() else {
+        return;
+    };
+    let url = open_in_app::url_at_cursor(&doc.buffer.borrow());
+
+    let result = if let Some(url) = url {
+        open_in_app::open_url(&url)
+    } else {
+        let Some(path) = doc.path.clone() else {
+            error_log_add_message(ctx, state, loc(LocId::OpenInAppUntitled).to_string());
+            return;
+        };
+
+        // The app reads the file from disk, so it must be up to date.
+        if doc.buffer.borrow().is_dirty() {
+            draw_handle_save(ctx, state);
+            if state.documents.active().is_none_or(|doc| doc.buffer.borrow().is_dirty()) {
+                return;
+            }
+        }
+
+        open_in_app::open_file(&path)
+    };
+
+    if let Err(err) = result {
+        error_log_add(ctx, state, err);
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Register {
+    Clipboard,
+    /// An index into [`edit::clipboard::Clipboard::ring`].
+    Ring(usize),
+    Named(char),
+}
+
+/// Lists the clipboard and the registers, see [`crate::registers`].
+pub fn draw_register_picker(ctx: &mut Context, state: &mut State) {
+    let mut entries = Vec::new();
+    let has_history;
+    {
+        let clipboard = ctx.clipboard_ref();
+        has_history = clipboard.ring().next().is_some() || !clipboard.read().is_empty();
+        if !clipboard.read().is_empty() {
+            let label = loc(LocId::RegistersClipboard);
+            entries.push((Register::Clipboard, format!("{label}  {}", preview(clipboard.read()))));
+        }
+        for (i, data) in clipboard.ring().enumerate() {
+            entries.push((Register::Ring(i), format!("{}  {}", i + 1, preview(data))));
+        }
+    }
+    for (name, data) in state.registers.iter() {
+        entries.push((Register::Named(name), format!("{name}  {}", preview(data))));
+    }
+
+    let width = (ctx.size().width - 20).max(10);
+    let height = (ctx.size().height - 11).clamp(1, entries.len().max(1) as CoordType);
+    let mut activated = None;
+    let mut store = None;
+    let mut clear = false;
+    let mut done = false;
+
+    ctx.modal_begin("registers", loc(LocId::RegistersDialogTitle));
+    ctx.attr_intrinsic_size(Size { width, height: height + 4 });
+    {
+        ctx.scrollarea_begin("entries", Size { width: 0, height });
+        ctx.attr_padding(Rect::two(1, 1));
+        ctx.inherit_focus();
+        {
+            if entries.is_empty() {
+                ctx.label("empty", loc(LocId::RegistersEmpty));
+            }
+
+            ctx.list_begin("entries");
+            ctx.inherit_focus();
+            for (i, (register, text)) in entries.iter().enumerate() {
+                ctx.next_block_id_mixin(i as u64);
+                if ctx.list_item(false, text) == ListSelection::Activated {
+                    activated = Some(*register);
+                }
+                ctx.attr_overflow(Overflow::TruncateTail);
+            }
+            ctx.list_end();
+        }
+        ctx.scrollarea_end();
+
+        ctx.label("hint", loc(LocId::RegistersHint));
+        ctx.attr_foreground_rgba(ctx.indexed_alpha(IndexedColor::Foreground, 1, 2));
+        ctx.attr_overflow(Overflow::TruncateTail);
+        ctx.attr_padding(Rect::two(0, 1));
+
+        // For when something sensitive like a password was copied.
+        if has_history {
+            clear = ctx.button("clear", loc(LocId::RegistersClearHistory), ButtonStyle::default());
+            ctx.attr_position(Position::Center);
+        }
+
+        for name in 'a'..='z' {
+            if let Some(key) = InputKey::from_ascii(name)
+                && ctx.consume_shortcut(key)
+            {
+                store = Some(name);
+            }
+        }
+    }
+    done |= ctx.modal_end();
+
+    if clear {
+        ctx.clipboard_mut().clear_history();
+        ctx.needs_rerender();
+    }
+
+    if let Some(name) = store {
+        let selection = state
+            .documents
+            .active()
+            .and_then(|doc| doc.buffer.borrow_mut().extract_user_selection(false));
+        let data = selection.unwrap_or_else(|| ctx.clipboard_ref().read().to_vec());
+        state.registers.set(name, data);
+        done = true;
+    }
+
+    if let Some(register) = activated {
+        if let Some(doc) = state.documents.active() {
+            let mut tb = doc.buffer.borrow_mut();
+            match register {
+                Register::Clipboard => tb.paste(ctx.clipboard_ref()),
+                Register::Ring(i) => {
+                    ctx.clipboard_mut().select(i);
+                    tb.paste(ctx.clipboard_ref());
+                }
+                Register::Named(name) => {
+                    if let Some(data) = state.registers.get(name) {
+                        tb.write_raw(data);
+                    }
+                }
+            }
+        }
+        done = true;
+    }
+
+    if done {
+        state.wants_register_picker = false;
+        ctx.needs_rerender();
+    }
+}
+
+/// Opens the URL or file name under the cursor, see [`crate::goto_this`].
+pub fn draw_handle_goto_this(ctx: &mut Context, state: &mut State) {
+    state.wants_goto_this = false;
+
+    let Some(doc) = state.documents.active() else {
+        return;
+    };
+    let (line, pos) = open_in_app::line_at_cursor(&doc.buffer.borrow());
+    let dirs: Vec<&Path> = [doc.dir.as_ref().map(|dir| dir.as_path()), state.filetree.root()]
+        .into_iter()
+        .flatten()
+        .collect();
+    let target = goto_this::find(&line, pos, &dirs);
+
+    match target {
+        Some(Target::Url(url)) => {
+            if let Err(err) = open_in_app::open_url(&url) {
+                error_log_add(ctx, state, err);
+            }
+        }
+        Some(Target::File(path, goto)) => {
+            match state.documents.add_file_path_with(&path, OpenArgs { goto, ..Default::default() })
+            {
+                Ok(doc) => doc.buffer.borrow_mut().make_cursor_visible(),
+                Err(err) => error_log_add(ctx, state, err),
+            }
+        }
+        None => error_log_add_message(ctx, state, loc(LocId::GotoThisNotFound).to_string()),
+    }
+    ctx.needs_rerender();
+}
+
+/// Asks for a subject and whether to share the text or the file, then opens the share sheet.
+/// The text is the selection if there is one, otherwise the whole document.
+pub fn draw_share_dialog(ctx: &mut Context, state: &mut State) {
+    let mut done = false;
+    let mut share_file = None;
+
+    if !open_in_app::can_share() {
+        error_log_add_message(ctx, state, loc(LocId::ShareUnavailable).to_string());
+        done = true;
+    } else if let Some(doc) = state.documents.active() {
+        let subject = state.share_subject.get_or_insert_with(|| doc.filename.clone());
+
+        ctx.modal_begin("share", loc(LocId::ShareDialogTitle));
+        {
+            ctx.table_begin("subject");
+            ctx.table_set_cell_gap(Size { width: 1, height: 0 });
+            ctx.attr_padding(Rect::three(1, 2, 0));
+            {
+                ctx.table_next_row();
+                ctx.label("label", loc(LocId::ShareSubject));
+                ctx.editline("subject", subject);
+                ctx.attr_intrinsic_size(Size { width: 32, height: 1 });
+                ctx.steal_focus();
+                if ctx.consume_shortcut(vk::RETURN) {
+                    share_file = Some(false);
+                }
+            }
+            ctx.table_end();
+
+            ctx.table_begin("choices");
+            ctx.attr_padding(Rect::three(1, 2, 1));
+            ctx.attr_position(Position::Center);
+            ctx.table_set_cell_gap(Size { width: 2, height: 0 });
+            ctx.table_stack_below(COMPACT_WIDTH);
+            {
+                ctx.table_next_row();
+                if ctx.button("text", loc(LocId::ShareAsText), ButtonStyle::default()) {
+                    share_file = Some(false);
+                }
+                if doc.path.is_some()
+                    && ctx.button("file", loc(LocId::ShareAsFile), ButtonStyle::default())
+                {
+                    share_file = Some(true);
+                }
+                if ctx.button("cancel", loc(LocId::Cancel), ButtonStyle::default()) {
+                    done = true;
+                }
+            }
+            ctx.table_end();
+        }
+        done |= ctx.modal_end();
+    } else {
+        done = true;
+    }
+
+    if let Some(file) = share_file {
+        let subject = state.share_subject.as_deref().unwrap_or_default();
+        let result = match state.documents.active() {
+            Some(doc) if file => match &doc.path {
+                Some(path) if !doc.buffer.borrow().is_dirty() => {
+                    open_in_app::share_file(path, subject)
+                }
+                // The app reads the file from disk, so it must be saved first.
+                _ => {
+                    error_log_add_message(ctx, state, loc(LocId::OpenInAppUntitled).to_string());
+                    Ok(())
+                }
+            },
+            Some(doc) => {
+                let mut tb = doc.buffer.borrow_mut();
+                let text = match tb.extract_user_selection(false) {
+                    Some(selection) => selection,
+                    None => open_in_app::buffer_text(&tb),
+                };
+                open_in_app::share_text(text, subject)
+            }
+            None => Ok(()),
+        };
+        if let Err(err) = result {
+            error_log_add(ctx, state, err);
+        }
+        done = true;
+    }
+
+    if done {
+        state.wants_share = false;
+        state.share_subject = None;
+        ctx.needs_rerender();
+    }
+}
+
+/// Inserts the dictated text at the cursor, as a single undo step.
+pub fn draw_handle_dictation(ctx: &mut Context, state: &mut State, result: Result<String, ()>) {
+    let Ok(text) = result else {
+        error_log_add_message(ctx, state, "termux-speech-to-text failed".to_string());
+        return;
+    };
+    if let Some(doc) = state.documents.active() {
+        doc.buffer.borrow_mut().write_raw(text.as_bytes());
+    }
+    ctx.needs_rerender();
+}
+
+pub fn draw_handle_autosave(ctx: &mut Context, state: &mut State) {
+    for err in autosave::autosave_documents(state) {
+        error_log_add_message(ctx, state, err.to_string());
+    }
+    ctx.needs_rerender();
+}
+
+/// Autosaves when the terminal window loses focus (if autosave is enabled),
+/// and picks up changes made by other programs (files and the clipboard) when it regains focus.
+pub fn draw_handle_window_focus(ctx: &mut Context, state: &mut State, focused: bool) {
+    if !focused {
+        state.notifier.focus_lost();
+        if state.autosave.is_enabled() {
+            draw_handle_autosave(ctx, state);
+        }
+        return;
+    }
+
+    // The user may have come back by tapping a notification about these results.
+    if state.notifier.focus_gained() == Some(Panel::ProjectReplace)
+        && state.project_replace.is_some()
+    {
+        state.wants_project_replace = true;
+    }
+
+    // Pick up whatever was copied in other apps in the meantime.
+    if let Some(backend) = &mut state.clipboard_backend {
+        backend.request();
+    }
+    state.osc52.request_paste();
+
+    let mut messages = Vec::new();
+    for doc in state.documents.iter_mut() {
+        if !doc.take_unreported_change_on_disk() {
+            continue;
+        }
+
+        let (dirty, encoding) = {
+            let tb = doc.buffer.borrow();
+            (tb.is_dirty(), tb.encoding())
+        };
+        if dirty {
+            // Don't throw away the user's changes. Autosave writes a swap file in this case.
+            messages.push(format!("{}: {}", doc.filename, loc(LocId::ErrorFileChangedOnDisk)));
+        } else if let Err(err) = doc.reread(Some(encoding)) {
+            messages.push(format!("{}: {}", doc.filename, FormatApperr::from(err)));
+        }
+    }
+
+    for msg in messages {
+        error_log_add_message(ctx, state, msg);
+    }
+    ctx.needs_rerender();
+}
+
 pub fn draw_handle_wants_close(ctx: &mut Context, state: &mut State) {
     let Some(doc) = state.documents.active() else {
         state.wants_close = false;
@@ -264,6 +1281,7 @@ pub fn draw_handle_wants_close(ctx: &mut Context, state: &mut State) {
         ctx.attr_padding(Rect::three(0, 2, 1));
         ctx.attr_position(Position::Center);
         ctx.table_set_cell_gap(Size { width: 2, height: 0 });
+        ctx.table_stack_below(COMPACT_WIDTH);
         {
             ctx.table_next_row();
             ctx.inherit_focus();
@@ -363,6 +1381,144 @@ pub fn draw_goto_menu(ctx: &mut Context, state: &mut State) {
     }
 }
 
+#[derive(Clone, Copy)]
+enum ContextAction {
+    Cut,
+    Copy,
+    Paste,
+    GotoDefinition,
+    FindSelection,
+    FormatSelection,
+}
+
+/// The menu for a right-click, a long press or the Menu key in the editor, opened at `pos`.
+pub fn draw_context_menu(ctx: &mut Context, state: &mut State, pos: Point) {
+    let Some(doc) = state.documents.active() else {
+        state.wants_context_menu = None;
+        return;
+    };
+    let can_format = doc.can_format() && doc.buffer.borrow().has_selection();
+    let items = if can_format { 6 } else { 5 };
+
+    // Open towards where there's room: Rightwards in the left half, and upwards near the bottom.
+    let size = ctx.size();
+    let left = pos.x < size.width / 2;
+    let below = pos.y + 1 + items + 2 <= size.height;
+    let mut action = None;
+    let mut done = false;
+
+    ctx.table_begin("context-menu");
+    ctx.attr_float(FloatSpec {
+        anchor: Anchor::Root,
+        gravity_x: if left { 0.0 } else { 1.0 },
+        gravity_y: if below { 0.0 } else { 1.0 },
+        offset_x: (pos.x + if left { 0 } else { 1 }) as f32,
+        offset_y: (pos.y + if below { 1 } else { 0 }) as f32,
+    });
+    ctx.attr_border();
+    ctx.attr_focus_well();
+    ctx.focus_on_first_present();
+    {
+        if ctx.menubar_menu_button(loc(LocId::EditCut), 'T', kbmod::CTRL | vk::X) {
+            action = Some(ContextAction::Cut);
+        }
+        if ctx.menubar_menu_button(loc(LocId::EditCopy), 'C', kbmod::CTRL | vk::C) {
+            action = Some(ContextAction::Copy);
+        }
+        if ctx.menubar_menu_button(loc(LocId::EditPaste), 'P', kbmod::CTRL | vk::V) {
+            action = Some(ContextAction::Paste);
+        }
+        if ctx.menubar_menu_button(loc(LocId::EditGotoDefinition), 'D', vk::NULL) {
+            action = Some(ContextAction::GotoDefinition);
+        }
+        if ctx.menubar_menu_button(loc(LocId::EditFindSelection), 'F', vk::NULL) {
+            action = Some(ContextAction::FindSelection);
+        }
+        if can_format && ctx.menubar_menu_button(loc(LocId::EditFormatSelection), 'O', vk::NULL) {
+            action = Some(ContextAction::FormatSelection);
+        }
+    }
+    ctx.table_end();
+
+    if ctx.contains_focus() && ctx.consume_shortcut(vk::ESCAPE) {
+        done = true;
+    }
+
+    // Clicking elsewhere takes the focus away and closes it.
+    if !ctx.contains_focus() && action.is_none() && !done {
+        state.wants_context_menu = None;
+        ctx.needs_rerender();
+        return;
+    }
+    if action.is_none() && !done {
+        return;
+    }
+
+    state.wants_context_menu = None;
+    state.wants_editor_focus = true;
+    ctx.needs_rerender();
+
+    let Some(action) = action else {
+        return;
+    };
+    let doc = state.documents.active().unwrap();
+    let mut tb = doc.buffer.borrow_mut();
+    match action {
+        ContextAction::Cut => tb.cut(ctx.clipboard_mut()),
+        ContextAction::Copy => tb.copy(ctx.clipboard_mut()),
+        ContextAction::Paste => tb.paste(ctx.clipboard_ref()),
+        ContextAction::GotoDefinition => goto_definition(&mut tb),
+        ContextAction::FindSelection => {
+            drop(tb);
+            find_selection(ctx, state);
+        }
+        ContextAction::FormatSelection => {
+            drop(tb);
+            format(ctx, state, true);
+        }
+    }
+}
+
+/// Jumps to the first occurrence of the word under the cursor. Without knowing
+/// the language, that's the best guess: Things are usually defined before they're used.
+fn goto_definition(tb: &mut TextBuffer) {
+    tb.select_word();
+    let word = tb.extract_user_selection(false).unwrap_or_default();
+    let word = String::from_utf8_lossy(&word);
+    if !word.chars().any(char::is_alphanumeric) {
+        tb.clear_selection();
+        return;
+    }
+    let options = SearchOptions { match_case: true, whole_word: true, use_regex: false };
+
+    tb.clear_selection();
+    tb.cursor_move_to_offset(0);
+    if tb.find_and_select(&word, options).is_ok() {
+        tb.make_cursor_visible();
+    }
+}
+
+/// Searches for the next occurrence of the selection, or the word under the cursor.
+pub fn find_selection(ctx: &mut Context, state: &mut State) {
+    let Some(doc) = state.documents.active() else {
+        return;
+    };
+    let needle = {
+        let mut tb = doc.buffer.borrow_mut();
+        if !tb.has_selection() {
+            tb.select_word();
+        }
+        tb.extract_user_selection(false)
+    };
+    let Some(needle) = needle else {
+        return;
+    };
+
+    state.search_needle = String::from_utf8_lossy_owned(needle);
+    state.wants_search.kind = StateSearchKind::Search;
+    search_execute(ctx, state, SearchAction::Search);
+}
+
 fn validate_goto_point(line: &str) -> Result<Point, ParseIntError> {
     let mut coords = [0; 2];
     let (y, x) = line.split_once(':').unwrap_or((line, "0"));
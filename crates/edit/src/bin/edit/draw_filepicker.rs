@@ -1,19 +1,25 @@
 // Copyright (c) Microsoft Corporation.
 // Licensed under the MIT License.
 
-use std::cmp::Ordering;
-use std::fs;
+use std::cmp::Reverse;
 use std::path::{Path, PathBuf};
+use std::{fs, io};
 
 use edit::framebuffer::IndexedColor;
+use edit::fuzzy::score_fuzzy;
 use edit::helpers::*;
 use edit::input::{kbmod, vk};
 use edit::tui::*;
-use edit::{icu, path};
+use edit::{apperr, icu, path};
 use stdext::arena::scratch_arena;
 
+use crate::draw_filetree::open_folder;
+use crate::draw_task::open_file;
 use crate::localization::*;
+use crate::notify::{Notification, Panel};
+use crate::saf::SafEvent;
 use crate::state::*;
+use crate::{config, export, paths, recent, storage};
 
 pub fn draw_file_picker(ctx: &mut Context, state: &mut State) {
     // The save dialog is pre-filled with the current document filename.
@@ -25,18 +31,29 @@ pub fn draw_file_picker(ctx: &mut Context, state: &mut State) {
                 state.documents.active().map_or("Untitled.txt", |doc| doc.filename.as_str()).into();
         }
     }
+    // The export dialog suggests HTML. Another extension exports text with ANSI escapes.
+    if state.wants_file_picker == StateFilePicker::Export {
+        state.wants_file_picker = StateFilePicker::ExportShown;
+
+        if let Some(doc) = state.documents.active() {
+            state.file_picker_pending_name = format!("{}.html", doc.filename).into();
+        }
+    }
 
     let width = (ctx.size().width - 20).max(10);
     let height = (ctx.size().height - 10).max(10);
     let mut doit = None;
     let mut done = false;
 
+    let folder = state.wants_file_picker == StateFilePicker::OpenFolder;
+
     ctx.modal_begin(
         "file-picker",
-        if state.wants_file_picker == StateFilePicker::Open {
-            loc(LocId::FileOpen)
-        } else {
-            loc(LocId::FileSaveAs)
+        match state.wants_file_picker {
+            StateFilePicker::Open => loc(LocId::FileOpen),
+            StateFilePicker::OpenFolder => loc(LocId::FileOpenFolder),
+            StateFilePicker::Export | StateFilePicker::ExportShown => loc(LocId::FileExport),
+            _ => loc(LocId::FileSaveAs),
         },
     );
     ctx.attr_intrinsic_size(Size { width, height });
@@ -70,6 +87,19 @@ pub fn draw_file_picker(ctx: &mut Context, state: &mut State) {
                 }
             } else if !state.file_picker_autocomplete.is_empty() {
                 state.file_picker_autocomplete.clear();
+                state.file_picker_autocomplete_index = None;
+            }
+
+            // Tab completes the name like a shell: Once, if there's only one candidate,
+            // and otherwise it cycles through them, Shift+Tab backwards.
+            // Without candidates, Tab moves the focus as usual.
+            if ctx.is_focused()
+                && let Some(key) = ctx.keyboard_input()
+                && (key == vk::TAB || key == kbmod::SHIFT | vk::TAB)
+                && autocomplete_cycle(state, key == vk::TAB)
+            {
+                ctx.set_input_consumed();
+                ctx.needs_rerender();
             }
 
             if !state.file_picker_autocomplete.is_empty() {
@@ -92,7 +122,13 @@ pub fn draw_file_picker(ctx: &mut Context, state: &mut State) {
                 ctx.attr_foreground_rgba(fg);
                 {
                     for (idx, suggestion) in state.file_picker_autocomplete.iter().enumerate() {
-                        let sel = ctx.list_item(false, suggestion.as_str());
+                        ctx.styled_list_item_begin();
+                        if state.file_picker_autocomplete_index == Some(idx) {
+                            ctx.styled_label_set_foreground(bg);
+                            ctx.styled_label_set_background(fg);
+                        }
+                        ctx.styled_label_add_text(suggestion.as_str());
+                        let sel = ctx.styled_list_item_end(false);
                         if sel != ListSelection::Unchanged {
                             state.file_picker_pending_name = suggestion.as_path().into();
                         }
@@ -123,15 +159,26 @@ pub fn draw_file_picker(ctx: &mut Context, state: &mut State) {
 
                 if autocomplete_done {
                     state.file_picker_autocomplete.clear();
+                    state.file_picker_autocomplete_index = None;
                 }
             }
 
             if ctx.is_focused() && ctx.consume_shortcut(vk::RETURN) {
                 activated = true;
             }
+
+            if folder {
+                ctx.table_next_row();
+                ctx.label("select-label", "");
+                if ctx.button("select", loc(LocId::FileOpenThisFolder), ButtonStyle::default()) {
+                    doit = Some(state.file_picker_pending_dir.as_path().to_path_buf());
+                }
+            }
         }
         ctx.table_end();
 
+        draw_file_picker_places(ctx, state);
+
         if state.file_picker_entries.is_none() {
             draw_dialog_saveas_refresh_files(state);
         }
@@ -143,11 +190,19 @@ pub fn draw_file_picker(ctx: &mut Context, state: &mut State) {
                 // -1 for the label (top)
                 // -1 for the label (bottom)
                 // -1 for the editline (bottom)
-                height: height - 3,
+                // -1 for the places
+                // -1 for the "Open This Folder" button
+                height: height - 4 - folder as CoordType,
             },
         );
         ctx.attr_background_rgba(ctx.indexed_alpha(IndexedColor::Black, 1, 4));
         {
+            if let Some(err) = &state.file_picker_error {
+                ctx.label("error", err);
+                ctx.attr_foreground_rgba(ctx.indexed(IndexedColor::BrightRed));
+                ctx.attr_overflow(Overflow::TruncateTail);
+            }
+
             ctx.next_block_id_mixin(state.file_picker_pending_dir_revision);
             ctx.list_begin("files");
             ctx.inherit_focus();
@@ -177,11 +232,21 @@ pub fn draw_file_picker(ctx: &mut Context, state: &mut State) {
         }
 
         if activated {
-            doit = draw_file_picker_update_path(state);
+            // Only files are listed, so Enter without a name picks the folder.
+            doit = if folder && state.file_picker_pending_name.as_os_str().is_empty() {
+                Some(state.file_picker_pending_dir.as_path().to_path_buf())
+            } else {
+                draw_file_picker_update_path(state)
+            };
 
             // Check if the file already exists and show an overwrite warning in that case.
-            if state.wants_file_picker != StateFilePicker::Open
-                && let Some(path) = doit.as_deref()
+            if matches!(
+                state.wants_file_picker,
+                StateFilePicker::SaveAs
+                    | StateFilePicker::SaveAsShown
+                    | StateFilePicker::Export
+                    | StateFilePicker::ExportShown
+            ) && let Some(path) = doit.as_deref()
                 && path.exists()
             {
                 state.file_picker_overwrite_warning = doit.take();
@@ -210,6 +275,7 @@ pub fn draw_file_picker(ctx: &mut Context, state: &mut State) {
             ctx.attr_padding(Rect::three(0, 2, 1));
             ctx.attr_position(Position::Center);
             ctx.table_set_cell_gap(Size { width: 2, height: 0 });
+            ctx.table_stack_below(COMPACT_WIDTH);
             {
                 ctx.table_next_row();
                 ctx.inherit_focus();
@@ -241,9 +307,32 @@ pub fn draw_file_picker(ctx: &mut Context, state: &mut State) {
 
     if let Some(path) = doit {
         let res = if state.wants_file_picker == StateFilePicker::Open {
-            state.documents.add_file_path(&path).map(|_| ())
+            open_file(state, &path).map_err(|err| FormatApperr::from(err).to_string())
+        } else if folder {
+            open_folder(state, &path).map_err(|err| FormatApperr::from(err).to_string())
+        } else if state.wants_file_picker == StateFilePicker::ExportShown
+            && let Some(doc) = state.documents.active()
+        {
+            let text = export::export(
+                &doc.buffer.borrow(),
+                export::Format::from_path(&path),
+                &export::Palette::from_context(ctx),
+                &doc.filename,
+            );
+            fs::write(&path, text).map_err(|err| err.to_string())
         } else if let Some(doc) = state.documents.active_mut() {
-            doc.save(Some(path))
+            let res = doc.save(Some(path.clone()), &state.backup).map_err(|err| err.to_string());
+            if res.is_ok() {
+                // The new name may call for different settings, e.g. "Untitled" becoming "*.py".
+                state.documents.redetect_active_filetype();
+                if let Some(command) = &state.after_save {
+                    config::run_after_save(command, &path);
+                }
+                if state.filetree.visible {
+                    state.filetree.refresh();
+                }
+            }
+            res
         } else {
             Ok(())
         };
@@ -252,7 +341,7 @@ pub fn draw_file_picker(ctx: &mut Context, state: &mut State) {
                 ctx.needs_rerender();
                 done = true;
             }
-            Err(err) => error_log_add(ctx, state, err),
+            Err(msg) => error_log_add_message(ctx, state, msg),
         }
     }
 
@@ -262,13 +351,53 @@ pub fn draw_file_picker(ctx: &mut Context, state: &mut State) {
         state.file_picker_entries = Default::default();
         state.file_picker_overwrite_warning = Default::default();
         state.file_picker_autocomplete = Default::default();
+        state.file_picker_autocomplete_index = None;
+        state.file_picker_places = Default::default();
+        state.file_picker_error = Default::default();
+    }
+}
+
+/// Draws a row of buttons for jumping to the home directory, the shared storage, etc.
+fn draw_file_picker_places(ctx: &mut Context, state: &mut State) {
+    let places = state.file_picker_places.get_or_insert_with(storage::places);
+    if places.is_empty() {
+        return;
+    }
+
+    let mut jump = None;
+
+    ctx.table_begin("places");
+    ctx.table_set_cell_gap(Size { width: 1, height: 0 });
+    ctx.attr_padding(Rect::two(0, 1));
+    {
+        ctx.table_next_row();
+        ctx.label("label", loc(LocId::FilePickerPlaces));
+
+        for (i, place) in places.iter().enumerate() {
+            ctx.next_block_id_mixin(i as u64);
+            if ctx.button("place", &place.label, ButtonStyle::default()) {
+                jump = Some(place.path.clone());
+            }
+        }
+    }
+    ctx.table_end();
+
+    if let Some(dir) = jump {
+        file_picker_set_dir(state, dir);
+        ctx.needs_rerender();
     }
 }
 
+pub fn file_picker_set_dir(state: &mut State, dir: PathBuf) {
+    state.file_picker_pending_dir = DisplayablePathBuf::from_path(dir);
+    state.file_picker_pending_dir_revision = state.file_picker_pending_dir_revision.wrapping_add(1);
+    state.file_picker_entries = None;
+}
+
 // Returns Some(path) if the path refers to a file.
 fn draw_file_picker_update_path(state: &mut State) -> Option<PathBuf> {
     let old_path = state.file_picker_pending_dir.as_path();
-    let path = old_path.join(&state.file_picker_pending_name);
+    let path = old_path.join(expand_name(&state.file_picker_pending_name));
     let path = path::normalize(&path);
 
     let (dir, name) = if path.is_dir() {
@@ -291,10 +420,7 @@ fn draw_file_picker_update_path(state: &mut State) -> Option<PathBuf> {
         (dir, name)
     };
     if dir != state.file_picker_pending_dir.as_path() {
-        state.file_picker_pending_dir = DisplayablePathBuf::from_path(dir.to_path_buf());
-        state.file_picker_pending_dir_revision =
-            state.file_picker_pending_dir_revision.wrapping_add(1);
-        state.file_picker_entries = None;
+        file_picker_set_dir(state, dir.to_path_buf());
     }
 
     state.file_picker_pending_name = name;
@@ -322,87 +448,347 @@ fn draw_dialog_saveas_refresh_files(state: &mut State) {
         dirs_files[0].push(DisplayablePathBuf::from(".."));
     }
 
-    if let Ok(iter) = fs::read_dir(dir) {
-        for entry in iter.flatten() {
-            if let Ok(metadata) = entry.metadata() {
-                let mut name = entry.file_name();
-                let dir = metadata.is_dir()
-                    || (metadata.is_symlink()
-                        && fs::metadata(entry.path()).is_ok_and(|m| m.is_dir()));
-                let idx = if dir { 1 } else { 2 };
-
-                if dir {
-                    name.push("/");
-                }
-
-                dirs_files[idx].push(DisplayablePathBuf::from(name));
-            }
+    state.file_picker_error = None;
+
+    let folder = state.wants_file_picker == StateFilePicker::OpenFolder;
+    if let Err(err) = list_dir(dir, &mut dirs_files[1..]) {
+        // Directories like /storage/emulated can be entered, but not listed.
+        // Say so, instead of showing an empty list, and keep ".." to get back out.
+        let hint = storage::read_dir_hint(dir, &err);
+        let mut msg = FormatApperr::from(apperr::Error::from(err)).to_string();
+        if let Some(hint) = hint {
+            msg.push(' ');
+            msg.push_str(hint);
         }
+        state.file_picker_error = Some(msg);
     }
 
-    for entries in &mut dirs_files[1..] {
-        entries.sort_by(|a, b| {
-            let a = a.as_bytes();
-            let b = b.as_bytes();
+    if folder {
+        dirs_files[2].clear();
+    }
+    state.file_picker_entries = Some(dirs_files);
+}
 
-            let a_is_dir = a.last() == Some(&b'/');
-            let b_is_dir = b.last() == Some(&b'/');
+/// Lists `dir` into `[directories, files]`, sorted. Directory names end in "/".
+pub fn list_dir(dir: &Path, dirs_files: &mut [Vec<DisplayablePathBuf>]) -> io::Result<()> {
+    for entry in fs::read_dir(dir)?.flatten() {
+        // FUSE storage may refuse to stat some entries. `file_type()` usually
+        // works without a stat, so those can still be listed.
+        let Ok(file_type) = entry.file_type() else {
+            continue;
+        };
+        let mut name = entry.file_name();
+        let dir = file_type.is_dir()
+            || (file_type.is_symlink() && fs::metadata(entry.path()).is_ok_and(|m| m.is_dir()));
+        let idx = if dir { 0 } else { 1 };
 
-            match b_is_dir.cmp(&a_is_dir) {
-                Ordering::Equal => icu::compare_strings(a, b),
-                other => other,
-            }
-        });
+        if dir {
+            name.push("/");
+        }
+
+        dirs_files[idx].push(DisplayablePathBuf::from(name));
     }
 
-    state.file_picker_entries = Some(dirs_files);
+    for entries in dirs_files {
+        entries.sort_by(|a, b| icu::compare_strings(a.as_bytes(), b.as_bytes()));
+    }
+    Ok(())
 }
 
+/// At most this many suggestions are shown below the name.
+const AUTOCOMPLETE_MAX: usize = 8;
+
+/// Suggests what the name may be completed to: The entries of the directory typed so far,
+/// filtered by the last component with the same fuzzy matcher as the other pickers.
 #[inline(never)]
 fn update_autocomplete_suggestions(state: &mut State) {
     state.file_picker_autocomplete.clear();
+    state.file_picker_autocomplete_index = None;
 
-    if state.file_picker_pending_name.as_os_str().is_empty() {
+    let name = state.file_picker_pending_name.to_string_lossy();
+    let (dir_part, needle) = name.split_at(name.rfind('/').map_or(0, |i| i + 1));
+    if dir_part.is_empty() && needle.is_empty() {
         return;
     }
 
+    // The directory that's shown can be searched without listing it again.
+    let listed;
+    let entries = if dir_part.is_empty()
+        && let Some(dirs_files) = &state.file_picker_entries
+    {
+        &dirs_files[1..]
+    } else {
+        let dir = state.file_picker_pending_dir.as_path().join(paths::expand(dir_part));
+        let mut dirs_files = [Vec::new(), Vec::new()];
+        _ = list_dir(&dir, &mut dirs_files);
+        listed = dirs_files;
+        &listed[..]
+    };
+
     let scratch = scratch_arena(None);
-    let needle = state.file_picker_pending_name.as_os_str().as_encoded_bytes();
     let mut matches = Vec::new();
 
-    // Using binary search below we'll quickly find the lower bound
-    // of items that match the needle (= share a common prefix).
-    //
-    // The problem is finding the upper bound. Here I'm using a trick:
-    // By appending U+10FFFF (the highest possible Unicode code point)
-    // we create a needle that naturally yields an upper bound.
-    let mut needle_upper_bound = Vec::with_capacity_in(needle.len() + 4, &*scratch);
-    needle_upper_bound.extend_from_slice(needle);
-    needle_upper_bound.extend_from_slice(b"\xf4\x8f\xbf\xbf");
-
-    if let Some(dirs_files) = &state.file_picker_entries {
-        'outer: for entries in &dirs_files[1..] {
-            let lower = entries
-                .binary_search_by(|entry| icu::compare_strings(entry.as_bytes(), needle))
-                .unwrap_or_else(|i| i);
-
-            for entry in &entries[lower..] {
-                let haystack = entry.as_bytes();
-                match icu::compare_strings(haystack, &needle_upper_bound) {
-                    Ordering::Less => {
-                        matches.push(entry.clone());
-                        if matches.len() >= 5 {
-                            break 'outer; // Limit to 5 suggestions
-                        }
+    for entry in entries.iter().flatten() {
+        let entry = entry.as_str();
+        // Like in a shell, hidden files only show up once their dot was typed.
+        if entry.starts_with('.') && !needle.starts_with('.') {
+            continue;
+        }
+        let score = if needle.is_empty() {
+            1
+        } else {
+            let local_scratch = scratch_arena(Some(&scratch));
+            score_fuzzy(&local_scratch, entry, needle, true).0
+        };
+        if score > 0 {
+            matches.push((score, entry));
+        }
+    }
+
+    // A stable sort keeps directories first among equally good matches.
+    matches.sort_by_key(|&(score, _)| Reverse(score));
+    state.file_picker_autocomplete = matches
+        .iter()
+        .take(AUTOCOMPLETE_MAX)
+        .map(|(_, entry)| DisplayablePathBuf::from_string(format!("{dir_part}{entry}")))
+        .collect();
+}
+
+/// Puts the next (or previous) suggestion into the name and returns true.
+/// A single one is taken right away, so that the next Tab completes what's below it.
+/// Returns false if there's nothing to complete.
+fn autocomplete_cycle(state: &mut State, forward: bool) -> bool {
+    if state.file_picker_autocomplete.is_empty() {
+        update_autocomplete_suggestions(state);
+    }
+
+    let len = state.file_picker_autocomplete.len();
+    let index = match state.file_picker_autocomplete_index {
+        _ if len == 0 => return false,
+        Some(i) if forward => (i + 1) % len,
+        Some(i) => (i + len - 1) % len,
+        None if forward => 0,
+        None => len - 1,
+    };
+    state.file_picker_pending_name = state.file_picker_autocomplete[index].as_path().into();
+
+    if len == 1 {
+        state.file_picker_autocomplete.clear();
+        state.file_picker_autocomplete_index = None;
+    } else {
+        state.file_picker_autocomplete_index = Some(index);
+    }
+    true
+}
+
+/// `~` and `$VAR` in the name, see [`paths::expand`].
+fn expand_name(name: &Path) -> PathBuf {
+    match name.to_str() {
+        Some(text) => paths::expand(text).into(),
+        None => name.to_path_buf(),
+    }
+}
+
+/// Offers the recently opened files, e.g. when started with `--pick-recent`.
+pub fn draw_recent_picker(ctx: &mut Context, state: &mut State) {
+    let files = state.recent_files.get_or_insert_with(recent::load);
+    let width = (ctx.size().width - 20).max(10);
+    let height = (ctx.size().height - 10).clamp(1, files.len().max(1) as CoordType);
+    let mut activated = None;
+    let mut done = false;
+
+    ctx.modal_begin("recent", loc(LocId::RecentDialogTitle));
+    ctx.attr_intrinsic_size(Size { width, height: height + 2 });
+    {
+        ctx.scrollarea_begin("files", Size { width: 0, height });
+        ctx.attr_padding(Rect::two(1, 1));
+        {
+            if files.is_empty() {
+                ctx.label("empty", loc(LocId::RecentEmpty));
+            }
+
+            ctx.list_begin("files");
+            ctx.inherit_focus();
+            for (i, path) in files.iter().enumerate() {
+                ctx.next_block_id_mixin(i as u64);
+                if ctx.list_item(false, &path.to_string_lossy()) == ListSelection::Activated {
+                    activated = Some(path.clone());
+                }
+                ctx.attr_overflow(Overflow::TruncateTail);
+            }
+            ctx.list_end();
+        }
+        ctx.scrollarea_end();
+    }
+    done |= ctx.modal_end();
+
+    if let Some(path) = activated {
+        match open_file(state, &path) {
+            Ok(()) => done = true,
+            Err(err) => error_log_add(ctx, state, err),
+        }
+    }
+
+    if done {
+        state.wants_recent_picker = false;
+        state.recent_files = None;
+        ctx.needs_rerender();
+    }
+}
+
+/// Lists a folder that Android granted access to, see [`crate::saf`].
+pub fn draw_saf_browser(ctx: &mut Context, state: &mut State) {
+    if state.saf_dirs.is_empty() && !state.saf.is_busy() {
+        state.saf.pick_dir();
+    }
+
+    let width = (ctx.size().width - 20).max(10);
+    let height = (ctx.size().height - 10).max(10);
+    let mut activated = None;
+    let mut go_up = false;
+    let mut done = false;
+
+    ctx.modal_begin("saf", loc(LocId::SafDialogTitle));
+    ctx.attr_intrinsic_size(Size { width, height });
+    {
+        let path = state.saf_dirs.iter().map(|dir| dir.name.as_str()).collect::<Vec<_>>();
+        ctx.label("dir", &path.join("/"));
+        ctx.attr_overflow(Overflow::TruncateMiddle);
+        ctx.attr_padding(Rect::two(0, 1));
+
+        ctx.scrollarea_begin(
+            "directory",
+            Size {
+                width: 0,
+                // -1 for the label (top)
+                // -1 for the buttons (bottom)
+                height: height - 2,
+            },
+        );
+        ctx.attr_background_rgba(ctx.indexed_alpha(IndexedColor::Black, 1, 4));
+        {
+            if state.saf.is_busy() {
+                ctx.label("waiting", loc(LocId::SafWaiting));
+            }
+
+            ctx.next_block_id_mixin(state.saf_dirs.len() as u64);
+            ctx.list_begin("files");
+            ctx.inherit_focus();
+
+            if state.saf_dirs.len() > 1 && ctx.list_item(false, "..") == ListSelection::Activated {
+                go_up = true;
+            }
+            if let Some(dir) = state.saf_dirs.last() {
+                for (i, entry) in dir.entries.iter().enumerate() {
+                    ctx.next_block_id_mixin(i as u64);
+                    let selection = if entry.is_dir {
+                        ctx.list_item(false, &format!("{}/", entry.name))
+                    } else {
+                        ctx.list_item(false, &entry.name)
+                    };
+                    if selection == ListSelection::Activated {
+                        activated = Some(entry.clone());
                     }
-                    // We're looking for suggestions, not for matches.
-                    Ordering::Equal => {}
-                    // No more matches possible.
-                    Ordering::Greater => break,
+                    ctx.attr_overflow(Overflow::TruncateMiddle);
                 }
             }
+
+            ctx.list_end();
+        }
+        ctx.scrollarea_end();
+
+        ctx.table_begin("choices");
+        ctx.attr_position(Position::Center);
+        ctx.table_set_cell_gap(Size { width: 2, height: 0 });
+        ctx.table_stack_below(COMPACT_WIDTH);
+        {
+            ctx.table_next_row();
+            if ctx.button("pick", loc(LocId::SafChooseFolder), ButtonStyle::default()) {
+                state.saf.pick_dir();
+            }
+            if ctx.button("cancel", loc(LocId::Cancel), ButtonStyle::default()) {
+                done = true;
+            }
+        }
+        ctx.table_end();
+
+        if state.saf_dirs.len() > 1
+            && ctx.contains_focus()
+            && (ctx.consume_shortcut(vk::BACK) || ctx.consume_shortcut(kbmod::ALT | vk::UP))
+        {
+            go_up = true;
         }
     }
+    done |= ctx.modal_end();
+
+    if go_up {
+        state.saf_dirs.pop();
+        ctx.needs_rerender();
+    } else if let Some(entry) = activated {
+        if entry.is_dir {
+            state.saf.list(&entry);
+        } else {
+            state.saf.open(&entry);
+        }
+    }
+
+    if done {
+        state.wants_saf_browser = false;
+        state.saf_dirs.clear();
+    }
+}
 
-    state.file_picker_autocomplete = matches;
+pub fn draw_handle_saf_event(ctx: &mut Context, state: &mut State, event: SafEvent) {
+    match event {
+        SafEvent::Dir(dir) => {
+            if state.wants_saf_browser {
+                // Picking a folder starts over at its root.
+                if state
+                    .saf_dirs
+                    .last()
+                    .is_none_or(|last| !last.entries.iter().any(|e| e.uri == dir.uri))
+                {
+                    state.saf_dirs.clear();
+                }
+                state.saf_dirs.push(dir);
+            }
+        }
+        SafEvent::Cancelled => {
+            if state.saf_dirs.is_empty() {
+                state.wants_saf_browser = false;
+            }
+        }
+        SafEvent::Opened { uri, path } => match state.documents.add_file_path(&path) {
+            Ok(doc) => {
+                doc.saf_uri = Some(uri);
+                state.wants_saf_browser = false;
+                state.saf_dirs.clear();
+            }
+            Err(err) => error_log_add(ctx, state, err),
+        },
+        SafEvent::Written => state.notifier.finished(
+            ctx.is_window_focused(),
+            Notification {
+                title: loc(LocId::NotifySafWritten).to_string(),
+                content: String::new(),
+                panel: None,
+            },
+        ),
+        SafEvent::Failed(msg) => {
+            state.notifier.finished(
+                ctx.is_window_focused(),
+                Notification {
+                    title: loc(LocId::NotifySafFailed).to_string(),
+                    content: msg.clone(),
+                    panel: Some(Panel::ErrorLog),
+                },
+            );
+            // Otherwise the browser would immediately ask for a folder again.
+            if state.saf_dirs.is_empty() {
+                state.wants_saf_browser = false;
+            }
+            error_log_add_message(ctx, state, msg);
+        }
+    }
+    ctx.needs_rerender();
 }
@@ -0,0 +1,317 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+use std::env;
+use std::path::Path;
+
+use edit::apperr;
+use edit::framebuffer::IndexedColor;
+use edit::helpers::*;
+use edit::input::{kbmod, vk};
+use edit::tui::*;
+
+use crate::draw_task::open_file;
+use crate::filetree::{self, Prompt};
+use crate::git::FileStatus;
+use crate::localization::*;
+use crate::state::*;
+
+/// Draws the sidebar next to the editor, `height` rows tall.
+pub fn draw_filetree(ctx: &mut Context, state: &mut State, height: CoordType) {
+    if state.filetree.root().is_none()
+        && let Ok(cwd) = env::current_dir()
+    {
+        state.filetree.set_root(cwd);
+    }
+
+    let mut activated = None;
+
+    ctx.block_begin("filetree");
+    ctx.attr_background_rgba(ctx.indexed_alpha(IndexedColor::Black, 1, 4));
+    ctx.attr_intrinsic_size(Size { width: 0, height });
+    {
+        let root = state.filetree.root().and_then(|root| root.file_name());
+        let root = root.map(|name| name.to_string_lossy()).unwrap_or_default();
+        ctx.label("root", &root);
+        ctx.attr_padding(Rect::two(0, 1));
+        ctx.attr_overflow(Overflow::TruncateMiddle);
+
+        ctx.table_begin("actions");
+        ctx.table_set_cell_gap(Size { width: 1, height: 0 });
+        {
+            ctx.table_next_row();
+            if ctx.button("new", loc(LocId::FileTreeNew), ButtonStyle::default()) {
+                prompt_create(state);
+            }
+            if ctx.button("rename", loc(LocId::FileTreeRename), ButtonStyle::default()) {
+                prompt_rename(state);
+            }
+            if ctx.button("delete", loc(LocId::FileTreeDelete), ButtonStyle::default()) {
+                prompt_delete(state);
+            }
+        }
+        ctx.table_end();
+
+        ctx.scrollarea_begin("scrollarea", Size { width: 0, height: (height - 2).max(1) });
+        {
+            let tree = &mut state.filetree;
+            let mut selected = tree.selected;
+
+            ctx.list_begin("rows");
+            for (i, row) in tree.rows().iter().enumerate() {
+                let marker = match (row.is_dir, row.expanded) {
+                    (false, _) => "  ",
+                    (true, false) => "▸ ",
+                    (true, true) => "▾ ",
+                };
+
+                ctx.styled_list_item_begin();
+                ctx.styled_label_add_text(&"  ".repeat(row.depth));
+                ctx.styled_label_add_text(marker);
+                if let Some(status) = tree.git_status(row) {
+                    let color = match status {
+                        FileStatus::Modified => IndexedColor::Yellow,
+                        FileStatus::Added | FileStatus::Untracked => IndexedColor::Green,
+                        FileStatus::Conflicted => IndexedColor::Red,
+                    };
+                    ctx.styled_label_set_foreground(ctx.indexed(color));
+                }
+                ctx.styled_label_add_text(&row.name);
+                match ctx.styled_list_item_end(false) {
+                    ListSelection::Unchanged => {}
+                    ListSelection::Selected => selected = i,
+                    ListSelection::Activated => activated = Some(i),
+                }
+                ctx.attr_overflow(Overflow::TruncateTail);
+
+                if state.wants_filetree_focus && i == tree.selected {
+                    state.wants_filetree_focus = false;
+                    ctx.list_item_steal_focus();
+                }
+            }
+            ctx.list_end();
+
+            tree.selected = selected;
+        }
+        ctx.scrollarea_end();
+
+        // There's nothing to focus in an empty directory, but it should still take the focus.
+        if state.wants_filetree_focus {
+            state.wants_filetree_focus = false;
+            ctx.steal_focus();
+        }
+    }
+    ctx.block_end();
+
+    if ctx.contains_focus() {
+        draw_handle_filetree_keys(ctx, state);
+    }
+
+    if let Some(i) = activated {
+        state.filetree.selected = i;
+        open_selected(ctx, state);
+    }
+}
+
+/// Keys beyond the list navigation, while the sidebar has the focus.
+fn draw_handle_filetree_keys(ctx: &mut Context, state: &mut State) {
+    let tree = &mut state.filetree;
+    let selected = tree.selected;
+    let row = tree.selected_row().map(|row| (row.is_dir, row.expanded));
+
+    // Typed letters come as both, text and keys.
+    if let Some(text) = ctx.text_input() {
+        // Like in other file tree plugins: Add, Rename, Delete.
+        match text {
+            "a" => prompt_create(state),
+            "r" => prompt_rename(state),
+            "d" => prompt_delete(state),
+            _ => return,
+        }
+    } else if let Some(key) = ctx.keyboard_input() {
+        match key {
+            vk::RIGHT if row == Some((true, false)) => tree.toggle(selected),
+            vk::RIGHT if row == Some((true, true)) && selected + 1 < tree.rows().len() => {
+                tree.selected += 1;
+                state.wants_filetree_focus = true;
+            }
+            vk::LEFT if row == Some((true, true)) => tree.toggle(selected),
+            vk::LEFT if let Some(parent) = tree.parent(selected) => {
+                tree.selected = parent;
+                state.wants_filetree_focus = true;
+            }
+            // Otherwise, the focus would move over to the editor.
+            vk::LEFT | vk::RIGHT => {}
+            vk::F2 => prompt_rename(state),
+            vk::DELETE => prompt_delete(state),
+            vk::ESCAPE => state.wants_editor_focus = true,
+            _ if key == kbmod::CTRL | vk::LEFT => tree.resize(-2),
+            _ if key == kbmod::CTRL | vk::RIGHT => tree.resize(2),
+            _ => return,
+        }
+    } else {
+        return;
+    }
+
+    ctx.set_input_consumed();
+    ctx.needs_rerender();
+}
+
+/// Opens the selected file, or expands or collapses the selected directory.
+fn open_selected(ctx: &mut Context, state: &mut State) {
+    let Some(row) = state.filetree.selected_row() else {
+        return;
+    };
+
+    if row.is_dir {
+        state.filetree.toggle(state.filetree.selected);
+        state.wants_filetree_focus = true;
+    } else {
+        let path = row.path.clone();
+        match open_file(state, &path) {
+            Ok(_) => state.wants_editor_focus = true,
+            Err(err) => error_log_add(ctx, state, err),
+        }
+    }
+    ctx.needs_rerender();
+}
+
+fn prompt_create(state: &mut State) {
+    if let Some(dir) = state.filetree.target_dir(state.filetree.selected) {
+        state.filetree.prompt = Some(Prompt::Create { dir, name: String::new() });
+    }
+}
+
+fn prompt_rename(state: &mut State) {
+    if let Some(row) = state.filetree.selected_row() {
+        let (path, name) = (row.path.clone(), row.name.clone());
+        state.filetree.prompt = Some(Prompt::Rename { path, name });
+    }
+}
+
+fn prompt_delete(state: &mut State) {
+    if let Some(row) = state.filetree.selected_row() {
+        state.filetree.prompt = Some(Prompt::Delete { path: row.path.clone() });
+    }
+}
+
+/// Asks for the name of a new or renamed file, or whether to really delete one.
+pub fn draw_filetree_prompt(ctx: &mut Context, state: &mut State) {
+    let Some(prompt) = &mut state.filetree.prompt else {
+        return;
+    };
+    let confirmed;
+    let mut cancelled = false;
+
+    let title = match prompt {
+        Prompt::Create { .. } => loc(LocId::FileTreeNewTitle),
+        Prompt::Rename { .. } => loc(LocId::FileTreeRenameTitle),
+        Prompt::Delete { .. } => loc(LocId::FileTreeDeleteTitle),
+    };
+    ctx.modal_begin("filetree-prompt", title);
+    {
+        match prompt {
+            Prompt::Create { name, .. } | Prompt::Rename { name, .. } => {
+                ctx.editline("name", name);
+                ctx.attr_intrinsic_size(Size { width: 32, height: 1 });
+                ctx.steal_focus();
+                if matches!(prompt, Prompt::Create { .. }) {
+                    ctx.label("hint", loc(LocId::FileTreeNewHint));
+                    ctx.attr_padding(Rect::two(0, 1));
+                }
+                confirmed = ctx.consume_shortcut(vk::RETURN);
+            }
+            Prompt::Delete { path } => {
+                let name = path.file_name().unwrap_or_default().to_string_lossy();
+                ctx.label("name", &name);
+                ctx.attr_padding(Rect::three(1, 2, 0));
+                ctx.label("description", loc(LocId::FileTreeDeleteDescription));
+                ctx.attr_padding(Rect::three(0, 2, 1));
+
+                ctx.table_begin("choices");
+                ctx.inherit_focus();
+                ctx.attr_padding(Rect::three(0, 2, 1));
+                ctx.attr_position(Position::Center);
+                ctx.table_set_cell_gap(Size { width: 2, height: 0 });
+                {
+                    ctx.table_next_row();
+                    ctx.inherit_focus();
+                    confirmed =
+                        ctx.button("yes", loc(LocId::FileTreeDelete), ButtonStyle::default());
+                    ctx.inherit_focus();
+                    cancelled = ctx.button("cancel", loc(LocId::Cancel), ButtonStyle::default());
+                }
+                ctx.table_end();
+            }
+        }
+    }
+    cancelled |= ctx.modal_end();
+
+    if confirmed {
+        let prompt = state.filetree.prompt.take().unwrap();
+        if let Err(err) = apply_prompt(state, prompt) {
+            error_log_add(ctx, state, err.into());
+        }
+    }
+    if cancelled {
+        state.filetree.prompt = None;
+    }
+    if confirmed || cancelled {
+        state.wants_filetree_focus = true;
+        ctx.needs_rerender();
+    }
+}
+
+fn apply_prompt(state: &mut State, prompt: Prompt) -> std::io::Result<()> {
+    let selected = match prompt {
+        Prompt::Create { dir, name } if !name.trim().is_empty() => {
+            let path = filetree::create(&dir, name.trim())?;
+            if path.is_file() {
+                _ = state.documents.add_file_path(&path);
+            }
+            Some(path)
+        }
+        Prompt::Rename { path, name } if !name.trim().is_empty() => {
+            let new_path = filetree::rename(&path, name.trim())?;
+            state.documents.rename_path(&path, &new_path);
+            Some(new_path)
+        }
+        Prompt::Delete { path } => {
+            filetree::delete(&path)?;
+            None
+        }
+        _ => None,
+    };
+
+    // Show where the new file went, even if its directory was collapsed.
+    state.filetree.refresh();
+    if let Some(path) = &selected {
+        for dir in path.ancestors().skip(1).collect::<Vec<_>>().into_iter().rev() {
+            if let Some(i) = state.filetree.rows().iter().position(|row| row.path == dir)
+                && !state.filetree.rows()[i].expanded
+            {
+                state.filetree.toggle(i);
+            }
+        }
+    }
+    state.filetree.select_path(selected.as_deref());
+    Ok(())
+}
+
+/// Makes `dir` the working directory, which the file tree, the project search
+/// and the terminal start out in, and shows it in the file tree.
+pub fn open_folder(state: &mut State, dir: &Path) -> apperr::Result<()> {
+    env::set_current_dir(dir)?;
+    state.filetree.set_root(dir.to_path_buf());
+    state.filetree.set_visible(true);
+    state.wants_filetree_focus = true;
+    Ok(())
+}
+
+/// Shows the sidebar and focuses it, or hides it and returns the focus to the editor.
+pub fn toggle_filetree(state: &mut State) {
+    let visible = !state.filetree.visible;
+    state.filetree.set_visible(visible);
+    state.wants_filetree_focus = visible;
+    state.wants_editor_focus = !visible;
+}
@@ -0,0 +1,159 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+use std::path::Path;
+
+use edit::buffer::syntax_fallback;
+use edit::framebuffer::{Attributes, IndexedColor, NamedColor};
+use edit::helpers::*;
+use edit::input::vk;
+use edit::oklab::StraightRgba;
+use edit::tui::*;
+
+use crate::documents::Document;
+use crate::markdown::{Preview, Row, Style};
+use crate::state::*;
+
+pub fn is_markdown(doc: &Document) -> bool {
+    doc.filetype.is_some_and(|filetype| filetype.name == "markdown")
+}
+
+/// Shows the preview in place of the editor, or the editor again. Either one
+/// scrolls to where the other one was.
+pub fn toggle_markdown_preview(state: &mut State) {
+    let preview = state.markdown_preview.take();
+    let Some(doc) = state.documents.active() else {
+        return;
+    };
+    let mut tb = doc.buffer.borrow_mut();
+    let y = tb.cursor_logical_pos().y;
+
+    match preview {
+        Some(preview) => {
+            if !(preview.top_line..=preview.bottom_line).contains(&(y as usize)) {
+                tb.cursor_move_to_logical(Point { x: 0, y: preview.top_line as CoordType });
+            }
+            tb.make_cursor_visible();
+        }
+        None => state.markdown_preview = Some(Preview::new(y as usize)),
+    }
+}
+
+/// Draws the preview of the active document, `height` rows tall.
+pub fn draw_markdown_preview(ctx: &mut Context, state: &mut State, height: CoordType) {
+    let (Some(doc), Some(preview)) = (state.documents.active(), &mut state.markdown_preview) else {
+        return;
+    };
+    let mut width = ctx.size().width - 2;
+    if state.filetree.visible {
+        width -= state.filetree.width + 1;
+    }
+
+    let tb = doc.buffer.borrow();
+    preview.layout(&tb, width);
+    let rows = preview.rows();
+    let end = (preview.scroll + height.max(1) as usize).min(rows.len());
+
+    ctx.block_begin("markdown");
+    ctx.attr_focusable();
+    ctx.attr_padding(Rect::two(0, 1));
+    for (i, row) in rows[preview.scroll..end].iter().enumerate() {
+        ctx.next_block_id_mixin(i as u64);
+        draw_row(ctx, row, doc.dir.as_ref().map(|dir| dir.as_path()));
+    }
+    ctx.block_end();
+
+    let last = rows.len().saturating_sub(1);
+    let bottom_line = rows.get(end.saturating_sub(1)).map_or(0, |row| row.line);
+    preview.bottom_line = bottom_line;
+    drop(tb);
+
+    let page = height.max(1) as usize;
+    let mut scroll = preview.scroll as isize;
+    if let Some(delta) = ctx.scroll_input() {
+        scroll += delta.y;
+        ctx.set_input_consumed();
+    } else if ctx.contains_focus()
+        && let Some(key) = ctx.keyboard_input()
+    {
+        match key {
+            vk::UP => scroll -= 1,
+            vk::DOWN => scroll += 1,
+            vk::PRIOR => scroll -= page as isize,
+            vk::NEXT => scroll += page as isize,
+            vk::HOME => scroll = 0,
+            vk::END => scroll = last.saturating_sub(page - 1) as isize,
+            vk::ESCAPE => toggle_markdown_preview(state),
+            _ => return,
+        }
+        ctx.set_input_consumed();
+    } else {
+        return;
+    }
+
+    if let Some(preview) = &mut state.markdown_preview {
+        preview.scroll = scroll.clamp(0, last as isize) as usize;
+    }
+    ctx.needs_rerender();
+}
+
+fn draw_row(ctx: &mut Context, row: &Row, dir: Option<&Path>) {
+    let code_bg = ctx.indexed_alpha(IndexedColor::BrightBlack, 1, 4);
+
+    ctx.styled_label_begin("row");
+    if row.code_block {
+        ctx.attr_background_rgba(code_bg);
+    }
+    for segment in &row.segments {
+        let Style { bold, italic, code, marker, heading } = segment.style;
+        let link = segment.link.as_deref().and_then(|url| resolve_link(url, dir));
+
+        let fg = if link.is_some() {
+            syntax_color(ctx, NamedColor::Function)
+        } else if heading > 0 {
+            syntax_color(ctx, NamedColor::Keyword)
+        } else if code {
+            syntax_color(ctx, NamedColor::String)
+        } else if marker {
+            syntax_color(ctx, NamedColor::Comment)
+        } else {
+            ctx.indexed(IndexedColor::Foreground)
+        };
+        let mut attr = Attributes::None;
+        if bold {
+            attr = attr | Attributes::Bold;
+        }
+        if italic {
+            attr = attr | Attributes::Italic;
+        }
+        if link.is_some() || heading == 1 {
+            attr = attr | Attributes::Underlined;
+        }
+
+        ctx.styled_label_set_foreground(fg);
+        ctx.styled_label_set_background(if code { code_bg } else { StraightRgba::zero() });
+        ctx.styled_label_set_attributes(attr);
+        ctx.styled_label_set_link(link.as_deref());
+        ctx.styled_label_add_text(&segment.text);
+    }
+    ctx.styled_label_end();
+}
+
+/// The color of the syntax highlighting, so that the preview looks like the editor.
+fn syntax_color(ctx: &Context, color: NamedColor) -> StraightRgba {
+    ctx.named(color)
+        .or_else(|| syntax_fallback(color).map(|c| ctx.indexed(c)))
+        .unwrap_or(ctx.indexed(IndexedColor::Foreground))
+}
+
+/// Links to other files are relative to the document. Those within it aren't links.
+fn resolve_link(url: &str, dir: Option<&Path>) -> Option<String> {
+    if url.contains("://") || url.starts_with("mailto:") {
+        return Some(url.to_string());
+    }
+    if url.starts_with('#') {
+        return None;
+    }
+    let path = dir?.join(url.split('#').next()?);
+    Some(format!("file://{}", path.display()).replace(' ', "%20"))
+}
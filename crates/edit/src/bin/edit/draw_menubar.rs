@@ -2,14 +2,35 @@
 // Licensed under the MIT License.
 
 use edit::helpers::*;
-use edit::input::{kbmod, vk};
+use edit::input::{InputKey, kbmod, vk};
 use edit::tui::*;
+use edit::unicode::Case;
+use edit::unicode::bidi::Direction;
 use stdext::arena_format;
 
+use crate::chord::{self, ChordAction};
+use crate::draw_editor::find_selection;
+use crate::draw_filetree::toggle_filetree;
+use crate::draw_markdown::{is_markdown, toggle_markdown_preview};
+use crate::draw_runner::run_command;
+use crate::draw_terminal::{send_selection_to_terminal, toggle_terminal};
 use crate::localization::*;
+use crate::pretty::Language;
 use crate::state::*;
+use crate::{gutter, theme};
 
+/// The letters of the menus, in the order they're drawn.
+const ACCELERATORS: [char; 5] = ['f', 'e', 's', 'v', 'h'];
+
+/// Draws the menubar. With `ui.menubar = false` it only shows up once F10 or Alt plus
+/// the letter of a menu was pressed, and goes away again once it lost the focus.
 pub fn draw_menubar(ctx: &mut Context, state: &mut State) {
+    let opening = state.menubar_hidden && !state.menubar_open && wants_menubar(ctx);
+    if state.menubar_hidden && !state.menubar_open && !opening {
+        return;
+    }
+    state.menubar_open = state.menubar_hidden;
+
     ctx.menubar_begin();
     ctx.attr_background_rgba(state.menubar_color_bg);
     ctx.attr_foreground_rgba(state.menubar_color_fg);
@@ -26,6 +47,9 @@ pub fn draw_menubar(ctx: &mut Context, state: &mut State) {
             if ctx.menubar_menu_begin(loc(LocId::Edit), 'E') {
                 draw_menu_edit(ctx, state);
             }
+            if ctx.menubar_menu_begin(loc(LocId::Selection), 'S') {
+                draw_menu_selection(ctx, state);
+            }
             if ctx.menubar_menu_begin(loc(LocId::View), 'V') {
                 draw_menu_view(ctx, state);
             }
@@ -35,6 +59,22 @@ pub fn draw_menubar(ctx: &mut Context, state: &mut State) {
         }
     }
     ctx.menubar_end();
+
+    if state.menubar_open && !opening && !ctx.contains_focus() {
+        state.menubar_open = false;
+        ctx.needs_rerender();
+    }
+}
+
+/// Whether the key opens a menu. It isn't consumed: The menubar does that once it's drawn.
+fn wants_menubar(ctx: &mut Context) -> bool {
+    let Some(key) = ctx.keyboard_input() else {
+        return false;
+    };
+    key == vk::F10
+        || ACCELERATORS
+            .iter()
+            .any(|&c| InputKey::from_ascii(c).is_some_and(|k| key == kbmod::ALT | k))
 }
 
 fn draw_menu_file(ctx: &mut Context, state: &mut State) {
@@ -44,16 +84,65 @@ fn draw_menu_file(ctx: &mut Context, state: &mut State) {
     if ctx.menubar_menu_button(loc(LocId::FileOpen), 'O', kbmod::CTRL | vk::O) {
         state.wants_file_picker = StateFilePicker::Open;
     }
+    if ctx.menubar_menu_button(loc(LocId::FileOpenFolder), 'F', vk::NULL) {
+        state.wants_file_picker = StateFilePicker::OpenFolder;
+    }
+    if ctx.menubar_menu_button(loc(LocId::FileOpenRecent), 'R', vk::NULL) {
+        state.wants_recent_picker = true;
+    }
+    if state.saf.is_available()
+        && ctx.menubar_menu_button(loc(LocId::FileOpenFromAndroid), 'D', vk::NULL)
+    {
+        state.wants_saf_browser = true;
+    }
     if state.documents.active().is_some() {
         if ctx.menubar_menu_button(loc(LocId::FileSave), 'S', kbmod::CTRL | vk::S) {
             state.wants_save = true;
         }
-        if ctx.menubar_menu_button(loc(LocId::FileSaveAs), 'A', vk::NULL) {
+        if ctx.menubar_menu_button_hint(
+            loc(LocId::FileSaveAs),
+            'A',
+            &chord::hint(ChordAction::SaveAs),
+        ) {
             state.wants_file_picker = StateFilePicker::SaveAs;
         }
+        if ctx.menubar_menu_button(loc(LocId::FileExport), 'E', vk::NULL) {
+            state.wants_file_picker = StateFilePicker::Export;
+        }
+        if ctx.menubar_menu_button_hint(
+            loc(LocId::FileOpenInApp),
+            'P',
+            &chord::hint(ChordAction::OpenInApp),
+        ) {
+            state.wants_open_in_app = true;
+        }
+        if ctx.menubar_menu_button_hint(
+            loc(LocId::FileShare),
+            'H',
+            &chord::hint(ChordAction::Share),
+        ) {
+            state.wants_share = true;
+        }
         if ctx.menubar_menu_button(loc(LocId::FileClose), 'C', kbmod::CTRL | vk::W) {
             state.wants_close = true;
         }
+        if state.autosave.is_enabled() {
+            let doc = state.documents.active_mut().unwrap();
+            if ctx.menubar_menu_checkbox_hint(
+                loc(LocId::FileAutosave),
+                'U',
+                &chord::hint(ChordAction::ToggleAutosave),
+                doc.autosave,
+            ) {
+                doc.autosave = !doc.autosave;
+            }
+        }
+    }
+    if ctx.menubar_menu_button(loc(LocId::FileSettings), 'T', vk::NULL) {
+        state.wants_settings = true;
+    }
+    if ctx.menubar_menu_button(loc(LocId::FileReloadSettings), 'L', vk::NULL) {
+        state.wants_config_reload = true;
     }
     if ctx.menubar_menu_button(loc(LocId::FileExit), 'X', kbmod::CTRL | vk::Q) {
         state.wants_exit = true;
@@ -85,6 +174,13 @@ fn draw_menu_edit(ctx: &mut Context, state: &mut State) {
         tb.paste(ctx.clipboard_ref());
         ctx.needs_rerender();
     }
+    if ctx.menubar_menu_button(loc(LocId::EditCyclePaste), 'Y', kbmod::ALT | vk::Y) {
+        tb.paste_rotate(ctx.clipboard_mut());
+        ctx.needs_rerender();
+    }
+    if ctx.menubar_menu_button(loc(LocId::EditRegisters), 'G', kbmod::CTRL_SHIFT | vk::V) {
+        state.wants_register_picker = true;
+    }
     if state.wants_search.kind != StateSearchKind::Disabled {
         if ctx.menubar_menu_button(loc(LocId::EditFind), 'F', kbmod::CTRL | vk::F) {
             state.wants_search.kind = StateSearchKind::Search;
@@ -94,11 +190,178 @@ fn draw_menu_edit(ctx: &mut Context, state: &mut State) {
             state.wants_search.kind = StateSearchKind::Replace;
             state.wants_search.focus = true;
         }
+        if ctx.menubar_menu_button(loc(LocId::EditReplaceInFiles), 'I', kbmod::CTRL_SHIFT | vk::H) {
+            state.wants_project_replace = true;
+        }
+    }
+    if let Some((prefix, suffix)) = doc.comment_strings()
+        && ctx.menubar_menu_button(loc(LocId::EditToggleComment), 'M', kbmod::CTRL | vk::OEM_2)
+    {
+        tb.toggle_comment(prefix, suffix);
+        ctx.needs_rerender();
     }
+    if ctx.menubar_menu_button_hint(
+        loc(LocId::EditInsertCharacter),
+        'K',
+        &chord::hint(ChordAction::InsertCharacter),
+    ) {
+        state.wants_insert_char = StateInsertChar::Picker;
+    }
+    if ctx.menubar_menu_button_hint(
+        loc(LocId::EditInsertCodepoint),
+        'X',
+        &chord::hint(ChordAction::InsertCodepoint),
+    ) {
+        state.wants_insert_char = StateInsertChar::Codepoint;
+    }
+    if ctx.menubar_menu_button(loc(LocId::EditToggleBookmark), 'B', kbmod::CTRL | vk::F2) {
+        gutter::toggle_bookmark(&mut tb);
+        ctx.needs_rerender();
+    }
+    if ctx.menubar_menu_button(loc(LocId::EditNextBookmark), 'E', vk::F2) {
+        gutter::goto_bookmark(&mut tb, true);
+        ctx.needs_rerender();
+    }
+    if ctx.menubar_menu_button(loc(LocId::EditPreviousBookmark), 'V', kbmod::SHIFT | vk::F2) {
+        gutter::goto_bookmark(&mut tb, false);
+        ctx.needs_rerender();
+    }
+    if doc.can_format()
+        && ctx.menubar_menu_button(loc(LocId::EditFormatDocument), 'O', kbmod::CTRL_ALT | vk::F)
+    {
+        state.wants_format = true;
+    }
+    if ctx.menubar_menu_button(loc(LocId::EditFilter), 'H', vk::NULL) {
+        state.wants_filter = true;
+    }
+    if tb.has_mixed_newlines()
+        && ctx.menubar_menu_button(loc(LocId::EditNormalizeNewlines), 'W', vk::NULL)
+    {
+        let crlf = tb.dominant_newline_is_crlf();
+        tb.repair_newlines(crlf);
+        ctx.needs_rerender();
+    }
+    if state.dictation.is_available()
+        && ctx.menubar_menu_button(loc(LocId::EditDictate), 'D', vk::NULL)
+    {
+        state.dictation.start();
+        ctx.needs_rerender();
+    }
+    drop(tb);
+    if ctx.menubar_menu_button_hint(
+        loc(LocId::EditRunInTerminal),
+        'S',
+        &chord::hint(ChordAction::RunInTerminal),
+    ) {
+        send_selection_to_terminal(ctx, state);
+    }
+    if ctx.menubar_menu_button(loc(LocId::EditRunCommand), 'N', vk::F5) {
+        state.wants_run_dialog = true;
+    }
+    if state.runner.is_running() {
+        if ctx.menubar_menu_button(loc(LocId::EditStopCommand), 'A', kbmod::SHIFT | vk::F5) {
+            state.runner.stop();
+        }
+    } else if !state.runner.command.is_empty()
+        && ctx.menubar_menu_button(loc(LocId::EditRerunCommand), 'A', kbmod::SHIFT | vk::F5)
+    {
+        run_command(ctx, state);
+    }
+    ctx.menubar_menu_end();
+}
+
+fn draw_menu_selection(ctx: &mut Context, state: &mut State) {
+    let doc = state.documents.active().unwrap();
+    let mut tb = doc.buffer.borrow_mut();
+
     if ctx.menubar_menu_button(loc(LocId::EditSelectAll), 'A', kbmod::CTRL | vk::A) {
         tb.select_all();
         ctx.needs_rerender();
     }
+    if ctx.menubar_menu_button(loc(LocId::SelectionSelectWord), 'W', vk::NULL) {
+        tb.select_word();
+        ctx.needs_rerender();
+    }
+    if ctx.menubar_menu_button(loc(LocId::SelectionSelectLine), 'L', kbmod::CTRL | vk::L) {
+        tb.select_line();
+        ctx.needs_rerender();
+    }
+    if ctx.menubar_menu_button(loc(LocId::SelectionDuplicateLines), 'D', kbmod::CTRL | vk::D) {
+        tb.duplicate_selected_lines();
+        ctx.needs_rerender();
+    }
+    if ctx.menubar_menu_button_hint(
+        loc(LocId::SelectionDeleteLines),
+        'E',
+        &chord::hint(ChordAction::DeleteLines),
+    ) {
+        tb.delete_selected_lines();
+        ctx.needs_rerender();
+    }
+    if ctx.menubar_menu_button_hint(
+        loc(LocId::SelectionJoinLines),
+        'J',
+        &chord::hint(ChordAction::JoinLines),
+    ) {
+        tb.join_lines(doc.line_comment());
+        ctx.needs_rerender();
+    }
+    if ctx.menubar_menu_button(loc(LocId::SelectionRewrap), 'Q', kbmod::ALT | vk::Q) {
+        tb.rewrap();
+        ctx.needs_rerender();
+    }
+    for (label, accelerator, case) in [
+        (LocId::SelectionUppercase, 'U', Case::Upper),
+        (LocId::SelectionLowercase, 'O', Case::Lower),
+        (LocId::SelectionTitleCase, 'T', Case::Title),
+        (LocId::SelectionSnakeCase, 'N', Case::Snake),
+        (LocId::SelectionCamelCase, 'C', Case::Camel),
+        (LocId::SelectionKebabCase, 'K', Case::Kebab),
+    ] {
+        if ctx.menubar_menu_button(loc(label), accelerator, vk::NULL) {
+            tb.convert_case(case);
+            ctx.needs_rerender();
+        }
+    }
+    for (label, accelerator, surround) in [
+        (LocId::SelectionSurroundWith, 'H', StateSurround::Add),
+        (LocId::SelectionChangeSurrounding, 'G', StateSurround::Change),
+        (LocId::SelectionDeleteSurrounding, 'P', StateSurround::Delete),
+    ] {
+        if ctx.menubar_menu_button(loc(label), accelerator, vk::NULL) {
+            state.wants_surround = surround;
+            ctx.needs_rerender();
+        }
+    }
+    if ctx.menubar_menu_button(loc(LocId::SelectionEncodeDecode), 'X', vk::NULL) {
+        state.wants_codec = true;
+        ctx.needs_rerender();
+    }
+    for (label, accelerator, language) in [
+        (LocId::SelectionPrettyPrintJson, 'Y', Language::Json),
+        (LocId::SelectionPrettyPrintXml, 'M', Language::Xml),
+    ] {
+        if ctx.menubar_menu_button(loc(label), accelerator, vk::NULL) {
+            state.wants_pretty_print = Some(language);
+        }
+    }
+    if ctx.menubar_menu_button(loc(LocId::SelectionIncrementNumber), 'I', kbmod::CTRL_ALT | vk::A) {
+        tb.add_to_number(1, false);
+        ctx.needs_rerender();
+    }
+    if ctx.menubar_menu_button(loc(LocId::SelectionDecrementNumber), 'R', kbmod::CTRL_ALT | vk::X) {
+        tb.add_to_number(-1, false);
+        ctx.needs_rerender();
+    }
+    if ctx.menubar_menu_button(loc(LocId::SelectionNumberLines), 'S', kbmod::CTRL_ALT_SHIFT | vk::A)
+    {
+        tb.add_to_number(1, true);
+        ctx.needs_rerender();
+    }
+    if ctx.menubar_menu_button(loc(LocId::EditFindSelection), 'F', vk::NULL) {
+        drop(tb);
+        find_selection(ctx, state);
+    }
     ctx.menubar_menu_end();
 }
 
@@ -106,9 +369,15 @@ fn draw_menu_view(ctx: &mut Context, state: &mut State) {
     if let Some(doc) = state.documents.active() {
         let mut tb = doc.buffer.borrow_mut();
         let word_wrap = tb.is_word_wrap_enabled();
+        let read_only = tb.is_read_only();
+        let whitespace = tb.is_whitespace_visible();
 
         // All values on the statusbar are currently document specific.
-        if ctx.menubar_menu_button(loc(LocId::ViewFocusStatusbar), 'S', vk::NULL) {
+        if ctx.menubar_menu_button_hint(
+            loc(LocId::ViewFocusStatusbar),
+            'S',
+            &chord::hint(ChordAction::FocusStatusbar),
+        ) {
             state.wants_statusbar_focus = true;
         }
         if ctx.menubar_menu_button(loc(LocId::ViewGoToFile), 'F', kbmod::CTRL | vk::P) {
@@ -117,10 +386,136 @@ fn draw_menu_view(ctx: &mut Context, state: &mut State) {
         if ctx.menubar_menu_button(loc(LocId::FileGoto), 'G', kbmod::CTRL | vk::G) {
             state.wants_goto = true;
         }
+        if ctx.menubar_menu_button_hint(
+            loc(LocId::ViewGotoThis),
+            'L',
+            &chord::hint(ChordAction::GotoThis),
+        ) {
+            state.wants_goto_this = true;
+        }
         if ctx.menubar_menu_checkbox(loc(LocId::ViewWordWrap), 'W', kbmod::ALT | vk::Z, word_wrap) {
             tb.set_word_wrap(!word_wrap);
             ctx.needs_rerender();
         }
+        if ctx.menubar_menu_checkbox(loc(LocId::ViewShowWhitespace), 'H', vk::NULL, whitespace) {
+            tb.set_whitespace_visible(!whitespace);
+            ctx.needs_rerender();
+        }
+        // Automatic goes by the first letter of each line, the others override it.
+        let (label, next) = match tb.text_direction() {
+            Direction::Auto => (LocId::ViewTextDirectionAuto, Direction::Rtl),
+            Direction::Rtl => (LocId::ViewTextDirectionRtl, Direction::Ltr),
+            Direction::Ltr => (LocId::ViewTextDirectionLtr, Direction::Auto),
+        };
+        if ctx.menubar_menu_button(loc(label), 'C', vk::NULL) {
+            tb.set_text_direction(next);
+            ctx.needs_rerender();
+        }
+        if ctx.menubar_menu_button(loc(LocId::ViewInspectCharacter), 'I', vk::NULL) {
+            state.wants_char_inspector = true;
+        }
+        if ctx.menubar_menu_checkbox_hint(
+            loc(LocId::ViewReadOnly),
+            'R',
+            &chord::hint(ChordAction::ToggleReadOnly),
+            read_only,
+        ) {
+            tb.set_read_only(!read_only);
+            ctx.needs_rerender();
+        }
+    }
+
+    if state.documents.active().is_some_and(is_markdown)
+        && ctx.menubar_menu_checkbox_hint(
+            loc(LocId::ViewMarkdownPreview),
+            'D',
+            &chord::hint(ChordAction::ToggleMarkdownPreview),
+            state.markdown_preview.is_some(),
+        )
+    {
+        toggle_markdown_preview(state);
+        ctx.needs_rerender();
+    }
+    if ctx.menubar_menu_checkbox(
+        loc(LocId::ViewFileTree),
+        'E',
+        kbmod::CTRL | vk::B,
+        state.filetree.visible,
+    ) {
+        toggle_filetree(state);
+        ctx.needs_rerender();
+    }
+    if ctx.menubar_menu_checkbox_hint(
+        loc(LocId::ViewTerminal),
+        'M',
+        &chord::hint(ChordAction::FocusTerminal),
+        state.terminal.visible,
+    ) {
+        toggle_terminal(state);
+        ctx.needs_rerender();
+    }
+    if (state.runner.is_running() || state.runner.term.has_exited())
+        && ctx.menubar_menu_checkbox(
+            loc(LocId::ViewCommandOutput),
+            'U',
+            vk::NULL,
+            state.runner.term.visible,
+        )
+    {
+        state.runner.term.visible = !state.runner.term.visible;
+        state.wants_runner_focus = state.runner.term.visible;
+        state.wants_editor_focus = !state.runner.term.visible;
+        ctx.needs_rerender();
+    }
+    if ctx.menubar_menu_checkbox_hint(
+        loc(LocId::ViewVimMode),
+        'V',
+        &chord::hint(ChordAction::ToggleVim),
+        state.vim.enabled,
+    ) {
+        let enabled = !state.vim.enabled;
+        state.vim.set_enabled(enabled);
+        ctx.needs_rerender();
+    }
+    if ctx.menubar_menu_checkbox(loc(LocId::ViewSoftkeys), 'K', vk::NULL, state.softkeys.enabled) {
+        state.softkeys.enabled = !state.softkeys.enabled;
+        ctx.needs_rerender();
+    }
+    if ctx.menubar_menu_checkbox(
+        loc(LocId::ViewPowerSaving),
+        'P',
+        vk::NULL,
+        state.power.is_saving(),
+    ) {
+        state.power.toggle();
+        ctx.needs_rerender();
+    }
+    if ctx.menubar_menu_button(loc(LocId::ViewTheme), 'T', vk::NULL) {
+        state.wants_theme_picker = true;
+    }
+    if ctx.menubar_menu_button_hint(
+        loc(LocId::ViewNotifications),
+        'N',
+        &chord::hint(ChordAction::Notifications),
+    ) {
+        state.wants_notification_history = true;
+    }
+    // Like the `debug-latency` status, it's for developers and not localized.
+    #[cfg(feature = "profiler")]
+    {
+        if ctx.menubar_menu_checkbox("Profiler", 'O', vk::NULL, state.profiler.visible) {
+            state.profiler.visible = !state.profiler.visible;
+            ctx.needs_rerender();
+        }
+        if ctx.menubar_menu_button("Export Profile", 'X', vk::NULL) {
+            match state.profiler.export() {
+                Ok(path) => {
+                    let msg = format!("Profile exported: {}", path.display());
+                    toast_add(ctx, state, crate::toast::Severity::Info, msg);
+                }
+                Err(err) => error_log_add(ctx, state, err.into()),
+            }
+        }
     }
 
     ctx.menubar_menu_end();
@@ -178,3 +573,70 @@ pub fn draw_dialog_about(ctx: &mut Context, state: &mut State) {
         state.wants_about = false;
     }
 }
+
+/// Offers the installed themes, see [`crate::theme`].
+pub fn draw_theme_picker(ctx: &mut Context, state: &mut State) {
+    let themes = state.themes.get_or_insert_with(theme::list);
+    let width = (ctx.size().width - 20).max(10);
+    let height = (ctx.size().height - 10).clamp(1, themes.len() as CoordType + 2);
+    let mut activated = None;
+    let mut done = false;
+
+    ctx.modal_begin("theme", loc(LocId::ThemeDialogTitle));
+    ctx.attr_intrinsic_size(Size { width, height: height + 2 });
+    {
+        ctx.scrollarea_begin("themes", Size { width: 0, height });
+        ctx.attr_padding(Rect::two(1, 1));
+        {
+            ctx.list_begin("themes");
+            ctx.inherit_focus();
+            if ctx.list_item(state.theme.is_none(), loc(LocId::ThemeTerminal))
+                == ListSelection::Activated
+            {
+                activated = Some(None);
+            }
+            for (i, theme) in themes.iter().enumerate() {
+                ctx.next_block_id_mixin(i as u64);
+                let active = state.theme.as_ref() == Some(&theme.name);
+                if ctx.list_item(active, &theme.name) == ListSelection::Activated {
+                    activated = Some(Some(i));
+                }
+                ctx.attr_overflow(Overflow::TruncateTail);
+            }
+            ctx.list_end();
+
+            if themes.is_empty() {
+                ctx.label("empty", loc(LocId::ThemeEmpty));
+                ctx.attr_overflow(Overflow::TruncateTail);
+            }
+        }
+        ctx.scrollarea_end();
+    }
+    done |= ctx.modal_end();
+
+    match activated {
+        Some(Some(i)) => {
+            let file = &themes[i];
+            match theme::load(&file.path) {
+                Ok(theme) => {
+                    state.theme = Some(file.name.clone());
+                    state.theme_change = Some(Some(theme));
+                    done = true;
+                }
+                Err(err) => error_log_add_message(ctx, state, err),
+            }
+        }
+        Some(None) => {
+            state.theme = None;
+            state.theme_change = Some(None);
+            done = true;
+        }
+        None => {}
+    }
+
+    if done {
+        state.wants_theme_picker = false;
+        state.themes = None;
+        ctx.needs_rerender();
+    }
+}
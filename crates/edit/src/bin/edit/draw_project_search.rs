@@ -0,0 +1,377 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+use std::borrow::Cow;
+use std::path::Path;
+
+use edit::buffer::FindMatch;
+use edit::framebuffer::{Attributes, IndexedColor};
+use edit::helpers::*;
+use edit::icu;
+use edit::input::vk;
+use edit::tui::*;
+use stdext::arena_format;
+
+use crate::localization::*;
+use crate::notify::{Notification, Panel};
+use crate::project_search::*;
+use crate::state::*;
+use crate::task::{Job, Task};
+
+pub fn draw_project_replace(ctx: &mut Context, state: &mut State) {
+    if let Err(err) = icu::init() {
+        error_log_add(ctx, state, err);
+        state.wants_project_replace = false;
+        return;
+    }
+
+    if state.project_replace_dir.is_empty() {
+        state.project_replace_dir = state
+            .documents
+            .active()
+            .and_then(|doc| doc.dir.as_ref())
+            .map(|dir| dir.as_str().to_string())
+            .or_else(|| std::env::current_dir().ok().map(|p| p.to_string_lossy().into_owned()))
+            .unwrap_or_default();
+    }
+
+    let width = (ctx.size().width - 10).max(20);
+    let height = (ctx.size().height - 6).max(12);
+    let mut find = false;
+    let mut replace = false;
+    let mut done = false;
+
+    ctx.modal_begin("project-replace", loc(LocId::ProjectReplaceDialogTitle));
+    ctx.attr_intrinsic_size(Size { width, height });
+    {
+        ctx.table_begin("inputs");
+        ctx.table_set_columns(&[0, COORD_TYPE_SAFE_MAX]);
+        ctx.table_set_cell_gap(Size { width: 1, height: 0 });
+        ctx.attr_padding(Rect::two(1, 1));
+        ctx.inherit_focus();
+        {
+            ctx.table_next_row();
+            ctx.inherit_focus();
+            ctx.label("needle-label", loc(LocId::SearchNeedleLabel));
+            ctx.editline("needle", &mut state.search_needle);
+            ctx.inherit_focus();
+            if ctx.is_focused() && ctx.consume_shortcut(vk::RETURN) {
+                find = true;
+            }
+
+            ctx.table_next_row();
+            ctx.label("replacement-label", loc(LocId::SearchReplacementLabel));
+            ctx.editline("replacement", &mut state.search_replacement);
+            if ctx.is_focused() && ctx.consume_shortcut(vk::RETURN) {
+                find = true;
+            }
+
+            ctx.table_next_row();
+            ctx.label("dir-label", loc(LocId::ProjectReplaceDirLabel));
+            ctx.editline("dir", &mut state.project_replace_dir);
+            if ctx.is_focused() && ctx.consume_shortcut(vk::RETURN) {
+                find = true;
+            }
+        }
+        ctx.table_end();
+
+        ctx.table_begin("options");
+        ctx.table_set_cell_gap(Size { width: 2, height: 0 });
+        ctx.attr_padding(Rect::two(0, 1));
+        {
+            ctx.table_next_row();
+
+            find |= ctx.checkbox(
+                "match-case",
+                loc(LocId::SearchMatchCase),
+                &mut state.search_options.match_case,
+            );
+            find |= ctx.checkbox(
+                "whole-word",
+                loc(LocId::SearchWholeWord),
+                &mut state.search_options.whole_word,
+            );
+            #[cfg(feature = "regex")]
+            {
+                find |= ctx.checkbox(
+                    "use-regex",
+                    loc(LocId::SearchUseRegex),
+                    &mut state.search_options.use_regex,
+                );
+            }
+            if ctx.button("find", loc(LocId::ProjectReplaceFind), ButtonStyle::default()) {
+                find = true;
+            }
+            if state.task.is_none()
+                && state.project_replace.as_ref().is_some_and(|s| s.selected_count() > 0)
+                && ctx.button("replace", loc(LocId::SearchReplaceAll), ButtonStyle::default())
+            {
+                replace = true;
+            }
+            if ctx.button("close", loc(LocId::SearchClose), ButtonStyle::default()) {
+                done = true;
+            }
+        }
+        ctx.table_end();
+
+        // -4 for the inputs (incl. padding)
+        // -1 for the options
+        // -1 for the summary
+        draw_project_replace_preview(ctx, state, (height - 6).max(1));
+
+        if let Some(summary) = state.project_replace_summary {
+            ctx.label(
+                "summary",
+                &arena_format!(
+                    ctx.arena(),
+                    "{} {} / {} {}",
+                    summary.matches,
+                    loc(LocId::ProjectReplaceSummaryMatches),
+                    summary.files,
+                    loc(LocId::ProjectReplaceSummaryFiles),
+                ),
+            );
+            ctx.attr_overflow(Overflow::TruncateTail);
+            ctx.attr_padding(Rect::two(0, 1));
+        }
+    }
+    done |= ctx.modal_end();
+
+    // Searching again or closing the dialog first stops the running search or replace.
+    if let Some(task) = &mut state.task
+        && matches!(task.job, Job::ProjectFind | Job::ProjectReplace)
+        && (find || done)
+    {
+        task.cancel();
+        find = false;
+        done = false;
+    }
+
+    if find {
+        project_replace_find(ctx, state);
+    }
+    if replace {
+        project_replace_apply(ctx, state);
+    }
+    if done {
+        state.wants_project_replace = false;
+        state.project_replace = None;
+        state.project_replace_summary = None;
+        state.project_replace_view.clear();
+        ctx.needs_rerender();
+    }
+}
+
+/// There may be tens of thousands of matches, so only the visible ones are drawn.
+/// Each file gets a row, followed by one for each of its matches. Space, Enter and
+/// double-clicks check or uncheck the selected ones, and a file all its matches.
+fn draw_project_replace_preview(ctx: &mut Context, state: &mut State, height: CoordType) {
+    let view = &mut state.project_replace_view;
+    let Some(search) = &mut state.project_replace else {
+        ctx.block_begin("preview");
+        ctx.attr_intrinsic_size(Size { width: 0, height });
+        ctx.block_end();
+        return;
+    };
+
+    let rows = PreviewRows::new(search);
+    view.set_len(rows.len());
+
+    let red = ctx.indexed(IndexedColor::Red);
+    let green = ctx.indexed(IndexedColor::Green);
+    let yellow = ctx.indexed(IndexedColor::BrightYellow);
+
+    let visible = ctx.virtual_list_begin("preview", view, height);
+    ctx.attr_background_rgba(ctx.indexed_alpha(IndexedColor::Black, 1, 4));
+    for row in visible {
+        ctx.virtual_list_item_begin(view, row);
+        ctx.attr_overflow(Overflow::TruncateTail);
+
+        match rows.get(search, row) {
+            PreviewRow::File(file) => {
+                let check = match file.selected.iter().filter(|&&s| s).count() {
+                    0 => "[ ]",
+                    n if n == file.selected.len() => "[x]",
+                    _ => "[-]",
+                };
+                ctx.styled_label_add_text(check);
+                ctx.styled_label_add_text(" ");
+                ctx.styled_label_set_foreground(yellow);
+                ctx.styled_label_add_text(&file.name);
+            }
+            PreviewRow::Match(file, m) => {
+                let [before, old, after] = match_line(&file.matches[m]);
+                let check = if file.selected[m] { "[x]" } else { "[ ]" };
+                let line = file.matches[m].line + 1;
+                ctx.styled_label_add_text(&arena_format!(ctx.arena(), "    {check} {line}: "));
+                ctx.styled_label_add_text(before.trim_start());
+                ctx.styled_label_set_foreground(red);
+                ctx.styled_label_set_attributes(Attributes::Underlined);
+                ctx.styled_label_add_text(&old);
+                ctx.styled_label_set_foreground(green);
+                ctx.styled_label_add_text(&String::from_utf8_lossy(&file.matches[m].replacement));
+                ctx.styled_label_set_foreground(ctx.indexed(IndexedColor::Foreground));
+                ctx.styled_label_set_attributes(Attributes::None);
+                ctx.styled_label_add_text(after.trim_end());
+            }
+            PreviewRow::Skipped(skip) => {
+                let reason = match &skip.reason {
+                    SkipReason::Encoding => loc(LocId::ProjectReplaceSkippedEncoding).to_string(),
+                    SkipReason::Unsaved => loc(LocId::ProjectReplaceSkippedUnsaved).to_string(),
+                    SkipReason::Changed => loc(LocId::ProjectReplaceSkippedChanged).to_string(),
+                    SkipReason::Io(err) => format!("{}", FormatApperr::from(*err)),
+                    SkipReason::Save(err) => err.to_string(),
+                };
+                ctx.styled_label_set_foreground(red);
+                ctx.styled_label_add_text(&arena_format!(ctx.arena(), "{}: {}", skip.name, reason));
+            }
+        }
+
+        ctx.virtual_list_item_end();
+    }
+    let mut toggle = ctx.virtual_list_end(view).is_some();
+    toggle |= ctx.contains_focus() && ctx.consume_shortcut(vk::SPACE);
+
+    if toggle {
+        // Like a checkbox with several selected: Unless they're all checked already, check them.
+        let mut checks = Vec::new();
+        for row in view.selection() {
+            match rows.get(search, row) {
+                PreviewRow::File(file) => {
+                    let f = rows.file_index(row);
+                    checks.extend((0..file.matches.len()).map(|m| (f, m)));
+                }
+                PreviewRow::Match(..) => checks.push((rows.file_index(row), rows.match_index(row))),
+                PreviewRow::Skipped(_) => {}
+            }
+        }
+        let check = checks.iter().any(|&(f, m)| !search.files[f].selected[m]);
+        for (f, m) in checks {
+            search.files[f].selected[m] = check;
+        }
+        ctx.needs_rerender();
+    }
+}
+
+enum PreviewRow<'a> {
+    File(&'a ProjectFile),
+    Match(&'a ProjectFile, usize),
+    Skipped(&'a SkippedFile),
+}
+
+/// Maps the rows of the preview to the files and matches they show.
+struct PreviewRows {
+    /// The first row of each file, followed by the end of the last one.
+    starts: Vec<usize>,
+    skipped: usize,
+}
+
+impl PreviewRows {
+    fn new(search: &ProjectSearch) -> Self {
+        let mut starts = Vec::with_capacity(search.files.len() + 1);
+        let mut row = 0;
+        starts.push(row);
+        for file in &search.files {
+            row += 1 + file.matches.len();
+            starts.push(row);
+        }
+        Self { starts, skipped: search.skipped.len() }
+    }
+
+    fn len(&self) -> usize {
+        self.starts.last().unwrap() + self.skipped
+    }
+
+    /// The file that `row` belongs to, or `files.len()` for the skipped ones.
+    fn file_index(&self, row: usize) -> usize {
+        self.starts.partition_point(|&start| start <= row) - 1
+    }
+
+    /// The match in its file that `row` shows. Only valid for matches.
+    fn match_index(&self, row: usize) -> usize {
+        row - self.starts[self.file_index(row)] - 1
+    }
+
+    fn get<'a>(&self, search: &'a ProjectSearch, row: usize) -> PreviewRow<'a> {
+        let f = self.file_index(row);
+        match search.files.get(f) {
+            Some(file) if row == self.starts[f] => PreviewRow::File(file),
+            Some(file) => PreviewRow::Match(file, self.match_index(row)),
+            None => PreviewRow::Skipped(&search.skipped[row - self.starts[f]]),
+        }
+    }
+}
+
+/// Splits the line of the match into the text before, the match itself and the text after.
+fn match_line(m: &FindMatch) -> [Cow<'_, str>; 3] {
+    let text = &m.line_text[..];
+    let beg = (m.range.start - m.line_offset).min(text.len());
+    let end = (m.range.end - m.line_offset).clamp(beg, text.len());
+    [&text[..beg], &text[beg..end], &text[end..]].map(String::from_utf8_lossy)
+}
+
+fn project_replace_find(ctx: &mut Context, state: &mut State) {
+    state.project_replace = None;
+    state.project_replace_summary = None;
+    state.project_replace_view.clear();
+    ctx.needs_rerender();
+
+    if state.search_needle.is_empty() {
+        return;
+    }
+
+    match ProjectSearch::new(
+        Path::new(&state.project_replace_dir),
+        &state.search_needle,
+        state.search_options,
+        &state.search_replacement,
+    ) {
+        Ok(search) => {
+            state.project_replace = Some(search);
+            let title = loc(LocId::TaskProjectFind).to_string();
+            state.task = Some(Task::new(title, Job::ProjectFind));
+        }
+        Err(err) => error_log_add(ctx, state, err),
+    }
+}
+
+fn project_replace_apply(ctx: &mut Context, state: &mut State) {
+    let Some(search) = &mut state.project_replace else {
+        return;
+    };
+
+    search.start_replace();
+    let title = loc(LocId::TaskProjectReplace).to_string();
+    state.task = Some(Task::new(title, Job::ProjectReplace));
+    ctx.needs_rerender();
+}
+
+/// Called once the replacements were applied, or when that was cancelled.
+pub fn project_replace_applied(ctx: &mut Context, state: &mut State) {
+    let Some(search) = &mut state.project_replace else {
+        return;
+    };
+
+    // Documents with unsaved changes were skipped. Clean ones get reloaded.
+    let (summary, paths) = search.finish_replace();
+    for err in state.documents.reread_paths(&paths) {
+        error_log_add(ctx, state, err);
+    }
+
+    state.project_replace_summary = Some(summary);
+    state.notifier.finished(
+        ctx.is_window_focused(),
+        Notification {
+            title: loc(LocId::NotifyProjectReplaceDone).to_string(),
+            content: format!(
+                "{} {} / {} {}",
+                summary.matches,
+                loc(LocId::ProjectReplaceSummaryMatches),
+                summary.files,
+                loc(LocId::ProjectReplaceSummaryFiles),
+            ),
+            panel: Some(Panel::ProjectReplace),
+        },
+    );
+    ctx.needs_rerender();
+}
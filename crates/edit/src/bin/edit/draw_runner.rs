@@ -0,0 +1,178 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+use edit::framebuffer::IndexedColor;
+use edit::helpers::*;
+use edit::input::{kbmod, vk};
+use edit::tui::*;
+use stdext::arena_format;
+
+use crate::documents::OpenArgs;
+use crate::draw_terminal::{draw_line, terminal_height};
+use crate::localization::*;
+use crate::runner::{line_text, parse_location};
+use crate::state::*;
+
+/// Draws the output of Edit > Run Command below the editor. It takes up [`runner_height`] rows.
+pub fn draw_runner(ctx: &mut Context, state: &mut State) {
+    let size = Size { width: ctx.size().width, height: runner_height(ctx, state) - 1 };
+    let runner = &mut state.runner;
+    runner.term.resize(size);
+
+    let mut clicked = None;
+
+    ctx.block_begin("runner");
+    ctx.attr_focusable();
+    if state.wants_runner_focus || ctx.contains_mouse_down() {
+        state.wants_runner_focus = false;
+        ctx.steal_focus();
+    }
+    {
+        let title =
+            if runner.is_running() { loc(LocId::RunnerRunning) } else { loc(LocId::RunnerExited) };
+        let code = runner.term.exit_code().unwrap_or(-1);
+        let mut title = arena_format!(ctx.arena(), "{title}");
+        title.replace_once_in_place("{command}", &runner.command);
+        title.replace_once_in_place("{code}", &arena_format!(ctx.arena(), "{code}"));
+        ctx.label("title", &title);
+        ctx.attr_background_rgba(ctx.indexed_alpha(IndexedColor::Black, 1, 4));
+        ctx.attr_padding(Rect::two(0, 1));
+        ctx.attr_overflow(Overflow::TruncateTail);
+
+        let screen = runner.term.screen();
+        let height = screen.size().height as usize;
+        let first = screen.line_count() - height - runner.term.scroll;
+
+        for y in 0..height {
+            let index = first + y;
+            let mut line = screen.line(index).to_vec();
+            // Locations are underlined, since they can be clicked.
+            let location = parse_location(&line_text(&line)).is_some();
+            if location {
+                line.iter_mut().for_each(|cell| cell.style.underline = true);
+            }
+
+            ctx.next_block_id_mixin(y as u64);
+            ctx.styled_label_begin("line");
+            draw_line(ctx, &line, -1);
+            ctx.styled_label_end();
+            if location && ctx.was_clicked() {
+                clicked = Some(index);
+            }
+        }
+    }
+    ctx.block_end();
+
+    let runner = &mut state.runner;
+    runner.term.focused = ctx.contains_focus();
+
+    if let Some(delta) = ctx.scroll_input() {
+        let term = &mut runner.term;
+        term.scroll = term.scroll.saturating_add_signed(-delta.y);
+        term.scroll = term.scroll.min(term.screen().scrollback_len());
+        ctx.set_input_consumed();
+        ctx.needs_rerender();
+    }
+    if runner.term.focused {
+        draw_handle_runner_input(ctx, state);
+    }
+
+    if let Some(index) = clicked {
+        open_location(ctx, state, index);
+    }
+}
+
+/// The number of rows the output panel takes up, including its title.
+/// Together with the terminal panel the editor keeps at least half of the window.
+pub fn runner_height(ctx: &Context, state: &State) -> CoordType {
+    if !state.runner.term.visible {
+        return 0;
+    }
+    let room = ctx.size().height / 2 - terminal_height(ctx, state);
+    (state.runner.term.height + 1).min(room).max(2)
+}
+
+/// Enter runs the command again once it's done and Ctrl+C stops it.
+/// Shift+PageUp/PageDown scroll back and Esc returns to the editor.
+fn draw_handle_runner_input(ctx: &mut Context, state: &mut State) {
+    let Some(key) = ctx.keyboard_input() else {
+        return;
+    };
+    let runner = &mut state.runner;
+    let page = runner.term.screen().size().height;
+
+    match key {
+        vk::RETURN if !runner.is_running() => run_command(ctx, state),
+        _ if key == kbmod::CTRL | vk::C && runner.is_running() => runner.stop(),
+        vk::ESCAPE => state.wants_editor_focus = true,
+        _ if key == kbmod::SHIFT | vk::PRIOR => {
+            let scrollback = runner.term.screen().scrollback_len();
+            runner.term.scroll = runner.term.scroll.saturating_add_signed(page).min(scrollback);
+        }
+        _ if key == kbmod::SHIFT | vk::NEXT => {
+            runner.term.scroll = runner.term.scroll.saturating_add_signed(-page);
+        }
+        _ => return,
+    }
+
+    ctx.set_input_consumed();
+    ctx.needs_rerender();
+}
+
+/// Opens the file that the line `index` of the output refers to, at the location.
+fn open_location(ctx: &mut Context, state: &mut State, index: usize) {
+    let Some((path, goto)) = state.runner.location(index) else {
+        return;
+    };
+    let args = OpenArgs { goto: Some(goto), ..Default::default() };
+    match state.documents.add_file_path_with(&path, args) {
+        Ok(doc) => {
+            doc.buffer.borrow_mut().make_cursor_visible();
+            state.wants_editor_focus = true;
+        }
+        Err(err) => error_log_add(ctx, state, err),
+    }
+    ctx.needs_rerender();
+}
+
+/// Runs the command in the panel, which shows up if it's hidden.
+pub fn run_command(ctx: &mut Context, state: &mut State) {
+    if state.runner.command.trim().is_empty() {
+        state.wants_run_dialog = true;
+        return;
+    }
+
+    state.runner.term.visible = true;
+    let size = Size { width: ctx.size().width, height: runner_height(ctx, state) - 1 };
+    if let Err(err) = state.runner.run(size) {
+        state.runner.term.visible = false;
+        error_log_add(ctx, state, err);
+    }
+    ctx.needs_rerender();
+}
+
+/// Asks for the command to run, offering the last one.
+pub fn draw_run_dialog(ctx: &mut Context, state: &mut State) {
+    let mut done = false;
+    let mut run = false;
+
+    ctx.modal_begin("run", loc(LocId::EditRunCommand));
+    {
+        ctx.editline("command", &mut state.runner.command);
+        ctx.attr_intrinsic_size(Size { width: 40, height: 1 });
+        ctx.steal_focus();
+        if ctx.consume_shortcut(vk::RETURN) {
+            run = !state.runner.command.trim().is_empty();
+            done = run;
+        }
+    }
+    done |= ctx.modal_end();
+
+    if done {
+        state.wants_run_dialog = false;
+        ctx.needs_rerender();
+    }
+    if run {
+        run_command(ctx, state);
+    }
+}
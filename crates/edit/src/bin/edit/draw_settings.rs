@@ -0,0 +1,118 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+use edit::framebuffer::IndexedColor;
+use edit::helpers::*;
+use edit::tui::*;
+
+use crate::localization::*;
+use crate::settings::{self, SETTINGS, SettingKind, SettingValue, SettingsDialog};
+use crate::state::*;
+
+pub fn draw_settings(ctx: &mut Context, state: &mut State) {
+    // Taken out of `state`, so that the settings can be read and changed meanwhile.
+    let mut dialog = state.settings.take().unwrap_or_else(|| SettingsDialog::new(state));
+    let values: Vec<_> = SETTINGS.iter().map(|setting| settings::get(state, setting.id)).collect();
+    let width = (ctx.size().width - 20).max(10);
+    let height = (ctx.size().height - 12).clamp(1, SETTINGS.len() as CoordType);
+    let mut change = None;
+
+    ctx.modal_begin("settings", loc(LocId::SettingsDialogTitle));
+    {
+        ctx.table_begin("search");
+        ctx.table_set_columns(&[0, COORD_TYPE_SAFE_MAX]);
+        ctx.table_set_cell_gap(Size { width: 1, height: 0 });
+        ctx.inherit_focus();
+        {
+            ctx.table_next_row();
+            ctx.inherit_focus();
+
+            ctx.label("needle-label", loc(LocId::SearchNeedleLabel));
+            ctx.editline("needle", &mut dialog.needle);
+            ctx.inherit_focus();
+        }
+        ctx.table_end();
+
+        ctx.scrollarea_begin("scrollarea", Size { width, height });
+        ctx.attr_background_rgba(ctx.indexed_alpha(IndexedColor::Black, 1, 4));
+        {
+            ctx.table_begin("settings");
+            ctx.table_set_columns(&[0, COORD_TYPE_SAFE_MAX]);
+            ctx.table_set_cell_gap(Size { width: 2, height: 0 });
+            ctx.attr_padding(Rect::two(0, 1));
+
+            let mut any = false;
+
+            for (i, setting) in SETTINGS.iter().enumerate() {
+                let label = loc(setting.label);
+                if !dialog.matches(setting, label) {
+                    continue;
+                }
+                any = true;
+
+                ctx.next_block_id_mixin(i as u64);
+                ctx.table_next_row();
+                ctx.label("label", label);
+
+                match (&setting.kind, &values[i]) {
+                    (SettingKind::Bool, &SettingValue::Bool(checked)) => {
+                        let mut checked = checked;
+                        if ctx.checkbox("value", "", &mut checked) {
+                            change = Some((i, SettingValue::Bool(checked)));
+                        }
+                    }
+                    (&SettingKind::Integer(min, max), _) => {
+                        let field = &mut dialog.fields[i];
+                        let edited = ctx.editline("value", field);
+                        ctx.attr_intrinsic_size(Size { width: 6, height: 1 });
+                        match field.trim().parse::<CoordType>() {
+                            Ok(n) if (min..=max).contains(&n) => {
+                                if edited {
+                                    change = Some((i, SettingValue::Integer(n)));
+                                }
+                            }
+                            _ => {
+                                ctx.attr_background_rgba(ctx.indexed(IndexedColor::Red));
+                                ctx.attr_foreground_rgba(ctx.indexed(IndexedColor::BrightWhite));
+                            }
+                        }
+                    }
+                    (SettingKind::Choice, SettingValue::Choice(current)) => {
+                        // Pressing it cycles through the choices.
+                        let choices = dialog.choices(setting.id);
+                        let text =
+                            if current.is_empty() { loc(LocId::ThemeTerminal) } else { current };
+                        if ctx.button("value", text, ButtonStyle::default()) {
+                            let index = choices.iter().position(|c| c == current);
+                            let next = index.map_or(0, |i| (i + 1) % choices.len());
+                            change = Some((i, SettingValue::Choice(choices[next].to_string())));
+                        }
+                    }
+                    _ => unreachable!(),
+                }
+            }
+
+            ctx.table_end();
+
+            if !any {
+                ctx.label("empty", loc(LocId::SettingsEmpty));
+            }
+        }
+        ctx.scrollarea_end();
+    }
+    let done = ctx.modal_end();
+
+    if let Some((i, value)) = change {
+        if let Err(err) = settings::set(state, &SETTINGS[i], value) {
+            error_log_add_message(ctx, state, err);
+        }
+        ctx.needs_rerender();
+    }
+
+    if done {
+        state.wants_settings = false;
+        ctx.needs_rerender();
+    } else {
+        state.settings = Some(dialog);
+    }
+}
@@ -1,203 +1,617 @@
 // Copyright (c) Microsoft Corporation.
 // Licensed under the MIT License.
 
+use std::env;
+use std::path::PathBuf;
+
+use edit::buffer::TextBuffer;
 use edit::framebuffer::{Attributes, IndexedColor};
 use edit::fuzzy::score_fuzzy;
 use edit::helpers::*;
 use edit::icu;
 use edit::input::vk;
 use edit::tui::*;
+use edit::unicode::MeasurementConfig;
 use stdext::arena::scratch_arena;
 use stdext::arena_format;
 
+use crate::chord::ChordPrefix;
+use crate::draw_editor::{SearchAction, search_execute};
+use crate::draw_tooltip::tooltip_show;
+use crate::filetype::FILETYPES;
+use crate::inspect::{codepoints, grapheme_at_cursor};
 use crate::localization::*;
+use crate::softkeys::{SoftkeyAction, SoftkeyContext};
 use crate::state::*;
+use crate::statusbar::{self, Segment};
+
+/// Shows the pressed chord prefix and a popup listing the keys that may follow it.
+/// Returns the width of the former.
+fn draw_chord_hints(ctx: &mut Context, prefix: &ChordPrefix) -> CoordType {
+    let ctrl = loc(LocId::Ctrl);
+    let text = format!("{ctrl}+{} …", prefix.key);
+    ctx.label("chord", &text);
+
+    ctx.table_begin("chord-hints");
+    ctx.attr_float(FloatSpec {
+        anchor: Anchor::Last,
+        gravity_x: 0.0,
+        gravity_y: 1.0,
+        offset_x: 0.0,
+        offset_y: 0.0,
+    });
+    ctx.attr_border();
+    ctx.attr_padding(Rect::two(0, 1));
+    ctx.table_set_cell_gap(Size { width: 2, height: 0 });
+    {
+        for (i, binding) in prefix.bindings.iter().enumerate() {
+            ctx.next_block_id_mixin(i as u64);
+            ctx.table_next_row();
+            ctx.label("key", binding.key.encode_utf8(&mut [0; 4]));
+            ctx.label("action", loc(binding.label));
+        }
+    }
+    ctx.table_end();
+
+    text_width(&text)
+}
+
+/// Draws the row of tappable keys below the statusbar.
+pub fn draw_softkeys(ctx: &mut Context, state: &mut State) {
+    let context = match state.wants_search.kind {
+        StateSearchKind::Search => SoftkeyContext::Search,
+        StateSearchKind::Replace => SoftkeyContext::Replace,
+        _ => SoftkeyContext::Editor,
+    };
+    let mut action = None;
+
+    ctx.table_begin("softkeys");
+    ctx.attr_focus_keep();
+    ctx.attr_background_rgba(state.menubar_color_bg);
+    ctx.attr_foreground_rgba(state.menubar_color_fg);
+    ctx.table_set_cell_gap(Size { width: 1, height: 0 });
+    ctx.attr_intrinsic_size(Size { width: COORD_TYPE_SAFE_MAX, height: 1 });
+    {
+        ctx.table_next_row();
+
+        for (i, key) in state.softkeys.layout(context).iter().enumerate() {
+            ctx.next_block_id_mixin(i as u64);
+            ctx.label("key", key.label());
+            ctx.attr_padding(Rect::two(0, 1));
+            ctx.attr_reverse();
+            if ctx.was_clicked() {
+                action = Some(key.action);
+            }
+        }
+    }
+    ctx.table_end();
+
+    match action {
+        Some(SoftkeyAction::Key(key)) => state.softkeys.press(key),
+        Some(SoftkeyAction::ReplaceAll) => {
+            search_execute(ctx, state, SearchAction::ReplaceAll);
+            ctx.needs_rerender();
+        }
+        Some(SoftkeyAction::Dictate) => {
+            state.dictation.start();
+            ctx.needs_rerender();
+        }
+        None => {}
+    }
+}
 
 pub fn draw_statusbar(ctx: &mut Context, state: &mut State) {
+    let compact = is_compact(ctx);
+    let gap = if compact { 1 } else { 2 };
+
     ctx.table_begin("statusbar");
     ctx.attr_focus_well();
     ctx.attr_background_rgba(state.menubar_color_bg);
     ctx.attr_foreground_rgba(state.menubar_color_fg);
-    ctx.table_set_cell_gap(Size { width: 2, height: 0 });
+    ctx.table_set_cell_gap(Size { width: gap, height: 0 });
     ctx.attr_intrinsic_size(Size { width: COORD_TYPE_SAFE_MAX, height: 1 });
     ctx.attr_padding(Rect::two(0, 1));
 
     if let Some(doc) = state.documents.active() {
-        let mut tb = doc.buffer.borrow_mut();
+        let buffer = doc.buffer.clone();
+        let mut tb = buffer.borrow_mut();
+        let info = DocumentInfo {
+            filename: doc.filename.clone(),
+            filetype: doc.filetype.map_or(loc(LocId::FileTypePlainText), |ft| ft.name),
+            dir: doc.dir.as_ref().map(|dir| dir.as_path().to_path_buf()),
+            has_path: doc.path.is_some(),
+        };
+        let loading = doc.loading.as_ref().map(|loader| loader.progress());
 
         ctx.table_next_row();
 
-        if ctx.button("newline", if tb.is_crlf() { "CRLF" } else { "LF" }, ButtonStyle::default()) {
-            let is_crlf = tb.is_crlf();
-            tb.normalize_newlines(!is_crlf);
+        // These aren't segments: They're shown while they matter, whatever the layout.
+        let mut hints_width = 0;
+        if let Some(prefix) = state.chords.pending() {
+            hints_width += draw_chord_hints(ctx, prefix) + gap;
         }
-        if state.wants_statusbar_focus {
-            state.wants_statusbar_focus = false;
-            ctx.steal_focus();
+        if state.digraph != StateDigraph::None {
+            let first = match state.digraph {
+                StateDigraph::Second(ch) => ch,
+                _ => '_',
+            };
+            let text = arena_format!(ctx.arena(), "{} {first}", loc(LocId::DigraphPending));
+            ctx.label("digraph", &text);
+            hints_width += text_width(&text) + gap;
+        }
+        if state.back.is_armed() {
+            let text = loc(LocId::BackPressAgain);
+            ctx.label("back-hint", text);
+            hints_width += text_width(text) + gap;
+        }
+        if state.dictation.is_listening() {
+            let text = loc(LocId::DictationListening);
+            ctx.label("dictation", text);
+            hints_width += text_width(text) + gap;
+        }
+        if let Some(progress) = loading {
+            let percent = (progress.fraction().unwrap_or(0.0) * 100.0) as u32;
+            let text = arena_format!(ctx.arena(), "{} {percent}%", loc(LocId::DocumentLoading));
+            ctx.label("loading", &text);
+            hints_width += text_width(&text) + gap;
         }
 
-        state.wants_encoding_picker |=
-            ctx.button("encoding", tb.encoding(), ButtonStyle::default());
-        if state.wants_encoding_picker {
-            if doc.path.is_some() {
-                ctx.block_begin("frame");
-                ctx.attr_float(FloatSpec {
-                    anchor: Anchor::Last,
-                    gravity_x: 0.0,
-                    gravity_y: 1.0,
-                    offset_x: 0.0,
-                    offset_y: 0.0,
-                });
-                ctx.attr_padding(Rect::two(0, 1));
-                ctx.attr_border();
-                {
-                    if ctx.button("reopen", loc(LocId::EncodingReopen), ButtonStyle::default()) {
-                        state.wants_encoding_change = StateEncodingChange::Reopen;
-                    }
-                    ctx.focus_on_first_present();
-                    if ctx.button("convert", loc(LocId::EncodingConvert), ButtonStyle::default()) {
-                        state.wants_encoding_change = StateEncodingChange::Convert;
-                    }
-                }
-                ctx.block_end();
-            } else {
-                // Can't reopen a file that doesn't exist.
-                state.wants_encoding_change = StateEncodingChange::Convert;
-            }
-
-            if !ctx.contains_focus() {
-                state.wants_encoding_picker = false;
-                ctx.needs_rerender();
-            }
+        let segments: Vec<_> = state
+            .statusbar
+            .left
+            .iter()
+            .map(|&segment| (segment, false))
+            .chain(state.statusbar.right.iter().map(|&segment| (segment, true)))
+            .collect();
+        let texts: Vec<_> = segments
+            .iter()
+            .map(|&(segment, _)| segment_text(ctx, state, &tb, &info, segment))
+            .collect();
+
+        // Everything but the file name is left out rather than truncated. The buttons
+        // have brackets and one column is kept for the gap before the right-aligned ones.
+        let mut widths: Vec<_> = segments
+            .iter()
+            .zip(&texts)
+            .map(|(&(segment, _), text)| {
+                let brackets = if is_button(segment) { 2 } else { 0 };
+                (segment, text.map_or(0, |text| text_width(text) + brackets))
+            })
+            .collect();
+        let available = ctx.size().width - 2 - hints_width - gap;
+        let filename_width = statusbar::fit(&mut widths, gap, available);
+
+        let (left, right): (Vec<_>, Vec<_>) = segments
+            .iter()
+            .zip(&texts)
+            .zip(&widths)
+            .filter(|(_, (_, width))| *width > 0)
+            .map(|((&(segment, right), text), _)| (segment, right, text.unwrap_or_default()))
+            .partition(|&(_, right, _)| !right);
+
+        for (segment, _, text) in left {
+            draw_segment(ctx, state, &mut tb, &info, segment, text, filename_width);
         }
 
-        state.wants_indentation_picker |= ctx.button(
-            "indentation",
-            &arena_format!(
-                ctx.arena(),
-                "{}:{}",
-                loc(if tb.indent_with_tabs() {
-                    LocId::IndentationTabs
-                } else {
-                    LocId::IndentationSpaces
-                }),
-                tb.tab_size(),
-            ),
-            ButtonStyle::default(),
+        #[cfg(feature = "debug-latency")]
+        ctx.label(
+            "stats",
+            &arena_format!(ctx.arena(), "{}/{}", tb.logical_line_count(), tb.visual_line_count(),),
         );
-        if state.wants_indentation_picker {
-            ctx.table_begin("indentation-picker");
-            ctx.attr_float(FloatSpec {
-                anchor: Anchor::Last,
-                gravity_x: 0.0,
-                gravity_y: 1.0,
-                offset_x: 0.0,
-                offset_y: 0.0,
-            });
-            ctx.attr_border();
-            ctx.attr_padding(Rect::two(0, 1));
-            ctx.table_set_cell_gap(Size { width: 1, height: 0 });
+
+        ctx.block_begin("right");
+        ctx.attr_intrinsic_size(Size { width: COORD_TYPE_SAFE_MAX, height: 1 });
+        {
+            ctx.table_begin("segments");
+            ctx.table_set_cell_gap(Size { width: gap, height: 0 });
+            ctx.attr_position(Position::Right);
+            ctx.inherit_focus();
             {
-                if ctx.contains_focus() && ctx.consume_shortcut(vk::RETURN) {
-                    ctx.toss_focus_up();
+                ctx.table_next_row();
+                for (segment, _, text) in right {
+                    draw_segment(ctx, state, &mut tb, &info, segment, text, filename_width);
                 }
+            }
+            ctx.table_end();
+        }
+        ctx.block_end();
 
-                ctx.table_next_row();
+        // A picker can't stay open when its button went away.
+        let shows = |segment| widths.iter().any(|&(s, width)| s == segment && width > 0);
+        state.wants_encoding_picker &= shows(Segment::Encoding);
+        state.wants_indentation_picker &= shows(Segment::Indentation);
+        state.wants_newline_picker &= shows(Segment::LineEnding);
+    } else {
+        state.wants_statusbar_focus = false;
+        state.wants_encoding_picker = false;
+        state.wants_indentation_picker = false;
+        state.wants_newline_picker = false;
+    }
 
-                ctx.list_begin("type");
-                ctx.focus_on_first_present();
-                ctx.attr_padding(Rect::two(0, 1));
-                {
-                    if ctx.list_item(tb.indent_with_tabs(), loc(LocId::IndentationTabs))
-                        != ListSelection::Unchanged
-                    {
-                        tb.set_indent_with_tabs(true);
-                        ctx.needs_rerender();
-                    }
-                    if ctx.list_item(!tb.indent_with_tabs(), loc(LocId::IndentationSpaces))
-                        != ListSelection::Unchanged
-                    {
-                        tb.set_indent_with_tabs(false);
-                        ctx.needs_rerender();
-                    }
-                }
-                ctx.list_end();
+    ctx.table_end();
+}
 
-                ctx.list_begin("width");
-                ctx.attr_padding(Rect::two(0, 2));
-                {
-                    for width in 1u8..=8 {
-                        let ch = [b'0' + width];
-                        let label = unsafe { std::str::from_utf8_unchecked(&ch) };
-
-                        if ctx.list_item(tb.tab_size() == width as CoordType, label)
-                            != ListSelection::Unchanged
-                        {
-                            tb.set_tab_size(width as CoordType);
-                            ctx.needs_rerender();
-                        }
-                    }
-                }
-                ctx.list_end();
+/// What the segments need to know about the active document, besides its buffer.
+struct DocumentInfo {
+    filename: String,
+    filetype: &'static str,
+    dir: Option<PathBuf>,
+    has_path: bool,
+}
+
+pub fn text_width(text: &str) -> CoordType {
+    MeasurementConfig::new(&text.as_bytes())
+        .goto_visual(Point { x: CoordType::MAX, y: 0 })
+        .visual_pos
+        .x
+}
+
+fn is_button(segment: Segment) -> bool {
+    !matches!(segment, Segment::Mode | Segment::Dirty | Segment::GitBranch)
+}
+
+/// What a segment shows, or `None` if there's nothing to show, like the dirty flag for a saved file.
+fn segment_text<'a>(
+    ctx: &Context<'a, '_>,
+    state: &mut State,
+    tb: &TextBuffer,
+    info: &DocumentInfo,
+    segment: Segment,
+) -> Option<&'a str> {
+    let compact = is_compact(ctx);
+    let text = match segment {
+        Segment::Mode if state.vim.enabled => {
+            arena_format!(ctx.arena(), "{}", state.vim.status(compact))
+        }
+        Segment::FileType => arena_format!(ctx.arena(), "{}", info.filetype),
+        Segment::LineEnding => arena_format!(
+            ctx.arena(),
+            "{}{}",
+            if tb.has_mixed_newlines() { "⚠ " } else { "" },
+            if tb.is_crlf() { "CRLF" } else { "LF" }
+        ),
+        // "UTF-8 BOM" already says so.
+        Segment::Encoding if tb.has_bom() && !tb.encoding().ends_with("BOM") => {
+            arena_format!(ctx.arena(), "{} BOM", tb.encoding())
+        }
+        Segment::Encoding => arena_format!(ctx.arena(), "{}", tb.encoding()),
+        Segment::Indentation if compact => arena_format!(
+            ctx.arena(),
+            "{}{}",
+            if tb.indent_with_tabs() { '⇥' } else { '␣' },
+            tb.tab_size()
+        ),
+        Segment::Indentation => arena_format!(
+            ctx.arena(),
+            "{}:{}",
+            loc(if tb.indent_with_tabs() {
+                LocId::IndentationTabs
+            } else {
+                LocId::IndentationSpaces
+            }),
+            tb.tab_size(),
+        ),
+        Segment::Position => arena_format!(
+            ctx.arena(),
+            "{}:{}",
+            tb.cursor_logical_pos().y + 1,
+            tb.cursor_logical_pos().x + 1
+        ),
+        Segment::Overtype if tb.is_overtype() => arena_format!(ctx.arena(), "OVR"),
+        Segment::ReadOnly if tb.is_read_only() => arena_format!(ctx.arena(), "R/O"),
+        Segment::Dirty if tb.is_dirty() => arena_format!(ctx.arena(), "*"),
+        Segment::Character => {
+            arena_format!(ctx.arena(), "{}", codepoints(&grapheme_at_cursor(tb)?, 3))
+        }
+        Segment::GitBranch => {
+            // Unnamed documents are saved to the current directory by default.
+            let dir = info.dir.clone().or_else(|| env::current_dir().ok())?;
+            arena_format!(ctx.arena(), "⎇ {}", state.git_branch.get(&dir)?)
+        }
+        Segment::FileName => {
+            let total = state.documents.len();
+            if total > 1 {
+                let sep = if compact { "+" } else { " + " };
+                arena_format!(ctx.arena(), "{}{}{}", info.filename, sep, total - 1)
+            } else {
+                arena_format!(ctx.arena(), "{}", info.filename)
             }
-            ctx.table_end();
+        }
+        _ => return None,
+    };
+    Some(text.leak())
+}
+
+fn draw_segment(
+    ctx: &mut Context,
+    state: &mut State,
+    tb: &mut TextBuffer,
+    info: &DocumentInfo,
+    segment: Segment,
+    text: &str,
+    filename_width: CoordType,
+) {
+    if segment == Segment::FileName {
+        // The button is truncated to the width of this container.
+        ctx.block_begin("filename-container");
+        let width = text_width(text) + 2;
+        ctx.attr_intrinsic_size(Size { width: width.min(filename_width), height: 1 });
+        ctx.inherit_focus();
+    }
+
+    let clicked = if is_button(segment) {
+        let clicked = ctx.button(segment_classname(segment), text, ButtonStyle::default());
+        // The first button takes the focus when View > Focus Statusbar asks for it.
+        if state.wants_statusbar_focus {
+            state.wants_statusbar_focus = false;
+            ctx.steal_focus();
+        }
+        clicked
+    } else {
+        ctx.label(segment_classname(segment), text);
+        false
+    };
+
+    match segment {
+        Segment::FileType => state.wants_filetype_picker |= clicked,
+        Segment::LineEnding => {
+            state.wants_newline_picker |= clicked;
+            if state.wants_newline_picker {
+                draw_newline_menu(ctx, state, tb);
+            }
+        }
+        Segment::Position => state.wants_goto |= clicked,
+        Segment::Character => state.wants_char_inspector |= clicked,
+        Segment::Encoding => {
+            state.wants_encoding_picker |= clicked;
+            if state.wants_encoding_picker {
+                draw_encoding_menu(ctx, state, tb, info.has_path);
+            }
+        }
+        Segment::Indentation => {
+            state.wants_indentation_picker |= clicked;
+            if state.wants_indentation_picker {
+                draw_indentation_picker(ctx, state, tb);
+            }
+        }
+        Segment::Overtype if clicked => {
+            tb.set_overtype(false);
+            ctx.needs_rerender();
+        }
+        Segment::ReadOnly if clicked => {
+            tb.set_read_only(false);
+            ctx.needs_rerender();
+        }
+        Segment::FileName => {
+            state.wants_go_to_file |= clicked;
+            ctx.inherit_focus();
+            ctx.attr_overflow(Overflow::TruncateMiddle);
+            // A long press, right-click or the Menu key reveal the truncated name in full.
+            if let Some(pos) = ctx.context_menu_input()
+                && text_width(text) + 2 > filename_width
+            {
+                let full = match &info.dir {
+                    Some(dir) => dir.join(&info.filename).display().to_string(),
+                    None => text.to_string(),
+                };
+                tooltip_show(ctx, state, pos, full, tb.cursor_logical_pos());
+            }
+            ctx.block_end();
+        }
+        _ => {}
+    }
+}
 
-            if !ctx.contains_focus() {
-                state.wants_indentation_picker = false;
+fn segment_classname(segment: Segment) -> &'static str {
+    match segment {
+        Segment::Character => "character",
+        Segment::GitBranch => "git-branch",
+        Segment::Encoding => "encoding",
+        Segment::LineEnding => "newline",
+        Segment::FileType => "filetype",
+        Segment::Indentation => "indentation",
+        Segment::Mode => "vim-mode",
+        Segment::Position => "location",
+        Segment::Overtype => "overtype",
+        Segment::ReadOnly => "read-only",
+        Segment::Dirty => "dirty",
+        Segment::FileName => "filename",
+    }
+}
+
+/// The popup of the encoding button: Reopen the file in another encoding, convert it,
+/// or add or remove its byte order mark.
+fn draw_encoding_menu(ctx: &mut Context, state: &mut State, tb: &mut TextBuffer, has_path: bool) {
+    if has_path {
+        ctx.block_begin("frame");
+        ctx.attr_float(FloatSpec {
+            anchor: Anchor::Last,
+            gravity_x: 0.0,
+            gravity_y: 1.0,
+            offset_x: 0.0,
+            offset_y: 0.0,
+        });
+        ctx.attr_padding(Rect::two(0, 1));
+        ctx.attr_border();
+        {
+            if ctx.button("reopen", loc(LocId::EncodingReopen), ButtonStyle::default()) {
+                state.wants_encoding_change = StateEncodingChange::Reopen;
+            }
+            ctx.focus_on_first_present();
+            if ctx.button("convert", loc(LocId::EncodingConvert), ButtonStyle::default()) {
+                state.wants_encoding_change = StateEncodingChange::Convert;
+            }
+            let bom = tb.has_bom();
+            let label = if bom { LocId::EncodingRemoveBom } else { LocId::EncodingAddBom };
+            if ctx.button("bom", loc(label), ButtonStyle::default()) {
+                tb.set_bom(!bom);
+                state.wants_encoding_picker = false;
                 ctx.needs_rerender();
             }
         }
+        ctx.block_end();
+    } else {
+        // Can't reopen a file that doesn't exist.
+        state.wants_encoding_change = StateEncodingChange::Convert;
+    }
 
-        ctx.label(
-            "location",
-            &arena_format!(
-                ctx.arena(),
-                "{}:{}",
-                tb.cursor_logical_pos().y + 1,
-                tb.cursor_logical_pos().x + 1
-            ),
-        );
+    if !ctx.contains_focus() {
+        state.wants_encoding_picker = false;
+        ctx.needs_rerender();
+    }
+}
 
-        #[cfg(feature = "debug-latency")]
-        ctx.label(
-            "stats",
-            &arena_format!(ctx.arena(), "{}/{}", tb.logical_line_count(), tb.visual_line_count(),),
-        );
+/// The popup of the line ending button: Convert the file to LF or CRLF, or repair it if it
+/// mixes both.
+fn draw_newline_menu(ctx: &mut Context, state: &mut State, tb: &mut TextBuffer) {
+    let mut crlf = None;
+
+    ctx.block_begin("frame");
+    ctx.attr_float(FloatSpec {
+        anchor: Anchor::Last,
+        gravity_x: 0.0,
+        gravity_y: 1.0,
+        offset_x: 0.0,
+        offset_y: 0.0,
+    });
+    ctx.attr_padding(Rect::two(0, 1));
+    ctx.attr_border();
+    {
+        // The current one is still listed, but the focus starts on the other.
+        // A mixed file is more likely to be repaired to the one most lines have.
+        let focus_crlf = tb.is_crlf() == tb.has_mixed_newlines();
+        if ctx.button("lf", loc(LocId::NewlineConvertLf), ButtonStyle::default()) {
+            crlf = Some(false);
+        }
+        if !focus_crlf {
+            ctx.focus_on_first_present();
+        }
+        if ctx.button("crlf", loc(LocId::NewlineConvertCrlf), ButtonStyle::default()) {
+            crlf = Some(true);
+        }
+        if focus_crlf {
+            ctx.focus_on_first_present();
+        }
+    }
+    ctx.block_end();
 
-        if tb.is_overtype() && ctx.button("overtype", "OVR", ButtonStyle::default()) {
-            tb.set_overtype(false);
-            ctx.needs_rerender();
+    if let Some(crlf) = crlf {
+        // Repairing a mixed file only changes the stray newlines, as a single undo step.
+        if tb.has_mixed_newlines() {
+            tb.repair_newlines(crlf);
+        } else {
+            tb.normalize_newlines(crlf);
+        }
+    }
+    if crlf.is_some() || !ctx.contains_focus() {
+        state.wants_newline_picker = false;
+        ctx.needs_rerender();
+    }
+}
+
+fn draw_indentation_picker(ctx: &mut Context, state: &mut State, tb: &mut TextBuffer) {
+    ctx.table_begin("indentation-picker");
+    ctx.attr_float(FloatSpec {
+        anchor: Anchor::Last,
+        gravity_x: 0.0,
+        gravity_y: 1.0,
+        offset_x: 0.0,
+        offset_y: 0.0,
+    });
+    ctx.attr_border();
+    ctx.attr_padding(Rect::two(0, 1));
+    ctx.table_set_cell_gap(Size { width: 1, height: 0 });
+    {
+        if ctx.contains_focus() && ctx.consume_shortcut(vk::RETURN) {
+            ctx.toss_focus_up();
         }
 
-        if tb.is_dirty() {
-            ctx.label("dirty", "*");
+        ctx.table_next_row();
+
+        ctx.list_begin("type");
+        ctx.focus_on_first_present();
+        ctx.attr_padding(Rect::two(0, 1));
+        {
+            if ctx.list_item(tb.indent_with_tabs(), loc(LocId::IndentationTabs))
+                != ListSelection::Unchanged
+            {
+                tb.set_indent_with_tabs(true);
+                ctx.needs_rerender();
+            }
+            if ctx.list_item(!tb.indent_with_tabs(), loc(LocId::IndentationSpaces))
+                != ListSelection::Unchanged
+            {
+                tb.set_indent_with_tabs(false);
+                ctx.needs_rerender();
+            }
         }
+        ctx.list_end();
 
-        ctx.block_begin("filename-container");
-        ctx.attr_intrinsic_size(Size { width: COORD_TYPE_SAFE_MAX, height: 1 });
+        ctx.list_begin("width");
+        ctx.attr_padding(Rect::two(0, 2));
         {
-            let total = state.documents.len();
-            let mut filename = doc.filename.as_str();
-            let filename_buf;
+            for width in 1u8..=8 {
+                let ch = [b'0' + width];
+                let label = unsafe { std::str::from_utf8_unchecked(&ch) };
 
-            if total > 1 {
-                filename_buf = arena_format!(ctx.arena(), "{} + {}", filename, total - 1);
-                filename = &filename_buf;
+                if ctx.list_item(tb.tab_size() == width as CoordType, label)
+                    != ListSelection::Unchanged
+                {
+                    tb.set_tab_size(width as CoordType);
+                    ctx.needs_rerender();
+                }
             }
+        }
+        ctx.list_end();
+    }
+    ctx.table_end();
 
-            state.wants_go_to_file |= ctx.button("filename", filename, ButtonStyle::default());
+    if !ctx.contains_focus() {
+        state.wants_indentation_picker = false;
+        ctx.needs_rerender();
+    }
+}
+
+pub fn draw_filetype_picker(ctx: &mut Context, state: &mut State) {
+    let current = state.documents.active().and_then(|doc| doc.filetype.map(|ft| ft.name));
+    let width = (ctx.size().width - 20).max(10);
+    let height = (ctx.size().height - 10).clamp(1, FILETYPES.len() as CoordType + 1);
+    let mut activated = None;
+
+    ctx.modal_begin("filetype", loc(LocId::FileTypeDialogTitle));
+    ctx.attr_intrinsic_size(Size { width, height: height + 2 });
+    {
+        ctx.scrollarea_begin("filetypes", Size { width: 0, height });
+        ctx.attr_padding(Rect::two(1, 1));
+        {
+            ctx.list_begin("filetypes");
             ctx.inherit_focus();
-            ctx.attr_overflow(Overflow::TruncateMiddle);
-            ctx.attr_position(Position::Right);
+            if ctx.list_item(current.is_none(), loc(LocId::FileTypePlainText))
+                == ListSelection::Activated
+            {
+                activated = Some(None);
+            }
+            for filetype in FILETYPES {
+                if ctx.list_item(current == Some(filetype.name), filetype.name)
+                    == ListSelection::Activated
+                {
+                    activated = Some(Some(filetype));
+                }
+            }
+            ctx.list_end();
         }
-        ctx.block_end();
-    } else {
-        state.wants_statusbar_focus = false;
-        state.wants_encoding_picker = false;
-        state.wants_indentation_picker = false;
+        ctx.scrollarea_end();
     }
+    let mut done = ctx.modal_end();
 
-    ctx.table_end();
+    if let Some(filetype) = activated {
+        state.documents.set_active_filetype(filetype);
+        done = true;
+    }
+    if done {
+        state.wants_filetype_picker = false;
+        ctx.needs_rerender();
+    }
 }
 
 pub fn draw_dialog_encoding_change(ctx: &mut Context, state: &mut State) {
@@ -260,13 +674,13 @@ pub fn draw_dialog_encoding_change(ctx: &mut Context, state: &mut State) {
         if reopen && doc.path.is_some() {
             let mut res = Ok(());
             if doc.buffer.borrow().is_dirty() {
-                res = doc.save(None);
+                res = doc.save(None, &state.backup).map_err(|err| err.to_string());
             }
             if res.is_ok() {
-                res = doc.reread(Some(encoding));
+                res = doc.reread(Some(encoding)).map_err(|err| FormatApperr::from(err).to_string());
             }
-            if let Err(err) = res {
-                error_log_add(ctx, state, err);
+            if let Err(msg) = res {
+                error_log_add_message(ctx, state, msg);
             }
         } else {
             doc.buffer.borrow_mut().set_encoding(encoding);
@@ -302,7 +716,7 @@ fn encoding_picker_update_list(state: &mut State) {
         }
     }
 
-    matches.sort_by(|a, b| b.0.cmp(&a.0));
+    matches.sort_by_key(|&(score, _)| std::cmp::Reverse(score));
     state.encoding_picker_results = Some(Vec::from_iter(matches.iter().map(|(_, enc)| *enc)));
 }
 
@@ -0,0 +1,115 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+use std::mem;
+use std::path::Path;
+
+use edit::apperr;
+use edit::input::vk;
+use edit::tui::*;
+
+use crate::documents::ReadAhead;
+use crate::draw_project_search::project_replace_applied;
+use crate::localization::*;
+use crate::state::*;
+use crate::task::{Job, Task};
+use crate::toast::Severity;
+
+/// Runs a slice of the current task if the main loop asked for one and finishes the task
+/// once it's done, see [`crate::task`]. Esc cancels it. This runs before anything else gets
+/// to see the key.
+pub fn draw_handle_task(ctx: &mut Context, state: &mut State) {
+    let Some(task) = &mut state.task else {
+        return;
+    };
+
+    if ctx.keyboard_input() == Some(vk::ESCAPE) {
+        task.cancel();
+        ctx.set_input_consumed();
+    }
+    // Not once per draw: The layout never settles, if each pass changes the progress.
+    if !mem::take(&mut state.wants_task_step) && !task.is_cancelled() {
+        return;
+    }
+
+    let deadline = Task::deadline();
+    let cancelled = task.is_cancelled();
+    let done = match &mut task.job {
+        _ if cancelled => true,
+        Job::Open(read_ahead) => {
+            let done = read_ahead.step(deadline);
+            task.progress = read_ahead.progress();
+            done
+        }
+        Job::ProjectFind => state.project_replace.as_mut().is_none_or(|search| {
+            let done = search.find_step(deadline);
+            task.progress = search.find_progress();
+            done
+        }),
+        Job::ProjectReplace => {
+            let documents = &state.documents;
+            let backup = &state.backup;
+            state.project_replace.as_mut().is_none_or(|search| {
+                let done = search.replace_step(deadline, backup, |path| documents.is_unsaved(path));
+                task.progress = search.replace_progress();
+                done
+            })
+        }
+    };
+    if !done {
+        return;
+    }
+    ctx.needs_rerender();
+
+    let task = state.task.take().unwrap();
+    match task.job {
+        Job::Open(read_ahead) if !cancelled => {
+            match state.documents.add_file_path(&read_ahead.path) {
+                Ok(_) => state.wants_editor_focus = true,
+                Err(err) => error_log_add(ctx, state, err),
+            }
+        }
+        Job::Open(_) | Job::ProjectFind => {}
+        Job::ProjectReplace => project_replace_applied(ctx, state),
+    }
+
+    if cancelled {
+        let msg = format!("{}: {}", task.title, loc(LocId::TaskCancelled));
+        toast_add(ctx, state, Severity::Info, msg);
+    }
+}
+
+/// Appends what was read of the large files that are still loading, see [`crate::documents::Loader`].
+pub fn draw_handle_loading(ctx: &mut Context, state: &mut State) {
+    // Not once per draw, for the same reason as in `draw_handle_task`.
+    state.wants_load_step = false;
+    let (changed, errors) = state.documents.poll_loading();
+    if changed {
+        ctx.needs_rerender();
+    }
+    for err in errors {
+        error_log_add(ctx, state, err);
+    }
+}
+
+/// Highlights the active document a bit further, see [`edit::buffer::TextBuffer::highlight_step`].
+pub fn draw_handle_highlighting(ctx: &mut Context, state: &mut State) {
+    state.wants_highlight_step = false;
+    if state.documents.highlight_step() {
+        ctx.needs_rerender();
+    }
+}
+
+/// Opens a file. Large ones are shown while they're loading, see [`crate::documents::Loader`],
+/// or if they aren't UTF-8, read ahead first with a progress bar, see [`ReadAhead`].
+/// That's skipped while another task is running, since there's only room for one.
+pub fn open_file(state: &mut State, path: &Path) -> apperr::Result<()> {
+    match state.task.is_none().then(|| ReadAhead::new(path)).flatten() {
+        Some(read_ahead) => {
+            let title = loc(LocId::TaskOpen).to_string();
+            state.task = Some(Task::new(title, Job::Open(read_ahead)));
+            Ok(())
+        }
+        None => state.documents.add_file_path(path).map(|_| ()),
+    }
+}
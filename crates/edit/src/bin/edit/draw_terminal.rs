@@ -0,0 +1,339 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+use std::env;
+
+use edit::framebuffer::{Attributes, IndexedColor};
+use edit::helpers::*;
+use edit::input::{InputKey, InputKeyMod, kbmod, vk};
+use edit::oklab::StraightRgba;
+use edit::tui::*;
+
+use crate::localization::*;
+use crate::state::*;
+use crate::terminal::{Cell, Color, Style};
+
+/// Draws the terminal panel below the editor. It takes up [`terminal_height`] rows.
+pub fn draw_terminal(ctx: &mut Context, state: &mut State) {
+    // Once the shell exited, its last words stay until Enter restarts it.
+    if !state.terminal.has_exited() && !start_shell(ctx, state) {
+        return;
+    }
+
+    let size = Size { width: ctx.size().width, height: terminal_height(ctx, state) - 1 };
+    let term = &mut state.terminal;
+    term.resize(size);
+
+    ctx.block_begin("terminal");
+    ctx.attr_focusable();
+    if state.wants_terminal_focus || ctx.contains_mouse_down() {
+        state.wants_terminal_focus = false;
+        ctx.steal_focus();
+    }
+    {
+        let title =
+            if term.has_exited() { loc(LocId::TerminalExited) } else { loc(LocId::TerminalTitle) };
+        ctx.label("title", title);
+        ctx.attr_background_rgba(ctx.indexed_alpha(IndexedColor::Black, 1, 4));
+        ctx.attr_padding(Rect::two(0, 1));
+        ctx.attr_overflow(Overflow::TruncateTail);
+
+        let screen = term.screen();
+        let height = screen.size().height as usize;
+        let first = screen.line_count() - height - term.scroll;
+        let cursor = screen.cursor();
+        let show_cursor = term.focused && term.scroll == 0 && screen.cursor_visible;
+
+        for y in 0..height {
+            ctx.next_block_id_mixin(y as u64);
+            ctx.styled_label_begin("line");
+            let cursor_x = if show_cursor && y as CoordType == cursor.y { cursor.x } else { -1 };
+            draw_line(ctx, screen.line(first + y), cursor_x);
+            ctx.styled_label_end();
+        }
+    }
+    ctx.block_end();
+
+    let term = &mut state.terminal;
+    term.focused = ctx.contains_focus();
+
+    if let Some(delta) = ctx.scroll_input() {
+        term.scroll = term.scroll.saturating_add_signed(-delta.y);
+        term.scroll = term.scroll.min(term.screen().scrollback_len());
+        ctx.set_input_consumed();
+        ctx.needs_rerender();
+    }
+    if term.focused {
+        draw_handle_terminal_input(ctx, state);
+    }
+}
+
+/// Starts the shell in the working directory, unless it's running already.
+/// Returns whether it runs.
+fn start_shell(ctx: &mut Context, state: &mut State) -> bool {
+    if state.terminal.is_running() {
+        return true;
+    }
+
+    let cwd = env::current_dir().unwrap_or_default();
+    let size = Size { width: ctx.size().width, height: terminal_height(ctx, state) - 1 };
+    match state.terminal.start(&cwd, size) {
+        Ok(()) => true,
+        Err(err) => {
+            state.terminal.visible = false;
+            error_log_add(ctx, state, err);
+            false
+        }
+    }
+}
+
+/// The number of rows the terminal panel takes up, including its title.
+/// The editor keeps at least half of the window.
+pub fn terminal_height(ctx: &Context, state: &State) -> CoordType {
+    if !state.terminal.visible {
+        return 0;
+    }
+    (state.terminal.height + 1).min(ctx.size().height / 2).max(2)
+}
+
+/// Adds a line of the screen to the current label, with `cursor_x` highlighted.
+pub fn draw_line(ctx: &mut Context, line: &[Cell], cursor_x: CoordType) {
+    let mut text = String::with_capacity(line.len());
+    let mut run_style = None;
+
+    for (x, cell) in line.iter().enumerate() {
+        if cell.ch == '\0' {
+            continue;
+        }
+        let mut style = cell.style;
+        if x as CoordType == cursor_x {
+            style.reverse = !style.reverse;
+        }
+        if run_style != Some(style) {
+            ctx.styled_label_add_text(&text);
+            text.clear();
+            set_style(ctx, style);
+            run_style = Some(style);
+        }
+        text.push(cell.ch);
+    }
+
+    ctx.styled_label_add_text(text.trim_end_matches(' '));
+}
+
+fn set_style(ctx: &mut Context, style: Style) {
+    let mut fg = style.fg;
+    // Bold text is traditionally drawn in the bright colors.
+    if style.bold
+        && let Color::Indexed(index @ 0..8) = fg
+    {
+        fg = Color::Indexed(index + 8);
+    }
+
+    let mut fg = resolve_color(ctx, fg, IndexedColor::Foreground);
+    let mut bg = resolve_color(ctx, style.bg, IndexedColor::Background);
+    if style.reverse {
+        (fg, bg) = (bg, fg);
+    }
+
+    let mut attr = Attributes::None;
+    if style.italic {
+        attr = attr | Attributes::Italic;
+    }
+    if style.underline {
+        attr = attr | Attributes::Underlined;
+    }
+
+    ctx.styled_label_set_foreground(fg);
+    ctx.styled_label_set_background(bg);
+    ctx.styled_label_set_attributes(attr);
+}
+
+fn resolve_color(ctx: &Context, color: Color, default: IndexedColor) -> StraightRgba {
+    match color {
+        Color::Default => ctx.indexed(default),
+        Color::Indexed(index) => ctx.indexed(IndexedColor::from(index)),
+        Color::Rgb(rgb) => StraightRgba::from_be(rgb << 8 | 0xff),
+    }
+}
+
+/// Everything typed goes to the shell, except for Ctrl+K, so that the chords
+/// can still be used to switch back to the editor, see [`crate::chord`].
+/// Shift+PageUp/PageDown scroll back and Ctrl+Shift+Up/Down resize the panel.
+fn draw_handle_terminal_input(ctx: &mut Context, state: &mut State) {
+    if state.terminal.has_exited() {
+        if ctx.keyboard_input() == Some(vk::RETURN) {
+            start_shell(ctx, state);
+            ctx.set_input_consumed();
+            ctx.needs_rerender();
+        }
+        return;
+    }
+
+    let term = &mut state.terminal;
+
+    // Typed letters come as both, text and keys.
+    if let Some(text) = ctx.text_input() {
+        term.write(text.as_bytes());
+    } else if let Some(key) = ctx.keyboard_input() {
+        let page = term.screen().size().height;
+        match key {
+            _ if key == kbmod::CTRL | vk::K => return,
+            _ if key == kbmod::CTRL_SHIFT | vk::UP => term.grow(1),
+            _ if key == kbmod::CTRL_SHIFT | vk::DOWN => term.grow(-1),
+            _ if key == kbmod::SHIFT | vk::PRIOR => {
+                let scrollback = term.screen().scrollback_len();
+                term.scroll = term.scroll.saturating_add_signed(page).min(scrollback);
+            }
+            _ if key == kbmod::SHIFT | vk::NEXT => {
+                term.scroll = term.scroll.saturating_add_signed(-page);
+            }
+            // Pastes arrive as Ctrl+V, see `Tui::create_context`.
+            _ if key == kbmod::CTRL | vk::V => {
+                let text = String::from_utf8_lossy(ctx.clipboard_ref().read()).into_owned();
+                term.paste(&text);
+            }
+            _ => match encode_key(key, term.screen().app_cursor_keys) {
+                Some(data) => term.write(&data),
+                None => return,
+            },
+        }
+    } else {
+        return;
+    }
+
+    ctx.set_input_consumed();
+    ctx.needs_rerender();
+}
+
+/// Translates a key into what a terminal would send for it, like xterm does.
+fn encode_key(key: InputKey, app_cursor_keys: bool) -> Option<Vec<u8>> {
+    const MODIFIERS: [(InputKeyMod, u8); 7] = [
+        (kbmod::SHIFT, 2),
+        (kbmod::ALT, 3),
+        (kbmod::ALT_SHIFT, 4),
+        (kbmod::CTRL, 5),
+        (kbmod::CTRL_SHIFT, 6),
+        (kbmod::CTRL_ALT, 7),
+        (kbmod::CTRL_ALT_SHIFT, 8),
+    ];
+
+    let base = key.key();
+    let modifier = MODIFIERS.iter().find(|&&(m, _)| key == m | base).map_or(1, |&(_, n)| n);
+
+    // Cursor keys and the like: `CSI 1 ; mod X` or `CSI n ; mod ~`.
+    let csi = |code: &str, end: char| {
+        let seq = match (modifier, code) {
+            (1, "1") if app_cursor_keys && matches!(end, 'A'..='D') => format!("\x1bO{end}"),
+            (1, "1") => format!("\x1b[{end}"),
+            (1, _) => format!("\x1b[{code}{end}"),
+            _ => format!("\x1b[{code};{modifier}{end}"),
+        };
+        Some(seq.into_bytes())
+    };
+    let f1_to_f4 = |end: char| match modifier {
+        1 => Some(format!("\x1bO{end}").into_bytes()),
+        _ => Some(format!("\x1b[1;{modifier}{end}").into_bytes()),
+    };
+
+    match base {
+        vk::UP => csi("1", 'A'),
+        vk::DOWN => csi("1", 'B'),
+        vk::RIGHT => csi("1", 'C'),
+        vk::LEFT => csi("1", 'D'),
+        vk::HOME => csi("1", 'H'),
+        vk::END => csi("1", 'F'),
+        vk::INSERT => csi("2", '~'),
+        vk::DELETE => csi("3", '~'),
+        vk::PRIOR => csi("5", '~'),
+        vk::NEXT => csi("6", '~'),
+        vk::F1 => f1_to_f4('P'),
+        vk::F2 => f1_to_f4('Q'),
+        vk::F3 => f1_to_f4('R'),
+        vk::F4 => f1_to_f4('S'),
+        vk::F5 => csi("15", '~'),
+        vk::F6 => csi("17", '~'),
+        vk::F7 => csi("18", '~'),
+        vk::F8 => csi("19", '~'),
+        vk::F9 => csi("20", '~'),
+        vk::F10 => csi("21", '~'),
+        vk::F11 => csi("23", '~'),
+        vk::F12 => csi("24", '~'),
+        _ => {
+            let byte = match base {
+                vk::RETURN => b'\r',
+                vk::TAB if modifier == 2 => return Some(b"\x1b[Z".to_vec()),
+                vk::TAB => b'\t',
+                vk::BACK => 0x7f,
+                vk::ESCAPE => 0x1b,
+                vk::SPACE if modifier == 5 => 0,
+                vk::SPACE => b' ',
+                // Ctrl+A is 0x01 and so on.
+                _ => {
+                    let letter =
+                        (b'a'..=b'z').find(|&c| InputKey::from_ascii(c as char) == Some(base))?;
+                    match modifier {
+                        5 | 7 => letter - b'a' + 1,
+                        2 | 4 => letter.to_ascii_uppercase(),
+                        _ => letter,
+                    }
+                }
+            };
+            // Alt sends the key prefixed with an Escape.
+            let alt = matches!(modifier, 3 | 4 | 7 | 8);
+            Some(if alt { vec![0x1b, byte] } else { vec![byte] })
+        }
+    }
+}
+
+/// Shows the terminal panel and focuses it, or returns to the editor if it's focused already.
+pub fn toggle_terminal_focus(state: &mut State) {
+    if state.terminal.visible && state.terminal.focused {
+        state.wants_editor_focus = true;
+    } else {
+        state.terminal.visible = true;
+        state.wants_terminal_focus = true;
+    }
+}
+
+/// Shows or hides the terminal panel. The shell keeps running while it's hidden.
+pub fn toggle_terminal(state: &mut State) {
+    state.terminal.visible = !state.terminal.visible;
+    state.wants_terminal_focus = state.terminal.visible;
+    state.wants_editor_focus = !state.terminal.visible;
+}
+
+/// Runs the selection in the shell, or the current line without one.
+/// The cursor then moves to the next line, so that a script can be run line by line.
+pub fn send_selection_to_terminal(ctx: &mut Context, state: &mut State) {
+    let Some(doc) = state.documents.active() else {
+        return;
+    };
+
+    let text = {
+        let mut tb = doc.buffer.borrow_mut();
+        if tb.has_selection() {
+            tb.extract_user_selection(false).unwrap_or_default()
+        } else {
+            // This leaves the cursor at the start of the next line.
+            tb.select_line();
+            let text = tb.extract_user_selection(false).unwrap_or_default();
+            tb.clear_selection();
+            text
+        }
+    };
+
+    let text = String::from_utf8_lossy(&text);
+    let text = text.trim_end_matches(['\r', '\n']);
+    if text.is_empty() {
+        return;
+    }
+
+    state.terminal.visible = true;
+    if !start_shell(ctx, state) {
+        return;
+    }
+    state.terminal.paste(text);
+    state.terminal.write(b"\r");
+    ctx.needs_rerender();
+}
@@ -0,0 +1,189 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+use edit::framebuffer::IndexedColor;
+use edit::helpers::*;
+use edit::tui::*;
+use edit::unicode::MeasurementConfig;
+
+use crate::localization::*;
+use crate::state::*;
+use crate::toast::Severity;
+
+/// Draws the toasts stacked in the top right corner, below the menubar, and above them
+/// the progress of the running task, see [`crate::task`]. Clicking a toast dismisses it.
+/// They never take the focus away from the editor.
+pub fn draw_toasts(ctx: &mut Context, state: &mut State) {
+    if state.toasts.expire() {
+        ctx.needs_rerender();
+    }
+    if state.toasts.visible().next().is_none() && state.task.is_none() {
+        return;
+    }
+
+    let max_width = (ctx.size().width * 2 / 3).max(10);
+    let texts: Vec<_> = state
+        .toasts
+        .visible()
+        .map(|toast| match severity_prefix(toast.severity) {
+            Some(prefix) => format!("{prefix}: {}", toast.message),
+            None => toast.message.clone(),
+        })
+        .collect();
+    let task = state.task.as_ref().map(|task| {
+        let percent = match task.progress.fraction() {
+            Some(fraction) => format!(" {:3}%", (fraction * 100.0) as u32),
+            None if task.progress.done != 0 => format!(" {}", task.progress.done),
+            None => String::new(),
+        };
+        let title = format!("{}{percent}", task.title);
+        (title, task.progress.fraction())
+    });
+    let width = texts
+        .iter()
+        .chain(task.as_ref().map(|(title, _)| title))
+        .map(|text| text_width(text) + 2)
+        .max()
+        .unwrap_or(0)
+        .max(PROGRESS_WIDTH + 2);
+    let height = texts.len() + if task.is_some() { 2 } else { 0 };
+    let mut dismissed = None;
+
+    ctx.block_begin("toasts");
+    ctx.attr_float(FloatSpec {
+        anchor: Anchor::Root,
+        gravity_x: 1.0,
+        gravity_y: 0.0,
+        offset_x: ctx.size().width as f32 - 1.0,
+        offset_y: 1.0,
+    });
+    ctx.attr_intrinsic_size(Size { width: width.min(max_width), height: height as CoordType });
+    ctx.attr_focus_keep();
+    {
+        if let Some((title, fraction)) = &task {
+            let (bg, fg) =
+                (ctx.indexed(IndexedColor::Blue), ctx.indexed(IndexedColor::BrightWhite));
+            ctx.label("task", title);
+            ctx.attr_background_rgba(bg);
+            ctx.attr_foreground_rgba(fg);
+            ctx.attr_padding(Rect::two(0, 1));
+            ctx.attr_overflow(Overflow::TruncateTail);
+
+            // Esc cancels it, see `draw_handle_task`.
+            ctx.table_begin("progress");
+            ctx.table_set_cell_gap(Size { width: 1, height: 0 });
+            ctx.attr_background_rgba(bg);
+            ctx.attr_foreground_rgba(fg);
+            ctx.attr_padding(Rect::two(0, 1));
+            {
+                ctx.table_next_row();
+                ctx.progress_bar("bar", *fraction, PROGRESS_WIDTH - 4);
+                ctx.label("cancel", "Esc");
+            }
+            ctx.table_end();
+        }
+
+        for (i, (toast, text)) in state.toasts.visible().zip(&texts).enumerate() {
+            let (bg, fg) = severity_colors(toast.severity);
+            ctx.next_block_id_mixin(i as u64);
+            ctx.label("toast", text);
+            ctx.attr_background_rgba(ctx.indexed(bg));
+            ctx.attr_foreground_rgba(ctx.indexed(fg));
+            ctx.attr_padding(Rect::two(0, 1));
+            ctx.attr_overflow(Overflow::TruncateTail);
+            if ctx.was_clicked() {
+                dismissed = Some(i);
+            }
+        }
+    }
+    ctx.block_end();
+
+    if let Some(i) = dismissed {
+        state.toasts.dismiss(i);
+        ctx.needs_rerender();
+    }
+}
+
+/// Lists everything that was shown as a toast or in the error dialog, newest first.
+pub fn draw_notification_history(ctx: &mut Context, state: &mut State) {
+    let count = state.toasts.history().count();
+    let width = (ctx.size().width - 20).max(10);
+    let height = (ctx.size().height - 10).clamp(1, count.max(1) as CoordType);
+    let mut done = false;
+
+    ctx.modal_begin("notifications", loc(LocId::NotificationsDialogTitle));
+    ctx.attr_intrinsic_size(Size { width, height: height + 3 });
+    {
+        ctx.scrollarea_begin("messages", Size { width: 0, height });
+        ctx.attr_padding(Rect::two(1, 1));
+        {
+            if count == 0 {
+                ctx.label("empty", loc(LocId::NotificationsEmpty));
+            }
+
+            ctx.list_begin("messages");
+            ctx.inherit_focus();
+            for (i, (severity, message)) in state.toasts.history().enumerate() {
+                let text = match severity_prefix(*severity) {
+                    Some(prefix) => format!("{prefix}: {message}"),
+                    None => message.clone(),
+                };
+                ctx.next_block_id_mixin(i as u64);
+                ctx.list_item(false, &text);
+                ctx.attr_overflow(Overflow::TruncateTail);
+            }
+            ctx.list_end();
+        }
+        ctx.scrollarea_end();
+
+        ctx.table_begin("choices");
+        ctx.attr_padding(Rect::three(0, 2, 0));
+        ctx.attr_position(Position::Center);
+        ctx.table_set_cell_gap(Size { width: 2, height: 0 });
+        {
+            ctx.table_next_row();
+            if ctx.button("clear", loc(LocId::NotificationsClear), ButtonStyle::default()) {
+                state.toasts.clear_history();
+                ctx.needs_rerender();
+            }
+            done |= ctx.button("ok", loc(LocId::Ok), ButtonStyle::default());
+            if count == 0 {
+                ctx.inherit_focus();
+            }
+        }
+        ctx.table_end();
+    }
+    done |= ctx.modal_end();
+
+    if done {
+        state.wants_notification_history = false;
+        ctx.needs_rerender();
+    }
+}
+
+/// The width of the progress bar, including the hint that Esc cancels it.
+const PROGRESS_WIDTH: CoordType = 24;
+
+/// The colors alone don't tell everyone what kind of message it is.
+fn severity_prefix(severity: Severity) -> Option<&'static str> {
+    match severity {
+        Severity::Info => None,
+        Severity::Warning => Some(loc(LocId::WarningDialogTitle)),
+        Severity::Error => Some(loc(LocId::ErrorDialogTitle)),
+    }
+}
+
+fn severity_colors(severity: Severity) -> (IndexedColor, IndexedColor) {
+    match severity {
+        Severity::Info => (IndexedColor::BrightBlack, IndexedColor::BrightWhite),
+        Severity::Warning => (IndexedColor::Yellow, IndexedColor::Black),
+        Severity::Error => (IndexedColor::Red, IndexedColor::BrightWhite),
+    }
+}
+
+fn text_width(text: &str) -> CoordType {
+    MeasurementConfig::new(&text.as_bytes())
+        .goto_visual(Point { x: CoordType::MAX, y: 0 })
+        .visual_pos
+        .x
+}
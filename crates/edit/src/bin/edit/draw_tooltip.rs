@@ -0,0 +1,44 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+use edit::helpers::*;
+use edit::input::vk;
+use edit::tui::*;
+
+use crate::state::*;
+
+/// Shows `text` in a tooltip next to the cell at `anchor`, until Esc is pressed or the cursor
+/// of the active document moves away from `cursor`, which is where it was when this was called.
+pub fn tooltip_show(
+    ctx: &mut Context,
+    state: &mut State,
+    anchor: Point,
+    text: String,
+    cursor: Point,
+) {
+    state.tooltip = Some(StateTooltip { anchor, text, cursor });
+    ctx.needs_rerender();
+}
+
+/// Dismisses the tooltip once the cursor moved or Esc is pressed.
+/// This runs before anything else gets to see the key.
+pub fn draw_handle_tooltip(ctx: &mut Context, state: &mut State) {
+    let Some(tooltip) = &state.tooltip else {
+        return;
+    };
+    if active_cursor(state) == Some(tooltip.cursor) && !ctx.consume_shortcut(vk::ESCAPE) {
+        return;
+    }
+    state.tooltip = None;
+    ctx.needs_rerender();
+}
+
+pub fn draw_tooltip(ctx: &mut Context, state: &mut State) {
+    if let Some(tooltip) = &state.tooltip {
+        ctx.tooltip("tooltip", tooltip.anchor, &tooltip.text);
+    }
+}
+
+fn active_cursor(state: &State) -> Option<Point> {
+    state.documents.active().map(|doc| doc.buffer.borrow().cursor_logical_pos())
+}
@@ -0,0 +1,121 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+use edit::framebuffer::IndexedColor;
+use edit::helpers::*;
+use edit::tui::*;
+
+use crate::draw_task::open_file;
+use crate::localization::*;
+use crate::recent;
+use crate::state::*;
+
+#[derive(Clone, Copy)]
+enum Action {
+    New,
+    Open,
+    OpenFolder,
+    Settings,
+}
+
+const ACTIONS: [(Action, LocId); 4] = [
+    (Action::New, LocId::FileNew),
+    (Action::Open, LocId::FileOpen),
+    (Action::OpenFolder, LocId::FileOpenFolder),
+    (Action::Settings, LocId::FileSettings),
+];
+
+/// Draws the start screen in place of the editor while no file is open,
+/// with the most common actions and the recent files. It's `height` rows tall.
+pub fn draw_welcome(ctx: &mut Context, state: &mut State, height: CoordType) {
+    let files = state.welcome_recent.get_or_insert_with(recent::load);
+    let dim = ctx.indexed_alpha(IndexedColor::Foreground, 1, 2);
+    let heading = ctx.indexed(IndexedColor::BrightBlue);
+    let mut action = None;
+    let mut activated = None;
+
+    ctx.block_begin("welcome");
+    ctx.inherit_focus();
+    // Tab moves between the actions and the recent files.
+    ctx.attr_focus_well();
+    ctx.attr_padding(Rect::three(1, 2, 0));
+    {
+        ctx.label("title", "Microsoft Edit");
+        ctx.attr_position(Position::Center);
+        ctx.attr_overflow(Overflow::TruncateTail);
+
+        ctx.label("start", loc(LocId::WelcomeStart));
+        ctx.attr_foreground_rgba(heading);
+        ctx.attr_padding(Rect::three(1, 0, 0));
+
+        ctx.list_begin("actions");
+        ctx.inherit_focus();
+        for (i, &(a, label)) in ACTIONS.iter().enumerate() {
+            ctx.next_block_id_mixin(i as u64);
+            if ctx.list_item(i == 0, loc(label)) == ListSelection::Activated {
+                action = Some(a);
+            }
+            ctx.attr_padding(Rect::two(0, 2));
+        }
+        ctx.list_end();
+
+        ctx.label("recent", loc(LocId::WelcomeRecent));
+        ctx.attr_foreground_rgba(heading);
+        ctx.attr_padding(Rect::three(1, 0, 0));
+
+        if files.is_empty() {
+            ctx.label("empty", loc(LocId::RecentEmpty));
+            ctx.attr_foreground_rgba(dim);
+            ctx.attr_padding(Rect::two(0, 2));
+        }
+
+        // The rows above: The title, the headings with a blank line each, and the actions.
+        let rows = height - 6 - ACTIONS.len() as CoordType;
+        ctx.scrollarea_begin("files", Size { width: 0, height: rows.max(1) });
+        {
+            ctx.list_begin("files");
+            ctx.inherit_focus();
+            for (i, path) in files.iter().enumerate() {
+                let name = path.file_name().unwrap_or_default().to_string_lossy();
+                let dir = path.parent().unwrap_or(path).to_string_lossy();
+
+                ctx.next_block_id_mixin(i as u64);
+                ctx.styled_list_item_begin();
+                ctx.styled_label_add_text(&name);
+                ctx.styled_label_set_foreground(dim);
+                ctx.styled_label_add_text("  ");
+                ctx.styled_label_add_text(&dir);
+                if ctx.styled_list_item_end(i == 0) == ListSelection::Activated {
+                    activated = Some(path.clone());
+                }
+                ctx.attr_padding(Rect::two(0, 2));
+                ctx.attr_overflow(Overflow::TruncateTail);
+            }
+            ctx.list_end();
+        }
+        ctx.scrollarea_end();
+    }
+    ctx.block_end();
+
+    if action.is_none() && activated.is_none() {
+        return;
+    }
+    ctx.needs_rerender();
+
+    match action {
+        Some(Action::New) => {
+            draw_add_untitled_document(ctx, state);
+            state.wants_editor_focus = true;
+        }
+        Some(Action::Open) => state.wants_file_picker = StateFilePicker::Open,
+        Some(Action::OpenFolder) => state.wants_file_picker = StateFilePicker::OpenFolder,
+        Some(Action::Settings) => state.wants_settings = true,
+        None => {}
+    }
+    if let Some(path) = activated {
+        match open_file(state, &path) {
+            Ok(()) => state.wants_editor_focus = true,
+            Err(err) => error_log_add(ctx, state, err),
+        }
+    }
+}
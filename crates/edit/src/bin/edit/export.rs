@@ -0,0 +1,183 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! File > Export writes the document with its syntax highlighting in the colors of
+//! the theme, either as a standalone HTML page or as text with ANSI escapes that
+//! `cat` and `less -R` show. The colors are the ones the editor draws the spans in.
+
+use std::fmt::Write as _;
+use std::path::Path;
+
+use edit::buffer::{Span, TextBuffer, syntax_fallback};
+use edit::framebuffer::{IndexedColor, NAMED_COLORS_COUNT, NamedColor};
+use edit::oklab::StraightRgba;
+use edit::tui::Context;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Format {
+    Html,
+    Ansi,
+}
+
+impl Format {
+    /// HTML for `.html` and `.htm` files, text with ANSI escapes for anything else.
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("html") || ext.eq_ignore_ascii_case("htm") => {
+                Self::Html
+            }
+            _ => Self::Ansi,
+        }
+    }
+}
+
+pub struct Palette {
+    pub background: StraightRgba,
+    pub foreground: StraightRgba,
+    /// The colors of the [`Span`]s by their [`NamedColor`]. Those without one are left alone.
+    pub syntax: [Option<StraightRgba>; NAMED_COLORS_COUNT],
+}
+
+impl Palette {
+    /// The colors of the current theme, or of the terminal without one.
+    pub fn from_context(ctx: &Context) -> Self {
+        let mut syntax = [None; NAMED_COLORS_COUNT];
+        for color in [
+            NamedColor::Comment,
+            NamedColor::Keyword,
+            NamedColor::String,
+            NamedColor::Number,
+            NamedColor::Constant,
+            NamedColor::Function,
+            NamedColor::Type,
+            NamedColor::Operator,
+        ] {
+            syntax[color as usize] =
+                ctx.named(color).or_else(|| syntax_fallback(color).map(|c| ctx.indexed(c)));
+        }
+        Self {
+            background: ctx.indexed(IndexedColor::Background),
+            foreground: ctx.indexed(IndexedColor::Foreground),
+            syntax,
+        }
+    }
+}
+
+/// Renders the whole of `tb`. `title` is the title of the HTML page.
+pub fn export(tb: &TextBuffer, format: Format, palette: &Palette, title: &str) -> String {
+    let mut out = String::new();
+    if format == Format::Html {
+        _ = write!(
+            out,
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n</head>\n\
+             <body style=\"margin:0\">\n<pre style=\"margin:0;padding:1em;background:{};color:{};tab-size:{}\">",
+            escape_html(title),
+            css_color(palette.background),
+            css_color(palette.foreground),
+            tb.tab_size(),
+        );
+    }
+
+    let mut first = true;
+    tb.highlight_lines(&mut |text, spans| {
+        if !first {
+            out.push('\n');
+        }
+        first = false;
+        write_line(&mut out, format, palette, text, spans);
+    });
+
+    if format == Format::Html {
+        out.push_str("</pre>\n</body>\n</html>\n");
+    }
+    out
+}
+
+/// Writes a line of text with its highlighted `spans`, which are in order and don't overlap.
+fn write_line(out: &mut String, format: Format, palette: &Palette, text: &[u8], spans: &[Span]) {
+    let mut pos = 0;
+    for span in spans {
+        let Some(color) = palette.syntax[span.color as usize] else {
+            continue;
+        };
+        write_text(out, format, &text[pos..span.range.start]);
+        match format {
+            Format::Html => _ = write!(out, "<span style=\"color:{}\">", css_color(color)),
+            Format::Ansi => {
+                _ = write!(out, "\x1b[38;2;{};{};{}m", color.red(), color.green(), color.blue())
+            }
+        }
+        write_text(out, format, &text[span.range.clone()]);
+        out.push_str(match format {
+            Format::Html => "</span>",
+            Format::Ansi => "\x1b[39m",
+        });
+        pos = span.range.end;
+    }
+    write_text(out, format, &text[pos..]);
+}
+
+fn write_text(out: &mut String, format: Format, text: &[u8]) {
+    let text = String::from_utf8_lossy(text);
+    match format {
+        Format::Html => out.push_str(&escape_html(&text)),
+        Format::Ansi => out.push_str(&text),
+    }
+}
+
+fn escape_html(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for ch in text.chars() {
+        match ch {
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '&' => out.push_str("&amp;"),
+            '"' => out.push_str("&quot;"),
+            _ => out.push(ch),
+        }
+    }
+    out
+}
+
+fn css_color(color: StraightRgba) -> String {
+    format!("#{:02x}{:02x}{:02x}", color.red(), color.green(), color.blue())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn palette() -> Palette {
+        let mut syntax = [None; NAMED_COLORS_COUNT];
+        syntax[NamedColor::Keyword as usize] = Some(StraightRgba::from_be(0xff8000ff));
+        Palette {
+            background: StraightRgba::from_be(0x000000ff),
+            foreground: StraightRgba::from_be(0xffffffff),
+            syntax,
+        }
+    }
+
+    #[test]
+    fn test_write_line() {
+        let text = b"if a<b {}";
+        let spans = [
+            Span { range: 0..2, color: NamedColor::Keyword },
+            Span { range: 4..5, color: NamedColor::Operator },
+        ];
+        let line = |format| {
+            let mut out = String::new();
+            write_line(&mut out, format, &palette(), text, &spans);
+            out
+        };
+        assert_eq!(line(Format::Html), "<span style=\"color:#ff8000\">if</span> a&lt;b {}");
+        assert_eq!(line(Format::Ansi), "\x1b[38;2;255;128;0mif\x1b[39m a<b {}");
+    }
+
+    #[test]
+    fn test_format_from_path() {
+        assert_eq!(Format::from_path(Path::new("a/main.rs.HTML")), Format::Html);
+        assert_eq!(Format::from_path(Path::new("main.htm")), Format::Html);
+        assert_eq!(Format::from_path(Path::new("main.ans")), Format::Ansi);
+        assert_eq!(Format::from_path(Path::new("html")), Format::Ansi);
+    }
+}
@@ -0,0 +1,94 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Draws attention to errors, which are otherwise easy to miss on a phone
+//! where the keyboard covers half the screen.
+//!
+//! Each kind of feedback can be turned on separately with `--feedback=LIST`:
+//! `bell` rings the terminal bell (which Termux turns into a vibration or
+//! a beep, depending on its `bell-character` setting), `flash` inverts the
+//! screen for a moment, and `vibrate` runs `termux-vibrate`.
+
+use std::process::Command;
+
+use edit::tui::Context;
+
+use crate::clipboard_backend::{find_in_path, spawn_detached};
+
+/// How long `termux-vibrate` vibrates, in milliseconds.
+const VIBRATE_DURATION_MS: &str = "50";
+
+pub struct Feedback {
+    pub bell: bool,
+    pub flash: bool,
+    pub vibrate: bool,
+    bell_pending: bool,
+}
+
+impl Feedback {
+    pub fn new() -> Self {
+        Self { bell: false, flash: false, vibrate: false, bell_pending: false }
+    }
+
+    /// Parses a comma-separated list of `bell`, `flash` and `vibrate`, or `none`.
+    /// Returns `false` if the list is invalid, leaving `self` unchanged.
+    pub fn parse(&mut self, value: &str) -> bool {
+        let mut parsed = Self::new();
+        for name in value.split(',').map(str::trim) {
+            match name {
+                "bell" => parsed.bell = true,
+                "flash" => parsed.flash = true,
+                "vibrate" => parsed.vibrate = true,
+                "none" => {}
+                _ => return false,
+            }
+        }
+        *self = parsed;
+        true
+    }
+
+    /// Gives the feedback for an error.
+    pub fn notify(&mut self, ctx: &mut Context) {
+        if self.flash {
+            ctx.flash();
+        }
+        if self.bell {
+            self.bell_pending = true;
+        }
+        if self.vibrate {
+            // Only try once. Without the Termux:API package, this would
+            // spawn a doomed process for every error.
+            if find_in_path("termux-vibrate") {
+                spawn_detached(Command::new("termux-vibrate").args(["-d", VIBRATE_DURATION_MS]));
+            } else {
+                self.vibrate = false;
+            }
+        }
+    }
+
+    /// Writes the bell queued by [`Feedback::notify`].
+    pub fn write_pending(&mut self, output: &mut String) {
+        if self.bell_pending {
+            self.bell_pending = false;
+            output.push('\x07');
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let mut feedback = Feedback::new();
+        assert!(feedback.parse("bell, vibrate"));
+        assert!(feedback.bell && !feedback.flash && feedback.vibrate);
+
+        assert!(!feedback.parse("bell,beep"));
+        assert!(feedback.bell && feedback.vibrate);
+
+        assert!(feedback.parse("none"));
+        assert!(!feedback.bell && !feedback.flash && !feedback.vibrate);
+    }
+}
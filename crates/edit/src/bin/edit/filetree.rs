@@ -0,0 +1,340 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! The file tree in the sidebar. Directories are only read once they're expanded,
+//! since listing a whole project is slow on Android's shared storage.
+//!
+//! Whether it's shown and how wide it is are kept in the state directory
+//! (usually `~/.local/state/edit/filetree`, see [`crate::paths`]).
+
+use std::cmp::Ordering;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use edit::helpers::CoordType;
+use edit::icu;
+
+use crate::git::{self, FileStatus};
+use crate::paths::{self, Kind};
+
+const DEFAULT_WIDTH: CoordType = 28;
+const MIN_WIDTH: CoordType = 16;
+const MAX_WIDTH: CoordType = 80;
+
+pub struct Row {
+    pub path: PathBuf,
+    pub name: String,
+    /// How deep it's nested below the root.
+    pub depth: usize,
+    pub is_dir: bool,
+    pub expanded: bool,
+}
+
+/// What the sidebar asks the user before changing the file system.
+pub enum Prompt {
+    /// A new file in `dir`, or a directory if the name ends with `/`.
+    Create {
+        dir: PathBuf,
+        name: String,
+    },
+    Rename {
+        path: PathBuf,
+        name: String,
+    },
+    Delete {
+        path: PathBuf,
+    },
+}
+
+pub struct FileTree {
+    pub visible: bool,
+    pub width: CoordType,
+    /// The index of the row the cursor is on.
+    pub selected: usize,
+    pub prompt: Option<Prompt>,
+    root: Option<PathBuf>,
+    /// Each expanded directory is followed by its contents.
+    rows: Vec<Row>,
+    git: Vec<(PathBuf, FileStatus)>,
+}
+
+impl FileTree {
+    pub fn new() -> Self {
+        Self {
+            visible: false,
+            width: DEFAULT_WIDTH,
+            selected: 0,
+            prompt: None,
+            root: None,
+            rows: Vec::new(),
+            git: Vec::new(),
+        }
+    }
+
+    /// Shows or hides it and sets the width, as it was last time.
+    pub fn restore_session(&mut self) {
+        if let Some(text) = session_path().and_then(|path| fs::read_to_string(path).ok()) {
+            self.parse_session(&text);
+        }
+    }
+
+    pub fn root(&self) -> Option<&Path> {
+        self.root.as_deref()
+    }
+
+    /// Shows the contents of `dir`, with everything collapsed.
+    pub fn set_root(&mut self, dir: PathBuf) {
+        self.rows = read_dir(&dir, 0);
+        self.root = Some(dir);
+        self.selected = 0;
+        self.refresh_git();
+    }
+
+    pub fn rows(&self) -> &[Row] {
+        &self.rows
+    }
+
+    pub fn selected_row(&self) -> Option<&Row> {
+        self.rows.get(self.selected)
+    }
+
+    pub fn set_visible(&mut self, visible: bool) {
+        self.visible = visible;
+        self.save_session();
+    }
+
+    /// Changes the width by `delta` columns.
+    pub fn resize(&mut self, delta: CoordType) {
+        self.width = (self.width + delta).clamp(MIN_WIDTH, MAX_WIDTH);
+        self.save_session();
+    }
+
+    /// Expands a collapsed directory and vice versa.
+    pub fn toggle(&mut self, index: usize) {
+        let Some(row) = self.rows.get_mut(index).filter(|row| row.is_dir) else {
+            return;
+        };
+
+        row.expanded = !row.expanded;
+        if row.expanded {
+            let children = read_dir(&row.path, row.depth + 1);
+            self.rows.splice(index + 1..index + 1, children);
+        } else {
+            let end = self.subtree_end(index);
+            self.rows.drain(index + 1..end);
+        }
+    }
+
+    /// The index of the directory that contains the row, if it's not at the top.
+    pub fn parent(&self, index: usize) -> Option<usize> {
+        let depth = self.rows.get(index)?.depth;
+        self.rows[..index].iter().rposition(|row| row.depth < depth)
+    }
+
+    /// The directory that new files go into when `index` is selected.
+    pub fn target_dir(&self, index: usize) -> Option<PathBuf> {
+        match self.rows.get(index) {
+            Some(row) if row.is_dir && row.expanded => Some(row.path.clone()),
+            Some(row) => row.path.parent().map(Path::to_path_buf),
+            None => self.root.clone(),
+        }
+    }
+
+    /// Reads the directories again, after the files in them changed.
+    /// The expanded ones stay expanded.
+    pub fn refresh(&mut self) {
+        let Some(root) = self.root.clone() else {
+            return;
+        };
+        let expanded: Vec<PathBuf> =
+            self.rows.iter().filter(|row| row.expanded).map(|row| row.path.clone()).collect();
+        let selected = self.selected_row().map(|row| row.path.clone());
+
+        self.rows = read_dir(&root, 0);
+        let mut i = 0;
+        while i < self.rows.len() {
+            if self.rows[i].is_dir && expanded.contains(&self.rows[i].path) {
+                self.toggle(i);
+            }
+            i += 1;
+        }
+
+        self.select_path(selected.as_deref());
+        self.refresh_git();
+    }
+
+    /// Moves the cursor onto the row of `path`, if it's shown.
+    pub fn select_path(&mut self, path: Option<&Path>) {
+        if let Some(i) = path.and_then(|path| self.rows.iter().position(|row| row.path == path)) {
+            self.selected = i;
+        }
+        self.selected = self.selected.min(self.rows.len().saturating_sub(1));
+    }
+
+    /// How the file differs from the last commit. Directories take on the status
+    /// of the files in them, like they do in other editors.
+    pub fn git_status(&self, row: &Row) -> Option<FileStatus> {
+        if !row.is_dir {
+            return self.git.iter().find(|(path, _)| *path == row.path).map(|&(_, status)| status);
+        }
+        let mut statuses = self
+            .git
+            .iter()
+            .filter(|(path, _)| path.starts_with(&row.path) || row.path.starts_with(path))
+            .map(|&(_, status)| status);
+        let first = statuses.next()?;
+        Some(statuses.fold(first, |a, b| if a == b { a } else { FileStatus::Modified }))
+    }
+
+    pub fn refresh_git(&mut self) {
+        self.git = self.root.as_deref().map(git::status).unwrap_or_default();
+    }
+
+    fn subtree_end(&self, index: usize) -> usize {
+        let depth = self.rows[index].depth;
+        self.rows[index + 1..]
+            .iter()
+            .position(|row| row.depth <= depth)
+            .map_or(self.rows.len(), |i| index + 1 + i)
+    }
+
+    fn parse_session(&mut self, text: &str) {
+        for line in text.lines() {
+            match line.split_once('=').map(|(k, v)| (k.trim(), v.trim())) {
+                Some(("visible", value)) => self.visible = value == "true",
+                Some(("width", value)) => {
+                    if let Ok(width) = value.parse::<CoordType>() {
+                        self.width = width.clamp(MIN_WIDTH, MAX_WIDTH);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Failing to remember it isn't worth bothering the user with.
+    fn save_session(&self) {
+        if let Some(path) = session_path() {
+            let text = format!("visible = {}\nwidth = {}\n", self.visible, self.width);
+            _ = path.parent().map(fs::create_dir_all);
+            _ = fs::write(path, text);
+        }
+    }
+}
+
+/// Creates a file in `dir`, or a directory if `name` ends with `/`. Returns its path.
+pub fn create(dir: &Path, name: &str) -> std::io::Result<PathBuf> {
+    let path = dir.join(name.trim_end_matches('/'));
+    if name.ends_with('/') {
+        fs::create_dir_all(&path)?;
+    } else {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::OpenOptions::new().write(true).create_new(true).open(&path)?;
+    }
+    Ok(path)
+}
+
+/// Renames the file or directory at `path` within its directory. Returns the new path.
+pub fn rename(path: &Path, name: &str) -> std::io::Result<PathBuf> {
+    let new_path = path.with_file_name(name);
+    if new_path.exists() {
+        return Err(std::io::ErrorKind::AlreadyExists.into());
+    }
+    fs::rename(path, &new_path)?;
+    Ok(new_path)
+}
+
+/// Deletes the file, or the directory with everything in it.
+pub fn delete(path: &Path) -> std::io::Result<()> {
+    if fs::symlink_metadata(path)?.is_dir() {
+        fs::remove_dir_all(path)
+    } else {
+        fs::remove_file(path)
+    }
+}
+
+/// Lists a directory, the subdirectories first. Unreadable ones appear empty.
+fn read_dir(dir: &Path, depth: usize) -> Vec<Row> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut rows: Vec<Row> = entries
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if name == ".git" {
+                return None;
+            }
+            let path = entry.path();
+            // Follows symlinks, unlike `entry.file_type()`.
+            let is_dir = path.is_dir();
+            Some(Row { path, name, depth, is_dir, expanded: false })
+        })
+        .collect();
+
+    rows.sort_by(|a, b| match b.is_dir.cmp(&a.is_dir) {
+        Ordering::Equal => icu::compare_strings(a.name.as_bytes(), b.name.as_bytes()),
+        other => other,
+    });
+    rows
+}
+
+fn session_path() -> Option<PathBuf> {
+    Some(paths::dir(Kind::State)?.join("filetree"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tree() {
+        let root = std::env::temp_dir().join(format!("edit-test-filetree-{}", std::process::id()));
+        fs::create_dir_all(root.join("src/deep")).unwrap();
+        fs::write(root.join("src/main.rs"), "").unwrap();
+        fs::write(root.join("README.md"), "").unwrap();
+
+        let mut tree = FileTree::new();
+        tree.set_root(root.clone());
+        let names = |tree: &FileTree| {
+            tree.rows()
+                .iter()
+                .map(|row| format!("{}{}", " ".repeat(row.depth), row.name))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(names(&tree), ["src", "README.md"]);
+
+        tree.toggle(0);
+        assert_eq!(names(&tree), ["src", " deep", " main.rs", "README.md"]);
+        assert_eq!(tree.parent(2), Some(0));
+        assert_eq!(tree.target_dir(2), Some(root.join("src")));
+
+        // New files show up after a refresh, and the expanded directories stay expanded.
+        create(&root.join("src"), "lib.rs").unwrap();
+        create(&root, "docs/").unwrap();
+        tree.refresh();
+        assert_eq!(names(&tree), ["docs", "src", " deep", " lib.rs", " main.rs", "README.md"]);
+
+        rename(&root.join("src/lib.rs"), "mod.rs").unwrap();
+        assert!(rename(&root.join("src/mod.rs"), "main.rs").is_err());
+        delete(&root.join("src")).unwrap();
+        tree.refresh();
+        assert_eq!(names(&tree), ["docs", "README.md"]);
+
+        tree.toggle(0);
+        tree.toggle(0);
+        assert_eq!(names(&tree), ["docs", "README.md"]);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_parse_session() {
+        let mut tree = FileTree::new();
+        tree.parse_session("visible = true\nwidth = 500\n");
+        assert!(tree.visible);
+        assert_eq!(tree.width, MAX_WIDTH);
+    }
+}
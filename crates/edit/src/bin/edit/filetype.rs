@@ -0,0 +1,352 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Tells what kind of file a document is, so that its settings and highlighting can follow:
+//! By an Emacs or Vim modeline in the first line, then by its name (`Makefile`, `*.rs`),
+//! then by the `#!` line of scripts, and last by what the first line starts with (`<?xml`).
+//! The settings for each type can be overridden in the config, see [`crate::config`].
+
+use std::path::Path;
+
+use edit::buffer::TextBuffer;
+use edit::helpers::CoordType;
+
+pub struct FileType {
+    /// The name used in the config and shown in the status bar.
+    pub name: &'static str,
+    /// File name patterns. A `*` matches anything, as in `*.rs` or `Dockerfile.*`.
+    patterns: &'static [&'static str],
+    /// The interpreters in `#!` lines, without their version: `python` for `python3.12`.
+    interpreters: &'static [&'static str],
+    pub line_comment: &'static str,
+    pub block_comment: (&'static str, &'static str),
+}
+
+const fn filetype(
+    name: &'static str,
+    patterns: &'static [&'static str],
+    interpreters: &'static [&'static str],
+    line_comment: &'static str,
+    block_comment: (&'static str, &'static str),
+) -> FileType {
+    FileType { name, patterns, interpreters, line_comment, block_comment }
+}
+
+const NO_BLOCK: (&str, &str) = ("", "");
+const C_BLOCK: (&str, &str) = ("/*", "*/");
+const XML_BLOCK: (&str, &str) = ("<!--", "-->");
+
+/// First lines that give the type away, matched case-insensitively at their start.
+const FIRST_LINES: [(&str, &str); 6] = [
+    ("<?xml", "xml"),
+    ("<!doctype html", "html"),
+    ("<html", "html"),
+    ("<svg", "xml"),
+    ("<?php", "php"),
+    ("diff --git ", "diff"),
+];
+
+/// The names other editors use in modelines for some of ours.
+const ALIASES: [(&str, &str); 14] = [
+    ("bash", "shell"),
+    ("c++", "cpp"),
+    ("cs", "csharp"),
+    ("dosbatch", "batch"),
+    ("gitcommit", "git-commit"),
+    ("js", "javascript"),
+    ("jsonc", "json"),
+    ("make", "makefile"),
+    ("md", "markdown"),
+    ("py", "python"),
+    ("rb", "ruby"),
+    ("sh", "shell"),
+    ("ts", "typescript"),
+    ("zsh", "shell"),
+];
+
+/// Sorted by name, as it's shown that way.
+pub const FILETYPES: &[FileType] = &[
+    filetype("batch", &["*.bat", "*.cmd"], &[], "REM", NO_BLOCK),
+    filetype("c", &["*.c", "*.h"], &[], "//", C_BLOCK),
+    filetype("cpp", &["*.cpp", "*.cc", "*.cxx", "*.hpp", "*.hh", "*.hxx"], &[], "//", C_BLOCK),
+    filetype("csharp", &["*.cs"], &[], "//", C_BLOCK),
+    filetype("css", &["*.css", "*.scss", "*.less"], &[], "", C_BLOCK),
+    filetype("diff", &["*.diff", "*.patch"], &[], "", NO_BLOCK),
+    filetype("dockerfile", &["Dockerfile", "Dockerfile.*", "*.dockerfile"], &[], "#", NO_BLOCK),
+    filetype("git-commit", &["COMMIT_EDITMSG", "MERGE_MSG", "TAG_EDITMSG"], &[], "#", NO_BLOCK),
+    filetype("go", &["*.go"], &[], "//", C_BLOCK),
+    filetype("html", &["*.html", "*.htm", "*.xhtml"], &[], "", XML_BLOCK),
+    filetype(
+        "ini",
+        &["*.ini", "*.cfg", "*.conf", ".editorconfig", ".gitconfig"],
+        &[],
+        ";",
+        NO_BLOCK,
+    ),
+    filetype("java", &["*.java"], &[], "//", C_BLOCK),
+    filetype("javascript", &["*.js", "*.mjs", "*.cjs", "*.jsx"], &["node"], "//", C_BLOCK),
+    filetype("json", &["*.json", "*.jsonc", ".prettierrc"], &[], "", NO_BLOCK),
+    filetype("kotlin", &["*.kt", "*.kts"], &[], "//", C_BLOCK),
+    filetype("lua", &["*.lua"], &["lua", "luajit"], "--", ("--[[", "]]")),
+    filetype(
+        "makefile",
+        &["Makefile", "GNUmakefile", "makefile", "*.mk", "*.mak"],
+        &["make"],
+        "#",
+        NO_BLOCK,
+    ),
+    filetype("markdown", &["*.md", "*.markdown"], &[], "", XML_BLOCK),
+    filetype("perl", &["*.pl", "*.pm"], &["perl"], "#", NO_BLOCK),
+    filetype("php", &["*.php"], &["php"], "//", C_BLOCK),
+    filetype("python", &["*.py", "*.pyw", "*.pyi"], &["python"], "#", NO_BLOCK),
+    filetype("ruby", &["*.rb", "Gemfile", "Rakefile"], &["ruby"], "#", NO_BLOCK),
+    filetype("rust", &["*.rs"], &[], "//", C_BLOCK),
+    filetype(
+        "shell",
+        &[
+            "*.sh",
+            "*.bash",
+            "*.zsh",
+            ".bashrc",
+            ".bash_aliases",
+            ".bash_logout",
+            ".bash_profile",
+            ".profile",
+            ".zprofile",
+            ".zshenv",
+            ".zshrc",
+            "PKGBUILD",
+        ],
+        &["sh", "bash", "zsh", "dash", "ksh", "ash"],
+        "#",
+        NO_BLOCK,
+    ),
+    filetype("sql", &["*.sql"], &[], "--", C_BLOCK),
+    filetype("toml", &["*.toml", "Cargo.lock"], &[], "#", NO_BLOCK),
+    filetype(
+        "typescript",
+        &["*.ts", "*.mts", "*.cts", "*.tsx"],
+        &["deno", "ts-node"],
+        "//",
+        C_BLOCK,
+    ),
+    filetype("xml", &["*.xml", "*.svg", "*.xsd", "*.xsl", "*.plist"], &[], "", XML_BLOCK),
+    filetype("yaml", &["*.yaml", "*.yml"], &[], "#", NO_BLOCK),
+];
+
+/// The settings for a file type from the config. Unset ones are left as they are.
+#[derive(Default, Clone, Debug, PartialEq, Eq)]
+pub struct FileTypeSettings {
+    pub tab_size: Option<CoordType>,
+    pub indent_with_tabs: Option<bool>,
+    pub word_wrap: Option<bool>,
+    pub auto_close: Option<bool>,
+    /// Where the ruler goes. 0 for none.
+    pub wrap_column: Option<CoordType>,
+    /// Columns with a thin line, instead of the editor's rulers.
+    pub rulers: Option<Vec<CoordType>>,
+    pub line_comment: Option<String>,
+    pub block_comment: Option<(String, String)>,
+    /// A command that formats the document, see [`FormatterInput`].
+    pub formatter: Option<String>,
+    pub formatter_input: Option<FormatterInput>,
+    /// How many seconds the formatter may take.
+    pub formatter_timeout: Option<CoordType>,
+    /// Whether File > Save runs the formatter first.
+    pub format_on_save: Option<bool>,
+}
+
+/// How a file type's `formatter` gets the document and gives it back.
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FormatterInput {
+    /// On stdin, printing it formatted on stdout.
+    #[default]
+    Stdin,
+    /// In a temporary file passed as `$1`, which it formats in place.
+    File,
+}
+
+impl FileTypeSettings {
+    /// Overrides the settings that `other` sets.
+    pub fn merge(&mut self, other: &Self) {
+        self.tab_size = other.tab_size.or(self.tab_size);
+        self.indent_with_tabs = other.indent_with_tabs.or(self.indent_with_tabs);
+        self.word_wrap = other.word_wrap.or(self.word_wrap);
+        self.auto_close = other.auto_close.or(self.auto_close);
+        self.wrap_column = other.wrap_column.or(self.wrap_column);
+        self.rulers = other.rulers.clone().or(self.rulers.take());
+        self.line_comment = other.line_comment.clone().or(self.line_comment.take());
+        self.block_comment = other.block_comment.clone().or(self.block_comment.take());
+        self.formatter = other.formatter.clone().or(self.formatter.take());
+        self.formatter_input = other.formatter_input.or(self.formatter_input);
+        self.formatter_timeout = other.formatter_timeout.or(self.formatter_timeout);
+        self.format_on_save = other.format_on_save.or(self.format_on_save);
+    }
+
+    /// Applies the settings to a buffer whose defaults were applied before.
+    pub fn apply(&self, tb: &mut TextBuffer) {
+        if let Some(tab_size) = self.tab_size {
+            tb.set_tab_size(tab_size);
+        }
+        if let Some(indent_with_tabs) = self.indent_with_tabs {
+            tb.set_indent_with_tabs(indent_with_tabs);
+        }
+        if let Some(word_wrap) = self.word_wrap {
+            tb.set_word_wrap(word_wrap);
+        }
+        if let Some(auto_close) = self.auto_close {
+            tb.set_auto_close_enabled(auto_close);
+        }
+        if let Some(column) = self.wrap_column {
+            tb.set_ruler(column);
+        }
+        if let Some(rulers) = &self.rulers {
+            tb.set_rulers(rulers.clone());
+        }
+    }
+}
+
+/// The settings for each file type that differ from the defaults.
+/// Those in the config are applied on top.
+pub fn builtin_settings(filetype: &FileType) -> FileTypeSettings {
+    match filetype.name {
+        // Git's convention for commit messages: A summary of up to 50 columns, then 72.
+        // It's prose, where quotes and brackets are rarely typed back to back.
+        "git-commit" => FileTypeSettings {
+            wrap_column: Some(72),
+            rulers: Some(vec![50]),
+            auto_close: Some(false),
+            ..Default::default()
+        },
+        "makefile" => FileTypeSettings { indent_with_tabs: Some(true), ..Default::default() },
+        "go" => FileTypeSettings { indent_with_tabs: Some(true), ..Default::default() },
+        _ => FileTypeSettings::default(),
+    }
+}
+
+pub fn by_name(name: &str) -> Option<&'static FileType> {
+    FILETYPES.iter().find(|filetype| filetype.name == name)
+}
+
+/// Detects the type of the file at `path` whose first line is `first_line`.
+pub fn detect(path: &Path, first_line: &[u8]) -> Option<&'static FileType> {
+    let filename = path.file_name().and_then(|name| name.to_str()).unwrap_or_default();
+    let by_filename = FILETYPES
+        .iter()
+        .find(|filetype| filetype.patterns.iter().any(|pattern| matches(pattern, filename)));
+
+    let by_interpreter = || {
+        let interpreter = interpreter(first_line)?;
+        FILETYPES.iter().find(|filetype| filetype.interpreters.contains(&interpreter))
+    };
+    let by_content = || {
+        let line = first_line.strip_prefix("\u{feff}".as_bytes()).unwrap_or(first_line);
+        let line = line.trim_ascii_start();
+        let &(_, name) = FIRST_LINES.iter().find(|(prefix, _)| {
+            line.get(..prefix.len()).is_some_and(|s| s.eq_ignore_ascii_case(prefix.as_bytes()))
+        })?;
+        by_name(name)
+    };
+
+    modeline(first_line)
+        .and_then(by_modeline_name)
+        .or(by_filename)
+        .or_else(by_interpreter)
+        .or_else(by_content)
+}
+
+/// Looks up a type by the name an Emacs or Vim modeline gives it.
+fn by_modeline_name(name: &str) -> Option<&'static FileType> {
+    let name = name.to_ascii_lowercase();
+    let name = ALIASES.iter().find(|(alias, _)| *alias == name).map_or(name.as_str(), |a| a.1);
+    by_name(name)
+}
+
+/// Returns the mode of an Emacs modeline like `-*- mode: python -*-` or `-*- python -*-`,
+/// or the file type of a Vim one like `vim: set ft=python:`.
+fn modeline(first_line: &[u8]) -> Option<&str> {
+    let line = str::from_utf8(first_line).ok()?;
+
+    if let Some((_, rest)) = line.split_once("-*-")
+        && let Some((vars, _)) = rest.split_once("-*-")
+    {
+        if !vars.contains(':') {
+            return Some(vars.trim());
+        }
+        return vars.split(';').find_map(|var| {
+            let (key, value) = var.split_once(':')?;
+            key.trim().eq_ignore_ascii_case("mode").then(|| value.trim())
+        });
+    }
+
+    let start = ["vim:", "vi:", "ex:"].iter().find_map(|marker| {
+        let i = line.find(marker)?;
+        (i == 0 || line[..i].ends_with(char::is_whitespace)).then(|| i + marker.len())
+    })?;
+    line[start..]
+        .split(|c: char| c.is_whitespace() || c == ':')
+        .find_map(|option| option.strip_prefix("ft=").or_else(|| option.strip_prefix("filetype=")))
+}
+
+/// Matches `filename` against a pattern with up to one `*`.
+fn matches(pattern: &str, filename: &str) -> bool {
+    match pattern.split_once('*') {
+        Some((prefix, suffix)) => {
+            filename.len() > prefix.len() + suffix.len()
+                && filename.starts_with(prefix)
+                && filename.ends_with(suffix)
+        }
+        None => pattern == filename,
+    }
+}
+
+/// Returns the interpreter of a `#!` line without its version,
+/// e.g. `python` for `#!/usr/bin/env -S python3.12 -u`.
+fn interpreter(first_line: &[u8]) -> Option<&str> {
+    let line = str::from_utf8(first_line.strip_prefix(b"#!")?).ok()?;
+    let mut args = line.split_ascii_whitespace();
+    let mut program = args.next()?.rsplit('/').next()?;
+    if program == "env" {
+        program = args.find(|arg| !arg.starts_with('-') && !arg.contains('='))?;
+    }
+    Some(program.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn detect_name(path: &str, first_line: &str) -> Option<&'static str> {
+        detect(Path::new(path), first_line.as_bytes()).map(|filetype| filetype.name)
+    }
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(detect_name("/src/main.rs", ""), Some("rust"));
+        assert_eq!(detect_name("Makefile", ""), Some("makefile"));
+        assert_eq!(detect_name("/app/Dockerfile.dev", ""), Some("dockerfile"));
+        assert_eq!(detect_name(".git/COMMIT_EDITMSG", "Fix it"), Some("git-commit"));
+        assert_eq!(detect_name("build", "#!/usr/bin/env python3"), Some("python"));
+        assert_eq!(detect_name("run", "#!/usr/bin/env -S python3.12 -u"), Some("python"));
+        assert_eq!(detect_name("run", "#!/data/data/com.termux/files/usr/bin/bash"), Some("shell"));
+        assert_eq!(detect_name("setup.py", "#!/bin/sh"), Some("python"));
+        assert_eq!(detect_name("/home/u/.bash_aliases", ""), Some("shell"));
+        assert_eq!(detect_name("notes", "hello"), None);
+        assert_eq!(detect_name("page", "\u{feff}<?xml version=\"1.0\"?>"), Some("xml"));
+        assert_eq!(detect_name("index", "  <!DOCTYPE html>"), Some("html"));
+        assert_eq!(detect_name("0001.patch.txt", "diff --git a/x b/x"), Some("diff"));
+        assert_eq!(detect_name("build.txt", "<html>"), Some("html"));
+        assert_eq!(detect_name("conf", "# -*- mode: sh; coding: utf-8 -*-"), Some("shell"));
+        assert_eq!(detect_name("a.txt", "// -*- C++ -*-"), Some("cpp"));
+        assert_eq!(detect_name("x.h", "/* vim: set ft=cpp ts=4: */"), Some("cpp"));
+        assert_eq!(detect_name("rc", "# vim:filetype=zsh"), Some("shell"));
+        assert_eq!(detect_name("notes", "# divim:ft=sh"), None);
+        assert_eq!(detect_name("notes.md", "-*- mode: nonsense -*-"), Some("markdown"));
+        assert_eq!(detect_name(".rs", ""), None);
+    }
+
+    #[test]
+    fn test_sorted() {
+        assert!(FILETYPES.windows(2).all(|w| w[0].name < w[1].name));
+    }
+}
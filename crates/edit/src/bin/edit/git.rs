@@ -0,0 +1,258 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Reads what we need to know about git repositories straight from `.git`,
+//! since running `git` for it on every redraw would be too slow on a phone.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{Duration, Instant};
+
+use edit::helpers::CoordType;
+
+use crate::clipboard_backend::{find_in_path, run_with_timeout};
+
+/// How long [`BranchCache`] trusts what it read. Checkouts happen outside of the editor.
+const BRANCH_TTL: Duration = Duration::from_secs(2);
+/// `git diff` runs while the UI waits, e.g. after saving.
+const DIFF_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum LineChange {
+    Added,
+    Modified,
+    /// Lines were deleted after this one, or before the first line if it's -1.
+    DeletedAfter,
+}
+
+/// How a file differs from the last commit, as shown in the file tree.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum FileStatus {
+    Modified,
+    Added,
+    Untracked,
+    Conflicted,
+}
+
+/// Returns the checked out branch of the repository that contains `dir`,
+/// or the abbreviated commit if the `HEAD` is detached.
+pub fn branch(dir: &Path) -> Option<String> {
+    let git_dir = find_git_dir(dir)?;
+    let head = fs::read_to_string(git_dir.join("HEAD")).ok()?;
+    let head = head.trim();
+
+    match head.strip_prefix("ref:") {
+        Some(reference) => {
+            let reference = reference.trim();
+            Some(reference.strip_prefix("refs/heads/").unwrap_or(reference).to_string())
+        }
+        None => Some(head.get(..7)?.to_string()),
+    }
+}
+
+/// Returns how the lines of the file at `path` differ from the last commit, with 0-based
+/// line numbers. It's empty if the file isn't in a repository or `git` isn't installed.
+pub fn changed_lines(path: &Path) -> Vec<(CoordType, LineChange)> {
+    let (Some(dir), Some(filename)) = (path.parent(), path.file_name()) else {
+        return Vec::new();
+    };
+    if find_git_dir(dir).is_none() {
+        return Vec::new();
+    }
+    if !find_in_path("git") {
+        return Vec::new();
+    }
+
+    let mut cmd = Command::new("git");
+    cmd.arg("-C").arg(dir).args(["diff", "--no-color", "--no-ext-diff", "-U0", "HEAD", "--"]);
+    cmd.arg(filename);
+    run_with_timeout(&mut cmd, b"", DIFF_TIMEOUT)
+        .map(|output| parse_hunks(&String::from_utf8_lossy(&output)))
+        .unwrap_or_default()
+}
+
+/// Returns the changed files of the repository that contains `dir`, with absolute paths.
+/// It's empty if `dir` isn't in a repository or `git` isn't installed.
+pub fn status(dir: &Path) -> Vec<(PathBuf, FileStatus)> {
+    let Some(work_tree) = dir.ancestors().find(|dir| dir.join(".git").exists()) else {
+        return Vec::new();
+    };
+    if !find_in_path("git") {
+        return Vec::new();
+    }
+
+    let mut cmd = Command::new("git");
+    cmd.arg("-C").arg(work_tree).args(["status", "--porcelain", "-z"]);
+    run_with_timeout(&mut cmd, b"", DIFF_TIMEOUT)
+        .map(|output| {
+            parse_status(&String::from_utf8_lossy(&output))
+                .into_iter()
+                .map(|(path, status)| (work_tree.join(path), status))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Parses the output of `git status --porcelain -z`: `XY path` entries, where renames
+/// are followed by the old path. Deleted files are left out, as there's nothing to show.
+fn parse_status(output: &str) -> Vec<(&str, FileStatus)> {
+    let mut changes = Vec::new();
+    let mut entries = output.split('\0');
+
+    while let Some(entry) = entries.next() {
+        let (Some(xy), Some(path)) = (entry.get(..2), entry.get(3..)) else {
+            continue;
+        };
+        let status = match xy {
+            "??" => FileStatus::Untracked,
+            "DD" | "AA" => FileStatus::Conflicted,
+            _ if xy.contains('U') => FileStatus::Conflicted,
+            _ if xy.starts_with('R') || xy.starts_with('C') => {
+                entries.next();
+                FileStatus::Added
+            }
+            _ if xy.contains('D') => continue,
+            _ if xy.starts_with('A') => FileStatus::Added,
+            _ => FileStatus::Modified,
+        };
+        changes.push((path, status));
+    }
+
+    changes
+}
+
+/// Parses the `@@ -12,3 +12,4 @@` lines of a diff without context.
+fn parse_hunks(diff: &str) -> Vec<(CoordType, LineChange)> {
+    let mut changes = Vec::new();
+
+    for line in diff.lines() {
+        let Some(ranges) = line.strip_prefix("@@ -").and_then(|l| l.split(" @@").next()) else {
+            continue;
+        };
+        let Some((old, new)) = ranges.split_once(" +") else {
+            continue;
+        };
+        let range = |range: &str| -> Option<(CoordType, CoordType)> {
+            match range.split_once(',') {
+                Some((start, count)) => Some((start.parse().ok()?, count.parse().ok()?)),
+                None => Some((range.parse().ok()?, 1)),
+            }
+        };
+        let (Some((_, old_count)), Some((start, count))) = (range(old), range(new)) else {
+            continue;
+        };
+
+        if count == 0 {
+            // `start` is the line before the deleted ones, 1-based.
+            changes.push((start - 1, LineChange::DeletedAfter));
+        } else {
+            let change = if old_count == 0 { LineChange::Added } else { LineChange::Modified };
+            changes.extend((start - 1..start - 1 + count).map(|line| (line, change)));
+        }
+    }
+
+    changes
+}
+
+/// Finds the `.git` directory of the repository that contains `dir`.
+/// Worktrees and submodules have a `.git` file that points to it instead.
+fn find_git_dir(dir: &Path) -> Option<PathBuf> {
+    for dir in dir.ancestors() {
+        let path = dir.join(".git");
+        if path.is_dir() {
+            return Some(path);
+        }
+        if let Ok(text) = fs::read_to_string(&path) {
+            let target = text.trim().strip_prefix("gitdir:")?.trim();
+            return Some(dir.join(target));
+        }
+    }
+    None
+}
+
+/// Remembers the branch of a directory for a little while.
+#[derive(Default)]
+pub struct BranchCache {
+    dir: PathBuf,
+    branch: Option<String>,
+    read_at: Option<Instant>,
+}
+
+impl BranchCache {
+    pub fn get(&mut self, dir: &Path) -> Option<&str> {
+        let fresh = self.read_at.is_some_and(|at| at.elapsed() < BRANCH_TTL);
+        if !fresh || self.dir != dir {
+            self.dir = dir.to_path_buf();
+            self.branch = branch(dir);
+            self.read_at = Some(Instant::now());
+        }
+        self.branch.as_deref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_branch() {
+        let root = std::env::temp_dir().join(format!("edit-git-test-{}", std::process::id()));
+        let sub = root.join("src/deep");
+        fs::create_dir_all(&sub).unwrap();
+        fs::create_dir_all(root.join(".git")).unwrap();
+
+        fs::write(root.join(".git/HEAD"), "ref: refs/heads/feature/x\n").unwrap();
+        assert_eq!(branch(&sub).as_deref(), Some("feature/x"));
+
+        fs::write(root.join(".git/HEAD"), "0123456789abcdef0123456789abcdef01234567\n").unwrap();
+        assert_eq!(branch(&root).as_deref(), Some("0123456"));
+
+        // A worktree, whose `.git` is a file.
+        let worktree = root.join("wt");
+        fs::create_dir_all(root.join(".git/worktrees/wt")).unwrap();
+        fs::create_dir_all(&worktree).unwrap();
+        fs::write(
+            worktree.join(".git"),
+            format!("gitdir: {}\n", root.join(".git/worktrees/wt").display()),
+        )
+        .unwrap();
+        fs::write(root.join(".git/worktrees/wt/HEAD"), "ref: refs/heads/wt\n").unwrap();
+        assert_eq!(branch(&worktree).as_deref(), Some("wt"));
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_parse_status() {
+        let output =
+            " M src/main.rs\0A  new.rs\0?? notes/\0R  to.rs\0from.rs\0 D gone.rs\0UU both.rs\0";
+        assert_eq!(
+            parse_status(output),
+            [
+                ("src/main.rs", FileStatus::Modified),
+                ("new.rs", FileStatus::Added),
+                ("notes/", FileStatus::Untracked),
+                ("to.rs", FileStatus::Added),
+                ("both.rs", FileStatus::Conflicted),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_hunks() {
+        let diff = "diff --git a/f b/f\n--- a/f\n+++ b/f\n\
+                    @@ -0,0 +1,2 @@\n+a\n+b\n\
+                    @@ -5 +7 @@ fn main() {\n-x\n+y\n\
+                    @@ -9,2 +10,0 @@\n-c\n-d\n";
+        assert_eq!(
+            parse_hunks(diff),
+            [
+                (0, LineChange::Added),
+                (1, LineChange::Added),
+                (6, LineChange::Modified),
+                (9, LineChange::DeletedAfter),
+            ]
+        );
+    }
+}
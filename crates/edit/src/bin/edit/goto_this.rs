@@ -0,0 +1,115 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! View > Go to Link or File opens what's under the cursor: A URL in the browser,
+//! or a file name like `src/main.rs:12:5` or the `"util.h"` of an include in the
+//! editor, at the given position if there's one. Relative paths are looked up
+//! next to the document first and then in the folder of the file tree.
+
+use std::path::{Path, PathBuf};
+
+use edit::helpers::Point;
+use edit::path;
+
+use crate::open_in_app::find_url;
+use crate::runner::parse_location;
+
+#[derive(PartialEq, Eq, Debug)]
+pub enum Target {
+    Url(String),
+    File(PathBuf, Option<Point>),
+}
+
+/// Finds what to open at the byte offset `pos` in `line`.
+/// `dirs` are where relative paths are looked up, in that order.
+pub fn find(line: &str, pos: usize, dirs: &[&Path]) -> Option<Target> {
+    if let Some(url) = find_url(line, pos) {
+        return Some(Target::Url(url.to_string()));
+    }
+
+    let word = word_at(line, pos)?;
+    let (file, goto) = match parse_location(word) {
+        Some((file, goto)) => (file, Some(goto)),
+        // Python's `File "app.py", line 12` has the line outside of the quotes.
+        None => {
+            let goto = parse_location(line).filter(|&(file, _)| file == word);
+            (word, goto.map(|(_, goto)| goto))
+        }
+    };
+    Some(Target::File(resolve(file, dirs)?, goto))
+}
+
+/// The word around `pos` that may be a file name: Anything but whitespace, quotes and
+/// brackets, except for punctuation at the end, which is more likely part of the prose.
+fn word_at(line: &str, pos: usize) -> Option<&str> {
+    let is_separator = |c: char| {
+        c.is_whitespace()
+            || matches!(
+                c,
+                '"' | '\'' | '`' | '<' | '>' | '(' | ')' | '[' | ']' | '{' | '}' | ',' | ';'
+            )
+    };
+    let start = line[..pos]
+        .char_indices()
+        .rev()
+        .find(|&(_, c)| is_separator(c))
+        .map_or(0, |(i, c)| i + c.len_utf8());
+    let end = line[pos..].find(is_separator).map_or(line.len(), |i| pos + i);
+    let word = line[start..end].trim_end_matches(['.', ':', '!', '?']);
+    (!word.is_empty()).then_some(word)
+}
+
+fn resolve(file: &str, dirs: &[&Path]) -> Option<PathBuf> {
+    let file = Path::new(file);
+    let path = if file.is_absolute() {
+        Some(file.to_path_buf()).filter(|path| path.is_file())
+    } else {
+        dirs.iter().map(|dir| dir.join(file)).find(|path| path.is_file())
+    };
+    path.map(|path| path::normalize(&path))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn test_word_at() {
+        assert_eq!(word_at("#include \"util.h\"", 12), Some("util.h"));
+        assert_eq!(word_at("see src/main.rs:12:5.", 6), Some("src/main.rs:12:5"));
+        assert_eq!(word_at("(docs/README.md)", 0), None);
+        assert_eq!(word_at("(docs/README.md)", 1), Some("docs/README.md"));
+        assert_eq!(word_at("a  b", 2), None);
+        assert_eq!(word_at("　x.rs", 3), Some("x.rs"));
+    }
+
+    #[test]
+    fn test_find() {
+        let dir = std::env::temp_dir().join("edit-test-goto-this");
+        fs::create_dir_all(dir.join("src")).unwrap();
+        fs::write(dir.join("src/main.rs"), "").unwrap();
+        fs::write(dir.join("app.py"), "").unwrap();
+        let dirs = [dir.join("docs"), dir.clone()];
+        let dirs: Vec<_> = dirs.iter().map(|dir| dir.as_path()).collect();
+
+        let at = |x, y| Some(Point { x, y });
+        assert_eq!(
+            find("open https://example.com now", 8, &dirs),
+            Some(Target::Url("https://example.com".to_string()))
+        );
+        assert_eq!(
+            find("error at src/main.rs:12:5", 12, &dirs),
+            Some(Target::File(dir.join("src/main.rs"), at(4, 11)))
+        );
+        assert_eq!(
+            find("  File \"app.py\", line 3, in main", 9, &dirs),
+            Some(Target::File(dir.join("app.py"), at(0, 2)))
+        );
+        assert_eq!(find("mod main;", 5, &dirs), None);
+        assert_eq!(find("missing.rs", 0, &dirs), None);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
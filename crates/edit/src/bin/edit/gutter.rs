@@ -0,0 +1,71 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! The columns of the gutter, which can be reordered or left out in the config,
+//! see [`crate::config`], and the marks we put into them.
+
+use std::path::Path;
+
+use edit::buffer::{GutterColumn, GutterMark, TextBuffer};
+use edit::framebuffer::IndexedColor;
+use edit::helpers::Point;
+
+use crate::git::{self, LineChange};
+
+pub const BOOKMARKS: &str = "bookmarks";
+pub const GIT_SIGNS: &str = "git_signs";
+
+/// The names in the config, in the default order.
+pub const GUTTER_COLUMNS: [(&str, GutterColumn); 3] = [
+    ("bookmarks", GutterColumn::Marks(BOOKMARKS)),
+    ("line_numbers", GutterColumn::LineNumbers),
+    ("git_signs", GutterColumn::Marks(GIT_SIGNS)),
+];
+
+pub fn default_columns() -> Vec<GutterColumn> {
+    GUTTER_COLUMNS.iter().map(|&(_, column)| column).collect()
+}
+
+/// Sets or removes the bookmark on the cursor's line.
+pub fn toggle_bookmark(tb: &mut TextBuffer) {
+    let line = tb.cursor_logical_pos().y;
+    let mut marks = tb.gutter_marks(BOOKMARKS).to_vec();
+    if let Some(i) = marks.iter().position(|mark| mark.line == line) {
+        marks.remove(i);
+    } else {
+        marks.push(GutterMark { line, glyph: '●', color: IndexedColor::BrightBlue });
+    }
+    tb.set_gutter_marks(BOOKMARKS, marks);
+}
+
+/// Moves the cursor to the next or previous bookmark, wrapping around.
+pub fn goto_bookmark(tb: &mut TextBuffer, forward: bool) {
+    let line = tb.cursor_logical_pos().y;
+    let marks = tb.gutter_marks(BOOKMARKS);
+    let target = if forward {
+        marks.iter().find(|mark| mark.line > line).or(marks.first())
+    } else {
+        marks.iter().rfind(|mark| mark.line < line).or(marks.last())
+    };
+    if let Some(mark) = target {
+        let y = mark.line;
+        tb.cursor_move_to_logical(Point { x: 0, y });
+    }
+}
+
+/// Marks the lines that differ from the last commit.
+pub fn update_git_signs(tb: &mut TextBuffer, path: &Path) {
+    let marks = git::changed_lines(path)
+        .into_iter()
+        .map(|(line, change)| {
+            let (glyph, color) = match change {
+                LineChange::Added => ('▎', IndexedColor::Green),
+                LineChange::Modified => ('▎', IndexedColor::Yellow),
+                LineChange::DeletedAfter if line < 0 => ('▔', IndexedColor::Red),
+                LineChange::DeletedAfter => ('▁', IndexedColor::Red),
+            };
+            GutterMark { line: line.max(0), glyph, color }
+        })
+        .collect();
+    tb.set_gutter_marks(GIT_SIGNS, marks);
+}
@@ -0,0 +1,141 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! View > Inspect Character and the `character` status bar segment: What the grapheme
+//! under the cursor is made of, to track down invisible characters and odd widths.
+
+use std::ops::Range;
+
+use edit::buffer::TextBuffer;
+use edit::helpers::{CoordType, Point};
+use edit::unicode::{MeasurementConfig, format_codepoint};
+
+/// Graphemes can be arbitrarily long, e.g. with many combining marks. The rest is left out.
+const MAX_GRAPHEME_LEN: usize = 128;
+
+pub struct Grapheme {
+    pub text: Vec<u8>,
+    /// How many columns it takes up, counted from the start of a line.
+    pub width: CoordType,
+    pub parts: Vec<Part>,
+}
+
+/// A codepoint of a [`Grapheme`], or a byte of it that isn't valid UTF-8.
+pub struct Part {
+    pub char: Option<char>,
+    /// Where it is in [`Grapheme::text`].
+    pub range: Range<usize>,
+    pub width: CoordType,
+}
+
+/// The grapheme under the cursor, or `None` at the end of the document.
+pub fn grapheme_at_cursor(tb: &TextBuffer) -> Option<Grapheme> {
+    let offset = tb.cursor_offset();
+    let mut text = Vec::new();
+    while text.len() < MAX_GRAPHEME_LEN && offset + text.len() < tb.text_length() {
+        let chunk = tb.read_forward(offset + text.len());
+        if chunk.is_empty() {
+            break;
+        }
+        text.extend_from_slice(&chunk[..chunk.len().min(MAX_GRAPHEME_LEN - text.len())]);
+    }
+    if text.is_empty() {
+        return None;
+    }
+
+    // A line break is a grapheme of its own, but measuring stops in front of it.
+    let (len, width) = if text.starts_with(b"\r\n") {
+        (2, 0)
+    } else if text[0] == b'\n' {
+        (1, 0)
+    } else {
+        let cursor = MeasurementConfig::new(&text.as_slice())
+            .with_tab_size(tb.tab_size())
+            .goto_logical(Point { x: 1, y: 0 });
+        (cursor.offset.max(1), cursor.visual_pos.x)
+    };
+    text.truncate(len);
+
+    let mut parts = Vec::new();
+    let mut off = 0;
+    for chunk in text.utf8_chunks() {
+        for c in chunk.valid().chars() {
+            let range = off..off + c.len_utf8();
+            let width = MeasurementConfig::new(&&text[range.clone()])
+                .with_tab_size(tb.tab_size())
+                .goto_offset(range.len())
+                .visual_pos
+                .x;
+            parts.push(Part { char: Some(c), range, width });
+            off += c.len_utf8();
+        }
+        for _ in chunk.invalid() {
+            parts.push(Part { char: None, range: off..off + 1, width: 1 });
+            off += 1;
+        }
+    }
+
+    Some(Grapheme { text, width, parts })
+}
+
+/// The codepoints of `grapheme` like `U+0065 U+0301`, at most `max` of them.
+pub fn codepoints(grapheme: &Grapheme, max: usize) -> String {
+    let mut out = String::new();
+    for (i, part) in grapheme.parts.iter().enumerate() {
+        if i > 0 {
+            out.push(' ');
+        }
+        if i == max {
+            out.push('…');
+            break;
+        }
+        match part.char {
+            Some(c) => out.push_str(&format_codepoint(c)),
+            None => out.push_str(&format!("0x{:02X}", grapheme.text[part.range.start])),
+        }
+    }
+    out
+}
+
+/// The bytes of `part` in hex, like `CC 81`.
+pub fn utf8_bytes(grapheme: &Grapheme, part: &Part) -> String {
+    let bytes = &grapheme.text[part.range.clone()];
+    bytes.iter().map(|b| format!("{b:02X}")).collect::<Vec<_>>().join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn inspect(text: &str, x: CoordType) -> Option<Grapheme> {
+        stdext::arena::init(16 * edit::helpers::MEBI).unwrap();
+
+        let mut tb = TextBuffer::new(false).unwrap();
+        tb.write_raw(text.as_bytes());
+        tb.cursor_move_to_logical(Point { x, y: 0 });
+        grapheme_at_cursor(&tb)
+    }
+
+    #[test]
+    fn test_grapheme_at_cursor() {
+        let g = inspect("ae\u{301}b", 1).unwrap();
+        assert_eq!(g.text, "e\u{301}".as_bytes());
+        assert_eq!(g.width, 1);
+        assert_eq!(codepoints(&g, 8), "U+0065 U+0301");
+        assert_eq!(utf8_bytes(&g, &g.parts[1]), "CC 81");
+        assert_eq!(g.parts[1].width, 0);
+
+        let g = inspect("中", 0).unwrap();
+        assert_eq!(g.width, 2);
+        assert_eq!(codepoints(&g, 1), "U+4E2D");
+
+        let g = inspect("a\nb", 1).unwrap();
+        assert_eq!(g.text, b"\n");
+        assert_eq!(codepoints(&g, 1), "U+000A");
+
+        let g = inspect("e\u{301}\u{302}\u{303}", 0).unwrap();
+        assert_eq!(codepoints(&g, 2), "U+0065 U+0301 …");
+
+        assert!(inspect("a", 1).is_none());
+    }
+}
@@ -0,0 +1,172 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Just enough JSON for the output of the Termux:API commands and for theme files.
+//! Comments and trailing commas are allowed, as in VS Code's settings and themes.
+
+/// Parses a JSON value. Returns `None` if it's malformed.
+pub fn parse(text: &str) -> Option<Json> {
+    let text = text.strip_prefix('\u{feff}').unwrap_or(text);
+    JsonParser { text, pos: 0 }.value()
+}
+
+/// A parsed value. Only strings and the structure are kept.
+pub enum Json {
+    Null,
+    Bool,
+    Number,
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    /// Returns the field of an object.
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(fields) => fields.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(s) => Some(s),
+            _ => None,
+        }
+    }
+}
+
+struct JsonParser<'a> {
+    text: &'a str,
+    pos: usize,
+}
+
+impl JsonParser<'_> {
+    fn value(&mut self) -> Option<Json> {
+        self.skip_whitespace();
+        let rest = &self.text[self.pos..];
+        match rest.as_bytes().first()? {
+            b'{' => {
+                self.pos += 1;
+                let mut fields = Vec::new();
+                if !self.eat(b'}') {
+                    loop {
+                        self.skip_whitespace();
+                        let key = self.string()?;
+                        if !self.eat(b':') {
+                            return None;
+                        }
+                        fields.push((key, self.value()?));
+                        if self.eat(b'}') {
+                            break;
+                        }
+                        if !self.eat(b',') {
+                            return None;
+                        }
+                        if self.eat(b'}') {
+                            break; // A trailing comma.
+                        }
+                    }
+                }
+                Some(Json::Object(fields))
+            }
+            b'[' => {
+                self.pos += 1;
+                let mut items = Vec::new();
+                if !self.eat(b']') {
+                    loop {
+                        items.push(self.value()?);
+                        if self.eat(b']') {
+                            break;
+                        }
+                        if !self.eat(b',') {
+                            return None;
+                        }
+                        if self.eat(b']') {
+                            break; // A trailing comma.
+                        }
+                    }
+                }
+                Some(Json::Array(items))
+            }
+            b'"' => self.string().map(Json::String),
+            _ => {
+                let len = rest
+                    .find(|c: char| !c.is_ascii_alphanumeric() && !matches!(c, '-' | '+' | '.'))
+                    .unwrap_or(rest.len());
+                let word = &rest[..len];
+                self.pos += len;
+                match word {
+                    "null" => Some(Json::Null),
+                    "true" | "false" => Some(Json::Bool),
+                    _ if word.parse::<f64>().is_ok() => Some(Json::Number),
+                    _ => None,
+                }
+            }
+        }
+    }
+
+    fn string(&mut self) -> Option<String> {
+        if !self.eat(b'"') {
+            return None;
+        }
+        let mut result = String::new();
+        let mut chars = self.text[self.pos..].char_indices();
+        loop {
+            let (i, ch) = chars.next()?;
+            match ch {
+                '"' => {
+                    self.pos += i + 1;
+                    return Some(result);
+                }
+                '\\' => {
+                    let (_, esc) = chars.next()?;
+                    result.push(match esc {
+                        'n' => '\n',
+                        'r' => '\r',
+                        't' => '\t',
+                        'b' => '\x08',
+                        'f' => '\x0c',
+                        'u' => {
+                            let hex: String = chars.by_ref().take(4).map(|(_, c)| c).collect();
+                            // Surrogate pairs aren't worth the trouble for file names.
+                            char::from_u32(u32::from_str_radix(&hex, 16).ok()?)
+                                .unwrap_or('\u{fffd}')
+                        }
+                        _ => esc,
+                    });
+                }
+                _ => result.push(ch),
+            }
+        }
+    }
+
+    fn eat(&mut self, ch: u8) -> bool {
+        self.skip_whitespace();
+        if self.text.as_bytes().get(self.pos) == Some(&ch) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Skips whitespace and comments.
+    fn skip_whitespace(&mut self) {
+        loop {
+            let rest = &self.text[self.pos..];
+            let trimmed = rest.trim_start();
+            self.pos += rest.len() - trimmed.len();
+
+            let len = if trimmed.starts_with("//") {
+                trimmed.find('\n').unwrap_or(trimmed.len())
+            } else if trimmed.starts_with("/*") {
+                trimmed.find("*/").map_or(trimmed.len(), |i| i + 2)
+            } else {
+                break;
+            };
+            self.pos += len;
+        }
+    }
+}
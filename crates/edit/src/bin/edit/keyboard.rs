@@ -0,0 +1,188 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Guesses whether a physical keyboard is attached to the phone.
+//!
+//! The terminal doesn't tell, so the input has to: Holding a key on a
+//! physical keyboard repeats it at a fast and steady pace, which soft
+//! keyboards don't do for letters. Function keys and chords with several
+//! modifiers are hard to type without one, too. Soft keyboards, in turn,
+//! commit whole words at once when autocorrect or gesture typing is used.
+//!
+//! With a physical keyboard, the softkey bar and the touch-friendly mouse
+//! handling only get in the way, so they're turned off until the soft
+//! keyboard is used again. `--keyboard=physical|soft` turns the guessing off.
+
+use std::time::{Duration, Instant};
+
+use edit::input::{Input, InputKey, kbmod, vk};
+
+/// Key repeat is typically 25-50 ms apart. Nobody types the same letter that quickly.
+const REPEAT_INTERVAL: Duration = Duration::from_millis(80);
+/// How many repeats in a row it takes to be sure it wasn't a fluke.
+const REPEAT_COUNT: u32 = 3;
+
+const FUNCTION_KEYS: [InputKey; 12] = [
+    vk::F1,
+    vk::F2,
+    vk::F3,
+    vk::F4,
+    vk::F5,
+    vk::F6,
+    vk::F7,
+    vk::F8,
+    vk::F9,
+    vk::F10,
+    vk::F11,
+    vk::F12,
+];
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum KeyboardMode {
+    /// Follows the input.
+    Auto,
+    Physical,
+    Soft,
+}
+
+pub struct Keyboard {
+    pub mode: KeyboardMode,
+    /// Whether to guess in [`KeyboardMode::Auto`]. Only Termux needs it.
+    detect: bool,
+    /// The current guess, if there's any yet.
+    physical: Option<bool>,
+    /// Set when `physical` changed and the UI needs to adapt.
+    changed: bool,
+    /// The last single character that was typed, when, and how often in a row.
+    last_char: Option<(char, Instant, u32)>,
+}
+
+impl Keyboard {
+    pub fn new() -> Self {
+        Self {
+            mode: KeyboardMode::Auto,
+            detect: false,
+            physical: None,
+            changed: false,
+            last_char: None,
+        }
+    }
+
+    /// Starts guessing, unless the user chose a keyboard.
+    pub fn enable_by_default(&mut self) {
+        self.detect = true;
+    }
+
+    /// Looks for hints in the input.
+    pub fn observe(&mut self, input: &Input, now: Instant) {
+        if self.mode != KeyboardMode::Auto || !self.detect {
+            return;
+        }
+
+        match *input {
+            Input::Keyboard(key) => {
+                let base = key.key();
+                let chord = [kbmod::CTRL_ALT, kbmod::CTRL_SHIFT, kbmod::ALT_SHIFT]
+                    .iter()
+                    .any(|&m| key == base.with_modifiers(m));
+                if chord || FUNCTION_KEYS.contains(&base) {
+                    self.set_physical(true);
+                }
+                self.last_char = None;
+            }
+            Input::Text(text) => {
+                let mut chars = text.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(ch), None) => self.observe_char(ch, now),
+                    _ => {
+                        self.last_char = None;
+                        // A word committed by the soft keyboard, e.g. "hello ".
+                        if text.chars().count() >= 3 && text.ends_with(' ') {
+                            self.set_physical(false);
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn observe_char(&mut self, ch: char, now: Instant) {
+        let count = match self.last_char {
+            Some((last, time, count))
+                if last == ch && now.saturating_duration_since(time) <= REPEAT_INTERVAL =>
+            {
+                count + 1
+            }
+            _ => 0,
+        };
+        self.last_char = Some((ch, now, count));
+        if count >= REPEAT_COUNT && ch.is_alphanumeric() {
+            self.set_physical(true);
+        }
+    }
+
+    fn set_physical(&mut self, physical: bool) {
+        if self.physical != Some(physical) {
+            self.physical = Some(physical);
+            self.changed = true;
+        }
+    }
+
+    /// Returns whether a physical keyboard is in use, if that changed since the last call.
+    pub fn take_change(&mut self) -> Option<bool> {
+        match self.mode {
+            KeyboardMode::Auto if self.changed => {
+                self.changed = false;
+                self.physical
+            }
+            KeyboardMode::Auto => None,
+            // Reported once, so that the UI adapts at startup.
+            KeyboardMode::Physical | KeyboardMode::Soft => {
+                let physical = self.mode == KeyboardMode::Physical;
+                if self.physical == Some(physical) {
+                    return None;
+                }
+                self.physical = Some(physical);
+                Some(physical)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_observe() {
+        let mut keyboard = Keyboard::new();
+        keyboard.enable_by_default();
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+
+        // Slowly typed or non-repeating letters prove nothing.
+        keyboard.observe(&Input::Text("a"), at(0));
+        keyboard.observe(&Input::Text("a"), at(200));
+        keyboard.observe(&Input::Text("b"), at(230));
+        keyboard.observe(&Input::Keyboard(kbmod::CTRL | vk::S), at(240));
+        assert_eq!(keyboard.take_change(), None);
+
+        for i in 0..=REPEAT_COUNT as u64 {
+            keyboard.observe(&Input::Text("x"), at(300 + i * 30));
+        }
+        assert_eq!(keyboard.take_change(), Some(true));
+        assert_eq!(keyboard.take_change(), None);
+
+        keyboard.observe(&Input::Text("hello "), at(1000));
+        assert_eq!(keyboard.take_change(), Some(false));
+
+        keyboard.observe(&Input::Keyboard(kbmod::CTRL_SHIFT | vk::P), at(1100));
+        assert_eq!(keyboard.take_change(), Some(true));
+
+        keyboard.mode = KeyboardMode::Soft;
+        keyboard.observe(&Input::Keyboard(vk::F5), at(1200));
+        assert_eq!(keyboard.take_change(), Some(false));
+        assert_eq!(keyboard.take_change(), None);
+    }
+}
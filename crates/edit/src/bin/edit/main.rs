@@ -3,34 +3,119 @@
 
 #![feature(allocator_api, linked_list_cursors, string_from_utf8_lossy_owned)]
 
+mod autosave;
+mod back;
+mod backup;
+mod batch;
+mod chord;
+mod clipboard_backend;
+mod colors;
+mod config;
+mod ctags;
+mod dictation;
+mod diff;
+mod digraph;
 mod documents;
+mod draw_breadcrumbs;
+mod draw_diff;
 mod draw_editor;
 mod draw_filepicker;
+mod draw_filetree;
+mod draw_markdown;
 mod draw_menubar;
+mod draw_project_search;
+mod draw_runner;
+mod draw_settings;
 mod draw_statusbar;
+mod draw_task;
+mod draw_terminal;
+mod draw_toast;
+mod draw_tooltip;
+mod draw_welcome;
+mod export;
+mod feedback;
+mod filetree;
+mod filetype;
+mod git;
+mod goto_this;
+mod gutter;
+mod inspect;
+mod json;
+mod keyboard;
 mod localization;
+mod markdown;
+mod notify;
+mod open_in_app;
+mod osc52;
+mod palette;
+mod paths;
+mod power;
+mod pretty;
+#[cfg(feature = "profiler")]
+mod profiler;
+mod project_search;
+mod recent;
+mod registers;
+mod resize;
+mod runner;
+mod saf;
+mod save;
+mod settings;
+mod softkeys;
 mod state;
+mod statusbar;
+mod storage;
+mod syntax;
+mod task;
+mod terminal;
+mod theme;
+mod theme_import;
+mod toast;
+mod vim;
+mod wake_lock;
 
 use std::borrow::Cow;
+use std::io::{self, IsTerminal as _, Write as _};
 use std::path::{Path, PathBuf};
-use std::time::Duration;
-use std::{env, process};
-
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+use std::{env, mem, process};
+
+use back::BackPolicy;
+use backup::BackupMode;
+use chord::{ChordAction, ChordResult};
+use dictation::DictationSuffix;
+use diff::Diff;
+use documents::OpenArgs;
+use draw_breadcrumbs::*;
 use draw_editor::*;
 use draw_filepicker::*;
+use draw_filetree::*;
+use draw_markdown::*;
 use draw_menubar::*;
+use draw_project_search::*;
+use draw_runner::*;
+use draw_settings::*;
 use draw_statusbar::*;
-use edit::framebuffer::{self, IndexedColor};
+use draw_task::*;
+use draw_terminal::*;
+use draw_toast::*;
+use draw_tooltip::*;
+use edit::framebuffer::{self, ColorMode, IndexedColor, NAMED_COLORS_COUNT, NamedColor};
 use edit::helpers::*;
-use edit::input::{self, kbmod, vk};
+use edit::input::{self, InputKey, kbmod, vk};
 use edit::oklab::StraightRgba;
 use edit::tui::*;
 use edit::vt::{self, Token};
-use edit::{apperr, base64, path, sys, unicode};
+use edit::{apperr, icu, path, sys, unicode};
+use keyboard::KeyboardMode;
 use localization::*;
+use power::PowerMode;
 use state::*;
 use stdext::arena::{self, Arena, ArenaString, scratch_arena};
 use stdext::arena_format;
+use theme::Theme;
+use toast::Severity;
 
 #[cfg(target_pointer_width = "32")]
 const SCRATCH_ARENA_CAPACITY: usize = 128 * MEBI;
@@ -48,7 +133,7 @@ fn main() -> process::ExitCode {
     }
 
     match run() {
-        Ok(()) => process::ExitCode::SUCCESS,
+        Ok(code) => code,
         Err(err) => {
             sys::write_stdout(&format!("{}\n", FormatApperr::from(err)));
             process::ExitCode::FAILURE
@@ -56,7 +141,7 @@ fn main() -> process::ExitCode {
     }
 }
 
-fn run() -> apperr::Result<()> {
+fn run() -> apperr::Result<process::ExitCode> {
     // Init `sys` first, as everything else may depend on its functionality (IO, function pointers, etc.).
     let _sys_deinit = sys::init();
     // Next init `arena`, so that `scratch_arena` works. `loc` depends on it.
@@ -66,7 +151,10 @@ fn run() -> apperr::Result<()> {
 
     let mut state = State::new()?;
     if handle_args(&mut state)? {
-        return Ok(());
+        return Ok(process::ExitCode::SUCCESS);
+    }
+    if let Some(script) = state.batch_script.take() {
+        return Ok(batch::run(&mut state, &script));
     }
 
     // This will reopen stdin if it's redirected (which may fail) and switch
@@ -80,38 +168,47 @@ fn run() -> apperr::Result<()> {
     let mut input_parser = input::Parser::new();
     let mut tui = Tui::new()?;
 
-    // Detect Termux environment and disable true color for compatibility
+    // Termux usually runs on a touchscreen, where mouse events are taps: Enable touch mode
+    // and the soft keys, let Back quit, and guess whether a hardware keyboard is attached.
+    // Each of these only applies unless the user configured it otherwise.
     if std::env::var("TERMUX_VERSION").is_ok() {
-        tui.set_disable_true_color(true);
+        tui.set_touch_mode(true);
+        state.softkeys.set_enabled_by_default(true);
+        state.back.enable_by_default();
+        state.keyboard.enable_by_default();
     }
 
     let _restore = setup_terminal(&mut tui, &mut state, &mut vt_parser);
+    // From here on the frames are turned into VT and written while the next input is handled.
+    tui.spawn_render_thread();
 
-    state.menubar_color_bg = tui.indexed(IndexedColor::Background).oklab_blend(tui.indexed_alpha(
-        IndexedColor::BrightBlue,
-        1,
-        2,
-    ));
-    state.menubar_color_fg = tui.contrasted(state.menubar_color_bg);
-    let floater_bg = tui
-        .indexed_alpha(IndexedColor::Background, 2, 3)
-        .oklab_blend(tui.indexed_alpha(IndexedColor::Foreground, 1, 3));
-    let floater_fg = tui.contrasted(floater_bg);
+    state.clipboard_backend = clipboard_backend::detect();
+    if let Some(backend) = &mut state.clipboard_backend {
+        backend.request();
+    }
+    state.osc52.request_paste();
+
+    if let Some(Some(theme)) = state.theme_change.take() {
+        apply_theme(&mut tui, &mut state, Some(&theme));
+    } else {
+        apply_palette(&mut tui, &state);
+        setup_ui_colors(&mut tui, &mut state);
+    }
+    watch_config(&mut state);
     tui.setup_modifier_translations(ModifierTranslations {
         ctrl: loc(LocId::Ctrl),
         alt: loc(LocId::Alt),
         shift: loc(LocId::Shift),
     });
-    tui.set_floater_default_bg(floater_bg);
-    tui.set_floater_default_fg(floater_fg);
-    tui.set_modal_default_bg(floater_bg);
-    tui.set_modal_default_fg(floater_fg);
 
     sys::inject_window_size_into_stdin();
 
     #[cfg(feature = "debug-latency")]
     let mut last_latency_width = 0;
 
+    // The VT output of each frame. It keeps its capacity between frames.
+    let mut output = String::new();
+
     loop {
         #[cfg(feature = "debug-latency")]
         let time_beg;
@@ -121,26 +218,70 @@ fn run() -> apperr::Result<()> {
         // Process a batch of input.
         {
             let scratch = scratch_arena(None);
-            let read_timeout = vt_parser.read_timeout().min(tui.read_timeout());
+            let focused = tui.is_window_focused();
+            // `Tui::read_timeout` resets the timeout, so it's always called.
+            let frame_interval = state.power.frame_interval(tui.read_timeout());
+            let mut read_timeout = vt_parser
+                .read_timeout()
+                .min(state.resize.read_timeout())
+                .min(state.autosave.read_timeout())
+                .min(state.saf.read_timeout())
+                .min(state.dictation.read_timeout())
+                .min(
+                    state
+                        .clipboard_backend
+                        .as_ref()
+                        .map_or(Duration::MAX, |b| b.read_timeout(focused)),
+                )
+                .min(state.documents.read_timeout(focused))
+                .min(state.terminal.read_timeout(focused))
+                .min(state.runner.term.read_timeout(focused))
+                .min(task::read_timeout(state.task.as_ref()));
+            // Animations, toasts and pending chords only matter to someone who's looking.
+            // While the window is unfocused, they wait for the next input.
+            if focused {
+                read_timeout = read_timeout
+                    .min(frame_interval)
+                    .min(state.chords.read_timeout())
+                    .min(state.back.read_timeout())
+                    .min(state.toasts.read_timeout());
+            }
             let Some(input) = sys::read_stdin(&scratch, read_timeout) else {
                 break;
             };
 
+            if !input.is_empty() {
+                state.autosave.notify_input();
+            }
+
             #[cfg(feature = "debug-latency")]
             {
                 time_beg = std::time::Instant::now();
                 passes = 0usize;
             }
+            #[cfg(feature = "profiler")]
+            state.profiler.begin();
 
+            let now = Instant::now();
             let vt_iter = vt_parser.parse(&input);
             let mut input_iter = input_parser.parse(vt_iter);
 
             while {
                 let input = input_iter.next();
                 let more = input.is_some();
-                let mut ctx = tui.create_context(input);
-
-                draw(&mut ctx, &mut state);
+                if let Some(input) = &input {
+                    state.keyboard.observe(input, now);
+                }
+                // Resizes that come in a burst are held back, see `resize`.
+                let held = match input {
+                    Some(input::Input::Resize(size)) => state.resize.hold(size, tui.size()),
+                    _ => false,
+                };
+
+                if !held {
+                    let mut ctx = tui.create_context(input);
+                    draw(&mut ctx, &mut state);
+                }
 
                 #[cfg(feature = "debug-latency")]
                 {
@@ -150,6 +291,86 @@ fn run() -> apperr::Result<()> {
                 more
             } {}
         }
+        #[cfg(feature = "profiler")]
+        state.profiler.mark(profiler::Phase::Input);
+
+        // The burst of resizes is over.
+        if let Some(size) = state.resize.take() {
+            let mut ctx = tui.create_context(Some(input::Input::Resize(size)));
+            draw(&mut ctx, &mut state);
+        }
+
+        // Long operations advance by one slice per frame, see `task`.
+        if state.task.is_some() {
+            state.wants_task_step = true;
+            let mut ctx = tui.create_context(None);
+            draw(&mut ctx, &mut state);
+        }
+
+        // Large files arrive a chunk at a time, see `documents::Loader`.
+        if state.documents.is_loading() {
+            state.wants_load_step = true;
+            let mut ctx = tui.create_context(None);
+            draw(&mut ctx, &mut state);
+        }
+
+        // The lines below the viewport are highlighted between frames, see `TextBuffer::highlight_step`.
+        #[cfg(feature = "profiler")]
+        state.profiler.mark(profiler::Phase::Layout);
+        if state.documents.is_highlighting() {
+            state.wants_highlight_step = true;
+            let mut ctx = tui.create_context(None);
+            draw(&mut ctx, &mut state);
+        }
+        #[cfg(feature = "profiler")]
+        state.profiler.mark(profiler::Phase::Highlight);
+
+        // The config was changed elsewhere, e.g. in another Termux session.
+        if state.config_watcher.poll() {
+            state.wants_config_reload = true;
+            let mut ctx = tui.create_context(None);
+            draw(&mut ctx, &mut state);
+        }
+
+        // With a physical keyboard, the softkey bar and touch handling only get in the way.
+        if let Some(physical) = state.keyboard.take_change() {
+            tui.set_touch_mode(!physical);
+            state.softkeys.set_enabled_by_default(!physical);
+            let mut ctx = tui.create_context(None);
+            draw(&mut ctx, &mut state);
+        }
+
+        // A theme was picked or the settings were reloaded.
+        // Everything needs to be drawn in the new colors.
+        if let Some(theme) = state.theme_change.take() {
+            apply_theme(&mut tui, &mut state, theme.as_ref());
+            watch_config(&mut state);
+            let mut ctx = tui.create_context(None);
+            draw(&mut ctx, &mut state);
+        }
+
+        // The color mode was changed in the settings.
+        if let Some(mode) = state.color_mode_change.take() {
+            tui.set_color_mode(mode.unwrap_or(state.detected_color_mode));
+            let mut ctx = tui.create_context(None);
+            draw(&mut ctx, &mut state);
+        }
+
+        // The width of ambiguous characters was changed in the settings.
+        // All text has to be measured and drawn again.
+        if let Some(width) = state.ambiguous_width_change.take() {
+            unicode::setup_ambiguous_width(width.unwrap_or(state.detected_ambiguous_width));
+            state.documents.reflow_all();
+            tui.invalidate();
+            let mut ctx = tui.create_context(None);
+            draw(&mut ctx, &mut state);
+        }
+
+        // Taps on the softkey bar turn into key presses for whatever is focused.
+        while let Some(key) = state.softkeys.take_pending() {
+            let mut ctx = tui.create_context(Some(input::Input::Keyboard(key)));
+            draw(&mut ctx, &mut state);
+        }
 
         // Continue rendering until the layout has settled.
         // This can take >1 frame, if the input focus is tossed between different controls.
@@ -168,16 +389,25 @@ fn run() -> apperr::Result<()> {
             break;
         }
 
+        #[cfg(feature = "profiler")]
+        {
+            state.profiler.mark(profiler::Phase::Layout);
+            if state.profiler.take_hidden() {
+                tui.invalidate();
+            }
+        }
+
         // Render the UI and write it to the terminal.
         {
-            let scratch = scratch_arena(None);
-            let mut output = tui.render(&scratch);
+            tui.render_into(&mut output);
 
             write_terminal_title(&mut output, &mut state);
 
             if state.osc_clipboard_sync {
                 write_osc_clipboard(&mut tui, &mut state, &mut output);
             }
+            state.osc52.write_pending_paste(&mut output);
+            state.feedback.write_pending(&mut output);
 
             #[cfg(feature = "debug-latency")]
             {
@@ -187,9 +417,9 @@ fn run() -> apperr::Result<()> {
                 let time_end = std::time::Instant::now();
                 let status = time_end - time_beg;
 
-                let scratch_alt = scratch_arena(Some(&scratch));
+                let scratch = scratch_arena(None);
                 let status = arena_format!(
-                    &scratch_alt,
+                    &*scratch,
                     "{}P {}B {:.3}μs",
                     passes,
                     output.len(),
@@ -220,29 +450,112 @@ fn run() -> apperr::Result<()> {
                 last_latency_width = cols;
             }
 
-            sys::write_stdout(&output);
+            #[cfg(feature = "profiler")]
+            {
+                state.profiler.mark(profiler::Phase::Render);
+                if state.profiler.visible {
+                    state.profiler.write_overlay(&mut output, tui.size().width);
+                }
+            }
+
+            tui.present(&mut output);
+
+            #[cfg(feature = "profiler")]
+            state.profiler.end();
         }
     }
 
-    Ok(())
+    // Let the render thread write out the last frame before `_restore` resets the terminal.
+    drop(tui);
+
+    // Only once the terminal is restored, so the document doesn't end up among the VT sequences.
+    if let Some(buffer) = state.stdout_buffer.take() {
+        drop(_restore);
+        drop(_sys_deinit);
+        let mut stdout = io::stdout().lock();
+        buffer.borrow().write_file_copy(&mut stdout)?;
+        stdout.flush()?;
+    }
+    Ok(process::ExitCode::SUCCESS)
 }
 
 // Returns true if the application should exit early.
 fn handle_args(state: &mut State) -> apperr::Result<bool> {
     let scratch = scratch_arena(None);
-    let mut paths: Vec<PathBuf, &Arena> = Vec::new_in(&*scratch);
+    // Each with the position of a `+line[:col]` before it.
+    let mut paths: Vec<(PathBuf, Option<Point>), &Arena> = Vec::new_in(&*scratch);
     let cwd = env::current_dir()?;
+
     let mut dir = None;
+    let mut keymap = None;
+
+    // The config file is found via `--app-dir`, and the other options override it.
+    // Errors in it are reported after `--help` and `--paths` had their chance.
+    for arg in env::args_os().skip(1) {
+        if let Some(dir) = arg.to_str().and_then(|a| a.strip_prefix("--app-dir=")) {
+            crate::paths::set_override(cwd.join(dir));
+        }
+    }
+    state.filetree.restore_session();
+    let config_error = match config::load() {
+        Ok(config) => {
+            state.documents.defaults = config.document;
+            state.documents.filetypes = config.filetypes;
+            state.documents.gutter = config.gutter;
+            state.documents.rulers = config.rulers;
+            state.statusbar = config.statusbar;
+            if let Some(mode) = config.color_mode {
+                state.color_mode = mode;
+            }
+            if let Some(width) = config.ambiguous_width {
+                state.ambiguous_width = width;
+            }
+            keymap = config.keymap;
+            state.after_save = config.after_save;
+            state.digraphs = config.digraphs;
+            state.palette = config.palette;
+            state.menubar_hidden = config.hide_menubar;
+            state.breadcrumbs = config.breadcrumbs;
+            match config.theme.map(|name| theme::load_by_name(&name).map(|theme| (name, theme))) {
+                Some(Ok((name, theme))) => {
+                    state.theme = Some(name);
+                    state.theme_change = Some(Some(theme));
+                    None
+                }
+                Some(Err(err)) => Some(err),
+                None => None,
+            }
+        }
+        Err(err) => Some(err),
+    };
+
+    let mut print_paths = false;
     let mut parse_args = true;
+    let mut stdin_arg = false;
+    let mut wait = false;
+    let mut goto = None;
+    let mut open_args = OpenArgs::default();
+    let mut filetype = None;
+    let mut batch_arg = false;
+    let mut diff_arg = false;
 
     // The best CLI argument parser in the world.
     for arg in env::args_os().skip(1) {
+        if mem::take(&mut batch_arg) {
+            state.batch_script = Some(cwd.join(&arg));
+            continue;
+        }
         if parse_args {
+            if arg == "--batch" {
+                batch_arg = true;
+                continue;
+            }
             if arg == "--" {
                 parse_args = false;
                 continue;
             }
             if arg == "-" {
+                stdin_arg = true;
                 paths.clear();
                 break;
             }
@@ -254,6 +567,141 @@ fn handle_args(state: &mut State) -> apperr::Result<bool> {
                 print_version();
                 return Ok(true);
             }
+            if arg == "-R" || arg == "--readonly" {
+                state.open_read_only = true;
+                continue;
+            }
+            if arg == "-d" || arg == "--diff" {
+                diff_arg = true;
+                continue;
+            }
+            if arg == "-w" || arg == "--wait" {
+                wait = true;
+                continue;
+            }
+            if let Some(pos) = arg.to_str().and_then(parse_goto_arg) {
+                goto = Some(pos);
+                continue;
+            }
+            if arg == "--vim" {
+                state.vim.set_enabled(true);
+                continue;
+            }
+            if arg == "--paths" {
+                print_paths = true;
+                continue;
+            }
+            if arg == "--pick-recent" {
+                state.wants_recent_picker = true;
+                continue;
+            }
+            if arg == "--osc52-paste" {
+                state.osc52.paste = true;
+                continue;
+            }
+            if arg == "--backup" {
+                state.backup.mode = BackupMode::Tilde;
+                continue;
+            }
+            if arg == "--commit-msg" {
+                filetype = filetype::by_name("git-commit");
+                continue;
+            }
+            if let Some((name, value)) = arg.to_str().and_then(|a| a.split_once('=')) {
+                let ok = match name {
+                    "--app-dir" => !value.is_empty(),
+                    "--autosave" => {
+                        value.parse().map(|s| state.autosave.delay = Duration::from_secs(s)).is_ok()
+                    }
+                    "--back" => match value {
+                        "off" => Some(BackPolicy::Off),
+                        "single" => Some(BackPolicy::Single),
+                        "double" => Some(BackPolicy::Double),
+                        _ => None,
+                    }
+                    .map(|policy| state.back.set_policy(policy))
+                    .is_some(),
+                    "--ambiguous-width" => config::AMBIGUOUS_WIDTHS
+                        .iter()
+                        .find(|&&(name, _)| name == value)
+                        .map(|&(_, width)| state.ambiguous_width = width)
+                        .is_some(),
+                    "--color" => match value {
+                        "auto" => Some(None),
+                        "truecolor" => Some(Some(ColorMode::TrueColor)),
+                        "256" => Some(Some(ColorMode::Palette256)),
+                        _ => None,
+                    }
+                    .map(|mode| state.color_mode = mode)
+                    .is_some(),
+                    "--backup-dir" => {
+                        state.backup.mode = BackupMode::Directory(cwd.join(value));
+                        true
+                    }
+                    "--backup-pattern" => {
+                        state.backup.pattern = value.to_string();
+                        !value.is_empty()
+                    }
+                    "--backup-keep" => value.parse().map(|n| state.backup.keep = n).is_ok(),
+                    "--keymap" => {
+                        state.keymap_arg = Some(cwd.join(value));
+                        true
+                    }
+                    "--dictation-suffix" => match value {
+                        "none" => Some(DictationSuffix::None),
+                        "space" => Some(DictationSuffix::Space),
+                        "newline" => Some(DictationSuffix::Newline),
+                        _ => None,
+                    }
+                    .map(|suffix| state.dictation.suffix = suffix)
+                    .is_some(),
+                    "--feedback" => state.feedback.parse(value),
+                    "--keyboard" => match value {
+                        "auto" => Some(KeyboardMode::Auto),
+                        "physical" => Some(KeyboardMode::Physical),
+                        "soft" => Some(KeyboardMode::Soft),
+                        _ => None,
+                    }
+                    .map(|mode| state.keyboard.mode = mode)
+                    .is_some(),
+                    "--encoding" => {
+                        open_args.encoding = parse_encoding_arg(value);
+                        open_args.encoding.is_some()
+                    }
+                    "--filetype" => {
+                        filetype = filetype::by_name(value);
+                        filetype.is_some()
+                    }
+                    "--osc52-max" => value.parse().map(|n| state.osc52.max_size = n).is_ok(),
+                    "--power-saving" => match value {
+                        "auto" => Some(PowerMode::Auto),
+                        "on" => Some(PowerMode::On),
+                        "off" => Some(PowerMode::Off),
+                        _ => None,
+                    }
+                    .map(|mode| state.power.mode = mode)
+                    .is_some(),
+                    "--battery-threshold" => value
+                        .parse()
+                        .ok()
+                        .filter(|&n| n <= 100)
+                        .map(|n| state.power.threshold = n)
+                        .is_some(),
+                    _ => false,
+                };
+                if ok {
+                    continue;
+                }
+                if name.starts_with("--") {
+                    print_help();
+                    return Ok(true);
+                }
+            }
+        }
+
+        if let Some(uri) = arg.to_str().filter(|a| a.starts_with("content://")) {
+            state.saf.open(&saf::entry_from_uri(uri));
+            continue;
         }
 
         let p = cwd.join(Path::new(&arg));
@@ -262,26 +710,78 @@ fn handle_args(state: &mut State) -> apperr::Result<bool> {
             state.wants_file_picker = StateFilePicker::Open;
             dir = Some(p);
         } else {
-            paths.push(p);
+            paths.push((p, goto.take()));
         }
     }
 
-    for p in &paths {
-        state.documents.add_file_path(p)?;
+    if print_paths {
+        sys::write_stdout(&crate::paths::describe());
+        return Ok(true);
+    }
+
+    if let Some(err) = config_error {
+        sys::write_stdout(&format!("{err}\n"));
+        return Ok(true);
+    }
+
+    if let Err(err) = state.softkeys.load(state.keymap_arg.clone().or(keymap)) {
+        sys::write_stdout(&format!("{err}\n"));
+        return Ok(true);
+    }
+
+    // `edit -d FILE1 FILE2` shows the diff instead of opening the files.
+    if diff_arg {
+        let [(left, _), (right, _)] = &paths[..] else {
+            print_help();
+            return Ok(true);
+        };
+        state.diff = Some(Diff::load(left, right)?);
+        paths.clear();
+    }
+
+    for (p, goto) in &paths {
+        let args = OpenArgs { goto: *goto, ..open_args };
+        let doc = state.documents.add_file_path_with(p, args)?;
+        doc.buffer.borrow_mut().set_read_only(state.open_read_only);
+        if wait {
+            state.wait_for.push(Rc::downgrade(&doc.buffer));
+        }
+        if filetype.is_some() {
+            state.documents.set_active_filetype(filetype);
+        }
     }
 
-    if let Some(mut file) = sys::open_stdin_if_redirected() {
+    // Without any files (not even Android documents) the welcome screen shows up,
+    // instead of an empty document. See `draw_welcome`.
+    // `-` asks for a document even if stdin isn't redirected, e.g. for `edit - > out.txt`.
+    // Scripts run from cron and the like have a stdin of /dev/null, which isn't meant as a file.
+    let batch_files = state.batch_script.is_some() && !paths.is_empty();
+    let stdin = if batch_files && !stdin_arg { None } else { sys::open_stdin_if_redirected() };
+    if stdin.is_some() || stdin_arg {
         let doc = state.documents.add_untitled()?;
         let mut tb = doc.buffer.borrow_mut();
-        tb.read_file(&mut file, None)?;
-        tb.mark_as_dirty();
-    } else if paths.is_empty() {
-        // No files were passed, and stdin is not redirected.
-        state.documents.add_untitled()?;
+        let redirected_stdout = !io::stdout().is_terminal();
+        if let Some(mut file) = stdin {
+            tb.read_file(&mut file, open_args.encoding)?;
+            // Without a redirected stdout, the piped text is lost unless it's saved somewhere.
+            if !redirected_stdout {
+                tb.mark_as_dirty();
+            }
+        }
+        tb.set_read_only(state.open_read_only);
+        drop(tb);
+
+        // Otherwise it's where the document goes on exit, like in `a | edit - | b`.
+        if redirected_stdout {
+            state.stdout_buffer = Some(doc.buffer.clone());
+        }
+        if filetype.is_some() {
+            state.documents.set_active_filetype(filetype);
+        }
     }
 
     if dir.is_none()
-        && let Some(parent) = paths.last().and_then(|p| p.parent())
+        && let Some(parent) = paths.last().and_then(|(p, _)| p.parent())
     {
         dir = Some(parent.to_path_buf());
     }
@@ -290,15 +790,75 @@ fn handle_args(state: &mut State) -> apperr::Result<bool> {
     Ok(false)
 }
 
+/// Parses `+line[:col]`, 1-based like the `file:line:col` suffix.
+fn parse_goto_arg(arg: &str) -> Option<Point> {
+    let arg = arg.strip_prefix('+')?;
+    let (line, col) = arg.split_once(':').unwrap_or((arg, "1"));
+    let line: CoordType = line.parse().ok()?;
+    let col: CoordType = col.parse().ok()?;
+    Some(Point { x: col.saturating_sub(1).max(0), y: line.saturating_sub(1).max(0) })
+}
+
+/// Finds the encoding named `value`, ignoring case, e.g. "utf-8 bom".
+fn parse_encoding_arg(value: &str) -> Option<&'static str> {
+    icu::get_available_encodings()
+        .all
+        .iter()
+        .find(|e| e.label.eq_ignore_ascii_case(value) || e.canonical.eq_ignore_ascii_case(value))
+        .map(|e| e.canonical)
+}
+
 fn print_help() {
     sys::write_stdout(concat!(
-        "Usage: edit [OPTIONS] [FILE[:LINE[:COLUMN]]]\n",
+        "Usage: edit [OPTIONS] [[+LINE[:COLUMN]] FILE[:LINE[:COLUMN]] | URI | -]...\n",
         "Options:\n",
         "    -h, --help       Print this help message\n",
         "    -v, --version    Print the version number\n",
+        "    -R, --readonly   Open the files in read-only mode\n",
+        "    -w, --wait       Exit once the files are closed, e.g. for $GIT_EDITOR\n",
+        "    -d, --diff FILE1 FILE2\n",
+        "                     Compare two files side by side, e.g. for git difftool\n",
+        "    --batch SCRIPT   Run the commands in SCRIPT on the files without the UI and exit\n",
+        "    --vim            Enable Vim-style modal editing\n",
+        "    --ambiguous-width=auto|1|2\n",
+        "                     Cells taken by characters like \"…\" (default: auto, asks the terminal)\n",
+        "    --app-dir=DIR    Keep the configuration and other files of edit in DIR\n",
+        "    --autosave=SECS  Save modified files after SECS seconds of inactivity\n",
+        "    --back=off|single|double\n",
+        "                     Whether Esc quits when there's nothing to cancel (default: double in Termux)\n",
+        "    --backup         Copy the previous version of a file to FILE~ when saving\n",
+        "    --backup-dir=DIR Copy the previous version of a file into DIR when saving\n",
+        "    --backup-pattern=PATTERN\n",
+        "                     Backup filename in DIR. {name}, {dir} and {date} are replaced\n",
+        "                     (default: {name}.{dir}.{date})\n",
+        "    --backup-keep=N  Number of backups per file to keep in DIR, 0 for all (default: 10)\n",
+        "    --battery-threshold=PERCENT\n",
+        "                     Save power below this charge level (default: 20)\n",
+        "    --color=auto|truecolor|256\n",
+        "                     Colors to use (default: auto, detected from the terminal)\n",
+        "    --commit-msg     Open the files as git commit messages, like COMMIT_EDITMSG\n",
+        "    --encoding=NAME  Open the files in this encoding instead of detecting it\n",
+        "    --filetype=NAME  Open the files as this file type instead of detecting it\n",
+        "    --dictation-suffix=none|space|newline\n",
+        "                     What to append to dictated text (default: none)\n",
+        "    --feedback=LIST  Alert on errors with any of: bell, flash, vibrate (default: none)\n",
+        "    --keyboard=auto|physical|soft\n",
+        "                     Hide the softkey bar and touch handling for a physical keyboard (default: auto)\n",
+        "    --keymap=FILE    Read the softkey bar layout from FILE (default: ~/.config/edit/keymap)\n",
+        "    --osc52-max=BYTES\n",
+        "                     Largest copy to send to the terminal's clipboard (default: 8388608)\n",
+        "    --osc52-paste    Read the terminal's clipboard via OSC 52, if it allows that\n",
+        "    --paths          Print where the configuration and other files of edit are kept\n",
+        "    --pick-recent    Choose one of the recently opened files to open\n",
+        "    --power-saving=auto|on|off\n",
+        "                     Lower the frame rate and autosave less often (default: auto, on low battery)\n",
         "\n",
         "Arguments:\n",
         "    FILE[:LINE[:COLUMN]]    The file to open, optionally with line and column (e.g., foo.txt:123:45)\n",
+        "    +LINE[:COLUMN]          Where to go in the next file (e.g., +123:45 foo.txt)\n",
+        "    --                      Treat all further arguments as files\n",
+        "    URI                     An Android content:// URI to open via Termux:API\n",
+        "    -                       Read stdin, and if stdout is redirected, write the document to it on exit\n",
     ));
 }
 
@@ -307,25 +867,168 @@ fn print_version() {
 }
 
 fn draw(ctx: &mut Context, state: &mut State) {
+    if let Some(backend) = &mut state.clipboard_backend
+        && ctx.is_window_focused()
+    {
+        backend.request_if_due();
+    }
+    if let Some(backend) = &mut state.clipboard_backend
+        && let Some(data) = backend.poll()
+    {
+        // Someone copied something in another app. It's already synchronized, obviously.
+        let clipboard = ctx.clipboard_mut();
+        clipboard.write(data);
+        clipboard.mark_as_synchronized();
+    }
+    state.power.poll();
+    state.autosave.power_saving = state.power.is_saving();
+    while let Some(event) = state.saf.poll() {
+        draw_handle_saf_event(ctx, state, event);
+    }
+    if let Some(result) = state.dictation.poll() {
+        draw_handle_dictation(ctx, state, result);
+    }
+    if state.chords.pending().is_some() {
+        draw_handle_chord(ctx, state);
+    }
+    if state.digraph != StateDigraph::None {
+        draw_handle_digraph(ctx, state);
+    }
+    if state.task.is_some() {
+        draw_handle_task(ctx, state);
+    }
+    if state.wants_load_step {
+        draw_handle_loading(ctx, state);
+    }
+    if state.wants_highlight_step {
+        draw_handle_highlighting(ctx, state);
+    }
+    if state.tooltip.is_some() {
+        draw_handle_tooltip(ctx, state);
+    }
+    state.terminal.poll();
+    state.runner.term.poll();
+
+    // Lines of the document shown in lists and tooltips line up like in the editor.
+    if let Some(doc) = state.documents.active() {
+        ctx.set_tab_size(doc.buffer.borrow().tab_size());
+    }
+
     draw_menubar(ctx, state);
+    if breadcrumbs_visible(state) {
+        draw_breadcrumbs(ctx, state);
+    }
     draw_editor(ctx, state);
+    if state.runner.term.visible {
+        draw_runner(ctx, state);
+    }
+    if state.terminal.visible {
+        draw_terminal(ctx, state);
+    }
     draw_statusbar(ctx, state);
+    if state.softkeys.enabled {
+        draw_softkeys(ctx, state);
+    }
 
+    if state.documents.active().is_some_and(|doc| doc.buffer.borrow_mut().take_edit_blocked()) {
+        error_log_add(ctx, state, apperr::APP_READ_ONLY);
+    }
+    if let Some(focused) = ctx.window_focus_changed() {
+        draw_handle_window_focus(ctx, state, focused);
+    }
+    if state.autosave.is_due() {
+        draw_handle_autosave(ctx, state);
+    }
     if state.wants_close {
         draw_handle_wants_close(ctx, state);
     }
+    // `--wait`: Once the files from the command line are closed, so is edit.
+    if !state.wait_for.is_empty() && !state.wait_for.iter().any(|b| state.documents.contains(b)) {
+        state.wait_for.clear();
+        state.wants_exit = true;
+    }
     if state.wants_exit {
         draw_handle_wants_exit(ctx, state);
     }
     if state.wants_goto {
         draw_goto_menu(ctx, state);
     }
+    if let Some(pos) = state.wants_context_menu {
+        draw_context_menu(ctx, state, pos);
+    }
+    if state.wants_open_in_app {
+        draw_handle_open_in_app(ctx, state);
+    }
+    if state.wants_goto_this {
+        draw_handle_goto_this(ctx, state);
+    }
+    if state.wants_share {
+        draw_share_dialog(ctx, state);
+    }
     if state.wants_file_picker != StateFilePicker::None {
         draw_file_picker(ctx, state);
     }
+    if state.wants_recent_picker {
+        draw_recent_picker(ctx, state);
+    }
+    if state.wants_register_picker {
+        draw_register_picker(ctx, state);
+    }
+    if state.wants_saf_browser {
+        draw_saf_browser(ctx, state);
+    }
+    if state.wants_theme_picker {
+        draw_theme_picker(ctx, state);
+    }
+    if state.wants_filetype_picker {
+        draw_filetype_picker(ctx, state);
+    }
+    if state.wants_run_dialog {
+        draw_run_dialog(ctx, state);
+    }
+    if state.wants_filter {
+        draw_filter_dialog(ctx, state);
+    }
+    if state.wants_surround != StateSurround::None {
+        draw_surround_dialog(ctx, state);
+    }
+    if state.wants_codec {
+        draw_codec_dialog(ctx, state);
+    }
+    if state.wants_insert_char != StateInsertChar::None {
+        draw_insert_char_dialog(ctx, state);
+    }
+    if state.wants_char_inspector {
+        draw_char_inspector(ctx, state);
+    }
+    if state.wants_format {
+        draw_handle_format(ctx, state);
+    }
+    if state.wants_pretty_print.is_some() {
+        draw_handle_pretty_print(ctx, state);
+    }
+    if state.wants_settings {
+        draw_settings(ctx, state);
+    }
+    if state.filetree.prompt.is_some() {
+        draw_filetree_prompt(ctx, state);
+    }
+    if state.wants_config_reload {
+        state.wants_config_reload = false;
+        match settings::reload(state) {
+            Ok(()) => {
+                toast_add(ctx, state, Severity::Info, loc(LocId::SettingsReloaded).to_string())
+            }
+            Err(err) => error_log_add_message(ctx, state, err),
+        }
+        ctx.needs_rerender();
+    }
     if state.wants_save {
         draw_handle_save(ctx, state);
     }
+    if state.wants_project_replace {
+        draw_project_replace(ctx, state);
+    }
     if state.wants_encoding_change != StateEncodingChange::None {
         draw_dialog_encoding_change(ctx, state);
     }
@@ -335,12 +1038,18 @@ fn draw(ctx: &mut Context, state: &mut State) {
     if state.wants_about {
         draw_dialog_about(ctx, state);
     }
+    if state.wants_notification_history {
+        draw_notification_history(ctx, state);
+    }
     if ctx.clipboard_ref().wants_host_sync() {
         draw_handle_clipboard_change(ctx, state);
     }
     if state.error_log_count != 0 {
         draw_error_log(ctx, state);
     }
+    // Last, so that they stay on top of the dialogs. Long operations usually start in one.
+    draw_toasts(ctx, state);
+    draw_tooltip(ctx, state);
 
     if let Some(key) = ctx.keyboard_input() {
         // Shortcuts that are not handled as part of the textarea, etc.
@@ -369,8 +1078,41 @@ fn draw(ctx: &mut Context, state: &mut State) {
         {
             state.wants_search.kind = StateSearchKind::Replace;
             state.wants_search.focus = true;
+        } else if key == kbmod::CTRL_SHIFT | vk::H
+            && state.wants_search.kind != StateSearchKind::Disabled
+        {
+            state.wants_project_replace = true;
+        } else if key == kbmod::CTRL_SHIFT | vk::V {
+            state.wants_register_picker = true;
+        } else if key == kbmod::CTRL | vk::OEM_2
+            && let Some(doc) = state.documents.active()
+            && let Some((prefix, suffix)) = doc.comment_strings()
+        {
+            doc.buffer.borrow_mut().toggle_comment(prefix, suffix);
+        } else if key == kbmod::CTRL_ALT | vk::F
+            && state.documents.active().is_some_and(|doc| doc.can_format())
+        {
+            state.wants_format = true;
+        } else if key == kbmod::CTRL | vk::F2
+            && let Some(doc) = state.documents.active()
+        {
+            gutter::toggle_bookmark(&mut doc.buffer.borrow_mut());
+        } else if (key == vk::F2 || key == kbmod::SHIFT | vk::F2)
+            && let Some(doc) = state.documents.active()
+        {
+            gutter::goto_bookmark(&mut doc.buffer.borrow_mut(), key == vk::F2);
         } else if key == vk::F3 {
             search_execute(ctx, state, SearchAction::Search);
+        } else if key == vk::F5 {
+            state.wants_run_dialog = true;
+        } else if key == kbmod::SHIFT | vk::F5 && state.runner.is_running() {
+            state.runner.stop();
+        } else if key == kbmod::SHIFT | vk::F5 && !state.runner.command.is_empty() {
+            run_command(ctx, state);
+        } else if key == kbmod::CTRL | vk::B {
+            toggle_filetree(state);
+        } else if state.chords.start(key) == ChordResult::Started {
+            // The continuation is handled by `draw_handle_chord` during the next input.
         } else {
             return;
         }
@@ -381,9 +1123,131 @@ fn draw(ctx: &mut Context, state: &mut State) {
     }
 }
 
+/// Completes or cancels a pending chord. This runs before anything else gets to see
+/// the input, so that e.g. the W in Ctrl+K W doesn't get typed into the document.
+fn draw_handle_chord(ctx: &mut Context, state: &mut State) {
+    if state.chords.is_expired() {
+        state.chords.cancel();
+        ctx.needs_rerender();
+        return;
+    }
+
+    let key = if let Some(text) = ctx.text_input() {
+        let mut chars = text.chars();
+        match (chars.next(), chars.next()) {
+            (Some(ch), None) => InputKey::from_ascii(ch).unwrap_or(vk::NULL),
+            _ => vk::NULL,
+        }
+    } else if let Some(key) = ctx.keyboard_input() {
+        key
+    } else {
+        return;
+    };
+
+    match state.chords.complete(key) {
+        ChordResult::Action(action) => match action {
+            ChordAction::SaveAs => state.wants_file_picker = StateFilePicker::SaveAs,
+            ChordAction::Close => state.wants_close = true,
+            ChordAction::Goto => state.wants_goto = true,
+            ChordAction::OpenInApp => state.wants_open_in_app = true,
+            ChordAction::GotoThis => state.wants_goto_this = true,
+            ChordAction::Registers => state.wants_register_picker = true,
+            ChordAction::InsertCharacter => state.wants_insert_char = StateInsertChar::Picker,
+            ChordAction::InsertCodepoint => state.wants_insert_char = StateInsertChar::Codepoint,
+            ChordAction::Digraph => state.digraph = StateDigraph::First,
+            ChordAction::Share => state.wants_share = true,
+            ChordAction::GoToFile => state.wants_go_to_file = true,
+            ChordAction::FocusStatusbar => state.wants_statusbar_focus = true,
+            ChordAction::FocusFileTree => {
+                state.filetree.set_visible(true);
+                state.wants_filetree_focus = true;
+            }
+            ChordAction::FocusTerminal => toggle_terminal_focus(state),
+            ChordAction::RunInTerminal => send_selection_to_terminal(ctx, state),
+            ChordAction::Notifications => state.wants_notification_history = true,
+            ChordAction::DeleteLines => {
+                if let Some(doc) = state.documents.active() {
+                    doc.buffer.borrow_mut().delete_selected_lines();
+                }
+            }
+            ChordAction::JoinLines => {
+                if let Some(doc) = state.documents.active() {
+                    doc.buffer.borrow_mut().join_lines(doc.line_comment());
+                }
+            }
+            ChordAction::ToggleWordWrap | ChordAction::ToggleReadOnly => {
+                if let Some(doc) = state.documents.active() {
+                    let mut tb = doc.buffer.borrow_mut();
+                    if action == ChordAction::ToggleWordWrap {
+                        let word_wrap = tb.is_word_wrap_enabled();
+                        tb.set_word_wrap(!word_wrap);
+                    } else {
+                        let read_only = tb.is_read_only();
+                        tb.set_read_only(!read_only);
+                    }
+                }
+            }
+            ChordAction::ToggleAutosave => {
+                if let Some(doc) = state.documents.active_mut() {
+                    doc.autosave = !doc.autosave;
+                }
+            }
+            ChordAction::ToggleVim => {
+                let enabled = !state.vim.enabled;
+                state.vim.set_enabled(enabled);
+            }
+            ChordAction::ToggleMarkdownPreview => {
+                if state.documents.active().is_some_and(is_markdown) {
+                    toggle_markdown_preview(state);
+                }
+            }
+        },
+        ChordResult::Unhandled => return,
+        ChordResult::Started | ChordResult::Cancelled => {}
+    }
+
+    ctx.needs_rerender();
+    ctx.set_input_consumed();
+}
+
+/// Collects the two characters typed after Ctrl+K K and inserts the digraph they stand for.
+/// Like the chords, this runs before anything else gets to see the input.
+fn draw_handle_digraph(ctx: &mut Context, state: &mut State) {
+    let ch = if let Some(text) = ctx.text_input() {
+        text.chars().next()
+    } else if ctx.keyboard_input().is_some() {
+        // Anything but a character, like Escape or an arrow key, cancels it.
+        None
+    } else {
+        return;
+    };
+
+    state.digraph = match (state.digraph, ch) {
+        (StateDigraph::First, Some(ch)) => StateDigraph::Second(ch),
+        (StateDigraph::Second(first), Some(second)) => {
+            if let Some(c) = digraph::lookup(&state.digraphs, first, second) {
+                if let Some(doc) = state.documents.active() {
+                    doc.buffer.borrow_mut().write_canon(c.encode_utf8(&mut [0; 4]).as_bytes());
+                }
+            } else {
+                let msg =
+                    loc(LocId::DigraphUnknown).replace("{digraph}", &format!("{first}{second}"));
+                toast_add(ctx, state, Severity::Warning, msg);
+            }
+            StateDigraph::None
+        }
+        _ => StateDigraph::None,
+    };
+
+    ctx.needs_rerender();
+    ctx.set_input_consumed();
+}
+
 fn draw_handle_wants_exit(_ctx: &mut Context, state: &mut State) {
     while let Some(doc) = state.documents.active() {
-        if doc.buffer.borrow().is_dirty() {
+        // The document that goes to stdout needn't be saved.
+        let to_stdout = state.stdout_buffer.as_ref().is_some_and(|b| Rc::ptr_eq(b, &doc.buffer));
+        if doc.buffer.borrow().is_dirty() && !to_stdout {
             state.wants_close = true;
             return;
         }
@@ -395,7 +1259,7 @@ fn draw_handle_wants_exit(_ctx: &mut Context, state: &mut State) {
     }
 }
 
-fn write_terminal_title(output: &mut ArenaString, state: &mut State) {
+fn write_terminal_title(output: &mut String, state: &mut State) {
     let (filename, dirty) = state
         .documents
         .active()
@@ -424,15 +1288,29 @@ fn write_terminal_title(output: &mut ArenaString, state: &mut State) {
 const LARGE_CLIPBOARD_THRESHOLD: usize = 128 * KIBI;
 
 fn draw_handle_clipboard_change(ctx: &mut Context, state: &mut State) {
+    if let Some(backend) = &mut state.clipboard_backend {
+        backend.write(ctx.clipboard_ref().read());
+    }
+
     let data_len = ctx.clipboard_ref().read().len();
 
-    if state.osc_clipboard_always_send || data_len < LARGE_CLIPBOARD_THRESHOLD {
+    let over_limit = data_len > state.osc52.max_size || data_len >= SCRATCH_ARENA_CAPACITY / 4;
+
+    if !over_limit && (state.osc_clipboard_always_send || data_len < LARGE_CLIPBOARD_THRESHOLD) {
         ctx.clipboard_mut().mark_as_synchronized();
         state.osc_clipboard_sync = true;
         return;
     }
 
-    let over_limit = data_len >= SCRATCH_ARENA_CAPACITY / 4;
+    // There's nothing to decide, so there's no need to interrupt the user.
+    if over_limit {
+        let msg = loc(LocId::SuperLargeClipboardWarning).to_string();
+        toast_add(ctx, state, Severity::Warning, msg);
+        state.osc_clipboard_sync = false;
+        ctx.clipboard_mut().mark_as_synchronized();
+        return;
+    }
+
     let mut done = None;
 
     ctx.modal_begin("warning", loc(LocId::WarningDialogTitle));
@@ -440,30 +1318,22 @@ fn draw_handle_clipboard_change(ctx: &mut Context, state: &mut State) {
         ctx.block_begin("description");
         ctx.attr_padding(Rect::three(1, 2, 1));
 
-        if over_limit {
-            ctx.label("line1", loc(LocId::LargeClipboardWarningLine1));
-            ctx.attr_position(Position::Center);
-            ctx.label("line2", loc(LocId::SuperLargeClipboardWarning));
-            ctx.attr_position(Position::Center);
-        } else {
-            let label2 = {
-                let template = loc(LocId::LargeClipboardWarningLine2);
-                let size = arena_format!(ctx.arena(), "{}", MetricFormatter(data_len));
-
-                let mut label =
-                    ArenaString::with_capacity_in(template.len() + size.len(), ctx.arena());
-                label.push_str(template);
-                label.replace_once_in_place("{size}", &size);
-                label
-            };
+        let label2 = {
+            let template = loc(LocId::LargeClipboardWarningLine2);
+            let size = arena_format!(ctx.arena(), "{}", MetricFormatter(data_len));
 
-            ctx.label("line1", loc(LocId::LargeClipboardWarningLine1));
-            ctx.attr_position(Position::Center);
-            ctx.label("line2", &label2);
-            ctx.attr_position(Position::Center);
-            ctx.label("line3", loc(LocId::LargeClipboardWarningLine3));
-            ctx.attr_position(Position::Center);
-        }
+            let mut label = ArenaString::with_capacity_in(template.len() + size.len(), ctx.arena());
+            label.push_str(template);
+            label.replace_once_in_place("{size}", &size);
+            label
+        };
+
+        ctx.label("line1", loc(LocId::LargeClipboardWarningLine1));
+        ctx.attr_position(Position::Center);
+        ctx.label("line2", &label2);
+        ctx.attr_position(Position::Center);
+        ctx.label("line3", loc(LocId::LargeClipboardWarningLine3));
+        ctx.attr_position(Position::Center);
         ctx.block_end();
 
         ctx.table_begin("choices");
@@ -471,34 +1341,28 @@ fn draw_handle_clipboard_change(ctx: &mut Context, state: &mut State) {
         ctx.attr_padding(Rect::three(0, 2, 1));
         ctx.attr_position(Position::Center);
         ctx.table_set_cell_gap(Size { width: 2, height: 0 });
+        ctx.table_stack_below(COMPACT_WIDTH);
         {
             ctx.table_next_row();
             ctx.inherit_focus();
 
-            if over_limit {
-                if ctx.button("ok", loc(LocId::Ok), ButtonStyle::default()) {
-                    done = Some(true);
-                }
-                ctx.inherit_focus();
-            } else {
-                if ctx.button("always", loc(LocId::Always), ButtonStyle::default()) {
-                    state.osc_clipboard_always_send = true;
-                    done = Some(true);
-                }
+            if ctx.button("always", loc(LocId::Always), ButtonStyle::default()) {
+                state.osc_clipboard_always_send = true;
+                done = Some(true);
+            }
 
-                if ctx.button("yes", loc(LocId::Yes), ButtonStyle::default()) {
-                    done = Some(true);
-                }
-                if data_len < 10 * LARGE_CLIPBOARD_THRESHOLD {
-                    ctx.inherit_focus();
-                }
+            if ctx.button("yes", loc(LocId::Yes), ButtonStyle::default()) {
+                done = Some(true);
+            }
+            if data_len < 10 * LARGE_CLIPBOARD_THRESHOLD {
+                ctx.inherit_focus();
+            }
 
-                if ctx.button("no", loc(LocId::No), ButtonStyle::default()) {
-                    done = Some(false);
-                }
-                if data_len >= 10 * LARGE_CLIPBOARD_THRESHOLD {
-                    ctx.inherit_focus();
-                }
+            if ctx.button("no", loc(LocId::No), ButtonStyle::default()) {
+                done = Some(false);
+            }
+            if data_len >= 10 * LARGE_CLIPBOARD_THRESHOLD {
+                ctx.inherit_focus();
             }
         }
         ctx.table_end();
@@ -515,21 +1379,8 @@ fn draw_handle_clipboard_change(ctx: &mut Context, state: &mut State) {
 }
 
 #[cold]
-fn write_osc_clipboard(tui: &mut Tui, state: &mut State, output: &mut ArenaString) {
-    let clipboard = tui.clipboard_mut();
-    let data = clipboard.read();
-
-    if !data.is_empty() {
-        // Rust doubles the size of a string when it needs to grow it.
-        // If `data` is *really* large, this may then double
-        // the size of the `output` from e.g. 100MB to 200MB. Not good.
-        // We can avoid that by reserving the needed size in advance.
-        output.reserve_exact(base64::encode_len(data.len()) + 16);
-        output.push_str("\x1b]52;c;");
-        base64::encode(output, data);
-        output.push_str("\x1b\\");
-    }
-
+fn write_osc_clipboard(tui: &mut Tui, state: &mut State, output: &mut String) {
+    state.osc52.write_copy(output, tui.clipboard_ref().read());
     state.osc_clipboard_sync = false;
 }
 
@@ -540,7 +1391,7 @@ impl Drop for RestoreModes {
         // Same as in the beginning but in the reverse order.
         // It also includes DECSCUSR 0 to reset the cursor style and DECTCEM to show the cursor.
         // We specifically don't reset mode 1036, because most applications expect it to be set nowadays.
-        sys::write_stdout("\x1b[0 q\x1b[?25h\x1b]0;\x07\x1b[?1002;1006;2004l\x1b[?1049l");
+        sys::write_stdout("\x1b[0 q\x1b[?25h\x1b]0;\x07\x1b[?1000;1002;1004;1006;2004l\x1b[?1049l");
     }
 }
 
@@ -549,11 +1400,13 @@ fn setup_terminal(tui: &mut Tui, state: &mut State, vt_parser: &mut vt::Parser)
         // 1049: Alternative Screen Buffer
         //   I put the ASB switch in the beginning, just in case the terminal performs
         //   some additional state tracking beyond the modes we enable/disable.
+        // 1000: Normal Mouse Tracking (fallback for terminals without 1002)
         // 1002: Cell Motion Mouse Tracking
-        // 1006: SGR Mouse Mode
+        // 1004: Focus In/Out Reporting
+        // 1006: SGR Mouse Mode (without it, we fall back to the X10 encoding)
         // 2004: Bracketed Paste Mode
         // 1036: Xterm: "meta sends escape" (Alt keypresses should be encoded with ESC + char)
-        "\x1b[?1049h\x1b[?1002;1006;2004h\x1b[?1036h",
+        "\x1b[?1049h\x1b[?1000;1002;1004;1006;2004h\x1b[?1036h",
         // OSC 4 color table requests for indices 0 through 15 (base colors).
         "\x1b]4;0;?;1;?;2;?;3;?;4;?;5;?;6;?;7;?\x07",
         "\x1b]4;8;?;9;?;10;?;11;?;12;?;13;?;14;?;15;?\x07",
@@ -565,6 +1418,14 @@ fn setup_terminal(tui: &mut Tui, state: &mut State, vt_parser: &mut vt::Parser)
         // actual display width of the character and assigns it columns accordingly.
         // We detect it by writing the character and asking for the cursor position.
         "\r…\x1b[6n",
+        // Same for emoji sequences, which many terminals draw in parts: A ZWJ sequence
+        // (family: man, woman, girl), a skin tone modifier (thumbs up) and a flag (DE).
+        // See `EmojiWidths::from_probes`. They're drawn over by the first frame.
+        "\r\u{1F468}\u{200D}\u{1F469}\u{200D}\u{1F467}\x1b[6n",
+        "\r\u{1F44D}\u{1F3FD}\x1b[6n",
+        "\r\u{1F1E9}\u{1F1EA}\x1b[6n",
+        // XTGETTCAP for true color support. Terminals that don't know it ignore it.
+        "\x1bP+q5463;524742\x1b\\",
         // CSI c reports the terminal capabilities.
         // It also helps us to detect the end of the responses, because not all
         // terminals support the OSC queries, but all of them support CSI c.
@@ -576,6 +1437,11 @@ fn setup_terminal(tui: &mut Tui, state: &mut State, vt_parser: &mut vt::Parser)
     let mut indexed_colors = framebuffer::DEFAULT_THEME;
     let mut color_responses = 0;
     let mut ambiguous_width = 1;
+    // The widths of the emoji probes, in the same order.
+    let mut emoji_widths = [2; 3];
+    let mut cpr_responses = 0;
+    let mut dcs_buffer = String::new();
+    let mut tcap = None;
 
     while !done {
         let scratch = scratch_arena(None);
@@ -593,9 +1459,27 @@ fn setup_terminal(tui: &mut Tui, state: &mut State, vt_parser: &mut vt::Parser)
                 Token::Csi(csi) => match csi.final_byte {
                     'c' => done = true,
                     // CPR (Cursor Position Report) response.
-                    'R' => ambiguous_width = csi.params[1] as CoordType - 1,
+                    'R' => {
+                        let width = csi.params[1] as CoordType - 1;
+                        match cpr_responses {
+                            0 => ambiguous_width = width.clamp(1, 2),
+                            1..=3 => emoji_widths[cpr_responses - 1] = width,
+                            _ => {}
+                        }
+                        cpr_responses += 1;
+                    }
                     _ => {}
                 },
+                Token::Dcs { data, partial } => {
+                    dcs_buffer.push_str(data);
+                    if !partial {
+                        // Some terminals answer for each capability separately.
+                        if let Some(supported) = colors::parse_xtgettcap(&dcs_buffer) {
+                            tcap = Some(tcap == Some(true) || supported);
+                        }
+                        dcs_buffer.clear();
+                    }
+                }
                 Token::Osc { mut data, partial } => {
                     if partial {
                         osc_buffer.push_str(data);
@@ -652,18 +1536,100 @@ fn setup_terminal(tui: &mut Tui, state: &mut State, vt_parser: &mut vt::Parser)
         }
     }
 
-    if ambiguous_width == 2 {
-        unicode::setup_ambiguous_width(2);
+    // Detected even if the user chose one, as it's the "auto" in the settings.
+    state.detected_ambiguous_width = ambiguous_width;
+    let ambiguous_width = state.ambiguous_width.unwrap_or(ambiguous_width);
+    let [family, modifier, flag] = emoji_widths;
+    let emoji_widths = unicode::EmojiWidths::from_probes(family, modifier, flag);
+    if ambiguous_width != 1 || emoji_widths != unicode::EmojiWidths::default() {
+        unicode::setup_ambiguous_width(ambiguous_width);
+        unicode::setup_emoji_widths(emoji_widths);
         state.documents.reflow_all();
     }
 
+    // Detected even if the user chose one, as it's the "auto" in the settings.
+    let colorterm = env::var("COLORTERM").ok();
+    let term = env::var("TERM").ok();
+    let env = colors::ColorEnv {
+        colorterm: colorterm.as_deref(),
+        term: term.as_deref(),
+        termux: env::var_os("TERMUX_VERSION").is_some(),
+    };
+    state.detected_color_mode = colors::detect(tcap, &env);
+    tui.set_color_mode(state.color_mode.unwrap_or(state.detected_color_mode));
+
     if color_responses == indexed_colors.len() {
         tui.setup_indexed_colors(indexed_colors);
+        state.terminal_colors = Some(indexed_colors);
     }
 
     RestoreModes
 }
 
+/// Switches to the colors of the theme, or back to the terminal's colors.
+fn apply_theme(tui: &mut Tui, state: &mut State, theme: Option<&Theme>) {
+    match theme {
+        Some(theme) => {
+            tui.setup_indexed_colors(theme.colors);
+            tui.fill_default_colors();
+            tui.setup_named_colors(theme.named);
+        }
+        None => {
+            match state.terminal_colors {
+                Some(colors) => tui.setup_indexed_colors(colors),
+                None => {
+                    tui.setup_indexed_colors(framebuffer::DEFAULT_THEME);
+                    tui.fill_default_colors();
+                }
+            }
+            tui.setup_named_colors([None; NAMED_COLORS_COUNT]);
+        }
+    }
+    apply_palette(tui, state);
+    setup_ui_colors(tui, state);
+    // Cells whose colors changed but whose contents didn't would be skipped otherwise.
+    tui.invalidate();
+}
+
+/// Remaps the colors that the accessibility palette replaces.
+fn apply_palette(tui: &mut Tui, state: &State) {
+    for &(index, color) in state.palette.colors() {
+        tui.set_indexed_color(index, color);
+    }
+}
+
+/// Watches the files that [`settings::reload`] reads: The config, the keymap and the theme.
+fn watch_config(state: &mut State) {
+    let keymap = state.keymap_arg.clone().or_else(softkeys::default_keymap_path);
+    let theme = state.theme.as_deref().and_then(theme::find).map(|theme| theme.path);
+    let files = config::path().into_iter().chain(keymap).chain(theme).collect();
+    state.config_watcher.watch(files);
+}
+
+/// Derives the colors of the bars and dialogs from the palette, unless the theme sets them.
+fn setup_ui_colors(tui: &mut Tui, state: &mut State) {
+    state.menubar_color_bg = tui.named(NamedColor::Bar).unwrap_or_else(|| {
+        tui.indexed(IndexedColor::Background).oklab_blend(tui.indexed_alpha(
+            IndexedColor::BrightBlue,
+            1,
+            2,
+        ))
+    });
+    state.menubar_color_fg = tui.contrasted(state.menubar_color_bg);
+    let floater_bg = tui.named(NamedColor::Floater).unwrap_or_else(|| {
+        tui.indexed_alpha(IndexedColor::Background, 2, 3).oklab_blend(tui.indexed_alpha(
+            IndexedColor::Foreground,
+            1,
+            3,
+        ))
+    });
+    let floater_fg = tui.contrasted(floater_bg);
+    tui.set_floater_default_bg(floater_bg);
+    tui.set_floater_default_fg(floater_fg);
+    tui.set_modal_default_bg(floater_bg);
+    tui.set_modal_default_fg(floater_fg);
+}
+
 /// Strips all C0 control characters from the string and replaces them with "_".
 ///
 /// Jury is still out on whether this should also strip C1 control characters.
@@ -683,3 +1649,52 @@ fn sanitize_control_chars(text: &str) -> Cow<'_, str> {
         Cow::Borrowed(text)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_goto_arg() {
+        let p = |x, y| Some(Point { x, y });
+        let cases = [
+            ("+1", p(0, 0)),
+            ("+12", p(0, 11)),
+            ("+12:5", p(4, 11)),
+            ("+0", p(0, 0)),
+            ("+0:0", p(0, 0)),
+            ("+-3", p(0, 0)),
+            ("+-2147483648", p(0, 0)),
+            ("+1:-2147483648", p(0, 0)),
+            ("+5:", None),
+            ("+:5", None),
+            ("+", None),
+            ("+a", None),
+            ("12", None),
+        ];
+        for (arg, expected) in cases {
+            assert_eq!(parse_goto_arg(arg), expected, "{arg}");
+        }
+
+        let (min, max) = (CoordType::MIN, CoordType::MAX);
+        assert_eq!(parse_goto_arg(&format!("+{min}:{min}")), p(0, 0));
+        assert_eq!(parse_goto_arg(&format!("+{max}:{max}")), p(max - 1, max - 1));
+        assert_eq!(parse_goto_arg(&format!("+{max}0")), None);
+    }
+
+    #[test]
+    fn test_parse_encoding_arg() {
+        let cases = [
+            ("UTF-8", Some("UTF-8")),
+            ("utf-8 bom", Some("UTF-8 BOM")),
+            ("utf-16le", Some("UTF-16LE")),
+            ("UTF-32BE", Some("UTF-32BE")),
+            ("", None),
+            ("utf8x", None),
+            ("klingon", None),
+        ];
+        for (arg, expected) in cases {
+            assert_eq!(parse_encoding_arg(arg), expected, "{arg}");
+        }
+    }
+}
@@ -0,0 +1,671 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Lays out Markdown for View > Markdown Preview: Headings, emphasis, code,
+//! lists, quotes, tables and links. It's the common subset of CommonMark and
+//! GitHub's flavor that READMEs use, not all of it. What isn't understood shows
+//! up as it's written, so nothing gets lost.
+//!
+//! Each [`Row`] remembers the line of the document it came from, so that the
+//! preview and the editor can scroll to the same place.
+
+use std::mem;
+
+use edit::buffer::TextBuffer;
+use edit::helpers::CoordType;
+
+use crate::draw_statusbar::text_width;
+use crate::open_in_app::buffer_text;
+
+/// The state of the preview while it's shown.
+pub struct Preview {
+    /// The first row on screen.
+    pub scroll: usize,
+    /// The line of the document to scroll to once the rows are known.
+    pub goto_line: Option<usize>,
+    /// The lines of the document at the top and the bottom of the screen,
+    /// for the editor to scroll to.
+    pub top_line: usize,
+    pub bottom_line: usize,
+    rows: Vec<Row>,
+    /// The buffer, its generation and the width that `rows` were laid out for.
+    layout: Option<(*const TextBuffer, u32, CoordType)>,
+}
+
+impl Preview {
+    /// Starts out at the `line` of the document.
+    pub fn new(line: usize) -> Self {
+        Self {
+            scroll: 0,
+            goto_line: Some(line),
+            top_line: line,
+            bottom_line: line,
+            rows: Vec::new(),
+            layout: None,
+        }
+    }
+
+    /// Lays out `tb` again, if it changed since.
+    pub fn layout(&mut self, tb: &TextBuffer, width: CoordType) {
+        let layout = Some((tb as *const _, tb.generation(), width));
+        if self.layout != layout {
+            self.layout = layout;
+            self.rows = render(&String::from_utf8_lossy(&buffer_text(tb)), width);
+        }
+        if let Some(line) = self.goto_line.take() {
+            self.scroll = self.rows.iter().position(|row| row.line >= line).unwrap_or(0);
+        }
+        self.scroll = self.scroll.min(self.rows.len().saturating_sub(1));
+        self.top_line = self.rows.get(self.scroll).map_or(0, |row| row.line);
+    }
+
+    pub fn rows(&self) -> &[Row] {
+        &self.rows
+    }
+}
+
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub struct Style {
+    pub bold: bool,
+    pub italic: bool,
+    pub code: bool,
+    /// Bullets, quote bars, table borders and rules.
+    pub marker: bool,
+    /// 1 to 6 in headings, otherwise 0.
+    pub heading: u8,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Segment {
+    pub text: String,
+    pub style: Style,
+    pub link: Option<String>,
+}
+
+#[derive(Clone, Default, PartialEq, Eq, Debug)]
+pub struct Row {
+    /// The line of the document, 0-based.
+    pub line: usize,
+    pub segments: Vec<Segment>,
+    /// In a fenced code block, which gets a background.
+    pub code_block: bool,
+}
+
+impl Row {
+    fn new(line: usize) -> Self {
+        Self { line, ..Default::default() }
+    }
+
+    fn push(&mut self, text: &str, style: Style, link: Option<&str>) {
+        if text.is_empty() {
+            return;
+        }
+        match self.segments.last_mut() {
+            Some(last) if last.style == style && last.link.as_deref() == link => {
+                last.text.push_str(text)
+            }
+            _ => self.segments.push(Segment {
+                text: text.to_string(),
+                style,
+                link: link.map(str::to_string),
+            }),
+        }
+    }
+
+    fn is_blank(&self) -> bool {
+        self.segments.iter().all(|s| s.text.trim().is_empty())
+    }
+}
+
+const MARKER: Style = Style { bold: false, italic: false, code: false, marker: true, heading: 0 };
+
+/// Lays out the Markdown `text` in rows of at most `width` columns.
+/// Code blocks and tables aren't wrapped and may be wider.
+pub fn render(text: &str, width: CoordType) -> Vec<Row> {
+    let lines: Vec<&str> = text.lines().collect();
+    let mut rows = Vec::new();
+    render_lines(&lines, 0, width.max(10), &mut rows);
+    rows
+}
+
+fn render_lines(lines: &[&str], first_line: usize, width: CoordType, rows: &mut Vec<Row>) {
+    let mut i = 0;
+
+    while i < lines.len() {
+        let line = lines[i];
+        let trimmed = line.trim_start();
+        let y = first_line + i;
+
+        if trimmed.is_empty() {
+            // Blocks are a blank row apart, however many blank lines there are.
+            if rows.last().is_some_and(|row: &Row| !row.is_blank()) {
+                rows.push(Row::new(y));
+            }
+            i += 1;
+        } else if let Some(fence) = fence(trimmed) {
+            i += 1;
+            while i < lines.len() && !lines[i].trim_start().starts_with(fence) {
+                let mut row = Row::new(first_line + i);
+                row.push(&format!(" {} ", lines[i].replace('\t', "    ")), Style::default(), None);
+                row.code_block = true;
+                rows.push(row);
+                i += 1;
+            }
+            // The closing fence.
+            i += 1;
+        } else if let Some((level, title)) = heading(trimmed) {
+            let style = Style { heading: level, bold: true, ..Default::default() };
+            wrap(&inline(title, style), width, &[], y, rows);
+            i += 1;
+        } else if is_rule(trimmed) {
+            let mut row = Row::new(y);
+            row.push(&"─".repeat(width as usize), MARKER, None);
+            rows.push(row);
+            i += 1;
+        } else if is_table(lines, i) {
+            let end = i + lines[i..].iter().take_while(|l| l.trim_start().starts_with('|')).count();
+            table(&lines[i..end], y, rows);
+            i = end;
+        } else if trimmed.starts_with('>') {
+            let end = i + lines[i..].iter().take_while(|l| l.trim_start().starts_with('>')).count();
+            let inner: Vec<&str> = lines[i..end]
+                .iter()
+                .map(|l| {
+                    let l = &l.trim_start()[1..];
+                    l.strip_prefix(' ').unwrap_or(l)
+                })
+                .collect();
+            let mut quoted = Vec::new();
+            render_lines(&inner, y, width - 2, &mut quoted);
+            for mut row in quoted {
+                row.segments
+                    .insert(0, Segment { text: "│ ".to_string(), style: MARKER, link: None });
+                rows.push(row);
+            }
+            i = end;
+        } else if let Some((indent, bullet, rest)) = list_item(line) {
+            let end = i + 1 + paragraph_len(&lines[i + 1..]);
+            let text = join(rest, &lines[i + 1..end]);
+            let pad = " ".repeat(indent / 2 * 2);
+            let prefix = format!("{pad}{bullet} ");
+            let hanging = " ".repeat(prefix.chars().count());
+            let mut segments = vec![Segment { text: prefix, style: MARKER, link: None }];
+            segments.extend(inline(&text, Style::default()));
+            wrap(&segments, width, &[hanging.as_str()], y, rows);
+            i = end;
+        } else {
+            let len = 1 + paragraph_len(&lines[i + 1..]);
+            // A setext heading is underlined with = or -.
+            let underline = lines.get(i + 1).map(|l| l.trim()).unwrap_or("");
+            if len >= 2 && !underline.is_empty() && underline.bytes().all(|b| b == b'=') {
+                let style = Style { heading: 1, bold: true, ..Default::default() };
+                wrap(&inline(trimmed, style), width, &[], y, rows);
+                i += 2;
+                continue;
+            }
+            if lines.get(i + 1).is_some_and(|l| {
+                let l = l.trim();
+                l.len() >= 2 && l.bytes().all(|b| b == b'-')
+            }) {
+                let style = Style { heading: 2, bold: true, ..Default::default() };
+                wrap(&inline(trimmed, style), width, &[], y, rows);
+                i += 2;
+                continue;
+            }
+            let text = join(trimmed, &lines[i + 1..i + len]);
+            wrap(&inline(&text, Style::default()), width, &[], y, rows);
+            i += len;
+        }
+    }
+}
+
+/// The number of `lines` that continue a paragraph, up to a blank line or another block.
+fn paragraph_len(lines: &[&str]) -> usize {
+    lines
+        .iter()
+        .enumerate()
+        .take_while(|&(i, line)| {
+            let trimmed = line.trim_start();
+            !trimmed.is_empty()
+                && fence(trimmed).is_none()
+                && heading(trimmed).is_none()
+                && !trimmed.starts_with('>')
+                && !(trimmed.starts_with('|') && is_table(lines, i))
+                && list_item(line).is_none()
+                && !(is_rule(trimmed) && trimmed.starts_with(['*', '_']))
+        })
+        .count()
+}
+
+fn join(first: &str, rest: &[&str]) -> String {
+    let mut text = first.trim().to_string();
+    for line in rest {
+        text.push(' ');
+        text.push_str(line.trim());
+    }
+    text
+}
+
+/// The fence that a line of ``` or ~~~ opens a code block with.
+fn fence(trimmed: &str) -> Option<&'static str> {
+    ["```", "~~~"].into_iter().find(|&fence| trimmed.starts_with(fence))
+}
+
+/// `## Title` as `(2, "Title")`. Closing #s are dropped.
+fn heading(trimmed: &str) -> Option<(u8, &str)> {
+    let level = trimmed.bytes().take_while(|&b| b == b'#').count();
+    let rest = &trimmed[level..];
+    if !(1..=6).contains(&level) || !(rest.is_empty() || rest.starts_with(' ')) {
+        return None;
+    }
+    Some((level as u8, rest.trim().trim_end_matches('#').trim_end()))
+}
+
+/// `---`, `***` or `___`, with spaces in between or not.
+fn is_rule(trimmed: &str) -> bool {
+    let chars: Vec<char> = trimmed.chars().filter(|c| !c.is_whitespace()).collect();
+    chars.len() >= 3 && ['-', '*', '_'].iter().any(|&c| chars.iter().all(|&x| x == c))
+}
+
+/// A table starts with a row of cells, followed by one like `|---|:-:|`.
+fn is_table(lines: &[&str], i: usize) -> bool {
+    lines[i].trim_start().starts_with('|')
+        && lines.get(i + 1).is_some_and(|l| {
+            let l = l.trim();
+            l.starts_with('|') && l.contains('-') && l.chars().all(|c| "|-: ".contains(c))
+        })
+}
+
+/// `  - item`, `1. item` or `- [x] item` as `(indent, bullet, text)`.
+fn list_item(line: &str) -> Option<(usize, String, &str)> {
+    let trimmed = line.trim_start();
+    let indent = line.len() - trimmed.len();
+
+    let (bullet, rest) = if let Some(rest) = trimmed.strip_prefix(['-', '*', '+']) {
+        ("•".to_string(), rest)
+    } else {
+        let digits = trimmed.bytes().take_while(u8::is_ascii_digit).count();
+        let rest =
+            trimmed[digits..].strip_prefix(['.', ')']).filter(|_| (1..=9).contains(&digits))?;
+        (format!("{}.", &trimmed[..digits]), rest)
+    };
+    let rest = rest.strip_prefix(' ')?;
+
+    let task = [("[ ] ", "☐"), ("[x] ", "☑"), ("[X] ", "☑")];
+    match task.iter().find(|(prefix, _)| rest.starts_with(prefix)) {
+        Some(&(prefix, check)) => {
+            Some((indent, format!("{bullet} {check}"), &rest[prefix.len()..]))
+        }
+        None => Some((indent, bullet, rest)),
+    }
+}
+
+/// Splits the text into runs of the same style. `base` is the style around it.
+fn inline(text: &str, base: Style) -> Vec<Segment> {
+    let mut row = Row::default();
+    inline_into(&mut row, text, base, None);
+    row.segments
+}
+
+fn inline_into(row: &mut Row, text: &str, base: Style, link: Option<&str>) {
+    let bytes = text.as_bytes();
+    let mut style = base;
+    let mut i = 0;
+    let mut plain = 0;
+
+    while i < bytes.len() {
+        let b = bytes[i];
+        let special = match b {
+            b'\\' => bytes.get(i + 1).is_some_and(u8::is_ascii_punctuation),
+            b'`' | b'*' | b'_' | b'[' | b'<' | b'!' => true,
+            _ => false,
+        };
+        if !special {
+            i += 1;
+            continue;
+        }
+        row.push(&text[plain..i], style, link);
+        plain = i;
+
+        match b {
+            b'\\' => {
+                plain = i + 1;
+                i += 2;
+            }
+            b'`' => {
+                let run = bytes[i..].iter().take_while(|&&c| c == b'`').count();
+                let ticks = &text[i..i + run];
+                match text[i + run..].find(ticks) {
+                    Some(len) => {
+                        let code = &text[i + run..i + run + len];
+                        let code = if code.len() > 2 && code.starts_with(' ') && code.ends_with(' ')
+                        {
+                            &code[1..code.len() - 1]
+                        } else {
+                            code
+                        };
+                        row.push(code, Style { code: true, ..style }, link);
+                        i += 2 * run + len;
+                        plain = i;
+                    }
+                    None => i += run,
+                }
+            }
+            b'*' | b'_' => {
+                let run = bytes[i..].iter().take_while(|&&c| c == b).count();
+                let delim = &text[i..i + run];
+                let before = text[..i].chars().next_back();
+                let after = text[i + run..].chars().next();
+                let opening = after.is_some_and(|c| !c.is_whitespace());
+                let closing = before.is_some_and(|c| !c.is_whitespace());
+                // snake_case_words stay as they are.
+                let intraword = b == b'_'
+                    && before.is_some_and(char::is_alphanumeric)
+                    && after.is_some_and(char::is_alphanumeric);
+                let toggles_bold = run >= 2;
+                let toggles_italic = run % 2 == 1;
+                let active = (toggles_bold && style.bold != base.bold)
+                    || (toggles_italic && style.italic != base.italic);
+
+                if run <= 3
+                    && !intraword
+                    && ((active && closing)
+                        || (!active && opening && text[i + run..].contains(delim)))
+                {
+                    if toggles_bold {
+                        style.bold = !style.bold;
+                    }
+                    if toggles_italic {
+                        style.italic = !style.italic;
+                    }
+                    plain = i + run;
+                }
+                i += run;
+            }
+            b'[' | b'!' => {
+                let image = b == b'!';
+                let start = i + image as usize;
+                match (bytes.get(start) == Some(&b'['), link_at(&text[start..])) {
+                    (true, Some((label, url, len))) => {
+                        let url = (!url.is_empty()).then_some(url);
+                        if image {
+                            row.push("🖼 ", MARKER, url.or(link));
+                        }
+                        inline_into(row, label, style, url.or(link));
+                        i = start + len;
+                        plain = i;
+                    }
+                    _ => i += 1,
+                }
+            }
+            b'<' => {
+                let end = text[i..].find('>').map(|len| i + len);
+                match end.map(|end| &text[i + 1..end]) {
+                    Some(url) if is_url(url) => {
+                        row.push(url, style, Some(url));
+                        i += url.len() + 2;
+                        plain = i;
+                    }
+                    _ => i += 1,
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
+    row.push(&text[plain..], style, link);
+}
+
+/// `[label](url "title")` as `(label, url, len)`.
+fn link_at(text: &str) -> Option<(&str, &str, usize)> {
+    let mut depth = 0;
+    let close = text.char_indices().find_map(|(i, c)| {
+        match c {
+            '[' => depth += 1,
+            ']' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => {}
+        }
+        None
+    })?;
+    let rest = text[close + 1..].strip_prefix('(')?;
+    let end = rest.find(')')?;
+    let target = rest[..end].trim();
+    let url = target.split_once(' ').map_or(target, |(url, _title)| url);
+    let url = url.trim_start_matches('<').trim_end_matches('>');
+    Some((&text[1..close], url, close + 2 + end + 1))
+}
+
+fn is_url(s: &str) -> bool {
+    !s.contains(char::is_whitespace)
+        && (s.starts_with("http://") || s.starts_with("https://") || s.starts_with("mailto:"))
+}
+
+/// Wraps the `segments` at spaces into rows of `width`. The rows after the first
+/// start with `hanging`, e.g. to line up with the text of a list item.
+fn wrap(
+    segments: &[Segment],
+    width: CoordType,
+    hanging: &[&str],
+    line: usize,
+    rows: &mut Vec<Row>,
+) {
+    let mut row = Row::new(line);
+    let mut row_width = 0;
+    // Whether the row has nothing but the hanging indent yet.
+    let mut fresh = false;
+
+    for segment in segments {
+        let link = segment.link.as_deref();
+        // Markers like bullets are never broken up.
+        let words: Vec<&str> = if segment.style.marker {
+            vec![segment.text.as_str()]
+        } else {
+            segment.text.split_inclusive(' ').collect()
+        };
+
+        for word in words {
+            if row_width > 0 && !fresh && row_width + text_width(word.trim_end()) > width {
+                rows.push(trim_end(mem::replace(&mut row, Row::new(line))));
+                row_width = 0;
+                for pad in hanging {
+                    row.push(pad, MARKER, None);
+                    row_width += text_width(pad);
+                }
+                fresh = true;
+            }
+            let word = if fresh { word.trim_start() } else { word };
+            if !word.is_empty() {
+                row.push(word, segment.style, link);
+                row_width += text_width(word);
+                fresh = false;
+            }
+        }
+    }
+    rows.push(trim_end(row));
+}
+
+fn trim_end(mut row: Row) -> Row {
+    if let Some(last) = row.segments.last_mut()
+        && !last.style.marker
+    {
+        last.text.truncate(last.text.trim_end().len());
+    }
+    row
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Align {
+    Left,
+    Center,
+    Right,
+}
+
+fn table(lines: &[&str], first_line: usize, rows: &mut Vec<Row>) {
+    let cells = |line: &str| -> Vec<String> {
+        let line = line.trim();
+        let line = line.strip_prefix('|').unwrap_or(line);
+        let line = line.strip_suffix('|').unwrap_or(line);
+        line.split('|').map(|cell| cell.trim().to_string()).collect()
+    };
+
+    let aligns: Vec<Align> = cells(lines[1])
+        .iter()
+        .map(|spec| match (spec.starts_with(':'), spec.ends_with(':')) {
+            (true, true) => Align::Center,
+            (false, true) => Align::Right,
+            _ => Align::Left,
+        })
+        .collect();
+    let body: Vec<(usize, Vec<Vec<Segment>>)> = lines
+        .iter()
+        .enumerate()
+        .filter(|&(i, _)| i != 1)
+        .map(|(i, line)| {
+            let style = Style { bold: i == 0, ..Default::default() };
+            (i, cells(line).iter().map(|cell| inline(cell, style)).collect())
+        })
+        .collect();
+
+    let columns = aligns.len();
+    let mut widths = vec![0; columns];
+    for (_, cells) in &body {
+        for (width, cell) in widths.iter_mut().zip(cells) {
+            *width = (*width).max(cell.iter().map(|s| text_width(&s.text)).sum());
+        }
+    }
+
+    for (i, cells) in &body {
+        let mut row = Row::new(first_line + i);
+        for (c, &width) in widths.iter().enumerate() {
+            if c > 0 {
+                row.push(" │ ", MARKER, None);
+            }
+            let cell = cells.get(c).map_or(&[][..], Vec::as_slice);
+            let pad =
+                (width - cell.iter().map(|s| text_width(&s.text)).sum::<CoordType>()) as usize;
+            let (left, right) = match aligns[c] {
+                Align::Left => (0, pad),
+                Align::Center => (pad / 2, pad - pad / 2),
+                Align::Right => (pad, 0),
+            };
+            row.push(&" ".repeat(left), Style::default(), None);
+            for segment in cell {
+                row.push(&segment.text, segment.style, segment.link.as_deref());
+            }
+            row.push(&" ".repeat(right), Style::default(), None);
+        }
+        rows.push(row);
+
+        if *i == 0 {
+            let mut rule = Row::new(first_line + 1);
+            let parts: Vec<String> = widths.iter().map(|&w| "─".repeat(w as usize)).collect();
+            rule.push(&parts.join("─┼─"), MARKER, None);
+            rows.push(rule);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The rows as text, with `*` around bold and `_` around italic text.
+    fn text(rows: &[Row]) -> Vec<String> {
+        rows.iter()
+            .map(|row| {
+                row.segments
+                    .iter()
+                    .map(|s| match (s.style.bold && s.style.heading == 0, s.style.italic) {
+                        (true, _) => format!("*{}*", s.text),
+                        (_, true) => format!("_{}_", s.text),
+                        _ => s.text.clone(),
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_blocks() {
+        let md = "# Title #\n\nSome **bold** and _it_\ntext.\n\n\n- one\n  - two\n\n> quoted\n\n```\nlet x;\n```\n---\n";
+        let rows = render(md, 40);
+        assert_eq!(
+            text(&rows),
+            [
+                "Title",
+                "",
+                "Some *bold* and _it_ text.",
+                "",
+                "• one",
+                "  • two",
+                "",
+                "│ quoted",
+                "",
+                " let x; ",
+                &"─".repeat(40),
+            ]
+        );
+        assert_eq!(rows[0].segments[0].style.heading, 1);
+        assert!(rows[9].code_block);
+        assert_eq!(
+            rows.iter().map(|r| r.line).collect::<Vec<_>>(),
+            [0, 1, 2, 4, 6, 7, 8, 9, 10, 12, 14]
+        );
+    }
+
+    #[test]
+    fn test_wrap() {
+        let rows = render("- a list item that wraps\n", 14);
+        assert_eq!(text(&rows), ["• a list item", "  that wraps"]);
+        let rows = render("Title\n=====\nword word word\n", 10);
+        assert_eq!(text(&rows), ["Title", "word word", "word"]);
+    }
+
+    #[test]
+    fn test_inline() {
+        let segments = inline(
+            r#"a `x*y` [link *it*](http://a.b "t") snake_case \* <https://c.d>"#,
+            Style::default(),
+        );
+        let texts: Vec<_> = segments.iter().map(|s| (s.text.as_str(), s.link.as_deref())).collect();
+        assert_eq!(
+            texts,
+            [
+                ("a ", None),
+                ("x*y", None),
+                (" ", None),
+                ("link ", Some("http://a.b")),
+                ("it", Some("http://a.b")),
+                (" snake_case * ", None),
+                ("https://c.d", Some("https://c.d")),
+            ]
+        );
+        assert!(segments[1].style.code);
+        assert!(segments[4].style.italic);
+        // Unclosed emphasis is left alone.
+        assert_eq!(
+            text(&[Row {
+                segments: inline("2 * 3 and *x", Style::default()),
+                ..Default::default()
+            }]),
+            ["2 * 3 and *x"]
+        );
+    }
+
+    #[test]
+    fn test_table() {
+        let rows = render("| a | long |\n|--:|:-:|\n| 100 | x |\n", 40);
+        assert_eq!(text(&rows), ["  *a* │ *long*", "────┼─────", "100 │  x  "]);
+        assert_eq!(rows.iter().map(|r| r.line).collect::<Vec<_>>(), [0, 1, 2]);
+    }
+
+    #[test]
+    fn test_task_list() {
+        let rows = render("- [x] done\n3. three\n", 40);
+        assert_eq!(text(&rows), ["• ☑ done", "3. three"]);
+    }
+}
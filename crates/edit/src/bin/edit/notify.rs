@@ -0,0 +1,128 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Tells the user about finished background work while they're in another app.
+//!
+//! In Termux, `termux-notification` posts an Android notification. Tapping
+//! it brings Termux back to the front, where the results the notification is
+//! about are shown. Returning to the editor by any other means removes it.
+//!
+//! Input is only read between the slices of a task (see [`crate::task`]), so we
+//! may only learn that the user switched apps from the focus event that arrives
+//! right after it finished. Such late focus losses still get the notification.
+
+use std::env;
+use std::process::Command;
+use std::time::{Duration, Instant};
+
+use crate::clipboard_backend::{find_in_path, spawn_detached};
+
+/// Replaces the notification posted by an earlier [`Notifier::finished`] call.
+const NOTIFICATION_ID: &str = "edit";
+/// Brings Termux to the front when the notification is tapped.
+const TAP_ACTION: &str = "am start --user current -n com.termux/com.termux.app.TermuxActivity";
+/// Focus losses within this long after a task finished happened while it ran.
+const LATE_FOCUS_LOSS: Duration = Duration::from_secs(1);
+
+/// What to show once the user returns.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Panel {
+    ProjectReplace,
+    /// The error log shows by itself as long as there are errors.
+    ErrorLog,
+}
+
+pub struct Notification {
+    pub title: String,
+    pub content: String,
+    pub panel: Option<Panel>,
+}
+
+pub struct Notifier {
+    available: bool,
+    /// A notification for a task that finished while the window was focused.
+    recent: Option<(Instant, Notification)>,
+    /// Whether a notification is posted, and the panel it's about.
+    posted: Option<Option<Panel>>,
+}
+
+impl Notifier {
+    pub fn new() -> Self {
+        Self {
+            available: env::var_os("TERMUX_VERSION").is_some()
+                && find_in_path("termux-notification"),
+            recent: None,
+            posted: None,
+        }
+    }
+
+    /// Call this when a task finished. Posts the notification if the user isn't looking.
+    pub fn finished(&mut self, window_focused: bool, notification: Notification) {
+        if !self.available {
+            return;
+        }
+        if window_focused {
+            self.recent = Some((Instant::now(), notification));
+        } else {
+            self.post(notification);
+        }
+    }
+
+    pub fn focus_lost(&mut self) {
+        if let Some((finished, notification)) = self.recent.take()
+            && finished.elapsed() < LATE_FOCUS_LOSS
+        {
+            self.post(notification);
+        }
+    }
+
+    /// Removes the notification and returns the panel to bring up, if any.
+    pub fn focus_gained(&mut self) -> Option<Panel> {
+        self.recent = None;
+        let panel = self.posted.take()?;
+        spawn_detached(Command::new("termux-notification-remove").arg(NOTIFICATION_ID));
+        panel
+    }
+
+    fn post(&mut self, notification: Notification) {
+        spawn_detached(Command::new("termux-notification").args([
+            "--id",
+            NOTIFICATION_ID,
+            "--title",
+            &notification.title,
+            "--content",
+            &notification.content,
+            "--action",
+            TAP_ACTION,
+        ]));
+        self.posted = Some(notification.panel);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn notification(panel: Option<Panel>) -> Notification {
+        Notification { title: String::new(), content: String::new(), panel }
+    }
+
+    #[test]
+    fn test_late_focus_loss() {
+        // Only the bookkeeping is tested. Outside of Termux, posting quietly fails.
+        let mut notifier = Notifier { available: true, recent: None, posted: None };
+
+        notifier.finished(true, notification(Some(Panel::ProjectReplace)));
+        notifier.focus_lost();
+        assert_eq!(notifier.focus_gained(), Some(Panel::ProjectReplace));
+        assert_eq!(notifier.focus_gained(), None);
+
+        notifier.finished(true, notification(Some(Panel::ErrorLog)));
+        notifier.recent.as_mut().unwrap().0 -= LATE_FOCUS_LOSS;
+        notifier.focus_lost();
+        assert_eq!(notifier.focus_gained(), None);
+
+        notifier.finished(false, notification(Some(Panel::ErrorLog)));
+        assert_eq!(notifier.focus_gained(), Some(Panel::ErrorLog));
+    }
+}
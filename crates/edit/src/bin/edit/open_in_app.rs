@@ -0,0 +1,241 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Hands files, URLs and text to Android apps via `termux-open`,
+//! `termux-open-url` and `termux-share`.
+//!
+//! This allows previewing an HTML, Markdown or image file in a real app
+//! straight from the editor. Outside of Termux the commands don't exist,
+//! and opening fails with the usual "not found" error. URLs are the exception:
+//! They go to `xdg-open` instead, so that they open in the desktop's browser.
+
+use std::ffi::OsStr;
+use std::io::Write as _;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::thread;
+
+use edit::apperr;
+use edit::buffer::TextBuffer;
+
+use crate::clipboard_backend;
+
+/// How far to look around the cursor for a URL.
+const MAX_URL_LEN: usize = 2048;
+
+/// Guesses the MIME type from the file extension, so that Android offers fitting apps.
+pub fn guess_mime_type(path: &Path) -> Option<&'static str> {
+    let ext = path.extension()?.to_str()?.to_ascii_lowercase();
+    Some(match ext.as_str() {
+        "htm" | "html" | "xhtml" => "text/html",
+        "md" | "markdown" => "text/markdown",
+        "txt" | "log" => "text/plain",
+        "css" => "text/css",
+        "csv" => "text/csv",
+        "js" => "text/javascript",
+        "json" => "application/json",
+        "xml" => "text/xml",
+        "pdf" => "application/pdf",
+        "zip" => "application/zip",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "svg" => "image/svg+xml",
+        "bmp" => "image/bmp",
+        "mp3" => "audio/mpeg",
+        "ogg" => "audio/ogg",
+        "wav" => "audio/wav",
+        "mp4" => "video/mp4",
+        "webm" => "video/webm",
+        _ => return None,
+    })
+}
+
+/// Returns the URL under the cursor, if any.
+pub fn url_at_cursor(tb: &TextBuffer) -> Option<String> {
+    let (line, pos) = line_at_cursor(tb);
+    find_url(&line, pos).map(str::to_string)
+}
+
+/// Returns the line the cursor is on (or up to [`MAX_URL_LEN`] bytes of it
+/// to either side) and the cursor's byte offset in it.
+pub fn line_at_cursor(tb: &TextBuffer) -> (String, usize) {
+    let offset = tb.cursor_offset();
+
+    // Collect the text around the cursor up to the nearest line breaks.
+    let mut before = Vec::new();
+    let mut off = offset;
+    while off > 0 && before.len() < MAX_URL_LEN {
+        let chunk = tb.read_backward(off);
+        if chunk.is_empty() {
+            break;
+        }
+        let start = chunk.iter().rposition(|&b| b == b'\n').map_or(0, |i| i + 1);
+        before.splice(0..0, chunk[start..].iter().copied());
+        if start != 0 {
+            break;
+        }
+        off -= chunk.len();
+    }
+
+    let mut after = Vec::new();
+    let mut off = offset;
+    while off < tb.text_length() && after.len() < MAX_URL_LEN {
+        let chunk = tb.read_forward(off);
+        if chunk.is_empty() {
+            break;
+        }
+        let end = chunk.iter().position(|&b| b == b'\n').unwrap_or(chunk.len());
+        after.extend_from_slice(&chunk[..end]);
+        if end != chunk.len() {
+            break;
+        }
+        off += chunk.len();
+    }
+
+    let pos = before.len();
+    before.extend_from_slice(&after);
+    let line = String::from_utf8_lossy(&before).into_owned();
+    // Invalid UTF-8 before the cursor shifts the offset, which mustn't end up within a character.
+    let pos = if line.is_char_boundary(pos) { pos } else { 0 };
+    (line, pos)
+}
+
+/// Finds the URL in `line` that contains the byte offset `pos`.
+pub fn find_url(line: &str, pos: usize) -> Option<&str> {
+    let mut search = 0;
+    while let Some(i) = line[search..].find("://") {
+        let scheme_end = search + i;
+        search = scheme_end + 3;
+
+        let start = line[..scheme_end]
+            .rfind(|c: char| !c.is_ascii_alphanumeric() && !matches!(c, '+' | '-' | '.'))
+            .map_or(0, |i| i + 1);
+        let end = line[scheme_end..]
+            .find(|c: char| c.is_whitespace() || matches!(c, '"' | '\'' | '<' | '>' | '`'))
+            .map_or(line.len(), |i| scheme_end + i);
+        // Punctuation at the end is more likely part of the prose than the URL,
+        // except for closing parentheses that belong to an opening one in the URL.
+        let mut url = &line[start..end];
+        while let Some(last) = url.chars().next_back() {
+            let strip = match last {
+                '.' | ',' | ';' | ':' | '!' | '?' => true,
+                ')' => url.matches('(').count() < url.matches(')').count(),
+                ']' => url.matches('[').count() < url.matches(']').count(),
+                _ => false,
+            };
+            if !strip {
+                break;
+            }
+            url = &url[..url.len() - 1];
+        }
+
+        if start == scheme_end || url.len() <= scheme_end - start + 3 {
+            continue;
+        }
+        if (start..=start + url.len()).contains(&pos) {
+            return Some(url);
+        }
+    }
+    None
+}
+
+/// Returns the entire contents of the buffer.
+pub fn buffer_text(tb: &TextBuffer) -> Vec<u8> {
+    let mut text = Vec::with_capacity(tb.text_length());
+    while text.len() < tb.text_length() {
+        text.extend_from_slice(tb.read_forward(text.len()));
+    }
+    text
+}
+
+pub fn open_file(path: &Path) -> apperr::Result<()> {
+    let mut cmd = Command::new("termux-open");
+    if let Some(mime) = guess_mime_type(path) {
+        cmd.arg("--content-type").arg(mime);
+    }
+    spawn(cmd.arg(path), None)
+}
+
+pub fn open_url(url: &str) -> apperr::Result<()> {
+    let program = if clipboard_backend::find_in_path("termux-open-url") {
+        "termux-open-url"
+    } else if cfg!(target_os = "macos") {
+        "open"
+    } else {
+        "xdg-open"
+    };
+    spawn(Command::new(program).arg(OsStr::new(url)), None)
+}
+
+/// Whether `termux-share` is installed. It's part of the optional `termux-api` package.
+pub fn can_share() -> bool {
+    clipboard_backend::find_in_path("termux-share")
+}
+
+/// Opens the share sheet for `text`, e.g. to send it via a messenger.
+pub fn share_text(text: Vec<u8>, subject: &str) -> apperr::Result<()> {
+    let mut cmd = Command::new("termux-share");
+    cmd.args(["-a", "send", "-c", "text/plain"]);
+    if !subject.is_empty() {
+        cmd.arg("-t").arg(subject);
+    }
+    spawn(&mut cmd, Some(text))
+}
+
+/// Opens the share sheet for the file at `path`, e.g. to attach it to an email.
+pub fn share_file(path: &Path, subject: &str) -> apperr::Result<()> {
+    let mut cmd = Command::new("termux-share");
+    cmd.args(["-a", "send"]);
+    if let Some(mime) = guess_mime_type(path) {
+        cmd.arg("-c").arg(mime);
+    }
+    if !subject.is_empty() {
+        cmd.arg("-t").arg(subject);
+    }
+    spawn(cmd.arg(path), None)
+}
+
+/// Starts `cmd` without waiting for it and feeds it `input`, if any.
+/// Its output would mess up the terminal, so it's discarded.
+fn spawn(cmd: &mut Command, input: Option<Vec<u8>>) -> apperr::Result<()> {
+    let stdin = if input.is_some() { Stdio::piped() } else { Stdio::null() };
+    let mut child = cmd.stdin(stdin).stdout(Stdio::null()).stderr(Stdio::null()).spawn()?;
+    let stdin = child.stdin.take();
+
+    // Reap it once it exits, so that it doesn't linger as a zombie.
+    thread::spawn(move || {
+        if let (Some(mut stdin), Some(input)) = (stdin, input) {
+            _ = stdin.write_all(&input);
+        }
+        _ = child.wait();
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_url() {
+        let line = "see https://example.com/a_(b), or (http://x.org).";
+        assert_eq!(find_url(line, 0), None);
+        assert_eq!(find_url(line, 4), Some("https://example.com/a_(b)"));
+        assert_eq!(find_url(line, 12), Some("https://example.com/a_(b)"));
+        assert_eq!(find_url(line, 29), Some("https://example.com/a_(b)"));
+        assert_eq!(find_url(line, 40), Some("http://x.org"));
+        assert_eq!(find_url("no scheme ://here", 12), None);
+        assert_eq!(find_url("empty https:// url", 8), None);
+    }
+
+    #[test]
+    fn test_guess_mime_type() {
+        assert_eq!(guess_mime_type(Path::new("/sdcard/index.HTML")), Some("text/html"));
+        assert_eq!(guess_mime_type(Path::new("README.md")), Some("text/markdown"));
+        assert_eq!(guess_mime_type(Path::new("photo.jpeg")), Some("image/jpeg"));
+        assert_eq!(guess_mime_type(Path::new("Makefile")), None);
+        assert_eq!(guess_mime_type(Path::new("main.rs")), None);
+    }
+}
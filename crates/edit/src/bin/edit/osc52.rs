@@ -0,0 +1,187 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Copy and paste through the terminal with OSC 52.
+//!
+//! This is what makes the clipboard work over SSH: the terminal on the other
+//! end (e.g. Termux on a phone) receives the sequence and puts the selection
+//! on its own clipboard. Reading the clipboard back is opt-in (`--osc52-paste`),
+//! because most terminals either don't support it or ask the user every time.
+//!
+//! tmux and GNU screen swallow OSC 52, so inside them the sequence is wrapped
+//! in their DCS passthrough. For tmux this requires `allow-passthrough on`.
+
+use std::env;
+
+use edit::base64;
+use edit::helpers::MEBI;
+
+/// Copies larger than this aren't sent to the terminal by default.
+/// Many terminals silently drop sequences that are much longer than this.
+pub const DEFAULT_MAX_SIZE: usize = 8 * MEBI;
+
+/// GNU screen limits the length of DCS strings, so the sequence is split into chunks.
+const SCREEN_CHUNK_SIZE: usize = 76;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Passthrough {
+    None,
+    Tmux,
+    Screen,
+}
+
+impl Passthrough {
+    /// Detects the terminal multiplexer we're running in, if any.
+    pub fn detect() -> Self {
+        if env::var_os("TMUX").is_some() {
+            Self::Tmux
+        } else if env::var_os("STY").is_some() {
+            Self::Screen
+        } else {
+            Self::None
+        }
+    }
+}
+
+pub struct Osc52 {
+    pub passthrough: Passthrough,
+    /// Copies larger than this many bytes only go to the internal clipboard.
+    pub max_size: usize,
+    /// Whether to ask the terminal for the contents of its clipboard.
+    pub paste: bool,
+    paste_pending: bool,
+}
+
+impl Osc52 {
+    pub fn new() -> Self {
+        Self {
+            passthrough: Passthrough::detect(),
+            max_size: DEFAULT_MAX_SIZE,
+            paste: false,
+            paste_pending: false,
+        }
+    }
+
+    /// Queues a query for the terminal's clipboard, if enabled.
+    /// The response arrives as [`edit::input::Input::Clipboard`].
+    pub fn request_paste(&mut self) {
+        self.paste_pending = self.paste;
+    }
+
+    /// Writes a sequence that puts `data` on the terminal's clipboard.
+    /// Nothing is written if it's empty or exceeds [`Osc52::max_size`].
+    pub fn write_copy(&self, output: &mut String, data: &[u8]) {
+        if data.is_empty() || data.len() > self.max_size {
+            return;
+        }
+        self.write_sequence(output, base64::encode_len(data.len()), |output| {
+            base64::encode(output, data)
+        });
+    }
+
+    /// Writes the clipboard query queued by [`Osc52::request_paste`].
+    pub fn write_pending_paste(&mut self, output: &mut String) {
+        if self.paste_pending {
+            self.paste_pending = false;
+            self.write_sequence(output, 1, |output| output.push('?'));
+        }
+    }
+
+    fn write_sequence(
+        &self,
+        output: &mut String,
+        payload_len: usize,
+        payload: impl FnOnce(&mut String),
+    ) {
+        match self.passthrough {
+            Passthrough::None => {
+                // Rust doubles the size of a string when it needs to grow it.
+                // If the payload is *really* large, this may then double
+                // the size of the `output` from e.g. 100MB to 200MB. Not good.
+                // We can avoid that by reserving the needed size in advance.
+                output.reserve_exact(payload_len + 16);
+                output.push_str("\x1b]52;c;");
+                payload(output);
+                output.push_str("\x1b\\");
+            }
+            Passthrough::Tmux => {
+                // Escape characters inside the passthrough must be doubled.
+                output.reserve_exact(payload_len + 32);
+                output.push_str("\x1bPtmux;\x1b\x1b]52;c;");
+                payload(output);
+                output.push_str("\x1b\x1b\\\x1b\\");
+            }
+            Passthrough::Screen => {
+                // A string terminator would end the DCS, so the sequence ends with BEL instead.
+                // It's assembled in `output` first and then moved into the chunks.
+                let start = output.len();
+                payload(output);
+                let encoded = output[start..].to_string();
+                output.replace_range(start.., "");
+
+                let seq_len = encoded.len() + 8;
+                output.reserve_exact(seq_len + seq_len.div_ceil(SCREEN_CHUNK_SIZE) * 4);
+                let seq = ["\x1b]52;c;", &encoded, "\x07"].concat();
+                // The sequence is pure ASCII, so any byte offset is a char boundary.
+                for chunk in seq.as_bytes().chunks(SCREEN_CHUNK_SIZE) {
+                    output.push_str("\x1bP");
+                    output.push_str(unsafe { str::from_utf8_unchecked(chunk) });
+                    output.push_str("\x1b\\");
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn copy(passthrough: Passthrough, max_size: usize, data: &[u8]) -> String {
+        let mut output = String::new();
+        let osc52 = Osc52 { passthrough, max_size, paste: false, paste_pending: false };
+        osc52.write_copy(&mut output, data);
+        output
+    }
+
+    #[test]
+    fn test_copy() {
+        assert_eq!(copy(Passthrough::None, 16, b"hello"), "\x1b]52;c;aGVsbG8=\x1b\\");
+        assert_eq!(copy(Passthrough::None, 4, b"hello"), "");
+        assert_eq!(copy(Passthrough::None, 16, b""), "");
+        assert_eq!(
+            copy(Passthrough::Tmux, 16, b"hello"),
+            "\x1bPtmux;\x1b\x1b]52;c;aGVsbG8=\x1b\x1b\\\x1b\\"
+        );
+
+        let data = [b'x'; 90];
+        let output = copy(Passthrough::Screen, 1024, &data);
+        let chunks: Vec<_> = output.split_terminator("\x1b\\").collect();
+        assert_eq!(chunks.len(), 2);
+        assert!(chunks.iter().all(|c| c.starts_with("\x1bP")));
+        assert_eq!(chunks[0].len(), 2 + SCREEN_CHUNK_SIZE);
+        assert!(chunks[0].starts_with("\x1bP\x1b]52;c;eHh4"));
+        assert!(chunks[1].ends_with('\x07'));
+    }
+
+    #[test]
+    fn test_paste() {
+        let mut output = String::new();
+        let mut osc52 = Osc52 {
+            passthrough: Passthrough::None,
+            max_size: DEFAULT_MAX_SIZE,
+            paste: false,
+            paste_pending: false,
+        };
+
+        osc52.request_paste();
+        osc52.write_pending_paste(&mut output);
+        assert_eq!(output, "");
+
+        osc52.paste = true;
+        osc52.request_paste();
+        osc52.write_pending_paste(&mut output);
+        osc52.write_pending_paste(&mut output);
+        assert_eq!(output, "\x1b]52;c;?\x1b\\");
+    }
+}
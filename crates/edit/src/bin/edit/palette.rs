@@ -0,0 +1,102 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Palettes that remap some of the [`IndexedColor`]s on top of the theme,
+//! for those who can't tell them apart otherwise. See `ui.palette` in [`crate::config`].
+
+use edit::framebuffer::IndexedColor;
+use edit::oklab::StraightRgba;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum Palette {
+    /// The theme's colors as they are.
+    #[default]
+    Default,
+    /// Bright colors on black, with white text.
+    HighContrast,
+    /// Red and green are hard to tell apart, so they become orange and blue.
+    Deuteranopia,
+    /// Like deuteranopia, but reds also look dark, so they become lighter.
+    Protanopia,
+    /// Blue and yellow are hard to tell apart, so they become purple and pink.
+    Tritanopia,
+}
+
+/// The names in the config.
+pub const PALETTES: [(&str, Palette); 5] = [
+    ("default", Palette::Default),
+    ("high_contrast", Palette::HighContrast),
+    ("deuteranopia", Palette::Deuteranopia),
+    ("protanopia", Palette::Protanopia),
+    ("tritanopia", Palette::Tritanopia),
+];
+
+const fn rgb(rgb: u32) -> StraightRgba {
+    StraightRgba::from_be(rgb << 8 | 0xff)
+}
+
+const HIGH_CONTRAST: [(IndexedColor, StraightRgba); 18] = [
+    (IndexedColor::Black, rgb(0x000000)),
+    (IndexedColor::Red, rgb(0xff4d4d)),
+    (IndexedColor::Green, rgb(0x4dff4d)),
+    (IndexedColor::Yellow, rgb(0xffff4d)),
+    (IndexedColor::Blue, rgb(0x4d9fff)),
+    (IndexedColor::Magenta, rgb(0xff4dff)),
+    (IndexedColor::Cyan, rgb(0x4dffff)),
+    (IndexedColor::White, rgb(0xffffff)),
+    (IndexedColor::BrightBlack, rgb(0xc0c0c0)),
+    (IndexedColor::BrightRed, rgb(0xff8080)),
+    (IndexedColor::BrightGreen, rgb(0x80ff80)),
+    (IndexedColor::BrightYellow, rgb(0xffff80)),
+    (IndexedColor::BrightBlue, rgb(0x80bfff)),
+    (IndexedColor::BrightMagenta, rgb(0xff80ff)),
+    (IndexedColor::BrightCyan, rgb(0x80ffff)),
+    (IndexedColor::BrightWhite, rgb(0xffffff)),
+    (IndexedColor::Background, rgb(0x000000)),
+    (IndexedColor::Foreground, rgb(0xffffff)),
+];
+
+// The color-blind palettes are based on the Okabe-Ito palette.
+const DEUTERANOPIA: [(IndexedColor, StraightRgba); 6] = [
+    (IndexedColor::Red, rgb(0xd55e00)),
+    (IndexedColor::BrightRed, rgb(0xf08a4b)),
+    (IndexedColor::Green, rgb(0x0072b2)),
+    (IndexedColor::BrightGreen, rgb(0x56b4e9)),
+    (IndexedColor::Magenta, rgb(0xcc79a7)),
+    (IndexedColor::BrightMagenta, rgb(0xe6a8cb)),
+];
+
+const PROTANOPIA: [(IndexedColor, StraightRgba); 6] = [
+    (IndexedColor::Red, rgb(0xe8740c)),
+    (IndexedColor::BrightRed, rgb(0xffa050)),
+    (IndexedColor::Green, rgb(0x0072b2)),
+    (IndexedColor::BrightGreen, rgb(0x56b4e9)),
+    (IndexedColor::Magenta, rgb(0xcc79a7)),
+    (IndexedColor::BrightMagenta, rgb(0xe6a8cb)),
+];
+
+const TRITANOPIA: [(IndexedColor, StraightRgba); 6] = [
+    (IndexedColor::Yellow, rgb(0xff6db6)),
+    (IndexedColor::BrightYellow, rgb(0xffb6db)),
+    (IndexedColor::Blue, rgb(0x490092)),
+    (IndexedColor::BrightBlue, rgb(0xb66dff)),
+    (IndexedColor::Cyan, rgb(0x009292)),
+    (IndexedColor::BrightCyan, rgb(0x6dd5d5)),
+];
+
+impl Palette {
+    pub fn name(self) -> &'static str {
+        PALETTES.iter().find(|&&(_, p)| p == self).map_or("default", |&(name, _)| name)
+    }
+
+    /// The colors it replaces.
+    pub fn colors(self) -> &'static [(IndexedColor, StraightRgba)] {
+        match self {
+            Self::Default => &[],
+            Self::HighContrast => &HIGH_CONTRAST,
+            Self::Deuteranopia => &DEUTERANOPIA,
+            Self::Protanopia => &PROTANOPIA,
+            Self::Tritanopia => &TRITANOPIA,
+        }
+    }
+}
@@ -0,0 +1,232 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Decides where our own files go, so that the same binary works in Termux,
+//! in a proot distro and on a desktop.
+//!
+//! On Unix, the XDG variables win. Without them, the files go below `$HOME`
+//! as usual. Termux has no `/usr` or `/tmp`: its packages install into
+//! `$PREFIX/share` and temporary files go to `$PREFIX/tmp`. If `$HOME` is
+//! missing, which happens when the binary is started by another Android app,
+//! it's found next to `$PREFIX`. `--app-dir=DIR` puts everything below DIR
+//! instead, and `--paths` shows where the files go.
+
+use std::env;
+use std::ffi::OsString;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+/// Set by `--app-dir`.
+static OVERRIDE: OnceLock<PathBuf> = OnceLock::new();
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Kind {
+    /// Files the user edits, like the keymap.
+    Config,
+    /// Files we keep between sessions, like the recent files.
+    State,
+    /// Files the user added, like themes and dictionaries.
+    Data,
+    /// Files that don't need to outlive the session.
+    Runtime,
+}
+
+impl Kind {
+    const ALL: [Self; 4] = [Self::Config, Self::State, Self::Data, Self::Runtime];
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Config => "config",
+            Self::State => "state",
+            Self::Data => "data",
+            Self::Runtime => "runtime",
+        }
+    }
+}
+
+/// Puts all our files below `dir`. Only the first call has an effect.
+pub fn set_override(dir: PathBuf) {
+    _ = OVERRIDE.set(dir);
+}
+
+/// Returns our directory of the given kind, e.g. `~/.config/edit`. It may not exist yet.
+pub fn dir(kind: Kind) -> Option<PathBuf> {
+    if let Some(dir) = OVERRIDE.get() {
+        return Some(dir.join(kind.name()));
+    }
+    resolve(kind, &|name| env::var_os(name)).map(|dir| dir.join("edit"))
+}
+
+/// Returns our directory for temporary files, which, unlike the others, always exists.
+pub fn runtime_dir() -> PathBuf {
+    dir(Kind::Runtime).unwrap_or_else(|| env::temp_dir().join("edit"))
+}
+
+/// Returns the directories that packages install our data files to, e.g. `/usr/share/edit`.
+/// They're searched after [`Kind::Data`].
+pub fn system_data_dirs() -> Vec<PathBuf> {
+    if OVERRIDE.get().is_some() {
+        return Vec::new();
+    }
+    resolve_system_data(&|name| env::var_os(name)).into_iter().map(|dir| dir.join("edit")).collect()
+}
+
+/// Prints where the files go, for `--paths`.
+pub fn describe() -> String {
+    let mut text = String::new();
+    for kind in Kind::ALL {
+        let dir = dir(kind).map(|dir| dir.display().to_string()).unwrap_or_default();
+        text.push_str(&format!("{:<8} {dir}\n", kind.name()));
+    }
+    for dir in system_data_dirs() {
+        text.push_str(&format!("{:<8} {}\n", "system", dir.display()));
+    }
+    text
+}
+
+fn resolve(kind: Kind, var: &dyn Fn(&str) -> Option<OsString>) -> Option<PathBuf> {
+    let path = |name: &str| var(name).filter(|v| !v.is_empty()).map(PathBuf::from);
+
+    if cfg!(windows) {
+        return match kind {
+            Kind::Config => path("APPDATA"),
+            Kind::State | Kind::Data => path("LOCALAPPDATA"),
+            Kind::Runtime => Some(env::temp_dir()),
+        };
+    }
+
+    let (xdg, below_home) = match kind {
+        Kind::Config => ("XDG_CONFIG_HOME", ".config"),
+        Kind::State => ("XDG_STATE_HOME", ".local/state"),
+        Kind::Data => ("XDG_DATA_HOME", ".local/share"),
+        Kind::Runtime => {
+            return path("XDG_RUNTIME_DIR")
+                .or_else(|| path("TMPDIR"))
+                .or_else(|| path("PREFIX").map(|prefix| prefix.join("tmp")))
+                .or_else(|| Some(PathBuf::from("/tmp")));
+        }
+    };
+    path(xdg).or_else(|| Some(home(var)?.join(below_home)))
+}
+
+fn home(var: &dyn Fn(&str) -> Option<OsString>) -> Option<PathBuf> {
+    let path = |name: &str| var(name).filter(|v| !v.is_empty()).map(PathBuf::from);
+    // Termux keeps the home directory at `$PREFIX/../home`.
+    path("HOME").or_else(|| Some(path("PREFIX")?.parent()?.join("home")))
+}
+
+/// Expands a typed path like a shell would: A leading `~` becomes the home directory
+/// and `$VAR` or `${VAR}` the variable's value. Unset variables are left as they are.
+pub fn expand(text: &str) -> String {
+    expand_with(text, &|name| env::var_os(name))
+}
+
+fn expand_with(text: &str, var: &dyn Fn(&str) -> Option<OsString>) -> String {
+    let mut res = String::with_capacity(text.len());
+    let mut rest = text;
+
+    if let Some(after) = text.strip_prefix('~')
+        && after.chars().next().is_none_or(std::path::is_separator)
+        && let Some(home) = home(var)
+    {
+        res.push_str(&home.to_string_lossy());
+        rest = after;
+    }
+
+    while let Some(i) = rest.find('$') {
+        res.push_str(&rest[..i]);
+        let after = &rest[i + 1..];
+        let (name, tail) = if let Some(braced) = after.strip_prefix('{')
+            && let Some(end) = braced.find('}')
+        {
+            (&braced[..end], &braced[end + 1..])
+        } else {
+            let end =
+                after.find(|c: char| !c.is_ascii_alphanumeric() && c != '_').unwrap_or(after.len());
+            after.split_at(end)
+        };
+
+        match var(name).filter(|_| !name.is_empty()) {
+            Some(value) => {
+                res.push_str(&value.to_string_lossy());
+                rest = tail;
+            }
+            None => {
+                res.push('$');
+                rest = after;
+            }
+        }
+    }
+
+    res.push_str(rest);
+    res
+}
+
+fn resolve_system_data(var: &dyn Fn(&str) -> Option<OsString>) -> Vec<PathBuf> {
+    if cfg!(windows) {
+        return Vec::new();
+    }
+    if let Some(dirs) = var("XDG_DATA_DIRS").filter(|v| !v.is_empty()) {
+        return env::split_paths(&dirs).collect();
+    }
+    match var("PREFIX").filter(|v| !v.is_empty()) {
+        Some(prefix) => vec![PathBuf::from(prefix).join("share")],
+        None => vec![PathBuf::from("/usr/local/share"), PathBuf::from("/usr/share")],
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+
+    fn env(vars: &[(&str, &str)]) -> impl Fn(&str) -> Option<OsString> {
+        let vars: Vec<_> = vars.iter().map(|&(k, v)| (k.to_string(), OsString::from(v))).collect();
+        move |name| vars.iter().find(|(k, _)| k == name).map(|(_, v)| v.clone())
+    }
+
+    #[test]
+    fn test_resolve_desktop() {
+        let var = env(&[("HOME", "/home/u"), ("XDG_CONFIG_HOME", "/cfg"), ("XDG_STATE_HOME", "")]);
+        assert_eq!(resolve(Kind::Config, &var).as_deref(), Some(Path::new("/cfg")));
+        assert_eq!(resolve(Kind::State, &var).as_deref(), Some(Path::new("/home/u/.local/state")));
+        assert_eq!(resolve(Kind::Data, &var).as_deref(), Some(Path::new("/home/u/.local/share")));
+        assert_eq!(resolve(Kind::Runtime, &var).as_deref(), Some(Path::new("/tmp")));
+        assert_eq!(
+            resolve_system_data(&var),
+            [Path::new("/usr/local/share"), Path::new("/usr/share")]
+        );
+    }
+
+    #[test]
+    fn test_resolve_termux() {
+        let var = env(&[("PREFIX", "/data/data/com.termux/files/usr")]);
+        assert_eq!(
+            resolve(Kind::Config, &var).as_deref(),
+            Some(Path::new("/data/data/com.termux/files/home/.config"))
+        );
+        assert_eq!(
+            resolve(Kind::Runtime, &var).as_deref(),
+            Some(Path::new("/data/data/com.termux/files/usr/tmp"))
+        );
+        assert_eq!(resolve_system_data(&var), [Path::new("/data/data/com.termux/files/usr/share")]);
+
+        let var = env(&[("PREFIX", "/p/usr"), ("XDG_DATA_DIRS", "/a:/b")]);
+        assert_eq!(resolve_system_data(&var), [Path::new("/a"), Path::new("/b")]);
+    }
+
+    #[test]
+    fn test_expand() {
+        let var = env(&[("HOME", "/home/u"), ("PROJ", "/src/p"), ("EMPTY", "")]);
+        assert_eq!(expand_with("~", &var), "/home/u");
+        assert_eq!(expand_with("~/a.txt", &var), "/home/u/a.txt");
+        assert_eq!(expand_with("~other/a", &var), "~other/a");
+        assert_eq!(expand_with("a/~", &var), "a/~");
+        assert_eq!(expand_with("$PROJ/src", &var), "/src/p/src");
+        assert_eq!(expand_with("${PROJ}x/$HOME", &var), "/src/px//home/u");
+        assert_eq!(expand_with("$EMPTY|", &var), "|");
+        assert_eq!(expand_with("$NOPE/$/${PROJ", &var), "$NOPE/$/${PROJ");
+        assert_eq!(expand_with("price$", &var), "price$");
+    }
+}
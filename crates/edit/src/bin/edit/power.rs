@@ -0,0 +1,172 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Saves battery while the phone runs low.
+//!
+//! In Termux, `termux-battery-status` reports the charge level. It takes a
+//! moment to answer, so it runs on a background thread and only every few
+//! minutes. While power saving, animations run at a lower frame rate and
+//! autosave waits longer between writes. The user can also turn power saving
+//! on or off for good, which stops the queries.
+
+use std::process::Command;
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::clipboard_backend::{find_in_path, run_with_timeout};
+
+const QUERY_INTERVAL: Duration = Duration::from_secs(300);
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(10);
+/// Power saving kicks in below this charge level, unless the phone is plugged in.
+const DEFAULT_THRESHOLD: u8 = 20;
+/// The shortest time between two frames while power saving, i.e. 20 FPS.
+const MIN_FRAME_INTERVAL: Duration = Duration::from_millis(50);
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PowerMode {
+    /// Follows the battery.
+    Auto,
+    On,
+    Off,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+struct Battery {
+    percentage: u8,
+    plugged: bool,
+}
+
+pub struct Power {
+    pub mode: PowerMode,
+    pub threshold: u8,
+    available: bool,
+    battery: Option<Battery>,
+    last_query: Option<Instant>,
+    pending: Option<Receiver<Option<Battery>>>,
+}
+
+impl Power {
+    pub fn new() -> Self {
+        Self {
+            mode: PowerMode::Auto,
+            threshold: DEFAULT_THRESHOLD,
+            available: find_in_path("termux-battery-status"),
+            battery: None,
+            last_query: None,
+            pending: None,
+        }
+    }
+
+    /// Whether to save power right now.
+    pub fn is_saving(&self) -> bool {
+        match self.mode {
+            PowerMode::Auto => self
+                .battery
+                .is_some_and(|battery| !battery.plugged && battery.percentage < self.threshold),
+            PowerMode::On => true,
+            PowerMode::Off => false,
+        }
+    }
+
+    /// Turns power saving on or off for good.
+    pub fn toggle(&mut self) {
+        self.mode = if self.is_saving() { PowerMode::Off } else { PowerMode::On };
+    }
+
+    /// Picks up the result of the last battery query and starts a new one, if it's time.
+    pub fn poll(&mut self) {
+        if let Some(receiver) = &self.pending {
+            match receiver.try_recv() {
+                Ok(Some(battery)) => {
+                    self.battery = Some(battery);
+                    self.pending = None;
+                }
+                // The Termux:API app is probably missing. Don't try again.
+                Ok(None) | Err(TryRecvError::Disconnected) => {
+                    self.available = false;
+                    self.pending = None;
+                }
+                Err(TryRecvError::Empty) => {}
+            }
+        }
+
+        if self.mode == PowerMode::Auto
+            && self.available
+            && self.pending.is_none()
+            && self.last_query.is_none_or(|t| t.elapsed() >= QUERY_INTERVAL)
+        {
+            let (sender, receiver) = mpsc::channel();
+            thread::spawn(move || {
+                let mut cmd = Command::new("termux-battery-status");
+                let output = run_with_timeout(&mut cmd, &[], COMMAND_TIMEOUT);
+                _ = sender.send(output.and_then(|output| parse_battery(&output)));
+            });
+            self.pending = Some(receiver);
+            self.last_query = Some(Instant::now());
+        }
+    }
+
+    /// Limits how soon the next animation frame may be drawn.
+    pub fn frame_interval(&self, timeout: Duration) -> Duration {
+        if self.is_saving() { timeout.max(MIN_FRAME_INTERVAL) } else { timeout }
+    }
+}
+
+/// Parses the JSON printed by `termux-battery-status`, e.g.
+/// `{"percentage": 15, "plugged": "UNPLUGGED", "status": "DISCHARGING", ...}`.
+fn parse_battery(output: &[u8]) -> Option<Battery> {
+    let text = str::from_utf8(output).ok()?;
+    let value = |key: &str| {
+        let rest = &text[text.find(&format!("\"{key}\""))? + key.len() + 2..];
+        let rest = rest.trim_start().strip_prefix(':')?.trim_start();
+        let end = rest.find([',', '}', '\n']).unwrap_or(rest.len());
+        Some(rest[..end].trim().trim_matches('"'))
+    };
+
+    let percentage = value("percentage")?.parse::<f64>().ok()?.clamp(0.0, 100.0) as u8;
+    let plugged = value("plugged").is_some_and(|plugged| plugged != "UNPLUGGED");
+    Some(Battery { percentage, plugged })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_battery() {
+        let output = br#"{
+  "health": "GOOD",
+  "percentage": 15,
+  "plugged": "UNPLUGGED",
+  "status": "DISCHARGING",
+  "temperature": 28.5
+}"#;
+        assert_eq!(parse_battery(output), Some(Battery { percentage: 15, plugged: false }));
+
+        let output = br#"{"percentage":80,"plugged":"PLUGGED_USB"}"#;
+        assert_eq!(parse_battery(output), Some(Battery { percentage: 80, plugged: true }));
+
+        assert_eq!(parse_battery(b"{}"), None);
+        assert_eq!(parse_battery(b"{\"percentage\": \"x\"}"), None);
+    }
+
+    #[test]
+    fn test_is_saving() {
+        let mut power = Power::new();
+        power.available = false;
+        assert!(!power.is_saving());
+
+        power.battery = Some(Battery { percentage: 10, plugged: false });
+        assert!(power.is_saving());
+        assert_eq!(power.frame_interval(Duration::ZERO), MIN_FRAME_INTERVAL);
+
+        power.battery = Some(Battery { percentage: 10, plugged: true });
+        assert!(!power.is_saving());
+        power.toggle();
+        assert_eq!(power.mode, PowerMode::On);
+        assert!(power.is_saving());
+        power.toggle();
+        assert_eq!(power.mode, PowerMode::Off);
+    }
+}
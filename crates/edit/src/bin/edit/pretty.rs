@@ -0,0 +1,422 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! The built-in formatter for JSON and XML, used by Format Document and Format Selection
+//! for files without a `formatter` in the config, and by Selection > Pretty-Print.
+//!
+//! Only the whitespace between the tokens changes: Keys keep their order, and strings,
+//! numbers and attributes are copied as they are. Like [`crate::json`], it's tolerant of
+//! comments and trailing commas in JSON. The comments are kept, the trailing commas dropped.
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Language {
+    Json,
+    Xml,
+}
+
+impl Language {
+    /// The language files of the type named `filetype` are written in, if any.
+    pub fn for_filetype(filetype: &str) -> Option<Language> {
+        match filetype {
+            "json" => Some(Language::Json),
+            "xml" => Some(Language::Xml),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Language::Json => "JSON",
+            Language::Xml => "XML",
+        }
+    }
+}
+
+/// Reindents `text`, with `indent` per level. On error, returns the offset where it went wrong.
+/// The result ends with a newline if `text` did.
+pub fn pretty_print(language: Language, text: &str, indent: &str) -> Result<String, usize> {
+    let mut printer = Printer {
+        text: text.as_bytes(),
+        pos: 0,
+        out: String::with_capacity(text.len() * 2),
+        indent,
+        depth: 0,
+        needs_newline: false,
+    };
+    match language {
+        Language::Json => printer.json()?,
+        Language::Xml => printer.xml()?,
+    }
+    if text.ends_with('\n') {
+        printer.out.push('\n');
+    }
+    Ok(printer.out)
+}
+
+struct Printer<'a> {
+    text: &'a [u8],
+    pos: usize,
+    out: String,
+    indent: &'a str,
+    depth: usize,
+    /// Set after a line comment or one on a line of its own, so that what follows goes below.
+    needs_newline: bool,
+}
+
+impl Printer<'_> {
+    fn newline(&mut self) {
+        if !self.out.is_empty() {
+            self.out.push('\n');
+        }
+        for _ in 0..self.depth {
+            self.out.push_str(self.indent);
+        }
+        self.needs_newline = false;
+    }
+
+    fn write(&mut self, s: &str) {
+        if self.needs_newline {
+            self.newline();
+        }
+        self.out.push_str(s);
+    }
+
+    fn write_range(&mut self, beg: usize, end: usize) {
+        // The ranges begin and end at ASCII delimiters, so they're valid UTF-8.
+        let s = unsafe { str::from_utf8_unchecked(&self.text[beg..end]) };
+        self.write(s);
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.text.get(self.pos).copied()
+    }
+
+    fn starts_with(&self, prefix: &[u8]) -> bool {
+        self.text[self.pos..].starts_with(prefix)
+    }
+
+    /// Moves past `delim`, or to the end of the text if it's missing and returns `false`.
+    fn skip_past(&mut self, delim: &[u8]) -> bool {
+        match self.text[self.pos..].windows(delim.len()).position(|w| w == delim) {
+            Some(i) => {
+                self.pos += i + delim.len();
+                true
+            }
+            None => {
+                self.pos = self.text.len();
+                false
+            }
+        }
+    }
+
+    fn json(&mut self) -> Result<(), usize> {
+        self.json_trivia()?;
+        self.json_value()?;
+        self.json_trivia()?;
+        if self.pos < self.text.len() {
+            return Err(self.pos);
+        }
+        Ok(())
+    }
+
+    /// Skips whitespace and copies comments: Those that follow something on the same line
+    /// stay there, and the others go on a line of their own.
+    fn json_trivia(&mut self) -> Result<(), usize> {
+        let mut own_line = self.out.is_empty();
+        loop {
+            match self.peek() {
+                Some(b'\n') => {
+                    own_line = true;
+                    self.pos += 1;
+                }
+                Some(b' ' | b'\t' | b'\r') => self.pos += 1,
+                Some(b'/') if self.starts_with(b"//") || self.starts_with(b"/*") => {
+                    let beg = self.pos;
+                    let line = self.starts_with(b"//");
+                    if line {
+                        self.skip_past(b"\n");
+                        // The newline isn't part of the comment.
+                        if self.text[self.pos - 1] == b'\n' {
+                            self.pos -= 1;
+                        }
+                    } else if !self.skip_past(b"*/") {
+                        return Err(beg);
+                    }
+                    let end = self.pos;
+                    let end = if self.text[..end].ends_with(b"\r") { end - 1 } else { end };
+
+                    if own_line {
+                        self.newline();
+                        self.write_range(beg, end);
+                    } else if self.out.ends_with(' ') {
+                        // Between a key and its value.
+                        self.write_range(beg, end);
+                        self.write(" ");
+                    } else {
+                        self.write(" ");
+                        self.write_range(beg, end);
+                    }
+                    self.needs_newline = line || own_line;
+                }
+                _ => return Ok(()),
+            }
+        }
+    }
+
+    fn json_value(&mut self) -> Result<(), usize> {
+        let beg = self.pos;
+        match self.peek() {
+            Some(b'{') => self.json_container(b'}', true),
+            Some(b'[') => self.json_container(b']', false),
+            Some(b'"') => self.json_string(),
+            Some(b) if b == b'-' || b.is_ascii_alphanumeric() => {
+                while self
+                    .peek()
+                    .is_some_and(|b| b.is_ascii_alphanumeric() || matches!(b, b'-' | b'+' | b'.'))
+                {
+                    self.pos += 1;
+                }
+                let word = unsafe { str::from_utf8_unchecked(&self.text[beg..self.pos]) };
+                let number =
+                    matches!(word.as_bytes()[0], b'-' | b'0'..=b'9') && word.parse::<f64>().is_ok();
+                if !number && !matches!(word, "true" | "false" | "null") {
+                    return Err(beg);
+                }
+                self.write(word);
+                Ok(())
+            }
+            _ => Err(beg),
+        }
+    }
+
+    fn json_string(&mut self) -> Result<(), usize> {
+        let beg = self.pos;
+        self.pos += 1;
+        loop {
+            match self.peek() {
+                Some(b'"') => break,
+                Some(b'\\') => self.pos += 2,
+                Some(b'\n') | None => return Err(beg),
+                Some(_) => self.pos += 1,
+            }
+        }
+        self.pos += 1;
+        self.write_range(beg, self.pos);
+        Ok(())
+    }
+
+    /// An object if `object`, otherwise an array. Empty ones stay on one line.
+    fn json_container(&mut self, close: u8, object: bool) -> Result<(), usize> {
+        self.write(if object { "{" } else { "[" });
+        self.pos += 1;
+        self.depth += 1;
+        self.json_trivia()?;
+
+        let mut empty = true;
+        while self.peek() != Some(close) {
+            self.newline();
+            empty = false;
+
+            if object {
+                if self.peek() != Some(b'"') {
+                    return Err(self.pos);
+                }
+                self.json_string()?;
+                self.json_trivia()?;
+                if self.peek() != Some(b':') {
+                    return Err(self.pos);
+                }
+                self.pos += 1;
+                self.write(": ");
+                self.json_trivia()?;
+            }
+            self.json_value()?;
+            self.json_trivia()?;
+
+            match self.peek() {
+                Some(b',') => {
+                    self.pos += 1;
+                    self.write(",");
+                    let comma = self.out.len() - 1;
+                    self.json_trivia()?;
+                    if self.peek() == Some(close) {
+                        // A trailing comma, maybe followed by a comment.
+                        self.out.remove(comma);
+                    }
+                }
+                Some(b) if b == close => {}
+                _ => return Err(self.pos),
+            }
+        }
+
+        self.pos += 1;
+        self.depth -= 1;
+        if !empty || self.needs_newline {
+            self.newline();
+        }
+        self.write(if object { "}" } else { "]" });
+        Ok(())
+    }
+
+    fn xml(&mut self) -> Result<(), usize> {
+        // The open elements, by the offset and length of their name.
+        let mut open: Vec<(usize, usize, usize)> = Vec::new();
+
+        while self.pos < self.text.len() {
+            let beg = self.pos;
+
+            if self.peek() != Some(b'<') {
+                while self.peek().is_some_and(|b| b != b'<') {
+                    self.pos += 1;
+                }
+                let text = self.text[beg..self.pos].trim_ascii();
+                if !text.is_empty() {
+                    self.newline();
+                    self.out.push_str(unsafe { str::from_utf8_unchecked(text) });
+                }
+                continue;
+            }
+
+            let (delim, element) = if self.starts_with(b"<!--") {
+                (&b"-->"[..], false)
+            } else if self.starts_with(b"<![CDATA[") {
+                (&b"]]>"[..], false)
+            } else if self.starts_with(b"<?") {
+                (&b"?>"[..], false)
+            } else if self.starts_with(b"<!") {
+                (&b">"[..], false)
+            } else {
+                (&b">"[..], true)
+            };
+
+            if !element {
+                if !self.skip_past(delim) {
+                    return Err(beg);
+                }
+                self.newline();
+                self.write_range(beg, self.pos);
+                continue;
+            }
+
+            let closing = self.text.get(beg + 1) == Some(&b'/');
+            let name_beg = beg + 1 + closing as usize;
+            let name_len = self.text[name_beg..]
+                .iter()
+                .position(|&b| b.is_ascii_whitespace() || matches!(b, b'/' | b'>'))
+                .unwrap_or(self.text.len() - name_beg);
+            let name = &self.text[name_beg..name_beg + name_len];
+            if !name.first().is_some_and(|&b| b.is_ascii_alphabetic() || b == b'_' || b >= 0x80) {
+                return Err(beg);
+            }
+
+            // Attribute values may contain a `>`.
+            self.pos = name_beg + name_len;
+            let mut quote = None;
+            loop {
+                match (self.peek(), quote) {
+                    (None, _) | (Some(b'<'), None) => return Err(beg),
+                    (Some(b'>'), None) => break,
+                    (Some(q @ (b'"' | b'\'')), None) => quote = Some(q),
+                    (Some(q), Some(open)) if q == open => quote = None,
+                    _ => {}
+                }
+                self.pos += 1;
+            }
+            self.pos += 1;
+            let end = self.pos;
+
+            if closing {
+                match open.pop() {
+                    Some((_, b, l)) if &self.text[b..b + l] == name => {}
+                    _ => return Err(beg),
+                }
+                self.depth -= 1;
+                self.newline();
+                self.write_range(beg, end);
+                continue;
+            }
+
+            self.newline();
+            self.write_range(beg, end);
+            if self.text[..end - 1].ends_with(b"/") {
+                continue;
+            }
+
+            // An element with nothing but text in it stays on one line.
+            let text_end =
+                self.text[end..].iter().position(|&b| b == b'<').map_or(end, |i| end + i);
+            let close_len = name.len() + 3;
+            let close = &self.text[text_end..(text_end + close_len).min(self.text.len())];
+            if close.len() == close_len
+                && close.starts_with(b"</")
+                && &close[2..close_len - 1] == name
+                && close[close_len - 1] == b'>'
+            {
+                let text = self.text[end..text_end].trim_ascii();
+                self.out.push_str(unsafe { str::from_utf8_unchecked(text) });
+                self.write_range(text_end, text_end + close_len);
+                self.pos = text_end + close_len;
+                continue;
+            }
+
+            open.push((beg, name_beg, name_len));
+            self.depth += 1;
+        }
+
+        match open.last() {
+            Some(&(beg, _, _)) => Err(beg),
+            None if self.out.is_empty() => Err(0),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_json() {
+        let json = |text| pretty_print(Language::Json, text, "  ");
+
+        assert_eq!(
+            json("{\"b\":[1,2.5e3,{}],\"a\":{\"x\":null, \"y\":[ ]},}\n").unwrap(),
+            "{\n  \"b\": [\n    1,\n    2.5e3,\n    {}\n  ],\n  \"a\": {\n    \"x\": null,\n    \"y\": []\n  }\n}\n"
+        );
+        assert_eq!(json(r#""a\"b""#).unwrap(), r#""a\"b""#);
+        assert_eq!(
+            json("{\n// first\n\"a\": 1, // one\n\"b\": /* two */ 2\n}").unwrap(),
+            "{\n  // first\n  \"a\": 1, // one\n  \"b\": /* two */ 2\n}"
+        );
+        assert_eq!(json("[1 // one\n]").unwrap(), "[\n  1 // one\n]");
+
+        assert_eq!(json("{\"a\" 1}"), Err(5));
+        assert_eq!(json("[1, 2"), Err(5));
+        assert_eq!(json("[1 2]"), Err(3));
+        assert_eq!(json("[tru]"), Err(1));
+        assert_eq!(json("{a: 1}"), Err(1));
+        assert_eq!(json("[\"a\n\"]"), Err(1));
+        assert_eq!(json("[] []"), Err(3));
+        assert_eq!(json(""), Err(0));
+    }
+
+    #[test]
+    fn test_xml() {
+        let xml = |text| pretty_print(Language::Xml, text, "\t");
+
+        assert_eq!(
+            xml("<?xml version=\"1.0\"?><a x=\"1>2\"><b>text</b><c/><!-- note --><d>\n<e></e></d></a>\n")
+                .unwrap(),
+            "<?xml version=\"1.0\"?>\n<a x=\"1>2\">\n\t<b>text</b>\n\t<c/>\n\t<!-- note -->\n\t<d>\n\t\t<e></e>\n\t</d>\n</a>\n"
+        );
+        assert_eq!(xml("<p>a <b>b</b> c</p>").unwrap(), "<p>\n\ta\n\t<b>b</b>\n\tc\n</p>");
+
+        assert_eq!(xml("<a><b></a>"), Err(6));
+        assert_eq!(xml("<a><b>"), Err(3));
+        assert_eq!(xml("</a>"), Err(0));
+        assert_eq!(xml("<a x=\"1></a>"), Err(0));
+        assert_eq!(xml("<a><!-- x</a>"), Err(3));
+        assert_eq!(xml("< a/>"), Err(0));
+        assert_eq!(xml(" "), Err(0));
+    }
+}
@@ -0,0 +1,172 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Where the time of each frame goes, with the `profiler` feature.
+//!
+//! The main loop calls [`Profiler::mark`] after each of its phases, which
+//! charges the time since the previous mark to that phase. The overlay in the
+//! top right corner shows the last frames as a sparkline, along with the worst
+//! frame, and the export writes all frames that are kept to a CSV file.
+
+use std::collections::VecDeque;
+use std::fmt::Write as _;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+use std::{fs, io, mem};
+
+use edit::helpers::CoordType;
+use edit::unicode::MeasurementConfig;
+
+use crate::paths::{self, Kind};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Phase {
+    /// Handling the input.
+    Input,
+    /// Drawing without input, until the layout settled.
+    Layout,
+    /// Highlighting in the background.
+    Highlight,
+    /// Turning the frame into VT.
+    Render,
+    /// Writing the VT, or handing it to the render thread.
+    Write,
+}
+
+const PHASES: [(Phase, &str); 5] = [
+    (Phase::Input, "input"),
+    (Phase::Layout, "layout"),
+    (Phase::Highlight, "highlight"),
+    (Phase::Render, "render"),
+    (Phase::Write, "write"),
+];
+
+/// How many frames are kept for the export. The overlay shows the last [`SPARKLINE_WIDTH`].
+const HISTORY: usize = 1024;
+const SPARKLINE_WIDTH: usize = 24;
+const SPARKS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+type Frame = [Duration; PHASES.len()];
+
+fn total(frame: &Frame) -> Duration {
+    frame.iter().sum()
+}
+
+fn millis(d: Duration) -> f64 {
+    d.as_secs_f64() * 1000.0
+}
+
+pub struct Profiler {
+    pub visible: bool,
+    frames: VecDeque<Frame>,
+    current: Frame,
+    worst: Frame,
+    last_mark: Option<Instant>,
+    /// How wide the overlay was last time, so that a shorter one can cover it.
+    overlay_width: CoordType,
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        Self {
+            visible: false,
+            frames: VecDeque::with_capacity(HISTORY),
+            current: Default::default(),
+            worst: Default::default(),
+            last_mark: None,
+            overlay_width: 0,
+        }
+    }
+
+    /// Starts a frame. The time spent waiting for input before it doesn't count.
+    pub fn begin(&mut self) {
+        self.current = Default::default();
+        self.last_mark = Some(Instant::now());
+    }
+
+    /// Charges the time since the last mark to `phase`.
+    pub fn mark(&mut self, phase: Phase) {
+        let now = Instant::now();
+        if let Some(last) = self.last_mark.replace(now) {
+            self.current[phase as usize] += now - last;
+        }
+    }
+
+    /// Ends the frame with a mark for [`Phase::Write`].
+    pub fn end(&mut self) {
+        self.mark(Phase::Write);
+        self.last_mark = None;
+
+        if self.frames.len() >= HISTORY {
+            self.frames.pop_front();
+        }
+        self.frames.push_back(self.current);
+        if total(&self.current) >= total(&self.worst) {
+            self.worst = self.current;
+        }
+    }
+
+    /// Whether the overlay was just hidden. What it covered must then be drawn again.
+    pub fn take_hidden(&mut self) -> bool {
+        !self.visible && mem::take(&mut self.overlay_width) > 0
+    }
+
+    /// Appends the overlay to the `output` of a frame that's `width` columns wide.
+    /// It's written on top of the frame, like the `debug-latency` status.
+    pub fn write_overlay(&mut self, output: &mut String, width: CoordType) {
+        let mut text = String::new();
+
+        let recent = self.frames.iter().rev().take(SPARKLINE_WIDTH).rev();
+        let max = recent.clone().map(total).max().unwrap_or_default().max(Duration::from_millis(1));
+        for frame in recent {
+            let i =
+                (total(frame).as_nanos() * (SPARKS.len() as u128 - 1) / max.as_nanos()) as usize;
+            text.push(SPARKS[i.min(SPARKS.len() - 1)]);
+        }
+
+        let last = self.frames.back().map(total).unwrap_or_default();
+        _ = write!(text, " {:.1}ms worst {:.1}ms", millis(last), millis(total(&self.worst)));
+        if let Some((i, d)) = self.worst.iter().enumerate().max_by_key(|&(_, d)| *d) {
+            _ = write!(text, " ({} {:.1})", PHASES[i].1, millis(*d));
+        }
+
+        // The sparks are ambiguous width, so they're measured.
+        let cols = MeasurementConfig::new(&text.as_bytes()).goto_offset(text.len()).visual_pos.x;
+        // The overlay may shrink, in which case the rest of the old one is overwritten.
+        let padding = (self.overlay_width - cols).max(0);
+        self.overlay_width = cols;
+
+        // Below the `debug-latency` status. To avoid moving the cursor,
+        // it's pushed and popped onto the VT cursor stack.
+        _ = write!(
+            output,
+            "\x1b7\x1b[0;44;97m\x1b[2;{}H{:pad$}{text}\x1b8",
+            (width - cols - padding + 1).max(1),
+            "",
+            pad = padding as usize,
+        );
+    }
+
+    /// Writes the kept frames to a CSV file in the state directory and returns its path.
+    pub fn export(&self) -> io::Result<PathBuf> {
+        let dir = paths::dir(Kind::State).unwrap_or_else(paths::runtime_dir);
+        fs::create_dir_all(&dir)?;
+        let path = dir.join("profile.csv");
+
+        let mut csv = String::from("frame");
+        for (_, name) in PHASES {
+            _ = write!(csv, ",{name}_us");
+        }
+        csv.push_str(",total_us\n");
+        for (i, frame) in self.frames.iter().enumerate() {
+            _ = write!(csv, "{i}");
+            for d in frame {
+                _ = write!(csv, ",{}", d.as_micros());
+            }
+            _ = writeln!(csv, ",{}", total(frame).as_micros());
+        }
+
+        fs::write(&path, csv)?;
+        Ok(path)
+    }
+}
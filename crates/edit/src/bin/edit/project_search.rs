@@ -0,0 +1,559 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Find & replace across all files below a directory.
+//!
+//! Both run a slice at a time, see [`crate::task`]. Finding which files contain a match
+//! is the exception: That's done by a pool of threads, which don't need a `TextBuffer` for it.
+
+use std::fs::{self, File};
+use std::io::Read as _;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::time::Instant;
+use std::{mem, thread};
+
+use edit::buffer::{FindMatch, SearchOptions, TextBuffer};
+use edit::helpers::{KIBI, MEBI};
+use edit::{apperr, icu, sys};
+
+use crate::backup::Backup;
+use crate::save::{self, SaveError, SaveStage};
+use crate::task::Progress;
+
+/// How many bytes at the start of a file we check for NUL bytes.
+const BINARY_SNIFF_LEN: usize = 8 * 1024;
+/// Files of up to this size are loaded into a small [`TextBuffer`].
+const SMALL_FILE_SIZE: usize = 64 * KIBI;
+/// Files of this size or more are mapped into memory instead of read.
+const MAP_SIZE: u64 = MEBI as u64;
+/// The most threads that search at once. One core is left for drawing and input.
+const MAX_WORKERS: usize = 8;
+
+/// A file with at least one hit.
+pub struct ProjectFile {
+    pub path: PathBuf,
+    /// `path` relative to the search root, for display purposes.
+    pub name: String,
+    pub matches: Vec<FindMatch>,
+    /// Parallel to `matches`. Only selected matches get replaced.
+    pub selected: Vec<bool>,
+}
+
+pub enum SkipReason {
+    /// The file isn't valid UTF-8.
+    Encoding,
+    /// The file is open and has unsaved changes.
+    Unsaved,
+    /// The file was modified since the preview was generated.
+    Changed,
+    /// Reading or writing the file failed, e.g. due to missing permissions.
+    Io(apperr::Error),
+    /// Writing the changes back failed.
+    Save(SaveError),
+}
+
+pub struct SkippedFile {
+    pub name: String,
+    pub reason: SkipReason,
+}
+
+#[derive(Default)]
+pub struct ProjectSearch {
+    pub root: PathBuf,
+    pub pattern: String,
+    pub options: SearchOptions,
+    pub replacement: String,
+    pub files: Vec<ProjectFile>,
+    pub skipped: Vec<SkippedFile>,
+    /// Checks on the worker threads whether a file contains a match.
+    matcher: Option<Arc<icu::Matcher>>,
+    /// Started once all directories were listed.
+    pool: Option<Pool>,
+    /// Directories that are yet to be listed.
+    pending_dirs: Vec<PathBuf>,
+    /// The files to search. Sorted once all directories were listed.
+    paths: Vec<PathBuf>,
+    /// How many of `paths` were searched.
+    searched: usize,
+    /// The files whose replacements are being applied.
+    replacing: Vec<ProjectFile>,
+    /// How many of `replacing` are done.
+    replaced: usize,
+    summary: ReplaceSummary,
+}
+
+#[derive(Default, Clone, Copy)]
+pub struct ReplaceSummary {
+    pub files: usize,
+    pub matches: usize,
+}
+
+impl ProjectSearch {
+    /// Prepares a search of all files below `root`, which also computes the replacement
+    /// for each hit. Hidden files and directories as well as binary files are ignored.
+    /// Run it with [`ProjectSearch::find_step`].
+    pub fn new(
+        root: &Path,
+        pattern: &str,
+        options: SearchOptions,
+        replacement: &str,
+    ) -> apperr::Result<Self> {
+        let mut search = Self {
+            root: root.to_path_buf(),
+            pattern: pattern.to_string(),
+            options,
+            replacement: replacement.to_string(),
+            ..Default::default()
+        };
+
+        // Fail early on bad patterns, instead of once per file.
+        search.matcher = Some(Arc::new(options.matcher(pattern)?));
+
+        search.pending_dirs.push(root.to_path_buf());
+        Ok(search)
+    }
+
+    /// Lists directories and searches files until `deadline`. Returns whether it's done.
+    pub fn find_step(&mut self, deadline: Instant) -> bool {
+        while Instant::now() < deadline {
+            if let Some(dir) = self.pending_dirs.pop() {
+                self.list_dir(&dir);
+                if self.pending_dirs.is_empty() {
+                    self.paths.sort();
+                    if let Some(matcher) = &self.matcher {
+                        self.pool = Some(Pool::spawn(&self.paths, matcher));
+                    }
+                }
+            } else if let Some(path) = self.paths.get(self.searched).cloned() {
+                // The results are taken in the order of `paths`, whichever thread is done first.
+                let Some(scan) = self.pool.as_mut().and_then(|p| p.take(self.searched, deadline))
+                else {
+                    break;
+                };
+                self.searched += 1;
+                let name = self.display_name(&path);
+                let res = match scan {
+                    Scan::Miss => continue,
+                    Scan::Hit => self.search_file(&path),
+                    Scan::Skip(reason) => Err(reason),
+                };
+                match res {
+                    Ok(Some(matches)) if !matches.is_empty() => {
+                        let selected = vec![true; matches.len()];
+                        self.files.push(ProjectFile { path, name, matches, selected });
+                    }
+                    Ok(_) => {}
+                    Err(reason) => self.skipped.push(SkippedFile { name, reason }),
+                }
+            } else {
+                self.pool = None;
+                return true;
+            }
+        }
+        false
+    }
+
+    /// How many files were searched. It's unknown how many there are until all directories were listed.
+    pub fn find_progress(&self) -> Progress {
+        if self.pending_dirs.is_empty() {
+            Progress { done: self.searched as u64, total: self.paths.len() as u64 }
+        } else {
+            Progress { done: self.paths.len() as u64, total: 0 }
+        }
+    }
+
+    /// Number of matches that are currently selected for replacement.
+    pub fn selected_count(&self) -> usize {
+        self.files.iter().map(|f| f.selected.iter().filter(|&&s| s).count()).sum()
+    }
+
+    /// Prepares to apply all selected replacements with [`ProjectSearch::replace_step`].
+    pub fn start_replace(&mut self) {
+        self.replacing = mem::take(&mut self.files);
+        self.replaced = 0;
+        self.summary = ReplaceSummary::default();
+    }
+
+    /// Applies the selected replacements until `deadline`. Returns whether it's done.
+    /// Each file is replaced atomically and the original is backed up like on a save, see `backup`.
+    /// `is_unsaved` is called for every file and allows the caller to veto files with pending
+    /// edits. Files that fail are appended to [`ProjectSearch::skipped`].
+    pub fn replace_step(
+        &mut self,
+        deadline: Instant,
+        backup: &Backup,
+        mut is_unsaved: impl FnMut(&Path) -> bool,
+    ) -> bool {
+        while Instant::now() < deadline {
+            let Some(file) = self.replacing.get(self.replaced) else {
+                return true;
+            };
+            self.replaced += 1;
+
+            let matches: Vec<FindMatch> = file
+                .matches
+                .iter()
+                .zip(&file.selected)
+                .filter(|&(_, &s)| s)
+                .map(|(m, _)| m.clone())
+                .collect();
+            if matches.is_empty() {
+                continue;
+            }
+
+            let res = if is_unsaved(&file.path) {
+                Err(SkipReason::Unsaved)
+            } else {
+                self.apply_file(file, &matches, backup)
+            };
+
+            match res {
+                Ok(()) => {
+                    self.summary.files += 1;
+                    self.summary.matches += matches.len();
+                }
+                Err(reason) => {
+                    let name = file.name.clone();
+                    self.skipped.push(SkippedFile { name, reason });
+                }
+            }
+        }
+        false
+    }
+
+    pub fn replace_progress(&self) -> Progress {
+        Progress { done: self.replaced as u64, total: self.replacing.len() as u64 }
+    }
+
+    /// Ends the replacing, even if it was cancelled halfway. The files that weren't
+    /// gotten to yet show up in the preview again. Returns the files that were.
+    pub fn finish_replace(&mut self) -> (ReplaceSummary, Vec<PathBuf>) {
+        self.files = self.replacing.split_off(self.replaced);
+        let done = mem::take(&mut self.replacing).into_iter().map(|file| file.path).collect();
+        (self.summary, done)
+    }
+
+    fn list_dir(&mut self, dir: &Path) {
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(err) => {
+                let name = self.display_name(dir);
+                self.skipped.push(SkippedFile { name, reason: SkipReason::Io(err.into()) });
+                return;
+            }
+        };
+
+        for entry in entries.flatten() {
+            if entry.file_name().as_encoded_bytes().starts_with(b".") {
+                continue;
+            }
+
+            let path = entry.path();
+            match entry.file_type() {
+                Ok(t) if t.is_dir() => self.pending_dirs.push(path),
+                Ok(t) if t.is_file() => self.paths.push(path),
+                _ => {}
+            }
+        }
+    }
+
+    /// Returns `Ok(None)` for binary files.
+    fn search_file(&self, path: &Path) -> Result<Option<Vec<FindMatch>>, SkipReason> {
+        let Some(tb) = load_text_buffer(path)? else {
+            return Ok(None);
+        };
+        tb.find_all(&self.pattern, self.options, self.replacement.as_bytes())
+            .map(Some)
+            .map_err(SkipReason::Io)
+    }
+
+    fn apply_file(
+        &self,
+        file: &ProjectFile,
+        matches: &[FindMatch],
+        backup: &Backup,
+    ) -> Result<(), SkipReason> {
+        let Some(mut tb) = load_text_buffer(&file.path)? else {
+            return Err(SkipReason::Changed);
+        };
+
+        // Make sure that the preview the user looked at is still accurate.
+        let current = tb
+            .find_all(&self.pattern, self.options, self.replacement.as_bytes())
+            .map_err(SkipReason::Io)?;
+        if current.len() != file.matches.len()
+            || current.iter().zip(&file.matches).any(|(a, b)| a.range != b.range)
+        {
+            return Err(SkipReason::Changed);
+        }
+
+        tb.replace_matches(matches);
+        write_atomic(&file.path, &mut tb, backup)
+    }
+
+    fn display_name(&self, path: &Path) -> String {
+        path.strip_prefix(&self.root).unwrap_or(path).to_string_lossy().into_owned()
+    }
+}
+
+/// What a worker found out about a file.
+enum Scan {
+    /// It doesn't contain a match or it's binary.
+    Miss,
+    /// It contains at least one match.
+    Hit,
+    Skip(SkipReason),
+}
+
+/// Threads that check which files contain a match. Each takes the next file that no one
+/// has taken yet. They stop after the file they're at, once the pool is dropped.
+struct Pool {
+    results: Receiver<(usize, Scan)>,
+    /// Results that arrived ahead of the ones before them, by index.
+    ready: Vec<Option<Scan>>,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl Pool {
+    fn spawn(paths: &[PathBuf], matcher: &Arc<icu::Matcher>) -> Self {
+        let paths: Arc<[PathBuf]> = paths.into();
+        let next = Arc::new(AtomicUsize::new(0));
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let (sender, results) = mpsc::channel();
+
+        // Phones have anywhere from 2 to 12 cores, and these days mostly 8.
+        let cores = thread::available_parallelism().map_or(1, |n| n.get());
+        let workers = cores.saturating_sub(1).clamp(1, MAX_WORKERS).min(paths.len());
+
+        for _ in 0..workers {
+            let paths = paths.clone();
+            let next = next.clone();
+            let cancelled = cancelled.clone();
+            let matcher = matcher.clone();
+            let sender = sender.clone();
+            thread::spawn(move || {
+                while !cancelled.load(Ordering::Relaxed) {
+                    let index = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(path) = paths.get(index) else {
+                        break;
+                    };
+                    if sender.send((index, scan_file(path, &matcher))).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+
+        Self { results, ready: paths.iter().map(|_| None).collect(), cancelled }
+    }
+
+    /// Waits until `deadline` for the result of the file at `index`.
+    fn take(&mut self, index: usize, deadline: Instant) -> Option<Scan> {
+        loop {
+            if let Some(scan) = self.ready[index].take() {
+                return Some(scan);
+            }
+            let timeout = deadline.saturating_duration_since(Instant::now());
+            match self.results.recv_timeout(timeout) {
+                Ok((i, scan)) => self.ready[i] = Some(scan),
+                Err(RecvTimeoutError::Timeout) => return None,
+                // Can't happen, since a worker only stops early if we're gone.
+                Err(RecvTimeoutError::Disconnected) => return Some(Scan::Miss),
+            }
+        }
+    }
+}
+
+impl Drop for Pool {
+    fn drop(&mut self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Runs on a worker thread.
+fn scan_file(path: &Path, matcher: &icu::Matcher) -> Scan {
+    let io = |err: std::io::Error| Scan::Skip(SkipReason::Io(err.into()));
+    let mut file = match File::open(path) {
+        Ok(file) => file,
+        Err(err) => return io(err),
+    };
+
+    // Large files are mapped, which saves copying them.
+    let len = file.metadata().map_or(0, |m| m.len());
+    let map = if len >= MAP_SIZE { sys::FileMap::new(&file).ok() } else { None };
+    let mut read = Vec::new();
+    let contents = match &map {
+        Some(map) => &map[..],
+        None => match file.read_to_end(&mut read) {
+            Ok(_) => &read[..],
+            Err(err) => return io(err),
+        },
+    };
+
+    if is_binary(contents) {
+        return Scan::Miss;
+    }
+    let text = contents.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(contents);
+    match str::from_utf8(text) {
+        Ok(text) if matcher.is_match(text) => Scan::Hit,
+        Ok(_) => Scan::Miss,
+        Err(_) => Scan::Skip(SkipReason::Encoding),
+    }
+}
+
+/// Returns `Ok(None)` for binary files.
+fn load_text_buffer(path: &Path) -> Result<Option<TextBuffer>, SkipReason> {
+    let contents = fs::read(path).map_err(|err| SkipReason::Io(err.into()))?;
+    if is_binary(&contents) {
+        return Ok(None);
+    }
+
+    let text = contents.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(&contents);
+    if str::from_utf8(text).is_err() {
+        return Err(SkipReason::Encoding);
+    }
+
+    let mut file = File::open(path).map_err(|err| SkipReason::Io(err.into()))?;
+    // Small buffers can't hold more than 128 KiB.
+    let small = contents.len() <= SMALL_FILE_SIZE;
+    let mut tb = TextBuffer::new(small).map_err(SkipReason::Io)?;
+    tb.read_file(&mut file, Some("UTF-8")).map_err(SkipReason::Io)?;
+    Ok(Some(tb))
+}
+
+fn is_binary(contents: &[u8]) -> bool {
+    contents[..contents.len().min(BINARY_SNIFF_LEN)].contains(&0)
+}
+
+/// Saves `tb` to `path`, after backing up the previous contents according to `backup`.
+fn write_atomic(path: &Path, tb: &mut TextBuffer, backup: &Backup) -> Result<(), SkipReason> {
+    backup.create(path).map_err(|err| SkipReason::Save(SaveError::at(SaveStage::Backup)(err)))?;
+    save::save_file(path, tb).map_err(SkipReason::Save)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backup::BackupMode;
+
+    #[test]
+    fn test_is_binary() {
+        assert!(!is_binary(b""));
+        assert!(!is_binary(b"hello\nworld\n"));
+        assert!(is_binary(b"\x7fELF\x02\x01\x01\x00"));
+
+        let mut late_nul = vec![b'a'; BINARY_SNIFF_LEN];
+        late_nul.push(0);
+        assert!(!is_binary(&late_nul));
+    }
+
+    #[test]
+    fn test_replace_in_dir() {
+        stdext::arena::init(16 * edit::helpers::MEBI).unwrap();
+
+        let root = std::env::temp_dir().join(format!("edit-project-search-{}", std::process::id()));
+        _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("sub")).unwrap();
+        fs::write(root.join("a.txt"), "foo bar\nbar foo\n").unwrap();
+        fs::write(root.join("sub/b.txt"), "\u{feff}foo\n").unwrap();
+        fs::write(root.join("latin1.txt"), b"foo \xE9\n").unwrap();
+        fs::write(root.join("binary.bin"), b"foo\0").unwrap();
+
+        let deadline = Instant::now() + std::time::Duration::from_secs(60);
+        let mut search = ProjectSearch::new(&root, "foo", SearchOptions::default(), "baz").unwrap();
+        assert!(search.find_step(deadline));
+        assert_eq!(search.find_progress(), Progress { done: 4, total: 4 });
+        assert_eq!(search.files.len(), 2);
+        assert_eq!(search.files[0].matches.len(), 2);
+        assert_eq!(search.skipped.len(), 1);
+        assert!(matches!(search.skipped[0].reason, SkipReason::Encoding));
+
+        // Deselect the second hit in a.txt.
+        search.files[0].selected[1] = false;
+        search.start_replace();
+        let backup = Backup { mode: BackupMode::Tilde, ..Default::default() };
+        assert!(search.replace_step(deadline, &backup, |_| false));
+        let (summary, done) = search.finish_replace();
+        assert_eq!((summary.files, summary.matches), (2, 2));
+        assert_eq!(done.len(), 2);
+        assert!(search.files.is_empty());
+
+        assert_eq!(fs::read_to_string(root.join("a.txt")).unwrap(), "baz bar\nbar foo\n");
+        assert_eq!(fs::read_to_string(root.join("a.txt~")).unwrap(), "foo bar\nbar foo\n");
+        assert_eq!(fs::read_to_string(root.join("sub/b.txt")).unwrap(), "\u{feff}baz\n");
+
+        _ = fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn test_replace_without_backup() {
+        stdext::arena::init(16 * MEBI).unwrap();
+
+        let root = std::env::temp_dir().join(format!("edit-project-nobak-{}", std::process::id()));
+        _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("sub")).unwrap();
+        fs::write(
+            root.join("a.txt"),
+            "foo
+",
+        )
+        .unwrap();
+        fs::write(
+            root.join("sub/b.txt"),
+            "foo
+",
+        )
+        .unwrap();
+
+        let deadline = Instant::now() + std::time::Duration::from_secs(60);
+        let mut search = ProjectSearch::new(&root, "foo", SearchOptions::default(), "baz").unwrap();
+        assert!(search.find_step(deadline));
+        search.start_replace();
+        assert!(search.replace_step(deadline, &Backup::default(), |_| false));
+        assert_eq!(search.finish_replace().0.files, 2);
+
+        assert_eq!(
+            fs::read_to_string(root.join("a.txt")).unwrap(),
+            "baz
+"
+        );
+        for dir in [root.clone(), root.join("sub")] {
+            let names: Vec<_> =
+                fs::read_dir(dir).unwrap().flatten().map(|e| e.file_name()).collect();
+            assert!(names.iter().all(|n| !n.to_string_lossy().ends_with('~')), "{names:?}");
+        }
+
+        _ = fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn test_find_is_ordered() {
+        stdext::arena::init(16 * MEBI).unwrap();
+
+        let root = std::env::temp_dir().join(format!("edit-project-find-{}", std::process::id()));
+        _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        for i in 0..40 {
+            fs::write(root.join(format!("{i:02}.txt")), "foo\n").unwrap();
+        }
+        // Large enough to be mapped.
+        let mut big = vec![b'x'; MAP_SIZE as usize];
+        big.extend_from_slice(b"\nfoo\n");
+        fs::write(root.join("big.txt"), &big).unwrap();
+
+        let deadline = Instant::now() + std::time::Duration::from_secs(60);
+        let mut search = ProjectSearch::new(&root, "foo", SearchOptions::default(), "").unwrap();
+        assert!(search.find_step(deadline));
+
+        let names: Vec<_> = search.files.iter().map(|f| f.name.as_str()).collect();
+        let mut expected: Vec<_> = (0..40).map(|i| format!("{i:02}.txt")).collect();
+        expected.push("big.txt".to_string());
+        assert_eq!(names, expected);
+        assert_eq!(search.files[40].matches[0].line, 1);
+
+        _ = fs::remove_dir_all(&root);
+    }
+}
@@ -0,0 +1,106 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Remembers the recently opened files.
+//!
+//! The list is kept in the state directory (usually `~/.local/state/edit/recent`,
+//! see [`crate::paths`]) with one absolute path per line, most recent
+//! first. It's meant to be read by scripts, too. For instance, a Termux:Widget
+//! shortcut in `~/.shortcuts` can reopen a file from the home screen:
+//!
+//! ```sh
+//! #!/data/data/com.termux/files/usr/bin/sh
+//! exec edit --pick-recent
+//! ```
+
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::{env, fs, io};
+
+use crate::paths::{self, Kind};
+
+const MAX_ENTRIES: usize = 30;
+
+/// Returns the recent files, most recent first. Ones that no longer exist are skipped.
+pub fn load() -> Vec<PathBuf> {
+    let Some(file) = list_path() else {
+        return Vec::new();
+    };
+    let text = fs::read_to_string(file).unwrap_or_default();
+    parse(&text).into_iter().filter(|path| path.is_file()).collect()
+}
+
+/// Moves `path` to the top of the list. Failing to do so isn't worth bothering the user with.
+pub fn add(path: &Path) {
+    // Temporary files, like the working copies of Android documents, can't be reopened later.
+    if path.starts_with(env::temp_dir()) || path.starts_with(paths::runtime_dir()) {
+        return;
+    }
+    if let Some(file) = list_path() {
+        _ = add_to(&file, path);
+    }
+}
+
+fn add_to(file: &Path, path: &Path) -> io::Result<()> {
+    let text = match fs::read_to_string(file) {
+        Ok(text) => text,
+        Err(err) if err.kind() == ErrorKind::NotFound => String::new(),
+        Err(err) => return Err(err),
+    };
+
+    let mut list = parse(&text);
+    list.retain(|p| p != path);
+    list.insert(0, path.to_path_buf());
+    list.truncate(MAX_ENTRIES);
+
+    let mut text = String::new();
+    for p in &list {
+        // The format can't represent these, and they're rare enough to not bother.
+        let Some(p) = p.to_str().filter(|p| !p.contains(['\n', '\r'])) else {
+            continue;
+        };
+        text.push_str(p);
+        text.push('\n');
+    }
+
+    // Write it to the side first, so that other instances never read half a list.
+    if let Some(dir) = file.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let tmp = file.with_extension("tmp");
+    fs::write(&tmp, text)?;
+    fs::rename(&tmp, file)
+}
+
+fn parse(text: &str) -> Vec<PathBuf> {
+    text.lines().map(str::trim_end).filter(|l| !l.is_empty()).map(PathBuf::from).collect()
+}
+
+fn list_path() -> Option<PathBuf> {
+    Some(paths::dir(Kind::State)?.join("recent"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_add_to() {
+        let dir = env::temp_dir().join(format!("edit-test-recent-{}", std::process::id()));
+        let file = dir.join("state/recent");
+
+        add_to(&file, Path::new("/a")).unwrap();
+        add_to(&file, Path::new("/b")).unwrap();
+        add_to(&file, Path::new("/a")).unwrap();
+        assert_eq!(fs::read_to_string(&file).unwrap(), "/a\n/b\n");
+
+        for i in 0..MAX_ENTRIES {
+            add_to(&file, Path::new(&format!("/{i}"))).unwrap();
+        }
+        let list = parse(&fs::read_to_string(&file).unwrap());
+        assert_eq!(list.len(), MAX_ENTRIES);
+        assert_eq!(list[0], Path::new(&format!("/{}", MAX_ENTRIES - 1)));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,83 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Edit > Registers lists the clipboard, the earlier clipboard contents that its
+//! kill ring holds on to (see [`edit::clipboard::Clipboard::ring`]), and the named
+//! registers `a` to `z`. Enter pastes the highlighted entry, and a letter stores
+//! the selection (or the clipboard, if there's none) in that register.
+
+/// How much of an entry the picker shows at most.
+const PREVIEW_CHARS: usize = 200;
+
+#[derive(Default)]
+pub struct Registers {
+    named: [Vec<u8>; 26],
+}
+
+impl Registers {
+    pub fn get(&self, name: char) -> Option<&[u8]> {
+        let data = &self.named[index(name)?];
+        (!data.is_empty()).then_some(data.as_slice())
+    }
+
+    pub fn set(&mut self, name: char, data: Vec<u8>) {
+        if let Some(i) = index(name) {
+            self.named[i] = data;
+        }
+    }
+
+    /// The registers that hold something, in alphabetical order.
+    pub fn iter(&self) -> impl Iterator<Item = (char, &[u8])> {
+        ('a'..='z').filter_map(|name| Some((name, self.get(name)?)))
+    }
+}
+
+fn index(name: char) -> Option<usize> {
+    name.is_ascii_lowercase().then(|| (name as u8 - b'a') as usize)
+}
+
+/// A single line preview of `data` for the picker, with runs of whitespace
+/// (including the newlines) collapsed into a single space.
+pub fn preview(data: &[u8]) -> String {
+    // The clipboard may be huge, but only the start of it is shown anyway.
+    let text = String::from_utf8_lossy(&data[..data.len().min(PREVIEW_CHARS * 4)]);
+    let mut preview = String::new();
+    let mut chars = 0;
+    for word in text.split_whitespace() {
+        if !preview.is_empty() {
+            preview.push(' ');
+        }
+        for c in word.chars() {
+            if chars == PREVIEW_CHARS {
+                preview.push('…');
+                return preview;
+            }
+            preview.push(c);
+            chars += 1;
+        }
+    }
+    preview
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_registers() {
+        let mut registers = Registers::default();
+        registers.set('b', b"two".to_vec());
+        registers.set('a', b"one".to_vec());
+        registers.set('A', b"ignored".to_vec());
+        assert_eq!(registers.get('a'), Some(&b"one"[..]));
+        assert_eq!(registers.get('c'), None);
+        assert_eq!(registers.iter().collect::<Vec<_>>(), [('a', &b"one"[..]), ('b', &b"two"[..])]);
+    }
+
+    #[test]
+    fn test_preview() {
+        assert_eq!(preview(b"fn main() {\n    println!();\n}\n"), "fn main() { println!(); }");
+        assert_eq!(preview(b"  \t\n"), "");
+        assert_eq!(preview(&b"x".repeat(1000)).chars().count(), PREVIEW_CHARS + 1);
+    }
+}
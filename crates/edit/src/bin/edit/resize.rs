@@ -0,0 +1,50 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Resizes come in bursts: While the window's edge is dragged, or while Android
+//! animates a rotation, there's a new size every few milliseconds. Each one
+//! reallocates the framebuffer and lays everything out anew, so [`Resize`] holds
+//! them back until they stop for [`DEBOUNCE`] and only passes on the last one.
+
+use std::time::{Duration, Instant};
+
+use edit::helpers::Size;
+
+/// How long the size must stay the same before it's applied.
+const DEBOUNCE: Duration = Duration::from_millis(50);
+
+#[derive(Default)]
+pub struct Resize {
+    pending: Option<(Size, Instant)>,
+}
+
+impl Resize {
+    /// Takes the new `size`, given the `current` one. Returns whether it's held back.
+    /// The first one isn't, since nothing can be drawn without a size.
+    pub fn hold(&mut self, size: Size, current: Size) -> bool {
+        if current.width == 0 || current.height == 0 {
+            self.pending = None;
+            return false;
+        }
+        self.pending = Some((size, Instant::now() + DEBOUNCE));
+        true
+    }
+
+    /// How long the main loop may block waiting for input: Until a held back size is due.
+    pub fn read_timeout(&self) -> Duration {
+        match self.pending {
+            Some((_, due)) => due.saturating_duration_since(Instant::now()),
+            None => Duration::MAX,
+        }
+    }
+
+    /// The held back size, once the burst is over.
+    pub fn take(&mut self) -> Option<Size> {
+        let (size, due) = self.pending?;
+        if Instant::now() < due {
+            return None;
+        }
+        self.pending = None;
+        Some(size)
+    }
+}
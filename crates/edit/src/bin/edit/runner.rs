@@ -0,0 +1,127 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Edit > Run Command runs a shell command in the working directory, like a build,
+//! with its output in a panel below the editor. It runs in a pseudo terminal,
+//! see [`Terminal::run`], so that compilers keep their colors.
+//!
+//! The locations in error messages can be clicked. Those understood are
+//! `file:line:col: ...` as gcc, clang and many others print them,
+//! `--> file:line:col` as rustc does and `File "file", line N` as Python does.
+
+use std::env;
+use std::path::PathBuf;
+
+use edit::apperr;
+use edit::helpers::{CoordType, Point, Size};
+
+use crate::terminal::{Cell, Terminal};
+
+pub struct Runner {
+    pub term: Terminal,
+    /// The last command. Rerun runs it again.
+    pub command: String,
+    /// Where it ran. The locations in its output are relative to it.
+    cwd: PathBuf,
+}
+
+impl Runner {
+    pub fn new() -> Self {
+        Self { term: Terminal::new(), command: String::new(), cwd: PathBuf::new() }
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.term.is_running()
+    }
+
+    /// Runs [`Runner::command`] in the working directory. What ran before is killed.
+    pub fn run(&mut self, size: Size) -> apperr::Result<()> {
+        self.cwd = env::current_dir()?;
+        self.term.run(&self.command, &self.cwd, size)
+    }
+
+    /// Interrupts the command as Ctrl+C in a terminal would.
+    pub fn stop(&mut self) {
+        self.term.write(b"\x03");
+    }
+
+    /// The location that the line `index` of the output refers to, if it's an existing file.
+    pub fn location(&self, index: usize) -> Option<(PathBuf, Point)> {
+        let text = line_text(self.term.screen().line(index));
+        let (file, pos) = parse_location(&text)?;
+        let path = self.cwd.join(file);
+        path.is_file().then_some((path, pos))
+    }
+}
+
+/// The text of a line of the output, without its colors.
+pub fn line_text(line: &[Cell]) -> String {
+    line.iter().map(|cell| cell.ch).filter(|&ch| ch != '\0').collect()
+}
+
+/// Finds a location like `src/main.rs:12:5` in a line of a compiler's output.
+/// The position is 0-based, while the compilers count from 1.
+pub fn parse_location(line: &str) -> Option<(&str, Point)> {
+    let line = line.trim();
+
+    if let Some(rest) = line.strip_prefix("File \"") {
+        let (file, rest) = rest.split_once('"')?;
+        let rest = rest.strip_prefix(", line ")?;
+        let (y, _) = parse_number(rest)?;
+        return Some((file, Point { x: 0, y }));
+    }
+
+    let line = line.strip_prefix("--> ").unwrap_or(line);
+    // The file name ends at the first colon followed by a line number.
+    let mut from = 0;
+    while let Some(i) = line[from..].find(':') {
+        let colon = from + i;
+        from = colon + 1;
+        let file = &line[..colon];
+        let Some((y, rest)) = parse_number(&line[colon + 1..]) else {
+            continue;
+        };
+        let (x, rest) = match rest.strip_prefix(':').and_then(parse_number) {
+            Some((x, rest)) => (x, rest),
+            None => (0, rest),
+        };
+        // A time like 12:30:45 isn't a file, neither is `see foo.c:12` in the middle of a sentence.
+        let valid = !file.is_empty()
+            && !file.contains(char::is_whitespace)
+            && !file.bytes().all(|b| b.is_ascii_digit())
+            && (rest.is_empty() || rest.starts_with(':'));
+        return valid.then_some((file, Point { x, y }));
+    }
+    None
+}
+
+/// Parses the 1-based number at the start of `s` into a 0-based one, followed by the rest.
+fn parse_number(s: &str) -> Option<(CoordType, &str)> {
+    let len = s.bytes().take_while(u8::is_ascii_digit).count();
+    let n: CoordType = s[..len].parse().ok()?;
+    Some(((n - 1).max(0), &s[len..]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_location() {
+        let at = |x, y| Point { x, y };
+        assert_eq!(parse_location("main.c:12:5: error: expected ';'"), Some(("main.c", at(4, 11))));
+        assert_eq!(
+            parse_location("Makefile:3: *** missing separator."),
+            Some(("Makefile", at(0, 2)))
+        );
+        assert_eq!(parse_location("   --> src/main.rs:10:17"), Some(("src/main.rs", at(16, 9))));
+        assert_eq!(
+            parse_location("  File \"app/views.py\", line 42, in index"),
+            Some(("app/views.py", at(0, 41)))
+        );
+        assert_eq!(parse_location("12:30:45 started"), None);
+        assert_eq!(parse_location("see main.c:12 for details"), None);
+        assert_eq!(parse_location("warning: unused variable: `x`"), None);
+        assert_eq!(parse_location(""), None);
+    }
+}
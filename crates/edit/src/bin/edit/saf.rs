@@ -0,0 +1,332 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Opens files through Android's Storage Access Framework (SAF).
+//!
+//! SAF reaches places Termux can't traverse directly, like cloud drives or
+//! other apps' folders. `termux-storage-get` can pick a single file, but it
+//! only hands out a copy without telling us where it came from, so saving
+//! couldn't write it back. Instead, the user grants access to a folder with
+//! `termux-saf-managedir`, we list it with `termux-saf-ls` and copy the chosen
+//! file into a working location with `termux-saf-read`. Saving the working copy
+//! writes it back with `termux-saf-write`.
+//!
+//! Other apps can also hand us a single `content://` URI, e.g. through a
+//! `~/bin/termux-file-editor` script that runs `edit "$1"`. It's read and
+//! written back the same way, provided that Termux:API may access it.
+//!
+//! All commands run on a worker thread, because they wait for Android UI.
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash as _, Hasher as _};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::time::Duration;
+use std::{fs, thread};
+
+use crate::clipboard_backend::{find_in_path, run_with_timeout};
+use crate::json::{self, Json};
+use crate::paths;
+
+/// The folder picker waits for the user, so it gets plenty of time.
+const PICK_TIMEOUT: Duration = Duration::from_secs(600);
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(30);
+/// How often to check for results while a command is running.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+#[derive(Clone)]
+pub struct SafEntry {
+    pub name: String,
+    pub uri: String,
+    pub is_dir: bool,
+}
+
+pub struct SafDir {
+    pub name: String,
+    pub uri: String,
+    pub entries: Vec<SafEntry>,
+}
+
+pub enum SafEvent {
+    /// The contents of a folder, in response to [`Saf::pick_dir`] or [`Saf::list`].
+    Dir(SafDir),
+    /// A file was copied to `path`, in response to [`Saf::open`].
+    Opened {
+        uri: String,
+        path: PathBuf,
+    },
+    /// The user dismissed the folder picker.
+    Cancelled,
+    /// The working copy was written back, in response to [`Saf::write_back`].
+    Written,
+    Failed(String),
+}
+
+enum Job {
+    PickDir,
+    List { name: String, uri: String },
+    Read(SafEntry),
+    Write { uri: String, path: PathBuf },
+}
+
+pub struct Saf {
+    available: bool,
+    worker: Option<(Sender<Job>, Receiver<SafEvent>)>,
+    busy: usize,
+}
+
+impl Saf {
+    pub fn new() -> Self {
+        Self { available: find_in_path("termux-saf-managedir"), worker: None, busy: 0 }
+    }
+
+    /// Whether the Termux:API SAF commands are installed.
+    pub fn is_available(&self) -> bool {
+        self.available
+    }
+
+    /// Lets the user pick a folder and lists it.
+    pub fn pick_dir(&mut self) {
+        self.send(Job::PickDir);
+    }
+
+    pub fn list(&mut self, entry: &SafEntry) {
+        self.send(Job::List { name: entry.name.clone(), uri: entry.uri.clone() });
+    }
+
+    /// Copies the file into a working location.
+    pub fn open(&mut self, entry: &SafEntry) {
+        self.send(Job::Read(entry.clone()));
+    }
+
+    /// Writes the working copy at `path` back to the document at `uri`.
+    pub fn write_back(&mut self, uri: &str, path: &Path) {
+        self.send(Job::Write { uri: uri.to_string(), path: path.to_path_buf() });
+    }
+
+    /// Whether a command is still running.
+    pub fn is_busy(&self) -> bool {
+        self.busy != 0
+    }
+
+    pub fn poll(&mut self) -> Option<SafEvent> {
+        let (_, events) = self.worker.as_ref()?;
+        let event = events.try_recv().ok()?;
+        self.busy -= 1;
+        Some(event)
+    }
+
+    /// How long the main loop may block waiting for input before checking for results.
+    pub fn read_timeout(&self) -> Duration {
+        if self.is_busy() { POLL_INTERVAL } else { Duration::MAX }
+    }
+
+    fn send(&mut self, job: Job) {
+        let (jobs, _) = self.worker.get_or_insert_with(spawn_worker);
+        if jobs.send(job).is_ok() {
+            self.busy += 1;
+        }
+    }
+}
+
+fn spawn_worker() -> (Sender<Job>, Receiver<SafEvent>) {
+    let (jobs, job_receiver) = mpsc::channel();
+    let (event_sender, events) = mpsc::channel();
+
+    thread::spawn(move || {
+        while let Ok(job) = job_receiver.recv() {
+            let event = match job {
+                Job::PickDir => pick_dir(),
+                Job::List { name, uri } => list(name, uri),
+                Job::Read(entry) => read(entry),
+                Job::Write { uri, path } => write(&uri, &path),
+            };
+            if event_sender.send(event).is_err() {
+                break;
+            }
+        }
+    });
+
+    (jobs, events)
+}
+
+fn pick_dir() -> SafEvent {
+    let Some(output) =
+        run_with_timeout(&mut Command::new("termux-saf-managedir"), &[], PICK_TIMEOUT)
+    else {
+        return SafEvent::Failed("termux-saf-managedir failed".to_string());
+    };
+    match parse_entries(&output).and_then(|dirs| dirs.into_iter().next()) {
+        Some(dir) => list(dir.name, dir.uri),
+        None => SafEvent::Cancelled,
+    }
+}
+
+fn list(name: String, uri: String) -> SafEvent {
+    let mut cmd = Command::new("termux-saf-ls");
+    cmd.arg(&uri);
+    match run_with_timeout(&mut cmd, &[], COMMAND_TIMEOUT).and_then(|output| parse_entries(&output))
+    {
+        Some(mut entries) => {
+            entries.sort_by(|a, b| b.is_dir.cmp(&a.is_dir).then_with(|| a.name.cmp(&b.name)));
+            SafEvent::Dir(SafDir { name, uri, entries })
+        }
+        None => SafEvent::Failed(format!("termux-saf-ls failed: {name}")),
+    }
+}
+
+fn read(entry: SafEntry) -> SafEvent {
+    let mut cmd = Command::new("termux-saf-read");
+    cmd.arg(&entry.uri);
+    let Some(contents) = run_with_timeout(&mut cmd, &[], COMMAND_TIMEOUT) else {
+        return SafEvent::Failed(format!("termux-saf-read failed: {}", entry.name));
+    };
+
+    let path = working_path(&entry);
+    let res =
+        path.parent().map_or(Ok(()), fs::create_dir_all).and_then(|_| fs::write(&path, contents));
+    match res {
+        Ok(()) => SafEvent::Opened { uri: entry.uri, path },
+        Err(err) => SafEvent::Failed(format!("{}: {err}", path.display())),
+    }
+}
+
+fn write(uri: &str, path: &Path) -> SafEvent {
+    let contents = match fs::read(path) {
+        Ok(contents) => contents,
+        Err(err) => return SafEvent::Failed(format!("{}: {err}", path.display())),
+    };
+    let mut cmd = Command::new("termux-saf-write");
+    cmd.arg(uri);
+    match run_with_timeout(&mut cmd, &contents, COMMAND_TIMEOUT) {
+        Some(_) => SafEvent::Written,
+        None => SafEvent::Failed(format!("termux-saf-write failed: {}", path.display())),
+    }
+}
+
+/// Makes an entry for a `content://` URI we were given without a folder listing.
+/// The name is guessed from the URI, whose last segment usually is a
+/// percent-encoded document ID, like `primary%3ADocuments%2Fnotes.txt`.
+pub fn entry_from_uri(uri: &str) -> SafEntry {
+    let segment = uri.rsplit('/').next().unwrap_or_default();
+    let id = percent_decode(segment);
+    let name = id.rsplit(['/', ':']).next().filter(|name| !name.is_empty()).unwrap_or("document");
+    SafEntry { name: name.to_string(), uri: uri.to_string(), is_dir: false }
+}
+
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut res = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes.get(i + 1..i + 3).and_then(|hex| str::from_utf8(hex).ok());
+        match hex.filter(|_| bytes[i] == b'%').and_then(|hex| u8::from_str_radix(hex, 16).ok()) {
+            Some(b) => {
+                res.push(b);
+                i += 3;
+            }
+            None => {
+                res.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&res).into_owned()
+}
+
+/// Each document gets its own directory, so that it keeps its name
+/// and files with the same name from different places don't clash.
+fn working_path(entry: &SafEntry) -> PathBuf {
+    let mut hasher = DefaultHasher::new();
+    entry.uri.hash(&mut hasher);
+    let name = entry.name.replace(['/', '\\', ':'], "_");
+    paths::runtime_dir().join("saf").join(format!("{:016x}", hasher.finish())).join(name)
+}
+
+/// Parses the JSON that the `termux-saf-*` commands print: Either a single object or an array of them.
+fn parse_entries(output: &[u8]) -> Option<Vec<SafEntry>> {
+    let text = str::from_utf8(output).ok()?.trim();
+    if text.is_empty() {
+        return Some(Vec::new());
+    }
+
+    let objects = match json::parse(text)? {
+        Json::Array(items) => items,
+        obj @ Json::Object(_) => vec![obj],
+        _ => return None,
+    };
+
+    objects
+        .into_iter()
+        .map(|obj| {
+            let Json::Object(fields) = obj else {
+                return None;
+            };
+            let get = |key: &str| {
+                fields.iter().find(|(k, _)| k == key).and_then(|(_, v)| match v {
+                    Json::String(s) => Some(s.clone()),
+                    _ => None,
+                })
+            };
+            Some(SafEntry {
+                name: get("name")?,
+                uri: get("uri")?,
+                is_dir: get("type").is_some_and(|t| t == "inode/directory"),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_entries() {
+        let output = br#"[
+  {"name": "notes", "type": "inode/directory", "uri": "content://a/notes", "length": 0},
+  {"name": "todo \"2\".md", "type": "text/markdown", "uri": "content://a/todo", "length": 1.5e3,
+   "writable": true, "extra": null, "nested": {"x": [1, 2]}}
+]"#;
+        let entries = parse_entries(output).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].name, "notes");
+        assert!(entries[0].is_dir);
+        assert_eq!(entries[1].name, "todo \"2\".md");
+        assert_eq!(entries[1].uri, "content://a/todo");
+        assert!(!entries[1].is_dir);
+
+        let single = r#"{"name": "Caf\u00e9", "uri": "content://b"}"#.as_bytes();
+        assert_eq!(parse_entries(single).unwrap()[0].name, "Café");
+
+        assert_eq!(parse_entries(b"").unwrap().len(), 0);
+        assert!(parse_entries(b"[{\"name\": 1}]").is_none());
+        assert!(parse_entries(b"[{\"name\" \"x\"}]").is_none());
+        assert!(parse_entries(b"{\"name\": \"x\", \"uri\": \"y\"").is_none());
+    }
+
+    #[test]
+    fn test_entry_from_uri() {
+        let name = |uri| entry_from_uri(uri).name;
+        assert_eq!(
+            name(
+                "content://com.android.externalstorage.documents/document/primary%3ADocs%2Fa%20b.txt"
+            ),
+            "a b.txt"
+        );
+        assert_eq!(name("content://media/external/file/42"), "42");
+        assert_eq!(name("content://x/document/primary%3A"), "document");
+        assert_eq!(name("content://x/%zz%"), "%zz%");
+    }
+
+    #[test]
+    fn test_working_path() {
+        let a =
+            SafEntry { name: "a/b.txt".to_string(), uri: "content://1".to_string(), is_dir: false };
+        let b =
+            SafEntry { name: "a/b.txt".to_string(), uri: "content://2".to_string(), is_dir: false };
+        assert_eq!(working_path(&a).file_name().unwrap(), "a_b.txt");
+        assert_ne!(working_path(&a), working_path(&b));
+    }
+}
@@ -0,0 +1,216 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Writes a text buffer to disk without risking the previous contents.
+//!
+//! The contents are written to a temporary file in the same directory, flushed
+//! to disk and then renamed over the original. The temporary file inherits the
+//! permissions, owner and extended attributes of the original. Some filesystems
+//! don't support renaming over an existing file (for instance the FUSE mounts
+//! under `/storage` on Android). In that case we fall back to overwriting the
+//! original in place. The same goes for filesystems that refuse to take over the
+//! attributes, since overwriting in place keeps the original ones anyway.
+
+use std::fmt;
+use std::fs::{self, File};
+use std::io::Write as _;
+use std::path::{Path, PathBuf};
+
+use edit::buffer::TextBuffer;
+use edit::{apperr, sys};
+
+use crate::localization::*;
+use crate::state::FormatApperr;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SaveStage {
+    Backup,
+    CreateTemp,
+    Write,
+    Sync,
+    Attributes,
+    Rename,
+    InPlace,
+}
+
+/// An error during [`save_file`], along with the stage at which it occurred.
+#[derive(Clone, Copy, Debug)]
+pub struct SaveError {
+    pub stage: SaveStage,
+    pub err: apperr::Error,
+}
+
+impl SaveError {
+    pub fn at(stage: SaveStage) -> impl FnOnce(apperr::Error) -> Self {
+        move |err| Self { stage, err }
+    }
+}
+
+impl From<SaveError> for apperr::Error {
+    fn from(err: SaveError) -> Self {
+        err.err
+    }
+}
+
+impl fmt::Display for SaveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let stage = loc(match self.stage {
+            SaveStage::Backup => LocId::SaveErrorBackup,
+            SaveStage::CreateTemp => LocId::SaveErrorCreateTemp,
+            SaveStage::Write => LocId::SaveErrorWrite,
+            SaveStage::Sync => LocId::SaveErrorSync,
+            SaveStage::Attributes => LocId::SaveErrorAttributes,
+            SaveStage::Rename => LocId::SaveErrorRename,
+            SaveStage::InPlace => LocId::SaveErrorInPlace,
+        });
+        write!(f, "{stage}: {}", FormatApperr::from(self.err))
+    }
+}
+
+/// Writes `tb` to `path` and marks it as clean.
+pub fn save_file(path: &Path, tb: &mut TextBuffer) -> Result<(), SaveError> {
+    save_file_with(path, tb, sys::copy_file_attributes)
+}
+
+type CopyAttributes = fn(&File, &File) -> apperr::Result<()>;
+
+fn save_file_with(
+    path: &Path,
+    tb: &mut TextBuffer,
+    copy_attributes: CopyAttributes,
+) -> Result<(), SaveError> {
+    // Write through symlinks instead of replacing them with a regular file.
+    let path = match fs::symlink_metadata(path) {
+        Ok(m) if m.file_type().is_symlink() => fs::canonicalize(path).unwrap_or(path.into()),
+        _ => path.to_path_buf(),
+    };
+
+    let original = File::open(&path).ok();
+
+    // Renaming over a file with multiple hard links would split them up.
+    if original.as_ref().is_some_and(is_hard_linked) {
+        write_in_place(&path, tb)?;
+    } else {
+        match write_atomic(&path, original.as_ref(), tb, copy_attributes) {
+            Ok(()) => {}
+            Err(e)
+                if matches!(
+                    e.stage,
+                    SaveStage::Rename | SaveStage::CreateTemp | SaveStage::Attributes
+                ) =>
+            {
+                write_in_place(&path, tb)?
+            }
+            Err(e) => return Err(e),
+        }
+    }
+
+    tb.mark_as_clean();
+    Ok(())
+}
+
+fn write_atomic(
+    path: &Path,
+    original: Option<&File>,
+    tb: &TextBuffer,
+    copy_attributes: CopyAttributes,
+) -> Result<(), SaveError> {
+    let temp = temp_path(path);
+    let mut file = File::options()
+        .write(true)
+        .create_new(true)
+        .open(&temp)
+        .map_err(|err| SaveError { stage: SaveStage::CreateTemp, err: err.into() })?;
+
+    let res = (|| {
+        tb.write_file_copy(&mut file).map_err(SaveError::at(SaveStage::Write))?;
+        file.flush().map_err(|err| SaveError { stage: SaveStage::Write, err: err.into() })?;
+        if let Some(original) = original {
+            copy_attributes(original, &file).map_err(SaveError::at(SaveStage::Attributes))?;
+        }
+        file.sync_all().map_err(|err| SaveError { stage: SaveStage::Sync, err: err.into() })?;
+        drop(file);
+        fs::rename(&temp, path)
+            .map_err(|err| SaveError { stage: SaveStage::Rename, err: err.into() })
+    })();
+
+    if res.is_err() {
+        _ = fs::remove_file(&temp);
+    } else {
+        sync_parent_dir(path);
+    }
+    res
+}
+
+fn write_in_place(path: &Path, tb: &TextBuffer) -> Result<(), SaveError> {
+    let at = |err: std::io::Error| SaveError { stage: SaveStage::InPlace, err: err.into() };
+    let mut file = File::create(path).map_err(at)?;
+    tb.write_file_copy(&mut file).map_err(SaveError::at(SaveStage::InPlace))?;
+    file.flush().map_err(at)?;
+    file.sync_all().map_err(at)?;
+    Ok(())
+}
+
+fn temp_path(path: &Path) -> PathBuf {
+    let mut name = std::ffi::OsString::from(".");
+    name.push(path.file_name().unwrap_or_default());
+    name.push(format!(".{}.tmp", std::process::id()));
+    path.with_file_name(name)
+}
+
+#[cfg(unix)]
+fn is_hard_linked(file: &File) -> bool {
+    use std::os::unix::fs::MetadataExt as _;
+    file.metadata().is_ok_and(|m| m.nlink() > 1)
+}
+
+#[cfg(not(unix))]
+fn is_hard_linked(_: &File) -> bool {
+    false
+}
+
+/// Makes the rename durable. Not supported everywhere, so errors are ignored.
+#[cfg(unix)]
+fn sync_parent_dir(path: &Path) {
+    if let Some(dir) = path.parent()
+        && let Ok(dir) = File::open(if dir.as_os_str().is_empty() { Path::new(".") } else { dir })
+    {
+        _ = dir.sync_all();
+    }
+}
+
+#[cfg(not(unix))]
+fn sync_parent_dir(_: &Path) {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_temp_path() {
+        let p = temp_path(Path::new("/a/b/c.txt"));
+        assert_eq!(p.parent(), Some(Path::new("/a/b")));
+        let name = p.file_name().unwrap().to_str().unwrap();
+        assert!(name.starts_with(".c.txt.") && name.ends_with(".tmp"));
+    }
+
+    #[test]
+    fn test_failing_attributes_fall_back_to_in_place() {
+        stdext::arena::init(16 * edit::helpers::MEBI).unwrap();
+
+        let dir = std::env::temp_dir().join(format!("edit-test-save-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("file.txt");
+        fs::write(&path, "old").unwrap();
+
+        let mut tb = TextBuffer::new(false).unwrap();
+        tb.write_raw(b"new");
+        // Like `fchmod` on the FUSE mounts under `/storage` on Android.
+        save_file_with(&path, &mut tb, |_, _| Err(apperr::Error::new_sys(1))).unwrap();
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "new");
+        assert!(!tb.is_dirty());
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+        _ = fs::remove_dir_all(&dir);
+    }
+}
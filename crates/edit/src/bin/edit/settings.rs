@@ -0,0 +1,314 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! The settings that File > Settings offers. Changes apply right away
+//! and are written to `config.toml`, see [`crate::config`].
+
+use edit::helpers::CoordType;
+
+use crate::localization::LocId;
+use crate::palette::{PALETTES, Palette};
+use crate::state::State;
+use crate::{config, theme};
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum SettingId {
+    TabWidth,
+    IndentWithTabs,
+    WordWrap,
+    LineNumbers,
+    ShowWhitespace,
+    IndentGuides,
+    AutoClose,
+    Color,
+    AmbiguousWidth,
+    Theme,
+    Palette,
+    Menubar,
+    Breadcrumbs,
+}
+
+pub enum SettingKind {
+    Bool,
+    Integer(CoordType, CoordType),
+    /// One of [`SettingsDialog::choices`].
+    Choice,
+}
+
+pub struct Setting {
+    pub id: SettingId,
+    /// The name in the config file.
+    pub name: &'static str,
+    pub label: LocId,
+    pub kind: SettingKind,
+}
+
+pub const SETTINGS: [Setting; 13] = [
+    Setting {
+        id: SettingId::TabWidth,
+        name: "editor.tab_width",
+        label: LocId::SettingsTabWidth,
+        kind: SettingKind::Integer(1, 8),
+    },
+    Setting {
+        id: SettingId::IndentWithTabs,
+        name: "editor.indent_with_tabs",
+        label: LocId::SettingsIndentWithTabs,
+        kind: SettingKind::Bool,
+    },
+    Setting {
+        id: SettingId::WordWrap,
+        name: "editor.word_wrap",
+        label: LocId::SettingsWordWrap,
+        kind: SettingKind::Bool,
+    },
+    Setting {
+        id: SettingId::LineNumbers,
+        name: "editor.line_numbers",
+        label: LocId::SettingsLineNumbers,
+        kind: SettingKind::Bool,
+    },
+    Setting {
+        id: SettingId::ShowWhitespace,
+        name: "editor.show_whitespace",
+        label: LocId::SettingsShowWhitespace,
+        kind: SettingKind::Bool,
+    },
+    Setting {
+        id: SettingId::IndentGuides,
+        name: "editor.indent_guides",
+        label: LocId::SettingsIndentGuides,
+        kind: SettingKind::Bool,
+    },
+    Setting {
+        id: SettingId::AutoClose,
+        name: "editor.auto_close",
+        label: LocId::SettingsAutoClose,
+        kind: SettingKind::Bool,
+    },
+    Setting {
+        id: SettingId::Color,
+        name: "ui.color",
+        label: LocId::SettingsColor,
+        kind: SettingKind::Choice,
+    },
+    Setting {
+        id: SettingId::AmbiguousWidth,
+        name: "ui.ambiguous_width",
+        label: LocId::SettingsAmbiguousWidth,
+        kind: SettingKind::Choice,
+    },
+    Setting {
+        id: SettingId::Theme,
+        name: "ui.theme",
+        label: LocId::SettingsTheme,
+        kind: SettingKind::Choice,
+    },
+    Setting {
+        id: SettingId::Palette,
+        name: "ui.palette",
+        label: LocId::SettingsPalette,
+        kind: SettingKind::Choice,
+    },
+    Setting {
+        id: SettingId::Menubar,
+        name: "ui.menubar",
+        label: LocId::SettingsMenubar,
+        kind: SettingKind::Bool,
+    },
+    Setting {
+        id: SettingId::Breadcrumbs,
+        name: "ui.breadcrumbs",
+        label: LocId::SettingsBreadcrumbs,
+        kind: SettingKind::Bool,
+    },
+];
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum SettingValue {
+    Bool(bool),
+    Integer(CoordType),
+    Choice(String),
+}
+
+impl SettingValue {
+    fn to_toml(&self) -> String {
+        match self {
+            Self::Bool(b) => b.to_string(),
+            Self::Integer(n) => n.to_string(),
+            Self::Choice(s) => config::toml_string(s),
+        }
+    }
+}
+
+/// What the dialog keeps while it's open.
+pub struct SettingsDialog {
+    pub needle: String,
+    /// The text in the fields of integer settings, by index in [`SETTINGS`].
+    pub fields: Vec<String>,
+    /// The installed themes. An empty name stands for the terminal's colors.
+    pub themes: Vec<String>,
+}
+
+impl SettingsDialog {
+    pub fn new(state: &State) -> Self {
+        let fields = SETTINGS
+            .iter()
+            .map(|setting| match get(state, setting.id) {
+                SettingValue::Integer(n) => n.to_string(),
+                _ => String::new(),
+            })
+            .collect();
+        let themes =
+            [String::new()].into_iter().chain(theme::list().into_iter().map(|t| t.name)).collect();
+        Self { needle: String::new(), fields, themes }
+    }
+
+    /// Whether the setting matches what the user searches for.
+    pub fn matches(&self, setting: &Setting, label: &str) -> bool {
+        let needle = self.needle.trim().to_lowercase();
+        needle.is_empty()
+            || label.to_lowercase().contains(&needle)
+            || setting.name.contains(&needle)
+    }
+
+    /// The choices of a [`SettingKind::Choice`] setting.
+    pub fn choices(&self, id: SettingId) -> Vec<&str> {
+        match id {
+            SettingId::Color => config::COLOR_MODES.iter().map(|&(name, _)| name).collect(),
+            SettingId::AmbiguousWidth => {
+                config::AMBIGUOUS_WIDTHS.iter().map(|&(name, _)| name).collect()
+            }
+            SettingId::Palette => PALETTES.iter().map(|&(name, _)| name).collect(),
+            _ => self.themes.iter().map(String::as_str).collect(),
+        }
+    }
+}
+
+pub fn get(state: &State, id: SettingId) -> SettingValue {
+    let defaults = &state.documents.defaults;
+    match id {
+        SettingId::TabWidth => SettingValue::Integer(defaults.tab_size),
+        SettingId::IndentWithTabs => SettingValue::Bool(defaults.indent_with_tabs),
+        SettingId::WordWrap => SettingValue::Bool(defaults.word_wrap),
+        SettingId::LineNumbers => SettingValue::Bool(defaults.line_numbers),
+        SettingId::ShowWhitespace => SettingValue::Bool(defaults.show_whitespace),
+        SettingId::IndentGuides => SettingValue::Bool(defaults.indent_guides),
+        SettingId::AutoClose => SettingValue::Bool(defaults.auto_close),
+        SettingId::Color => {
+            let name = config::COLOR_MODES
+                .iter()
+                .find(|(_, mode)| *mode == state.color_mode)
+                .map_or("auto", |(name, _)| name);
+            SettingValue::Choice(name.to_string())
+        }
+        SettingId::AmbiguousWidth => {
+            let name = config::AMBIGUOUS_WIDTHS
+                .iter()
+                .find(|(_, width)| *width == state.ambiguous_width)
+                .map_or("auto", |(name, _)| name);
+            SettingValue::Choice(name.to_string())
+        }
+        SettingId::Theme => SettingValue::Choice(state.theme.clone().unwrap_or_default()),
+        SettingId::Palette => SettingValue::Choice(state.palette.name().to_string()),
+        SettingId::Menubar => SettingValue::Bool(!state.menubar_hidden),
+        SettingId::Breadcrumbs => SettingValue::Bool(state.breadcrumbs),
+    }
+}
+
+/// Applies the setting and saves it to the config file.
+pub fn set(state: &mut State, setting: &Setting, value: SettingValue) -> Result<(), String> {
+    let defaults = &mut state.documents.defaults;
+    match (setting.id, &value) {
+        (SettingId::TabWidth, &SettingValue::Integer(n)) => defaults.tab_size = n,
+        (SettingId::IndentWithTabs, &SettingValue::Bool(b)) => defaults.indent_with_tabs = b,
+        (SettingId::WordWrap, &SettingValue::Bool(b)) => defaults.word_wrap = b,
+        // Read while drawing, so there's nothing else to do.
+        (SettingId::LineNumbers, &SettingValue::Bool(b)) => defaults.line_numbers = b,
+        (SettingId::ShowWhitespace, &SettingValue::Bool(b)) => defaults.show_whitespace = b,
+        (SettingId::IndentGuides, &SettingValue::Bool(b)) => defaults.indent_guides = b,
+        (SettingId::AutoClose, &SettingValue::Bool(b)) => defaults.auto_close = b,
+        (SettingId::Color, SettingValue::Choice(name)) => {
+            let mode = config::COLOR_MODES.iter().find(|(n, _)| n == name).and_then(|(_, m)| *m);
+            state.color_mode = mode;
+            state.color_mode_change = Some(mode);
+        }
+        (SettingId::AmbiguousWidth, SettingValue::Choice(name)) => {
+            let width =
+                config::AMBIGUOUS_WIDTHS.iter().find(|(n, _)| n == name).and_then(|(_, w)| *w);
+            state.ambiguous_width = width;
+            state.ambiguous_width_change = Some(width);
+        }
+        (SettingId::Theme, SettingValue::Choice(name)) if name.is_empty() => {
+            state.theme = None;
+            state.theme_change = Some(None);
+        }
+        (SettingId::Theme, SettingValue::Choice(name)) => {
+            let theme = theme::load_by_name(name)?;
+            state.theme = Some(name.clone());
+            state.theme_change = Some(Some(theme));
+        }
+        (SettingId::Palette, SettingValue::Choice(name)) => {
+            // The palette goes on top of the theme, which has to be set up again first.
+            let theme = state.theme.as_deref().map(theme::load_by_name).transpose()?;
+            state.palette =
+                PALETTES.iter().find(|(n, _)| n == name).map_or_else(Palette::default, |&(_, p)| p);
+            state.theme_change = Some(theme);
+        }
+        (SettingId::Menubar, &SettingValue::Bool(b)) => state.menubar_hidden = !b,
+        (SettingId::Breadcrumbs, &SettingValue::Bool(b)) => state.breadcrumbs = b,
+        _ => unreachable!(),
+    }
+    if matches!(
+        setting.id,
+        SettingId::TabWidth
+            | SettingId::IndentWithTabs
+            | SettingId::WordWrap
+            | SettingId::ShowWhitespace
+            | SettingId::IndentGuides
+            | SettingId::AutoClose
+    ) {
+        state.documents.apply_defaults();
+    }
+
+    config::save_setting(setting.name, &value.to_toml())?;
+    state.config_watcher.acknowledge();
+    Ok(())
+}
+
+/// Reads the config, keymap and theme files again and applies them, including to
+/// the open documents. If any of them has mistakes, nothing changes.
+pub fn reload(state: &mut State) -> Result<(), String> {
+    let config = config::load()?;
+    let theme = config.theme.as_deref().map(theme::load_by_name).transpose()?;
+    state.softkeys.reload(state.keymap_arg.clone().or(config.keymap))?;
+
+    state.documents.defaults = config.document;
+    state.documents.filetypes = config.filetypes;
+    state.documents.gutter = config.gutter;
+    state.documents.rulers = config.rulers;
+    state.documents.apply_config();
+    state.statusbar = config.statusbar;
+    state.after_save = config.after_save;
+    state.digraphs = config.digraphs;
+
+    if let Some(mode) = config.color_mode
+        && mode != state.color_mode
+    {
+        state.color_mode = mode;
+        state.color_mode_change = Some(mode);
+    }
+    if let Some(width) = config.ambiguous_width
+        && width != state.ambiguous_width
+    {
+        state.ambiguous_width = width;
+        state.ambiguous_width_change = Some(width);
+    }
+    // Even if it's the same theme, its file may have changed.
+    state.theme = config.theme;
+    state.theme_change = Some(theme);
+    state.palette = config.palette;
+    state.menubar_hidden = config.hide_menubar;
+    state.breadcrumbs = config.breadcrumbs;
+    Ok(())
+}
@@ -0,0 +1,275 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! An on-screen row of keys for touch devices.
+//!
+//! Termux's extra-keys row is the same in every app, whereas this bar can
+//! change with the context, e.g. to offer "Replace All" while replacing.
+//! Taps turn into key presses for whatever is focused, so the bar doesn't
+//! steal the focus from the editor.
+//!
+//! The layouts can be changed in the keymap file:
+//!
+//! ```text
+//! # Show the bar even when not running in Termux.
+//! softkeys = on
+//! softkeys.editor = Esc Tab Left Down Up Right Undo Save Find
+//! softkeys.replace = Esc Enter ReplaceAll
+//! ```
+
+use std::path::PathBuf;
+use std::{fs, io};
+
+use edit::input::{InputKey, kbmod, vk};
+
+use crate::localization::{LocId, loc};
+use crate::paths::{self, Kind};
+
+#[derive(Clone, Copy)]
+pub enum SoftkeyAction {
+    /// Sends the key press to the focused control.
+    Key(InputKey),
+    ReplaceAll,
+    Dictate,
+}
+
+enum SoftkeyLabel {
+    Text(&'static str),
+    Loc(LocId),
+}
+
+pub struct Softkey {
+    /// The name used in the keymap file.
+    pub name: &'static str,
+    label: SoftkeyLabel,
+    pub action: SoftkeyAction,
+}
+
+impl Softkey {
+    pub fn label(&self) -> &'static str {
+        match self.label {
+            SoftkeyLabel::Text(text) => text,
+            SoftkeyLabel::Loc(id) => loc(id),
+        }
+    }
+}
+
+const fn key(name: &'static str, label: &'static str, key: InputKey) -> Softkey {
+    Softkey { name, label: SoftkeyLabel::Text(label), action: SoftkeyAction::Key(key) }
+}
+
+const fn shortcut(name: &'static str, label: LocId, key: InputKey) -> Softkey {
+    Softkey { name, label: SoftkeyLabel::Loc(label), action: SoftkeyAction::Key(key) }
+}
+
+pub const SOFTKEYS: &[Softkey] = &[
+    key("Esc", "Esc", vk::ESCAPE),
+    key("Tab", "Tab", vk::TAB),
+    key("Enter", "↵", vk::RETURN),
+    key("Backspace", "⌫", vk::BACK),
+    key("Delete", "Del", vk::DELETE),
+    key("Left", "←", vk::LEFT),
+    key("Right", "→", vk::RIGHT),
+    key("Up", "↑", vk::UP),
+    key("Down", "↓", vk::DOWN),
+    key("Home", "Home", vk::HOME),
+    key("End", "End", vk::END),
+    key("PgUp", "PgUp", vk::PRIOR),
+    key("PgDn", "PgDn", vk::NEXT),
+    shortcut("Save", LocId::FileSave, vk::S.with_modifiers(kbmod::CTRL)),
+    shortcut("Find", LocId::EditFind, vk::F.with_modifiers(kbmod::CTRL)),
+    shortcut("Replace", LocId::EditReplace, vk::R.with_modifiers(kbmod::CTRL)),
+    shortcut("Undo", LocId::EditUndo, vk::Z.with_modifiers(kbmod::CTRL)),
+    shortcut("Redo", LocId::EditRedo, vk::Y.with_modifiers(kbmod::CTRL)),
+    shortcut("Cut", LocId::EditCut, vk::X.with_modifiers(kbmod::CTRL)),
+    shortcut("Copy", LocId::EditCopy, vk::C.with_modifiers(kbmod::CTRL)),
+    shortcut("Paste", LocId::EditPaste, vk::V.with_modifiers(kbmod::CTRL)),
+    shortcut("SelectAll", LocId::EditSelectAll, vk::A.with_modifiers(kbmod::CTRL)),
+    Softkey {
+        name: "ReplaceAll",
+        label: SoftkeyLabel::Loc(LocId::SearchReplaceAll),
+        action: SoftkeyAction::ReplaceAll,
+    },
+    Softkey {
+        name: "Dictate",
+        label: SoftkeyLabel::Loc(LocId::EditDictate),
+        action: SoftkeyAction::Dictate,
+    },
+];
+
+/// Which layout to show.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SoftkeyContext {
+    Editor,
+    Search,
+    Replace,
+}
+
+impl SoftkeyContext {
+    const ALL: [Self; 3] = [Self::Editor, Self::Search, Self::Replace];
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Editor => "editor",
+            Self::Search => "search",
+            Self::Replace => "replace",
+        }
+    }
+}
+
+const DEFAULT_LAYOUTS: [&str; 3] = [
+    "Esc Tab Left Down Up Right Undo Save Find",
+    "Esc Tab Enter Up Down",
+    "Esc Tab Enter ReplaceAll",
+];
+
+pub struct Softkeys {
+    pub enabled: bool,
+    /// Set if the keymap file turned the bar on or off.
+    configured: bool,
+    layouts: [Vec<&'static Softkey>; 3],
+    pending: Option<InputKey>,
+}
+
+impl Softkeys {
+    pub fn new() -> Self {
+        Self {
+            enabled: false,
+            configured: false,
+            layouts: DEFAULT_LAYOUTS.map(|layout| parse_layout(layout).unwrap()),
+            pending: None,
+        }
+    }
+
+    /// Shows or hides the bar, unless the keymap file says otherwise.
+    pub fn set_enabled_by_default(&mut self, enabled: bool) {
+        if !self.configured {
+            self.enabled = enabled;
+        }
+    }
+
+    pub fn layout(&self, context: SoftkeyContext) -> &[&'static Softkey] {
+        &self.layouts[context as usize]
+    }
+
+    /// Queues a key press to be sent once the current frame is done.
+    pub fn press(&mut self, key: InputKey) {
+        self.pending = Some(key);
+    }
+
+    pub fn take_pending(&mut self) -> Option<InputKey> {
+        self.pending.take()
+    }
+
+    /// Applies the keymap file at `path`, or the default one if `None`.
+    /// A missing default file is not an error.
+    pub fn load(&mut self, path: Option<PathBuf>) -> Result<(), String> {
+        let explicit = path.is_some();
+        let Some(path) = path.or_else(default_keymap_path) else {
+            return Ok(());
+        };
+
+        match fs::read_to_string(&path) {
+            Ok(text) => self.parse(&text).map_err(|err| format!("{}:{}", path.display(), err)),
+            Err(err) if !explicit && err.kind() == io::ErrorKind::NotFound => Ok(()),
+            Err(err) => Err(format!("{}: {}", path.display(), err)),
+        }
+    }
+
+    /// Like [`Softkeys::load`], but starts over from the default layouts, as the
+    /// keymap file may have been changed. Nothing changes if it has mistakes.
+    pub fn reload(&mut self, path: Option<PathBuf>) -> Result<(), String> {
+        let mut fresh = Self::new();
+        fresh.load(path)?;
+        if fresh.configured {
+            self.enabled = fresh.enabled;
+        }
+        self.configured = fresh.configured;
+        self.layouts = fresh.layouts;
+        Ok(())
+    }
+
+    /// Parses the contents of a keymap file.
+    /// Errors are formatted as `line: message`.
+    fn parse(&mut self, text: &str) -> Result<(), String> {
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let err = |msg: &str| Err(format!("{}: {}", i + 1, msg));
+            let Some((name, value)) = line.split_once('=') else {
+                return err("expected `name = value`");
+            };
+            let (name, value) = (name.trim(), value.trim());
+
+            if name == "softkeys" {
+                self.enabled = match value {
+                    "on" => true,
+                    "off" => false,
+                    _ => return err("expected `on` or `off`"),
+                };
+                self.configured = true;
+            } else if let Some(context) = name
+                .strip_prefix("softkeys.")
+                .and_then(|ctx| SoftkeyContext::ALL.into_iter().find(|c| c.name() == ctx))
+            {
+                match parse_layout(value) {
+                    Ok(layout) => self.layouts[context as usize] = layout,
+                    Err(key) => return err(&format!("unknown key `{key}`")),
+                }
+            } else {
+                return err(&format!("unknown setting `{name}`"));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Parses a space separated list of key names. Returns the first unknown one on failure.
+fn parse_layout(layout: &str) -> Result<Vec<&'static Softkey>, &str> {
+    layout
+        .split_whitespace()
+        .map(|name| SOFTKEYS.iter().find(|k| k.name.eq_ignore_ascii_case(name)).ok_or(name))
+        .collect()
+}
+
+pub fn default_keymap_path() -> Option<PathBuf> {
+    Some(paths::dir(Kind::Config)?.join("keymap"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(softkeys: &Softkeys, context: SoftkeyContext) -> Vec<&'static str> {
+        softkeys.layout(context).iter().map(|k| k.name).collect()
+    }
+
+    #[test]
+    fn test_parse() {
+        let mut softkeys = Softkeys::new();
+        assert_eq!(
+            names(&softkeys, SoftkeyContext::Replace),
+            ["Esc", "Tab", "Enter", "ReplaceAll"]
+        );
+
+        softkeys
+            .parse("# comment\n\nsoftkeys = off\nsoftkeys.editor = esc  Left Right  Save\n")
+            .unwrap();
+        assert!(!softkeys.enabled);
+        softkeys.set_enabled_by_default(true);
+        assert!(!softkeys.enabled);
+        assert_eq!(names(&softkeys, SoftkeyContext::Editor), ["Esc", "Left", "Right", "Save"]);
+        assert_eq!(names(&softkeys, SoftkeyContext::Search), ["Esc", "Tab", "Enter", "Up", "Down"]);
+
+        assert_eq!(softkeys.parse("softkeys.editor = Esc Foo"), Err("1: unknown key `Foo`".into()));
+        assert_eq!(
+            softkeys.parse("\nsoftkeys.menu = Esc"),
+            Err("2: unknown setting `softkeys.menu`".into())
+        );
+        assert_eq!(softkeys.parse("softkeys"), Err("1: expected `name = value`".into()));
+        assert_eq!(softkeys.parse("softkeys = maybe"), Err("1: expected `on` or `off`".into()));
+    }
+}
@@ -5,15 +5,60 @@ use std::borrow::Cow;
 use std::ffi::{OsStr, OsString};
 use std::mem;
 use std::path::{Path, PathBuf};
+use std::rc::Weak;
 
-use edit::framebuffer::IndexedColor;
+use edit::framebuffer::{ColorMode, INDEXED_COLORS_COUNT, IndexedColor};
 use edit::helpers::*;
 use edit::oklab::StraightRgba;
 use edit::tui::*;
 use edit::{apperr, buffer, icu, sys};
 
+use crate::autosave::Autosave;
+use crate::back::Back;
+use crate::backup::Backup;
+use crate::chord::Chords;
+use crate::clipboard_backend::ClipboardBackend;
+use crate::config::ConfigWatcher;
+use crate::ctags::Tag;
+use crate::dictation::Dictation;
+use crate::diff::Diff;
+use crate::digraph::Digraph;
 use crate::documents::DocumentManager;
+use crate::feedback::Feedback;
+use crate::filetree::FileTree;
+use crate::git::BranchCache;
+use crate::keyboard::Keyboard;
 use crate::localization::*;
+use crate::markdown::Preview;
+use crate::notify::Notifier;
+use crate::osc52::Osc52;
+use crate::palette::Palette;
+use crate::power::Power;
+use crate::pretty::Language;
+#[cfg(feature = "profiler")]
+use crate::profiler::Profiler;
+use crate::project_search::{ProjectSearch, ReplaceSummary};
+use crate::registers::Registers;
+use crate::resize::Resize;
+use crate::runner::Runner;
+use crate::saf::{Saf, SafDir};
+use crate::settings::SettingsDialog;
+use crate::softkeys::Softkeys;
+use crate::statusbar::StatusbarLayout;
+use crate::storage::Place;
+use crate::task::Task;
+use crate::terminal::Terminal;
+use crate::theme::{Theme, ThemeFile};
+use crate::toast::{Severity, Toasts};
+use crate::vim::Vim;
+
+/// Below this many columns (e.g. a phone in portrait), the UI switches to a compact layout:
+/// Shorter statusbar segments, no line numbers, and dialog buttons stacked vertically.
+pub const COMPACT_WIDTH: CoordType = 60;
+
+pub fn is_compact(ctx: &Context) -> bool {
+    ctx.size().width < COMPACT_WIDTH
+}
 
 #[repr(transparent)]
 pub struct FormatApperr(apperr::Error);
@@ -28,6 +73,7 @@ impl std::fmt::Display for FormatApperr {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self.0 {
             apperr::APP_ICU_MISSING => f.write_str(loc(LocId::ErrorIcuMissing)),
+            apperr::APP_READ_ONLY => f.write_str(loc(LocId::ErrorReadOnly)),
             apperr::Error::App(code) => write!(f, "Unknown app error code: {code}"),
             apperr::Error::Icu(code) => icu::apperr_format(f, code),
             apperr::Error::Sys(code) => sys::apperr_format(f, code),
@@ -92,6 +138,31 @@ impl<T: ?Sized + AsRef<OsStr>> From<&T> for DisplayablePathBuf {
     }
 }
 
+/// The dropdown of a segment of the breadcrumb bar, see `draw_breadcrumbs`.
+pub struct BreadcrumbMenu {
+    pub segment: usize,
+    pub entries: Vec<BreadcrumbEntry>,
+}
+
+pub enum BreadcrumbEntry {
+    File(PathBuf),
+    Dir(PathBuf),
+    /// A symbol and its 1-based line.
+    Symbol(String, CoordType),
+}
+
+impl BreadcrumbEntry {
+    pub fn label(&self) -> Cow<'_, str> {
+        match self {
+            Self::File(path) => path.file_name().unwrap_or_default().to_string_lossy(),
+            Self::Dir(path) => {
+                Cow::Owned(format!("{}/", path.file_name().unwrap_or_default().to_string_lossy()))
+            }
+            Self::Symbol(name, _) => Cow::Borrowed(name),
+        }
+    }
+}
+
 pub struct StateSearch {
     pub kind: StateSearchKind,
     pub focus: bool,
@@ -109,9 +180,12 @@ pub enum StateSearchKind {
 pub enum StateFilePicker {
     None,
     Open,
+    OpenFolder,
     SaveAs,
+    Export,
 
     SaveAsShown, // Transitioned from SaveAs
+    ExportShown, // Transitioned from Export
 }
 
 #[derive(Clone, Copy, PartialEq, Eq)]
@@ -121,6 +195,40 @@ pub enum StateEncodingChange {
     Reopen,
 }
 
+/// Selection > Surround With… and friends, which wait for a bracket or quote to be typed.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum StateSurround {
+    None,
+    Add,
+    Change,
+    /// Change Surrounding… once the one to replace was typed.
+    ChangeTo(char),
+    Delete,
+}
+
+/// Edit > Insert Character… (searching by name) and Insert Codepoint… (asking for `U+XXXX`).
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum StateInsertChar {
+    None,
+    Picker,
+    Codepoint,
+}
+
+/// Ctrl+K K, waiting for the two characters of a digraph.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum StateDigraph {
+    None,
+    First,
+    Second(char),
+}
+
+pub struct StateTooltip {
+    pub anchor: Point,
+    pub text: String,
+    // Where the cursor was when it was shown. It goes away once that changes.
+    pub cursor: Point,
+}
+
 #[derive(Default)]
 pub struct OscTitleFileStatus {
     pub filename: String,
@@ -128,6 +236,12 @@ pub struct OscTitleFileStatus {
 }
 
 pub struct State {
+    pub menubar_hidden: bool, // `ui.menubar = false`
+    pub menubar_open: bool,   // Shown anyway, see `draw_menubar`.
+    pub breadcrumbs: bool,    // `ui.breadcrumbs`
+    pub breadcrumb_menu: Option<BreadcrumbMenu>,
+    /// The tags of the active document, for the path in front.
+    pub breadcrumb_tags: Option<(PathBuf, Vec<Tag>)>,
     pub menubar_color_bg: StraightRgba,
     pub menubar_color_fg: StraightRgba,
 
@@ -138,6 +252,13 @@ pub struct State {
     pub error_log_index: usize,
     pub error_log_count: usize,
 
+    pub toasts: Toasts,
+    pub wants_notification_history: bool,
+    pub task: Option<Task>, // The long operation that's running, if any.
+    pub wants_task_step: bool,
+    pub wants_load_step: bool, // Like `wants_task_step`, but for the documents that are loading.
+    pub wants_highlight_step: bool, // Like `wants_task_step`, but for the background highlighting.
+
     pub wants_file_picker: StateFilePicker,
     pub file_picker_pending_dir: DisplayablePathBuf,
     pub file_picker_pending_dir_revision: u64, // Bumped every time `file_picker_pending_dir` changes.
@@ -145,6 +266,9 @@ pub struct State {
     pub file_picker_entries: Option<[Vec<DisplayablePathBuf>; 3]>, // ["..", directories, files]
     pub file_picker_overwrite_warning: Option<PathBuf>,            // The path the warning is about.
     pub file_picker_autocomplete: Vec<DisplayablePathBuf>,
+    pub file_picker_autocomplete_index: Option<usize>, // The one Tab put into the name.
+    pub file_picker_places: Option<Vec<Place>>,
+    pub file_picker_error: Option<String>, // Why `file_picker_pending_dir` couldn't be listed.
 
     pub wants_search: StateSearch,
     pub search_needle: String,
@@ -154,6 +278,12 @@ pub struct State {
     // (current_index, total_count)
     pub search_count: Option<(usize, usize)>,
 
+    pub wants_project_replace: bool,
+    pub project_replace_dir: String,
+    pub project_replace: Option<ProjectSearch>,
+    pub project_replace_view: VirtualList, // The rows of the preview, see `draw_project_replace`.
+    pub project_replace_summary: Option<ReplaceSummary>,
+
     pub wants_encoding_picker: bool,
     pub wants_encoding_change: StateEncodingChange,
     pub encoding_picker_needle: String,
@@ -161,24 +291,109 @@ pub struct State {
 
     pub wants_save: bool,
     pub wants_statusbar_focus: bool,
+    pub statusbar: StatusbarLayout,
+    pub filetree: FileTree,
+    pub wants_filetree_focus: bool,
+    pub wants_editor_focus: bool,
+    pub terminal: Terminal,
+    pub wants_terminal_focus: bool,
+    pub runner: Runner,
+    pub markdown_preview: Option<Preview>,
+    /// `edit -d`, shown in place of the editor.
+    pub diff: Option<Diff>,
+    pub wants_runner_focus: bool,
+    pub wants_run_dialog: bool,
+    pub git_branch: BranchCache,
     pub wants_indentation_picker: bool,
+    pub wants_newline_picker: bool,
     pub wants_go_to_file: bool,
     pub wants_about: bool,
     pub wants_close: bool,
     pub wants_exit: bool,
     pub wants_goto: bool,
+    pub wants_context_menu: Option<Point>, // Where the editor's context menu is open.
+    pub tooltip: Option<StateTooltip>,
+    pub wants_open_in_app: bool,
+    pub wants_goto_this: bool,
+    pub wants_share: bool,
+    pub share_subject: Option<String>,
+    pub wants_recent_picker: bool,
+    pub recent_files: Option<Vec<PathBuf>>,
+    pub wants_register_picker: bool,
+    pub registers: Registers,
+    pub welcome_recent: Option<Vec<PathBuf>>,
+    pub wants_saf_browser: bool,
+    pub saf_dirs: Vec<SafDir>, // The folders the user navigated through, innermost last.
+    pub wants_theme_picker: bool,
+    pub wants_filetype_picker: bool,
+    pub wants_format: bool,
+    pub wants_pretty_print: Option<Language>,
+    pub wants_filter: bool,
+    pub filter_command: String, // The last one, offered again.
+    pub wants_surround: StateSurround,
+    pub wants_codec: bool,
+    pub wants_insert_char: StateInsertChar,
+    pub insert_char_needle: String,
+    pub insert_char_results: Vec<char>,
+    pub digraph: StateDigraph,
+    pub wants_char_inspector: bool,
+    pub digraphs: Vec<Digraph>, // The user's, from the config.
+    pub wants_settings: bool,
+    pub settings: Option<SettingsDialog>,
+    pub wants_config_reload: bool,
+    pub config_watcher: ConfigWatcher,
+    pub keymap_arg: Option<PathBuf>, // From `--keymap`, which wins over the config.
+    pub themes: Option<Vec<ThemeFile>>,
+    pub theme: Option<String>, // The active theme's name. `None` for the terminal's colors.
+    pub theme_change: Option<Option<Theme>>, // Applied by the main loop.
+    pub palette: Palette,      // Remaps colors of the theme.
+    pub terminal_colors: Option<[StraightRgba; INDEXED_COLORS_COUNT]>, // If it told us.
     pub goto_target: String,
     pub goto_invalid: bool,
 
+    pub open_read_only: bool,
+    pub stdout_buffer: Option<buffer::RcTextBuffer>, // Written to stdout on exit.
+    pub wait_for: Vec<Weak<buffer::TextBufferCell>>, // `--wait`: Exit once they're closed.
+    pub batch_script: Option<PathBuf>,               // `--batch`: Run it instead of the UI.
+    pub color_mode: Option<ColorMode>,               // `None` to detect it.
+    pub color_mode_change: Option<Option<ColorMode>>, // Applied by the main loop.
+    pub detected_color_mode: ColorMode,
+    pub ambiguous_width: Option<CoordType>, // `None` to detect it.
+    pub ambiguous_width_change: Option<Option<CoordType>>, // Applied by the main loop.
+    pub detected_ambiguous_width: CoordType,
+    pub autosave: Autosave,
+    pub backup: Backup,
+    pub after_save: Option<String>, // A shell command to run on the saved file.
+    pub vim: Vim,
+    pub chords: Chords,
+    pub back: Back,
+    pub keyboard: Keyboard,
+
     pub osc_title_file_status: OscTitleFileStatus,
+    pub clipboard_backend: Option<Box<dyn ClipboardBackend>>,
     pub osc_clipboard_sync: bool,
     pub osc_clipboard_always_send: bool,
+    pub osc52: Osc52,
+    pub softkeys: Softkeys,
+    pub saf: Saf,
+    pub power: Power,
+    pub resize: Resize,
+    #[cfg(feature = "profiler")]
+    pub profiler: Profiler,
+    pub feedback: Feedback,
+    pub dictation: Dictation,
+    pub notifier: Notifier,
     pub exit: bool,
 }
 
 impl State {
     pub fn new() -> apperr::Result<Self> {
         Ok(Self {
+            menubar_hidden: false,
+            menubar_open: false,
+            breadcrumbs: false,
+            breadcrumb_menu: None,
+            breadcrumb_tags: None,
             menubar_color_bg: StraightRgba::zero(),
             menubar_color_fg: StraightRgba::zero(),
 
@@ -188,6 +403,13 @@ impl State {
             error_log_index: 0,
             error_log_count: 0,
 
+            toasts: Toasts::new(),
+            wants_notification_history: false,
+            task: None,
+            wants_task_step: false,
+            wants_load_step: false,
+            wants_highlight_step: false,
+
             wants_file_picker: StateFilePicker::None,
             file_picker_pending_dir: Default::default(),
             file_picker_pending_dir_revision: 0,
@@ -195,6 +417,9 @@ impl State {
             file_picker_entries: None,
             file_picker_overwrite_warning: None,
             file_picker_autocomplete: Vec::new(),
+            file_picker_autocomplete_index: None,
+            file_picker_places: None,
+            file_picker_error: None,
 
             wants_search: StateSearch { kind: StateSearchKind::Hidden, focus: false },
             search_needle: Default::default(),
@@ -203,25 +428,110 @@ impl State {
             search_success: true,
             search_count: None,
 
+            wants_project_replace: false,
+            project_replace_dir: Default::default(),
+            project_replace: None,
+            project_replace_view: VirtualList::new(),
+            project_replace_summary: None,
+
             wants_encoding_picker: false,
             encoding_picker_needle: Default::default(),
             encoding_picker_results: Default::default(),
 
             wants_save: false,
             wants_statusbar_focus: false,
+            statusbar: Default::default(),
+            filetree: FileTree::new(),
+            wants_filetree_focus: false,
+            wants_editor_focus: false,
+            terminal: Terminal::new(),
+            wants_terminal_focus: false,
+            runner: Runner::new(),
+            markdown_preview: None,
+            diff: None,
+            wants_runner_focus: false,
+            wants_run_dialog: false,
+            git_branch: Default::default(),
             wants_encoding_change: StateEncodingChange::None,
             wants_indentation_picker: false,
+            wants_newline_picker: false,
             wants_go_to_file: false,
             wants_about: false,
             wants_close: false,
             wants_exit: false,
             wants_goto: false,
+            wants_context_menu: None,
+            tooltip: None,
+            wants_open_in_app: false,
+            wants_goto_this: false,
+            wants_share: false,
+            share_subject: None,
+            wants_recent_picker: false,
+            recent_files: None,
+            wants_register_picker: false,
+            registers: Default::default(),
+            welcome_recent: None,
+            wants_saf_browser: false,
+            saf_dirs: Vec::new(),
+            wants_theme_picker: false,
+            wants_filetype_picker: false,
+            wants_format: false,
+            wants_pretty_print: None,
+            wants_filter: false,
+            filter_command: Default::default(),
+            wants_surround: StateSurround::None,
+            wants_codec: false,
+            wants_insert_char: StateInsertChar::None,
+            insert_char_needle: Default::default(),
+            insert_char_results: Default::default(),
+            digraph: StateDigraph::None,
+            wants_char_inspector: false,
+            digraphs: Default::default(),
+            wants_settings: false,
+            settings: None,
+            wants_config_reload: false,
+            config_watcher: Default::default(),
+            keymap_arg: None,
+            themes: None,
+            theme: None,
+            theme_change: None,
+            palette: Palette::Default,
+            terminal_colors: None,
             goto_target: Default::default(),
             goto_invalid: false,
 
+            open_read_only: false,
+            stdout_buffer: None,
+            wait_for: Vec::new(),
+            batch_script: None,
+            color_mode: None,
+            color_mode_change: None,
+            detected_color_mode: ColorMode::TrueColor,
+            ambiguous_width: None,
+            ambiguous_width_change: None,
+            detected_ambiguous_width: 1,
+            autosave: Autosave::new(),
+            backup: Default::default(),
+            after_save: None,
+            vim: Vim::new(),
+            chords: Chords::new(),
+            back: Back::new(),
+            keyboard: Keyboard::new(),
+
             osc_title_file_status: Default::default(),
+            clipboard_backend: None,
             osc_clipboard_sync: false,
             osc_clipboard_always_send: false,
+            osc52: Osc52::new(),
+            softkeys: Softkeys::new(),
+            saf: Saf::new(),
+            power: Power::new(),
+            resize: Resize::default(),
+            #[cfg(feature = "profiler")]
+            profiler: Profiler::new(),
+            feedback: Feedback::new(),
+            dictation: Dictation::new(),
+            notifier: Notifier::new(),
             exit: false,
         })
     }
@@ -234,15 +544,26 @@ pub fn draw_add_untitled_document(ctx: &mut Context, state: &mut State) {
 }
 
 pub fn error_log_add(ctx: &mut Context, state: &mut State, err: apperr::Error) {
-    let msg = format!("{}", FormatApperr::from(err));
+    error_log_add_message(ctx, state, format!("{}", FormatApperr::from(err)));
+}
+
+pub fn error_log_add_message(ctx: &mut Context, state: &mut State, msg: String) {
     if !msg.is_empty() {
+        state.toasts.add_to_history(Severity::Error, msg.clone());
         state.error_log[state.error_log_index] = msg;
         state.error_log_index = (state.error_log_index + 1) % state.error_log.len();
         state.error_log_count = state.error_log.len().min(state.error_log_count + 1);
+        state.feedback.notify(ctx);
         ctx.needs_rerender();
     }
 }
 
+/// Shows a message in the top right corner for a few seconds, see [`crate::toast`].
+pub fn toast_add(ctx: &mut Context, state: &mut State, severity: Severity, msg: String) {
+    state.toasts.add(severity, msg);
+    ctx.needs_rerender();
+}
+
 pub fn draw_error_log(ctx: &mut Context, state: &mut State) {
     ctx.modal_begin("error", loc(LocId::ErrorDialogTitle));
     ctx.attr_background_rgba(ctx.indexed(IndexedColor::Red));
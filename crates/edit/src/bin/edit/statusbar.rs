@@ -0,0 +1,142 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! What the status bar shows and where. The layout can be changed in the config,
+//! see [`crate::config`], and [`fit`] decides what's left out on narrow screens.
+
+use edit::helpers::CoordType;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Segment {
+    Character,
+    GitBranch,
+    Encoding,
+    LineEnding,
+    FileType,
+    Indentation,
+    Mode,
+    Position,
+    Overtype,
+    ReadOnly,
+    Dirty,
+    FileName,
+}
+
+/// The names in the config, least important first: That's the order in which
+/// they're left out when the status bar is too narrow.
+pub const SEGMENTS: [(&str, Segment); 12] = [
+    ("character", Segment::Character),
+    ("git_branch", Segment::GitBranch),
+    ("encoding", Segment::Encoding),
+    ("line_ending", Segment::LineEnding),
+    ("filetype", Segment::FileType),
+    ("indentation", Segment::Indentation),
+    ("mode", Segment::Mode),
+    ("position", Segment::Position),
+    ("overtype", Segment::Overtype),
+    ("read_only", Segment::ReadOnly),
+    ("dirty", Segment::Dirty),
+    ("filename", Segment::FileName),
+];
+
+/// The file name is truncated in the middle rather than left out,
+/// but it should stay recognizable.
+const MIN_FILENAME_WIDTH: CoordType = 12;
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct StatusbarLayout {
+    /// Aligned to the left edge, in this order.
+    pub left: Vec<Segment>,
+    /// Aligned to the right edge.
+    pub right: Vec<Segment>,
+}
+
+impl Default for StatusbarLayout {
+    fn default() -> Self {
+        Self {
+            left: vec![
+                Segment::Mode,
+                Segment::FileType,
+                Segment::LineEnding,
+                Segment::Encoding,
+                Segment::Indentation,
+                Segment::Position,
+                Segment::Overtype,
+                Segment::ReadOnly,
+                Segment::Dirty,
+            ],
+            right: vec![Segment::GitBranch, Segment::FileName],
+        }
+    }
+}
+
+fn importance(segment: Segment) -> usize {
+    SEGMENTS.iter().position(|&(_, s)| s == segment).unwrap_or(0)
+}
+
+/// Leaves out the least important segments until the rest fits into `available` columns,
+/// with `gap` columns between each. `widths` are those of the shown segments, and the
+/// ones left out are set to 0. Returns how wide the file name can be.
+pub fn fit(widths: &mut [(Segment, CoordType)], gap: CoordType, available: CoordType) -> CoordType {
+    let total = |widths: &[(Segment, CoordType)]| {
+        widths
+            .iter()
+            .filter(|&&(_, width)| width > 0)
+            .map(|&(segment, width)| {
+                let width = if segment == Segment::FileName {
+                    width.min(MIN_FILENAME_WIDTH)
+                } else {
+                    width
+                };
+                width + gap
+            })
+            .sum::<CoordType>()
+            - gap
+    };
+
+    while total(widths) > available {
+        let least = widths
+            .iter_mut()
+            .filter(|(segment, width)| *width > 0 && *segment != Segment::FileName)
+            .min_by_key(|(segment, _)| importance(*segment));
+        match least {
+            Some((_, width)) => *width = 0,
+            None => break,
+        }
+    }
+
+    let others = widths
+        .iter()
+        .filter(|&&(segment, width)| width > 0 && segment != Segment::FileName)
+        .map(|&(_, width)| width + gap)
+        .sum::<CoordType>();
+    (available - others).max(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fit() {
+        let mut widths = [
+            (Segment::Mode, 6),
+            (Segment::LineEnding, 4),
+            (Segment::Position, 5),
+            (Segment::GitBranch, 6),
+            (Segment::FileName, 20),
+        ];
+
+        // Everything fits.
+        assert_eq!(fit(&mut widths, 1, 80), 80 - 6 - 4 - 5 - 6 - 4);
+        assert!(widths.iter().all(|&(_, width)| width > 0));
+
+        // The branch goes first, then the line ending. The file name gets the rest.
+        assert_eq!(fit(&mut widths, 1, 25), 25 - 6 - 5 - 2);
+        assert_eq!(widths.map(|(_, width)| width), [6, 0, 5, 0, 20],);
+
+        // The file name is never left out.
+        assert_eq!(fit(&mut widths, 1, 5), 5);
+        assert_eq!(widths.map(|(_, width)| width), [0, 0, 0, 0, 20]);
+    }
+}
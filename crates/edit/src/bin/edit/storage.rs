@@ -0,0 +1,112 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Knows where the interesting directories are on Android.
+//!
+//! Termux keeps its own files in `$HOME` and `$PREFIX`. After running
+//! `termux-setup-storage`, `~/storage` contains symlinks to the shared
+//! storage, the downloads, and any external SD cards. Those live on a FUSE
+//! file system which refuses to list some directories (e.g. `/storage/emulated`),
+//! even though they can be entered.
+
+use std::path::{Path, PathBuf};
+use std::{env, fs, io};
+
+use crate::localization::*;
+
+pub struct Place {
+    pub label: String,
+    pub path: PathBuf,
+}
+
+/// Returns the directories worth jumping to from the file picker.
+pub fn places() -> Vec<Place> {
+    let home = env::var_os("HOME").map(PathBuf::from);
+    let prefix = env::var_os("PREFIX").map(PathBuf::from);
+    find_places(home.as_deref(), prefix.as_deref(), Path::new("/storage"))
+}
+
+fn find_places(home: Option<&Path>, prefix: Option<&Path>, storage_root: &Path) -> Vec<Place> {
+    let mut places = Vec::new();
+    let mut add = |label: String, path: PathBuf| {
+        if path.is_dir() {
+            places.push(Place { label, path });
+        }
+    };
+
+    if let Some(home) = home {
+        add(loc(LocId::FilePickerHome).to_string(), home.to_path_buf());
+        add(loc(LocId::FilePickerShared).to_string(), home.join("storage/shared"));
+        add(loc(LocId::FilePickerDownloads).to_string(), home.join("storage/downloads"));
+    }
+    if let Some(prefix) = prefix {
+        add("$PREFIX".to_string(), prefix.to_path_buf());
+    }
+
+    // Removable storage shows up as /storage/XXXX-XXXX, next to the internal "emulated" and "self".
+    if let Ok(iter) = fs::read_dir(storage_root) {
+        let mut cards: Vec<_> = iter
+            .flatten()
+            .map(|entry| entry.file_name().to_string_lossy().into_owned())
+            .filter(|name| name != "emulated" && name != "self")
+            .collect();
+        cards.sort();
+        for name in cards {
+            let label = format!("{} ({name})", loc(LocId::FilePickerSdCard));
+            add(label, storage_root.join(name));
+        }
+    }
+
+    places
+}
+
+/// Returns a hint on how to resolve `err` when listing `dir`, if there's one.
+pub fn read_dir_hint(dir: &Path, err: &io::Error) -> Option<&'static str> {
+    let is_storage = dir.starts_with("/storage")
+        || dir.starts_with("/sdcard")
+        || dir.components().any(|c| c.as_os_str() == "storage");
+    (err.kind() == io::ErrorKind::PermissionDenied && is_storage)
+        .then(|| loc(LocId::FilePickerStorageHint))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_places() {
+        let root = env::temp_dir().join(format!("edit-test-storage-{}", std::process::id()));
+        let home = root.join("home");
+        let storage = root.join("storage");
+        for dir in
+            ["home/storage/shared", "usr", "storage/emulated", "storage/self", "storage/1A2B-3C4D"]
+        {
+            fs::create_dir_all(root.join(dir)).unwrap();
+        }
+
+        let places = find_places(Some(&home), Some(&root.join("usr")), &storage);
+        let paths: Vec<_> = places.iter().map(|p| p.path.strip_prefix(&root).unwrap()).collect();
+        assert_eq!(
+            paths,
+            [
+                Path::new("home"),
+                Path::new("home/storage/shared"),
+                Path::new("usr"),
+                Path::new("storage/1A2B-3C4D")
+            ]
+        );
+        assert!(places[3].label.ends_with("(1A2B-3C4D)"));
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_read_dir_hint() {
+        let denied = io::Error::from(io::ErrorKind::PermissionDenied);
+        let missing = io::Error::from(io::ErrorKind::NotFound);
+        assert!(read_dir_hint(Path::new("/storage/emulated"), &denied).is_some());
+        assert!(read_dir_hint(Path::new("/data/home/storage/shared/x"), &denied).is_some());
+        assert!(read_dir_hint(Path::new("/storage/emulated"), &missing).is_none());
+        assert!(read_dir_hint(Path::new("/root"), &denied).is_none());
+    }
+}
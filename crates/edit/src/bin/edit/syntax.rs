@@ -0,0 +1,456 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! What the file types look like to the highlighter: Their quotes and words.
+//! The comments come from the [`FileType`].
+
+use edit::buffer::Syntax;
+
+use crate::filetype::FileType;
+
+/// How the strings of a language are quoted.
+struct Quotes {
+    quotes: &'static str,
+    multiline: &'static str,
+    triple: bool,
+}
+
+const C_QUOTES: Quotes = Quotes { quotes: "\"'", multiline: "", triple: false };
+const JS_QUOTES: Quotes = Quotes { quotes: "\"'`", multiline: "`", triple: false };
+const SCRIPT_QUOTES: Quotes = Quotes { quotes: "\"'", multiline: "\"'", triple: false };
+const DOUBLE_QUOTES: Quotes = Quotes { quotes: "\"", multiline: "", triple: false };
+
+const C_TYPES: &[&str] = &[
+    "bool", "char", "double", "float", "int", "long", "short", "signed", "size_t", "unsigned",
+    "void",
+];
+const C_KEYWORDS: &[&str] = &[
+    "break", "case", "const", "continue", "default", "do", "else", "enum", "extern", "for", "goto",
+    "if", "inline", "return", "sizeof", "static", "struct", "switch", "typedef", "union",
+    "volatile", "while",
+];
+const CPP_KEYWORDS: &[&str] = &[
+    "auto",
+    "break",
+    "case",
+    "catch",
+    "class",
+    "const",
+    "constexpr",
+    "continue",
+    "default",
+    "delete",
+    "do",
+    "else",
+    "enum",
+    "explicit",
+    "extern",
+    "for",
+    "friend",
+    "if",
+    "inline",
+    "namespace",
+    "new",
+    "noexcept",
+    "operator",
+    "override",
+    "private",
+    "protected",
+    "public",
+    "return",
+    "sizeof",
+    "static",
+    "struct",
+    "switch",
+    "template",
+    "this",
+    "throw",
+    "try",
+    "typedef",
+    "typename",
+    "union",
+    "using",
+    "virtual",
+    "while",
+];
+const CSHARP_KEYWORDS: &[&str] = &[
+    "abstract",
+    "as",
+    "async",
+    "await",
+    "base",
+    "break",
+    "case",
+    "catch",
+    "class",
+    "const",
+    "continue",
+    "default",
+    "do",
+    "else",
+    "enum",
+    "foreach",
+    "for",
+    "get",
+    "if",
+    "in",
+    "interface",
+    "internal",
+    "is",
+    "namespace",
+    "new",
+    "out",
+    "override",
+    "private",
+    "protected",
+    "public",
+    "readonly",
+    "ref",
+    "return",
+    "sealed",
+    "set",
+    "static",
+    "struct",
+    "switch",
+    "this",
+    "throw",
+    "try",
+    "using",
+    "var",
+    "virtual",
+    "while",
+    "yield",
+];
+const CSHARP_TYPES: &[&str] = &[
+    "bool", "byte", "char", "decimal", "double", "float", "int", "long", "object", "string",
+    "uint", "ulong", "void",
+];
+const GO_KEYWORDS: &[&str] = &[
+    "break",
+    "case",
+    "chan",
+    "const",
+    "continue",
+    "default",
+    "defer",
+    "else",
+    "fallthrough",
+    "for",
+    "func",
+    "go",
+    "goto",
+    "if",
+    "import",
+    "interface",
+    "map",
+    "package",
+    "range",
+    "return",
+    "select",
+    "struct",
+    "switch",
+    "type",
+    "var",
+];
+const GO_TYPES: &[&str] = &[
+    "bool", "byte", "error", "float32", "float64", "int", "int8", "int16", "int32", "int64",
+    "rune", "string", "uint", "uint8", "uint16", "uint32", "uint64", "uintptr",
+];
+const JAVA_KEYWORDS: &[&str] = &[
+    "abstract",
+    "break",
+    "case",
+    "catch",
+    "class",
+    "continue",
+    "default",
+    "do",
+    "else",
+    "enum",
+    "extends",
+    "final",
+    "finally",
+    "for",
+    "if",
+    "implements",
+    "import",
+    "instanceof",
+    "interface",
+    "new",
+    "package",
+    "private",
+    "protected",
+    "public",
+    "return",
+    "static",
+    "super",
+    "switch",
+    "synchronized",
+    "this",
+    "throw",
+    "throws",
+    "try",
+    "var",
+    "while",
+];
+const JAVA_TYPES: &[&str] =
+    &["boolean", "byte", "char", "double", "float", "int", "long", "short", "void", "String"];
+const JS_KEYWORDS: &[&str] = &[
+    "async",
+    "await",
+    "break",
+    "case",
+    "catch",
+    "class",
+    "const",
+    "continue",
+    "default",
+    "delete",
+    "do",
+    "else",
+    "export",
+    "extends",
+    "finally",
+    "for",
+    "from",
+    "function",
+    "if",
+    "import",
+    "in",
+    "instanceof",
+    "let",
+    "new",
+    "of",
+    "return",
+    "static",
+    "super",
+    "switch",
+    "this",
+    "throw",
+    "try",
+    "typeof",
+    "var",
+    "void",
+    "while",
+    "yield",
+];
+const TS_KEYWORDS: &[&str] = &[
+    "abstract",
+    "as",
+    "async",
+    "await",
+    "break",
+    "case",
+    "catch",
+    "class",
+    "const",
+    "continue",
+    "declare",
+    "default",
+    "do",
+    "else",
+    "enum",
+    "export",
+    "extends",
+    "finally",
+    "for",
+    "from",
+    "function",
+    "if",
+    "implements",
+    "import",
+    "in",
+    "instanceof",
+    "interface",
+    "keyof",
+    "let",
+    "namespace",
+    "new",
+    "of",
+    "private",
+    "protected",
+    "public",
+    "readonly",
+    "return",
+    "static",
+    "super",
+    "switch",
+    "this",
+    "throw",
+    "try",
+    "type",
+    "typeof",
+    "var",
+    "while",
+    "yield",
+];
+const TS_TYPES: &[&str] =
+    &["any", "boolean", "never", "number", "object", "string", "symbol", "unknown", "void"];
+const JS_CONSTANTS: &[&str] = &["false", "null", "true", "undefined", "NaN", "Infinity"];
+const KOTLIN_KEYWORDS: &[&str] = &[
+    "as",
+    "break",
+    "class",
+    "companion",
+    "continue",
+    "data",
+    "do",
+    "else",
+    "for",
+    "fun",
+    "if",
+    "import",
+    "in",
+    "interface",
+    "is",
+    "object",
+    "override",
+    "package",
+    "private",
+    "return",
+    "sealed",
+    "this",
+    "throw",
+    "try",
+    "val",
+    "var",
+    "when",
+    "while",
+];
+const LUA_KEYWORDS: &[&str] = &[
+    "and", "break", "do", "else", "elseif", "end", "for", "function", "goto", "if", "in", "local",
+    "not", "or", "repeat", "return", "then", "until", "while",
+];
+const PHP_KEYWORDS: &[&str] = &[
+    "abstract",
+    "as",
+    "break",
+    "case",
+    "catch",
+    "class",
+    "const",
+    "continue",
+    "default",
+    "do",
+    "echo",
+    "else",
+    "elseif",
+    "extends",
+    "final",
+    "finally",
+    "fn",
+    "for",
+    "foreach",
+    "function",
+    "if",
+    "implements",
+    "interface",
+    "namespace",
+    "new",
+    "private",
+    "protected",
+    "public",
+    "require",
+    "return",
+    "static",
+    "switch",
+    "throw",
+    "trait",
+    "try",
+    "use",
+    "while",
+];
+const PERL_KEYWORDS: &[&str] = &[
+    "else", "elsif", "for", "foreach", "if", "last", "local", "my", "next", "our", "package",
+    "return", "sub", "unless", "until", "use", "while",
+];
+const PYTHON_KEYWORDS: &[&str] = &[
+    "and", "as", "assert", "async", "await", "break", "class", "continue", "def", "del", "elif",
+    "else", "except", "finally", "for", "from", "global", "if", "import", "in", "is", "lambda",
+    "nonlocal", "not", "or", "pass", "raise", "return", "try", "while", "with", "yield",
+];
+const PYTHON_CONSTANTS: &[&str] = &["False", "None", "True"];
+const RUBY_KEYWORDS: &[&str] = &[
+    "and", "begin", "break", "case", "class", "def", "do", "else", "elsif", "end", "ensure", "for",
+    "if", "in", "module", "next", "not", "or", "raise", "require", "rescue", "return", "self",
+    "then", "unless", "until", "when", "while", "yield",
+];
+const RUST_KEYWORDS: &[&str] = &[
+    "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum", "extern",
+    "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub", "ref",
+    "return", "self", "Self", "static", "struct", "super", "trait", "type", "unsafe", "use",
+    "where", "while",
+];
+const RUST_TYPES: &[&str] = &[
+    "bool", "char", "f32", "f64", "i8", "i16", "i32", "i64", "i128", "isize", "str", "u8", "u16",
+    "u32", "u64", "u128", "usize", "Box", "Option", "Result", "String", "Vec",
+];
+const RUST_CONSTANTS: &[&str] = &["false", "true", "None", "Some", "Ok", "Err"];
+const SHELL_KEYWORDS: &[&str] = &[
+    "case", "do", "done", "elif", "else", "esac", "export", "fi", "for", "function", "if", "in",
+    "local", "return", "then", "until", "while",
+];
+const SQL_KEYWORDS: &[&str] = &[
+    "and", "as", "by", "create", "delete", "from", "group", "insert", "into", "join", "not",
+    "null", "on", "or", "order", "select", "set", "table", "update", "values", "where", "AND",
+    "AS", "BY", "CREATE", "DELETE", "FROM", "GROUP", "INSERT", "INTO", "JOIN", "NOT", "NULL", "ON",
+    "OR", "ORDER", "SELECT", "SET", "TABLE", "UPDATE", "VALUES", "WHERE",
+];
+const CONSTANTS: &[&str] = &["false", "null", "true"];
+const C_CONSTANTS: &[&str] = &["false", "true", "NULL", "nullptr"];
+
+/// The syntax of `filetype`, or `None` if it isn't highlighted.
+pub fn for_filetype(filetype: &FileType) -> Option<Syntax> {
+    let (quotes, keywords, types, constants): (_, &[&str], &[&str], &[&str]) = match filetype.name {
+        "c" => (C_QUOTES, C_KEYWORDS, C_TYPES, C_CONSTANTS),
+        "cpp" => (C_QUOTES, CPP_KEYWORDS, C_TYPES, C_CONSTANTS),
+        "csharp" => (C_QUOTES, CSHARP_KEYWORDS, CSHARP_TYPES, C_CONSTANTS),
+        "css" => (SCRIPT_QUOTES, &[], &[], &[]),
+        "go" => (JS_QUOTES, GO_KEYWORDS, GO_TYPES, &["false", "iota", "nil", "true"]),
+        "java" => (C_QUOTES, JAVA_KEYWORDS, JAVA_TYPES, CONSTANTS),
+        "javascript" => (JS_QUOTES, JS_KEYWORDS, &[], JS_CONSTANTS),
+        "json" => (DOUBLE_QUOTES, &[], &[], CONSTANTS),
+        "kotlin" => (C_QUOTES, KOTLIN_KEYWORDS, &[], CONSTANTS),
+        "lua" => (C_QUOTES, LUA_KEYWORDS, &[], &["false", "nil", "true"]),
+        "perl" => (SCRIPT_QUOTES, PERL_KEYWORDS, &[], &[]),
+        "php" => (SCRIPT_QUOTES, PHP_KEYWORDS, &[], CONSTANTS),
+        "python" => {
+            let quotes = Quotes { triple: true, ..C_QUOTES };
+            (quotes, PYTHON_KEYWORDS, &[], PYTHON_CONSTANTS)
+        }
+        "ruby" => (SCRIPT_QUOTES, RUBY_KEYWORDS, &[], &["false", "nil", "true"]),
+        "rust" => {
+            // `'` is also used for lifetimes, which would look like unterminated strings.
+            let quotes = Quotes { multiline: "\"", ..DOUBLE_QUOTES };
+            (quotes, RUST_KEYWORDS, RUST_TYPES, RUST_CONSTANTS)
+        }
+        "shell" => (SCRIPT_QUOTES, SHELL_KEYWORDS, &[], &[]),
+        "sql" => (C_QUOTES, SQL_KEYWORDS, &[], &[]),
+        "toml" => (C_QUOTES, &[], &[], CONSTANTS),
+        "typescript" => (JS_QUOTES, TS_KEYWORDS, TS_TYPES, JS_CONSTANTS),
+        "yaml" => (C_QUOTES, &[], &[], CONSTANTS),
+        // Only comments and strings. Prose is full of apostrophes that aren't quotes.
+        "dockerfile" | "ini" | "makefile" => (DOUBLE_QUOTES, &[], &[], &[]),
+        "html" | "xml" => (DOUBLE_QUOTES, &[], &[], &[]),
+        "git-commit" => {
+            return Some(Syntax {
+                line_comment: filetype.line_comment,
+                prose: true,
+                ..Default::default()
+            });
+        }
+        _ => return None,
+    };
+
+    Some(Syntax {
+        line_comment: filetype.line_comment,
+        block_comment: filetype.block_comment,
+        quotes: quotes.quotes,
+        multiline_quotes: quotes.multiline,
+        triple_quotes: quotes.triple,
+        keywords,
+        types,
+        constants,
+        prose: false,
+    })
+}
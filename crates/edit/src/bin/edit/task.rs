@@ -0,0 +1,90 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Long operations, like a project-wide search, run a slice at a time between
+//! frames. That way their progress can be drawn and Esc cancels them.
+//!
+//! They can't simply run on another thread, because `TextBuffer` allocates
+//! from the scratch arenas, which are single-threaded.
+
+use std::time::{Duration, Instant};
+
+use crate::documents::ReadAhead;
+use crate::wake_lock::WakeLock;
+
+/// How long a slice may take. Short enough for typing not to lag behind.
+const SLICE: Duration = Duration::from_millis(30);
+
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub struct Progress {
+    pub done: u64,
+    /// 0 if it isn't known (yet) how much there is to do.
+    pub total: u64,
+}
+
+impl Progress {
+    /// How much of it is done, from 0 to 1. `None` if that isn't known.
+    pub fn fraction(&self) -> Option<f32> {
+        (self.total != 0).then(|| self.done.min(self.total) as f32 / self.total as f32)
+    }
+}
+
+pub enum Job {
+    /// Opens a large file once [`ReadAhead`] is done.
+    Open(ReadAhead),
+    /// Searches the project in `State::project_replace`.
+    ProjectFind,
+    /// Applies the replacements in `State::project_replace`.
+    ProjectReplace,
+}
+
+pub struct Task {
+    pub title: String,
+    pub job: Job,
+    pub progress: Progress,
+    cancelled: bool,
+    // Android shouldn't suspend us halfway through.
+    _wake_lock: WakeLock,
+}
+
+impl Task {
+    pub fn new(title: String, job: Job) -> Self {
+        Self {
+            title,
+            job,
+            progress: Progress::default(),
+            cancelled: false,
+            _wake_lock: WakeLock::acquire(),
+        }
+    }
+
+    /// When the slice that starts now has to end.
+    pub fn deadline() -> Instant {
+        Instant::now() + SLICE
+    }
+
+    pub fn cancel(&mut self) {
+        self.cancelled = true;
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled
+    }
+}
+
+/// How long the main loop may block waiting for input: Not at all, while there's work to do.
+pub fn read_timeout(task: Option<&Task>) -> Duration {
+    if task.is_some() { Duration::ZERO } else { Duration::MAX }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_progress() {
+        assert_eq!(Progress { done: 5, total: 0 }.fraction(), None);
+        assert_eq!(Progress { done: 1, total: 4 }.fraction(), Some(0.25));
+        assert_eq!(Progress { done: 9, total: 4 }.fraction(), Some(1.0));
+    }
+}
@@ -0,0 +1,797 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! The terminal panel below the editor: A shell in a pseudo terminal,
+//! and a small VT interpreter that keeps its screen and scrollback, see [`Screen`].
+//!
+//! It understands what shells, compilers, `ls` and the like print.
+//! Full screen programs mostly work, but don't expect everything of them.
+
+use std::collections::VecDeque;
+use std::ffi::{OsStr, OsString};
+use std::path::Path;
+use std::time::Duration;
+use std::{env, mem};
+
+use edit::apperr;
+use edit::helpers::{CoordType, Point, Size};
+use edit::sys::Pty;
+use edit::unicode::MeasurementConfig;
+use edit::vt::{self, Csi, Token};
+
+/// How many lines are kept after they scrolled off the screen.
+const SCROLLBACK: usize = 2000;
+/// How often the output is checked while the shell runs.
+const POLL_INTERVAL: Duration = Duration::from_millis(30);
+/// The same while the panel is hidden. The shell would stop once its output fills up.
+const HIDDEN_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+const DEFAULT_HEIGHT: CoordType = 10;
+const MIN_HEIGHT: CoordType = 3;
+const MAX_HEIGHT: CoordType = 50;
+
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub enum Color {
+    #[default]
+    Default,
+    /// One of the 16 standard colors.
+    Indexed(u8),
+    /// `0xRRGGBB`
+    Rgb(u32),
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub struct Style {
+    pub fg: Color,
+    pub bg: Color,
+    pub bold: bool,
+    pub italic: bool,
+    pub underline: bool,
+    pub reverse: bool,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Cell {
+    /// `'\0'` in the column right of a wide character.
+    pub ch: char,
+    pub style: Style,
+}
+
+impl Cell {
+    const fn blank(style: Style) -> Self {
+        // Erasing keeps the background color, but nothing else.
+        Self {
+            ch: ' ',
+            style: Style {
+                fg: Color::Default,
+                bg: style.bg,
+                bold: false,
+                italic: false,
+                underline: false,
+                reverse: false,
+            },
+        }
+    }
+}
+
+/// The screen of a terminal, as far as what was written to it made it.
+pub struct Screen {
+    parser: vt::Parser,
+    size: Size,
+    /// The scrollback, followed by the `size.height` rows of the screen.
+    lines: VecDeque<Vec<Cell>>,
+    /// The lines of the main screen, while a full screen program uses the alternate one.
+    main_lines: Option<VecDeque<Vec<Cell>>>,
+    /// Relative to the top left of the screen.
+    cursor: Point,
+    saved_cursor: Point,
+    style: Style,
+    /// The last column was written to. The next character goes into the next line.
+    wrap_pending: bool,
+    /// The rows that scroll, as set by `CSI r`. The end is exclusive.
+    scroll_region: (CoordType, CoordType),
+    /// `ESC (` and friends pick a character set, named by the next character.
+    skip_charset: bool,
+    /// An incomplete UTF-8 sequence at the end of the last output.
+    partial: Vec<u8>,
+    /// Answers to queries like the cursor position, for the program to read.
+    replies: Vec<u8>,
+    pub cursor_visible: bool,
+    /// The arrow keys send `ESC O A` instead of `ESC [ A`.
+    pub app_cursor_keys: bool,
+    pub bracketed_paste: bool,
+}
+
+impl Screen {
+    pub fn new(size: Size) -> Self {
+        let size = Size { width: size.width.max(1), height: size.height.max(1) };
+        Self {
+            parser: vt::Parser::new(),
+            size,
+            lines: blank_lines(size),
+            main_lines: None,
+            cursor: Point::default(),
+            saved_cursor: Point::default(),
+            style: Style::default(),
+            wrap_pending: false,
+            scroll_region: (0, size.height),
+            skip_charset: false,
+            partial: Vec::new(),
+            replies: Vec::new(),
+            cursor_visible: true,
+            app_cursor_keys: false,
+            bracketed_paste: false,
+        }
+    }
+
+    pub fn size(&self) -> Size {
+        self.size
+    }
+
+    /// The cursor position on the screen.
+    pub fn cursor(&self) -> Point {
+        self.cursor
+    }
+
+    /// The number of lines in the scrollback and on the screen.
+    pub fn line_count(&self) -> usize {
+        self.lines.len()
+    }
+
+    /// A line of the scrollback, or of the screen if `index` is past the scrollback.
+    pub fn line(&self, index: usize) -> &[Cell] {
+        &self.lines[index]
+    }
+
+    pub fn scrollback_len(&self) -> usize {
+        self.lines.len() - self.size.height as usize
+    }
+
+    /// Changes the size without reflowing the lines. Lines that no longer fit
+    /// on the screen go into the scrollback, just like they do in other terminals.
+    pub fn resize(&mut self, size: Size) {
+        let size = Size { width: size.width.max(1), height: size.height.max(1) };
+        if size == self.size {
+            return;
+        }
+
+        // Drop empty lines below the cursor first, so that the cursor stays in view.
+        let mut height = self.size.height;
+        while height > size.height
+            && self.cursor.y < height - 1
+            && self.lines.back().is_some_and(|line| line.iter().all(|c| c.ch == ' '))
+        {
+            self.lines.pop_back();
+            height -= 1;
+        }
+        if height > size.height {
+            self.cursor.y -= height - size.height;
+        }
+
+        for lines in [Some(&mut self.lines), self.main_lines.as_mut()].into_iter().flatten() {
+            for line in lines.iter_mut() {
+                line.resize(size.width as usize, Cell::blank(Style::default()));
+            }
+            while lines.len() < size.height as usize {
+                lines.push_back(blank_line(size.width));
+            }
+        }
+
+        self.size = size;
+        self.scroll_region = (0, size.height);
+        self.cursor.x = self.cursor.x.min(size.width - 1);
+        self.cursor.y = self.cursor.y.clamp(0, size.height - 1);
+        self.wrap_pending = false;
+    }
+
+    /// Interprets the output of the program.
+    pub fn write(&mut self, output: &[u8]) {
+        let mut data = mem::take(&mut self.partial);
+        data.extend_from_slice(output);
+
+        // Keep an incomplete UTF-8 sequence at the end for next time.
+        let valid = match str::from_utf8(&data) {
+            Err(err) if err.error_len().is_none() => err.valid_up_to(),
+            _ => data.len(),
+        };
+        self.partial = data.split_off(valid);
+        let text = String::from_utf8_lossy(&data);
+
+        // The tokens borrow the parser, while handling them needs the rest of `self`.
+        let mut parser = mem::replace(&mut self.parser, vt::Parser::new());
+        let mut stream = parser.parse(&text);
+        while let Some(token) = stream.next() {
+            match token {
+                Token::Text(text) => {
+                    for ch in text.chars() {
+                        if mem::take(&mut self.skip_charset) {
+                            continue;
+                        }
+                        self.print(ch);
+                    }
+                }
+                Token::Ctrl(ch) => self.control(ch),
+                Token::Esc(ch) => self.escape(ch),
+                Token::Csi(csi) => self.csi(csi),
+                // Titles, hyperlinks and the like.
+                Token::SS3(_) | Token::Osc { .. } | Token::Dcs { .. } => {}
+            }
+        }
+        self.parser = parser;
+    }
+
+    /// Returns the answers to the program's queries.
+    pub fn take_replies(&mut self) -> Vec<u8> {
+        mem::take(&mut self.replies)
+    }
+
+    fn print(&mut self, ch: char) {
+        let width = char_width(ch);
+        if width == 0 || width > self.size.width {
+            return;
+        }
+
+        if self.wrap_pending || self.cursor.x + width > self.size.width {
+            self.cursor.x = 0;
+            self.linefeed();
+        }
+        self.wrap_pending = false;
+
+        let x = self.cursor.x as usize;
+        let w = width as usize;
+        let style = self.style;
+        let line = self.line_mut(self.cursor.y);
+
+        // Don't leave half of a wide character behind.
+        if line[x].ch == '\0' && x > 0 {
+            line[x - 1].ch = ' ';
+        }
+        if line.get(x + w).is_some_and(|c| c.ch == '\0') {
+            line[x + w].ch = ' ';
+        }
+
+        line[x] = Cell { ch, style };
+        if w == 2 {
+            line[x + 1] = Cell { ch: '\0', style };
+        }
+
+        self.cursor.x += width;
+        if self.cursor.x >= self.size.width {
+            self.cursor.x = self.size.width - 1;
+            self.wrap_pending = true;
+        }
+    }
+
+    fn control(&mut self, ch: char) {
+        match ch {
+            '\r' => self.cursor.x = 0,
+            '\n' | '\x0b' | '\x0c' => self.linefeed(),
+            '\x08' => self.cursor.x = (self.cursor.x - 1).max(0),
+            '\t' => self.cursor.x = ((self.cursor.x / 8 + 1) * 8).min(self.size.width - 1),
+            _ => return,
+        }
+        self.wrap_pending = false;
+    }
+
+    fn escape(&mut self, ch: char) {
+        match ch {
+            '7' => self.saved_cursor = self.cursor,
+            '8' => self.cursor = self.saved_cursor,
+            'D' => self.linefeed(),
+            'E' => {
+                self.cursor.x = 0;
+                self.linefeed();
+            }
+            'M' => {
+                if self.cursor.y == self.scroll_region.0 {
+                    self.scroll_down(self.scroll_region.0, 1);
+                } else {
+                    self.cursor.y = (self.cursor.y - 1).max(0);
+                }
+            }
+            'c' => {
+                let replies = mem::take(&mut self.replies);
+                *self = Self::new(self.size);
+                self.replies = replies;
+            }
+            '(' | ')' | '*' | '+' => self.skip_charset = true,
+            _ => return,
+        }
+        self.wrap_pending = false;
+    }
+
+    fn csi(&mut self, csi: &Csi) {
+        // Most parameters default to 1, and 0 means the same.
+        let param = |i: usize, default: CoordType| match csi.params[i] {
+            0 => default,
+            n => n as CoordType,
+        };
+        let n = param(0, 1);
+        let Size { width, height } = self.size;
+        let Point { x, y } = self.cursor;
+
+        match (csi.private_byte, csi.final_byte) {
+            ('\0', 'A') => self.cursor.y = (y - n).max(0),
+            ('\0', 'B' | 'e') => self.cursor.y = (y + n).min(height - 1),
+            ('\0', 'C' | 'a') => self.cursor.x = (x + n).min(width - 1),
+            ('\0', 'D') => self.cursor.x = (x - n).max(0),
+            ('\0', 'E') => self.cursor = Point { x: 0, y: (y + n).min(height - 1) },
+            ('\0', 'F') => self.cursor = Point { x: 0, y: (y - n).max(0) },
+            ('\0', 'G' | '`') => self.cursor.x = (n - 1).min(width - 1),
+            ('\0', 'd') => self.cursor.y = (n - 1).min(height - 1),
+            ('\0', 'H' | 'f') => {
+                self.cursor =
+                    Point { x: (param(1, 1) - 1).min(width - 1), y: (n - 1).min(height - 1) };
+            }
+            ('\0', 'J') => match csi.params[0] {
+                0 => {
+                    self.erase(y, x, width);
+                    for y in y + 1..height {
+                        self.erase(y, 0, width);
+                    }
+                }
+                1 => {
+                    for y in 0..y {
+                        self.erase(y, 0, width);
+                    }
+                    self.erase(y, 0, x + 1);
+                }
+                2 | 3 => {
+                    for y in 0..height {
+                        self.erase(y, 0, width);
+                    }
+                    if csi.params[0] == 3 {
+                        let scrollback = self.scrollback_len();
+                        self.lines.drain(..scrollback);
+                    }
+                }
+                _ => {}
+            },
+            ('\0', 'K') => match csi.params[0] {
+                0 => self.erase(y, x, width),
+                1 => self.erase(y, 0, x + 1),
+                2 => self.erase(y, 0, width),
+                _ => {}
+            },
+            ('\0', '@') => {
+                let blank = Cell::blank(self.style);
+                let line = self.line_mut(y);
+                for _ in 0..n.min(width - x) {
+                    line.pop();
+                    line.insert(x as usize, blank);
+                }
+            }
+            ('\0', 'P') => {
+                let blank = Cell::blank(self.style);
+                let line = self.line_mut(y);
+                for _ in 0..n.min(width - x) {
+                    line.remove(x as usize);
+                    line.push(blank);
+                }
+            }
+            ('\0', 'X') => self.erase(y, x, (x + n).min(width)),
+            ('\0', 'L') if (self.scroll_region.0..self.scroll_region.1).contains(&y) => {
+                self.scroll_down(y, n);
+                self.cursor.x = 0;
+            }
+            ('\0', 'M') if (self.scroll_region.0..self.scroll_region.1).contains(&y) => {
+                self.scroll_up(y, n);
+                self.cursor.x = 0;
+            }
+            ('\0', 'S') => self.scroll_up(self.scroll_region.0, n),
+            ('\0', 'T') => self.scroll_down(self.scroll_region.0, n),
+            ('\0', 'm') => self.sgr(csi),
+            ('\0', 'r') => {
+                let top = n - 1;
+                let bottom = param(1, height).min(height);
+                if top < bottom - 1 {
+                    self.scroll_region = (top, bottom);
+                    self.cursor = Point::default();
+                }
+            }
+            ('\0', 'n') if csi.params[0] == 5 => self.replies.extend_from_slice(b"\x1b[0n"),
+            ('\0', 'n') if csi.params[0] == 6 => {
+                let reply = format!("\x1b[{};{}R", y + 1, x + 1);
+                self.replies.extend_from_slice(reply.as_bytes());
+            }
+            ('\0', 'c') => self.replies.extend_from_slice(b"\x1b[?1;2c"),
+            ('\0', 's') => self.saved_cursor = self.cursor,
+            ('\0', 'u') => self.cursor = self.saved_cursor,
+            ('?', 'h' | 'l') => {
+                let on = csi.final_byte == 'h';
+                for &mode in &csi.params[..csi.param_count] {
+                    match mode {
+                        1 => self.app_cursor_keys = on,
+                        25 => self.cursor_visible = on,
+                        47 | 1047 | 1049 => self.set_alternate_screen(on, mode == 1049),
+                        2004 => self.bracketed_paste = on,
+                        _ => {}
+                    }
+                }
+            }
+            _ => return,
+        }
+        self.wrap_pending = false;
+    }
+
+    /// Select Graphic Rendition, i.e. colors and such.
+    fn sgr(&mut self, csi: &Csi) {
+        let params = &csi.params[..csi.param_count.max(1)];
+        let mut i = 0;
+
+        while i < params.len() {
+            let style = &mut self.style;
+            match params[i] {
+                0 => *style = Style::default(),
+                1 => style.bold = true,
+                3 => style.italic = true,
+                4 => style.underline = true,
+                7 => style.reverse = true,
+                22 => style.bold = false,
+                23 => style.italic = false,
+                24 => style.underline = false,
+                27 => style.reverse = false,
+                n @ 30..=37 => style.fg = Color::Indexed(n as u8 - 30),
+                39 => style.fg = Color::Default,
+                n @ 40..=47 => style.bg = Color::Indexed(n as u8 - 40),
+                49 => style.bg = Color::Default,
+                n @ 90..=97 => style.fg = Color::Indexed(n as u8 - 90 + 8),
+                n @ 100..=107 => style.bg = Color::Indexed(n as u8 - 100 + 8),
+                n @ (38 | 48) => {
+                    let (color, len) = match params.get(i + 1) {
+                        Some(5) => (params.get(i + 2).map(|&c| color_256(c)), 2),
+                        Some(2) => match params.get(i + 2..i + 5) {
+                            Some(&[r, g, b]) => {
+                                let rgb = (r as u32 & 0xff) << 16 | (g as u32 & 0xff) << 8;
+                                (Some(Color::Rgb(rgb | b as u32 & 0xff)), 4)
+                            }
+                            _ => (None, 4),
+                        },
+                        _ => (None, 0),
+                    };
+                    if let Some(color) = color {
+                        if n == 38 {
+                            style.fg = color;
+                        } else {
+                            style.bg = color;
+                        }
+                    }
+                    i += len;
+                }
+                _ => {}
+            }
+            i += 1;
+        }
+    }
+
+    fn set_alternate_screen(&mut self, on: bool, save_cursor: bool) {
+        if on && self.main_lines.is_none() {
+            if save_cursor {
+                self.saved_cursor = self.cursor;
+            }
+            self.main_lines = Some(mem::replace(&mut self.lines, blank_lines(self.size)));
+        } else if !on && let Some(lines) = self.main_lines.take() {
+            self.lines = lines;
+            if save_cursor {
+                self.cursor = self.saved_cursor;
+            }
+        }
+        self.scroll_region = (0, self.size.height);
+    }
+
+    fn linefeed(&mut self) {
+        if self.cursor.y == self.scroll_region.1 - 1 {
+            self.scroll_up(self.scroll_region.0, 1);
+        } else if self.cursor.y < self.size.height - 1 {
+            self.cursor.y += 1;
+        }
+    }
+
+    /// Moves the lines from `top` to the end of the scroll region up by `n`.
+    fn scroll_up(&mut self, top: CoordType, n: CoordType) {
+        let bottom = self.scroll_region.1;
+        let n = n.min(bottom - top);
+
+        if top == 0 && bottom == self.size.height && self.main_lines.is_none() {
+            // What scrolls off the top of the main screen goes into the scrollback.
+            for _ in 0..n {
+                self.lines.push_back(blank_line(self.size.width));
+            }
+            let excess = self.scrollback_len().saturating_sub(SCROLLBACK);
+            self.lines.drain(..excess);
+        } else {
+            let offset = self.scrollback_len();
+            for _ in 0..n {
+                self.lines.remove(offset + top as usize);
+                self.lines.insert(offset + bottom as usize - 1, blank_line(self.size.width));
+            }
+        }
+    }
+
+    /// Moves the lines from `top` to the end of the scroll region down by `n`.
+    fn scroll_down(&mut self, top: CoordType, n: CoordType) {
+        let bottom = self.scroll_region.1;
+        let offset = self.scrollback_len();
+        for _ in 0..n.min(bottom - top) {
+            self.lines.remove(offset + bottom as usize - 1);
+            self.lines.insert(offset + top as usize, blank_line(self.size.width));
+        }
+    }
+
+    /// Blanks the columns `beg..end` of screen row `y`.
+    fn erase(&mut self, y: CoordType, beg: CoordType, end: CoordType) {
+        let blank = Cell::blank(self.style);
+        let line = self.line_mut(y);
+        line[beg as usize..end as usize].fill(blank);
+    }
+
+    fn line_mut(&mut self, y: CoordType) -> &mut Vec<Cell> {
+        let offset = self.scrollback_len();
+        &mut self.lines[offset + y as usize]
+    }
+}
+
+/// The shell in the terminal panel.
+pub struct Terminal {
+    pub visible: bool,
+    /// The number of rows it should have, without the title.
+    /// It gets fewer if the window is small, see [`crate::draw_terminal::terminal_height`].
+    pub height: CoordType,
+    /// How many lines it's scrolled back, into the scrollback.
+    pub scroll: usize,
+    /// Whether the panel had the focus when it was last drawn.
+    pub focused: bool,
+    screen: Screen,
+    pty: Option<Pty>,
+    exited: bool,
+    /// How the program exited, if it did and that's known.
+    exit_code: Option<i32>,
+}
+
+impl Terminal {
+    pub fn new() -> Self {
+        Self {
+            visible: false,
+            height: DEFAULT_HEIGHT,
+            scroll: 0,
+            focused: false,
+            screen: Screen::new(Size { width: 80, height: DEFAULT_HEIGHT }),
+            pty: None,
+            exited: false,
+            exit_code: None,
+        }
+    }
+
+    pub fn screen(&self) -> &Screen {
+        &self.screen
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.pty.is_some()
+    }
+
+    /// Whether the shell ran, but exited. Its output stays until it's restarted.
+    pub fn has_exited(&self) -> bool {
+        self.exited
+    }
+
+    pub fn exit_code(&self) -> Option<i32> {
+        self.exit_code
+    }
+
+    /// Starts the shell (`$SHELL`) in `cwd`, unless it's running already.
+    pub fn start(&mut self, cwd: &Path, size: Size) -> apperr::Result<()> {
+        if self.pty.is_some() {
+            return Ok(());
+        }
+        self.spawn(&shell(), &[], cwd, size)
+    }
+
+    /// Runs `command` with `sh -c` in `cwd`, instead of what ran before.
+    pub fn run(&mut self, command: &str, cwd: &Path, size: Size) -> apperr::Result<()> {
+        self.pty = None;
+        let args = [OsStr::new("-c"), OsStr::new(command)];
+        self.spawn(OsStr::new("sh"), &args, cwd, size)
+    }
+
+    fn spawn(
+        &mut self,
+        program: &OsStr,
+        args: &[&OsStr],
+        cwd: &Path,
+        size: Size,
+    ) -> apperr::Result<()> {
+        self.screen = Screen::new(size);
+        self.pty = Some(Pty::spawn(program, args, cwd, self.screen.size())?);
+        self.exited = false;
+        self.exit_code = None;
+        self.scroll = 0;
+        Ok(())
+    }
+
+    /// Changes the number of rows by `delta`.
+    pub fn grow(&mut self, delta: CoordType) {
+        self.height = (self.height + delta).clamp(MIN_HEIGHT, MAX_HEIGHT);
+    }
+
+    /// Adapts the screen to the panel, which depends on the size of the window.
+    pub fn resize(&mut self, size: Size) {
+        if size != self.screen.size() {
+            self.screen.resize(size);
+            if let Some(pty) = &mut self.pty {
+                pty.resize(self.screen.size());
+            }
+        }
+    }
+
+    /// Reads what the shell printed. Returns whether there was anything.
+    pub fn poll(&mut self) -> bool {
+        let Some(pty) = &mut self.pty else {
+            return false;
+        };
+
+        let mut buf = [0; 16 * 1024];
+        let mut changed = false;
+        // Something like `yes` never stops printing. The UI must still get a chance.
+        for _ in 0..16 {
+            match pty.read(&mut buf) {
+                Some(0) => break,
+                Some(n) => {
+                    self.screen.write(&buf[..n]);
+                    changed = true;
+                }
+                None => {
+                    self.exit_code = pty.wait();
+                    self.pty = None;
+                    self.exited = true;
+                    return true;
+                }
+            }
+        }
+
+        let replies = self.screen.take_replies();
+        if !replies.is_empty() {
+            pty.write(&replies);
+        }
+        self.scroll = self.scroll.min(self.screen.scrollback_len());
+        changed
+    }
+
+    /// Sends input to the shell, as if it was typed.
+    pub fn write(&mut self, data: &[u8]) {
+        if let Some(pty) = &mut self.pty {
+            pty.write(data);
+            self.scroll = 0;
+        }
+    }
+
+    /// Sends text to the shell as a paste, which keeps it from running
+    /// each line on its own, if the shell supports that.
+    pub fn paste(&mut self, text: &str) {
+        if self.screen.bracketed_paste {
+            let text = text.replace('\x1b', "");
+            self.write(format!("\x1b[200~{text}\x1b[201~").as_bytes());
+        } else {
+            self.write(text.replace("\r\n", "\r").replace('\n', "\r").as_bytes());
+        }
+    }
+
+    /// How long the main loop may block waiting for input before checking for output.
+    /// Output that nobody can see only needs to be drained, so that the program doesn't block.
+    pub fn read_timeout(&self, focused: bool) -> Duration {
+        match (&self.pty, self.visible && focused) {
+            (None, _) => Duration::MAX,
+            (Some(_), true) => POLL_INTERVAL,
+            (Some(_), false) => HIDDEN_POLL_INTERVAL,
+        }
+    }
+}
+
+fn shell() -> OsString {
+    env::var_os("SHELL").filter(|shell| !shell.is_empty()).unwrap_or_else(|| "sh".into())
+}
+
+/// The number of columns that `ch` takes up.
+fn char_width(ch: char) -> CoordType {
+    if ch < '\u{300}' {
+        return 1;
+    }
+    let mut buf = [0; 4];
+    let text = ch.encode_utf8(&mut buf).as_bytes();
+    MeasurementConfig::new(&text).goto_offset(text.len()).visual_pos.x
+}
+
+/// Converts a color of the 256 color palette.
+fn color_256(index: u16) -> Color {
+    const LEVELS: [u32; 6] = [0, 95, 135, 175, 215, 255];
+    match index {
+        0..16 => Color::Indexed(index as u8),
+        16..232 => {
+            let i = index as usize - 16;
+            Color::Rgb(LEVELS[i / 36] << 16 | LEVELS[i / 6 % 6] << 8 | LEVELS[i % 6])
+        }
+        _ => {
+            let gray = 8 + 10 * (index.min(255) as u32 - 232);
+            Color::Rgb(gray << 16 | gray << 8 | gray)
+        }
+    }
+}
+
+fn blank_line(width: CoordType) -> Vec<Cell> {
+    vec![Cell::blank(Style::default()); width as usize]
+}
+
+fn blank_lines(size: Size) -> VecDeque<Vec<Cell>> {
+    (0..size.height).map(|_| blank_line(size.width)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(screen: &Screen) -> Vec<String> {
+        (0..screen.line_count())
+            .map(|i| {
+                let line: String =
+                    screen.line(i).iter().map(|c| c.ch).filter(|&c| c != '\0').collect();
+                line.trim_end().to_string()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_screen() {
+        let mut screen = Screen::new(Size { width: 8, height: 3 });
+        screen.write(b"$ ls\r\nabcdefghij\r\n\x1b[31mred\x1b[0m");
+        // The first line scrolled into the scrollback, and the long one wrapped.
+        assert_eq!(text(&screen), ["$ ls", "abcdefgh", "ij", "red"]);
+        assert_eq!(screen.scrollback_len(), 1);
+        assert_eq!(screen.line(3)[0].style.fg, Color::Indexed(1));
+        assert_eq!(screen.line(3)[3].style.fg, Color::Default);
+        assert_eq!(screen.cursor(), Point { x: 3, y: 2 });
+
+        // A prompt redrawing its line, as shells do while typing.
+        screen.write(b"\r\x1b[Kok \x1b[1;2H\x1b[2P");
+        assert_eq!(text(&screen), ["$ ls", "adefgh", "ij", "ok"]);
+
+        // Clearing the screen keeps the scrollback, unless asked to.
+        screen.write(b"\x1b[H\x1b[2J");
+        assert_eq!(text(&screen), ["$ ls", "", "", ""]);
+        screen.write(b"\x1b[3J");
+        assert_eq!(text(&screen), ["", "", ""]);
+
+        // Queries get answered.
+        screen.write(b"\x1b[2;3H\x1b[6n");
+        assert_eq!(screen.take_replies(), b"\x1b[2;3R");
+    }
+
+    #[test]
+    fn test_screen_unicode() {
+        let mut screen = Screen::new(Size { width: 5, height: 2 });
+        // A split UTF-8 sequence, and a wide character that doesn't fit at the end.
+        let text_bytes = "ab€中中".as_bytes();
+        screen.write(&text_bytes[..4]);
+        screen.write(&text_bytes[4..]);
+        assert_eq!(text(&screen), ["ab€中", "中"]);
+        assert_eq!(screen.line(0)[4].ch, '\0');
+    }
+
+    #[test]
+    fn test_screen_alternate() {
+        let mut screen = Screen::new(Size { width: 4, height: 2 });
+        screen.write(b"one\r\ntwo\x1b[?1049h\x1b[Hfull");
+        assert_eq!(text(&screen), ["full", ""]);
+        screen.write(b"\x1b[?1049l!");
+        assert_eq!(text(&screen), ["one", "two!"]);
+    }
+
+    #[test]
+    fn test_sgr() {
+        let mut screen = Screen::new(Size { width: 4, height: 1 });
+        screen.write(b"\x1b[1;38;5;196;48;2;1;2;3;4ma");
+        let style = screen.line(0)[0].style;
+        assert!(style.bold && style.underline);
+        assert_eq!(style.fg, Color::Rgb(0xff0000));
+        assert_eq!(style.bg, Color::Rgb(0x010203));
+    }
+}
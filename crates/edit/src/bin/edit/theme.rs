@@ -0,0 +1,277 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Loads color themes from the `themes` directories, which `--paths` shows
+//! (the data directories). A theme is a TOML or JSON file named after it:
+//!
+//! ```toml
+//! # The terminal palette. Missing colors are taken from the default theme.
+//! [colors]
+//! background = "#1e1e2e"
+//! foreground = "#cdd6f4"
+//! black = "#45475a"
+//! bright_blue = "#89b4fa"
+//!
+//! # Missing colors are derived from the palette, as without a theme.
+//! [ui]
+//! selection = "#585b70"
+//!
+//! [syntax]
+//! comment = "#6c7086"
+//! ```
+//!
+//! The same as JSON is `{"colors": {"background": "#1e1e2e", ...}, "ui": {...}}`.
+//! VS Code themes and base16 schemes work, too, see [`crate::theme_import`].
+//! Without a theme, the terminal's own palette is used.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use edit::framebuffer::{DEFAULT_THEME, INDEXED_COLORS_COUNT, NAMED_COLORS_COUNT};
+use edit::oklab::StraightRgba;
+
+use crate::json::{self, Json};
+use crate::paths::{self, Kind};
+use crate::{config, theme_import};
+
+/// In the order of [`edit::framebuffer::IndexedColor`].
+const COLOR_NAMES: [&str; INDEXED_COLORS_COUNT] = [
+    "black",
+    "red",
+    "green",
+    "yellow",
+    "blue",
+    "magenta",
+    "cyan",
+    "white",
+    "bright_black",
+    "bright_red",
+    "bright_green",
+    "bright_yellow",
+    "bright_blue",
+    "bright_magenta",
+    "bright_cyan",
+    "bright_white",
+    "background",
+    "foreground",
+];
+/// In the order of [`edit::framebuffer::NamedColor`], followed by [`SYNTAX_NAMES`].
+const UI_NAMES: [&str; 7] =
+    ["bar", "floater", "selection", "line_number", "whitespace", "ruler", "focus"];
+const SYNTAX_NAMES: [&str; 8] =
+    ["comment", "keyword", "string", "number", "constant", "function", "type", "operator"];
+const _: () = assert!(UI_NAMES.len() + SYNTAX_NAMES.len() == NAMED_COLORS_COUNT);
+
+pub struct Theme {
+    pub colors: [StraightRgba; INDEXED_COLORS_COUNT],
+    pub named: [Option<StraightRgba>; NAMED_COLORS_COUNT],
+}
+
+pub struct ThemeFile {
+    /// The file name without the extension.
+    pub name: String,
+    pub path: PathBuf,
+}
+
+/// Lists the installed themes by name. The user's own shadow those installed by packages.
+pub fn list() -> Vec<ThemeFile> {
+    let dirs = paths::dir(Kind::Data).into_iter().chain(paths::system_data_dirs());
+    let mut themes: Vec<ThemeFile> = Vec::new();
+
+    for dir in dirs {
+        let Ok(entries) = fs::read_dir(dir.join("themes")) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let is_theme = path
+                .extension()
+                .and_then(|ext| ext.to_str())
+                .is_some_and(|ext| matches!(ext, "toml" | "json" | "yaml" | "yml"));
+            let Some(name) = path.file_stem().and_then(|s| s.to_str()).filter(|_| is_theme) else {
+                continue;
+            };
+            if !themes.iter().any(|theme| theme.name == name) {
+                themes.push(ThemeFile { name: name.to_string(), path });
+            }
+        }
+    }
+
+    themes.sort_by(|a, b| a.name.cmp(&b.name));
+    themes
+}
+
+/// Finds an installed theme by name.
+pub fn find(name: &str) -> Option<ThemeFile> {
+    list().into_iter().find(|theme| theme.name == name)
+}
+
+/// Finds an installed theme by name and loads it.
+pub fn load_by_name(name: &str) -> Result<Theme, String> {
+    match find(name) {
+        Some(theme) => load(&theme.path),
+        None => Err(format!("theme `{name}` not found, see the themes directories in `--paths`")),
+    }
+}
+
+/// Errors are formatted as `path: message`.
+pub fn load(path: &Path) -> Result<Theme, String> {
+    let text = fs::read_to_string(path).map_err(|err| format!("{}: {}", path.display(), err))?;
+    let res = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("json") => parse_json(&text),
+        Some("yaml" | "yml") => theme_import::base16(&text),
+        _ => parse_toml(&text),
+    };
+    res.map_err(|msg| format!("{}: {}", path.display(), msg))
+}
+
+fn parse_toml(text: &str) -> Result<Theme, String> {
+    let root = toml_span::parse(text).map_err(|err| {
+        let (line, column) = config::position(text, err.span.start);
+        format!("{line}:{column}: {err}")
+    })?;
+    let mut entries = Vec::new();
+
+    if let Some(root) = root.as_table() {
+        for (section, value) in root {
+            let section = section.name.as_ref();
+            names(section)?;
+            let Some(table) = value.as_table() else {
+                return Err(format!("`{section}` must be a section like `[{section}]`"));
+            };
+            for (key, value) in table {
+                entries.push((section, key.name.as_ref(), value.as_str()));
+            }
+        }
+    }
+
+    build(&entries)
+}
+
+fn parse_json(text: &str) -> Result<Theme, String> {
+    let root = json::parse(text);
+    if let Some(root) = &root
+        && theme_import::is_vscode(root)
+    {
+        return theme_import::vscode(root);
+    }
+    let Some(Json::Object(root)) = root else {
+        return Err("expected a JSON object like `{\"colors\": {...}}`".to_string());
+    };
+    let mut entries = Vec::new();
+
+    for (section, value) in &root {
+        names(section)?;
+        let Json::Object(fields) = value else {
+            return Err(format!("`{section}` must be an object"));
+        };
+        for (key, value) in fields {
+            let value = match value {
+                Json::String(s) => Some(s.as_str()),
+                _ => None,
+            };
+            entries.push((section.as_str(), key.as_str(), value));
+        }
+    }
+
+    build(&entries)
+}
+
+/// Builds the theme from `(section, key, value)` entries. `value` is `None` if it's not a string.
+pub fn build(entries: &[(&str, &str, Option<&str>)]) -> Result<Theme, String> {
+    let mut theme = Theme { colors: DEFAULT_THEME, named: [None; NAMED_COLORS_COUNT] };
+
+    for &(section, key, value) in entries {
+        let names = names(section)?;
+        let Some(index) = names.iter().position(|&name| name == key) else {
+            let expected = names.join(", ");
+            return Err(format!("unknown color `{section}.{key}`, expected one of {expected}"));
+        };
+        let Some(color) = value.and_then(parse_color) else {
+            let found = value.map(|v| format!(", not \"{v}\"")).unwrap_or_default();
+            return Err(format!("`{section}.{key}` must be a color like \"#1e1e2e\"{found}"));
+        };
+
+        match section {
+            "colors" => theme.colors[index] = color,
+            "ui" => theme.named[index] = Some(color),
+            _ => theme.named[UI_NAMES.len() + index] = Some(color),
+        }
+    }
+
+    Ok(theme)
+}
+
+/// Returns the color names in a section.
+fn names(section: &str) -> Result<&'static [&'static str], String> {
+    match section {
+        "colors" => Ok(&COLOR_NAMES),
+        "ui" => Ok(&UI_NAMES),
+        "syntax" => Ok(&SYNTAX_NAMES),
+        _ => Err(format!("unknown section `{section}`, expected one of `colors`, `ui`, `syntax`")),
+    }
+}
+
+/// Parses `#rrggbb` or `#rrggbbaa`.
+pub fn parse_color(s: &str) -> Option<StraightRgba> {
+    let hex = s.strip_prefix('#')?;
+    if !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    let value = u32::from_str_radix(hex, 16).ok()?;
+    match hex.len() {
+        6 => Some(StraightRgba::from_be(value << 8 | 0xff)),
+        8 => Some(StraightRgba::from_be(value)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use edit::framebuffer::{IndexedColor, NamedColor};
+
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let toml = "[colors]\nbackground = \"#1e1e2e\"\n[ui]\nselection = \"#585b70\"\n\
+                    [syntax]\ncomment = \"#6c708680\"\n";
+        let json = r##"{"colors": {"background": "#1e1e2e"}, "ui": {"selection": "#585b70"},
+                        "syntax": {"comment": "#6c708680"}}"##;
+
+        for theme in [parse_toml(toml).unwrap(), parse_json(json).unwrap()] {
+            let bg = IndexedColor::Background as usize;
+            assert_eq!(theme.colors[bg], StraightRgba::from_be(0x1e1e2eff));
+            assert_eq!(theme.colors[0], DEFAULT_THEME[0]);
+            assert_eq!(
+                theme.named[NamedColor::Selection as usize],
+                Some(StraightRgba::from_be(0x585b70ff))
+            );
+            assert_eq!(
+                theme.named[NamedColor::Comment as usize],
+                Some(StraightRgba::from_be(0x6c708680))
+            );
+            assert_eq!(theme.named[NamedColor::Bar as usize], None);
+        }
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let err = |text| parse_toml(text).err().unwrap();
+        assert_eq!(
+            err("[colors]\nred = \"red\"\n"),
+            "`colors.red` must be a color like \"#1e1e2e\", not \"red\""
+        );
+        assert_eq!(
+            err("[ui]\nmenubar = \"#000000\"\n"),
+            "unknown color `ui.menubar`, expected one of bar, floater, selection, line_number, \
+             whitespace, ruler, focus"
+        );
+        assert_eq!(
+            err("[palette]\n"),
+            "unknown section `palette`, expected one of `colors`, `ui`, `syntax`"
+        );
+        assert!(err("[colors\n").starts_with("1:"));
+        assert!(parse_json("{\"colors\": [1]}").is_err());
+    }
+}
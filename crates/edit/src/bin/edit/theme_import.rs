@@ -0,0 +1,280 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Reads themes made for other editors, so that the existing ones can be
+//! dropped into the themes directory as they are: VS Code color themes
+//! (`*.json` with `colors` like `editor.background` and `tokenColors`) and
+//! base16 schemes (`*.yaml`).
+//!
+//! Colors that don't exist in their format, or that a theme leaves out,
+//! are derived from the palette as usual.
+
+use crate::json::Json;
+use crate::theme::{self, Theme};
+
+/// Our colors and VS Code's names for them.
+const VSCODE_COLORS: [(&str, &str, &str); 25] = [
+    ("colors", "black", "terminal.ansiBlack"),
+    ("colors", "red", "terminal.ansiRed"),
+    ("colors", "green", "terminal.ansiGreen"),
+    ("colors", "yellow", "terminal.ansiYellow"),
+    ("colors", "blue", "terminal.ansiBlue"),
+    ("colors", "magenta", "terminal.ansiMagenta"),
+    ("colors", "cyan", "terminal.ansiCyan"),
+    ("colors", "white", "terminal.ansiWhite"),
+    ("colors", "bright_black", "terminal.ansiBrightBlack"),
+    ("colors", "bright_red", "terminal.ansiBrightRed"),
+    ("colors", "bright_green", "terminal.ansiBrightGreen"),
+    ("colors", "bright_yellow", "terminal.ansiBrightYellow"),
+    ("colors", "bright_blue", "terminal.ansiBrightBlue"),
+    ("colors", "bright_magenta", "terminal.ansiBrightMagenta"),
+    ("colors", "bright_cyan", "terminal.ansiBrightCyan"),
+    ("colors", "bright_white", "terminal.ansiBrightWhite"),
+    ("colors", "background", "editor.background"),
+    ("colors", "foreground", "editor.foreground"),
+    ("ui", "bar", "statusBar.background"),
+    ("ui", "floater", "editorWidget.background"),
+    ("ui", "selection", "editor.selectionBackground"),
+    ("ui", "line_number", "editorLineNumber.foreground"),
+    ("ui", "whitespace", "editorWhitespace.foreground"),
+    ("ui", "ruler", "editorRuler.foreground"),
+    ("ui", "focus", "list.activeSelectionBackground"),
+];
+
+/// Our syntax colors and the TextMate scope that represents each best.
+const VSCODE_SCOPES: [(&str, &str); 8] = [
+    ("comment", "comment"),
+    ("keyword", "keyword"),
+    ("string", "string"),
+    ("number", "constant.numeric"),
+    ("constant", "constant.language"),
+    ("function", "entity.name.function"),
+    ("type", "entity.name.type"),
+    ("operator", "keyword.operator"),
+];
+
+/// Our colors and the base16 colors for them, as in base16-shell and the base16 styling guidelines.
+const BASE16_COLORS: [(&str, &str, u8); 33] = [
+    ("colors", "black", 0x00),
+    ("colors", "red", 0x08),
+    ("colors", "green", 0x0b),
+    ("colors", "yellow", 0x0a),
+    ("colors", "blue", 0x0d),
+    ("colors", "magenta", 0x0e),
+    ("colors", "cyan", 0x0c),
+    ("colors", "white", 0x05),
+    ("colors", "bright_black", 0x03),
+    ("colors", "bright_red", 0x08),
+    ("colors", "bright_green", 0x0b),
+    ("colors", "bright_yellow", 0x0a),
+    ("colors", "bright_blue", 0x0d),
+    ("colors", "bright_magenta", 0x0e),
+    ("colors", "bright_cyan", 0x0c),
+    ("colors", "bright_white", 0x07),
+    ("colors", "background", 0x00),
+    ("colors", "foreground", 0x05),
+    ("ui", "bar", 0x01),
+    ("ui", "floater", 0x01),
+    ("ui", "selection", 0x02),
+    ("ui", "line_number", 0x03),
+    ("ui", "whitespace", 0x03),
+    ("ui", "ruler", 0x01),
+    ("ui", "focus", 0x0d),
+    ("syntax", "comment", 0x03),
+    ("syntax", "keyword", 0x0e),
+    ("syntax", "string", 0x0b),
+    ("syntax", "number", 0x09),
+    ("syntax", "constant", 0x09),
+    ("syntax", "function", 0x0d),
+    ("syntax", "type", 0x0a),
+    ("syntax", "operator", 0x05),
+];
+
+/// Tells VS Code themes apart from our own JSON themes.
+pub fn is_vscode(root: &Json) -> bool {
+    root.get("tokenColors").is_some()
+        || matches!(root.get("colors"), Some(Json::Object(fields)) if fields.iter().any(|(key, _)| key.contains('.')))
+}
+
+pub fn vscode(root: &Json) -> Result<Theme, String> {
+    let mut entries = Vec::new();
+
+    if let Some(colors) = root.get("colors") {
+        for (section, name, key) in VSCODE_COLORS {
+            if let Some(value) = colors.get(key).and_then(Json::as_str) {
+                entries.push((section, name, value));
+            }
+        }
+    }
+    if let Some(Json::Array(rules)) = root.get("tokenColors") {
+        for (name, scope) in VSCODE_SCOPES {
+            if let Some(value) = token_color(rules, scope) {
+                entries.push(("syntax", name, value));
+            }
+        }
+    }
+
+    build(&entries)
+}
+
+/// Returns the color VS Code would use for `scope`:
+/// That of the most specific rule that applies to it. Later rules win ties.
+fn token_color<'a>(rules: &'a [Json], scope: &str) -> Option<&'a str> {
+    let mut best: Option<(usize, &str)> = None;
+
+    for rule in rules {
+        let Some(color) =
+            rule.get("settings").and_then(|s| s.get("foreground")).and_then(Json::as_str)
+        else {
+            continue;
+        };
+        let selectors: Vec<&str> = match rule.get("scope") {
+            Some(Json::String(s)) => s.split(',').collect(),
+            Some(Json::Array(items)) => items.iter().filter_map(Json::as_str).collect(),
+            _ => continue,
+        };
+
+        for selector in selectors {
+            let selector = selector.trim();
+            let applies = scope
+                .strip_prefix(selector)
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with('.'));
+            if applies && best.is_none_or(|(len, _)| selector.len() >= len) {
+                best = Some((selector.len(), color));
+            }
+        }
+    }
+
+    best.map(|(_, color)| color)
+}
+
+/// Reads both the classic format (`base00: "181818"`)
+/// and the newer one, which nests them below `palette:`.
+pub fn base16(text: &str) -> Result<Theme, String> {
+    let mut palette: [Option<String>; 16] = Default::default();
+
+    for line in text.lines() {
+        let Some((key, value)) = line.split_once(':') else {
+            continue;
+        };
+        let key = key.trim();
+        let Some(index) = key
+            .strip_prefix("base0")
+            .filter(|digit| digit.len() == 1)
+            .and_then(|digit| u8::from_str_radix(digit, 16).ok())
+        else {
+            continue;
+        };
+
+        let value = value.trim();
+        let value = match value.chars().next() {
+            Some(quote @ ('"' | '\'')) => value[1..].split(quote).next().unwrap_or(""),
+            _ => value.split(" #").next().unwrap_or("").trim(),
+        };
+        let hex = value.strip_prefix('#').unwrap_or(value);
+        palette[index as usize] = Some(format!("#{hex}"));
+    }
+
+    for (index, value) in palette.iter().enumerate() {
+        match value {
+            None => {
+                return Err(format!(
+                    "`base{index:02X}` is missing, base16 schemes have base00 to base0F"
+                ));
+            }
+            Some(value) if theme::parse_color(value).is_none() => {
+                return Err(format!(
+                    "`base{index:02X}` must be a color like \"1e1e2e\", not \"{value}\""
+                ));
+            }
+            Some(_) => {}
+        }
+    }
+
+    let entries: Vec<_> = BASE16_COLORS
+        .iter()
+        .map(|&(section, name, index)| (section, name, palette[index as usize].as_deref().unwrap()))
+        .collect();
+
+    build(&entries)
+}
+
+/// Leaves out the colors we can't read, since the formats are less strict than ours.
+fn build(entries: &[(&str, &str, &str)]) -> Result<Theme, String> {
+    let entries: Vec<_> = entries
+        .iter()
+        .filter(|(_, _, value)| theme::parse_color(value).is_some())
+        .map(|&(section, name, value)| (section, name, Some(value)))
+        .collect();
+    theme::build(&entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use edit::framebuffer::{IndexedColor, NamedColor};
+    use edit::oklab::StraightRgba;
+
+    use super::*;
+    use crate::json;
+
+    #[test]
+    fn test_vscode() {
+        let text = r##"{
+            // VS Code allows comments...
+            "name": "Test",
+            "colors": {
+                "editor.background": "#1e1e1e",
+                "editor.selectionBackground": "#264f7880",
+                "statusBar.background": "invalid",
+            },
+            /* ...and trailing commas. */
+            "tokenColors": [
+                { "settings": { "foreground": "#d4d4d4" } },
+                { "scope": "keyword", "settings": { "foreground": "#569cd6" } },
+                { "scope": "keyword.operator", "settings": { "foreground": "#d4d4d4" } },
+                { "scope": ["constant", "comment.line"], "settings": { "foreground": "#4fc1ff" } },
+                { "scope": "string, comment", "settings": { "foreground": "#ce9178" } },
+                { "scope": "constant.numeric", "settings": { "foreground": "#b5cea8" } },
+            ],
+        }"##;
+        let root = json::parse(text).unwrap();
+        assert!(is_vscode(&root));
+        let theme = vscode(&root).unwrap();
+
+        let color = |hex| Some(StraightRgba::from_be(hex));
+        let named = |name: NamedColor| theme.named[name as usize];
+        assert_eq!(theme.colors[IndexedColor::Background as usize], color(0x1e1e1eff).unwrap());
+        assert_eq!(named(NamedColor::Selection), color(0x264f7880));
+        assert_eq!(named(NamedColor::Bar), None);
+        assert_eq!(named(NamedColor::Keyword), color(0x569cd6ff));
+        assert_eq!(named(NamedColor::Operator), color(0xd4d4d4ff));
+        assert_eq!(named(NamedColor::String), color(0xce9178ff));
+        assert_eq!(named(NamedColor::Comment), color(0xce9178ff));
+        assert_eq!(named(NamedColor::Constant), color(0x4fc1ffff));
+        assert_eq!(named(NamedColor::Number), color(0xb5cea8ff));
+        assert_eq!(named(NamedColor::Function), None);
+
+        let ours = json::parse(r##"{"colors": {"background": "#000000"}}"##).unwrap();
+        assert!(!is_vscode(&ours));
+    }
+
+    #[test]
+    fn test_base16() {
+        let mut classic = String::from("scheme: \"Test\"\nauthor: \"Someone\"\n");
+        let mut palette = String::from("system: \"base16\"\nname: \"Test\"\npalette:\n");
+        for i in 0..16 {
+            classic.push_str(&format!("base{i:02X}: \"{i:x}{i:x}{i:x}{i:x}{i:x}{i:x}\"\n"));
+            palette.push_str(&format!("  base{i:02X}: '#{i:x}{i:x}{i:x}{i:x}{i:x}{i:x}' # {i}\n"));
+        }
+
+        for text in [&classic, &palette] {
+            let theme = base16(text).unwrap();
+            assert_eq!(theme.colors[IndexedColor::Background as usize].to_be(), 0x000000ff);
+            assert_eq!(theme.colors[IndexedColor::Red as usize].to_be(), 0x888888ff);
+            assert_eq!(theme.named[NamedColor::Comment as usize].unwrap().to_be(), 0x333333ff);
+        }
+
+        let err = base16(&classic.replace("base0F", "base0G")).err().unwrap();
+        assert_eq!(err, "`base0F` is missing, base16 schemes have base00 to base0F");
+    }
+}
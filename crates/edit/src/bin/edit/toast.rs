@@ -0,0 +1,136 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Short messages in the top right corner that go away by themselves,
+//! for things the user should know about but needn't act upon.
+//!
+//! Everything that was shown, errors included, stays in the history,
+//! so that a message that went by too fast or was read out incompletely
+//! by a screen reader can be read again.
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// At most this many toasts are stacked at once. Older ones make room.
+const MAX_VISIBLE: usize = 3;
+/// The history forgets the oldest messages beyond this.
+const MAX_HISTORY: usize = 100;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Severity {
+    Info,
+    Warning,
+    Error,
+}
+
+impl Severity {
+    /// Warnings stay longer, since they're usually about something that went wrong.
+    fn timeout(self) -> Duration {
+        match self {
+            Self::Info => Duration::from_secs(4),
+            Self::Warning | Self::Error => Duration::from_secs(8),
+        }
+    }
+}
+
+pub struct Toast {
+    pub severity: Severity,
+    pub message: String,
+    shown: Instant,
+}
+
+pub struct Toasts {
+    /// The ones on screen, oldest first.
+    visible: VecDeque<Toast>,
+    /// Everything, newest first.
+    history: VecDeque<(Severity, String)>,
+}
+
+impl Toasts {
+    pub fn new() -> Self {
+        Self { visible: VecDeque::new(), history: VecDeque::new() }
+    }
+
+    /// Shows a toast and adds it to the history.
+    pub fn add(&mut self, severity: Severity, message: String) {
+        self.add_to_history(severity, message.clone());
+        if self.visible.len() >= MAX_VISIBLE {
+            self.visible.pop_front();
+        }
+        self.visible.push_back(Toast { severity, message, shown: Instant::now() });
+    }
+
+    /// For messages that are shown some other way, like errors in the error dialog.
+    pub fn add_to_history(&mut self, severity: Severity, message: String) {
+        if self.history.len() >= MAX_HISTORY {
+            self.history.pop_back();
+        }
+        self.history.push_front((severity, message));
+    }
+
+    pub fn visible(&self) -> impl Iterator<Item = &Toast> {
+        self.visible.iter()
+    }
+
+    pub fn history(&self) -> impl Iterator<Item = &(Severity, String)> {
+        self.history.iter()
+    }
+
+    pub fn dismiss(&mut self, index: usize) {
+        self.visible.remove(index);
+    }
+
+    pub fn clear_history(&mut self) {
+        self.history.clear();
+    }
+
+    /// Removes the toasts that were shown for long enough. Returns whether any were.
+    pub fn expire(&mut self) -> bool {
+        let len = self.visible.len();
+        self.visible.retain(|toast| toast.shown.elapsed() < toast.severity.timeout());
+        self.visible.len() != len
+    }
+
+    /// How long the main loop may block waiting for input before the next toast expires.
+    pub fn read_timeout(&self) -> Duration {
+        self.visible
+            .iter()
+            .map(|toast| toast.severity.timeout().saturating_sub(toast.shown.elapsed()))
+            .min()
+            .unwrap_or(Duration::MAX)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_toasts() {
+        let mut toasts = Toasts::new();
+        assert_eq!(toasts.read_timeout(), Duration::MAX);
+
+        for i in 0..5 {
+            toasts.add(Severity::Info, format!("{i}"));
+        }
+        toasts.add_to_history(Severity::Error, "error".to_string());
+
+        let visible: Vec<_> = toasts.visible().map(|t| t.message.as_str()).collect();
+        assert_eq!(visible, ["2", "3", "4"]);
+        let history: Vec<_> = toasts.history().map(|(_, m)| m.as_str()).collect();
+        assert_eq!(history, ["error", "4", "3", "2", "1", "0"]);
+        assert!(toasts.read_timeout() <= Severity::Info.timeout());
+
+        toasts.dismiss(0);
+        assert_eq!(toasts.visible().count(), 2);
+        assert!(!toasts.expire());
+
+        // Pretend they've been around for a while.
+        for toast in &mut toasts.visible {
+            toast.shown -= Severity::Info.timeout();
+        }
+        assert!(toasts.expire());
+        assert_eq!(toasts.visible().count(), 0);
+        assert_eq!(toasts.history().count(), 6);
+    }
+}
@@ -0,0 +1,1533 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Optional Vim-style modal editing.
+//!
+//! This is a layer in front of the editor's textarea. In normal and visual mode it
+//! consumes the keyboard input and turns it into calls on the [`TextBuffer`].
+//! In insert mode it stays out of the way, apart from recording what gets typed,
+//! so that `.` can repeat it. Only the commonly used subset of Vim is supported:
+//!
+//! * Motions: `h j k l w b e 0 ^ $ gg G f F t T` and the arrow keys
+//! * Operators: `d c y > <` with a motion, doubled (`dd`), or on a visual selection
+//! * Commands: `x X D C Y s S p P J r ~ u Ctrl+R Ctrl+A Ctrl+X . i a I A o O v V n / ZZ ZQ`
+//! * Counts, registers (`"a`, `"A` to append, `"+` for the clipboard, `"_`)
+//! * `:w`, `:q`, `:q!`, `:wq`, `:x` and `:<line>`
+//! * Ctrl+K followed by two characters in insert mode for a digraph, see `digraph.rs`
+
+use std::collections::HashMap;
+
+use edit::buffer::{CursorMovement, TextBuffer};
+use edit::clipboard::Clipboard;
+use edit::helpers::{CoordType, Point};
+use edit::input::{InputKey, kbmod, vk};
+
+/// Counts larger than this are clamped, so that `99999999x` doesn't hang.
+const MAX_COUNT: usize = 10000;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum VimMode {
+    Normal,
+    Insert,
+    Visual,
+    VisualLine,
+}
+
+/// A key as far as Vim emulation is concerned.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum VimKey {
+    Char(char),
+    Escape,
+    Enter,
+    Backspace,
+    Delete,
+    Tab,
+    Left,
+    Right,
+    Up,
+    Down,
+    Redo,
+    Increment,
+    Decrement,
+}
+
+impl VimKey {
+    fn from_input(key: InputKey) -> Option<Self> {
+        Some(match key {
+            vk::ESCAPE => Self::Escape,
+            vk::RETURN => Self::Enter,
+            vk::BACK => Self::Backspace,
+            vk::DELETE => Self::Delete,
+            vk::TAB => Self::Tab,
+            vk::LEFT => Self::Left,
+            vk::RIGHT => Self::Right,
+            vk::UP => Self::Up,
+            vk::DOWN => Self::Down,
+            _ if key == kbmod::CTRL | vk::R => Self::Redo,
+            _ if key == kbmod::CTRL | vk::A => Self::Increment,
+            _ if key == kbmod::CTRL | vk::X => Self::Decrement,
+            _ => return None,
+        })
+    }
+}
+
+/// Actions that the editor around the buffer has to carry out.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum VimCommand {
+    Save,
+    Close,
+    ForceClose,
+    SaveClose,
+    Search,
+    SearchNext,
+    ReloadConfig,
+    WriteStdout,
+    /// Ctrl+K in insert mode, which waits for the two characters of a digraph.
+    Digraph,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Motion {
+    Left,
+    Right,
+    Up,
+    Down,
+    WordForward,
+    WordBackward,
+    WordEnd,
+    LineStart,
+    FirstNonBlank,
+    LineEnd,
+    FileStart,
+    FileEnd,
+    FindForward(char),
+    FindBackward(char),
+    TillForward(char),
+    TillBackward(char),
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Operator {
+    Delete,
+    Change,
+    Yank,
+    Indent,
+    Outdent,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum OperatorTarget {
+    Motion(Motion),
+    /// `dd`, `cc`, `yy`, etc.
+    Line,
+    /// The visual mode selection.
+    Selection,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Action {
+    Move(Motion),
+    Operator(Operator, OperatorTarget),
+    DeleteChar,
+    DeleteCharBackward,
+    PasteAfter,
+    PasteBefore,
+    Undo,
+    Redo,
+    Insert,
+    Append,
+    InsertLineStart,
+    AppendLineEnd,
+    OpenBelow,
+    OpenAbove,
+    Visual,
+    VisualLine,
+    VisualSwap,
+    Repeat,
+    Join,
+    Replace(char),
+    ToggleCase,
+    /// Ctrl+A and Ctrl+X, with 1 and -1.
+    AddToNumber(i64),
+    CommandLine,
+    Command(VimCommand),
+    Escape,
+}
+
+impl Action {
+    /// Whether `.` should be able to repeat this action.
+    fn is_change(self) -> bool {
+        match self {
+            Self::Operator(op, target) => {
+                op != Operator::Yank && target != OperatorTarget::Selection
+            }
+            Self::DeleteChar
+            | Self::DeleteCharBackward
+            | Self::PasteAfter
+            | Self::PasteBefore
+            | Self::Insert
+            | Self::Append
+            | Self::InsertLineStart
+            | Self::AppendLineEnd
+            | Self::OpenBelow
+            | Self::OpenAbove
+            | Self::Join
+            | Self::Replace(_)
+            | Self::ToggleCase
+            | Self::AddToNumber(_) => true,
+            _ => false,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+struct Command {
+    register: char,
+    count: Option<usize>,
+    action: Action,
+}
+
+enum Parse<T> {
+    Incomplete,
+    Invalid,
+    Done(T),
+}
+
+#[derive(Clone, Default)]
+struct Register {
+    text: Vec<u8>,
+    linewise: bool,
+}
+
+/// A range of text that an operator works on.
+struct Span {
+    beg: usize,
+    end: usize,
+    linewise: bool,
+}
+
+pub struct Vim {
+    pub enabled: bool,
+    mode: VimMode,
+    /// The keys of the command that's currently being typed.
+    pending: Vec<VimKey>,
+    /// The contents of the `:` command line, while it's open.
+    cmdline: Option<String>,
+    /// The keys that make up the last change, for `.`.
+    last_change: Vec<VimKey>,
+    /// The keys of the change that's in progress while in insert mode.
+    recording: Option<Vec<VimKey>>,
+    registers: HashMap<char, Register>,
+    /// The column that `j` and `k` try to stay in.
+    want_col: Option<CoordType>,
+    /// In visual mode: The offset where the selection started and the current one.
+    visual_anchor: usize,
+    visual_cursor: usize,
+    command: Option<VimCommand>,
+}
+
+impl Vim {
+    pub fn new() -> Self {
+        Self {
+            enabled: false,
+            mode: VimMode::Normal,
+            pending: Vec::new(),
+            cmdline: None,
+            last_change: Vec::new(),
+            recording: None,
+            registers: HashMap::new(),
+            want_col: None,
+            visual_anchor: 0,
+            visual_cursor: 0,
+            command: None,
+        }
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        self.mode = VimMode::Normal;
+        self.pending.clear();
+        self.cmdline = None;
+        self.recording = None;
+    }
+
+    /// Returns what should be shown in the status bar, e.g. `NORMAL 2d` or `:wq`.
+    /// `short` abbreviates the mode to e.g. `N 2d`, for narrow screens.
+    pub fn status(&self, short: bool) -> String {
+        if let Some(cmdline) = &self.cmdline {
+            return format!(":{cmdline}");
+        }
+
+        let mut status = String::from(match (self.mode, short) {
+            (VimMode::Normal, false) => "NORMAL",
+            (VimMode::Normal, true) => "N",
+            (VimMode::Insert, false) => "INSERT",
+            (VimMode::Insert, true) => "I",
+            (VimMode::Visual, false) => "VISUAL",
+            (VimMode::Visual, true) => "V",
+            (VimMode::VisualLine, false) => "V-LINE",
+            (VimMode::VisualLine, true) => "VL",
+        });
+        if !self.pending.is_empty() {
+            status.push(' ');
+            for key in &self.pending {
+                if let VimKey::Char(ch) = key {
+                    status.push(*ch);
+                }
+            }
+        }
+        status
+    }
+
+    /// Whether Esc has nothing left to cancel.
+    pub fn is_idle(&self) -> bool {
+        self.mode == VimMode::Normal && self.pending.is_empty() && self.cmdline.is_none()
+    }
+
+    /// Returns an action that the caller needs to carry out, if any.
+    pub fn take_command(&mut self) -> Option<VimCommand> {
+        self.command.take()
+    }
+
+    /// Handles typed text. Returns `true` if it was consumed.
+    pub fn handle_text(
+        &mut self,
+        tb: &mut TextBuffer,
+        clipboard: &mut Clipboard,
+        text: &str,
+    ) -> bool {
+        if self.mode == VimMode::Insert {
+            // Let the textarea insert it, but remember it for `.`.
+            for ch in text.chars() {
+                self.record(VimKey::Char(ch));
+            }
+            return false;
+        }
+
+        // If the text switches us into insert mode (e.g. "ihello" typed very quickly),
+        // we have to insert the remainder ourselves, because we consume all of it.
+        for ch in text.chars() {
+            self.key(tb, clipboard, VimKey::Char(ch), true);
+        }
+        true
+    }
+
+    /// Handles a key press. Returns `true` if it was consumed.
+    pub fn handle_key(
+        &mut self,
+        tb: &mut TextBuffer,
+        clipboard: &mut Clipboard,
+        key: InputKey,
+    ) -> bool {
+        if self.mode == VimMode::Insert && key == kbmod::CTRL | vk::K {
+            self.command = Some(VimCommand::Digraph);
+            return true;
+        }
+        match VimKey::from_input(key) {
+            Some(key) => self.key(tb, clipboard, key, false),
+            None => false,
+        }
+    }
+
+    fn key(
+        &mut self,
+        tb: &mut TextBuffer,
+        clipboard: &mut Clipboard,
+        key: VimKey,
+        apply: bool,
+    ) -> bool {
+        if matches!(self.mode, VimMode::Visual | VimMode::VisualLine) && !tb.has_selection() {
+            // The selection was removed from outside, e.g. by a mouse click.
+            self.mode = VimMode::Normal;
+            self.pending.clear();
+        }
+
+        if self.mode == VimMode::Insert {
+            return self.insert_key(tb, key, apply);
+        }
+
+        if self.cmdline.is_some() {
+            self.cmdline_key(tb, key);
+            return true;
+        }
+
+        self.pending.push(key);
+        match parse(&self.pending, self.mode != VimMode::Normal) {
+            Parse::Incomplete => {}
+            Parse::Invalid => self.pending.clear(),
+            Parse::Done(cmd) => {
+                let keys = std::mem::take(&mut self.pending);
+                self.execute(tb, clipboard, cmd, keys);
+            }
+        }
+
+        if self.mode == VimMode::Normal {
+            clamp_cursor(tb);
+        }
+        tb.make_cursor_visible();
+        true
+    }
+
+    fn insert_key(&mut self, tb: &mut TextBuffer, key: VimKey, apply: bool) -> bool {
+        if key == VimKey::Escape {
+            if let Some(mut keys) = self.recording.take() {
+                keys.push(VimKey::Escape);
+                self.last_change = keys;
+            }
+            self.mode = VimMode::Normal;
+            // Like in Vim, the cursor moves onto the last inserted character.
+            if tb.cursor_logical_pos().x > 0 {
+                tb.cursor_move_delta(CursorMovement::Grapheme, -1);
+            }
+            return true;
+        }
+
+        if !matches!(key, VimKey::Left | VimKey::Right | VimKey::Up | VimKey::Down) {
+            self.record(key);
+        }
+
+        if !apply {
+            return false;
+        }
+
+        match key {
+            VimKey::Char(ch) => tb.write_typed(ch.encode_utf8(&mut [0; 4]).as_bytes()),
+            VimKey::Enter => tb.write_canon(b"\n"),
+            VimKey::Tab => tb.write_canon(b"\t"),
+            VimKey::Backspace => tb.delete(CursorMovement::Grapheme, -1),
+            VimKey::Delete => tb.delete(CursorMovement::Grapheme, 1),
+            _ => {}
+        }
+        true
+    }
+
+    fn record(&mut self, key: VimKey) {
+        if let Some(keys) = &mut self.recording {
+            keys.push(key);
+        }
+    }
+
+    fn cmdline_key(&mut self, tb: &mut TextBuffer, key: VimKey) {
+        let Some(cmdline) = &mut self.cmdline else {
+            return;
+        };
+
+        match key {
+            VimKey::Char(ch) => cmdline.push(ch),
+            VimKey::Backspace if !cmdline.is_empty() => _ = cmdline.pop(),
+            VimKey::Enter => {
+                let cmdline = self.cmdline.take().unwrap_or_default();
+                self.command = match cmdline.trim() {
+                    "w" => Some(VimCommand::Save),
+                    "q" => Some(VimCommand::Close),
+                    "q!" => Some(VimCommand::ForceClose),
+                    "wq" | "x" => Some(VimCommand::SaveClose),
+                    "reload-config" => Some(VimCommand::ReloadConfig),
+                    "write-stdout" => Some(VimCommand::WriteStdout),
+                    line => {
+                        if let Ok(line) = line.parse::<CoordType>() {
+                            goto_line(tb, line.saturating_sub(1));
+                        }
+                        None
+                    }
+                };
+            }
+            _ => self.cmdline = None,
+        }
+    }
+
+    fn execute(
+        &mut self,
+        tb: &mut TextBuffer,
+        clipboard: &mut Clipboard,
+        cmd: Command,
+        keys: Vec<VimKey>,
+    ) {
+        let count = cmd.count.unwrap_or(1);
+        let mut enters_insert = false;
+
+        if !matches!(cmd.action, Action::Move(Motion::Up | Motion::Down)) {
+            self.want_col = None;
+        }
+
+        match cmd.action {
+            Action::Move(motion) => {
+                let from = self.cursor(tb);
+                if matches!(motion, Motion::Up | Motion::Down) && self.want_col.is_none() {
+                    self.want_col = Some(pos_of(tb, from).x);
+                }
+                if let Some(target) = self.motion(tb, from, motion, cmd.count) {
+                    self.set_cursor(tb, target.end);
+                }
+            }
+            Action::Operator(op, target) => {
+                let span = match target {
+                    OperatorTarget::Selection => self.visual_span(tb),
+                    OperatorTarget::Line => {
+                        let from = self.cursor(tb);
+                        let y = pos_of(tb, from).y;
+                        Some(line_span(tb, y, y + count as CoordType - 1))
+                    }
+                    OperatorTarget::Motion(mut motion) => {
+                        let from = self.cursor(tb);
+                        // `cw` is special: It behaves like `ce`, unless you're on whitespace.
+                        if op == Operator::Change
+                            && motion == Motion::WordForward
+                            && char_class(byte_at(tb, from)) != 0
+                        {
+                            motion = Motion::WordEnd;
+                        }
+                        self.motion(tb, from, motion, cmd.count).map(|mut span| {
+                            span.beg = from;
+                            normalize_span(tb, span, motion)
+                        })
+                    }
+                };
+                if let Some(span) = span {
+                    self.mode = VimMode::Normal;
+                    enters_insert = self.apply_operator(tb, clipboard, op, cmd.register, span);
+                }
+            }
+            Action::DeleteChar | Action::DeleteCharBackward => {
+                let from = self.cursor(tb);
+                let y = pos_of(tb, from).y;
+                let (beg, end) = if cmd.action == Action::DeleteChar {
+                    let line_end = offset_of(tb, Point { x: CoordType::MAX, y });
+                    (from, advance(tb, from, count, line_end))
+                } else {
+                    let line_beg = offset_of(tb, Point { x: 0, y });
+                    (retreat(tb, from, count, line_beg), from)
+                };
+                if beg < end {
+                    let span = Span { beg, end, linewise: false };
+                    self.apply_operator(tb, clipboard, Operator::Delete, cmd.register, span);
+                }
+            }
+            Action::PasteAfter | Action::PasteBefore => {
+                self.paste(tb, clipboard, cmd.register, count, cmd.action == Action::PasteAfter);
+            }
+            Action::Undo => {
+                for _ in 0..count {
+                    tb.undo();
+                }
+            }
+            Action::Redo => {
+                for _ in 0..count {
+                    tb.redo();
+                }
+            }
+            Action::Insert => enters_insert = true,
+            Action::Append => {
+                let from = self.cursor(tb);
+                let y = pos_of(tb, from).y;
+                let line_end = offset_of(tb, Point { x: CoordType::MAX, y });
+                tb.cursor_move_to_offset(advance(tb, from, 1, line_end));
+                enters_insert = true;
+            }
+            Action::InsertLineStart => {
+                let from = self.cursor(tb);
+                let y = pos_of(tb, from).y;
+                let off = first_non_blank(tb, y);
+                tb.cursor_move_to_offset(off);
+                enters_insert = true;
+            }
+            Action::AppendLineEnd => {
+                let y = tb.cursor_logical_pos().y;
+                tb.cursor_move_to_logical(Point { x: CoordType::MAX, y });
+                enters_insert = true;
+            }
+            Action::OpenBelow => {
+                let y = tb.cursor_logical_pos().y;
+                tb.cursor_move_to_logical(Point { x: CoordType::MAX, y });
+                // Not raw, so that the new line gets indented like the current one.
+                tb.write_canon(b"\n");
+                enters_insert = true;
+            }
+            Action::OpenAbove => {
+                let y = tb.cursor_logical_pos().y;
+                let beg = offset_of(tb, Point { x: 0, y });
+                let end = first_non_blank(tb, y);
+                let indent = extract(tb, beg, end);
+                tb.cursor_move_to_offset(beg);
+                let mut text = indent.clone();
+                text.push(b'\n');
+                tb.write_raw(&text);
+                tb.cursor_move_to_offset(beg + indent.len());
+                enters_insert = true;
+            }
+            Action::Visual | Action::VisualLine => {
+                let mode = if cmd.action == Action::Visual {
+                    VimMode::Visual
+                } else {
+                    VimMode::VisualLine
+                };
+                if self.mode == mode {
+                    self.leave_visual(tb);
+                } else {
+                    if self.mode == VimMode::Normal {
+                        self.visual_anchor = tb.cursor_offset();
+                        self.visual_cursor = self.visual_anchor;
+                    }
+                    self.mode = mode;
+                    self.update_visual(tb);
+                }
+            }
+            Action::VisualSwap => {
+                std::mem::swap(&mut self.visual_anchor, &mut self.visual_cursor);
+                self.update_visual(tb);
+            }
+            Action::Repeat => {
+                self.repeat(tb, clipboard, cmd.count);
+                return;
+            }
+            Action::Join => {
+                let (y0, y1) = match self.visual_span(tb) {
+                    Some(span) => {
+                        self.mode = VimMode::Normal;
+                        let y0 = pos_of(tb, span.beg).y;
+                        let y1 = pos_of(tb, span.end.saturating_sub(1)).y;
+                        (y0, y1.max(y0 + 1))
+                    }
+                    None => {
+                        let y = tb.cursor_logical_pos().y;
+                        (y, y + (count as CoordType - 1).max(1))
+                    }
+                };
+                join_lines(tb, y0, y1);
+            }
+            Action::Replace(ch) => {
+                let from = self.cursor(tb);
+                let y = pos_of(tb, from).y;
+                let line_end = offset_of(tb, Point { x: CoordType::MAX, y });
+                let end = advance(tb, from, count, line_end);
+                if end > from && char_count(tb, from, end) == count {
+                    let mut text = String::new();
+                    for _ in 0..count {
+                        text.push(ch);
+                    }
+                    select(tb, from, end);
+                    tb.write_raw(text.as_bytes());
+                    tb.cursor_move_delta(CursorMovement::Grapheme, -1);
+                }
+            }
+            Action::ToggleCase => {
+                let (beg, end) = match self.visual_span(tb) {
+                    Some(span) => {
+                        self.mode = VimMode::Normal;
+                        (span.beg, span.end)
+                    }
+                    None => {
+                        let from = self.cursor(tb);
+                        let y = pos_of(tb, from).y;
+                        let line_end = offset_of(tb, Point { x: CoordType::MAX, y });
+                        (from, advance(tb, from, count, line_end))
+                    }
+                };
+                if beg < end {
+                    let text = String::from_utf8_lossy(&extract(tb, beg, end))
+                        .chars()
+                        .map(|c| {
+                            if c.is_lowercase() {
+                                c.to_uppercase().next().unwrap_or(c)
+                            } else {
+                                c.to_lowercase().next().unwrap_or(c)
+                            }
+                        })
+                        .collect::<String>();
+                    select(tb, beg, end);
+                    tb.write_raw(text.as_bytes());
+                }
+            }
+            Action::AddToNumber(delta) => {
+                // Visual mode changes the first number of every line.
+                if matches!(self.mode, VimMode::Visual | VimMode::VisualLine) {
+                    self.mode = VimMode::Normal;
+                }
+                tb.add_to_number(delta * count as i64, false);
+                tb.clear_selection();
+            }
+            Action::CommandLine => self.cmdline = Some(String::new()),
+            Action::Command(command) => self.command = Some(command),
+            Action::Escape => {
+                if matches!(self.mode, VimMode::Visual | VimMode::VisualLine) {
+                    self.leave_visual(tb);
+                }
+            }
+        }
+
+        if enters_insert {
+            self.mode = VimMode::Insert;
+        }
+        if cmd.action.is_change() {
+            if enters_insert {
+                self.recording = Some(keys);
+            } else {
+                self.last_change = keys;
+            }
+        }
+    }
+
+    /// The offset that motions start from.
+    fn cursor(&self, tb: &TextBuffer) -> usize {
+        match self.mode {
+            VimMode::Visual | VimMode::VisualLine => self.visual_cursor,
+            _ => tb.cursor_offset(),
+        }
+    }
+
+    fn set_cursor(&mut self, tb: &mut TextBuffer, offset: usize) {
+        match self.mode {
+            VimMode::Visual | VimMode::VisualLine => {
+                self.visual_cursor = offset;
+                self.update_visual(tb);
+            }
+            _ => tb.cursor_move_to_offset(offset),
+        }
+    }
+
+    /// Computes where `motion` leads to, starting at `from`.
+    /// The result is returned in [`Span::end`].
+    fn motion(
+        &mut self,
+        tb: &mut TextBuffer,
+        from: usize,
+        motion: Motion,
+        count: Option<usize>,
+    ) -> Option<Span> {
+        let n = count.unwrap_or(1);
+        let pos = pos_of(tb, from);
+        let last_line = tb.logical_line_count() - 1;
+        let mut linewise = false;
+
+        let end = match motion {
+            Motion::Left => offset_of(tb, Point { x: (pos.x - n as CoordType).max(0), y: pos.y }),
+            Motion::Right => {
+                let line_end = offset_of(tb, Point { x: CoordType::MAX, y: pos.y });
+                advance(tb, from, n, line_end)
+            }
+            Motion::Up | Motion::Down => {
+                let delta = if motion == Motion::Up { -(n as CoordType) } else { n as CoordType };
+                let y = (pos.y + delta).clamp(0, last_line);
+                linewise = true;
+                offset_of(tb, Point { x: self.want_col.unwrap_or(pos.x), y })
+            }
+            Motion::WordForward => (0..n).fold(from, |off, _| word_forward(tb, off)),
+            Motion::WordBackward => (0..n).fold(from, |off, _| word_backward(tb, off)),
+            Motion::WordEnd => (0..n).fold(from, |off, _| word_end(tb, off)),
+            Motion::LineStart => offset_of(tb, Point { x: 0, y: pos.y }),
+            Motion::FirstNonBlank => first_non_blank(tb, pos.y),
+            Motion::LineEnd => {
+                let y = (pos.y + n as CoordType - 1).min(last_line);
+                offset_of(tb, Point { x: CoordType::MAX, y })
+            }
+            Motion::FileStart | Motion::FileEnd => {
+                let y = match count {
+                    Some(n) => n as CoordType - 1,
+                    None if motion == Motion::FileStart => 0,
+                    None => last_line,
+                };
+                linewise = true;
+                first_non_blank(tb, y.clamp(0, last_line))
+            }
+            Motion::FindForward(ch) | Motion::TillForward(ch) => {
+                let line_end = offset_of(tb, Point { x: CoordType::MAX, y: pos.y });
+                let mut off = from;
+                for _ in 0..n {
+                    off = find_in_line(tb, off, line_end, ch, true)?;
+                }
+                if let Motion::TillForward(_) = motion {
+                    off = retreat(tb, off, 1, from);
+                }
+                off
+            }
+            Motion::FindBackward(ch) | Motion::TillBackward(ch) => {
+                let line_beg = offset_of(tb, Point { x: 0, y: pos.y });
+                let mut off = from;
+                for _ in 0..n {
+                    off = find_in_line(tb, off, line_beg, ch, false)?;
+                }
+                if let Motion::TillBackward(_) = motion {
+                    off = advance(tb, off, 1, from);
+                }
+                off
+            }
+        };
+
+        // Restore the cursor, which we moved around above.
+        if !matches!(self.mode, VimMode::Visual | VimMode::VisualLine) {
+            tb.cursor_move_to_offset(from);
+        }
+
+        // Inclusive motions like `e` are turned into exclusive ones by `normalize_span`.
+        Some(Span { beg: from, end, linewise })
+    }
+
+    fn visual_span(&self, tb: &mut TextBuffer) -> Option<Span> {
+        let (a, c) = (self.visual_anchor, self.visual_cursor);
+        match self.mode {
+            VimMode::Visual => {
+                let (beg, end) = (a.min(c), a.max(c));
+                Some(Span { beg, end: advance(tb, end, 1, tb.text_length()), linewise: false })
+            }
+            VimMode::VisualLine => {
+                let y0 = pos_of(tb, a.min(c)).y;
+                let y1 = pos_of(tb, a.max(c)).y;
+                Some(line_span(tb, y0, y1))
+            }
+            _ => None,
+        }
+    }
+
+    fn update_visual(&mut self, tb: &mut TextBuffer) {
+        let Some(span) = self.visual_span(tb) else {
+            return;
+        };
+        let (anchor, cursor) = if self.visual_cursor >= self.visual_anchor {
+            (span.beg, span.end)
+        } else {
+            (span.end, span.beg)
+        };
+        tb.cursor_move_to_offset(anchor);
+        tb.selection_update_offset(cursor);
+    }
+
+    fn leave_visual(&mut self, tb: &mut TextBuffer) {
+        self.mode = VimMode::Normal;
+        tb.cursor_move_to_offset(self.visual_cursor);
+    }
+
+    /// Applies `op` to `span`. Returns `true` if insert mode should be entered.
+    fn apply_operator(
+        &mut self,
+        tb: &mut TextBuffer,
+        clipboard: &mut Clipboard,
+        op: Operator,
+        register: char,
+        span: Span,
+    ) -> bool {
+        let mut text = extract(tb, span.beg, span.end);
+        if span.linewise && !text.ends_with(b"\n") {
+            text.push(b'\n');
+        }
+        let y0 = pos_of(tb, span.beg).y;
+
+        match op {
+            Operator::Yank => {
+                self.set_register(clipboard, register, text, span.linewise, true);
+                if span.linewise {
+                    let x = tb.cursor_logical_pos().x;
+                    tb.cursor_move_to_logical(Point { x, y: y0 });
+                } else {
+                    tb.cursor_move_to_offset(span.beg);
+                }
+                false
+            }
+            Operator::Delete => {
+                self.set_register(clipboard, register, text, span.linewise, false);
+                let mut beg = span.beg;
+                // Deleting the last line(s) also has to remove the newline before them.
+                if span.linewise && span.end == tb.text_length() && y0 > 0 {
+                    beg = offset_of(tb, Point { x: CoordType::MAX, y: y0 - 1 });
+                }
+                select(tb, beg, span.end);
+                tb.extract_user_selection(true);
+                if span.linewise {
+                    let y = y0.min(tb.logical_line_count() - 1);
+                    let off = first_non_blank(tb, y);
+                    tb.cursor_move_to_offset(off);
+                }
+                false
+            }
+            Operator::Change => {
+                self.set_register(clipboard, register, text, span.linewise, false);
+                let mut end = span.end;
+                if span.linewise {
+                    // `cc` keeps the newline and the indentation.
+                    let y1 = pos_of(tb, span.end.saturating_sub(1)).y;
+                    let y1 = if span.end == tb.text_length() { y1 } else { y1.max(y0) };
+                    end = offset_of(tb, Point { x: CoordType::MAX, y: y1 });
+                    let beg = first_non_blank(tb, y0);
+                    if beg < end {
+                        select(tb, beg, end);
+                        tb.extract_user_selection(true);
+                    }
+                    tb.cursor_move_to_offset(beg);
+                } else if span.beg < end {
+                    select(tb, span.beg, end);
+                    tb.extract_user_selection(true);
+                } else {
+                    tb.cursor_move_to_offset(span.beg);
+                }
+                true
+            }
+            Operator::Indent | Operator::Outdent => {
+                let y1 = pos_of(tb, span.end.saturating_sub(1).max(span.beg)).y;
+                let beg = offset_of(tb, Point { x: 0, y: y0 });
+                let end = offset_of(tb, Point { x: CoordType::MAX, y: y1 });
+                select(tb, beg, end);
+                tb.indent_change(if op == Operator::Indent { 1 } else { -1 });
+                let off = first_non_blank(tb, y0);
+                tb.cursor_move_to_offset(off);
+                false
+            }
+        }
+    }
+
+    fn paste(
+        &mut self,
+        tb: &mut TextBuffer,
+        clipboard: &mut Clipboard,
+        register: char,
+        count: usize,
+        after: bool,
+    ) {
+        let Some(reg) = self.get_register(clipboard, register) else {
+            return;
+        };
+        if reg.text.is_empty() {
+            return;
+        }
+
+        let mut text = Vec::with_capacity(reg.text.len() * count);
+        for _ in 0..count {
+            text.extend_from_slice(&reg.text);
+        }
+
+        if let Some(span) = self.visual_span(tb) {
+            // Replace the selection.
+            self.mode = VimMode::Normal;
+            select(tb, span.beg, span.end);
+            tb.write_raw(&text);
+            return;
+        }
+
+        let from = tb.cursor_offset();
+        let pos = pos_of(tb, from);
+
+        if reg.linewise {
+            let last_line = tb.logical_line_count() - 1;
+            let y = if after { pos.y + 1 } else { pos.y };
+            if y > last_line {
+                // There's no line after the last one to insert before, so we prepend a newline instead.
+                let mut with_newline = vec![b'\n'];
+                with_newline.extend_from_slice(&text[..text.len() - 1]);
+                tb.cursor_move_to_offset(tb.text_length());
+                tb.write_raw(&with_newline);
+            } else {
+                let off = offset_of(tb, Point { x: 0, y });
+                tb.cursor_move_to_offset(off);
+                tb.write_raw(&text);
+            }
+            let off = first_non_blank(tb, y);
+            tb.cursor_move_to_offset(off);
+        } else {
+            let at = if after {
+                let line_end = offset_of(tb, Point { x: CoordType::MAX, y: pos.y });
+                advance(tb, from, 1, line_end)
+            } else {
+                from
+            };
+            tb.cursor_move_to_offset(at);
+            tb.write_raw(&text);
+            tb.cursor_move_delta(CursorMovement::Grapheme, -1);
+        }
+    }
+
+    fn repeat(&mut self, tb: &mut TextBuffer, clipboard: &mut Clipboard, count: Option<usize>) {
+        let mut keys = self.last_change.clone();
+
+        // A count replaces the original one.
+        if let Some(count) = count {
+            let skip = if keys.first() == Some(&VimKey::Char('"')) { 2 } else { 0 };
+            let digits = keys[skip.min(keys.len())..]
+                .iter()
+                .take_while(|k| matches!(k, VimKey::Char('1'..='9')) || (**k == VimKey::Char('0')))
+                .count();
+            let mut with_count = keys[..skip.min(keys.len())].to_vec();
+            with_count.extend(count.to_string().chars().map(VimKey::Char));
+            with_count.extend_from_slice(&keys[(skip + digits).min(keys.len())..]);
+            keys = with_count;
+        }
+
+        for key in keys {
+            self.key(tb, clipboard, key, true);
+        }
+    }
+
+    fn set_register(
+        &mut self,
+        clipboard: &mut Clipboard,
+        register: char,
+        text: Vec<u8>,
+        linewise: bool,
+        yank: bool,
+    ) {
+        let reg = Register { text, linewise };
+        match register {
+            '_' => return,
+            '+' | '*' => {
+                clipboard.write(reg.text.clone());
+                clipboard.write_was_line_copy(linewise);
+            }
+            'A'..='Z' => {
+                let entry = self.registers.entry(register.to_ascii_lowercase()).or_default();
+                entry.text.extend_from_slice(&reg.text);
+                entry.linewise |= linewise;
+                let entry = entry.clone();
+                self.registers.insert('"', entry);
+                return;
+            }
+            '"' if yank => _ = self.registers.insert('0', reg.clone()),
+            _ => {}
+        }
+        if register != '"' {
+            self.registers.insert(register, reg.clone());
+        }
+        self.registers.insert('"', reg);
+    }
+
+    fn get_register(&self, clipboard: &Clipboard, register: char) -> Option<Register> {
+        match register {
+            '+' | '*' => Some(Register {
+                text: clipboard.read().to_vec(),
+                linewise: clipboard.is_line_copy(),
+            }),
+            _ => self.registers.get(&register.to_ascii_lowercase()).cloned(),
+        }
+    }
+}
+
+fn parse(keys: &[VimKey], visual: bool) -> Parse<Command> {
+    let mut i = 0;
+    let mut register = '"';
+
+    if keys.first() == Some(&VimKey::Char('"')) {
+        match keys.get(1) {
+            None => return Parse::Incomplete,
+            Some(VimKey::Char(ch)) => register = *ch,
+            Some(_) => return Parse::Invalid,
+        }
+        i = 2;
+    }
+
+    let (count, n) = parse_count(&keys[i..]);
+    i += n;
+
+    let Some(&key) = keys.get(i) else {
+        return Parse::Incomplete;
+    };
+
+    let action = match parse_motion(&keys[i..]) {
+        Parse::Done((motion, _)) => Action::Move(motion),
+        Parse::Incomplete => return Parse::Incomplete,
+        Parse::Invalid => {
+            let VimKey::Char(ch) = key else {
+                return match key {
+                    VimKey::Escape => {
+                        Parse::Done(Command { register, count, action: Action::Escape })
+                    }
+                    VimKey::Redo => Parse::Done(Command { register, count, action: Action::Redo }),
+                    VimKey::Increment | VimKey::Decrement => {
+                        let delta = if key == VimKey::Increment { 1 } else { -1 };
+                        Parse::Done(Command { register, count, action: Action::AddToNumber(delta) })
+                    }
+                    VimKey::Delete => {
+                        let action = if visual {
+                            Action::Operator(Operator::Delete, OperatorTarget::Selection)
+                        } else {
+                            Action::DeleteChar
+                        };
+                        Parse::Done(Command { register, count, action })
+                    }
+                    _ => Parse::Invalid,
+                };
+            };
+
+            let operator = match ch {
+                'd' => Some(Operator::Delete),
+                'c' => Some(Operator::Change),
+                'y' => Some(Operator::Yank),
+                '>' => Some(Operator::Indent),
+                '<' => Some(Operator::Outdent),
+                _ => None,
+            };
+
+            if let Some(op) = operator {
+                if visual {
+                    Action::Operator(op, OperatorTarget::Selection)
+                } else {
+                    let rest = &keys[i + 1..];
+                    let (motion_count, n) = parse_count(rest);
+                    let count = match (count, motion_count) {
+                        (Some(a), Some(b)) => Some((a * b).min(MAX_COUNT)),
+                        (a, b) => a.or(b),
+                    };
+                    let target = match rest.get(n) {
+                        None => return Parse::Incomplete,
+                        Some(&VimKey::Char(c)) if c == ch => OperatorTarget::Line,
+                        Some(_) => match parse_motion(&rest[n..]) {
+                            Parse::Done((motion, _)) => OperatorTarget::Motion(motion),
+                            Parse::Incomplete => return Parse::Incomplete,
+                            Parse::Invalid => return Parse::Invalid,
+                        },
+                    };
+                    return Parse::Done(Command {
+                        register,
+                        count,
+                        action: Action::Operator(op, target),
+                    });
+                }
+            } else {
+                match ch {
+                    'x' if visual => Action::Operator(Operator::Delete, OperatorTarget::Selection),
+                    's' if visual => Action::Operator(Operator::Change, OperatorTarget::Selection),
+                    'x' => Action::DeleteChar,
+                    'X' => Action::DeleteCharBackward,
+                    'D' => {
+                        Action::Operator(Operator::Delete, OperatorTarget::Motion(Motion::LineEnd))
+                    }
+                    'C' => {
+                        Action::Operator(Operator::Change, OperatorTarget::Motion(Motion::LineEnd))
+                    }
+                    'Y' => Action::Operator(Operator::Yank, OperatorTarget::Line),
+                    's' => {
+                        Action::Operator(Operator::Change, OperatorTarget::Motion(Motion::Right))
+                    }
+                    'S' => Action::Operator(Operator::Change, OperatorTarget::Line),
+                    'p' => Action::PasteAfter,
+                    'P' => Action::PasteBefore,
+                    'u' => Action::Undo,
+                    'i' => Action::Insert,
+                    'a' => Action::Append,
+                    'I' => Action::InsertLineStart,
+                    'A' => Action::AppendLineEnd,
+                    'o' if visual => Action::VisualSwap,
+                    'o' => Action::OpenBelow,
+                    'O' => Action::OpenAbove,
+                    'v' => Action::Visual,
+                    'V' => Action::VisualLine,
+                    '.' => Action::Repeat,
+                    'J' => Action::Join,
+                    '~' => Action::ToggleCase,
+                    ':' => Action::CommandLine,
+                    '/' => Action::Command(VimCommand::Search),
+                    'n' => Action::Command(VimCommand::SearchNext),
+                    'r' => match keys.get(i + 1) {
+                        None => return Parse::Incomplete,
+                        Some(VimKey::Char(c)) => Action::Replace(*c),
+                        Some(_) => return Parse::Invalid,
+                    },
+                    'Z' => match keys.get(i + 1) {
+                        None => return Parse::Incomplete,
+                        Some(VimKey::Char('Z')) => Action::Command(VimCommand::SaveClose),
+                        Some(VimKey::Char('Q')) => Action::Command(VimCommand::ForceClose),
+                        Some(_) => return Parse::Invalid,
+                    },
+                    _ => return Parse::Invalid,
+                }
+            }
+        }
+    };
+
+    Parse::Done(Command { register, count, action })
+}
+
+/// Parses a count like `12`. Returns the count and how many keys it spans.
+fn parse_count(keys: &[VimKey]) -> (Option<usize>, usize) {
+    let mut count = None;
+    let mut n = 0;
+    for key in keys {
+        match *key {
+            // A leading 0 is the "go to line start" motion.
+            VimKey::Char(ch @ '0'..='9') if ch != '0' || count.is_some() => {
+                let digit = ch as usize - '0' as usize;
+                count = Some((count.unwrap_or(0) * 10 + digit).min(MAX_COUNT));
+                n += 1;
+            }
+            _ => break,
+        }
+    }
+    (count, n)
+}
+
+fn parse_motion(keys: &[VimKey]) -> Parse<(Motion, usize)> {
+    let Some(&key) = keys.first() else {
+        return Parse::Incomplete;
+    };
+
+    let motion = match key {
+        VimKey::Left | VimKey::Backspace => Motion::Left,
+        VimKey::Right => Motion::Right,
+        VimKey::Up => Motion::Up,
+        VimKey::Down | VimKey::Enter => Motion::Down,
+        VimKey::Char(ch) => match ch {
+            'h' => Motion::Left,
+            'l' | ' ' => Motion::Right,
+            'k' => Motion::Up,
+            'j' => Motion::Down,
+            'w' | 'W' => Motion::WordForward,
+            'b' | 'B' => Motion::WordBackward,
+            'e' | 'E' => Motion::WordEnd,
+            '0' => Motion::LineStart,
+            '^' => Motion::FirstNonBlank,
+            '$' => Motion::LineEnd,
+            'G' => Motion::FileEnd,
+            'g' => {
+                return match keys.get(1) {
+                    None => Parse::Incomplete,
+                    Some(VimKey::Char('g')) => Parse::Done((Motion::FileStart, 2)),
+                    Some(_) => Parse::Invalid,
+                };
+            }
+            'f' | 'F' | 't' | 'T' => {
+                return match keys.get(1) {
+                    None => Parse::Incomplete,
+                    Some(&VimKey::Char(c)) => Parse::Done((
+                        match ch {
+                            'f' => Motion::FindForward(c),
+                            'F' => Motion::FindBackward(c),
+                            't' => Motion::TillForward(c),
+                            _ => Motion::TillBackward(c),
+                        },
+                        2,
+                    )),
+                    Some(_) => Parse::Invalid,
+                };
+            }
+            _ => return Parse::Invalid,
+        },
+        _ => return Parse::Invalid,
+    };
+
+    Parse::Done((motion, 1))
+}
+
+/// Adjusts the span of a motion for use by an operator.
+fn normalize_span(tb: &mut TextBuffer, span: Span, motion: Motion) -> Span {
+    let (mut beg, mut end) = (span.beg.min(span.end), span.beg.max(span.end));
+
+    if span.linewise {
+        let y0 = pos_of(tb, beg).y;
+        let y1 = pos_of(tb, end).y;
+        return line_span(tb, y0, y1);
+    }
+
+    match motion {
+        Motion::WordEnd | Motion::FindForward(_) | Motion::TillForward(_) => {
+            end = advance(tb, end, 1, tb.text_length());
+        }
+        Motion::WordForward => {
+            // `dw` on the last word of a line doesn't join the next line.
+            let y = pos_of(tb, beg).y;
+            let line_end = offset_of(tb, Point { x: CoordType::MAX, y });
+            if end > line_end && line_end > beg {
+                end = line_end;
+            }
+        }
+        Motion::FileStart | Motion::FileEnd => {
+            let y0 = pos_of(tb, beg).y;
+            let y1 = pos_of(tb, end).y;
+            return line_span(tb, y0, y1);
+        }
+        _ => {}
+    }
+
+    beg = beg.min(end);
+    Span { beg, end, linewise: false }
+}
+
+/// Returns the span that covers the lines `y0` to `y1` including the trailing newline.
+fn line_span(tb: &mut TextBuffer, y0: CoordType, y1: CoordType) -> Span {
+    let last_line = tb.logical_line_count() - 1;
+    let y0 = y0.clamp(0, last_line);
+    let y1 = y1.clamp(y0, last_line);
+    let beg = offset_of(tb, Point { x: 0, y: y0 });
+    let end =
+        if y1 < last_line { offset_of(tb, Point { x: 0, y: y1 + 1 }) } else { tb.text_length() };
+    Span { beg, end, linewise: true }
+}
+
+fn join_lines(tb: &mut TextBuffer, y0: CoordType, y1: CoordType) {
+    let last_line = tb.logical_line_count() - 1;
+    for _ in y0..y1.min(last_line) {
+        let beg = offset_of(tb, Point { x: CoordType::MAX, y: y0 });
+        let end = first_non_blank(tb, y0 + 1);
+        let next_is_empty = end == offset_of(tb, Point { x: CoordType::MAX, y: y0 + 1 });
+        let prev_is_empty = beg == offset_of(tb, Point { x: 0, y: y0 });
+        select(tb, beg, end);
+        tb.write_raw(if next_is_empty || prev_is_empty { b"" } else { b" " });
+    }
+}
+
+/// In normal mode, the cursor sits on a character, not after the last one.
+fn clamp_cursor(tb: &mut TextBuffer) {
+    let pos = tb.cursor_logical_pos();
+    tb.cursor_move_to_logical(Point { x: CoordType::MAX, y: pos.y });
+    let len = tb.cursor_logical_pos().x;
+    tb.cursor_move_to_logical(Point { x: pos.x.min((len - 1).max(0)), y: pos.y });
+}
+
+fn goto_line(tb: &mut TextBuffer, y: CoordType) {
+    let y = y.clamp(0, tb.logical_line_count() - 1);
+    let off = first_non_blank(tb, y);
+    tb.cursor_move_to_offset(off);
+}
+
+fn select(tb: &mut TextBuffer, beg: usize, end: usize) {
+    tb.cursor_move_to_offset(beg);
+    tb.selection_update_offset(end);
+}
+
+fn extract(tb: &mut TextBuffer, beg: usize, end: usize) -> Vec<u8> {
+    if beg >= end {
+        return Vec::new();
+    }
+    let cursor = tb.cursor_offset();
+    select(tb, beg, end);
+    let text = tb.extract_user_selection(false).unwrap_or_default();
+    tb.cursor_move_to_offset(cursor);
+    text
+}
+
+fn pos_of(tb: &mut TextBuffer, offset: usize) -> Point {
+    let cursor = tb.cursor_offset();
+    tb.cursor_move_to_offset(offset);
+    let pos = tb.cursor_logical_pos();
+    tb.cursor_move_to_offset(cursor);
+    pos
+}
+
+fn offset_of(tb: &mut TextBuffer, pos: Point) -> usize {
+    let cursor = tb.cursor_offset();
+    tb.cursor_move_to_logical(pos);
+    let offset = tb.cursor_offset();
+    tb.cursor_move_to_offset(cursor);
+    offset
+}
+
+fn first_non_blank(tb: &mut TextBuffer, y: CoordType) -> usize {
+    let mut off = offset_of(tb, Point { x: 0, y });
+    while matches!(byte_at(tb, off), Some(b' ' | b'\t')) {
+        off += 1;
+    }
+    off
+}
+
+fn byte_at(tb: &TextBuffer, offset: usize) -> Option<u8> {
+    if offset < tb.text_length() { tb.read_forward(offset).first().copied() } else { None }
+}
+
+fn is_continuation(b: Option<u8>) -> bool {
+    matches!(b, Some(0x80..=0xbf))
+}
+
+/// Moves `n` characters forward from `offset`, but not past `limit`.
+fn advance(tb: &TextBuffer, mut offset: usize, n: usize, limit: usize) -> usize {
+    for _ in 0..n {
+        if offset >= limit {
+            break;
+        }
+        offset += 1;
+        while offset < limit && is_continuation(byte_at(tb, offset)) {
+            offset += 1;
+        }
+    }
+    offset.min(limit)
+}
+
+/// Moves `n` characters backward from `offset`, but not before `limit`.
+fn retreat(tb: &TextBuffer, mut offset: usize, n: usize, limit: usize) -> usize {
+    for _ in 0..n {
+        if offset <= limit {
+            break;
+        }
+        offset -= 1;
+        while offset > limit && is_continuation(byte_at(tb, offset)) {
+            offset -= 1;
+        }
+    }
+    offset.max(limit)
+}
+
+fn char_count(tb: &TextBuffer, beg: usize, end: usize) -> usize {
+    (beg..end).filter(|&off| !is_continuation(byte_at(tb, off))).count()
+}
+
+/// 0 for whitespace, 1 for punctuation, 2 for word characters.
+fn char_class(b: Option<u8>) -> u8 {
+    match b {
+        None | Some(b' ' | b'\t' | b'\r' | b'\n') => 0,
+        Some(b) if b.is_ascii_alphanumeric() || b == b'_' || b >= 0x80 => 2,
+        Some(_) => 1,
+    }
+}
+
+fn word_forward(tb: &TextBuffer, mut off: usize) -> usize {
+    let len = tb.text_length();
+    let class = char_class(byte_at(tb, off));
+    if class != 0 {
+        while off < len && char_class(byte_at(tb, off)) == class {
+            off += 1;
+        }
+    }
+    while off < len && char_class(byte_at(tb, off)) == 0 {
+        off += 1;
+    }
+    off
+}
+
+fn word_backward(tb: &TextBuffer, mut off: usize) -> usize {
+    if off == 0 {
+        return 0;
+    }
+    off -= 1;
+    while off > 0 && char_class(byte_at(tb, off)) == 0 {
+        off -= 1;
+    }
+    let class = char_class(byte_at(tb, off));
+    while off > 0 && char_class(byte_at(tb, off - 1)) == class {
+        off -= 1;
+    }
+    off
+}
+
+fn word_end(tb: &TextBuffer, from: usize) -> usize {
+    let len = tb.text_length();
+    let mut off = from + 1;
+    while off < len && char_class(byte_at(tb, off)) == 0 {
+        off += 1;
+    }
+    if off >= len {
+        return from;
+    }
+    let class = char_class(byte_at(tb, off));
+    while off + 1 < len && char_class(byte_at(tb, off + 1)) == class {
+        off += 1;
+    }
+    // Move onto the start of the (possibly multi-byte) character.
+    while off > 0 && is_continuation(byte_at(tb, off)) {
+        off -= 1;
+    }
+    off
+}
+
+/// Searches for `ch` between `from` (exclusive) and `limit`.
+fn find_in_line(
+    tb: &TextBuffer,
+    from: usize,
+    limit: usize,
+    ch: char,
+    forward: bool,
+) -> Option<usize> {
+    let mut buf = [0; 4];
+    let needle = ch.encode_utf8(&mut buf).as_bytes();
+    let matches_at =
+        |off: usize| (0..needle.len()).all(|i| byte_at(tb, off + i) == Some(needle[i]));
+
+    if forward {
+        let mut off = advance(tb, from, 1, limit);
+        while off < limit {
+            if matches_at(off) {
+                return Some(off);
+            }
+            off = advance(tb, off, 1, limit);
+        }
+    } else {
+        let mut off = from;
+        while off > limit {
+            off = retreat(tb, off, 1, limit);
+            if matches_at(off) {
+                return Some(off);
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(text: &str, cursor: usize, input: &str) -> (String, Vim) {
+        stdext::arena::init(16 * edit::helpers::MEBI).unwrap();
+
+        let mut tb = TextBuffer::new(false).unwrap();
+        let mut clipboard = Clipboard::default();
+        tb.write_raw(text.as_bytes());
+        tb.cursor_move_to_offset(cursor);
+
+        let mut vim = Vim::new();
+        vim.set_enabled(true);
+        for ch in input.chars() {
+            let key = match ch {
+                '\x1b' => VimKey::Escape,
+                '\n' => VimKey::Enter,
+                '\x01' => VimKey::Increment,
+                '\x18' => VimKey::Decrement,
+                _ => VimKey::Char(ch),
+            };
+            vim.key(&mut tb, &mut clipboard, key, true);
+        }
+
+        let mut actual = String::new();
+        tb.save_as_string(&mut actual);
+        (actual, vim)
+    }
+
+    #[test]
+    fn test_operators() {
+        assert_eq!(run("foo bar baz", 0, "dw").0, "bar baz");
+        assert_eq!(run("foo bar baz", 0, "2dw").0, "baz");
+        assert_eq!(run("foo bar baz", 0, "d2w").0, "baz");
+        assert_eq!(run("foo bar baz", 4, "d$").0, "foo ");
+        assert_eq!(run("foo bar baz", 0, "cwqux\x1b").0, "qux bar baz");
+        assert_eq!(run("foo(bar)", 0, "dt)").0, ")");
+        assert_eq!(run("foo(bar)", 0, "df(").0, "bar)");
+        assert_eq!(run("a\nb\nc\n", 2, "dd").0, "a\nc\n");
+        assert_eq!(run("a\nb\nc", 4, "dd").0, "a\nb");
+        assert_eq!(run("a\nb\nc\n", 0, "2dd").0, "c\n");
+        assert_eq!(run("a\nb\nc\n", 0, "dj").0, "c\n");
+    }
+
+    #[test]
+    fn test_paste_and_registers() {
+        assert_eq!(run("a\nb\n", 0, "yyp").0, "a\na\nb\n");
+        assert_eq!(run("a\nb\n", 0, "ddp").0, "b\na\n");
+        assert_eq!(run("ab", 0, "xp").0, "ba");
+        assert_eq!(run("a b", 0, "\"qyw$\"qp").0, "a ba ");
+        assert_eq!(run("abc", 0, "3x").0, "");
+    }
+
+    #[test]
+    fn test_insert_and_repeat() {
+        assert_eq!(run("b", 0, "ia\x1b").0, "ab");
+        assert_eq!(run("a", 0, "Ab\x1b").0, "ab");
+        assert_eq!(run("a", 0, "ob\x1b").0, "a\nb");
+        assert_eq!(run("abcd", 0, "x.").0, "cd");
+        assert_eq!(run("a a a", 0, "ix\x1bw.").0, "xa xa a");
+        assert_eq!(run("abcdef", 0, "x3.").0, "ef");
+    }
+
+    #[test]
+    fn test_visual_and_misc() {
+        assert_eq!(run("abcd", 0, "vld").0, "cd");
+        assert_eq!(run("a\nb\nc\n", 0, "Vjd").0, "c\n");
+        assert_eq!(run("a\nb", 0, "J").0, "a b");
+        assert_eq!(run("abc", 0, "rx").0, "xbc");
+        assert_eq!(run("abc", 0, "~~").0, "ABc");
+        assert_eq!(run("x = 9", 0, "\x01").0, "x = 10");
+        assert_eq!(run("x = 9", 0, "10\x18").0, "x = -1");
+        assert_eq!(run("x = 9", 0, "\x01.").0, "x = 11");
+        assert_eq!(run("0\n0\n0", 0, "Vj\x01").0, "1\n1\n0");
+        assert_eq!(run("a\nb\nc", 0, "Gdd").0, "a\nb");
+        assert_eq!(run("a\nb", 0, ":wq\n").1.take_command(), Some(VimCommand::SaveClose));
+        assert_eq!(
+            run("a\nb", 0, ":reload-config\n").1.take_command(),
+            Some(VimCommand::ReloadConfig)
+        );
+        assert_eq!(
+            run("a\nb", 0, ":write-stdout\n").1.take_command(),
+            Some(VimCommand::WriteStdout)
+        );
+    }
+}
@@ -0,0 +1,67 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Keeps Android from dozing off in the middle of a long operation.
+//!
+//! Once the screen turns off, Android may suspend Termux within seconds, which
+//! would leave e.g. a multi-file replace half done. `termux-wake-lock` prevents
+//! that until `termux-wake-unlock` is called. The lock belongs to the whole
+//! Termux session, so this also releases a lock the user acquired by hand.
+
+use std::process::{Command, Stdio};
+use std::sync::LazyLock;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Sender};
+use std::{env, thread};
+
+use crate::clipboard_backend::find_in_path;
+
+/// The number of live [`WakeLock`]s.
+static COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// Runs the commands in order, without blocking the caller.
+/// `None` if we're not running in Termux or the Termux:API package is missing.
+static WORKER: LazyLock<Option<Sender<bool>>> = LazyLock::new(|| {
+    if env::var_os("TERMUX_VERSION").is_none() || !find_in_path("termux-wake-lock") {
+        return None;
+    }
+
+    let (sender, receiver) = mpsc::channel::<bool>();
+    thread::spawn(move || {
+        while let Ok(lock) = receiver.recv() {
+            let program = if lock { "termux-wake-lock" } else { "termux-wake-unlock" };
+            _ = Command::new(program)
+                .stdin(Stdio::null())
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .status();
+        }
+    });
+    Some(sender)
+});
+
+/// Holds the wake lock until dropped. Nested ones share the same lock.
+pub struct WakeLock(());
+
+impl WakeLock {
+    pub fn acquire() -> Self {
+        if COUNT.fetch_add(1, Ordering::Relaxed) == 0 {
+            send(true);
+        }
+        Self(())
+    }
+}
+
+impl Drop for WakeLock {
+    fn drop(&mut self) {
+        if COUNT.fetch_sub(1, Ordering::Relaxed) == 1 {
+            send(false);
+        }
+    }
+}
+
+fn send(lock: bool) {
+    if let Some(sender) = &*WORKER {
+        _ = sender.send(lock);
+    }
+}
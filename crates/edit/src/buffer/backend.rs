@@ -0,0 +1,172 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+use std::ops::Range;
+
+use super::{GapBuffer, PieceTree};
+use crate::apperr;
+use crate::document::{ReadableDocument, WriteableDocument};
+
+/// The storage of a [`super::TextBuffer`]: A [`GapBuffer`], except for large files.
+/// Edits on it move the text between the edit and the last one, which for 100s of MiB
+/// takes too long. The [`PieceTree`] doesn't do that, at the cost of slower reads.
+pub enum Backend {
+    Gap(GapBuffer),
+    Pieces(PieceTree),
+}
+
+impl Backend {
+    pub fn new(small: bool) -> apperr::Result<Self> {
+        Ok(Self::Gap(GapBuffer::new(small)?))
+    }
+
+    /// Switches to the piece tree for text of at least `large` bytes and back for all other.
+    /// The contents are dropped if it switches. Returns whether it did.
+    pub fn select(&mut self, len: usize, large: usize) -> apperr::Result<bool> {
+        let pieces = len >= large;
+        if pieces == matches!(self, Self::Pieces(_)) {
+            return Ok(false);
+        }
+
+        let generation = self.generation();
+        *self = if pieces { Self::Pieces(PieceTree::new()?) } else { Self::new(false)? };
+        self.set_generation(generation);
+        Ok(true)
+    }
+
+    #[allow(clippy::len_without_is_empty)]
+    pub fn len(&self) -> usize {
+        match self {
+            Self::Gap(b) => b.len(),
+            Self::Pieces(b) => b.len(),
+        }
+    }
+
+    pub fn generation(&self) -> u32 {
+        match self {
+            Self::Gap(b) => b.generation(),
+            Self::Pieces(b) => b.generation(),
+        }
+    }
+
+    pub fn set_generation(&mut self, generation: u32) {
+        match self {
+            Self::Gap(b) => b.set_generation(generation),
+            Self::Pieces(b) => b.set_generation(generation),
+        }
+    }
+
+    /// WARNING: The returned slice must not necessarily be the same length as `len` (due to OOM).
+    pub fn allocate_gap(&mut self, off: usize, len: usize, delete: usize) -> &mut [u8] {
+        match self {
+            Self::Gap(b) => b.allocate_gap(off, len, delete),
+            Self::Pieces(b) => b.allocate_gap(off, len, delete),
+        }
+    }
+
+    pub fn commit_gap(&mut self, len: usize) {
+        match self {
+            Self::Gap(b) => b.commit_gap(len),
+            Self::Pieces(b) => b.commit_gap(len),
+        }
+    }
+
+    pub fn replace(&mut self, range: Range<usize>, src: &[u8]) {
+        match self {
+            Self::Gap(b) => b.replace(range, src),
+            Self::Pieces(b) => b.replace(range, src),
+        }
+    }
+
+    pub fn clear(&mut self) {
+        match self {
+            Self::Gap(b) => b.clear(),
+            Self::Pieces(b) => b.clear(),
+        }
+    }
+
+    pub fn extract_raw(&self, range: Range<usize>, out: &mut Vec<u8>, out_off: usize) {
+        match self {
+            Self::Gap(b) => b.extract_raw(range, out, out_off),
+            Self::Pieces(b) => b.extract_raw(range, out, out_off),
+        }
+    }
+
+    /// Replaces the entire buffer contents with the given `text`.
+    /// The method is optimized for the case where the given `text` already matches
+    /// the existing contents. Returns `true` if the buffer contents were changed.
+    pub fn copy_from(&mut self, src: &dyn ReadableDocument) -> bool {
+        let mut off = 0;
+
+        // Find the position at which the contents change.
+        loop {
+            let dst_chunk = self.read_forward(off);
+            let src_chunk = src.read_forward(off);
+
+            let dst_len = dst_chunk.len();
+            let src_len = src_chunk.len();
+            let len = dst_len.min(src_len);
+            let mismatch = dst_chunk[..len] != src_chunk[..len];
+
+            if mismatch {
+                break; // The contents differ.
+            }
+            if len == 0 {
+                if dst_len == src_len {
+                    return false; // Both done simultaneously. -> Done.
+                }
+                break; // One of the two is shorter.
+            }
+
+            off += len;
+        }
+
+        // Update the buffer starting at `off`.
+        loop {
+            let chunk = src.read_forward(off);
+            self.replace(off..usize::MAX, chunk);
+            off += chunk.len();
+
+            // No more data to copy -> Done. By checking this _after_ the replace()
+            // call, we ensure that the initial `off..usize::MAX` range is deleted.
+            // This fixes going from some buffer contents to being empty.
+            if chunk.is_empty() {
+                return true;
+            }
+        }
+    }
+
+    /// Copies the contents of the buffer into a string.
+    pub fn copy_into(&self, dst: &mut dyn WriteableDocument) {
+        let mut beg = 0;
+        let mut off = 0;
+
+        while {
+            let chunk = self.read_forward(off);
+
+            // The first write will be 0..usize::MAX and effectively clear() the destination.
+            // Every subsequent write will be usize::MAX..usize::MAX and thus effectively append().
+            dst.replace(beg..usize::MAX, chunk);
+            beg = usize::MAX;
+
+            off += chunk.len();
+            off < self.len()
+        } {}
+    }
+}
+
+impl ReadableDocument for Backend {
+    fn read_forward(&self, off: usize) -> &[u8] {
+        match self {
+            Self::Gap(b) => b.read_forward(off),
+            Self::Pieces(b) => b.read_forward(off),
+        }
+    }
+
+    fn read_backward(&self, off: usize) -> &[u8] {
+        match self {
+            Self::Gap(b) => b.read_backward(off),
+            Self::Pieces(b) => b.read_backward(off),
+        }
+    }
+}
@@ -0,0 +1,309 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! The encodings of Selection > Encode or Decode…, see [`super::TextBuffer::transcode`].
+
+use std::fmt::Write as _;
+use std::str;
+
+use crate::base64;
+
+/// How [`super::TextBuffer::transcode`] encodes or decodes text.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Codec {
+    /// Standard Base64 with padding. Decoding also takes the URL-safe alphabet,
+    /// missing padding and line breaks.
+    Base64,
+    /// URL percent-encoding of everything but the unreserved characters of RFC 3986.
+    Percent,
+    /// HTML escaping of `&<>"'`. Unescaping also takes numeric and the common named entities.
+    Html,
+    /// A hex dump in the format of `xxd`. Undumping also takes plain hex digits.
+    Hex,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CodecError {
+    /// The text isn't valid in the given [`Codec`].
+    Invalid,
+    /// It decodes to something that isn't UTF-8, which can't be edited as text.
+    NotUtf8,
+}
+
+const HEX_DUMP_WIDTH: usize = 16;
+
+pub fn encode(codec: Codec, input: &[u8]) -> Vec<u8> {
+    match codec {
+        Codec::Base64 => encode_base64(input),
+        Codec::Percent => encode_percent(input),
+        Codec::Html => encode_html(input),
+        Codec::Hex => encode_hex(input),
+    }
+}
+
+pub fn decode(codec: Codec, input: &[u8]) -> Result<Vec<u8>, CodecError> {
+    let output = match codec {
+        Codec::Base64 => decode_base64(input),
+        Codec::Percent => decode_percent(input),
+        Codec::Html => decode_html(input),
+        Codec::Hex => decode_hex(input),
+    }
+    .ok_or(CodecError::Invalid)?;
+    if str::from_utf8(&output).is_err() {
+        return Err(CodecError::NotUtf8);
+    }
+    Ok(output)
+}
+
+fn encode_base64(input: &[u8]) -> Vec<u8> {
+    let mut out = String::new();
+    base64::encode(&mut out, input);
+    out.into_bytes()
+}
+
+/// Unlike [`base64::decode`] this also takes the URL-safe alphabet and line breaks.
+fn decode_base64(input: &[u8]) -> Option<Vec<u8>> {
+    let mut standard = Vec::with_capacity(input.len());
+    for &b in input {
+        match b {
+            b'-' => standard.push(b'+'),
+            b'_' => standard.push(b'/'),
+            b' ' | b'\t' | b'\r' | b'\n' => {}
+            _ => standard.push(b),
+        }
+    }
+
+    // A single character after the last group of 4 can't be a whole byte.
+    let len = standard.iter().position(|&b| b == b'=').unwrap_or(standard.len());
+    if len % 4 == 1 {
+        return None;
+    }
+    base64::decode(&standard)
+}
+
+fn encode_percent(input: &[u8]) -> Vec<u8> {
+    let mut out = String::with_capacity(input.len());
+    for &b in input {
+        if b.is_ascii_alphanumeric() || matches!(b, b'-' | b'.' | b'_' | b'~') {
+            out.push(b as char);
+        } else {
+            _ = write!(out, "%{b:02X}");
+        }
+    }
+    out.into_bytes()
+}
+
+fn decode_percent(input: &[u8]) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(input.len());
+    let mut i = 0;
+    while i < input.len() {
+        if input[i] == b'%' {
+            out.push(hex_byte(input.get(i + 1..i + 3)?)?);
+            i += 3;
+        } else {
+            out.push(input[i]);
+            i += 1;
+        }
+    }
+    Some(out)
+}
+
+fn encode_html(input: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(input.len());
+    for &b in input {
+        match b {
+            b'&' => out.extend_from_slice(b"&amp;"),
+            b'<' => out.extend_from_slice(b"&lt;"),
+            b'>' => out.extend_from_slice(b"&gt;"),
+            b'"' => out.extend_from_slice(b"&quot;"),
+            b'\'' => out.extend_from_slice(b"&#39;"),
+            _ => out.push(b),
+        }
+    }
+    out
+}
+
+/// Named entities other than these are left as they are, as is a `&` that doesn't start
+/// an entity. Numeric ones must be valid codepoints, though.
+fn decode_html(input: &[u8]) -> Option<Vec<u8>> {
+    const NAMED: &[(&[u8], char)] = &[
+        (b"amp", '&'),
+        (b"apos", '\''),
+        (b"copy", '©'),
+        (b"gt", '>'),
+        (b"hellip", '…'),
+        (b"laquo", '«'),
+        (b"ldquo", '“'),
+        (b"lsquo", '‘'),
+        (b"lt", '<'),
+        (b"mdash", '—'),
+        (b"nbsp", '\u{a0}'),
+        (b"ndash", '–'),
+        (b"quot", '"'),
+        (b"raquo", '»'),
+        (b"rdquo", '”'),
+        (b"reg", '®'),
+        (b"rsquo", '’'),
+        (b"trade", '™'),
+    ];
+
+    let mut out = Vec::with_capacity(input.len());
+    let mut i = 0;
+    while i < input.len() {
+        let entity = if input[i] == b'&' {
+            input[i + 1..]
+                .iter()
+                .take(32)
+                .position(|&b| b == b';')
+                .map(|len| &input[i + 1..i + 1 + len])
+        } else {
+            None
+        };
+
+        let Some(entity) = entity else {
+            out.push(input[i]);
+            i += 1;
+            continue;
+        };
+
+        let c = if let Some(num) = entity.strip_prefix(b"#") {
+            let (digits, radix) = match num {
+                [b'x' | b'X', hex @ ..] => (hex, 16),
+                _ => (num, 10),
+            };
+            let digits = str::from_utf8(digits).ok()?;
+            Some(char::from_u32(u32::from_str_radix(digits, radix).ok()?)?)
+        } else {
+            NAMED.iter().find(|&&(name, _)| name == entity).map(|&(_, c)| c)
+        };
+
+        match c {
+            Some(c) => {
+                out.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+                i += entity.len() + 2;
+            }
+            None => {
+                out.push(b'&');
+                i += 1;
+            }
+        }
+    }
+    Some(out)
+}
+
+/// Like `xxd`: `00000000: 4865 6c6c 6f0a  Hello.`
+fn encode_hex(input: &[u8]) -> Vec<u8> {
+    let mut out = String::with_capacity(input.len() * 4 + input.len() / HEX_DUMP_WIDTH * 12);
+    for (i, chunk) in input.chunks(HEX_DUMP_WIDTH).enumerate() {
+        _ = write!(out, "{:08x}:", i * HEX_DUMP_WIDTH);
+        for j in 0..HEX_DUMP_WIDTH {
+            if j.is_multiple_of(2) {
+                out.push(' ');
+            }
+            match chunk.get(j) {
+                Some(b) => _ = write!(out, "{b:02x}"),
+                None => out.push_str("  "),
+            }
+        }
+        out.push_str("  ");
+        for &b in chunk {
+            out.push(if b.is_ascii_graphic() || b == b' ' { b as char } else { '.' });
+        }
+        out.push('\n');
+    }
+    out.into_bytes()
+}
+
+/// Takes the hex digits of each line, leaving out the offset in front of a `:`
+/// and the text column after two spaces, like `xxd -r`.
+fn decode_hex(input: &[u8]) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(input.len() / 3);
+    for line in input.split(|&b| b == b'\n') {
+        let mut line = line.strip_suffix(b"\r").unwrap_or(line);
+        if let Some(colon) = line.iter().position(|&b| b == b':') {
+            line = &line[colon + 1..];
+        }
+        if let Some(end) = line.trim_ascii_start().windows(2).position(|w| w == b"  ") {
+            line = &line.trim_ascii_start()[..end];
+        }
+
+        let digits: Vec<u8> = line.iter().copied().filter(|b| !b.is_ascii_whitespace()).collect();
+        if !digits.len().is_multiple_of(2) {
+            return None;
+        }
+        for pair in digits.chunks(2) {
+            out.push(hex_byte(pair)?);
+        }
+    }
+    Some(out)
+}
+
+fn hex_byte(digits: &[u8]) -> Option<u8> {
+    let digits = str::from_utf8(digits).ok()?;
+    if !digits.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    u8::from_str_radix(digits, 16).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn roundtrip(codec: Codec, text: &str, encoded: &str) {
+        assert_eq!(str::from_utf8(&encode(codec, text.as_bytes())).unwrap(), encoded);
+        assert_eq!(decode(codec, encoded.as_bytes()).unwrap(), text.as_bytes());
+    }
+
+    #[test]
+    fn test_base64() {
+        roundtrip(Codec::Base64, "", "");
+        roundtrip(Codec::Base64, "f", "Zg==");
+        roundtrip(Codec::Base64, "fo", "Zm8=");
+        roundtrip(Codec::Base64, "foo", "Zm9v");
+        roundtrip(Codec::Base64, "héllo?>", "aMOpbGxvPz4=");
+        assert_eq!(decode(Codec::Base64, b"aMOp\nbGxvPz4").unwrap(), "héllo?>".as_bytes());
+        assert_eq!(decode(Codec::Base64, b"/w=="), Err(CodecError::NotUtf8));
+        assert_eq!(decode(Codec::Base64, b"Zm9v!"), Err(CodecError::Invalid));
+        assert_eq!(decode(Codec::Base64, b"Z"), Err(CodecError::Invalid));
+        assert_eq!(decode(Codec::Base64, b"Zg=a"), Err(CodecError::Invalid));
+    }
+
+    #[test]
+    fn test_percent() {
+        roundtrip(Codec::Percent, "a b/c~é", "a%20b%2Fc~%C3%A9");
+        assert_eq!(decode(Codec::Percent, b"a+b%2f").unwrap(), b"a+b/");
+        assert_eq!(decode(Codec::Percent, b"100%"), Err(CodecError::Invalid));
+        assert_eq!(decode(Codec::Percent, b"%zz"), Err(CodecError::Invalid));
+        assert_eq!(decode(Codec::Percent, b"%+1"), Err(CodecError::Invalid));
+        assert_eq!(decode(Codec::Percent, b"%FF"), Err(CodecError::NotUtf8));
+    }
+
+    #[test]
+    fn test_html() {
+        roundtrip(
+            Codec::Html,
+            r#"<a href="x">'&'</a>"#,
+            "&lt;a href=&quot;x&quot;&gt;&#39;&amp;&#39;&lt;/a&gt;",
+        );
+        assert_eq!(decode(Codec::Html, b"&#x263a;&#233;&hellip;").unwrap(), "☺é…".as_bytes());
+        assert_eq!(decode(Codec::Html, b"a & b &foo; c;").unwrap(), b"a & b &foo; c;");
+        assert_eq!(decode(Codec::Html, b"&#xD800;"), Err(CodecError::Invalid));
+        assert_eq!(decode(Codec::Html, b"&#12a;"), Err(CodecError::Invalid));
+    }
+
+    #[test]
+    fn test_hex() {
+        roundtrip(
+            Codec::Hex,
+            "Hello, World!\nBye",
+            "00000000: 4865 6c6c 6f2c 2057 6f72 6c64 210a 4279  Hello, World!.By\n\
+             00000010: 65                                       e\n",
+        );
+        assert_eq!(decode(Codec::Hex, b"48 65\r\n6c6c6f").unwrap(), b"Hello");
+        assert_eq!(decode(Codec::Hex, b"00000000: 4142  AB  CD").unwrap(), b"AB");
+        assert_eq!(decode(Codec::Hex, b"414"), Err(CodecError::Invalid));
+        assert_eq!(decode(Codec::Hex, b"4g"), Err(CodecError::Invalid));
+        assert_eq!(decode(Codec::Hex, b"ff"), Err(CodecError::NotUtf8));
+    }
+}
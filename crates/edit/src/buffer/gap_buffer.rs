@@ -8,7 +8,7 @@ use std::slice;
 use stdext::sys::{virtual_commit, virtual_release, virtual_reserve};
 
 use crate::apperr;
-use crate::document::{ReadableDocument, WriteableDocument};
+use crate::document::ReadableDocument;
 use crate::helpers::*;
 
 #[cfg(target_pointer_width = "32")]
@@ -266,68 +266,6 @@ impl GapBuffer {
             out_off += chunk.len();
         }
     }
-
-    /// Replaces the entire buffer contents with the given `text`.
-    /// The method is optimized for the case where the given `text` already matches
-    /// the existing contents. Returns `true` if the buffer contents were changed.
-    pub fn copy_from(&mut self, src: &dyn ReadableDocument) -> bool {
-        let mut off = 0;
-
-        // Find the position at which the contents change.
-        loop {
-            let dst_chunk = self.read_forward(off);
-            let src_chunk = src.read_forward(off);
-
-            let dst_len = dst_chunk.len();
-            let src_len = src_chunk.len();
-            let len = dst_len.min(src_len);
-            let mismatch = dst_chunk[..len] != src_chunk[..len];
-
-            if mismatch {
-                break; // The contents differ.
-            }
-            if len == 0 {
-                if dst_len == src_len {
-                    return false; // Both done simultaneously. -> Done.
-                }
-                break; // One of the two is shorter.
-            }
-
-            off += len;
-        }
-
-        // Update the buffer starting at `off`.
-        loop {
-            let chunk = src.read_forward(off);
-            self.replace(off..usize::MAX, chunk);
-            off += chunk.len();
-
-            // No more data to copy -> Done. By checking this _after_ the replace()
-            // call, we ensure that the initial `off..usize::MAX` range is deleted.
-            // This fixes going from some buffer contents to being empty.
-            if chunk.is_empty() {
-                return true;
-            }
-        }
-    }
-
-    /// Copies the contents of the buffer into a string.
-    pub fn copy_into(&self, dst: &mut dyn WriteableDocument) {
-        let mut beg = 0;
-        let mut off = 0;
-
-        while {
-            let chunk = self.read_forward(off);
-
-            // The first write will be 0..usize::MAX and effectively clear() the destination.
-            // Every subsequent write will be usize::MAX..usize::MAX and thus effectively append().
-            dst.replace(beg..usize::MAX, chunk);
-            beg = usize::MAX;
-
-            off += chunk.len();
-            off < self.text_length
-        } {}
-    }
 }
 
 impl ReadableDocument for GapBuffer {
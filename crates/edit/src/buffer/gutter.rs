@@ -0,0 +1,80 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! The gutter left of the text: A stack of columns like the line numbers,
+//! or marks that the embedder sets per line, like bookmarks.
+
+use crate::framebuffer::IndexedColor;
+use crate::helpers::CoordType;
+
+/// A column of the gutter, see [`super::TextBuffer::set_gutter`].
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum GutterColumn {
+    /// The line numbers, if [`super::TextBuffer::set_margin_enabled`] enables them.
+    LineNumbers,
+    /// The marks of the given name, see [`super::TextBuffer::set_gutter_marks`].
+    /// It takes up one column, but only while there are any.
+    Marks(&'static str),
+}
+
+/// A glyph in a [`GutterColumn::Marks`] column. It should be one column wide.
+#[derive(Clone, Copy)]
+pub struct GutterMark {
+    /// The logical line, 0-based.
+    pub line: CoordType,
+    pub glyph: char,
+    pub color: IndexedColor,
+}
+
+/// Keeps marks on their lines after `removed` lines were replaced with `added` ones
+/// at `line` and `column`.
+/// The marks on removed lines are dropped. `marks` must be sorted by line.
+pub(super) fn shift_marks(
+    marks: &mut Vec<GutterMark>,
+    line: CoordType,
+    column: CoordType,
+    removed: CoordType,
+    added: CoordType,
+) {
+    // Text inserted at the start of a line pushes that line down, mark and all.
+    let after = if column == 0 && removed == 0 { line - 1 } else { line };
+
+    marks.retain(|mark| mark.line <= after || mark.line > after + removed);
+    for mark in marks.iter_mut().filter(|mark| mark.line > after) {
+        mark.line += added - removed;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn marks(lines: &[CoordType]) -> Vec<GutterMark> {
+        lines
+            .iter()
+            .map(|&line| GutterMark { line, glyph: '●', color: IndexedColor::Blue })
+            .collect()
+    }
+
+    fn lines(marks: &[GutterMark]) -> Vec<CoordType> {
+        marks.iter().map(|mark| mark.line).collect()
+    }
+
+    #[test]
+    fn test_shift_marks() {
+        // Two lines inserted in the middle of line 3.
+        let mut m = marks(&[1, 3, 5]);
+        shift_marks(&mut m, 3, 4, 0, 2);
+        assert_eq!(lines(&m), [1, 3, 7]);
+
+        // ...and at its start, which moves line 3 down as well.
+        let mut m = marks(&[1, 3, 5]);
+        shift_marks(&mut m, 3, 0, 0, 2);
+        assert_eq!(lines(&m), [1, 5, 7]);
+
+        // Lines 2 to 4 joined into line 1.
+        let mut m = marks(&[1, 3, 5]);
+        shift_marks(&mut m, 1, 2, 3, 0);
+        assert_eq!(lines(&m), [1, 2]);
+    }
+}
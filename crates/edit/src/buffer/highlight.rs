@@ -0,0 +1,399 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Syntax highlighting with a simple lexer: Comments, strings, numbers, operators and
+//! the words of a [`Syntax`]. What a line looks like only depends on the text before it
+//! through the [`State`] at its start, for instance whether it's in a block comment.
+//!
+//! [`Highlighter`] keeps those states for each line. They're computed in the background
+//! a few lines at a time, see [`super::TextBuffer::highlight_step`], while the rows on the
+//! screen are lexed when they're drawn, starting at the nearest known state.
+//! Edits only invalidate the states after the edited line, and the lines after that are
+//! taken over again as soon as the state at their start comes out the same as before.
+
+use std::ops::Range;
+
+use crate::framebuffer::NamedColor;
+
+/// What a language looks like to the lexer.
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+pub struct Syntax {
+    /// Empty if there's none.
+    pub line_comment: &'static str,
+    /// Empty if there's none.
+    pub block_comment: (&'static str, &'static str),
+    /// The characters that delimit strings. A `\` escapes the next character.
+    pub quotes: &'static str,
+    /// The ones of [`Syntax::quotes`] whose strings may go on over several lines.
+    pub multiline_quotes: &'static str,
+    /// Whether tripled quotes start a string that ends with the same, as in Python.
+    pub triple_quotes: bool,
+    pub keywords: &'static [&'static str],
+    pub types: &'static [&'static str],
+    pub constants: &'static [&'static str],
+    /// Prose, like a commit message: Only comments that take up a whole line are highlighted.
+    pub prose: bool,
+}
+
+/// What the lexer is in at the start of a line.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub enum State {
+    #[default]
+    Normal,
+    Comment,
+    /// In a string that started with this quote.
+    String(u8),
+    /// In a string that started with this quote three times.
+    Triple(u8),
+}
+
+/// A highlighted range of a line.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Span {
+    pub range: Range<usize>,
+    pub color: NamedColor,
+}
+
+/// Lexes a `line` without its newline, which starts in `state`.
+/// Calls `span` for each highlighted range and returns the state the next line starts in.
+pub fn lex(syntax: &Syntax, mut state: State, line: &[u8], span: &mut dyn FnMut(Span)) -> State {
+    let mut emit = |range: Range<usize>, color| span(Span { range, color });
+    let mut i = 0;
+
+    if syntax.prose {
+        if !syntax.line_comment.is_empty() && line.starts_with(syntax.line_comment.as_bytes()) {
+            emit(0..line.len(), NamedColor::Comment);
+        }
+        return State::Normal;
+    }
+
+    loop {
+        // Continue what the last line (or the last token) left open.
+        match state {
+            State::Normal => {}
+            State::Comment => {
+                let end = syntax.block_comment.1.as_bytes();
+                let (end, closed) = match find(line, i, end) {
+                    Some(at) => (at + end.len(), true),
+                    None => (line.len(), false),
+                };
+                emit(i..end, NamedColor::Comment);
+                if !closed {
+                    return State::Comment;
+                }
+                i = end;
+                state = State::Normal;
+            }
+            State::String(quote) | State::Triple(quote) => {
+                let triple = matches!(state, State::Triple(_));
+                let (end, closed) = string_end(line, i, quote, triple);
+                emit(i..end, NamedColor::String);
+                if !closed {
+                    // Single-line strings end with the line, unless the newline is escaped.
+                    let multiline = triple
+                        || syntax.multiline_quotes.as_bytes().contains(&quote)
+                        || line.ends_with(b"\\");
+                    return if multiline { state } else { State::Normal };
+                }
+                i = end;
+                state = State::Normal;
+            }
+        }
+
+        let Some(&c) = line.get(i) else {
+            return State::Normal;
+        };
+        let rest = &line[i..];
+
+        if !syntax.line_comment.is_empty() && rest.starts_with(syntax.line_comment.as_bytes()) {
+            emit(i..line.len(), NamedColor::Comment);
+            return State::Normal;
+        }
+        if !syntax.block_comment.0.is_empty() && rest.starts_with(syntax.block_comment.0.as_bytes())
+        {
+            let beg = i;
+            i += syntax.block_comment.0.len();
+            let end = syntax.block_comment.1.as_bytes();
+            match find(line, i, end) {
+                Some(at) => {
+                    i = at + end.len();
+                    emit(beg..i, NamedColor::Comment);
+                }
+                None => {
+                    emit(beg..line.len(), NamedColor::Comment);
+                    return State::Comment;
+                }
+            }
+            continue;
+        }
+        if syntax.quotes.as_bytes().contains(&c) {
+            let triple = syntax.triple_quotes && rest.starts_with(&[c, c, c]);
+            let beg = i;
+            i += if triple { 3 } else { 1 };
+            let (end, closed) = string_end(line, i, c, triple);
+            if closed {
+                emit(beg..end, NamedColor::String);
+                i = end;
+            } else {
+                // Let the code at the top decide whether it goes on.
+                emit(beg..i, NamedColor::String);
+                state = if triple { State::Triple(c) } else { State::String(c) };
+            }
+            continue;
+        }
+
+        if c.is_ascii_digit() {
+            let end = i + word_len(rest, |c| c.is_ascii_alphanumeric() || c == b'.' || c == b'_');
+            emit(i..end, NamedColor::Number);
+            i = end;
+        } else if is_word(c) {
+            let end = i + word_len(rest, is_word);
+            let word = &line[i..end];
+            let is = |words: &[&str]| words.iter().any(|w| w.as_bytes() == word);
+            let color = if is(syntax.keywords) {
+                Some(NamedColor::Keyword)
+            } else if is(syntax.types) {
+                Some(NamedColor::Type)
+            } else if is(syntax.constants) {
+                Some(NamedColor::Constant)
+            } else if line[end..].iter().find(|&&c| c != b' ') == Some(&b'(') {
+                Some(NamedColor::Function)
+            } else {
+                None
+            };
+            if let Some(color) = color {
+                emit(i..end, color);
+            }
+            i = end;
+        } else if b"+-*/%=<>!&|^~?:".contains(&c) {
+            let end = i + word_len(rest, |c| b"+-*/%=<>!&|^~?:".contains(&c));
+            emit(i..end, NamedColor::Operator);
+            i = end;
+        } else {
+            i += 1;
+        }
+    }
+}
+
+/// Where the string that started before `beg` ends, after its closing quote.
+/// Returns the line length and `false` if it doesn't end on this line.
+fn string_end(line: &[u8], mut beg: usize, quote: u8, triple: bool) -> (usize, bool) {
+    while beg < line.len() {
+        match line[beg] {
+            b'\\' => beg += 2,
+            c if c == quote => {
+                if !triple {
+                    return (beg + 1, true);
+                }
+                if line[beg..].starts_with(&[quote, quote, quote]) {
+                    return (beg + 3, true);
+                }
+                beg += 1;
+            }
+            _ => beg += 1,
+        }
+    }
+    (line.len(), false)
+}
+
+fn find(line: &[u8], beg: usize, needle: &[u8]) -> Option<usize> {
+    if needle.is_empty() {
+        return None;
+    }
+    line.get(beg..)?.windows(needle.len()).position(|w| w == needle).map(|at| beg + at)
+}
+
+fn is_word(c: u8) -> bool {
+    c.is_ascii_alphanumeric() || c == b'_' || c >= 0x80
+}
+
+fn word_len(text: &[u8], f: impl Fn(u8) -> bool) -> usize {
+    text.iter().position(|&c| !f(c)).unwrap_or(text.len())
+}
+
+/// The state at the start of each line of a document.
+pub struct Highlighter {
+    syntax: Syntax,
+    /// The state at the start of each line. Those from `valid` on are from before the last edits.
+    states: Vec<State>,
+    /// How many of `states` are known to be correct.
+    valid: usize,
+    /// The lines before this one weren't edited since their state was computed,
+    /// so `states` are correct from the first one after it that comes out the same.
+    edited_end: usize,
+}
+
+impl Highlighter {
+    pub fn new(syntax: Syntax) -> Self {
+        Self { syntax, states: vec![State::Normal], valid: 1, edited_end: 0 }
+    }
+
+    pub fn syntax(&self) -> &Syntax {
+        &self.syntax
+    }
+
+    /// The state at the start of line `y`, if it's known.
+    pub fn state(&self, y: usize) -> Option<State> {
+        if y < self.valid { self.states.get(y).copied() } else { None }
+    }
+
+    /// The next line to lex with [`Highlighter::advance`].
+    pub fn next_line(&self) -> usize {
+        self.valid - 1
+    }
+
+    /// Whether the states of the first `lines` lines are known.
+    pub fn is_done(&self, lines: usize) -> bool {
+        self.valid >= lines
+    }
+
+    /// Lexes the [`Highlighter::next_line`], which is `line`.
+    pub fn advance(&mut self, line: &[u8]) {
+        let y = self.valid - 1;
+        let state = lex(&self.syntax, self.states[y], line, &mut |_| {});
+
+        self.valid += 1;
+        match self.states.get_mut(y + 1) {
+            // The same as before the edit and past the edited lines: All the ones after are too.
+            Some(old) if *old == state && y + 1 >= self.edited_end => {
+                self.valid = self.states.len();
+                self.edited_end = 0;
+            }
+            Some(old) => *old = state,
+            None => self.states.push(state),
+        }
+    }
+
+    /// Keeps the states on their lines after the lines `y + 1..=y + removed`
+    /// were replaced with `added` ones. Line `y` was edited as well.
+    pub fn edited(&mut self, y: usize, removed: usize, added: usize) {
+        let beg = (y + 1).min(self.states.len());
+        let end = (y + 1 + removed).min(self.states.len());
+        self.states.splice(beg..end, std::iter::repeat_n(State::Normal, added));
+
+        if self.edited_end > y + removed {
+            self.edited_end = self.edited_end - removed + added;
+        }
+        self.edited_end = self.edited_end.max(y + added + 1);
+        self.valid = self.valid.min(y + 1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const C: Syntax = Syntax {
+        line_comment: "//",
+        block_comment: ("/*", "*/"),
+        quotes: "\"'",
+        multiline_quotes: "",
+        triple_quotes: false,
+        keywords: &["if", "return"],
+        types: &["int"],
+        constants: &["NULL"],
+        prose: false,
+    };
+
+    fn spans(state: State, line: &str) -> (Vec<(&str, NamedColor)>, State) {
+        let mut spans = Vec::new();
+        let state = lex(&C, state, line.as_bytes(), &mut |s| spans.push(s));
+        (spans.into_iter().map(|s| (&line[s.range], s.color)).collect(), state)
+    }
+
+    #[test]
+    fn test_lex() {
+        let (s, state) = spans(State::Normal, r#"if (x) return f(12, "a\"b"); // end"#);
+        assert_eq!(
+            s,
+            [
+                ("if", NamedColor::Keyword),
+                ("return", NamedColor::Keyword),
+                ("f", NamedColor::Function),
+                ("12", NamedColor::Number),
+                (r#""a\"b""#, NamedColor::String),
+                ("// end", NamedColor::Comment),
+            ]
+        );
+        assert_eq!(state, State::Normal);
+
+        let (s, state) = spans(State::Normal, "int x = NULL; /* open");
+        assert_eq!(
+            s,
+            [
+                ("int", NamedColor::Type),
+                ("=", NamedColor::Operator),
+                ("NULL", NamedColor::Constant),
+                ("/* open", NamedColor::Comment),
+            ]
+        );
+        assert_eq!(state, State::Comment);
+
+        let (s, state) = spans(State::Comment, "closed */ 'c'");
+        assert_eq!(s, [("closed */", NamedColor::Comment), ("'c'", NamedColor::String)]);
+        assert_eq!(state, State::Normal);
+
+        // Unterminated strings end with the line, unless it's escaped.
+        assert_eq!(spans(State::Normal, "\"abc").1, State::Normal);
+        assert_eq!(spans(State::Normal, "\"abc\\").1, State::String(b'"'));
+    }
+
+    #[test]
+    fn test_triple_quotes() {
+        let py = Syntax { quotes: "\"'", triple_quotes: true, ..Default::default() };
+        let lex = |state, line: &str| lex(&py, state, line.as_bytes(), &mut |_| {});
+        assert_eq!(lex(State::Normal, "x = \"\"\"doc"), State::Triple(b'"'));
+        assert_eq!(lex(State::Triple(b'"'), "still \" doc"), State::Triple(b'"'));
+        assert_eq!(lex(State::Triple(b'"'), "end\"\"\""), State::Normal);
+    }
+
+    #[test]
+    fn test_prose() {
+        let commit = Syntax { line_comment: "#", prose: true, ..C };
+        let spans = |line: &str| {
+            let mut spans = Vec::new();
+            lex(&commit, State::Normal, line.as_bytes(), &mut |s| spans.push(s));
+            spans
+        };
+        assert_eq!(spans("Fix f(1) for \"x\", see #12"), []);
+        assert_eq!(
+            spans("# Please enter the commit message"),
+            [Span { range: 0..33, color: NamedColor::Comment }]
+        );
+    }
+
+    #[test]
+    fn test_edited() {
+        let lines = ["a", "/* b", "c", "d */", "e"];
+        let mut h = Highlighter::new(C);
+        while !h.is_done(lines.len()) {
+            h.advance(lines[h.next_line()].as_bytes());
+        }
+        assert_eq!(h.state(3), Some(State::Comment));
+        assert_eq!(h.state(4), Some(State::Normal));
+
+        // Editing "c" doesn't change the state after it, so the rest is taken over.
+        h.edited(2, 0, 0);
+        assert_eq!(h.state(3), None);
+        h.advance(b"x");
+        assert!(h.is_done(lines.len()));
+        assert_eq!(h.state(4), Some(State::Normal));
+
+        // Closing the comment early changes the states until the old ones agree again.
+        h.edited(1, 0, 0);
+        h.advance(b"/* b */");
+        assert_eq!(h.state(2), Some(State::Normal));
+        assert!(!h.is_done(lines.len()));
+        h.advance(b"c");
+        h.advance(b"d */");
+        assert!(h.is_done(lines.len()));
+
+        // A line inserted after line 0.
+        h.edited(0, 0, 1);
+        assert_eq!(h.state(1), None);
+        h.advance(b"a");
+        h.advance(b"new");
+        assert!(h.is_done(lines.len() + 1));
+        assert_eq!(h.state(5), Some(State::Normal));
+    }
+}
@@ -0,0 +1,60 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Vim's `J`, see [`super::TextBuffer::join_lines`].
+
+/// Joins the lines in `text` into one and returns it, along with the offset where the
+/// last line was joined on.
+///
+/// The indentation of the joined lines turns into a single space, which is left out
+/// before a `)` or where either side is empty. If the first line starts with the line
+/// comment leader `comment`, it's removed from the joined lines, too.
+pub fn join_lines(text: &[u8], comment: &[u8]) -> (Vec<u8>, usize) {
+    let mut lines =
+        text.split(|&c| c == b'\n').map(|line| line.strip_suffix(b"\r").unwrap_or(line));
+    let mut out = lines.next().unwrap_or_default().to_vec();
+    let is_comment = !comment.is_empty() && out.trim_ascii_start().starts_with(comment);
+    let mut join = out.len();
+
+    for line in lines {
+        let mut rest = line.trim_ascii_start();
+        if is_comment && let Some(r) = rest.strip_prefix(comment) {
+            rest = r.trim_ascii_start();
+        }
+
+        let content = out.trim_ascii_end().len();
+        let indent_only = out.trim_ascii_start().is_empty();
+        if !indent_only {
+            out.truncate(content);
+        }
+        join = out.len();
+        if !indent_only && !rest.is_empty() && !rest.starts_with(b")") {
+            out.push(b' ');
+        }
+        out.extend_from_slice(rest);
+    }
+
+    (out, join)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn join(text: &str, comment: &str) -> (String, usize) {
+        let (out, join) = join_lines(text.as_bytes(), comment.as_bytes());
+        (String::from_utf8(out).unwrap(), join)
+    }
+
+    #[test]
+    fn test_join_lines() {
+        assert_eq!(join("foo(\n    bar\n)", ""), ("foo( bar)".to_string(), 8));
+        assert_eq!(join("a  \r\n\tb", ""), ("a b".to_string(), 1));
+        assert_eq!(join("a\n\nb", ""), ("a b".to_string(), 1));
+        assert_eq!(join("\n  b", ""), ("b".to_string(), 0));
+        assert_eq!(join("    \nb", ""), ("    b".to_string(), 4));
+        assert_eq!(join("  // one\n  // two", "//"), ("  // one two".to_string(), 8));
+        assert_eq!(join("x // one\n  // two", "//"), ("x // one // two".to_string(), 8));
+        assert_eq!(join("a\n  # b", "#"), ("a # b".to_string(), 1));
+    }
+}
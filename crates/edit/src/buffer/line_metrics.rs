@@ -0,0 +1,212 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! With word wrap, finding out where a line's rows start means measuring it. Rendering,
+//! cursor movement and updating the line statistics all need to know, often for the same
+//! lines in the same frame, so [`LineMetrics`] remembers it.
+
+use crate::helpers::*;
+use crate::unicode::Cursor;
+
+/// At most this many lines are cached. That's a few screens full.
+const CAPACITY: usize = 1024;
+
+/// A measured logical line.
+pub struct Line {
+    pub y: CoordType,
+    /// Offset of the line start.
+    pub offset: usize,
+    /// Length in bytes, including the newline.
+    pub len: usize,
+    /// Whether the line ends in a newline, as opposed to the end of the document.
+    pub newline: bool,
+    /// The start of each row, relative to the line start: `.offset` is relative to
+    /// [`Line::offset`] and `.visual_pos.y` is the row. `.logical_pos.y` is unused.
+    pub rows: Vec<Cursor>,
+}
+
+impl Line {
+    pub fn height(&self) -> CoordType {
+        self.rows.len() as CoordType
+    }
+
+    /// Turns row `row` into an absolute cursor, given the `start` of the line.
+    pub fn row(&self, start: Cursor, row: usize) -> Cursor {
+        let r = self.rows[row];
+        Cursor {
+            offset: start.offset + r.offset,
+            logical_pos: Point { x: r.logical_pos.x, y: start.logical_pos.y },
+            visual_pos: Point { x: r.visual_pos.x, y: start.visual_pos.y + r.visual_pos.y },
+            column: r.column,
+            wrap_opp: r.wrap_opp,
+        }
+    }
+
+    /// The row `cursor` is on. At a wrap, there are two positions at the same offset: The end
+    /// of the row before and the start of the next. Which one is told by `.visual_pos.x`.
+    pub fn row_of(&self, cursor: Cursor) -> usize {
+        let off = cursor.offset - self.offset;
+        let row = self.rows.partition_point(|r| r.offset <= off).max(1) - 1;
+        if row > 0 && self.rows[row].offset == off && cursor.visual_pos.x != 0 {
+            row - 1
+        } else {
+            row
+        }
+    }
+
+    /// The start of the line after this one, given the `start` of this one.
+    pub fn next_start(&self, start: Cursor) -> Cursor {
+        Cursor {
+            offset: start.offset + self.len,
+            logical_pos: Point { x: 0, y: start.logical_pos.y + 1 },
+            visual_pos: Point { x: 0, y: start.visual_pos.y + self.height() },
+            column: 0,
+            wrap_opp: false,
+        }
+    }
+}
+
+/// The cache. It's only valid for the buffer generation and layout it was filled with.
+#[derive(Default)]
+pub struct LineMetrics {
+    generation: u32,
+    word_wrap_column: CoordType,
+    tab_size: CoordType,
+    /// Sorted by [`Line::y`].
+    lines: Vec<Line>,
+}
+
+impl LineMetrics {
+    /// Drops everything, unless it's for the given buffer generation and layout.
+    pub fn validate(&mut self, generation: u32, word_wrap_column: CoordType, tab_size: CoordType) {
+        if (self.generation, self.word_wrap_column, self.tab_size)
+            != (generation, word_wrap_column, tab_size)
+        {
+            self.lines.clear();
+            self.generation = generation;
+            self.word_wrap_column = word_wrap_column;
+            self.tab_size = tab_size;
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.lines.clear();
+    }
+
+    pub fn get(&self, y: CoordType) -> Option<&Line> {
+        let i = self.lines.binary_search_by_key(&y, |l| l.y).ok()?;
+        Some(&self.lines[i])
+    }
+
+    /// The total height of the lines `beg..end`, if all of them are cached.
+    pub fn height(&self, beg: CoordType, end: CoordType) -> Option<CoordType> {
+        if beg >= end {
+            return Some(0);
+        }
+        let i = self.lines.binary_search_by_key(&beg, |l| l.y).ok()?;
+        let lines = self.lines.get(i..i + (end - beg) as usize)?;
+        // Since they're sorted and unique, they're contiguous if the last one is `end - 1`.
+        if lines.last()?.y != end - 1 {
+            return None;
+        }
+        Some(lines.iter().map(Line::height).sum())
+    }
+
+    pub fn insert(&mut self, line: Line) -> &Line {
+        if self.lines.len() >= CAPACITY {
+            // Keep the half closer to where we are, assuming that's where we're going.
+            let half = self.lines.len() / 2;
+            if line.y >= self.lines[half].y {
+                self.lines.drain(..half);
+            } else {
+                self.lines.truncate(half);
+            }
+        }
+
+        let i = match self.lines.binary_search_by_key(&line.y, |l| l.y) {
+            Ok(i) => {
+                self.lines[i] = line;
+                i
+            }
+            Err(i) => {
+                self.lines.insert(i, line);
+                i
+            }
+        };
+        &self.lines[i]
+    }
+
+    /// Updates the cache for an edit, which took the buffer from generation `before` to `after`.
+    /// At `off` it replaced `deleted` bytes with `inserted` ones, which changed the line count by
+    /// `lines`. The lines it touched are dropped and the ones after it are moved.
+    pub fn edited(
+        &mut self,
+        before: u32,
+        after: u32,
+        off: usize,
+        deleted: usize,
+        inserted: usize,
+        lines: CoordType,
+    ) {
+        if self.generation != before {
+            self.lines.clear();
+        }
+        self.generation = after;
+
+        // Lines that end right at `off` are dropped too, since without a newline they were extended.
+        self.lines.retain_mut(|line| {
+            if line.offset + line.len < off {
+                true
+            } else if line.offset > off + deleted {
+                line.offset = line.offset - deleted + inserted;
+                line.y += lines;
+                true
+            } else {
+                false
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line(y: CoordType, offset: usize, len: usize) -> Line {
+        let rows = vec![Cursor::default(), Cursor { offset: 3, ..Default::default() }];
+        Line { y, offset, len, newline: true, rows }
+    }
+
+    #[test]
+    fn test_edited() {
+        let mut metrics = LineMetrics::default();
+        metrics.validate(1, 10, 4);
+        metrics.insert(line(0, 0, 10));
+        metrics.insert(line(1, 10, 10));
+        metrics.insert(line(2, 20, 10));
+        assert_eq!(metrics.height(0, 3), Some(6));
+
+        // Insert 2 lines into line 1.
+        metrics.edited(1, 2, 15, 0, 8, 2);
+        assert_eq!(metrics.get(0).map(|l| l.offset), Some(0));
+        assert!(metrics.get(1).is_none());
+        assert_eq!(metrics.get(4).map(|l| l.offset), Some(28));
+        assert_eq!(metrics.height(0, 2), None);
+
+        // Another generation than expected drops everything.
+        metrics.edited(1, 3, 0, 0, 1, 0);
+        assert!(metrics.get(4).is_none());
+    }
+
+    #[test]
+    fn test_row_of() {
+        let l = line(0, 10, 10);
+        let at = |offset, x| Cursor { offset, visual_pos: Point { x, y: 0 }, ..Default::default() };
+        assert_eq!(l.row_of(at(10, 0)), 0);
+        assert_eq!(l.row_of(at(12, 2)), 0);
+        // The wrap is ambiguous.
+        assert_eq!(l.row_of(at(13, 3)), 0);
+        assert_eq!(l.row_of(at(13, 0)), 1);
+        assert_eq!(l.row_of(at(15, 2)), 1);
+    }
+}
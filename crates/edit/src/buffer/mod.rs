@@ -9,10 +9,9 @@
 //!
 //! ---
 //!
-//! If the project ever outgrows a basic gap buffer (e.g. to add time travel)
-//! an ideal, alternative architecture would be a piece table with immutable trees.
-//! The tree nodes can be allocated on the same arena allocator as the added chunks,
-//! making lifetime management fairly easy. The algorithm is described here:
+//! Files of 64 MiB or more are instead stored in a piece table with immutable trees,
+//! because moving the gap across 100s of MiB makes every edit far from the last one stall.
+//! The algorithm is described here:
 //! * <https://cdacamar.github.io/data%20structures/algorithms/benchmarking/text%20editors/c++/editor-data-structures/>
 //! * <https://github.com/cdacamar/fredbuf>
 //!
@@ -20,31 +19,52 @@
 //! The solution to the former is to keep line caches, which further complicates the architecture.
 //! There's no solution for the latter. However, there's a chance that the performance will still be sufficient.
 
+mod backend;
+mod codec;
 mod gap_buffer;
+mod gutter;
+mod highlight;
+mod join;
+mod line_metrics;
 mod navigation;
+mod newlines;
+mod number;
+mod piece_tree;
+mod rewrap;
+mod surround;
 
 use std::borrow::Cow;
 use std::cell::UnsafeCell;
 use std::collections::LinkedList;
 use std::fmt::Write as _;
 use std::fs::File;
-use std::io::{Read as _, Write as _};
+use std::io::{self, Read as _};
 use std::mem::{self, MaybeUninit};
 use std::ops::Range;
 use std::rc::Rc;
 use std::str;
+use std::time::{Duration, Instant};
 
+use backend::Backend;
+pub use codec::{Codec, CodecError};
 pub use gap_buffer::GapBuffer;
+pub use gutter::{GutterColumn, GutterMark};
+use highlight::Highlighter;
+pub use highlight::{Span, State as HighlightState, Syntax, lex as highlight_lex};
+use line_metrics::{Line, LineMetrics};
+pub use piece_tree::PieceTree;
 use stdext::arena::{Arena, ArenaString, scratch_arena};
+pub use surround::delimiters as surround_delimiters;
 
 use crate::cell::SemiRefCell;
 use crate::clipboard::Clipboard;
 use crate::document::{ReadableDocument, WriteableDocument};
-use crate::framebuffer::{Framebuffer, IndexedColor};
+use crate::framebuffer::{Framebuffer, IndexedColor, NamedColor};
 use crate::helpers::*;
 use crate::oklab::StraightRgba;
 use crate::simd::memchr2;
-use crate::unicode::{self, Cursor, MeasurementConfig, Utf8Chars};
+use crate::unicode::bidi::{self, VisualCluster};
+use crate::unicode::{self, Case, Cursor, MeasurementConfig, Utf8Chars};
 use crate::{apperr, icu, simd};
 
 /// The margin template is used for line numbers.
@@ -58,6 +78,13 @@ const VISUAL_SPACE: &str = "･";
 const VISUAL_SPACE_PREFIX_ADD: usize = '･'.len_utf8() - 1;
 const VISUAL_TAB: &str = "￫       ";
 const VISUAL_TAB_PREFIX_ADD: usize = '￫'.len_utf8() - 1;
+const VISUAL_NBSP: &str = "⍽";
+const VISUAL_NEWLINE: &str = "↵";
+/// Files of this size or more are stored in a [`PieceTree`] instead of a [`GapBuffer`].
+const PIECE_TREE_SIZE: usize = 64 * MEBI;
+/// How long rendering may lex lines above the screen, to highlight it right.
+/// The rest is left to [`TextBuffer::highlight_step`].
+const HIGHLIGHT_RENDER_BUDGET: Duration = Duration::from_millis(2);
 
 /// Stores statistics about the whole document.
 #[derive(Copy, Clone)]
@@ -105,6 +132,9 @@ struct HistoryEntry {
     deleted: Vec<u8>,
     /// Text that was added to the buffer.
     added: Vec<u8>,
+    /// Whether the newlines in [`HistoryEntry::deleted`] and [`HistoryEntry::added`] are
+    /// restored as they are, instead of as the buffer's newline type.
+    verbatim: bool,
 }
 
 /// Caches an ICU search operation.
@@ -133,7 +163,7 @@ struct ActiveSearch {
 }
 
 /// Options for a search operation.
-#[derive(Default, Clone, Copy, Eq, PartialEq)]
+#[derive(Default, Clone, Copy, Eq, PartialEq, Debug)]
 pub struct SearchOptions {
     /// If true, the search is case-sensitive.
     pub match_case: bool,
@@ -143,6 +173,72 @@ pub struct SearchOptions {
     pub use_regex: bool,
 }
 
+impl SearchOptions {
+    /// Compiles `pattern` into a matcher that, unlike a [`TextBuffer`], can be used on any thread.
+    /// It finds the same files as [`TextBuffer::find_all()`], but not where in them.
+    pub fn matcher(self, pattern: &str) -> apperr::Result<icu::Matcher> {
+        let (pattern, flags) = self.regex_pattern(pattern)?;
+        icu::Matcher::new(&pattern, flags)
+    }
+
+    /// Turns `pattern` into the pattern and flags for [`icu::Regex`].
+    fn regex_pattern(self, pattern: &str) -> apperr::Result<(Cow<'_, str>, i32)> {
+        if pattern.is_empty() {
+            return Err(apperr::Error::Icu(1)); // U_ILLEGAL_ARGUMENT_ERROR
+        }
+
+        let sanitized_pattern = if self.whole_word && self.use_regex {
+            Cow::Owned(format!(r"\b(?:{pattern})\b"))
+        } else if self.whole_word {
+            let mut p = String::with_capacity(pattern.len() + 16);
+            p.push_str(r"\b");
+
+            // Escape regex special characters.
+            let b = unsafe { p.as_mut_vec() };
+            for &byte in pattern.as_bytes() {
+                match byte {
+                    b'*' | b'?' | b'+' | b'[' | b'(' | b')' | b'{' | b'}' | b'^' | b'$' | b'|'
+                    | b'\\' | b'.' => {
+                        b.push(b'\\');
+                        b.push(byte);
+                    }
+                    _ => b.push(byte),
+                }
+            }
+
+            p.push_str(r"\b");
+            Cow::Owned(p)
+        } else {
+            Cow::Borrowed(pattern)
+        };
+
+        let mut flags = icu::Regex::MULTILINE;
+        if !self.match_case {
+            flags |= icu::Regex::CASE_INSENSITIVE;
+        }
+        if !self.use_regex && !self.whole_word {
+            flags |= icu::Regex::LITERAL;
+        }
+
+        Ok((sanitized_pattern, flags))
+    }
+}
+
+/// A single hit returned by [`TextBuffer::find_all()`].
+#[derive(Clone)]
+pub struct FindMatch {
+    /// The byte range of the match.
+    pub range: Range<usize>,
+    /// The logical line the match starts on.
+    pub line: CoordType,
+    /// The byte offset at which [`FindMatch::line_text`] starts.
+    pub line_offset: usize,
+    /// The contents of the line the match starts on, without the trailing newline.
+    pub line_text: Vec<u8>,
+    /// The text the match would be replaced with.
+    pub replacement: Vec<u8>,
+}
+
 enum RegexReplacement<'a> {
     Group(i32),
     Text(Vec<u8, &'a Arena>),
@@ -185,6 +281,13 @@ pub enum CursorMovement {
     Word,
 }
 
+/// See [`TextBuffer::selection_update_visual_by`].
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum SelectionUnit {
+    Word,
+    Line,
+}
+
 /// See [`TextBuffer::move_selected_lines`].
 pub enum MoveLineDirection {
     Up,
@@ -197,6 +300,17 @@ pub struct RenderResult {
     pub visual_pos_x_max: CoordType,
 }
 
+/// What [`TextBuffer::render()`] keeps about each row to draw the indent guides.
+#[derive(Clone, Copy)]
+struct IndentRow {
+    line: CoordType,
+    /// The indentation in columns. For blank lines, that of the lines around them.
+    columns: CoordType,
+    blank: bool,
+    /// Continuation rows of wrapped lines have text where the guides would go.
+    first_row: bool,
+}
+
 /// A [`TextBuffer`] with inner mutability.
 pub type TextBufferCell = SemiRefCell<TextBuffer>;
 
@@ -208,7 +322,7 @@ pub type RcTextBuffer = Rc<TextBufferCell>;
 
 /// A text buffer for a text editor.
 pub struct TextBuffer {
-    buffer: GapBuffer,
+    buffer: Backend,
 
     undo_stack: LinkedList<SemiRefCell<HistoryEntry>>,
     redo_stack: LinkedList<SemiRefCell<HistoryEntry>>,
@@ -227,25 +341,55 @@ pub struct TextBuffer {
     // To avoid this, we cache the cursor position for rendering.
     // Must be cleared on every edit or reflow.
     cursor_for_rendering: Option<Cursor>,
+    /// Where the rows of word wrapped lines start.
+    line_metrics: SemiRefCell<LineMetrics>,
+    highlighter: Option<Highlighter>,
+    /// The first line that the last [`TextBuffer::render`] highlighted without knowing its state.
+    highlight_guessed: Option<CoordType>,
     selection: Option<TextBufferSelection>,
     selection_generation: u32,
     search: Option<UnsafeCell<ActiveSearch>>,
+    /// The generation and cursor offset after the last cut, so that the next one can add to it.
+    last_cut: Option<(u32, usize)>,
+    /// The generation after the last paste, for [`TextBuffer::paste_rotate`].
+    last_paste: Option<u32>,
+    /// The offsets of closing brackets and quotes that [`TextBuffer::write_typed`] inserted,
+    /// innermost last, and the generation they're valid for.
+    auto_closers: (u32, Vec<usize>),
 
     width: CoordType,
     margin_width: CoordType,
     margin_enabled: bool,
+    gutter: Vec<GutterColumn>,
+    gutter_marks: Vec<(&'static str, Vec<GutterMark>)>,
+    line_number_width: CoordType,
     word_wrap_column: CoordType,
     word_wrap_enabled: bool,
     tab_size: CoordType,
     indent_with_tabs: bool,
     line_highlight_enabled: bool,
+    whitespace_visible: bool,
+    text_direction: bidi::Direction,
+    indent_guides_enabled: bool,
     ruler: CoordType,
+    rulers: Vec<CoordType>,
     encoding: &'static str,
+    /// Whether a non-UTF-8 file has a byte order mark, see [`TextBuffer::has_bom`].
+    bom: bool,
     newlines_are_crlf: bool,
+    /// Whether the file also has newlines of the other kind, see [`TextBuffer::has_mixed_newlines`].
+    mixed_newlines: bool,
     insert_final_newline: bool,
+    auto_close: bool,
     overtype: bool,
+    read_only: bool,
+    /// Whether the rest of the file is still being appended, see [`TextBuffer::read_loaded`].
+    loading: bool,
+    /// The start of the last line while loading and the word wrap column it was measured with.
+    load_tail: Option<(CoordType, Cursor)>,
 
     wants_cursor_visibility: bool,
+    edit_blocked: bool,
 }
 
 impl TextBuffer {
@@ -260,7 +404,7 @@ impl TextBuffer {
     /// if the buffer is optimized for <1MiB contents.
     pub fn new(small: bool) -> apperr::Result<Self> {
         Ok(Self {
-            buffer: GapBuffer::new(small)?,
+            buffer: Backend::new(small)?,
 
             undo_stack: LinkedList::new(),
             redo_stack: LinkedList::new(),
@@ -275,25 +419,45 @@ impl TextBuffer {
             stats: TextBufferStatistics { logical_lines: 1, visual_lines: 1 },
             cursor: Default::default(),
             cursor_for_rendering: None,
+            line_metrics: Default::default(),
+            highlighter: None,
+            highlight_guessed: None,
             selection: None,
             selection_generation: 0,
             search: None,
+            last_cut: None,
+            last_paste: None,
+            auto_closers: (0, Vec::new()),
 
             width: 0,
             margin_width: 0,
             margin_enabled: false,
+            gutter: vec![GutterColumn::LineNumbers],
+            gutter_marks: Vec::new(),
+            line_number_width: 0,
             word_wrap_column: 0,
             word_wrap_enabled: false,
             tab_size: 4,
             indent_with_tabs: false,
             line_highlight_enabled: false,
+            whitespace_visible: false,
+            text_direction: bidi::Direction::Auto,
+            indent_guides_enabled: false,
             ruler: 0,
+            rulers: Vec::new(),
             encoding: "UTF-8",
+            bom: false,
             newlines_are_crlf: cfg!(windows), // Windows users want CRLF
+            mixed_newlines: false,
             insert_final_newline: false,
+            auto_close: false,
             overtype: false,
+            read_only: false,
+            loading: false,
+            load_tail: None,
 
             wants_cursor_visibility: false,
+            edit_blocked: false,
         })
     }
 
@@ -331,7 +495,8 @@ impl TextBuffer {
         self.last_save_generation = self.buffer.generation().wrapping_sub(1);
     }
 
-    fn mark_as_clean(&mut self) {
+    /// Mark the buffer as saved.
+    pub fn mark_as_clean(&mut self) {
         self.last_save_generation = self.buffer.generation();
     }
 
@@ -342,8 +507,26 @@ impl TextBuffer {
 
     /// Set the encoding used during reading/writing.
     pub fn set_encoding(&mut self, encoding: &'static str) {
-        if self.encoding != encoding {
+        if self.encoding != encoding && !self.check_read_only() {
             self.encoding = encoding;
+            // Without it, other programs mistake UTF-16 and UTF-32 for 8-bit encodings.
+            self.bom = encoding.starts_with("UTF-16") || encoding.starts_with("UTF-32");
+            self.mark_as_dirty();
+        }
+    }
+
+    /// Whether the file starts with a byte order mark, which is written back on save.
+    /// For UTF-8 that's the "UTF-8 BOM" encoding.
+    pub fn has_bom(&self) -> bool {
+        if self.encoding.starts_with("UTF-8") { self.encoding == "UTF-8 BOM" } else { self.bom }
+    }
+
+    /// Adds or removes the byte order mark, see [`TextBuffer::has_bom`].
+    pub fn set_bom(&mut self, bom: bool) {
+        if self.encoding.starts_with("UTF-8") {
+            self.set_encoding(if bom { "UTF-8 BOM" } else { "UTF-8" });
+        } else if self.bom != bom && !self.check_read_only() {
+            self.bom = bom;
             self.mark_as_dirty();
         }
     }
@@ -358,10 +541,84 @@ impl TextBuffer {
         self.newlines_are_crlf = crlf;
     }
 
+    /// Whether the file mixes LF and CRLF. It's checked when loading the file
+    /// and stays set until the newlines are normalized.
+    pub fn has_mixed_newlines(&self) -> bool {
+        self.mixed_newlines
+    }
+
+    /// The newline type most lines of the document end in.
+    pub fn dominant_newline_is_crlf(&self) -> bool {
+        let (mut lf, mut crlf) = (0usize, 0usize);
+        self.for_each_newline(|_, cr| if cr { crlf += 1 } else { lf += 1 });
+        if lf == crlf { self.newlines_are_crlf } else { crlf > lf }
+    }
+
+    /// Changes all newlines that aren't of the given type, unlike
+    /// [`TextBuffer::normalize_newlines`] as a single undo step.
+    pub fn repair_newlines(&mut self, crlf: bool) {
+        if self.check_read_only() {
+            return;
+        }
+
+        let mut stray = Vec::new();
+        self.for_each_newline(|off, cr| {
+            if cr != crlf {
+                stray.push((off - cr as usize..off + 1, &b"\n"[..]));
+            }
+        });
+
+        // Written newlines are of the buffer's type, so "\n" turns into the right one.
+        self.newlines_are_crlf = crlf;
+        self.mixed_newlines = false;
+
+        let pos = self.cursor.logical_pos;
+        let generation = self.buffer.generation();
+        self.replace_ranges(&stray);
+
+        // Undo brings the stray newlines back as they were.
+        for entry in self.undo_stack.iter().rev() {
+            let mut entry = entry.borrow_mut();
+            if entry.generation_before != generation {
+                break;
+            }
+            entry.verbatim = true;
+        }
+        self.set_selection(None);
+        self.cursor_move_to_logical(pos);
+    }
+
+    /// Calls `f` with the offset of each LF and whether a CR precedes it.
+    fn for_each_newline(&self, mut f: impl FnMut(usize, bool)) {
+        let mut off = 0;
+        let mut prev = 0;
+        loop {
+            let chunk = self.read_forward(off);
+            if chunk.is_empty() {
+                break;
+            }
+            let mut i = 0;
+            loop {
+                i = memchr2(b'\n', b'\n', chunk, i);
+                if i >= chunk.len() {
+                    break;
+                }
+                f(off + i, if i > 0 { chunk[i - 1] } else { prev } == b'\r');
+                i += 1;
+            }
+            prev = chunk[chunk.len() - 1];
+            off += chunk.len();
+        }
+    }
+
     /// Changes the newline type used in the document.
     ///
     /// NOTE: Cannot be undone.
     pub fn normalize_newlines(&mut self, crlf: bool) {
+        if self.check_read_only() {
+            return;
+        }
+
         let newline: &[u8] = if crlf { b"\r\n" } else { b"\n" };
         let mut off = 0;
 
@@ -423,8 +680,10 @@ impl TextBuffer {
         if let Some(cursor) = &mut self.cursor_for_rendering {
             cursor.offset = cursor_for_rendering_offset;
         }
+        self.line_metrics.borrow_mut().clear();
 
         self.newlines_are_crlf = crlf;
+        self.mixed_newlines = false;
     }
 
     /// If enabled, automatically insert a final newline
@@ -443,12 +702,41 @@ impl TextBuffer {
         self.overtype = overtype;
     }
 
+    /// Whether the buffer rejects all modifications.
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    /// Set the read-only mode. Searching, selecting and copying still work.
+    pub fn set_read_only(&mut self, read_only: bool) {
+        self.read_only = read_only;
+    }
+
+    /// Returns `true` if an edit was rejected due to the read-only mode
+    /// since the last call to this function.
+    pub fn take_edit_blocked(&mut self) -> bool {
+        mem::take(&mut self.edit_blocked)
+    }
+
+    /// Returns `true` and records the attempt if the buffer is read-only,
+    /// which it also is while the file is still loading.
+    fn check_read_only(&mut self) -> bool {
+        let blocked = self.read_only || self.loading;
+        self.edit_blocked |= blocked;
+        blocked
+    }
+
     /// Gets the logical cursor position, that is,
     /// the position in lines and graphemes per line.
     pub fn cursor_logical_pos(&self) -> Point {
         self.cursor.logical_pos
     }
 
+    /// Gets the cursor position as a byte offset into the buffer.
+    pub fn cursor_offset(&self) -> usize {
+        self.cursor.offset
+    }
+
     /// Gets the visual cursor position, that is,
     /// the position in laid out rows and columns.
     pub fn cursor_visual_pos(&self) -> Point {
@@ -460,7 +748,7 @@ impl TextBuffer {
         self.margin_width
     }
 
-    /// Is the left margin enabled?
+    /// Are the line numbers in the left margin enabled?
     pub fn set_margin_enabled(&mut self, enabled: bool) -> bool {
         if self.margin_enabled == enabled {
             false
@@ -471,6 +759,153 @@ impl TextBuffer {
         }
     }
 
+    /// Sets the columns of the left margin, from left to right.
+    pub fn set_gutter(&mut self, columns: Vec<GutterColumn>) {
+        if self.gutter != columns {
+            self.gutter = columns;
+            self.reflow();
+        }
+    }
+
+    /// Gets the marks of the given name, sorted by line.
+    pub fn gutter_marks(&self, name: &str) -> &[GutterMark] {
+        self.gutter_marks.iter().find(|(n, _)| *n == name).map_or(&[], |(_, marks)| marks)
+    }
+
+    /// Sets the marks of the given name. They're shown if a [`GutterColumn::Marks`]
+    /// column of that name is in the gutter, and they move along with their lines on edits.
+    pub fn set_gutter_marks(&mut self, name: &'static str, mut marks: Vec<GutterMark>) {
+        marks.sort_by_key(|mark| mark.line);
+        let was_empty = self.gutter_marks(name).is_empty();
+        let is_empty = marks.is_empty();
+
+        match self.gutter_marks.iter_mut().find(|(n, _)| *n == name) {
+            Some((_, m)) => *m = marks,
+            None => self.gutter_marks.push((name, marks)),
+        }
+
+        // The column takes up space only while there are marks.
+        if was_empty != is_empty && self.gutter.contains(&GutterColumn::Marks(name)) {
+            self.reflow();
+        }
+    }
+
+    /// Keeps what belongs to lines with them, after `removed` lines were replaced with `added`
+    /// ones at `pos`: The gutter marks, see [`gutter::shift_marks`], and the highlighting.
+    fn lines_edited(&mut self, pos: Point, removed: CoordType, added: CoordType) {
+        if let Some(highlighter) = &mut self.highlighter {
+            highlighter.edited(pos.y as usize, removed as usize, added as usize);
+        }
+        if removed == 0 && added == 0 {
+            return;
+        }
+        for (_, marks) in &mut self.gutter_marks {
+            gutter::shift_marks(marks, pos.y, pos.x, removed, added);
+        }
+    }
+
+    fn gutter_marks_shown(&self, name: &str) -> bool {
+        !self.gutter_marks(name).is_empty()
+    }
+
+    /// Sets the language to highlight, or turns highlighting off.
+    pub fn set_syntax(&mut self, syntax: Option<Syntax>) {
+        if self.highlighter.as_ref().map(|h| h.syntax()) != syntax.as_ref() {
+            self.highlighter = syntax.map(Highlighter::new);
+            self.highlight_guessed = None;
+        }
+    }
+
+    pub fn syntax(&self) -> Option<&Syntax> {
+        self.highlighter.as_ref().map(|h| h.syntax())
+    }
+
+    /// Whether [`TextBuffer::highlight_step`] has lines left.
+    pub fn is_highlighting(&self) -> bool {
+        self.highlighter.as_ref().is_some_and(|h| !h.is_done(self.stats.logical_lines as usize))
+    }
+
+    /// Lexes lines in the background until `deadline`, so that those further down the document
+    /// are highlighted correctly, even if they're in a block comment that started way above.
+    /// Returns whether the lines that were rendered last look different now.
+    pub fn highlight_step(&mut self, deadline: Instant) -> bool {
+        self.highlight_advance(self.stats.logical_lines, deadline);
+        match self.highlight_guessed {
+            Some(y) if self.highlighter.as_ref().is_some_and(|h| h.is_done(y as usize + 1)) => {
+                self.highlight_guessed = None;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Lexes lines until the state of the first `lines` is known or it's `deadline`.
+    fn highlight_advance(&mut self, lines: CoordType, deadline: Instant) {
+        let Some(mut highlighter) = self.highlighter.take() else {
+            return;
+        };
+        let lines = lines.min(self.stats.logical_lines) as usize;
+        let scratch = scratch_arena(None);
+        let mut line = Vec::new_in(&*scratch);
+        let mut off =
+            self.seek_line_start(self.cursor, highlighter.next_line() as CoordType).offset;
+
+        for i in 0.. {
+            if highlighter.is_done(lines) {
+                break;
+            }
+            // Checking the time takes longer than lexing a short line.
+            if i % 64 == 63 && Instant::now() >= deadline {
+                break;
+            }
+            off = self.read_line(off, &mut line);
+            highlighter.advance(&line);
+        }
+
+        self.highlighter = Some(highlighter);
+    }
+
+    /// Lexes the whole document from the top, for exporting it with its highlighting.
+    /// Calls `line` for each line with its text, without the newline, and its spans.
+    pub fn highlight_lines(&self, line: &mut dyn FnMut(&[u8], &[Span])) {
+        let scratch = scratch_arena(None);
+        let mut text = Vec::new_in(&*scratch);
+        let mut spans = Vec::new();
+        let mut state = HighlightState::Normal;
+        let mut off = 0;
+
+        for _ in 0..self.stats.logical_lines {
+            off = self.read_line(off, &mut text);
+            spans.clear();
+            if let Some(syntax) = self.syntax() {
+                state = highlight::lex(syntax, state, &text, &mut |span| spans.push(span));
+            }
+            line(&text, &spans);
+        }
+    }
+
+    /// Copies the line at `off` into `line` without its newline, or the first [`MEBI`] bytes of it.
+    /// Returns the offset of the next line.
+    fn read_line(&self, mut off: usize, line: &mut Vec<u8, &Arena>) -> usize {
+        line.clear();
+        loop {
+            let chunk = self.read_forward(off);
+            if chunk.is_empty() {
+                return off;
+            }
+            let (delta, y) = simd::lines_fwd(chunk, 0, 0, 1);
+            let take = delta.min(MEBI.saturating_sub(line.len()));
+            line.extend_from_slice(&chunk[..take]);
+            off += delta;
+            if y == 1 {
+                while let Some(b'\n' | b'\r') = line.last() {
+                    line.pop();
+                }
+                return off;
+            }
+        }
+    }
+
     /// Gets the width of the text contents for layout.
     pub fn text_width(&self) -> CoordType {
         self.width - self.margin_width
@@ -579,11 +1014,60 @@ impl TextBuffer {
         self.line_highlight_enabled = enabled;
     }
 
+    /// Returns whether whitespace is shown outside of selections, too.
+    pub fn is_whitespace_visible(&self) -> bool {
+        self.whitespace_visible
+    }
+
+    /// Sets whether tabs, trailing spaces, non-breaking spaces and line ends are shown
+    /// everywhere. Within selections all whitespace is always shown.
+    pub fn set_whitespace_visible(&mut self, visible: bool) {
+        self.whitespace_visible = visible;
+    }
+
+    /// Returns the direction of paragraphs, see [`TextBuffer::set_text_direction`].
+    pub fn text_direction(&self) -> bidi::Direction {
+        self.text_direction
+    }
+
+    /// Sets the direction of paragraphs, i.e. lines. With [`bidi::Direction::Auto`],
+    /// each line goes the way of its first letter.
+    pub fn set_text_direction(&mut self, direction: bidi::Direction) {
+        self.text_direction = direction;
+    }
+
+    /// Returns whether indent guides are drawn.
+    pub fn is_indent_guides_enabled(&self) -> bool {
+        self.indent_guides_enabled
+    }
+
+    /// Sets whether faint vertical lines are drawn at each level of indentation.
+    pub fn set_indent_guides_enabled(&mut self, enabled: bool) {
+        self.indent_guides_enabled = enabled;
+    }
+
+    /// Returns whether typing an opening bracket or quote also inserts the closing one.
+    pub fn is_auto_close_enabled(&self) -> bool {
+        self.auto_close
+    }
+
+    /// Sets whether typing an opening bracket or quote also inserts the closing one,
+    /// see [`TextBuffer::write_typed`].
+    pub fn set_auto_close_enabled(&mut self, enabled: bool) {
+        self.auto_close = enabled;
+    }
+
     /// Sets a ruler column, e.g. 80.
     pub fn set_ruler(&mut self, column: CoordType) {
         self.ruler = column;
     }
 
+    /// Sets the columns that get a thin vertical line, e.g. 80 and 120.
+    /// Unlike [`TextBuffer::set_ruler`] only the column itself is tinted.
+    pub fn set_rulers(&mut self, columns: Vec<CoordType>) {
+        self.rulers = columns;
+    }
+
     pub fn reflow(&mut self) {
         self.reflow_internal(true);
     }
@@ -596,14 +1080,25 @@ impl TextBuffer {
         let word_wrap_column_before = self.word_wrap_column;
 
         {
-            // +1 onto logical_lines, because line numbers are 1-based.
             // +1 onto log10, because we want the digit width and not the actual log10.
-            // +3 onto log10, because we append " | " to the line numbers to form the margin.
-            self.margin_width = if self.margin_enabled {
-                self.stats.logical_lines.ilog10() as CoordType + 4
-            } else {
-                0
-            };
+            self.line_number_width =
+                if self.margin_enabled && self.gutter.contains(&GutterColumn::LineNumbers) {
+                    self.stats.logical_lines.ilog10() as CoordType + 1
+                } else {
+                    0
+                };
+
+            // +1 for the space after the line numbers. Marks are 1 column each.
+            // +2 if there's anything, because we append "| " to form the margin.
+            let mut width = if self.line_number_width > 0 { self.line_number_width + 1 } else { 0 };
+            for column in &self.gutter {
+                if let &GutterColumn::Marks(name) = column
+                    && self.gutter_marks_shown(name)
+                {
+                    width += 1;
+                }
+            }
+            self.margin_width = if width > 0 { width + 2 } else { 0 };
 
             let text_width = self.text_width();
             // 2 columns are required, because otherwise wide glyphs wouldn't ever fit.
@@ -614,6 +1109,8 @@ impl TextBuffer {
         self.cursor_for_rendering = None;
 
         if force || self.word_wrap_column != word_wrap_column_before {
+            self.line_metrics.borrow_mut().clear();
+
             // Recalculate the cursor position.
             self.cursor = self.cursor_move_to_logical_internal(
                 if self.word_wrap_column > 0 {
@@ -644,6 +1141,7 @@ impl TextBuffer {
             let delete = self.buffer.len() - self.cursor.offset;
             if delete != 0 {
                 self.buffer.allocate_gap(self.cursor.offset, 0, delete);
+                self.line_metrics.borrow_mut().clear();
             }
         }
     }
@@ -656,6 +1154,9 @@ impl TextBuffer {
         self.cursor = Default::default();
         self.set_selection(None);
         self.mark_as_clean();
+        if let Some(highlighter) = &mut self.highlighter {
+            *highlighter = Highlighter::new(*highlighter.syntax());
+        }
         self.reflow();
     }
 
@@ -692,9 +1193,14 @@ impl TextBuffer {
             self.encoding = bom.unwrap_or("UTF-8");
         }
 
+        let len = file.metadata().map_or(0, |m| m.len() as usize);
+        self.buffer.select(len, PIECE_TREE_SIZE)?;
+
         // TODO: Since reading the file can fail, we should ensure that we also reset the cursor here.
         // I don't do it, so that `recalc_after_content_swap()` works.
         self.buffer.clear();
+        self.loading = false;
+        self.bom = false;
 
         let done = read == 0;
         if self.encoding == "UTF-8" {
@@ -703,114 +1209,210 @@ impl TextBuffer {
             self.read_file_with_icu(file, &mut buf, first_chunk_len, done)?;
         }
 
-        // Figure out
-        // * the logical line count
-        // * the newline type (LF or CRLF)
-        // * the indentation type (tabs or spaces)
-        // * whether there's a final newline
-        {
-            let chunk = self.read_forward(0);
-            let mut offset = 0;
-            let mut lines = 0;
-            // Number of lines ending in CRLF.
-            let mut crlf_count = 0;
-            // Number of lines starting with a tab.
-            let mut tab_indentations = 0;
-            // Number of lines starting with a space.
-            let mut space_indentations = 0;
-            // Histogram of the indentation depth of lines starting with between 2 and 8 spaces.
-            // In other words, `space_indentation_sizes[0]` is the number of lines starting with 2 spaces.
-            let mut space_indentation_sizes = [0; 7];
+        self.detect_text_format();
 
-            loop {
-                // Check if the line starts with a tab.
-                if offset < chunk.len() && chunk[offset] == b'\t' {
-                    tab_indentations += 1;
-                } else {
-                    // Otherwise, check how many spaces the line starts with. Searching for >8 spaces
-                    // allows us to reject lines that have more than 1 level of indentation.
-                    let space_indentation =
-                        chunk[offset..].iter().take(9).take_while(|&&c| c == b' ').count();
-
-                    // We'll also reject lines starting with 1 space, because that's too fickle as a heuristic.
-                    if (2..=8).contains(&space_indentation) {
-                        space_indentations += 1;
-
-                        // If we encounter an indentation depth of 6, it may either be a 6-space indentation,
-                        // two 3-space indentation or 3 2-space indentations. To make this work, we increment
-                        // all 3 possible histogram slots.
-                        //   2 -> 2
-                        //   3 -> 3
-                        //   4 -> 4 2
-                        //   5 -> 5
-                        //   6 -> 6 3 2
-                        //   7 -> 7
-                        //   8 -> 8 4 2
-                        space_indentation_sizes[space_indentation - 2] += 1;
-                        if space_indentation & 4 != 0 {
-                            space_indentation_sizes[0] += 1;
-                        }
-                        if space_indentation == 6 || space_indentation == 8 {
-                            space_indentation_sizes[space_indentation / 2 - 2] += 1;
-                        }
-                    }
-                }
+        self.recalc_after_content_swap();
+        Ok(())
+    }
 
-                (offset, lines) = simd::lines_fwd(chunk, offset, lines, lines + 1);
+    /// Replaces the contents with the beginning of a UTF-8 file. The rest is added with
+    /// [`TextBuffer::append_loaded`] as it arrives, and until [`TextBuffer::finish_loading`]
+    /// the buffer can't be edited. Like [`TextBuffer::read_file`] it detects the newline
+    /// type and the indentation, but only from `text`. `len` is the size of the whole file.
+    ///
+    /// Returns `false` without changing anything, if `text` starts with another encoding's BOM.
+    pub fn read_loaded(&mut self, text: &[u8], len: usize) -> apperr::Result<bool> {
+        let text = match detect_bom(text) {
+            None => {
+                self.encoding = "UTF-8";
+                text
+            }
+            Some("UTF-8") => {
+                self.encoding = "UTF-8 BOM";
+                &text[3..]
+            }
+            Some(_) => return Ok(false),
+        };
+        self.bom = false;
 
-                // Check if the preceding line ended in CRLF.
-                if offset >= 2 && &chunk[offset - 2..offset] == b"\r\n" {
-                    crlf_count += 1;
-                }
+        self.buffer.select(len, PIECE_TREE_SIZE)?;
+        self.buffer.clear();
+        self.buffer.replace(0..0, text);
+        self.detect_text_format();
+        self.recalc_after_content_swap();
+        self.loading = true;
+        self.load_tail = None;
+        Ok(true)
+    }
 
-                // We'll limit our heuristics to the first 1000 lines.
-                // That should hopefully be enough in practice.
-                if offset >= chunk.len() || lines >= 1000 {
-                    break;
-                }
-            }
+    /// Appends the next part of the file that [`TextBuffer::read_loaded`] began.
+    /// It's not an edit: It can't be undone and the buffer stays clean.
+    pub fn append_loaded(&mut self, text: &[u8]) {
+        if text.is_empty() {
+            return;
+        }
+
+        let clean = !self.is_dirty();
+        let off = self.text_length();
+        let prev = self.read_backward(off).last().copied().unwrap_or(0);
+        self.mixed_newlines |= newlines::has_stray_newline(text, prev, self.newlines_are_crlf);
+        let generation = self.buffer.generation();
+        self.buffer.replace(off..off, text);
+        let lines = simd::lines_fwd(text, 0, 0, CoordType::MAX).1;
+        self.line_metrics.borrow_mut().edited(
+            generation,
+            self.buffer.generation(),
+            off,
+            0,
+            text.len(),
+            lines,
+        );
+        if let Some(highlighter) = &mut self.highlighter {
+            highlighter.edited(self.stats.logical_lines as usize - 1, 0, lines as usize);
+        }
+        self.stats.logical_lines += lines;
+        self.insert_final_newline = text.ends_with(b"\n");
+
+        if self.word_wrap_column > 0 {
+            // Only the rows from the last line on need to be counted,
+            // as long as the wrap column is the same as last time.
+            let tail = match self.load_tail {
+                Some((column, tail)) if column == self.word_wrap_column => tail,
+                _ => self.cursor,
+            };
+            let last_line = Point { x: 0, y: self.stats.logical_lines - 1 };
+            let tail = self.cursor_move_to_logical_internal(tail, last_line);
+            let end = self.cursor_move_to_logical_internal(tail, Point::MAX);
+            self.stats.visual_lines = end.visual_pos.y + 1;
+            self.load_tail = Some((self.word_wrap_column, tail));
+        } else {
+            self.stats.visual_lines = self.stats.logical_lines;
+        }
+
+        if clean {
+            self.mark_as_clean();
+        }
+        self.recalc_after_content_changed();
+    }
+
+    /// Ends what [`TextBuffer::read_loaded`] began, once the whole file is there.
+    pub fn finish_loading(&mut self) {
+        self.loading = false;
+        self.load_tail = None;
+    }
+
+    /// Whether the file is still being appended, see [`TextBuffer::read_loaded`].
+    pub fn is_loading(&self) -> bool {
+        self.loading
+    }
 
-            // We'll assume CRLF if more than half of the lines end in CRLF.
-            let newlines_are_crlf = crlf_count >= lines / 2;
+    /// Figures out from the contents
+    /// * the logical line count
+    /// * the newline type (LF or CRLF)
+    /// * the indentation type (tabs or spaces)
+    /// * whether there's a final newline
+    fn detect_text_format(&mut self) {
+        let chunk = self.read_forward(0);
+        let mut offset = 0;
+        let mut lines = 0;
+        // Number of lines ending in CRLF.
+        let mut crlf_count = 0;
+        // Number of lines starting with a tab.
+        let mut tab_indentations = 0;
+        // Number of lines starting with a space.
+        let mut space_indentations = 0;
+        // Histogram of the indentation depth of lines starting with between 2 and 8 spaces.
+        // In other words, `space_indentation_sizes[0]` is the number of lines starting with 2 spaces.
+        let mut space_indentation_sizes = [0; 7];
 
-            // We'll assume tabs if there are more lines starting with tabs than with spaces.
-            let indent_with_tabs = tab_indentations > space_indentations;
-            let tab_size = if indent_with_tabs {
-                // Tabs will get a visual size of 4 spaces by default.
-                4
+        loop {
+            // Check if the line starts with a tab.
+            if offset < chunk.len() && chunk[offset] == b'\t' {
+                tab_indentations += 1;
             } else {
-                // Otherwise, we'll assume the most common indentation depth.
-                // If there are conflicting indentation depths, we'll prefer the maximum, because in the loop
-                // above we incremented the histogram slot for 2-spaces when encountering 4-spaces and so on.
-                let mut max = 1;
-                let mut tab_size = 4;
-                for (i, &count) in space_indentation_sizes.iter().enumerate() {
-                    if count >= max {
-                        max = count;
-                        tab_size = i as CoordType + 2;
+                // Otherwise, check how many spaces the line starts with. Searching for >8 spaces
+                // allows us to reject lines that have more than 1 level of indentation.
+                let space_indentation =
+                    chunk[offset..].iter().take(9).take_while(|&&c| c == b' ').count();
+
+                // We'll also reject lines starting with 1 space, because that's too fickle as a heuristic.
+                if (2..=8).contains(&space_indentation) {
+                    space_indentations += 1;
+
+                    // If we encounter an indentation depth of 6, it may either be a 6-space indentation,
+                    // two 3-space indentation or 3 2-space indentations. To make this work, we increment
+                    // all 3 possible histogram slots.
+                    //   2 -> 2
+                    //   3 -> 3
+                    //   4 -> 4 2
+                    //   5 -> 5
+                    //   6 -> 6 3 2
+                    //   7 -> 7
+                    //   8 -> 8 4 2
+                    space_indentation_sizes[space_indentation - 2] += 1;
+                    if space_indentation & 4 != 0 {
+                        space_indentation_sizes[0] += 1;
+                    }
+                    if space_indentation == 6 || space_indentation == 8 {
+                        space_indentation_sizes[space_indentation / 2 - 2] += 1;
                     }
                 }
-                tab_size
-            };
+            }
 
-            // If the file has more than 1000 lines, figure out how many are remaining.
-            if offset < chunk.len() {
-                (_, lines) = simd::lines_fwd(chunk, offset, lines, CoordType::MAX);
+            (offset, lines) = simd::lines_fwd(chunk, offset, lines, lines + 1);
+
+            // Check if the preceding line ended in CRLF.
+            if offset >= 2 && &chunk[offset - 2..offset] == b"\r\n" {
+                crlf_count += 1;
+            }
+
+            // We'll limit our heuristics to the first 1000 lines.
+            // That should hopefully be enough in practice.
+            if offset >= chunk.len() || lines >= 1000 {
+                break;
             }
+        }
 
-            let final_newline = chunk.ends_with(b"\n");
+        // We'll assume CRLF if more than half of the lines end in CRLF.
+        let newlines_are_crlf = crlf_count >= lines / 2;
 
-            // Add 1, because the last line doesn't end in a newline (it ends in the literal end).
-            self.stats.logical_lines = lines + 1;
-            self.stats.visual_lines = self.stats.logical_lines;
-            self.newlines_are_crlf = newlines_are_crlf;
-            self.insert_final_newline = final_newline;
-            self.indent_with_tabs = indent_with_tabs;
-            self.tab_size = tab_size;
+        // We'll assume tabs if there are more lines starting with tabs than with spaces.
+        let indent_with_tabs = tab_indentations > space_indentations;
+        let tab_size = if indent_with_tabs {
+            // Tabs will get a visual size of 4 spaces by default.
+            4
+        } else {
+            // Otherwise, we'll assume the most common indentation depth.
+            // If there are conflicting indentation depths, we'll prefer the maximum, because in the loop
+            // above we incremented the histogram slot for 2-spaces when encountering 4-spaces and so on.
+            let mut max = 1;
+            let mut tab_size = 4;
+            for (i, &count) in space_indentation_sizes.iter().enumerate() {
+                if count >= max {
+                    max = count;
+                    tab_size = i as CoordType + 2;
+                }
+            }
+            tab_size
+        };
+
+        // If the file has more than 1000 lines, figure out how many are remaining.
+        if offset < chunk.len() {
+            (_, lines) = simd::lines_fwd(chunk, offset, lines, CoordType::MAX);
         }
 
-        self.recalc_after_content_swap();
-        Ok(())
+        let final_newline = chunk.ends_with(b"\n");
+        // Unlike the heuristics above, this looks at the whole file.
+        let mixed_newlines = newlines::has_stray_newline(chunk, 0, newlines_are_crlf);
+
+        // Add 1, because the last line doesn't end in a newline (it ends in the literal end).
+        self.stats.logical_lines = lines + 1;
+        self.stats.visual_lines = self.stats.logical_lines;
+        self.newlines_are_crlf = newlines_are_crlf;
+        self.mixed_newlines = mixed_newlines;
+        self.insert_final_newline = final_newline;
+        self.indent_with_tabs = indent_with_tabs;
+        self.tab_size = tab_size;
     }
 
     fn read_file_as_utf8(
@@ -877,9 +1479,10 @@ impl TextBuffer {
         let scratch = scratch_arena(None);
         let pivot_buffer = scratch.alloc_uninit_slice(4 * KIBI);
         let mut c = icu::Converter::new(pivot_buffer, self.encoding, "UTF-8")?;
-        let mut first_chunk = unsafe { buf[..first_chunk_len].assume_init_ref() };
+        let mut consumed = 0;
 
-        while !first_chunk.is_empty() {
+        while consumed < first_chunk_len {
+            let first_chunk = unsafe { buf[consumed..first_chunk_len].assume_init_ref() };
             let off = self.text_length();
             let gap = self.buffer.allocate_gap(off, 8 * KIBI, 0);
             let (input_advance, mut output_advance) =
@@ -893,14 +1496,21 @@ impl TextBuffer {
                 if written.starts_with(b"\xEF\xBB\xBF") {
                     written.copy_within(3.., 0);
                     output_advance -= 3;
+                    self.bom = true;
                 }
             }
 
             self.buffer.commit_gap(output_advance);
-            first_chunk = &first_chunk[input_advance..];
+            consumed += input_advance;
+
+            // A char cut off at the end of the chunk is completed by the next read.
+            if input_advance == 0 {
+                break;
+            }
         }
 
-        let mut buf_len = 0;
+        buf.copy_within(consumed..first_chunk_len, 0);
+        let mut buf_len = first_chunk_len - consumed;
 
         loop {
             if !done {
@@ -919,11 +1529,16 @@ impl TextBuffer {
 
             self.buffer.commit_gap(output_advance);
 
-            let flush = done && buf_len == 0;
+            // Once everything's read, a char cut off at the end of the file can't be completed.
+            let flush = done && input_advance == 0;
             buf_len -= input_advance;
             buf.copy_within(input_advance.., 0);
 
             if flush {
+                let rest = unsafe { buf[..buf_len].assume_init_ref() };
+                let gap = self.buffer.allocate_gap(self.text_length(), 4, 0);
+                let output_advance = c.flush(rest, slice_as_uninit_mut(gap));
+                self.buffer.commit_gap(output_advance);
                 break;
             }
         }
@@ -933,6 +1548,14 @@ impl TextBuffer {
 
     /// Writes the text buffer contents to a file, handling BOM and encoding.
     pub fn write_file(&mut self, file: &mut File) -> apperr::Result<()> {
+        self.write_file_copy(file)?;
+        self.mark_as_clean();
+        Ok(())
+    }
+
+    /// Like [`TextBuffer::write_file()`], but doesn't mark the buffer as clean.
+    /// This is meant for writing recovery and backup copies, or to stdout.
+    pub fn write_file_copy(&self, file: &mut dyn io::Write) -> apperr::Result<()> {
         let mut offset = 0;
 
         if self.encoding.starts_with("UTF-8") {
@@ -951,30 +1574,39 @@ impl TextBuffer {
             self.write_file_with_icu(file)?;
         }
 
-        self.mark_as_clean();
         Ok(())
     }
 
-    fn write_file_with_icu(&mut self, file: &mut File) -> apperr::Result<()> {
+    fn write_file_with_icu(&self, file: &mut dyn io::Write) -> apperr::Result<()> {
         let scratch = scratch_arena(None);
         let pivot_buffer = scratch.alloc_uninit_slice(4 * KIBI);
         let buf = scratch.alloc_uninit_slice(4 * KIBI);
         let mut c = icu::Converter::new(pivot_buffer, "UTF-8", self.encoding)?;
         let mut offset = 0;
 
-        // Write the BOM for the encodings we know need it.
-        if self.encoding.starts_with("UTF-16")
-            || self.encoding.starts_with("UTF-32")
-            || self.encoding == "GB18030"
-        {
+        if self.bom {
             let (_, output_advance) = c.convert(b"\xEF\xBB\xBF", buf)?;
             let chunk = unsafe { buf[..output_advance].assume_init_ref() };
             file.write_all(chunk)?;
         }
 
         loop {
-            let chunk = self.read_forward(offset);
-            let (input_advance, output_advance) = c.convert(chunk, buf)?;
+            let mut chunk = self.read_forward(offset);
+            let mut joined = [0; 4];
+            let (mut input_advance, mut output_advance) = c.convert(chunk, buf)?;
+
+            // A char split between two chunks: Convert it from a copy of both halves.
+            if input_advance == 0 && !chunk.is_empty() {
+                let mut len = 0;
+                while len < joined.len() && !chunk.is_empty() {
+                    let n = chunk.len().min(joined.len() - len);
+                    joined[len..len + n].copy_from_slice(&chunk[..n]);
+                    len += n;
+                    chunk = self.read_forward(offset + len);
+                }
+                (input_advance, output_advance) = c.convert(&joined[..len], buf)?;
+            }
+
             let chunk = unsafe { buf[..output_advance].assume_init_ref() };
 
             file.write_all(chunk)?;
@@ -1025,6 +1657,41 @@ impl TextBuffer {
         ));
     }
 
+    /// Moves the cursor to `visual_pos` and updates the selection to contain it,
+    /// rounded outwards to whole words or lines. This is used when dragging after a
+    /// double or triple click. `anchor` is the range that was selected by the click.
+    /// It stays selected no matter in which direction the selection gets extended.
+    pub fn selection_update_visual_by(
+        &mut self,
+        visual_pos: Point,
+        unit: SelectionUnit,
+        anchor: Range<usize>,
+    ) {
+        let target = self.cursor_move_to_visual_internal(self.cursor, visual_pos);
+        let Range { start, end } = match unit {
+            SelectionUnit::Word => navigation::word_select(&self.buffer, target.offset),
+            SelectionUnit::Line => {
+                let y = target.logical_pos.y;
+                let beg = self.cursor_move_to_logical_internal(target, Point { x: 0, y });
+                let end = self.cursor_move_to_logical_internal(beg, Point { x: 0, y: y + 1 });
+                beg.offset..end.offset
+            }
+        };
+
+        let (beg, end) = if start < anchor.start {
+            (anchor.end, start)
+        } else {
+            (anchor.start, end.max(anchor.end))
+        };
+        let beg = self.cursor_move_to_offset_internal(self.cursor, beg);
+        let end = self.cursor_move_to_offset_internal(beg, end);
+        unsafe { self.set_cursor(end) };
+        self.set_selection(Some(TextBufferSelection {
+            beg: beg.logical_pos,
+            end: end.logical_pos,
+        }));
+    }
+
     /// Select the current word.
     pub fn select_word(&mut self) {
         let Range { start, end } = navigation::word_select(&self.buffer, self.cursor.offset);
@@ -1169,6 +1836,10 @@ impl TextBuffer {
         options: SearchOptions,
         replacement: &[u8],
     ) -> apperr::Result<Option<(usize, usize)>> {
+        if self.check_read_only() {
+            return Ok(None);
+        }
+
         let scratch = scratch_arena(None);
         let mut search = self.find_construct_search(pattern, options)?;
         let mut offset = 0;
@@ -1189,48 +1860,88 @@ impl TextBuffer {
         Ok(None)
     }
 
-    fn find_construct_search(
+    /// Find all occurrences of the given `pattern` without modifying the buffer.
+    /// Each hit is returned together with the text it would be replaced with.
+    pub fn find_all(
         &self,
         pattern: &str,
         options: SearchOptions,
-    ) -> apperr::Result<ActiveSearch> {
-        if pattern.is_empty() {
-            return Err(apperr::Error::Icu(1)); // U_ILLEGAL_ARGUMENT_ERROR
-        }
+        replacement: &[u8],
+    ) -> apperr::Result<Vec<FindMatch>> {
+        let scratch = scratch_arena(None);
+        let mut search = self.find_construct_search(pattern, options)?;
+        let parsed_replacements = Self::find_parse_replacement(&scratch, &mut search, replacement);
+        let mut res = Vec::new();
+        let mut cursor = self.cursor;
+
+        // The capture groups are only valid for the most recent match,
+        // so we have to step through the regex instead of using `search.matches`.
+        search.regex.reset(0);
+        while let Some(range) = search.regex.next() {
+            let filled = self.find_fill_replacement(&mut search, replacement, &parsed_replacements);
+
+            cursor = self.cursor_move_to_offset_internal(cursor, range.start);
+            let line_beg = self.goto_line_start(cursor, cursor.logical_pos.y);
+            let line_end = self.cursor_move_to_logical_internal(
+                line_beg,
+                Point { x: CoordType::MAX, y: line_beg.logical_pos.y },
+            );
 
-        let sanitized_pattern = if options.whole_word && options.use_regex {
-            Cow::Owned(format!(r"\b(?:{pattern})\b"))
-        } else if options.whole_word {
-            let mut p = String::with_capacity(pattern.len() + 16);
-            p.push_str(r"\b");
+            let mut line_text = Vec::new();
+            self.buffer.extract_raw(line_beg.offset..line_end.offset, &mut line_text, 0);
+            let len = unicode::strip_newline(&line_text).len();
+            line_text.truncate(len);
+
+            res.push(FindMatch {
+                range,
+                line: cursor.logical_pos.y,
+                line_offset: line_beg.offset,
+                line_text,
+                replacement: filled.into_owned(),
+            });
+        }
 
-            // Escape regex special characters.
-            let b = unsafe { p.as_mut_vec() };
-            for &byte in pattern.as_bytes() {
-                match byte {
-                    b'*' | b'?' | b'+' | b'[' | b'(' | b')' | b'{' | b'}' | b'^' | b'$' | b'|'
-                    | b'\\' | b'.' => {
-                        b.push(b'\\');
-                        b.push(byte);
-                    }
-                    _ => b.push(byte),
-                }
-            }
+        Ok(res)
+    }
 
-            p.push_str(r"\b");
-            Cow::Owned(p)
-        } else {
-            Cow::Borrowed(pattern)
-        };
+    /// Replaces the given ranges with their [`FindMatch::replacement`].
+    /// The matches must be sorted and not overlap, as returned by [`TextBuffer::find_all()`].
+    /// All replacements are recorded as a single undo step.
+    pub fn replace_matches(&mut self, matches: &[FindMatch]) {
+        let edits: Vec<_> =
+            matches.iter().map(|m| (m.range.clone(), m.replacement.as_slice())).collect();
+        self.replace_ranges(&edits);
+    }
 
-        let mut flags = icu::Regex::MULTILINE;
-        if !options.match_case {
-            flags |= icu::Regex::CASE_INSENSITIVE;
+    /// Like [`TextBuffer::replace_matches`], for plain ranges and their replacements.
+    fn replace_ranges(&mut self, edits: &[(Range<usize>, &[u8])]) {
+        if edits.is_empty() || self.check_read_only() {
+            return;
         }
-        if !options.use_regex && !options.whole_word {
-            flags |= icu::Regex::LITERAL;
+
+        self.edit_begin_grouping();
+
+        // Going back to front keeps the offsets of the remaining matches valid.
+        for (range, replacement) in edits.iter().rev() {
+            let beg = self.cursor_move_to_offset_internal(self.cursor, range.start);
+            let end = self.cursor_move_to_offset_internal(beg, range.end);
+            self.set_selection(Some(TextBufferSelection {
+                beg: beg.logical_pos,
+                end: end.logical_pos,
+            }));
+            self.write(replacement, beg, true);
         }
 
+        self.edit_end_grouping();
+    }
+
+    fn find_construct_search(
+        &self,
+        pattern: &str,
+        options: SearchOptions,
+    ) -> apperr::Result<ActiveSearch> {
+        let (sanitized_pattern, flags) = options.regex_pattern(pattern)?;
+
         // Move the start of the search to the start of the selection,
         // or otherwise to the current cursor position.
 
@@ -1239,9 +1950,7 @@ impl TextBuffer {
 
         let mut matches = Vec::new();
         regex.reset(0);
-        while let Some(range) = regex.next() {
-            matches.push(range);
-        }
+        matches.extend(regex.by_ref());
 
         Ok(ActiveSearch {
             pattern: pattern.to_string(),
@@ -1262,9 +1971,7 @@ impl TextBuffer {
             
             search.matches.clear();
             search.regex.reset(0);
-            while let Some(range) = search.regex.next() {
-                search.matches.push(range);
-            }
+            search.matches.extend(search.regex.by_ref());
             search.current_match_index = None;
         } 
         
@@ -1438,46 +2145,82 @@ impl TextBuffer {
             .with_tab_size(self.tab_size)
     }
 
-    fn goto_line_start(&self, cursor: Cursor, y: CoordType) -> Cursor {
-        let mut result = cursor;
-        let mut seek_to_line_start = true;
+    /// Calls `f` with the measured line that starts at `start`, see [`LineMetrics`].
+    fn with_line<R>(&self, start: Cursor, f: impl FnOnce(&Line) -> R) -> R {
+        let mut metrics = self.line_metrics.borrow_mut();
+        metrics.validate(self.buffer.generation(), self.word_wrap_column, self.tab_size);
 
-        if y > result.logical_pos.y {
-            while y > result.logical_pos.y {
-                let chunk = self.read_forward(result.offset);
-                if chunk.is_empty() {
-                    break;
-                }
+        let y = start.logical_pos.y;
+        if !metrics.get(y).is_some_and(|line| line.offset == start.offset) {
+            metrics.insert(self.measure_line(start));
+        }
+        f(metrics.get(y).unwrap())
+    }
 
-                let (delta, line) = simd::lines_fwd(chunk, 0, result.logical_pos.y, y);
-                result.offset += delta;
-                result.logical_pos.y = line;
-            }
+    fn measure_line(&self, start: Cursor) -> Line {
+        let mut line = Line {
+            y: start.logical_pos.y,
+            offset: start.offset,
+            len: 0,
+            newline: false,
+            rows: vec![Cursor::default()],
+        };
+        let mut config = self.measurement_config().with_cursor(start);
 
-            // If we're at the end of the buffer, we could either be there because the last
-            // character in the buffer is genuinely a newline, or because the buffer ends in a
-            // line of text without trailing newline. The only way to make sure is to seek
-            // backwards to the line start again. But otherwise we can skip that.
-            seek_to_line_start =
-                result.offset == self.text_length() && result.offset != cursor.offset;
+        loop {
+            let y = start.visual_pos.y + line.height();
+            let next = config.goto_visual(Point { x: 0, y });
+            if next.logical_pos.y != start.logical_pos.y || next.visual_pos.y != y {
+                line.len = next.offset - start.offset;
+                line.newline = next.logical_pos.y != start.logical_pos.y;
+                return line;
+            }
+            line.rows.push(Cursor {
+                offset: next.offset - start.offset,
+                logical_pos: Point { x: next.logical_pos.x, y: 0 },
+                visual_pos: Point { x: next.visual_pos.x, y: line.height() },
+                ..next
+            });
         }
+    }
 
-        if seek_to_line_start {
-            loop {
-                let chunk = self.read_backward(result.offset);
-                if chunk.is_empty() {
-                    break;
-                }
+    /// The start of the line `cursor` is on, if that line is cached.
+    fn line_start_cached(&self, cursor: Cursor) -> Option<Cursor> {
+        let mut metrics = self.line_metrics.borrow_mut();
+        metrics.validate(self.buffer.generation(), self.word_wrap_column, self.tab_size);
 
-                let (delta, line) = simd::lines_bwd(chunk, chunk.len(), result.logical_pos.y, y);
-                result.offset -= chunk.len() - delta;
-                result.logical_pos.y = line;
-                if delta > 0 {
-                    break;
-                }
-            }
+        let line = metrics.get(cursor.logical_pos.y)?;
+        if !(line.offset..=line.offset + line.len).contains(&cursor.offset) {
+            return None;
+        }
+        let row = line.row_of(cursor) as CoordType;
+        Some(Cursor {
+            offset: line.offset,
+            logical_pos: Point { x: 0, y: cursor.logical_pos.y },
+            visual_pos: Point { x: 0, y: cursor.visual_pos.y - row },
+            column: 0,
+            wrap_opp: false,
+        })
+    }
+
+    /// The `.visual_pos.y` of the line start `target`, if the lines from `cursor` to it are cached.
+    fn line_visual_y_cached(&self, cursor: Cursor, target: Cursor) -> Option<CoordType> {
+        let start = self.line_start_cached(cursor)?;
+        let metrics = self.line_metrics.borrow();
+        if metrics.get(target.logical_pos.y).is_some_and(|line| line.offset != target.offset) {
+            return None;
         }
 
+        let (a, b) = (start.logical_pos.y, target.logical_pos.y);
+        Some(if b >= a {
+            start.visual_pos.y + metrics.height(a, b)?
+        } else {
+            start.visual_pos.y - metrics.height(b, a)?
+        })
+    }
+
+    fn goto_line_start(&self, cursor: Cursor, y: CoordType) -> Cursor {
+        let mut result = self.seek_line_start(cursor, y);
         if result.offset == cursor.offset {
             return result;
         }
@@ -1489,6 +2232,11 @@ impl TextBuffer {
         result.wrap_opp = false;
 
         if self.word_wrap_column > 0 {
+            if let Some(y) = self.line_visual_y_cached(cursor, result) {
+                result.visual_pos.y = y;
+                return result;
+            }
+
             let upward = result.offset < cursor.offset;
             let (top, bottom) = if upward { (result, cursor) } else { (cursor, result) };
 
@@ -1521,6 +2269,50 @@ impl TextBuffer {
         result
     }
 
+    /// Seeks to the start of line `y`, updating only `.offset` and `.logical_pos.y`.
+    fn seek_line_start(&self, cursor: Cursor, y: CoordType) -> Cursor {
+        let mut result = cursor;
+        let mut seek_to_line_start = true;
+
+        if y > result.logical_pos.y {
+            while y > result.logical_pos.y {
+                let chunk = self.read_forward(result.offset);
+                if chunk.is_empty() {
+                    break;
+                }
+
+                let (delta, line) = simd::lines_fwd(chunk, 0, result.logical_pos.y, y);
+                result.offset += delta;
+                result.logical_pos.y = line;
+            }
+
+            // If we're at the end of the buffer, we could either be there because the last
+            // character in the buffer is genuinely a newline, or because the buffer ends in a
+            // line of text without trailing newline. The only way to make sure is to seek
+            // backwards to the line start again. But otherwise we can skip that.
+            seek_to_line_start =
+                result.offset == self.text_length() && result.offset != cursor.offset;
+        }
+
+        if seek_to_line_start {
+            loop {
+                let chunk = self.read_backward(result.offset);
+                if chunk.is_empty() {
+                    break;
+                }
+
+                let (delta, line) = simd::lines_bwd(chunk, chunk.len(), result.logical_pos.y, y);
+                result.offset -= chunk.len() - delta;
+                result.logical_pos.y = line;
+                if delta > 0 {
+                    break;
+                }
+            }
+        }
+
+        result
+    }
+
     fn cursor_move_to_offset_internal(&self, mut cursor: Cursor, offset: usize) -> Cursor {
         if offset == cursor.offset {
             return cursor;
@@ -1590,6 +2382,31 @@ impl TextBuffer {
             if pos.y == cursor.visual_pos.y && pos.x < cursor.visual_pos.x {
                 cursor = self.goto_line_start(cursor, cursor.logical_pos.y);
             }
+
+            // Skip the lines and rows before `pos.y` using the cache, measuring lines once.
+            let start = match cursor.logical_pos.x {
+                0 => Some(cursor),
+                _ => self.line_start_cached(cursor),
+            };
+            if let Some(mut start) = start {
+                loop {
+                    let (next, done) = self.with_line(start, |line| {
+                        let row = pos.y - start.visual_pos.y;
+                        if row < line.height() || !line.newline {
+                            (line.row(start, row.clamp(0, line.height() - 1) as usize), true)
+                        } else {
+                            (line.next_start(start), false)
+                        }
+                    });
+                    if done {
+                        if next.offset > cursor.offset {
+                            cursor = next;
+                        }
+                        break;
+                    }
+                    start = next;
+                }
+            }
         }
 
         self.measurement_config().with_cursor(cursor).goto_visual(pos)
@@ -1676,6 +2493,74 @@ impl TextBuffer {
         unsafe { self.set_cursor(self.cursor_move_to_logical_internal(self.cursor, pos)) }
     }
 
+    /// Undoes the reordering of right-to-left text by [`TextBuffer::render`] for a
+    /// position on the screen, e.g. of a click, so that it can be passed to
+    /// [`TextBuffer::cursor_move_to_visual`]. `origin_x` is the horizontal scroll
+    /// position and `width` that of the text area.
+    pub fn screen_to_visual_pos(&self, pos: Point, origin_x: CoordType, width: CoordType) -> Point {
+        let scratch = scratch_arena(None);
+        let beg = self.cursor_move_to_visual_internal(self.cursor, Point { x: origin_x, y: pos.y });
+        if beg.visual_pos.y != pos.y {
+            return pos;
+        }
+        let end = self.cursor_move_to_visual_internal(beg, Point { x: origin_x + width, y: pos.y });
+        match self.bidi_row(&scratch, beg, end) {
+            Some(clusters) => Point { x: bidi::visual_to_logical(&clusters, pos.x), y: pos.y },
+            None => pos,
+        }
+    }
+
+    /// Lays out the row from `beg` to `end` in visual order, unless it's all left-to-right.
+    fn bidi_row<'a>(
+        &self,
+        arena: &'a Arena,
+        beg: Cursor,
+        end: Cursor,
+    ) -> Option<Vec<VisualCluster, &'a Arena>> {
+        let mut text = Vec::new_in(arena);
+        let mut off = beg.offset;
+        while off < end.offset {
+            let chunk = self.read_forward(off);
+            if chunk.is_empty() {
+                break;
+            }
+            let chunk = &chunk[..chunk.len().min(end.offset - off)];
+            text.extend_from_slice(chunk);
+            off += chunk.len();
+        }
+
+        let rtl = match self.text_direction {
+            bidi::Direction::Rtl => true,
+            _ if text.is_empty() || !bidi::has_rtl(&text) => return None,
+            bidi::Direction::Ltr => false,
+            bidi::Direction::Auto => self.is_line_rtl(beg),
+        };
+        let text = &text[..];
+        let cfg = MeasurementConfig::new(&text).with_tab_size(self.tab_size);
+        let start = Cursor {
+            visual_pos: Point { x: beg.visual_pos.x, y: 0 },
+            column: beg.column,
+            ..Default::default()
+        };
+        Some(bidi::reorder(arena, text, cfg, start, rtl))
+    }
+
+    /// Whether the first letter of the line of `cursor` is right-to-left.
+    fn is_line_rtl(&self, cursor: Cursor) -> bool {
+        let mut off = self.goto_line_start(cursor, cursor.logical_pos.y).offset;
+        loop {
+            let chunk = self.read_forward(off);
+            let len = chunk.iter().position(|&b| b == b'\n').unwrap_or(chunk.len());
+            if let Some(rtl) = bidi::first_strong_rtl(&chunk[..len]) {
+                return rtl;
+            }
+            if len < chunk.len() || chunk.is_empty() {
+                return false;
+            }
+            off += len;
+        }
+    }
+
     /// Moves the cursor to the given visual position.
     pub fn cursor_move_to_visual(&mut self, pos: Point) {
         unsafe { self.set_cursor(self.cursor_move_to_visual_internal(self.cursor, pos)) }
@@ -1742,11 +2627,19 @@ impl TextBuffer {
         let scratch = scratch_arena(None);
         let width = destination.width();
         let height = destination.height();
-        let line_number_width = self.margin_width.max(3) as usize - 3;
+        let line_number_width = self.line_number_width as usize;
         let text_width = width - self.margin_width;
+        // The gutter marks that were placed, colored once the margin is.
+        let mut mark_cells = Vec::new_in(&*scratch);
         let mut visualizer_buf = [0xE2, 0x90, 0x80]; // U+2400 in UTF8
         let mut line = ArenaString::new_in(&scratch);
         let mut visual_pos_x_max = 0;
+        let mut indent_rows = Vec::new_in(&*scratch);
+        // The highlighted spans of the line the row is on, and the state the next one starts in.
+        let mut highlight_text = Vec::new_in(&*scratch);
+        let mut highlight_spans = Vec::new_in(&*scratch);
+        let mut highlight_line = None;
+        let mut highlight_guessed = None;
 
         // Pick the cursor closer to the `origin.y`.
         let mut cursor = {
@@ -1761,6 +2654,8 @@ impl TextBuffer {
             None => [Point::MIN, Point::MIN],
             Some(TextBufferSelection { beg, end }) => minmax(beg, end),
         };
+        // Where the cursor is shown if its row was reordered for right-to-left text.
+        let mut cursor_bidi_x = None;
 
         line.reserve(width as usize * 2);
 
@@ -1778,43 +2673,145 @@ impl TextBuffer {
             // Accelerate the next render pass by remembering where we started off.
             if y == 0 {
                 self.cursor_for_rendering = Some(cursor_beg);
+                // What's on the screen comes first, before the background pass goes on.
+                self.highlight_advance(
+                    cursor_beg.logical_pos.y + height,
+                    Instant::now() + HIGHLIGHT_RENDER_BUDGET,
+                );
             }
 
-            if line_number_width != 0 {
-                if visual_line >= self.stats.visual_lines {
-                    // Past the end of the buffer? Place "    | " in the margin.
-                    // Since we know that we won't see line numbers greater than i64::MAX (9223372036854775807)
-                    // any time soon, we can use a static string as the template (`MARGIN`) and slice it,
-                    // because `line_number_width` can't possibly be larger than 19.
-                    let off = 19 - line_number_width;
-                    unsafe { std::hint::assert_unchecked(off < MARGIN_TEMPLATE.len()) };
-                    line.push_str(&MARGIN_TEMPLATE[off..]);
-                } else if self.word_wrap_column <= 0 || cursor_beg.logical_pos.x == 0 {
-                    // Regular line? Place "123 | " in the margin.
-                    _ = write!(line, "{:1$} │ ", cursor_beg.logical_pos.y + 1, line_number_width);
-                } else {
-                    // Wrapped line? Place " ... | " in the margin.
-                    let number_width = (cursor_beg.logical_pos.y + 1).ilog10() as usize + 1;
-                    _ = write!(
-                        line,
-                        "{0:1$}{0:∙<2$} │ ",
-                        "",
-                        line_number_width - number_width,
-                        number_width
-                    );
-                    // Blending in the background color will "dim" the indicator dots.
-                    let left = destination.left;
-                    let top = destination.top + y;
-                    fb.blend_fg(
-                        Rect {
-                            left,
-                            top,
-                            right: left + line_number_width as CoordType,
-                            bottom: top + 1,
-                        },
-                        fb.indexed_alpha(IndexedColor::Background, 1, 2),
-                    );
+            if self.margin_width > 0 {
+                let past_end = visual_line >= self.stats.visual_lines;
+                // The continuation rows of wrapped lines have no number or marks.
+                let first_row = self.word_wrap_column <= 0 || cursor_beg.logical_pos.x == 0;
+                let mut x = 0;
+
+                for &column in &self.gutter {
+                    match column {
+                        GutterColumn::LineNumbers if line_number_width != 0 => {
+                            if past_end {
+                                // Past the end of the buffer? Place "    " in the margin.
+                                // Since we know that we won't see line numbers greater than i64::MAX (9223372036854775807)
+                                // any time soon, we can use a static string as the template (`MARGIN`) and slice it,
+                                // because `line_number_width` can't possibly be larger than 19.
+                                line.push_str(&MARGIN_TEMPLATE[..line_number_width + 1]);
+                            } else if first_row {
+                                // Regular line? Place "123 " in the margin.
+                                _ = write!(
+                                    line,
+                                    "{:1$} ",
+                                    cursor_beg.logical_pos.y + 1,
+                                    line_number_width
+                                );
+                            } else {
+                                // Wrapped line? Place " ... " in the margin.
+                                let number_width =
+                                    (cursor_beg.logical_pos.y + 1).ilog10() as usize + 1;
+                                _ = write!(
+                                    line,
+                                    "{0:1$}{0:∙<2$} ",
+                                    "",
+                                    line_number_width - number_width,
+                                    number_width
+                                );
+                                // Blending in the background color will "dim" the indicator dots.
+                                let left = destination.left + x;
+                                let top = destination.top + y;
+                                fb.blend_fg(
+                                    Rect {
+                                        left,
+                                        top,
+                                        right: left + line_number_width as CoordType,
+                                        bottom: top + 1,
+                                    },
+                                    fb.named(NamedColor::LineNumber).unwrap_or_else(|| {
+                                        fb.indexed_alpha(IndexedColor::Background, 1, 2)
+                                    }),
+                                );
+                            }
+                            x += line_number_width as CoordType + 1;
+                        }
+                        GutterColumn::Marks(name) if self.gutter_marks_shown(name) => {
+                            let marks = self.gutter_marks(name);
+                            let line_y = cursor_beg.logical_pos.y;
+                            let mark = if past_end || !first_row {
+                                None
+                            } else {
+                                marks
+                                    .binary_search_by_key(&line_y, |mark| mark.line)
+                                    .ok()
+                                    .map(|i| marks[i])
+                            };
+                            match mark {
+                                Some(mark) => {
+                                    line.push(mark.glyph);
+                                    mark_cells.push((destination.left + x, y, mark.color));
+                                }
+                                None => line.push(' '),
+                            }
+                            x += 1;
+                        }
+                        _ => {}
+                    }
+                }
+
+                line.push_str("│ ");
+            }
+
+            if self.indent_guides_enabled && visual_line < self.stats.visual_lines {
+                let line_y = cursor_beg.logical_pos.y;
+                let first_row = self.word_wrap_column <= 0 || cursor_beg.logical_pos.x == 0;
+                let row = match indent_rows.last() {
+                    Some(&row @ IndentRow { line, .. }) if line == line_y => {
+                        IndentRow { first_row, ..row }
+                    }
+                    _ => {
+                        let start = self.goto_line_start(cursor_beg, line_y);
+                        let (chars, columns) =
+                            self.measure_indent_internal(start.offset, CoordType::MAX);
+                        let blank = matches!(
+                            self.read_forward(start.offset + chars as usize).first(),
+                            None | Some(b'\n' | b'\r')
+                        );
+                        IndentRow { line: line_y, columns, blank, first_row }
+                    }
+                };
+                indent_rows.push(row);
+            }
+
+            if visual_line < self.stats.visual_lines
+                && let Some(highlighter) = &self.highlighter
+            {
+                let line_y = cursor_beg.logical_pos.y;
+                if highlight_line.is_none_or(|(y, _)| y != line_y) {
+                    let state = match (highlighter.state(line_y as usize), highlight_line) {
+                        (Some(state), _) => state,
+                        (None, Some((y, state))) if y == line_y - 1 => state,
+                        // The background pass isn't there yet. It's most likely not in a comment.
+                        (None, _) => {
+                            highlight_guessed.get_or_insert(line_y);
+                            HighlightState::Normal
+                        }
+                    };
+                    let start = self.seek_line_start(cursor_beg, line_y).offset;
+                    self.read_line(start, &mut highlight_text);
+                    highlight_spans.clear();
+                    let state =
+                        highlight::lex(highlighter.syntax(), state, &highlight_text, &mut |span| {
+                            let range = start + span.range.start..start + span.range.end;
+                            highlight_spans.push(Span { range, ..span });
+                        });
+                    highlight_line = Some((line_y, state));
                 }
+                self.render_highlights(
+                    &highlight_spans,
+                    cursor_beg,
+                    cursor_end,
+                    origin,
+                    destination,
+                    y,
+                    fb,
+                );
             }
 
             let mut selection_off = 0..0;
@@ -1859,11 +2856,13 @@ impl TextBuffer {
                     bottom: top + 1,
                 };
 
-                let mut bg = fb.indexed(IndexedColor::Foreground).oklab_blend(fb.indexed_alpha(
-                    IndexedColor::BrightBlue,
-                    1,
-                    2,
-                ));
+                let mut bg = fb.named(NamedColor::Selection).unwrap_or_else(|| {
+                    fb.indexed(IndexedColor::Foreground).oklab_blend(fb.indexed_alpha(
+                        IndexedColor::BrightBlue,
+                        1,
+                        2,
+                    ))
+                });
                 if !focused {
                     bg = bg.oklab_blend(fb.indexed_alpha(IndexedColor::Background, 1, 2));
                 };
@@ -1872,6 +2871,19 @@ impl TextBuffer {
                 fb.blend_fg(rect, fg);
             }
 
+            // Whether this row ends its line, rather than wrapping or being cut off.
+            let line_end = cursor_end.visual_pos.y == visual_line
+                && matches!(self.read_forward(cursor_end.offset).first(), Some(b'\n' | b'\r'));
+            // Where the whitespace at the end of the line starts. Spaces are only shown
+            // outside of selections if they're in there.
+            let trailing_beg = if self.whitespace_visible
+                && (line_end || cursor_end.offset == self.text_length())
+            {
+                self.trailing_whitespace_beg(cursor_end.offset)
+            } else {
+                usize::MAX
+            };
+
             // Nothing to do if the entire line is empty.
             if cursor_beg.offset != cursor_end.offset {
                 // If we couldn't reach the left edge, we may have stopped short due to a wide glyph.
@@ -1910,7 +2922,9 @@ impl TextBuffer {
 
                         if ch == ' ' || ch == '\t' {
                             let is_tab = ch == '\t';
-                            let visualize = selection_off.contains(&global_off);
+                            let visualize = selection_off.contains(&global_off)
+                                || (self.whitespace_visible
+                                    && (is_tab || global_off >= trailing_beg));
                             let mut whitespace = TAB_WHITESPACE;
                             let mut prefix_add = 0;
 
@@ -1945,11 +2959,33 @@ impl TextBuffer {
                                 };
                                 fb.blend_fg(
                                     visualizer_rect,
-                                    fb.indexed_alpha(IndexedColor::Foreground, 1, 2),
+                                    fb.named(NamedColor::Whitespace).unwrap_or_else(|| {
+                                        fb.indexed_alpha(IndexedColor::Foreground, 1, 2)
+                                    }),
                                 );
                             }
 
                             line.push_str(&whitespace[..prefix_add + tab_size as usize]);
+                        } else if ch == '\u{a0}'
+                            && (self.whitespace_visible || selection_off.contains(&global_off))
+                        {
+                            line.push_str(VISUAL_NBSP);
+
+                            cursor_line =
+                                self.cursor_move_to_offset_internal(cursor_line, global_off);
+                            let visualizer_rect = {
+                                let left =
+                                    destination.left + self.margin_width + cursor_line.visual_pos.x
+                                        - origin.x;
+                                let top = destination.top + cursor_line.visual_pos.y - origin.y;
+                                Rect { left, top, right: left + 1, bottom: top + 1 }
+                            };
+                            fb.blend_fg(
+                                visualizer_rect,
+                                fb.named(NamedColor::Whitespace).unwrap_or_else(|| {
+                                    fb.indexed_alpha(IndexedColor::Foreground, 1, 2)
+                                }),
+                            );
                         } else if ch <= '\x1f' || ('\u{7f}'..='\u{9f}').contains(&ch) {
                             // Append a Unicode representation of the C0 or C1 control character.
                             visualizer_buf[2] = if ch <= '\x1f' {
@@ -1973,7 +3009,9 @@ impl TextBuffer {
                                 let top = destination.top + cursor_line.visual_pos.y - origin.y;
                                 Rect { left, top, right: left + 1, bottom: top + 1 }
                             };
-                            let bg = fb.indexed(IndexedColor::Yellow);
+                            let bg = fb
+                                .named(NamedColor::Whitespace)
+                                .unwrap_or_else(|| fb.indexed(IndexedColor::Yellow));
                             let fg = fb.contrasted(bg);
                             fb.blend_bg(visualizer_rect, bg);
                             fb.blend_fg(visualizer_rect, fg);
@@ -1988,8 +3026,58 @@ impl TextBuffer {
                 visual_pos_x_max = visual_pos_x_max.max(cursor_end.visual_pos.x);
             }
 
+            // The line end goes right after the text, if that's on the screen.
+            let newline_selected =
+                selection_beg <= cursor_end.logical_pos && selection_end > cursor_end.logical_pos;
+            let mut x = cursor_end.visual_pos.x - origin.x;
+
+            // The CR of a CRLF is out of place in an LF file. Show it like a control character.
+            if line_end
+                && !self.newlines_are_crlf
+                && self.read_forward(cursor_end.offset).first() == Some(&b'\r')
+                && self.read_forward(cursor_end.offset + 1).first() == Some(&b'\n')
+                && (0..text_width).contains(&x)
+            {
+                line.push_str("\u{240d}");
+                let left = destination.left + self.margin_width + x;
+                let top = destination.top + y;
+                let rect = Rect { left, top, right: left + 1, bottom: top + 1 };
+                let bg = fb
+                    .named(NamedColor::Whitespace)
+                    .unwrap_or_else(|| fb.indexed(IndexedColor::Yellow));
+                let fg = fb.contrasted(bg);
+                fb.blend_bg(rect, bg);
+                fb.blend_fg(rect, fg);
+                x += 1;
+            }
+            if line_end
+                && (self.whitespace_visible || newline_selected)
+                && (0..text_width).contains(&x)
+            {
+                line.push_str(VISUAL_NEWLINE);
+
+                let left = destination.left + self.margin_width + x;
+                let top = destination.top + y;
+                fb.blend_fg(
+                    Rect { left, top, right: left + 1, bottom: top + 1 },
+                    fb.named(NamedColor::Whitespace)
+                        .unwrap_or_else(|| fb.indexed_alpha(IndexedColor::Foreground, 1, 2)),
+                );
+            }
+
             fb.replace_text(destination.top + y, destination.left, destination.right, &line);
 
+            // Right-to-left text is reordered with everything drawn on it, like the selection.
+            // Only the part on the screen is, which is exact unless it's scrolled horizontally.
+            if let Some(clusters) = self.bidi_row(&scratch, cursor_beg, cursor_end) {
+                let left = destination.left + self.margin_width - origin.x;
+                fb.reorder_row(destination.top + y, left, &clusters);
+                if self.cursor.visual_pos.y == visual_line {
+                    let x = bidi::logical_to_visual(&clusters, self.cursor.visual_pos.x);
+                    cursor_bidi_x = Some(x);
+                }
+            }
+
             cursor = cursor_end;
         }
 
@@ -2002,21 +3090,51 @@ impl TextBuffer {
                 bottom: destination.bottom,
             };
             fb.blend_fg(margin, StraightRgba::from_le(0x7f7f7f7f));
+
+            for &(left, y, color) in &mark_cells {
+                let top = destination.top + y;
+                let rect = Rect { left, top, right: left + 1, bottom: top + 1 };
+                fb.blend_fg(rect, fb.indexed(color));
+            }
         }
 
+        if !indent_rows.is_empty() {
+            self.render_indent_guides(&mut indent_rows, origin, destination, fb);
+        }
+        self.highlight_guessed = highlight_guessed;
+
         if self.ruler > 0 {
             let left = destination.left + self.margin_width + (self.ruler - origin.x).max(0);
             let right = destination.right;
             if left < right {
                 fb.blend_bg(
                     Rect { left, top: destination.top, right, bottom: destination.bottom },
-                    fb.indexed_alpha(IndexedColor::BrightRed, 1, 4),
+                    fb.named(NamedColor::Ruler)
+                        .unwrap_or_else(|| fb.indexed_alpha(IndexedColor::BrightRed, 1, 4)),
+                );
+            }
+        }
+
+        // Columns are visual, so wide glyphs and tabs before them are already accounted for.
+        for &column in &self.rulers {
+            let x = column - origin.x;
+            if column > 0 && (0..text_width).contains(&x) {
+                let left = destination.left + self.margin_width + x;
+                fb.blend_bg(
+                    Rect {
+                        left,
+                        top: destination.top,
+                        right: left + 1,
+                        bottom: destination.bottom,
+                    },
+                    fb.named(NamedColor::Ruler)
+                        .unwrap_or_else(|| fb.indexed_alpha(IndexedColor::Foreground, 1, 8)),
                 );
             }
         }
 
         if focused {
-            let mut x = self.cursor.visual_pos.x;
+            let mut x = cursor_bidi_x.unwrap_or(self.cursor.visual_pos.x);
             let mut y = self.cursor.visual_pos.y;
 
             if self.word_wrap_column > 0 && x >= self.word_wrap_column {
@@ -2058,6 +3176,103 @@ impl TextBuffer {
         Some(RenderResult { visual_pos_x_max })
     }
 
+    /// Colors the parts of the highlighted `spans` that are on the row from `beg` to `end`.
+    #[allow(clippy::too_many_arguments)]
+    fn render_highlights(
+        &self,
+        spans: &[Span],
+        beg: Cursor,
+        end: Cursor,
+        origin: Point,
+        destination: Rect,
+        y: CoordType,
+        fb: &mut Framebuffer,
+    ) {
+        let text = Rect { left: destination.left + self.margin_width, ..destination };
+        let left = text.left - origin.x;
+        let top = destination.top + y;
+        let mut cursor = beg;
+
+        for span in spans {
+            let span_beg = span.range.start.max(beg.offset);
+            let span_end = span.range.end.min(end.offset);
+            if span_beg >= span_end {
+                continue;
+            }
+            let Some(color) =
+                fb.named(span.color).or_else(|| syntax_fallback(span.color).map(|c| fb.indexed(c)))
+            else {
+                continue;
+            };
+
+            cursor = self.cursor_move_to_offset_internal(cursor, span_beg);
+            let x_beg = cursor.visual_pos.x;
+            // The end of the row might be the start of the next one, if it wraps.
+            let x_end = if span_end == end.offset {
+                end.visual_pos.x
+            } else {
+                cursor = self.cursor_move_to_offset_internal(cursor, span_end);
+                cursor.visual_pos.x
+            };
+            let rect = Rect { left: left + x_beg, top, right: left + x_end, bottom: top + 1 };
+            fb.blend_fg(rect.intersect(text), color);
+        }
+    }
+
+    /// Draws the indent guides as a tint of the cells, rather than with characters,
+    /// so that the text stays as it is. The guides around the cursor are brighter.
+    fn render_indent_guides(
+        &self,
+        rows: &mut [IndentRow],
+        origin: Point,
+        destination: Rect,
+        fb: &mut Framebuffer,
+    ) {
+        // Blank lines continue the guides of the lines above and below them.
+        let mut above = 0;
+        for i in 0..rows.len() {
+            if rows[i].blank {
+                let below =
+                    rows[i + 1..].iter().find(|row| !row.blank).map_or(0, |row| row.columns);
+                rows[i].columns = above.min(below);
+            } else {
+                above = rows[i].columns;
+            }
+        }
+
+        // The cursor's innermost guide is brighter, for as long as the lines around it are deeper.
+        let mut active = None;
+        if let Some(i) = rows.iter().position(|row| row.line == self.cursor.logical_pos.y)
+            && rows[i].columns > 0
+        {
+            let column = self.tab_size_prev_column(rows[i].columns);
+            let beg = rows[..i].iter().rposition(|row| row.columns <= column).map_or(0, |j| j + 1);
+            let end = rows[i..]
+                .iter()
+                .position(|row| row.columns <= column)
+                .map_or(rows.len(), |j| i + j);
+            active = Some((column, beg..end));
+        }
+
+        let faint = fb.indexed_alpha(IndexedColor::Foreground, 1, 4);
+        let bright = fb.indexed_alpha(IndexedColor::Foreground, 1, 2);
+        let text = Rect { left: destination.left + self.margin_width, ..destination };
+
+        for (y, row) in rows.iter().enumerate() {
+            if !row.first_row {
+                continue;
+            }
+            for column in (0..row.columns).step_by(self.tab_size as usize) {
+                let left = text.left + column - origin.x;
+                let top = destination.top + y as CoordType;
+                let rect = Rect { left, top, right: left + 1, bottom: top + 1 }.intersect(text);
+                let is_active =
+                    active.as_ref().is_some_and(|(c, rows)| *c == column && rows.contains(&y));
+                fb.blend_bg(rect, if is_active { bright } else { faint });
+            }
+        }
+    }
+
     pub fn cut(&mut self, clipboard: &mut Clipboard) {
         self.cut_copy(clipboard, true);
     }
@@ -2068,14 +3283,21 @@ impl TextBuffer {
 
     fn cut_copy(&mut self, clipboard: &mut Clipboard, cut: bool) {
         let line_copy = !self.has_selection();
+        // Cuts without anything else in between add up, like a kill ring's.
+        let append = cut && self.last_cut == Some((self.buffer.generation(), self.cursor.offset));
         let selection = self.extract_selection(cut);
-        clipboard.write(selection);
-        clipboard.write_was_line_copy(line_copy);
+        if append {
+            clipboard.append(&selection);
+        } else {
+            clipboard.write(selection);
+            clipboard.write_was_line_copy(line_copy);
+        }
+        self.last_cut = cut.then(|| (self.buffer.generation(), self.cursor.offset));
     }
 
     pub fn paste(&mut self, clipboard: &Clipboard) {
         let data = clipboard.read();
-        if data.is_empty() {
+        if data.is_empty() || self.check_read_only() {
             return;
         }
 
@@ -2086,17 +3308,119 @@ impl TextBuffer {
             self.cursor
         };
 
-        self.write(data, at, true);
+        self.write(data, at, true);
+
+        if clipboard.is_line_copy() {
+            self.cursor_move_to_logical(Point { x: pos.x, y: pos.y + 1 });
+        }
+        self.last_paste = Some(self.buffer.generation());
+    }
+
+    /// Right after a paste, replaces what was pasted with the
+    /// next entry of the clipboard's kill ring, see [`Clipboard::rotate`].
+    pub fn paste_rotate(&mut self, clipboard: &mut Clipboard) {
+        if self.last_paste != Some(self.buffer.generation()) || !clipboard.rotate() {
+            return;
+        }
+        // The paste is an undo step of its own, which also restores the selection it replaced.
+        self.undo();
+        self.paste(clipboard);
+    }
+
+    /// Inserts the user input `text` at the current cursor position.
+    /// Replaces tabs with whitespace if needed, etc.
+    pub fn write_canon(&mut self, text: &[u8]) {
+        self.write(text, self.cursor, false);
+    }
+
+    /// Like [`TextBuffer::write_canon`], but for text the user typed:
+    /// If auto-closing is enabled, an opening bracket or quote also inserts the closing one,
+    /// with the cursor in between, and typing that closing one just moves past it.
+    /// With a selection, both go around it instead of replacing it.
+    pub fn write_typed(&mut self, text: &[u8]) {
+        if self.auto_close
+            && self.has_selection()
+            && let &[c] = text
+            && let Some(close) = auto_close_pair(c)
+        {
+            self.surround(char::from(c), char::from(close));
+            return;
+        }
+        if !self.auto_close || self.overtype || self.read_only || self.has_selection() {
+            self.write_canon(text);
+            return;
+        }
+
+        // Fast typing arrives in batches, so brackets and quotes are handled one by one.
+        let mut rest = text;
+        while let Some(i) = rest.iter().position(|&c| b"()[]{}\"'".contains(&c)) {
+            self.write_typed_plain(&rest[..i]);
+            self.write_typed_bracket(rest[i]);
+            rest = &rest[i + 1..];
+        }
+        self.write_typed_plain(rest);
+    }
+
+    fn write_typed_bracket(&mut self, c: u8) {
+        let off = self.cursor.offset;
+        let prev = self.read_backward(off).last().copied();
+        let next = self.read_forward(off).first().copied();
+        let mut closers = mem::take(self.auto_closers_current());
+
+        if closers.last() == Some(&off) && next == Some(c) {
+            closers.pop();
+            self.cursor_move_to_offset(off + 1);
+            self.auto_closers.1 = closers;
+            return;
+        }
+
+        // Pair up only where the closing one can't belong to the text after the cursor,
+        // and don't take the apostrophe in "don't" or the second quote in `""` for an opening one.
+        let before_ok =
+            !matches!(c, b'"' | b'\'') || prev.is_none_or(|p| !p.is_ascii_alphanumeric() && p != c);
+        let after_ok = next.is_none_or(|n| {
+            n.is_ascii_whitespace() || matches!(n, b')' | b']' | b'}' | b',' | b';' | b':')
+        });
+        let Some(close) = auto_close_pair(c).filter(|_| before_ok && after_ok) else {
+            self.auto_closers.1 = closers;
+            self.write_typed_plain(&[c]);
+            return;
+        };
+
+        for closer in closers.iter_mut().filter(|closer| **closer >= off) {
+            *closer += 2;
+        }
+        closers.push(off + 1);
+
+        // A single undo step, which the cursor movement ends.
+        self.write_canon(&[c, close]);
+        self.cursor_move_to_offset(off + 1);
+        self.auto_closers = (self.buffer.generation(), closers);
+    }
+
+    /// Writes `text` and moves the closers after it along.
+    fn write_typed_plain(&mut self, text: &[u8]) {
+        if text.is_empty() {
+            return;
+        }
 
-        if clipboard.is_line_copy() {
-            self.cursor_move_to_logical(Point { x: pos.x, y: pos.y + 1 });
+        let off = self.cursor.offset;
+        let mut closers = mem::take(self.auto_closers_current());
+        let len = self.text_length();
+        self.write_canon(text);
+        let added = self.text_length().saturating_sub(len);
+        for closer in closers.iter_mut().filter(|closer| **closer >= off) {
+            *closer += added;
         }
+        self.auto_closers = (self.buffer.generation(), closers);
     }
 
-    /// Inserts the user input `text` at the current cursor position.
-    /// Replaces tabs with whitespace if needed, etc.
-    pub fn write_canon(&mut self, text: &[u8]) {
-        self.write(text, self.cursor, false);
+    /// The closers inserted by [`TextBuffer::write_typed`], unless the text changed since.
+    fn auto_closers_current(&mut self) -> &mut Vec<usize> {
+        if self.auto_closers.0 != self.buffer.generation() {
+            self.auto_closers = (self.buffer.generation(), Vec::new());
+        }
+        &mut self.auto_closers.1
     }
 
     /// Inserts `text` as-is at the current cursor position.
@@ -2106,6 +3430,10 @@ impl TextBuffer {
     }
 
     fn write(&mut self, text: &[u8], at: Cursor, raw: bool) {
+        if self.check_read_only() {
+            return;
+        }
+
         let history_type = if raw { HistoryType::Other } else { HistoryType::Write };
         let mut edit_begun = false;
 
@@ -2268,10 +3596,11 @@ impl TextBuffer {
     /// The selection is cleared after the call.
     /// Deletes characters from the buffer based on a delta from the cursor.
     pub fn delete(&mut self, granularity: CursorMovement, delta: CoordType) {
-        if delta == 0 {
+        if delta == 0 || self.check_read_only() {
             return;
         }
 
+        let backspace = matches!(granularity, CursorMovement::Grapheme) && delta == -1;
         let mut beg;
         let mut end;
 
@@ -2295,9 +3624,29 @@ impl TextBuffer {
             }
         }
 
+        // Backspace between an empty pair that `write_typed` inserted removes both.
+        let off = self.cursor.offset;
+        let mut closers = mem::take(self.auto_closers_current());
+        if backspace
+            && !self.has_selection()
+            && closers.last() == Some(&off)
+            && let Some(&open) = self.read_backward(off).last()
+            && self.read_forward(off).first().copied() == auto_close_pair(open)
+        {
+            closers.pop();
+            end = self.cursor_move_to_offset_internal(end, off + 1);
+        }
+
+        let removed = end.offset - beg.offset;
+        closers.retain(|&closer| closer < beg.offset || closer >= end.offset);
+        for closer in closers.iter_mut().filter(|closer| **closer >= end.offset) {
+            *closer -= removed;
+        }
+
         self.edit_begin(HistoryType::Delete, beg);
         self.edit_delete(end);
         self.edit_end();
+        self.auto_closers = (self.buffer.generation(), closers);
 
         self.set_selection(None);
     }
@@ -2312,6 +3661,10 @@ impl TextBuffer {
 
     /// Indents/unindents the current selection or line.
     pub fn indent_change(&mut self, direction: CoordType) {
+        if self.check_read_only() {
+            return;
+        }
+
         let selection = self.selection;
         let mut selection_beg = self.cursor.logical_pos;
         let mut selection_end = selection_beg;
@@ -2417,8 +3770,118 @@ impl TextBuffer {
         (chars, columns)
     }
 
+    /// Comments out the current selection or line, or uncomments it if all of it already is.
+    /// With an empty `suffix` `prefix` is a line comment like `//`, otherwise the
+    /// two wrap each line like `/*` and `*/`. Empty lines are left alone.
+    pub fn toggle_comment(&mut self, prefix: &str, suffix: &str) {
+        let prefix = prefix.trim().as_bytes();
+        let suffix = suffix.trim().as_bytes();
+        if prefix.is_empty() || self.check_read_only() {
+            return;
+        }
+
+        let selection = self.selection;
+        let cursor = self.cursor.logical_pos;
+        let [beg, end] = match selection {
+            Some(TextBufferSelection { beg, end }) => minmax(beg, end),
+            None => [cursor, cursor],
+        };
+        // A selection that ends at the start of a line doesn't include that line.
+        let last = if end.y > beg.y && end.x == 0 { end.y - 1 } else { end.y };
+
+        // Each non-empty line, its start offset, its indentation and its contents.
+        let mut lines = Vec::new();
+        for y in beg.y..=last {
+            let line_beg = self.goto_line_start(self.cursor, y);
+            let line_end = self.cursor_move_to_logical_internal(line_beg, Point { x: 0, y: y + 1 });
+            let mut text = Vec::new();
+            self.buffer.extract_raw(line_beg.offset..line_end.offset, &mut text, 0);
+            while text.last().is_some_and(|c| c.is_ascii_whitespace()) {
+                text.pop();
+            }
+            let indent = text.iter().take_while(|&&c| c == b' ' || c == b'\t').count();
+            if indent < text.len() {
+                lines.push((y, line_beg.offset, indent, text));
+            }
+        }
+        if lines.is_empty() {
+            return;
+        }
+
+        let commented = lines.iter().all(|(_, _, indent, text)| {
+            let text = &text[*indent..];
+            text.len() >= prefix.len() + suffix.len()
+                && text.starts_with(prefix)
+                && text.ends_with(suffix)
+        });
+        let mut cursor_delta = 0;
+
+        self.set_selection(None);
+        self.edit_begin_grouping();
+
+        // Going backwards keeps the offsets of the lines before valid.
+        for &(y, offset, indent, ref text) in lines.iter().rev() {
+            let indent_offset = offset + indent;
+            let text_end = offset + text.len();
+
+            if commented {
+                let inner = &text[indent + prefix.len()..text.len() - suffix.len()];
+                let space_after = inner.first() == Some(&b' ');
+                let space_before = !suffix.is_empty() && inner.len() > 1 && inner.ends_with(b" ");
+                if !suffix.is_empty() {
+                    let beg = text_end - suffix.len() - space_before as usize;
+                    self.delete_range(beg, text_end);
+                }
+                let removed = prefix.len() + space_after as usize;
+                self.delete_range(indent_offset, indent_offset + removed);
+                if y == cursor.y {
+                    cursor_delta = -(cursor.x - indent as CoordType).clamp(0, removed as CoordType);
+                }
+            } else {
+                if !suffix.is_empty() {
+                    let at = self.cursor_move_to_offset_internal(self.cursor, text_end);
+                    self.write(&[b" ", suffix].concat(), at, true);
+                }
+                let at = self.cursor_move_to_offset_internal(self.cursor, indent_offset);
+                self.write(&[prefix, b" "].concat(), at, true);
+                if y == cursor.y && cursor.x >= indent as CoordType {
+                    cursor_delta = prefix.len() as CoordType + 1;
+                }
+            }
+        }
+
+        self.edit_end_grouping();
+
+        if selection.is_some() {
+            // Select the lines in full, as their start and end moved.
+            let beg = self.cursor_move_to_logical_internal(self.cursor, Point { x: 0, y: beg.y });
+            let end =
+                self.cursor_move_to_logical_internal(beg, Point { x: CoordType::MAX, y: last });
+            self.set_cursor_internal(end);
+            self.set_selection(Some(TextBufferSelection {
+                beg: beg.logical_pos,
+                end: end.logical_pos,
+            }));
+        } else {
+            let pos = Point { x: cursor.x + cursor_delta, y: cursor.y };
+            self.set_cursor_internal(self.cursor_move_to_logical_internal(self.cursor, pos));
+        }
+    }
+
+    fn delete_range(&mut self, beg: usize, end: usize) {
+        let beg = self.cursor_move_to_offset_internal(self.cursor, beg);
+        let end = self.cursor_move_to_offset_internal(beg, end);
+        self.edit_begin(HistoryType::Delete, beg);
+        self.edit_delete(end);
+        self.edit_end();
+    }
+
     /// Displaces the current, cursor or the selection, line(s) in the given direction.
     pub fn move_selected_lines(&mut self, direction: MoveLineDirection) {
+        if self.check_read_only() {
+            return;
+        }
+
         let selection = self.selection;
         let cursor = self.cursor;
 
@@ -2485,6 +3948,399 @@ impl TextBuffer {
         }));
     }
 
+    /// Inserts a copy of the current, cursor or the selection, line(s) below them.
+    /// The cursor and selection move along to the copy, as a single undo step.
+    pub fn duplicate_selected_lines(&mut self) {
+        if self.check_read_only() {
+            return;
+        }
+
+        let selection = self.selection;
+        let cursor = self.cursor;
+        let [beg, end] = self.selected_lines();
+        let count = end - beg + 1;
+
+        let line_beg = self.cursor_move_to_logical_internal(cursor, Point { x: 0, y: beg });
+        let line_end = self.cursor_move_to_logical_internal(line_beg, Point { x: 0, y: end + 1 });
+        let mut text = Vec::new();
+        self.buffer.extract_raw(line_beg.offset..line_end.offset, &mut text, 0);
+
+        // Without a trailing newline the last line ends the document,
+        // and the copy needs one to go on a line of its own.
+        if line_end.logical_pos.y == end {
+            let newline: &[u8] = if self.newlines_are_crlf { b"\r\n" } else { b"\n" };
+            text.splice(0..0, newline.iter().copied());
+        }
+
+        self.edit_begin(HistoryType::Other, line_end);
+        self.edit_write(&text);
+        self.edit_end();
+
+        self.cursor_move_to_logical(Point {
+            x: cursor.logical_pos.x,
+            y: cursor.logical_pos.y + count,
+        });
+        self.set_selection(selection.map(|mut s| {
+            s.beg.y += count;
+            s.end.y += count;
+            s
+        }));
+    }
+
+    /// Deletes the current, cursor or the selection, line(s), as a single undo step.
+    /// The cursor stays in its column on the line that follows them.
+    pub fn delete_selected_lines(&mut self) {
+        if self.check_read_only() {
+            return;
+        }
+
+        let cursor = self.cursor;
+        let [beg, end] = self.selected_lines();
+
+        let mut from = self.cursor_move_to_logical_internal(cursor, Point { x: 0, y: beg });
+        let to = self.cursor_move_to_logical_internal(from, Point { x: 0, y: end + 1 });
+        // The last line has no newline of its own to delete, so the one before it goes instead.
+        if to.logical_pos.y == end && beg > 0 {
+            from =
+                self.cursor_move_to_logical_internal(from, Point { x: CoordType::MAX, y: beg - 1 });
+        }
+        if from.offset == to.offset {
+            return;
+        }
+
+        self.set_selection(None);
+        self.edit_begin(HistoryType::Other, from);
+        self.edit_delete(to);
+        self.edit_end();
+
+        let y = beg.min(self.stats.logical_lines - 1);
+        self.cursor_move_to_logical(Point { x: cursor.logical_pos.x, y });
+    }
+
+    /// Joins the next line, or all of the selection's, onto the current one as a single
+    /// undo step, like Vim's `J`. With the line comment leader `comment`, comments join
+    /// into one, too. The cursor goes where the last line was joined on.
+    pub fn join_lines(&mut self, comment: &str) {
+        if self.check_read_only() {
+            return;
+        }
+
+        let [beg, end] = self.selected_lines();
+        let end = end.max(beg + 1).min(self.stats.logical_lines - 1);
+        if beg >= end {
+            return;
+        }
+
+        let from = self.cursor_move_to_logical_internal(self.cursor, Point { x: 0, y: beg });
+        let to = self.cursor_move_to_logical_internal(from, Point { x: CoordType::MAX, y: end });
+        let mut text = Vec::new();
+        self.buffer.extract_raw(from.offset..to.offset, &mut text, 0);
+        let (joined, join) = join::join_lines(&text, comment.trim().as_bytes());
+
+        self.set_selection(None);
+        self.edit_begin(HistoryType::Other, from);
+        self.edit_delete(to);
+        self.edit_write(&joined);
+        self.edit_end();
+        self.cursor_move_to_offset(from.offset + join);
+    }
+
+    /// Rewraps the paragraphs in the selection, or without one the paragraph at the cursor,
+    /// so that they fit into the ruler's column (or 80 without one), as a single undo step.
+    /// Their indentation and comment leaders are kept, see [`rewrap::rewrap`].
+    pub fn rewrap(&mut self) {
+        if self.check_read_only() {
+            return;
+        }
+
+        let [mut beg, mut end] = self.selected_lines();
+        if self.selection.is_none() {
+            let key = |tb: &Self, y| {
+                let line = tb.logical_line_text(y);
+                str::from_utf8(&line).ok().and_then(rewrap::paragraph_key).map(str::to_string)
+            };
+            let Some(paragraph) = key(self, beg) else {
+                return;
+            };
+            while beg > 0 && key(self, beg - 1).as_ref() == Some(&paragraph) {
+                beg -= 1;
+            }
+            while end + 1 < self.stats.logical_lines
+                && key(self, end + 1).as_ref() == Some(&paragraph)
+            {
+                end += 1;
+            }
+        }
+
+        let from = self.cursor_move_to_logical_internal(self.cursor, Point { x: 0, y: beg });
+        let to = self.cursor_move_to_logical_internal(from, Point { x: CoordType::MAX, y: end });
+        let mut text = Vec::new();
+        self.buffer.extract_raw(from.offset..to.offset, &mut text, 0);
+        let Ok(text) = str::from_utf8(&text) else {
+            return;
+        };
+        let width = if self.ruler > 0 { self.ruler } else { 80 };
+        let wrapped = rewrap::rewrap(text, width, self.tab_size);
+
+        self.set_selection(None);
+        if wrapped != text {
+            self.edit_begin(HistoryType::Other, from);
+            self.edit_delete(to);
+            self.edit_write(wrapped.as_bytes());
+            self.edit_end();
+        }
+    }
+
+    /// The contents of the logical line `y`, without its newline.
+    fn logical_line_text(&self, y: CoordType) -> Vec<u8> {
+        let beg = self.cursor_move_to_logical_internal(self.cursor, Point { x: 0, y });
+        let end = self.cursor_move_to_logical_internal(beg, Point { x: CoordType::MAX, y });
+        let mut line = Vec::new();
+        self.buffer.extract_raw(beg.offset..end.offset, &mut line, 0);
+        line
+    }
+
+    /// Converts the selection, or without one the word at the cursor, to `case`,
+    /// as a single undo step. A selection stays around the converted text.
+    pub fn convert_case(&mut self, case: Case) {
+        if self.check_read_only() {
+            return;
+        }
+
+        let selection = self.selection;
+        let cursor = self.cursor;
+        if selection.is_none() {
+            self.select_word();
+        }
+        let Some((beg, end)) = self.selection_range_internal(false) else {
+            return;
+        };
+
+        let mut text = Vec::new();
+        self.buffer.extract_raw(beg.offset..end.offset, &mut text, 0);
+        let converted = match str::from_utf8(&text) {
+            Ok(text) => unicode::convert_case(text, case),
+            Err(_) => String::new(),
+        };
+
+        if converted.is_empty() || converted.as_bytes() == text {
+            self.set_selection(selection);
+        } else {
+            self.edit_begin(HistoryType::Other, beg);
+            self.edit_delete(end);
+            self.edit_write(converted.as_bytes());
+            self.edit_end();
+            let sel = TextBufferSelection { beg: beg.logical_pos, end: self.cursor.logical_pos };
+            self.set_selection(selection.map(|_| sel));
+        }
+        if selection.is_none() {
+            self.cursor_move_to_logical(cursor.logical_pos);
+        }
+    }
+
+    /// Encodes the selection with `codec`, or decodes it if `decode`, as a single edit.
+    /// Without a selection it's the line the cursor is on. On error nothing is changed.
+    pub fn transcode(&mut self, codec: Codec, decode: bool) -> Result<(), CodecError> {
+        if self.check_read_only() {
+            return Ok(());
+        }
+
+        let selection = self.selection;
+        let (beg, end) = match self.selection_range_internal(false) {
+            Some(range) => range,
+            None => {
+                let y = self.cursor.logical_pos.y;
+                let beg = self.cursor_move_to_logical_internal(self.cursor, Point { x: 0, y });
+                let end = self.cursor_move_to_logical_internal(beg, Point { x: CoordType::MAX, y });
+                (beg, end)
+            }
+        };
+
+        let mut text = Vec::new();
+        self.buffer.extract_raw(beg.offset..end.offset, &mut text, 0);
+        let converted =
+            if decode { codec::decode(codec, &text)? } else { codec::encode(codec, &text) };
+
+        if converted != text {
+            self.edit_begin(HistoryType::Other, beg);
+            self.edit_delete(end);
+            self.edit_write(&converted);
+            self.edit_end();
+            let sel = TextBufferSelection { beg: beg.logical_pos, end: self.cursor.logical_pos };
+            self.set_selection(selection.map(|_| sel));
+        }
+        Ok(())
+    }
+
+    /// Adds `delta` to the number at or after the cursor, like Vim's Ctrl+A and Ctrl+X,
+    /// and puts the cursor on its last digit. With a selection over several lines,
+    /// it's the first number of each line instead. If `sequential`, the n-th of them
+    /// gets n times `delta`, which turns a column of zeros into a numbered list.
+    pub fn add_to_number(&mut self, delta: i64, sequential: bool) {
+        if self.check_read_only() {
+            return;
+        }
+
+        let multiline = self.selection.is_some_and(|s| s.beg.y != s.end.y);
+        let [beg, end] = if multiline {
+            self.selected_lines()
+        } else {
+            [self.cursor.logical_pos.y, self.cursor.logical_pos.y]
+        };
+        let selection = self.selection;
+        let mut n = 0;
+        if !multiline {
+            self.set_selection(None);
+        }
+
+        self.edit_begin_grouping();
+        for y in beg..=end {
+            let line_beg = self.cursor_move_to_logical_internal(self.cursor, Point { x: 0, y });
+            let line_end =
+                self.cursor_move_to_logical_internal(line_beg, Point { x: CoordType::MAX, y });
+            let mut line = Vec::new();
+            self.buffer.extract_raw(line_beg.offset..line_end.offset, &mut line, 0);
+
+            let col = if multiline { 0 } else { self.cursor.offset - line_beg.offset };
+            n += 1;
+            let delta = if sequential { delta.saturating_mul(n) } else { delta };
+            let Some((range, replacement)) = number::add_to_number(&line, col, delta) else {
+                continue;
+            };
+
+            let from = self.cursor_move_to_offset_internal(line_beg, line_beg.offset + range.start);
+            let to = self.cursor_move_to_offset_internal(from, line_beg.offset + range.end);
+            self.edit_begin(HistoryType::Other, from);
+            self.edit_delete(to);
+            self.edit_write(replacement.as_bytes());
+            self.edit_end();
+            if !multiline {
+                self.cursor_move_delta(CursorMovement::Grapheme, -1);
+            }
+        }
+        self.edit_end_grouping();
+
+        if multiline {
+            self.set_selection(selection);
+        }
+    }
+
+    /// Puts `open` and `close` around the selection, or without one the word at the cursor,
+    /// as a single undo step. A selection stays around the text within.
+    pub fn surround(&mut self, open: char, close: char) {
+        if self.check_read_only() {
+            return;
+        }
+
+        let selection = self.selection;
+        let cursor = self.cursor;
+        if selection.is_none() {
+            self.select_word();
+        }
+        let (beg, end) = match self.selection_range_internal(false) {
+            Some(range) => range,
+            None => (cursor, cursor),
+        };
+
+        let mut text = Vec::new();
+        text.extend_from_slice(open.encode_utf8(&mut [0; 4]).as_bytes());
+        self.buffer.extract_raw(beg.offset..end.offset, &mut text, usize::MAX);
+        text.extend_from_slice(close.encode_utf8(&mut [0; 4]).as_bytes());
+
+        self.set_selection(None);
+        self.edit_begin(HistoryType::Other, beg);
+        self.edit_delete(end);
+        self.edit_write(&text);
+        self.edit_end();
+
+        let inner_beg = beg.offset + open.len_utf8();
+        if selection.is_some() {
+            let beg = self.cursor_move_to_offset_internal(beg, inner_beg);
+            let end = self.cursor_move_to_offset_internal(beg, end.offset + open.len_utf8());
+            unsafe { self.set_cursor(end) };
+            self.set_selection(Some(TextBufferSelection {
+                beg: beg.logical_pos,
+                end: end.logical_pos,
+            }));
+        } else {
+            self.cursor_move_to_offset(cursor.offset.max(beg.offset) + open.len_utf8());
+        }
+    }
+
+    /// Replaces the brackets or quotes `old` around the cursor (see [`surround::delimiters`])
+    /// with `new`, or removes them without one, as a single undo step.
+    /// Returns `false` if there are none.
+    pub fn change_surrounding(&mut self, old: char, new: Option<char>) -> bool {
+        if self.check_read_only() {
+            return false;
+        }
+
+        // Plenty for the brackets around a function, without copying all of a huge file.
+        const WINDOW: usize = 256 * KIBI;
+        let off = self.cursor.offset;
+        let window_beg = off.saturating_sub(WINDOW);
+        let mut text = Vec::new();
+        self.buffer.extract_raw(window_beg..off.saturating_add(WINDOW), &mut text, 0);
+
+        let (open, close) = surround::delimiters(old);
+        let Some((beg, end)) = surround::find_surrounding(&text, off - window_beg, open, close)
+        else {
+            return false;
+        };
+        let (beg, end) = (window_beg + beg, window_beg + end);
+
+        let mut new_open = [0; 4];
+        let mut new_close = [0; 4];
+        let (new_open, new_close) = match new.map(surround::delimiters) {
+            Some((o, c)) => {
+                (o.encode_utf8(&mut new_open).as_bytes(), c.encode_utf8(&mut new_close).as_bytes())
+            }
+            None => (&[][..], &[][..]),
+        };
+
+        self.set_selection(None);
+        self.edit_begin_grouping();
+        // The closing one first, so that `beg` remains valid.
+        for (at, len, replacement) in
+            [(end, close.len_utf8(), new_close), (beg, open.len_utf8(), new_open)]
+        {
+            let from = self.cursor_move_to_offset_internal(self.cursor, at);
+            let to = self.cursor_move_to_offset_internal(from, at + len);
+            self.edit_begin(HistoryType::Other, from);
+            self.edit_delete(to);
+            self.edit_write(replacement);
+            self.edit_end();
+        }
+        self.edit_end_grouping();
+
+        // Keep the cursor on the same character, or the delimiter that replaced the one it was on.
+        let open_delta = new_open.len() as isize - open.len_utf8() as isize;
+        let close_delta = new_close.len() as isize - close.len_utf8() as isize;
+        let cursor = match off {
+            off if off <= beg => off,
+            off if off < beg + open.len_utf8() => beg,
+            off if off <= end => off.saturating_add_signed(open_delta),
+            off if off < end + close.len_utf8() => end.saturating_add_signed(open_delta),
+            off => off.saturating_add_signed(open_delta + close_delta),
+        };
+        self.cursor_move_to_offset(cursor);
+        true
+    }
+
+    /// The first and last line of the selection, or the cursor's line twice without one.
+    /// A selection that ends at the start of a line, like that of [`TextBuffer::select_line`],
+    /// doesn't include that line.
+    fn selected_lines(&self) -> [CoordType; 2] {
+        match self.selection {
+            Some(s) => {
+                let [beg, end] = minmax(s.beg, s.end);
+                let end_y = if end.x == 0 && end.y > beg.y { end.y - 1 } else { end.y };
+                [beg.y, end_y]
+            }
+            None => [self.cursor.logical_pos.y, self.cursor.logical_pos.y],
+        }
+    }
+
     /// Extracts the contents of the current selection.
     /// May optionally delete it, if requested. This is meant to be used for Ctrl+X.
     fn extract_selection(&mut self, delete: bool) -> Vec<u8> {
@@ -2496,7 +4352,7 @@ impl TextBuffer {
         let mut out = Vec::new();
         self.buffer.extract_raw(beg.offset..end.offset, &mut out, 0);
 
-        if delete && !out.is_empty() {
+        if delete && !out.is_empty() && !self.check_read_only() {
             self.edit_begin(HistoryType::Delete, beg);
             self.edit_delete(end);
             self.edit_end();
@@ -2598,6 +4454,7 @@ impl TextBuffer {
                 cursor: cursor.logical_pos,
                 deleted: Vec::new(),
                 added: Vec::new(),
+                verbatim: false,
             }));
 
             if let Some(info) = &self.active_edit_group
@@ -2634,7 +4491,8 @@ impl TextBuffer {
     /// Writes `text` into the buffer at the current cursor position.
     /// It records the change in the undo stack.
     fn edit_write(&mut self, text: &[u8]) {
-        let logical_y_before = self.cursor.logical_pos.y;
+        let logical_pos_before = self.cursor.logical_pos;
+        let logical_y_before = logical_pos_before.y;
 
         // Copy the written portion into the undo entry.
         {
@@ -2643,13 +4501,24 @@ impl TextBuffer {
         }
 
         // Write!
+        let generation = self.buffer.generation();
         self.buffer.replace(self.active_edit_off..self.active_edit_off, text);
+        self.line_metrics.borrow_mut().edited(
+            generation,
+            self.buffer.generation(),
+            self.active_edit_off,
+            0,
+            text.len(),
+            simd::lines_fwd(text, 0, 0, CoordType::MAX).1,
+        );
 
         // Move self.cursor to the end of the newly written text. Can't use `self.set_cursor_internal`,
         // because we're still in the progress of recalculating the line stats.
         self.active_edit_off += text.len();
         self.cursor = self.cursor_move_to_offset_internal(self.cursor, self.active_edit_off);
-        self.stats.logical_lines += self.cursor.logical_pos.y - logical_y_before;
+        let added = self.cursor.logical_pos.y - logical_y_before;
+        self.stats.logical_lines += added;
+        self.lines_edited(logical_pos_before, 0, added);
     }
 
     /// Deletes the text between the current cursor position and `to`.
@@ -2676,9 +4545,20 @@ impl TextBuffer {
 
         // Delete the portion from the buffer by enlarging the gap.
         let count = to.offset - off;
+        let generation = self.buffer.generation();
         self.buffer.allocate_gap(off, 0, count);
+        self.line_metrics.borrow_mut().edited(
+            generation,
+            self.buffer.generation(),
+            off,
+            count,
+            0,
+            logical_y_before - to.logical_pos.y,
+        );
 
         self.stats.logical_lines += logical_y_before - to.logical_pos.y;
+        drop(undo);
+        self.lines_edited(self.cursor.logical_pos, to.logical_pos.y - logical_y_before, 0);
     }
 
     /// Finalizes the current edit operation
@@ -2740,8 +4620,14 @@ impl TextBuffer {
     }
 
     fn undo_redo(&mut self, undo: bool) {
+        if self.check_read_only() {
+            return;
+        }
+
         let buffer_generation = self.buffer.generation();
         let mut entry_buffer_generation = None;
+        // Undo takes the generation back, so it can't tell the cache apart from an older one.
+        self.line_metrics.borrow_mut().clear();
 
         loop {
             // Transfer the last entry from the undo stack to the redo stack or vice versa.
@@ -2786,6 +4672,7 @@ impl TextBuffer {
                 cursor
             };
 
+            let lines_changed;
             {
                 let mut change = change.borrow_mut();
                 let change = &mut *change;
@@ -2798,6 +4685,7 @@ impl TextBuffer {
 
                 // Reinsert the deleted portion.
                 {
+                    let verbatim = change.verbatim;
                     let added = &change.added[..];
                     let mut beg = 0;
                     let mut offset = cursor.offset;
@@ -2806,14 +4694,14 @@ impl TextBuffer {
                         let (end, line) = simd::lines_fwd(added, beg, 0, 1);
                         let has_newline = line != 0;
                         let link = &added[beg..end];
-                        let line = unicode::strip_newline(link);
+                        let line = if verbatim { link } else { unicode::strip_newline(link) };
                         let mut written;
 
                         {
                             let gap = self.buffer.allocate_gap(offset, line.len() + 2, 0);
                             written = slice_copy_safe(gap, line);
 
-                            if has_newline {
+                            if has_newline && !verbatim {
                                 if self.newlines_are_crlf && written < gap.len() {
                                     gap[written] = b'\r';
                                     written += 1;
@@ -2832,6 +4720,13 @@ impl TextBuffer {
                     }
                 }
 
+                let newlines = |text: &[u8]| simd::lines_fwd(text, 0, 0, CoordType::MAX).1;
+                lines_changed = (newlines(&change.deleted), newlines(&change.added));
+                if change.verbatim {
+                    self.mixed_newlines |=
+                        newlines::has_stray_newline(&change.added, 0, self.newlines_are_crlf);
+                }
+
                 // Restore the previous line statistics.
                 mem::swap(&mut self.stats, &mut change.stats_before);
 
@@ -2853,6 +4748,9 @@ impl TextBuffer {
                     self.last_history_type = HistoryType::Other;
                 }
             }
+
+            let (removed, added) = lines_changed;
+            self.lines_edited(cursor.logical_pos, removed as CoordType, added as CoordType);
         }
 
         if entry_buffer_generation.is_some() {
@@ -2860,8 +4758,20 @@ impl TextBuffer {
         }
     }
 
+    /// Returns the offset where the run of spaces and tabs that ends at `off` starts.
+    fn trailing_whitespace_beg(&self, mut off: usize) -> usize {
+        loop {
+            let chunk = self.read_backward(off);
+            let len = chunk.iter().rev().take_while(|&&c| c == b' ' || c == b'\t').count();
+            off -= len;
+            if len == 0 || len < chunk.len() {
+                return off;
+            }
+        }
+    }
+
     /// For interfacing with ICU.
-    pub(crate) fn read_backward(&self, off: usize) -> &[u8] {
+    pub fn read_backward(&self, off: usize) -> &[u8] {
         self.buffer.read_backward(off)
     }
 
@@ -2871,6 +4781,21 @@ impl TextBuffer {
     }
 }
 
+/// The colors of the syntax highlighting, for themes that don't set them.
+/// Operators are left alone, as they're everywhere.
+pub fn syntax_fallback(color: NamedColor) -> Option<IndexedColor> {
+    let indexed = match color {
+        NamedColor::Comment => IndexedColor::BrightBlack,
+        NamedColor::Keyword => IndexedColor::Magenta,
+        NamedColor::String => IndexedColor::Green,
+        NamedColor::Number | NamedColor::Constant => IndexedColor::Yellow,
+        NamedColor::Function => IndexedColor::BrightBlue,
+        NamedColor::Type => IndexedColor::Cyan,
+        _ => return None,
+    };
+    Some(indexed)
+}
+
 pub enum Bom {
     None,
     UTF8,
@@ -2883,7 +4808,19 @@ pub enum Bom {
 
 const BOM_MAX_LEN: usize = 4;
 
-fn detect_bom(bytes: &[u8]) -> Option<&'static str> {
+/// The closing bracket or quote for `c`, see [`TextBuffer::write_typed`].
+fn auto_close_pair(c: u8) -> Option<u8> {
+    match c {
+        b'(' => Some(b')'),
+        b'[' => Some(b']'),
+        b'{' => Some(b'}'),
+        b'"' | b'\'' => Some(c),
+        _ => None,
+    }
+}
+
+/// Returns the encoding whose byte order mark `bytes` starts with, if any.
+pub fn detect_bom(bytes: &[u8]) -> Option<&'static str> {
     if bytes.len() >= 4 {
         if bytes.starts_with(b"\xFF\xFE\x00\x00") {
             return Some("UTF-32LE");
@@ -2908,3 +4845,31 @@ fn detect_bom(bytes: &[u8]) -> Option<&'static str> {
     }
     None
 }
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    fn read(name: &str, encoding: &'static str, contents: &[u8]) -> String {
+        stdext::arena::init(16 * MEBI).unwrap();
+
+        let path = std::env::temp_dir().join(format!("edit-test-{name}-{}", std::process::id()));
+        fs::write(&path, contents).unwrap();
+        let mut tb = TextBuffer::new(false).unwrap();
+        tb.read_file(&mut File::open(&path).unwrap(), Some(encoding)).unwrap();
+        _ = fs::remove_file(&path);
+
+        let mut text = String::new();
+        tb.save_as_string(&mut text);
+        text
+    }
+
+    #[test]
+    fn test_read_truncated_utf16_utf32() {
+        // A lone byte at the end of a UTF-16 file becomes U+FFFD instead of vanishing.
+        assert_eq!(read("odd-utf16", "UTF-16LE", b"a\0b\0c"), "ab\u{fffd}");
+        assert_eq!(read("tail-utf32", "UTF-32BE", b"\0\0\0a\0\0\0"), "a\u{fffd}");
+    }
+}
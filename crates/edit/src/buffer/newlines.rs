@@ -0,0 +1,69 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Files that mix LF and CRLF, see [`super::TextBuffer::repair_newlines`].
+
+use crate::simd::memchr2;
+
+/// Whether `text` has a newline other than the given type. `prev` is the byte in front of it.
+pub fn has_stray_newline(text: &[u8], prev: u8, crlf: bool) -> bool {
+    let mut off = 0;
+    loop {
+        off = memchr2(b'\n', b'\n', text, off);
+        if off >= text.len() {
+            return false;
+        }
+        let cr = if off > 0 { text[off - 1] } else { prev } == b'\r';
+        if cr != crlf {
+            return true;
+        }
+        off += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::TextBuffer;
+    use super::*;
+
+    fn contents(tb: &TextBuffer) -> String {
+        let mut out = Vec::new();
+        while out.len() < tb.text_length() {
+            out.extend_from_slice(tb.read_forward(out.len()));
+        }
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_has_stray_newline() {
+        assert!(!has_stray_newline(b"a\nb\n", 0, false));
+        assert!(has_stray_newline(b"a\r\nb\n", 0, false));
+        assert!(has_stray_newline(b"a\r\nb\n", 0, true));
+        assert!(!has_stray_newline(b"\na", b'\r', true));
+        assert!(!has_stray_newline(b"a\rb", 0, false));
+    }
+
+    #[test]
+    fn test_repair_newlines() {
+        stdext::arena::init(16 * crate::helpers::MEBI).unwrap();
+
+        let text = b"a\r\nb\nc\r\nd";
+        let mut tb = TextBuffer::new(false).unwrap();
+        assert!(tb.read_loaded(text, text.len()).unwrap());
+        tb.finish_loading();
+        assert!(tb.has_mixed_newlines());
+        assert!(tb.dominant_newline_is_crlf());
+
+        tb.repair_newlines(true);
+        assert_eq!(contents(&tb), "a\r\nb\r\nc\r\nd");
+        assert!(!tb.has_mixed_newlines());
+
+        tb.undo();
+        assert_eq!(contents(&tb), "a\r\nb\nc\r\nd");
+        assert!(tb.has_mixed_newlines());
+
+        tb.repair_newlines(false);
+        assert_eq!(contents(&tb), "a\nb\nc\nd");
+        assert!(!tb.is_crlf());
+    }
+}
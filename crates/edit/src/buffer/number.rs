@@ -0,0 +1,104 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Vim's Ctrl+A and Ctrl+X, see [`super::TextBuffer::add_to_number`].
+
+use std::ops::Range;
+
+/// Finds the number in `line` that the column `col` is on, or else the first one after it,
+/// and returns its range and what it becomes once `delta` is added to it.
+///
+/// Decimal numbers may be negative, unless the `-` follows a letter or digit, as in `x-1`.
+/// Hexadecimal numbers with `0x` wrap around like unsigned ones and keep their width and case.
+/// Leading zeros are kept too, so that `007` becomes `008`.
+pub fn add_to_number(line: &[u8], col: usize, delta: i64) -> Option<(Range<usize>, String)> {
+    let mut i = 0;
+    while i < line.len() {
+        let Some((range, hex)) = number_at(line, i) else {
+            i += 1;
+            continue;
+        };
+        if range.end <= col {
+            i = range.end;
+            continue;
+        }
+
+        let text = std::str::from_utf8(&line[range.clone()]).ok()?;
+        let replacement = if hex { add_hex(text, delta)? } else { add_decimal(text, delta)? };
+        return Some((range, replacement));
+    }
+    None
+}
+
+/// The number that starts at `i`, if any, and whether it's hexadecimal.
+fn number_at(line: &[u8], i: usize) -> Option<(Range<usize>, bool)> {
+    let digits_from = |beg: usize, hex: bool| {
+        let len = line[beg..]
+            .iter()
+            .take_while(|&&c| if hex { c.is_ascii_hexdigit() } else { c.is_ascii_digit() })
+            .count();
+        beg + len
+    };
+
+    match line[i] {
+        b'0' if matches!(line.get(i + 1), Some(b'x' | b'X'))
+            && line.get(i + 2).is_some_and(|c| c.is_ascii_hexdigit()) =>
+        {
+            Some((i..digits_from(i + 2, true), true))
+        }
+        b'0'..=b'9' => Some((i..digits_from(i, false), false)),
+        b'-' if line.get(i + 1).is_some_and(|c| c.is_ascii_digit())
+            && (i == 0 || !line[i - 1].is_ascii_alphanumeric()) =>
+        {
+            Some((i..digits_from(i + 1, false), false))
+        }
+        _ => None,
+    }
+}
+
+fn add_decimal(text: &str, delta: i64) -> Option<String> {
+    let value: i64 = text.parse().ok()?;
+    let digits = text.trim_start_matches('-');
+    let width = if digits.len() > 1 && digits.starts_with('0') { digits.len() } else { 0 };
+    let value = value.saturating_add(delta);
+    let sign = if value < 0 { "-" } else { "" };
+    Some(format!("{sign}{:0width$}", value.unsigned_abs()))
+}
+
+fn add_hex(text: &str, delta: i64) -> Option<String> {
+    let (prefix, digits) = text.split_at(2);
+    let value = u64::from_str_radix(digits, 16).ok()?.wrapping_add(delta as u64);
+    let width = digits.len();
+    Some(if digits.bytes().any(|c| c.is_ascii_uppercase()) {
+        format!("{prefix}{value:0width$X}")
+    } else {
+        format!("{prefix}{value:0width$x}")
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn add(line: &str, col: usize, delta: i64) -> Option<String> {
+        let (range, replacement) = add_to_number(line.as_bytes(), col, delta)?;
+        Some(format!("{}{replacement}{}", &line[..range.start], &line[range.end..]))
+    }
+
+    #[test]
+    fn test_add_to_number() {
+        assert_eq!(add("x = 9;", 0, 1).as_deref(), Some("x = 10;"));
+        assert_eq!(add("a1 b22", 3, 5).as_deref(), Some("a1 b27"));
+        assert_eq!(add("a1 b22", 1, 5).as_deref(), Some("a6 b22"));
+        assert_eq!(add("a1 b22", 6, 5), None);
+        assert_eq!(add("-3 apples", 0, 5).as_deref(), Some("2 apples"));
+        assert_eq!(add("x-1", 0, 1).as_deref(), Some("x-2"));
+        assert_eq!(add("(-1)", 2, -1).as_deref(), Some("(-2)"));
+        assert_eq!(add("007", 0, 1).as_deref(), Some("008"));
+        assert_eq!(add("0xff", 0, 1).as_deref(), Some("0x100"));
+        assert_eq!(add("0x0A", 2, 1).as_deref(), Some("0x0B"));
+        assert_eq!(add("0x00", 0, -1).as_deref(), Some("0xffffffffffffffff"));
+        assert_eq!(add("0x", 0, 1).as_deref(), Some("1x"));
+        assert_eq!(add("99999999999999999999", 0, 1), None);
+    }
+}
@@ -0,0 +1,444 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+use std::cell::Cell;
+use std::ops::Range;
+use std::ptr::NonNull;
+use std::rc::Rc;
+use std::slice;
+
+use stdext::sys::{virtual_commit, virtual_release, virtual_reserve};
+
+use crate::apperr;
+use crate::document::ReadableDocument;
+use crate::helpers::*;
+
+#[cfg(target_pointer_width = "32")]
+const CAPACITY: usize = 128 * MEBI;
+#[cfg(target_pointer_width = "64")]
+const CAPACITY: usize = 4 * GIBI;
+const ALLOC_CHUNK: usize = 64 * KIBI;
+
+/// An append-only byte store. It never moves, so that the bytes
+/// below `len` stay valid and unchanged for as long as it lives.
+struct Store {
+    ptr: NonNull<u8>,
+    commit: Cell<usize>,
+    len: Cell<usize>,
+}
+
+impl Store {
+    fn new() -> apperr::Result<Self> {
+        let ptr = unsafe { virtual_reserve(CAPACITY)? };
+        Ok(Self { ptr, commit: Cell::new(0), len: Cell::new(0) })
+    }
+
+    /// Commits memory for at least `len` more bytes, if possible.
+    /// Returns how many bytes there are past the end.
+    fn reserve(&self, len: usize) -> usize {
+        let commit = self.commit.get();
+        let want = self.len.get() + len;
+
+        if want > commit {
+            let want = ((want + ALLOC_CHUNK - 1) & !(ALLOC_CHUNK - 1)).min(CAPACITY);
+            if unsafe { virtual_commit(self.ptr.add(commit), want - commit) }.is_ok() {
+                self.commit.set(want);
+            }
+        }
+
+        self.commit.get() - self.len.get()
+    }
+
+    fn slice(&self, beg: usize, len: usize) -> &[u8] {
+        debug_assert!(beg + len <= self.len.get());
+        unsafe { slice::from_raw_parts(self.ptr.add(beg).as_ptr(), len) }
+    }
+}
+
+impl Drop for Store {
+    fn drop(&mut self) {
+        unsafe { virtual_release(self.ptr, CAPACITY) };
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Source {
+    Original,
+    Added,
+}
+
+/// A contiguous run of text in one of the two stores.
+#[derive(Clone, Copy)]
+struct Piece {
+    source: Source,
+    beg: usize,
+    len: usize,
+}
+
+type Link = Option<Rc<Node>>;
+
+/// A node of a treap: Ordered by text offset, and a max-heap by `priority`,
+/// which keeps it balanced with high probability. Nodes are immutable and
+/// edits copy the path to the root, which lets split and merge share subtrees.
+struct Node {
+    piece: Piece,
+    priority: u32,
+    /// Length of the text in this subtree.
+    total: usize,
+    left: Link,
+    right: Link,
+}
+
+fn total(link: &Link) -> usize {
+    link.as_ref().map_or(0, |n| n.total)
+}
+
+fn node(piece: Piece, priority: u32, left: Link, right: Link) -> Link {
+    let total = total(&left) + piece.len + total(&right);
+    Some(Rc::new(Node { piece, priority, total, left, right }))
+}
+
+/// Splits the text into `..off` and `off..`. The piece at `off` gets cut in two.
+fn split(link: &Link, off: usize) -> (Link, Link) {
+    let Some(n) = link else {
+        return (None, None);
+    };
+    if off == 0 {
+        return (None, link.clone());
+    }
+    if off >= n.total {
+        return (link.clone(), None);
+    }
+
+    let left_len = total(&n.left);
+    let right_beg = left_len + n.piece.len;
+
+    if off <= left_len {
+        let (l, r) = split(&n.left, off);
+        (l, node(n.piece, n.priority, r, n.right.clone()))
+    } else if off >= right_beg {
+        let (l, r) = split(&n.right, off - right_beg);
+        (node(n.piece, n.priority, n.left.clone(), l), r)
+    } else {
+        // Both halves keep the priority. That's fine, since they end up in different trees.
+        let cut = off - left_len;
+        let head = Piece { len: cut, ..n.piece };
+        let tail = Piece { beg: n.piece.beg + cut, len: n.piece.len - cut, ..n.piece };
+        (
+            node(head, n.priority, n.left.clone(), None),
+            node(tail, n.priority, None, n.right.clone()),
+        )
+    }
+}
+
+/// Concatenates two trees.
+fn merge(a: Link, b: Link) -> Link {
+    match (a, b) {
+        (None, b) => b,
+        (a, None) => a,
+        (Some(a), Some(b)) => {
+            if a.priority >= b.priority {
+                let right = merge(a.right.clone(), Some(b));
+                node(a.piece, a.priority, a.left.clone(), right)
+            } else {
+                let left = merge(Some(a), b.left.clone());
+                node(b.piece, b.priority, left, b.right.clone())
+            }
+        }
+    }
+}
+
+fn last(link: &Link) -> Option<Piece> {
+    let mut n = link.as_ref()?;
+    while let Some(right) = &n.right {
+        n = right;
+    }
+    Some(n.piece)
+}
+
+/// Makes the last piece `len` bytes longer.
+fn extend_last(link: &Link, len: usize) -> Link {
+    let n = link.as_ref()?;
+    if n.right.is_some() {
+        node(n.piece, n.priority, n.left.clone(), extend_last(&n.right, len))
+    } else {
+        let piece = Piece { len: n.piece.len + len, ..n.piece };
+        node(piece, n.priority, n.left.clone(), None)
+    }
+}
+
+/// The pieces of a [`PieceTree`] and the stores they refer to.
+struct Text {
+    root: Link,
+    original: Store,
+    added: Store,
+}
+
+impl Text {
+    #[allow(clippy::len_without_is_empty)]
+    pub fn len(&self) -> usize {
+        total(&self.root)
+    }
+
+    /// Returns the piece that contains `off` and the offset it starts at.
+    fn find(&self, off: usize) -> Option<(Piece, usize)> {
+        let mut link = &self.root;
+        let mut beg = 0;
+
+        while let Some(n) = link {
+            let left_len = total(&n.left);
+            if off < beg + left_len {
+                link = &n.left;
+            } else if off < beg + left_len + n.piece.len {
+                return Some((n.piece, beg + left_len));
+            } else {
+                beg += left_len + n.piece.len;
+                link = &n.right;
+            }
+        }
+
+        None
+    }
+
+    fn bytes(&self, piece: Piece, range: Range<usize>) -> &[u8] {
+        let store = match piece.source {
+            Source::Original => &self.original,
+            Source::Added => &self.added,
+        };
+        store.slice(piece.beg + range.start, range.end - range.start)
+    }
+}
+
+impl ReadableDocument for Text {
+    fn read_forward(&self, off: usize) -> &[u8] {
+        match self.find(off) {
+            Some((piece, beg)) => self.bytes(piece, off - beg..piece.len),
+            None => &[],
+        }
+    }
+
+    fn read_backward(&self, off: usize) -> &[u8] {
+        let off = off.min(self.len());
+        if off == 0 {
+            return &[];
+        }
+        match self.find(off - 1) {
+            Some((piece, beg)) => self.bytes(piece, 0..off - beg),
+            None => &[],
+        }
+    }
+}
+
+/// A piece tree: The text is a sequence of pieces, each of which refers either to the
+/// original text or to text added by edits. Both are kept in append-only stores and
+/// the pieces in a balanced tree, so edits are `O(log n)` no matter the size of the text.
+///
+/// Deleted text stays in the stores until [`PieceTree::clear`].
+/// Compared to [`super::GapBuffer`], reads return smaller chunks the more it gets edited.
+pub struct PieceTree {
+    text: Text,
+    /// Whether the text has only been appended to since [`PieceTree::clear`].
+    /// Until then, it goes into the original store, so that it's one piece.
+    pristine: bool,
+    /// Where [`PieceTree::commit_gap`] inserts the text.
+    gap_off: usize,
+    /// Increments every time the buffer is modified.
+    generation: u32,
+    /// State of the random number generator for the node priorities.
+    seed: u32,
+}
+
+impl PieceTree {
+    pub fn new() -> apperr::Result<Self> {
+        Ok(Self {
+            text: Text { root: None, original: Store::new()?, added: Store::new()? },
+            pristine: true,
+            gap_off: 0,
+            generation: 0,
+            seed: 0x9E3779B9,
+        })
+    }
+
+    #[allow(clippy::len_without_is_empty)]
+    pub fn len(&self) -> usize {
+        self.text.len()
+    }
+
+    pub fn generation(&self) -> u32 {
+        self.generation
+    }
+
+    pub fn set_generation(&mut self, generation: u32) {
+        self.generation = generation;
+    }
+
+    fn store(&self) -> &Store {
+        if self.pristine { &self.text.original } else { &self.text.added }
+    }
+
+    /// Same as [`super::GapBuffer::allocate_gap`]: Deletes `delete` bytes at `off` and returns
+    /// space for at least `len` bytes, of which [`PieceTree::commit_gap`] inserts the beginning.
+    ///
+    /// WARNING: The returned slice must not necessarily be the same length as `len` (due to OOM).
+    pub fn allocate_gap(&mut self, off: usize, len: usize, delete: usize) -> &mut [u8] {
+        let off = off.min(self.len());
+        let delete = delete.min(self.len() - off);
+
+        if delete > 0 {
+            let (left, rest) = split(&self.text.root, off);
+            let (_, right) = split(&rest, delete);
+            self.text.root = merge(left, right);
+        }
+
+        self.pristine &= delete == 0 && off == self.len();
+        self.gap_off = off;
+        self.generation = self.generation.wrapping_add(1);
+
+        let store = self.store();
+        let spare = store.reserve(len);
+        // The bytes past `len` aren't part of any piece.
+        unsafe { slice::from_raw_parts_mut(store.ptr.add(store.len.get()).as_ptr(), spare) }
+    }
+
+    pub fn commit_gap(&mut self, len: usize) {
+        let store = self.store();
+        assert!(len <= store.commit.get() - store.len.get());
+        if len == 0 {
+            return;
+        }
+
+        let source = if self.pristine { Source::Original } else { Source::Added };
+        let piece = Piece { source, beg: store.len.get(), len };
+        store.len.set(piece.beg + len);
+
+        let (left, right) = split(&self.text.root, self.gap_off);
+        let left = match last(&left) {
+            // Typing and reading a file append to the piece before, instead of adding one.
+            Some(p) if p.source == source && p.beg + p.len == piece.beg => extend_last(&left, len),
+            _ => {
+                // xorshift32
+                self.seed ^= self.seed << 13;
+                self.seed ^= self.seed >> 17;
+                self.seed ^= self.seed << 5;
+                merge(left, node(piece, self.seed, None, None))
+            }
+        };
+        self.text.root = merge(left, right);
+        self.gap_off += len;
+    }
+
+    pub fn replace(&mut self, range: Range<usize>, src: &[u8]) {
+        let gap = self.allocate_gap(range.start, src.len(), range.end.saturating_sub(range.start));
+        let len = slice_copy_safe(gap, src);
+        self.commit_gap(len);
+    }
+
+    pub fn clear(&mut self) {
+        self.text.root = None;
+        self.pristine = true;
+        self.gap_off = 0;
+        self.generation = self.generation.wrapping_add(1);
+        self.text.original.len.set(0);
+        self.text.added.len.set(0);
+    }
+
+    pub fn extract_raw(&self, range: Range<usize>, out: &mut Vec<u8>, mut out_off: usize) {
+        let end = range.end.min(self.len());
+        let mut beg = range.start.min(end);
+        out_off = out_off.min(out.len());
+
+        if beg >= end {
+            return;
+        }
+
+        out.reserve(end - beg);
+
+        while beg < end {
+            let chunk = self.read_forward(beg);
+            let chunk = &chunk[..chunk.len().min(end - beg)];
+            out.replace_range(out_off..out_off, chunk);
+            beg += chunk.len();
+            out_off += chunk.len();
+        }
+    }
+}
+
+impl ReadableDocument for PieceTree {
+    fn read_forward(&self, off: usize) -> &[u8] {
+        self.text.read_forward(off)
+    }
+
+    fn read_backward(&self, off: usize) -> &[u8] {
+        self.text.read_backward(off)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn contents(doc: &dyn ReadableDocument) -> Vec<u8> {
+        let mut out = Vec::new();
+        loop {
+            let chunk = doc.read_forward(out.len());
+            if chunk.is_empty() {
+                return out;
+            }
+            out.extend_from_slice(chunk);
+        }
+    }
+
+    #[test]
+    fn test_edits() {
+        let mut tree = PieceTree::new().unwrap();
+        let mut expected = Vec::new();
+        let mut seed = 12345u32;
+
+        for i in 0..2000 {
+            seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+            let off = (seed >> 8) as usize % (expected.len() + 1);
+            let delete = if i % 3 == 0 { (seed & 7) as usize } else { 0 };
+            let delete = delete.min(expected.len() - off);
+            let text = format!("{i},");
+
+            tree.replace(off..off + delete, text.as_bytes());
+            expected.splice(off..off + delete, text.bytes());
+        }
+
+        assert_eq!(tree.len(), expected.len());
+        assert_eq!(contents(&tree), expected);
+
+        // Reading backwards has to arrive at the same text.
+        let mut off = tree.len();
+        let mut backwards = Vec::new();
+        while off > 0 {
+            let chunk = tree.read_backward(off);
+            backwards.splice(0..0, chunk.iter().copied());
+            off -= chunk.len();
+        }
+        assert_eq!(backwards, expected);
+
+        let mut out = Vec::new();
+        tree.extract_raw(100..200, &mut out, 0);
+        assert_eq!(out, &expected[100..200]);
+    }
+
+    #[test]
+    fn test_appends_are_one_piece() {
+        let mut tree = PieceTree::new().unwrap();
+        for _ in 0..100 {
+            let off = tree.len();
+            let gap = tree.allocate_gap(off, 10, 0);
+            gap[..10].copy_from_slice(b"0123456789");
+            tree.commit_gap(10);
+        }
+        assert_eq!(tree.read_forward(0).len(), 1000);
+
+        // So is typing in the middle.
+        for (i, c) in b"abc".iter().enumerate() {
+            tree.replace(500 + i..500 + i, &[*c]);
+        }
+        assert_eq!(tree.read_forward(500), b"abc");
+        assert_eq!(tree.read_backward(503), b"abc");
+    }
+}
@@ -0,0 +1,200 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Hard wraps paragraphs, see [`super::TextBuffer::rewrap`].
+
+use crate::helpers::{CoordType, Point};
+use crate::unicode::MeasurementConfig;
+
+/// Wide characters that mustn't start a line, like the `。` that ends a sentence.
+const NO_BREAK_BEFORE: &str = "、。，．：；！？）］｝〉》」』】〕ー～";
+/// Wide characters that mustn't end a line.
+const NO_BREAK_AFTER: &str = "（［｛〈《「『【〔";
+
+/// Rewraps the paragraphs in `text`, so that their lines fit into `width` columns,
+/// unless a single word is wider than that.
+///
+/// A paragraph is a run of lines that aren't blank and start with the same indentation
+/// and comment leader (see [`prefix_len`]), which every wrapped line starts with, too.
+/// Blank lines are left alone. Words are separated by whitespace, except for wide
+/// characters like those of CJK text, between which lines may break as well.
+pub fn rewrap(text: &str, width: CoordType, tab_size: CoordType) -> String {
+    let mut out = String::with_capacity(text.len() + text.len() / 8);
+    let mut lines = text.split_inclusive('\n').peekable();
+
+    while let Some(line) = lines.next() {
+        let content = line.trim_end_matches(['\r', '\n']);
+        let Some(key) = paragraph_key(content) else {
+            out.push_str(line);
+            continue;
+        };
+
+        let prefix = &content[..prefix_len(content)];
+        let newline = &line[content.len()..];
+        let mut paragraph = vec![&content[prefix.len()..]];
+        let mut last_newline = newline;
+        while let Some(next) = lines.peek() {
+            let content = next.trim_end_matches(['\r', '\n']);
+            if paragraph_key(content) != Some(key) {
+                break;
+            }
+            paragraph.push(&content[prefix_len(content)..]);
+            last_newline = &next[content.len()..];
+            lines.next();
+        }
+
+        let eol = if newline.is_empty() { "\n" } else { newline };
+        fill(&mut out, prefix, &paragraph, width, tab_size, eol);
+        out.push_str(last_newline);
+    }
+
+    out
+}
+
+/// The length of the indentation and comment leader (`//`, `///`, `//!`, `#` or `>`)
+/// that `line` starts with, including the whitespace after the leader.
+pub fn prefix_len(line: &str) -> usize {
+    let indent = line.len() - line.trim_start().len();
+    let rest = &line[indent..];
+    let leader = if let Some(r) = rest.strip_prefix("//") {
+        2 + r.starts_with(['/', '!']) as usize
+    } else if rest.starts_with('#') {
+        rest.len() - rest.trim_start_matches('#').len()
+    } else {
+        // Nested quotes in emails and Markdown look like `> > text`.
+        rest.len() - rest.trim_start_matches(['>', ' ']).len()
+    };
+    if leader == 0 {
+        return indent;
+    }
+    let rest = &rest[leader..];
+    indent + leader + rest.len() - rest.trim_start().len()
+}
+
+/// Lines with the same key belong to the same paragraph. Blank ones have none.
+pub fn paragraph_key(line: &str) -> Option<&str> {
+    let len = prefix_len(line);
+    if line[len..].trim().is_empty() { None } else { Some(line[..len].trim_end()) }
+}
+
+fn fill(
+    out: &mut String,
+    prefix: &str,
+    lines: &[&str],
+    width: CoordType,
+    tab_size: CoordType,
+    eol: &str,
+) {
+    let prefix_width = text_width(prefix, tab_size);
+    let mut column = prefix_width;
+    let mut empty = true;
+    let mut prev_wide = false;
+    out.push_str(prefix);
+
+    for (i, line) in lines.iter().enumerate() {
+        for (j, word) in line.split_whitespace().enumerate() {
+            for (k, segment) in segments(word).into_iter().enumerate() {
+                // Lines of CJK text that were wrapped before join up without a space.
+                let glued = k > 0
+                    || (i > 0
+                        && j == 0
+                        && prev_wide
+                        && segment.chars().next().is_some_and(is_wide));
+                let segment_width = text_width(segment, tab_size);
+                let space = if empty || glued { 0 } else { 1 };
+
+                if !empty && column + space + segment_width > width {
+                    out.push_str(eol);
+                    out.push_str(prefix);
+                    column = prefix_width;
+                } else {
+                    if space > 0 {
+                        out.push(' ');
+                    }
+                    column += space;
+                }
+
+                out.push_str(segment);
+                column += segment_width;
+                empty = false;
+                prev_wide = segment.chars().next_back().is_some_and(is_wide);
+            }
+        }
+    }
+}
+
+/// Splits `word` where a line may break within it, which is around wide characters.
+fn segments(word: &str) -> Vec<&str> {
+    let mut segments = Vec::new();
+    let mut beg = 0;
+    let mut prev = None;
+
+    for (i, c) in word.char_indices() {
+        if let Some(p) = prev
+            && (is_wide(c) || is_wide(p))
+            && !NO_BREAK_BEFORE.contains(c)
+            && !NO_BREAK_AFTER.contains(p)
+        {
+            segments.push(&word[beg..i]);
+            beg = i;
+        }
+        prev = Some(c);
+    }
+
+    segments.push(&word[beg..]);
+    segments
+}
+
+fn is_wide(c: char) -> bool {
+    !c.is_ascii() && text_width(c.encode_utf8(&mut [0; 4]), 1) > 1
+}
+
+fn text_width(text: &str, tab_size: CoordType) -> CoordType {
+    MeasurementConfig::new(&text.as_bytes())
+        .with_tab_size(tab_size)
+        .goto_visual(Point { x: CoordType::MAX, y: 0 })
+        .visual_pos
+        .x
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prefix_len() {
+        assert_eq!(prefix_len("    foo"), 4);
+        assert_eq!(prefix_len("  // foo"), 5);
+        assert_eq!(prefix_len("/// foo"), 4);
+        assert_eq!(prefix_len("//! foo"), 4);
+        assert_eq!(prefix_len("## foo"), 3);
+        assert_eq!(prefix_len("> > foo"), 4);
+        assert_eq!(prefix_len("a // b"), 0);
+        assert_eq!(paragraph_key("  //   "), None);
+        assert_eq!(paragraph_key("  // x"), Some("  //"));
+    }
+
+    #[test]
+    fn test_rewrap() {
+        assert_eq!(
+            rewrap("one two three\nfour five six\n\nseven\n", 10, 4),
+            "one two\nthree four\nfive six\n\nseven\n"
+        );
+        assert_eq!(
+            rewrap("    // aaa bbb\n    // ccc ddd\nfn x() {}\n", 15, 4),
+            "    // aaa bbb\n    // ccc ddd\nfn x() {}\n"
+        );
+        assert_eq!(rewrap("# a b c d\r\n# e", 7, 4), "# a b c\r\n# d e");
+        assert_eq!(rewrap("> a\n> b\n>\n> c\n", 80, 4), "> a b\n>\n> c\n");
+        assert_eq!(rewrap("\tx y\n", 10, 8), "\tx\n\ty\n");
+        assert_eq!(rewrap("averylongword a\n", 5, 4), "averylongword\na\n");
+    }
+
+    #[test]
+    fn test_rewrap_cjk() {
+        assert_eq!(rewrap("日本語の文章です。\n", 10, 4), "日本語の文\n章です。\n");
+        assert_eq!(rewrap("日本語の\n文章です。\n", 80, 4), "日本語の文章です。\n");
+        assert_eq!(rewrap("「日本」語\n", 4, 4), "「日\n本」\n語\n");
+        assert_eq!(rewrap("abc 中文\n", 5, 4), "abc\n中文\n");
+    }
+}
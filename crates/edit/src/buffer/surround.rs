@@ -0,0 +1,142 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Finds the brackets or quotes around the cursor, see [`super::TextBuffer::change_surrounding`].
+
+/// The opening and closing delimiter for `c`. Brackets pair up with their counterpart,
+/// whichever of the two is given, and anything else, like a quote, with itself.
+pub fn delimiters(c: char) -> (char, char) {
+    match c {
+        '(' | ')' => ('(', ')'),
+        '[' | ']' => ('[', ']'),
+        '{' | '}' => ('{', '}'),
+        '<' | '>' => ('<', '>'),
+        _ => (c, c),
+    }
+}
+
+/// Finds the delimiters `open` and `close` around `off` in `text`, or on it,
+/// and returns the offsets of both.
+///
+/// Brackets nest, so that in `(a (b) c)` it's the outer pair for the `c`.
+/// Quotes can't, so they're paired up from the start of the line on instead,
+/// skipping those escaped with a backslash: In `"a" b "c"` the `b` isn't quoted.
+pub fn find_surrounding(
+    text: &[u8],
+    off: usize,
+    open: char,
+    close: char,
+) -> Option<(usize, usize)> {
+    let open = open.encode_utf8(&mut [0; 4]).as_bytes().to_vec();
+    let close = close.encode_utf8(&mut [0; 4]).as_bytes().to_vec();
+    if open == close {
+        find_quotes(text, off, &open)
+    } else {
+        find_brackets(text, off, &open, &close)
+    }
+}
+
+fn find_brackets(text: &[u8], off: usize, open: &[u8], close: &[u8]) -> Option<(usize, usize)> {
+    let at = |i: usize, delim: &[u8]| text[i..].starts_with(delim);
+
+    // On the opening bracket itself, or else the first unmatched one before `off`.
+    let beg = if off < text.len() && at(off, open) {
+        off
+    } else {
+        let mut depth = 0usize;
+        let mut beg = None;
+        for i in (0..off.min(text.len())).rev() {
+            if at(i, close) {
+                depth += 1;
+            } else if at(i, open) {
+                if depth == 0 {
+                    beg = Some(i);
+                    break;
+                }
+                depth -= 1;
+            }
+        }
+        beg?
+    };
+
+    let mut depth = 0usize;
+    let mut i = beg + open.len();
+    while i < text.len() {
+        if at(i, open) {
+            depth += 1;
+            i += open.len();
+        } else if at(i, close) {
+            if depth == 0 {
+                return Some((beg, i));
+            }
+            depth -= 1;
+            i += close.len();
+        } else {
+            i += 1;
+        }
+    }
+    None
+}
+
+fn find_quotes(text: &[u8], off: usize, quote: &[u8]) -> Option<(usize, usize)> {
+    let off = off.min(text.len());
+    let line_beg = text[..off].iter().rposition(|&c| c == b'\n').map_or(0, |i| i + 1);
+    let line_end = text[off..].iter().position(|&c| c == b'\n').map_or(text.len(), |i| off + i);
+
+    let mut beg = None;
+    let mut i = line_beg;
+    while i < line_end {
+        if text[i] == b'\\' {
+            i += 2;
+            continue;
+        }
+        if !text[i..line_end].starts_with(quote) {
+            i += 1;
+            continue;
+        }
+        match beg.take() {
+            None if i > off => return None,
+            None => beg = Some(i),
+            Some(beg) if off <= i => return Some((beg, i)),
+            Some(_) => {}
+        }
+        i += quote.len();
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn find(text: &str, open: char) -> Option<(usize, usize)> {
+        let off = text.find('|').unwrap();
+        let text = text.replace('|', "");
+        let (open, close) = delimiters(open);
+        find_surrounding(text.as_bytes(), off, open, close)
+    }
+
+    #[test]
+    fn test_brackets() {
+        assert_eq!(find("f(a, |b)", '('), Some((1, 6)));
+        assert_eq!(find("(a (b) |c)", ')'), Some((0, 8)));
+        assert_eq!(find("(a (|b) c)", '('), Some((3, 5)));
+        assert_eq!(find("|(a)", '('), Some((0, 2)));
+        assert_eq!(find("(a|)", '('), Some((0, 2)));
+        assert_eq!(find("{\n  x|\n}", '{'), Some((0, 6)));
+        assert_eq!(find("(a) |b", '('), None);
+        assert_eq!(find("(a |b", '('), None);
+    }
+
+    #[test]
+    fn test_quotes() {
+        assert_eq!(find("say \"h|i\"", '"'), Some((4, 7)));
+        assert_eq!(find("\"a\" |b \"c\"", '"'), None);
+        assert_eq!(find("\"a\" b \"|c\"", '"'), Some((6, 8)));
+        assert_eq!(find("|'a'", '\''), Some((0, 2)));
+        assert_eq!(find("\"a \\\" |b\"", '"'), Some((0, 7)));
+        assert_eq!(find("\"a\n|b\"", '"'), None);
+        assert_eq!(find("«|a»", '«'), None);
+        assert_eq!(find("*a|*", '*'), Some((0, 2)));
+    }
+}
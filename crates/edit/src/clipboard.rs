@@ -1,5 +1,11 @@
 //! Clipboard facilities for the editor.
 
+use std::collections::VecDeque;
+use std::mem;
+
+/// How many earlier contents the kill ring holds on to.
+const RING_CAPACITY: usize = 32;
+
 /// The builtin, internal clipboard of the editor.
 ///
 /// This is useful particularly when the terminal doesn't support
@@ -9,6 +15,8 @@ pub struct Clipboard {
     data: Vec<u8>,
     line_copy: bool,
     wants_host_sync: bool,
+    /// The earlier contents and whether they were line copies, the most recent first.
+    ring: VecDeque<(Vec<u8>, bool)>,
 }
 
 impl Clipboard {
@@ -38,16 +46,119 @@ impl Clipboard {
     }
 
     /// Fill the clipboard with the given data.
+    /// The previous contents move into the kill ring.
     pub fn write(&mut self, data: Vec<u8>) {
         if !data.is_empty() {
-            self.data = data;
+            let previous = mem::replace(&mut self.data, data);
+            self.ring.retain(|(data, _)| *data != self.data);
+            if !previous.is_empty() && previous != self.data {
+                self.ring.push_front((previous, self.line_copy));
+                self.ring.truncate(RING_CAPACITY);
+            }
             self.line_copy = false;
             self.wants_host_sync = true;
         }
     }
 
+    /// Adds `data` to the end of the contents, for consecutive cuts.
+    pub fn append(&mut self, data: &[u8]) {
+        if !data.is_empty() {
+            self.data.extend_from_slice(data);
+            self.wants_host_sync = true;
+        }
+    }
+
+    /// The earlier contents, the most recent first.
+    pub fn ring(&self) -> impl Iterator<Item = &[u8]> {
+        self.ring.iter().map(|(data, _)| data.as_slice())
+    }
+
+    /// Forgets the contents and the kill ring, e.g. after copying a password.
+    /// The system clipboard is left alone, as it belongs to the host.
+    pub fn clear_history(&mut self) {
+        self.data.clear();
+        self.line_copy = false;
+        self.ring.clear();
+        self.wants_host_sync = false;
+    }
+
+    /// Makes the `index`-th entry of [`Clipboard::ring`] the contents.
+    /// The current contents become the most recent entry of the ring.
+    pub fn select(&mut self, index: usize) {
+        if let Some(entry) = self.ring.remove(index) {
+            self.swap_in(entry, true);
+        }
+    }
+
+    /// Makes the most recent entry of the ring the contents and moves the
+    /// current ones to its far end, so that repeated calls cycle through all of them.
+    /// Returns false if the ring is empty.
+    pub fn rotate(&mut self) -> bool {
+        let Some(entry) = self.ring.pop_front() else {
+            return false;
+        };
+        self.swap_in(entry, false);
+        true
+    }
+
+    fn swap_in(&mut self, (data, line_copy): (Vec<u8>, bool), front: bool) {
+        let previous =
+            (mem::replace(&mut self.data, data), mem::replace(&mut self.line_copy, line_copy));
+        if !previous.0.is_empty() {
+            if front {
+                self.ring.push_front(previous);
+            } else {
+                self.ring.push_back(previous);
+            }
+        }
+        self.wants_host_sync = true;
+    }
+
     /// See [`Clipboard::is_line_copy`].
     pub fn write_was_line_copy(&mut self, line_copy: bool) {
         self.line_copy = line_copy;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ring(clipboard: &Clipboard) -> Vec<&[u8]> {
+        clipboard.ring().collect()
+    }
+
+    #[test]
+    fn test_ring() {
+        let mut clipboard = Clipboard::default();
+        clipboard.write(b"a".to_vec());
+        clipboard.write(b"b".to_vec());
+        clipboard.write(b"c".to_vec());
+        assert_eq!(clipboard.read(), b"c");
+        assert_eq!(ring(&clipboard), [b"b", b"a"]);
+
+        // Copying something again moves it to the front instead of duplicating it.
+        clipboard.write(b"a".to_vec());
+        assert_eq!(ring(&clipboard), [b"c", b"b"]);
+
+        assert!(clipboard.rotate());
+        assert_eq!(clipboard.read(), b"c");
+        assert!(clipboard.rotate());
+        assert_eq!(clipboard.read(), b"b");
+        assert!(clipboard.rotate());
+        assert_eq!(clipboard.read(), b"a");
+
+        clipboard.select(1);
+        assert_eq!(clipboard.read(), b"b");
+        assert_eq!(ring(&clipboard), [b"a", b"c"]);
+
+        clipboard.append(b"x");
+        assert_eq!(clipboard.read(), b"bx");
+
+        clipboard.clear_history();
+        assert_eq!(clipboard.read(), b"");
+        assert_eq!(ring(&clipboard).len(), 0);
+        clipboard.write(b"d".to_vec());
+        assert_eq!(ring(&clipboard).len(), 0);
+    }
+}
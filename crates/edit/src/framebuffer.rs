@@ -6,15 +6,19 @@
 use std::cell::Cell;
 use std::fmt::Write;
 use std::ops::{BitOr, BitXor};
-use std::ptr;
 use std::slice::ChunksExact;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread::{self, JoinHandle};
+use std::{mem, ptr};
 
-use stdext::arena::{Arena, ArenaString};
+use stdext::arena::{Arena, ArenaString, scratch_arena};
 
-use crate::helpers::{CoordType, Point, Rect, Size};
+use crate::helpers::{CoordType, MEBI, Point, Rect, Size};
 use crate::oklab::StraightRgba;
 use crate::simd::{MemsetSafe, memset};
+use crate::sys;
 use crate::unicode::MeasurementConfig;
+use crate::unicode::bidi::{self, VisualCluster};
 
 // Same constants as used in the PCG family of RNGs.
 #[cfg(target_pointer_width = "32")]
@@ -30,6 +34,9 @@ const CACHE_TABLE_SIZE: usize = 1 << CACHE_TABLE_LOG2_SIZE;
 /// 8 bits out, but rather shift 56 bits down to get the best bits from the top.
 const CACHE_TABLE_SHIFT: usize = usize::BITS as usize - CACHE_TABLE_LOG2_SIZE;
 
+/// [`Framebuffer::present`] keeps the output's capacity for the next frame, up to this much.
+const OUTPUT_CAPACITY: usize = MEBI;
+
 /// Standard 16 VT & default foreground/background colors.
 #[derive(Clone, Copy)]
 pub enum IndexedColor {
@@ -60,9 +67,50 @@ impl<T: Into<u8>> From<T> for IndexedColor {
     }
 }
 
+/// How RGB colors are sent to the terminal.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum ColorMode {
+    /// 24-bit RGB colors (`CSI 38;2;r;g;b m`).
+    #[default]
+    TrueColor,
+    /// The closest color in the 256-color palette (`CSI 38;5;n m`),
+    /// for terminals and multiplexers without true color support.
+    Palette256,
+}
+
 /// Number of indices used by [`IndexedColor`].
 pub const INDEXED_COLORS_COUNT: usize = 18;
 
+/// Colors for parts of the UI and the text, beyond the [`IndexedColor`]s.
+/// Themes may set them. Otherwise, they're derived from the palette.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum NamedColor {
+    /// The menubar and statusbar.
+    Bar,
+    /// Popups and dialogs.
+    Floater,
+    /// The background of selected text.
+    Selection,
+    LineNumber,
+    /// Tabs and spaces shown while indenting, and control characters.
+    Whitespace,
+    Ruler,
+    /// The background of the focused list item.
+    Focus,
+
+    Comment,
+    Keyword,
+    String,
+    Number,
+    Constant,
+    Function,
+    Type,
+    Operator,
+}
+
+/// Number of indices used by [`NamedColor`].
+pub const NAMED_COLORS_COUNT: usize = 15;
+
 /// Fallback theme. Matches Windows Terminal's Ottosson theme.
 pub const DEFAULT_THEME: [StraightRgba; INDEXED_COLORS_COUNT] = [
     StraightRgba::from_be(0x000000ff), // Black
@@ -99,6 +147,8 @@ pub const DEFAULT_THEME: [StraightRgba; INDEXED_COLORS_COUNT] = [
 pub struct Framebuffer {
     /// Store the color palette.
     indexed_colors: [StraightRgba; INDEXED_COLORS_COUNT],
+    /// The extended palette. `None` where the palette's colors are used.
+    named_colors: [Option<StraightRgba>; NAMED_COLORS_COUNT],
     /// Front and back buffers. Indexed by `frame_counter & 1`.
     buffers: [Buffer; 2],
     /// The current frame counter. Increments on every `flip` call.
@@ -114,9 +164,17 @@ pub struct Framebuffer {
     contrast_colors: [Cell<(StraightRgba, StraightRgba)>; CACHE_TABLE_SIZE],
     background_fill: StraightRgba,
     foreground_fill: StraightRgba,
-    /// When true, uses 256-color mode instead of true color (24-bit RGB).
-    /// This is needed for compatibility with some terminal environments like Termux over SSH.
-    disable_true_color: bool,
+    color_mode: ColorMode,
+    /// Whether the cursor should blink. Turned off while the terminal window is unfocused.
+    cursor_blink: bool,
+    /// Serializes and writes the frames, see [`Framebuffer::spawn_render_thread`].
+    render_thread: Option<RenderThread>,
+    /// The frame that [`Framebuffer::present`] hands to the render thread.
+    pending: Option<Frame>,
+    /// Whether the render thread must redraw everything, see [`Framebuffer::invalidate`].
+    invalidated: bool,
+    /// How wide tabs passed to [`Framebuffer::replace_text`] are.
+    tab_size: CoordType,
 }
 
 impl Framebuffer {
@@ -124,6 +182,7 @@ impl Framebuffer {
     pub fn new() -> Self {
         Self {
             indexed_colors: DEFAULT_THEME,
+            named_colors: [None; NAMED_COLORS_COUNT],
             buffers: Default::default(),
             frame_counter: 0,
             auto_colors: [
@@ -135,14 +194,50 @@ impl Framebuffer {
                 CACHE_TABLE_SIZE],
             background_fill: DEFAULT_THEME[IndexedColor::Background as usize],
             foreground_fill: DEFAULT_THEME[IndexedColor::Foreground as usize],
-            disable_true_color: false,
+            color_mode: ColorMode::TrueColor,
+            cursor_blink: true,
+            render_thread: None,
+            pending: None,
+            invalidated: false,
+            tab_size: 8,
         }
     }
 
-    /// Disables true color (24-bit RGB) and uses 256-color mode instead.
-    /// This is useful for compatibility with environments that have limited terminal support.
-    pub fn set_disable_true_color(&mut self, disable: bool) {
-        self.disable_true_color = disable;
+    /// Sets how wide tabs passed to [`Framebuffer::replace_text`] are. Clamped to 1-8.
+    pub fn set_tab_size(&mut self, tab_size: CoordType) {
+        self.tab_size = tab_size.clamp(1, 8);
+    }
+
+    pub fn tab_size(&self) -> CoordType {
+        self.tab_size
+    }
+
+    /// Moves turning the frames into VT and writing them to stdout to a thread of its own.
+    /// From then on, [`Framebuffer::render`] only hands the frame over and returns nothing,
+    /// and the output must be written with [`Framebuffer::present`].
+    ///
+    /// That way a slow terminal doesn't hold up the handling of the next input.
+    pub fn spawn_render_thread(&mut self) {
+        if self.render_thread.is_none() {
+            self.render_thread = Some(RenderThread::spawn());
+        }
+    }
+
+    /// Sets how RGB colors are sent to the terminal.
+    pub fn set_color_mode(&mut self, mode: ColorMode) {
+        if self.color_mode != mode {
+            self.color_mode = mode;
+            // What's on screen was sent the other way.
+            self.invalidate();
+        }
+    }
+
+    /// Makes the next frame redraw everything instead of only what changed,
+    /// e.g. because the colors changed in ways the frame contents don't show.
+    pub fn invalidate(&mut self) {
+        self.buffers[self.frame_counter & 1].invalidate();
+        // The render thread keeps its own copy of what's on screen.
+        self.invalidated = true;
     }
 
     /// Sets the base color palette.
@@ -153,7 +248,25 @@ impl Framebuffer {
         self.indexed_colors = colors;
         self.background_fill = StraightRgba::zero();
         self.foreground_fill = StraightRgba::zero();
+        self.update_auto_colors();
+    }
 
+    /// Remaps a single color of the base palette, e.g. for accessibility.
+    ///
+    /// Remapped default colors are painted from then on,
+    /// as the terminal doesn't know about them.
+    pub fn set_indexed_color(&mut self, index: IndexedColor, color: StraightRgba) {
+        self.indexed_colors[index as usize] = color;
+        match index {
+            IndexedColor::Background => self.background_fill = color,
+            IndexedColor::Foreground => self.foreground_fill = color,
+            _ => {}
+        }
+        self.update_auto_colors();
+    }
+
+    /// Derives the colors for `contrasted()` from the palette.
+    fn update_auto_colors(&mut self) {
         self.auto_colors = [
             self.indexed_colors[IndexedColor::Black as usize],
             self.indexed_colors[IndexedColor::BrightWhite as usize],
@@ -171,23 +284,42 @@ impl Framebuffer {
         if lightness[0] > lightness[1] {
             self.auto_colors.swap(0, 1);
         }
+
+        // The palette may change at runtime, when switching themes.
+        for slot in &self.contrast_colors {
+            slot.set((StraightRgba::zero(), StraightRgba::zero()));
+        }
+    }
+
+    /// Sets the extended color palette. `None` entries fall back to the base palette.
+    pub fn set_named_colors(&mut self, colors: [Option<StraightRgba>; NAMED_COLORS_COUNT]) {
+        self.named_colors = colors;
+    }
+
+    /// Paints the default background and foreground in the palette's colors,
+    /// instead of leaving them to the terminal. Needed for palettes that
+    /// don't come from the terminal, like themes.
+    pub fn fill_default_colors(&mut self) {
+        self.background_fill = self.indexed(IndexedColor::Background);
+        self.foreground_fill = self.indexed(IndexedColor::Foreground);
     }
 
     /// Begins a new frame with the given `size`.
     pub fn flip(&mut self, size: Size) {
-        if size != self.buffers[0].bg_bitmap.size {
-            for buffer in &mut self.buffers {
-                buffer.text = LineBuffer::new(size);
-                buffer.bg_bitmap = Bitmap::new(size);
-                buffer.fg_bitmap = Bitmap::new(size);
-                buffer.attributes = AttributeBuffer::new(size);
+        let mut resized = false;
+        for buffer in &mut self.buffers {
+            // With a render thread, one of them may be a spare from an earlier size.
+            if buffer.size() != size {
+                *buffer = Buffer::new(size);
+                resized = true;
             }
+        }
 
-            let front = &mut self.buffers[self.frame_counter & 1];
-            // Trigger a full redraw. (Yes, it's a hack.)
-            front.fg_bitmap.fill(StraightRgba::from_le(1));
+        // The render thread notices the new size on its own.
+        if resized && self.render_thread.is_none() {
+            self.invalidate();
             // Trigger a cursor update as well, just to be sure.
-            front.cursor = Cursor::new_invalid();
+            self.buffers[self.frame_counter & 1].cursor = Cursor::new_invalid();
         }
 
         self.frame_counter = self.frame_counter.wrapping_add(1);
@@ -198,12 +330,15 @@ impl Framebuffer {
         back.bg_bitmap.fill(self.background_fill);
         back.fg_bitmap.fill(self.foreground_fill);
         back.attributes.reset();
+        back.links.clear();
         back.cursor = Cursor::new_disabled();
     }
 
     /// Replaces text contents in a single line of the framebuffer.
     /// All coordinates are in viewport coordinates.
-    /// Assumes that control characters have been replaced or escaped.
+    /// Assumes that control characters other than tabs have been replaced or escaped.
+    /// Tabs turn into spaces up to the next multiple of [`Framebuffer::tab_size`],
+    /// counted from the start of `text`.
     pub fn replace_text(
         &mut self,
         y: CoordType,
@@ -211,10 +346,100 @@ impl Framebuffer {
         clip_right: CoordType,
         text: &str,
     ) {
+        let scratch = scratch_arena(None);
+        let text = if text.contains('\t') {
+            let bytes = text.as_bytes();
+            let mut cfg = MeasurementConfig::new(&bytes).with_tab_size(self.tab_size);
+            let mut expanded = ArenaString::new_in(&scratch);
+            let mut beg = 0;
+            for (off, _) in text.match_indices('\t') {
+                let column = cfg.goto_offset(off).visual_pos.x;
+                expanded.push_str(&text[beg..off]);
+                expanded.push_repeat(' ', (self.tab_size - column % self.tab_size) as usize);
+                beg = off + 1;
+            }
+            expanded.push_str(&text[beg..]);
+            expanded.leak()
+        } else {
+            text
+        };
+
         let back = &mut self.buffers[self.frame_counter & 1];
         back.text.replace_text(y, origin_x, clip_right, text)
     }
 
+    /// Shows the text that [`Framebuffer::replace_text`] put on row `y` in visual order.
+    /// The `clusters` come from [`bidi::reorder`] and are at `left + x`. Their colors
+    /// and attributes move along with them. Brackets in right-to-left runs are mirrored.
+    pub fn reorder_row(&mut self, y: CoordType, left: CoordType, clusters: &[VisualCluster]) {
+        let back = &mut self.buffers[self.frame_counter & 1];
+        let size = back.size();
+        let (Some(beg), Some(end)) = (
+            clusters.iter().map(|c| left + c.x).min(),
+            clusters.iter().map(|c| left + c.x + c.width).max(),
+        ) else {
+            return;
+        };
+        if !(0..size.height).contains(&y) || beg < 0 || end > size.width {
+            return;
+        }
+
+        let scratch = scratch_arena(None);
+        let line = &mut back.text.lines[y as usize];
+
+        // Find the text of each cluster, going from left to right.
+        let mut order = Vec::new_in(&*scratch);
+        order.extend(0..clusters.len());
+        order.sort_unstable_by_key(|&i| clusters[i].x);
+        let mut ranges = Vec::new_in(&*scratch);
+        ranges.resize(clusters.len(), 0..0);
+        let bytes = line.as_bytes();
+        let mut cfg = MeasurementConfig::new(&bytes);
+        for &i in &order {
+            let c = clusters[i];
+            let beg = cfg.goto_visual(Point { x: left + c.x, y: 0 }).offset;
+            let end = cfg.goto_visual(Point { x: left + c.x + c.width, y: 0 }).offset;
+            ranges[i] = beg..end;
+        }
+
+        let mut text = ArenaString::new_in(&scratch);
+        for (c, range) in clusters.iter().zip(&ranges) {
+            if c.rtl {
+                text.extend(line[range.clone()].chars().map(bidi::mirror));
+            } else {
+                text.push_str(&line[range.clone()]);
+            }
+        }
+        let text_beg = ranges[order[0]].start;
+        let text_end = ranges[order[order.len() - 1]].end;
+        line.replace_range(text_beg..text_end, &text);
+
+        fn permute<T: Copy>(
+            data: &mut [T],
+            beg: usize,
+            shift: CoordType,
+            clusters: &[VisualCluster],
+        ) {
+            let scratch = scratch_arena(None);
+            let mut old = Vec::new_in(&*scratch);
+            old.extend_from_slice(&data[beg..]);
+            let mut x = 0;
+            for c in clusters {
+                let src = (c.x + shift) as usize;
+                let width = c.width as usize;
+                data[beg + x..beg + x + width].copy_from_slice(&old[src..src + width]);
+                x += width;
+            }
+        }
+
+        // The clusters cover `beg..end` without gaps, so `permute` stays inside of it.
+        let row = (y * size.width + beg) as usize;
+        let end = (y * size.width + end) as usize;
+        permute(&mut back.bg_bitmap.data[..end], row, left - beg, clusters);
+        permute(&mut back.fg_bitmap.data[..end], row, left - beg, clusters);
+        permute(&mut back.attributes.data[..end], row, left - beg, clusters);
+    }
+
     /// Draws a scrollbar in the given `track` rectangle.
     ///
     /// Not entirely sure why I put it here instead of elsewhere.
@@ -343,6 +568,12 @@ impl Framebuffer {
         self.indexed_colors[index as usize]
     }
 
+    /// Returns a color from the extended palette, if it's set.
+    #[inline]
+    pub fn named(&self, color: NamedColor) -> Option<StraightRgba> {
+        self.named_colors[color as usize]
+    }
+
     /// Returns a color from the palette.
     ///
     /// To facilitate constant folding by the compiler,
@@ -375,6 +606,20 @@ impl Framebuffer {
         contrast
     }
 
+    /// Highlights the cell at `pos` as a draggable selection handle (used for touch input).
+    pub fn draw_selection_handle(&mut self, clip_rect: Rect, pos: Point) {
+        let target = Rect { left: pos.x, top: pos.y, right: pos.x + 1, bottom: pos.y + 1 };
+        let target = target.intersect(clip_rect);
+        if target.is_empty() {
+            return;
+        }
+
+        let bg = self.indexed(IndexedColor::BrightBlue);
+        let fg = self.contrasted(bg);
+        self.blend_bg(target, bg);
+        self.blend_fg(target, fg);
+    }
+
     /// Blends the given sRGB color onto the background bitmap.
     ///
     /// TODO: The current approach blends foreground/background independently,
@@ -423,6 +668,17 @@ impl Framebuffer {
         back.attributes.replace(target, mask, attr);
     }
 
+    /// Turns the text in the given rectangle into a link to `url`, which terminals
+    /// that support OSC 8 open on click. The others ignore it.
+    pub fn add_link(&mut self, target: Rect, url: &str) {
+        let back = &mut self.buffers[self.frame_counter & 1];
+        let rect = target.intersect(back.size().as_rect());
+        // The terminator of the OSC must not show up in it.
+        if !rect.is_empty() && !url.contains(|c: char| c.is_ascii_control()) {
+            back.links.push(Link { rect, url: url.to_string() });
+        }
+    }
+
     /// Sets the current visible cursor position and type.
     ///
     /// Call this when focus is inside an editable area and you want to show the cursor.
@@ -430,144 +686,243 @@ impl Framebuffer {
         let back = &mut self.buffers[self.frame_counter & 1];
         back.cursor.pos = pos;
         back.cursor.overtype = overtype;
+        back.cursor.blink = self.cursor_blink;
+    }
+
+    /// Sets whether the cursor blinks. Applies from the next [`Framebuffer::set_cursor`] call.
+    pub fn set_cursor_blink(&mut self, blink: bool) {
+        self.cursor_blink = blink;
     }
 
     /// Renders the framebuffer contents accumulated since the
     /// last call to `flip()` and returns them serialized as VT.
+    ///
+    /// With a render thread, the frame is only put aside for [`Framebuffer::present`]
+    /// and the result is empty.
     pub fn render<'a>(&mut self, arena: &'a Arena) -> ArenaString<'a> {
+        let mut result = ArenaString::new_in(arena);
+        self.render_to(&mut result);
+        result
+    }
+
+    /// Like [`Framebuffer::render`], but appends to `output`. Together with
+    /// [`Framebuffer::present`], which clears it, the same `output` can be
+    /// used for every frame and its capacity is reused.
+    pub fn render_into(&mut self, output: &mut String) {
+        self.render_to(output);
+    }
+
+    fn render_to(&mut self, result: &mut impl Write) {
         let idx = self.frame_counter & 1;
-        // Borrows the front/back buffers without letting Rust know that we have a reference to self.
-        // SAFETY: Well this is certainly correct, but whether Rust and its strict rules likes it is another question.
-        let (back, front) = unsafe {
-            let ptr = self.buffers.as_mut_ptr();
-            let back = &mut *ptr.add(idx);
-            let front = &*ptr.add(1 - idx);
-            (back, front)
+        let options = VtOptions {
+            color_mode: self.color_mode,
+            foreground: self.indexed(IndexedColor::Foreground),
+            background: self.indexed(IndexedColor::Background),
         };
 
-        let mut front_lines = front.text.lines.iter(); // hahaha
-        let mut front_bgs = front.bg_bitmap.iter();
-        let mut front_fgs = front.fg_bitmap.iter();
-        let mut front_attrs = front.attributes.iter();
+        if let Some(thread) = &self.render_thread {
+            // This waits, if the render thread is still busy with the frame before the last one.
+            let spare = thread.spares.recv().unwrap_or_default();
+            let buffer = mem::replace(&mut self.buffers[idx], spare);
+            let invalidated = mem::take(&mut self.invalidated);
+            self.pending = Some(Frame { buffer, options, invalidated });
+            return;
+        }
 
-        let mut back_lines = back.text.lines.iter();
-        let mut back_bgs = back.bg_bitmap.iter();
-        let mut back_fgs = back.fg_bitmap.iter();
-        let mut back_attrs = back.attributes.iter();
+        self.invalidated = false;
+        let back = &self.buffers[idx];
+        let front = &self.buffers[1 - idx];
+        serialize(front, back, &options, result);
+    }
 
-        let mut result = ArenaString::new_in(arena);
-        let mut last_bg = u64::MAX;
-        let mut last_fg = u64::MAX;
-        let mut last_attr = Attributes::None;
-
-        for y in 0..front.text.size.height {
-            // SAFETY: The only thing that changes the size of these containers,
-            // is the reset() method and it always resets front/back to the same size.
-            let front_line = unsafe { front_lines.next().unwrap_unchecked() };
-            let front_bg = unsafe { front_bgs.next().unwrap_unchecked() };
-            let front_fg = unsafe { front_fgs.next().unwrap_unchecked() };
-            let front_attr = unsafe { front_attrs.next().unwrap_unchecked() };
-
-            let back_line = unsafe { back_lines.next().unwrap_unchecked() };
-            let back_bg = unsafe { back_bgs.next().unwrap_unchecked() };
-            let back_fg = unsafe { back_fgs.next().unwrap_unchecked() };
-            let back_attr = unsafe { back_attrs.next().unwrap_unchecked() };
-
-            // TODO: Ideally, we should properly diff the contents and so if
-            // only parts of a line change, we should only update those parts.
-            if front_line == back_line
-                && front_bg == back_bg
-                && front_fg == back_fg
-                && front_attr == back_attr
-            {
-                continue;
+    /// Writes the `output` of [`Framebuffer::render_into`] to stdout and clears it.
+    /// With a render thread, the frame that was put aside is handed to it, followed by
+    /// `output`, which is swapped for one that the thread is done with.
+    pub fn present(&mut self, output: &mut String) {
+        match &self.render_thread {
+            Some(thread) => {
+                let spare = thread.tails.try_recv().unwrap_or_default();
+                let tail = mem::replace(output, spare);
+                let job = Job { frame: self.pending.take(), tail };
+                if let Some(jobs) = &thread.jobs {
+                    _ = jobs.send(job);
+                }
             }
+            None => sys::write_stdout(output),
+        }
+        recycle_output(output);
+    }
+}
 
-            let line_bytes = back_line.as_bytes();
-            let mut cfg = MeasurementConfig::new(&line_bytes);
-            let mut chunk_end = 0;
+/// Clears `output` for the next frame. Its capacity is kept, unless it grew
+/// unusually large, e.g. for a clipboard sent via OSC 52.
+fn recycle_output(output: &mut String) {
+    output.clear();
+    output.shrink_to(OUTPUT_CAPACITY);
+}
 
-            if result.is_empty() {
-                result.push_str("\x1b[m");
-            }
-            _ = write!(result, "\x1b[{};1H", y + 1);
+/// What [`serialize`] needs to know about the palette.
+#[derive(Clone, Copy)]
+struct VtOptions {
+    color_mode: ColorMode,
+    foreground: StraightRgba,
+    background: StraightRgba,
+}
+
+/// Appends the VT that turns the `front` buffer on screen into the `back` buffer to `result`.
+fn serialize(front: &Buffer, back: &Buffer, options: &VtOptions, result: &mut impl Write) {
+    let mut front_lines = front.text.lines.iter(); // hahaha
+    let mut front_bgs = front.bg_bitmap.iter();
+    let mut front_fgs = front.fg_bitmap.iter();
+    let mut front_attrs = front.attributes.iter();
+
+    let mut back_lines = back.text.lines.iter();
+    let mut back_bgs = back.bg_bitmap.iter();
+    let mut back_fgs = back.fg_bitmap.iter();
+    let mut back_attrs = back.attributes.iter();
+
+    let mut last_bg = u64::MAX;
+    let mut last_fg = u64::MAX;
+    let mut last_attr = Attributes::None;
+    let mut dirty = false;
+
+    for y in 0..front.text.size.height {
+        // SAFETY: The only thing that changes the size of these containers,
+        // is the reset() method and it always resets front/back to the same size.
+        let front_line = unsafe { front_lines.next().unwrap_unchecked() };
+        let front_bg = unsafe { front_bgs.next().unwrap_unchecked() };
+        let front_fg = unsafe { front_fgs.next().unwrap_unchecked() };
+        let front_attr = unsafe { front_attrs.next().unwrap_unchecked() };
+
+        let back_line = unsafe { back_lines.next().unwrap_unchecked() };
+        let back_bg = unsafe { back_bgs.next().unwrap_unchecked() };
+        let back_fg = unsafe { back_fgs.next().unwrap_unchecked() };
+        let back_attr = unsafe { back_attrs.next().unwrap_unchecked() };
+
+        // TODO: Ideally, we should properly diff the contents and so if
+        // only parts of a line change, we should only update those parts.
+        if front_line == back_line
+            && front_bg == back_bg
+            && front_fg == back_fg
+            && front_attr == back_attr
+            && front.links_on_row(y).eq(back.links_on_row(y))
+        {
+            continue;
+        }
+
+        let line_bytes = back_line.as_bytes();
+        let mut cfg = MeasurementConfig::new(&line_bytes);
+        let mut chunk_end = 0;
+
+        if !dirty {
+            dirty = true;
+            _ = result.write_str("\x1b[m");
+        }
+        _ = write!(result, "\x1b[{};1H", y + 1);
+
+        let mut last_link = None;
 
+        while {
+            let bg = back_bg[chunk_end];
+            let fg = back_fg[chunk_end];
+            let attr = back_attr[chunk_end];
+            let link = back.link_at(chunk_end as CoordType, y);
+
+            // Chunk into runs of the same color.
             while {
-                let bg = back_bg[chunk_end];
-                let fg = back_fg[chunk_end];
-                let attr = back_attr[chunk_end];
+                chunk_end += 1;
+                chunk_end < back_bg.len()
+                    && back_bg[chunk_end] == bg
+                    && back_fg[chunk_end] == fg
+                    && back_attr[chunk_end] == attr
+                    && back.link_at(chunk_end as CoordType, y) == link
+            } {}
 
-                // Chunk into runs of the same color.
-                while {
-                    chunk_end += 1;
-                    chunk_end < back_bg.len()
-                        && back_bg[chunk_end] == bg
-                        && back_fg[chunk_end] == fg
-                        && back_attr[chunk_end] == attr
-                } {}
+            if last_link != link {
+                last_link = link;
+                let url = link.map_or("", |i| back.links[i].url.as_str());
+                _ = write!(result, "\x1b]8;;{url}\x1b\\");
+            }
 
-                if last_bg != bg.to_ne() as u64 {
-                    last_bg = bg.to_ne() as u64;
-                    self.format_color(&mut result, false, bg);
-                }
+            if last_bg != bg.to_ne() as u64 {
+                last_bg = bg.to_ne() as u64;
+                options.format_color(result, false, bg);
+            }
 
-                if last_fg != fg.to_ne() as u64 {
-                    last_fg = fg.to_ne() as u64;
-                    self.format_color(&mut result, true, fg);
-                }
+            if last_fg != fg.to_ne() as u64 {
+                last_fg = fg.to_ne() as u64;
+                options.format_color(result, true, fg);
+            }
 
-                if last_attr != attr {
-                    let diff = last_attr ^ attr;
-                    if diff.is(Attributes::Italic) {
-                        if attr.is(Attributes::Italic) {
-                            result.push_str("\x1b[3m");
-                        } else {
-                            result.push_str("\x1b[23m");
-                        }
+            if last_attr != attr {
+                let diff = last_attr ^ attr;
+                if diff.is(Attributes::Italic) {
+                    if attr.is(Attributes::Italic) {
+                        _ = result.write_str("\x1b[3m");
+                    } else {
+                        _ = result.write_str("\x1b[23m");
                     }
-                    if diff.is(Attributes::Underlined) {
-                        if attr.is(Attributes::Underlined) {
-                            result.push_str("\x1b[4m");
-                        } else {
-                            result.push_str("\x1b[24m");
-                        }
+                }
+                if diff.is(Attributes::Underlined) {
+                    if attr.is(Attributes::Underlined) {
+                        _ = result.write_str("\x1b[4m");
+                    } else {
+                        _ = result.write_str("\x1b[24m");
                     }
-                    last_attr = attr;
                 }
+                if diff.is(Attributes::Bold) {
+                    if attr.is(Attributes::Bold) {
+                        _ = result.write_str("\x1b[1m");
+                    } else {
+                        _ = result.write_str("\x1b[22m");
+                    }
+                }
+                last_attr = attr;
+            }
 
-                let beg = cfg.cursor().offset;
-                let end = cfg.goto_visual(Point { x: chunk_end as CoordType, y: 0 }).offset;
-                result.push_str(&back_line[beg..end]);
+            let beg = cfg.cursor().offset;
+            let end = cfg.goto_visual(Point { x: chunk_end as CoordType, y: 0 }).offset;
+            _ = result.write_str(&back_line[beg..end]);
 
-                chunk_end < back_bg.len()
-            } {}
-        }
+            chunk_end < back_bg.len()
+        } {}
 
-        // If the cursor has changed since the last frame we naturally need to update it,
-        // but this also applies if the code above wrote to the screen,
-        // as it uses CUP sequences to reposition the cursor for writing.
-        if !result.is_empty() || back.cursor != front.cursor {
-            if back.cursor.pos.x >= 0 && back.cursor.pos.y >= 0 {
-                // CUP to the cursor position.
-                // DECSCUSR to set the cursor style.
-                // DECTCEM to show the cursor.
-                _ = write!(
-                    result,
-                    "\x1b[{};{}H\x1b[{} q\x1b[?25h",
-                    back.cursor.pos.y + 1,
-                    back.cursor.pos.x + 1,
-                    if back.cursor.overtype { 1 } else { 5 }
-                );
-            } else {
-                // DECTCEM to hide the cursor.
-                result.push_str("\x1b[?25l");
-            }
+        // Links end with the row, as the next one is written after a CUP.
+        if last_link.is_some() {
+            _ = result.write_str("\x1b]8;;\x1b\\");
         }
+    }
 
-        result
+    // If the cursor has changed since the last frame we naturally need to update it,
+    // but this also applies if the code above wrote to the screen,
+    // as it uses CUP sequences to reposition the cursor for writing.
+    if dirty || back.cursor != front.cursor {
+        if back.cursor.pos.x >= 0 && back.cursor.pos.y >= 0 {
+            // CUP to the cursor position.
+            // DECSCUSR to set the cursor style.
+            // DECTCEM to show the cursor.
+            _ = write!(
+                result,
+                "\x1b[{};{}H\x1b[{} q\x1b[?25h",
+                back.cursor.pos.y + 1,
+                back.cursor.pos.x + 1,
+                // Blinking or steady block/bar respectively.
+                match (back.cursor.overtype, back.cursor.blink) {
+                    (true, true) => 1,
+                    (true, false) => 2,
+                    (false, true) => 5,
+                    (false, false) => 6,
+                }
+            );
+        } else {
+            // DECTCEM to hide the cursor.
+            _ = result.write_str("\x1b[?25l");
+        }
     }
+}
 
-    fn format_color(&self, dst: &mut ArenaString, fg: bool, mut color: StraightRgba) {
+impl VtOptions {
+    fn format_color(&self, dst: &mut impl Write, fg: bool, mut color: StraightRgba) {
         let typ = if fg { '3' } else { '4' };
 
         // Some terminals support transparent backgrounds which are used
@@ -589,8 +944,7 @@ impl Framebuffer {
         }
 
         if color.alpha() != 0xff {
-            let idx = if fg { IndexedColor::Foreground } else { IndexedColor::Background };
-            let dst = self.indexed(idx);
+            let dst = if fg { self.foreground } else { self.background };
             color = dst.oklab_blend(color);
         }
 
@@ -598,7 +952,7 @@ impl Framebuffer {
         let g = color.green();
         let b = color.blue();
 
-        if self.disable_true_color {
+        if self.color_mode == ColorMode::Palette256 {
             // Use 256-color approximation instead of true color.
             // The 256-color palette consists of:
             // - 0-15: Standard colors (already matched by indexed_colors if used)
@@ -616,17 +970,145 @@ impl Framebuffer {
     }
 }
 
+/// A frame on its way to the render thread.
+struct Frame {
+    buffer: Buffer,
+    options: VtOptions,
+    invalidated: bool,
+}
+
+/// What the render thread writes: The frame, if there's a new one, and what follows it.
+struct Job {
+    frame: Option<Frame>,
+    tail: String,
+}
+
+/// The thread behind [`Framebuffer::spawn_render_thread`].
+///
+/// It keeps the buffer that's on screen, while the main thread draws into the other one.
+/// Each frame's buffer is sent over and the one it replaces on screen is sent back,
+/// to be drawn into next.
+struct RenderThread {
+    jobs: Option<Sender<Job>>,
+    spares: Receiver<Buffer>,
+    /// The [`Job::tail`]s that were written, for [`Framebuffer::present`] to reuse.
+    tails: Receiver<String>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl RenderThread {
+    fn spawn() -> Self {
+        let (jobs, job_receiver) = mpsc::channel::<Job>();
+        let (spare_sender, spares) = mpsc::channel();
+        let (tail_sender, tails) = mpsc::channel();
+
+        let handle = thread::spawn(move || {
+            let mut presenter = Presenter::default();
+            _ = spare_sender.send(Buffer::default());
+
+            for mut job in job_receiver {
+                let spare = presenter.step(&mut job);
+                sys::write_stdout(&presenter.output);
+                if let Some(spare) = spare {
+                    _ = spare_sender.send(spare);
+                }
+                recycle_output(&mut job.tail);
+                _ = tail_sender.send(job.tail);
+            }
+        });
+
+        Self { jobs: Some(jobs), spares, tails, handle: Some(handle) }
+    }
+}
+
+impl Drop for RenderThread {
+    fn drop(&mut self) {
+        // Closing the channel ends the thread once it wrote everything that's queued.
+        // That has to happen before the caller restores the terminal modes.
+        drop(self.jobs.take());
+        if let Some(handle) = self.handle.take() {
+            _ = handle.join();
+        }
+    }
+}
+
+/// The state of the render thread: What's on screen and the VT it wrote last.
 #[derive(Default)]
+struct Presenter {
+    front: Buffer,
+    output: String,
+}
+
+impl Presenter {
+    /// Serializes the `job` into `output`. Returns the buffer that's no longer on screen.
+    fn step(&mut self, job: &mut Job) -> Option<Buffer> {
+        recycle_output(&mut self.output);
+
+        let spare = job.frame.take().map(|frame| {
+            let size = frame.buffer.size();
+            if self.front.size() != size {
+                self.front = Buffer::new(size);
+                self.front.invalidate();
+                self.front.cursor = Cursor::new_invalid();
+            } else if frame.invalidated {
+                self.front.invalidate();
+            }
+
+            serialize(&self.front, &frame.buffer, &frame.options, &mut self.output);
+            mem::replace(&mut self.front, frame.buffer)
+        });
+
+        self.output.push_str(&job.tail);
+        spare
+    }
+}
+
+#[derive(Clone, Default)]
 struct Buffer {
     text: LineBuffer,
     bg_bitmap: Bitmap,
     fg_bitmap: Bitmap,
     attributes: AttributeBuffer,
+    /// The hyperlinks, in the order they were added. See [`Framebuffer::add_link`].
+    links: Vec<Link>,
     cursor: Cursor,
 }
 
+impl Buffer {
+    fn new(size: Size) -> Self {
+        Self {
+            text: LineBuffer::new(size),
+            bg_bitmap: Bitmap::new(size),
+            fg_bitmap: Bitmap::new(size),
+            attributes: AttributeBuffer::new(size),
+            links: Vec::new(),
+            cursor: Cursor::default(),
+        }
+    }
+
+    /// The index of the link at `x`/`y`. The last one added wins if they overlap.
+    fn link_at(&self, x: CoordType, y: CoordType) -> Option<usize> {
+        self.links.iter().rposition(|link| link.rect.contains(Point { x, y }))
+    }
+
+    /// The links that cover a part of row `y`, for diffing it.
+    fn links_on_row(&self, y: CoordType) -> impl Iterator<Item = &Link> {
+        self.links.iter().filter(move |link| link.rect.top <= y && y < link.rect.bottom)
+    }
+
+    fn size(&self) -> Size {
+        self.bg_bitmap.size
+    }
+
+    /// Makes it differ from any other frame, so that the next one is drawn in full.
+    fn invalidate(&mut self) {
+        // Trigger a full redraw. (Yes, it's a hack.)
+        self.fg_bitmap.fill(StraightRgba::from_le(1));
+    }
+}
+
 /// A buffer for the text contents of the framebuffer.
-#[derive(Default)]
+#[derive(Clone, Default)]
 struct LineBuffer {
     lines: Vec<String>,
     size: Size,
@@ -773,7 +1255,7 @@ impl LineBuffer {
 }
 
 /// An sRGB bitmap.
-#[derive(Default)]
+#[derive(Clone, Default)]
 struct Bitmap {
     data: Vec<StraightRgba>,
     size: Size,
@@ -857,7 +1339,8 @@ impl Attributes {
     pub const None: Self = Self(0);
     pub const Italic: Self = Self(0b1);
     pub const Underlined: Self = Self(0b10);
-    pub const All: Self = Self(0b11);
+    pub const Bold: Self = Self(0b100);
+    pub const All: Self = Self(0b111);
 
     pub const fn is(self, attr: Self) -> bool {
         (self.0 & attr.0) == attr.0
@@ -883,7 +1366,7 @@ impl BitXor for Attributes {
 }
 
 /// Stores VT attributes for the framebuffer.
-#[derive(Default)]
+#[derive(Clone, Default)]
 struct AttributeBuffer {
     data: Vec<Attributes>,
     size: Size,
@@ -931,19 +1414,131 @@ impl AttributeBuffer {
     }
 }
 
+/// A hyperlink, written as OSC 8 around the text in `rect`.
+#[derive(Clone, PartialEq, Eq)]
+struct Link {
+    rect: Rect,
+    url: String,
+}
+
 /// Stores cursor position and type for the framebuffer.
-#[derive(Default, PartialEq, Eq)]
+#[derive(Clone, Default, PartialEq, Eq)]
 struct Cursor {
     pos: Point,
     overtype: bool,
+    blink: bool,
 }
 
 impl Cursor {
     const fn new_invalid() -> Self {
-        Self { pos: Point::MIN, overtype: false }
+        Self { pos: Point::MIN, overtype: false, blink: false }
     }
 
     const fn new_disabled() -> Self {
-        Self { pos: Point { x: -1, y: -1 }, overtype: false }
+        Self { pos: Point { x: -1, y: -1 }, overtype: false, blink: false }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_set_indexed_color() {
+        let mut fb = Framebuffer::new();
+        let white = StraightRgba::from_be(0xffffffff);
+        let navy = StraightRgba::from_be(0x202040ff);
+
+        assert!(fb.contrasted(white) == DEFAULT_THEME[IndexedColor::Black as usize]);
+
+        // The cached contrast must not outlive the palette it came from.
+        fb.set_indexed_color(IndexedColor::Black, navy);
+        assert!(fb.indexed(IndexedColor::Black) == navy);
+        assert!(fb.contrasted(white) == navy);
+    }
+
+    #[test]
+    fn test_presenter() {
+        let arena = Arena::new(64 * 1024).unwrap();
+        let mut fb = Framebuffer::new();
+        let mut presenter = Presenter::default();
+        let small = Size { width: 10, height: 2 };
+        let large = Size { width: 12, height: 3 };
+
+        // The render thread must write the same as `render()` does without it.
+        for (size, text) in [(small, "hello"), (small, "hello"), (small, "world"), (large, "world")]
+        {
+            fb.flip(size);
+            fb.replace_text(1, 2, size.width, text);
+            fb.set_cursor(Point { x: 3, y: 1 }, false);
+            let expected = fb.render(&arena);
+
+            let frame = Frame {
+                buffer: fb.buffers[fb.frame_counter & 1].clone(),
+                options: VtOptions {
+                    color_mode: fb.color_mode,
+                    foreground: fb.indexed(IndexedColor::Foreground),
+                    background: fb.indexed(IndexedColor::Background),
+                },
+                invalidated: false,
+            };
+            presenter.step(&mut Job { frame: Some(frame), tail: "tail".to_string() });
+            assert_eq!(presenter.output, format!("{expected}tail"));
+        }
+    }
+
+    #[test]
+    fn test_replace_text_tabs() {
+        stdext::arena::init(16 * MEBI).unwrap();
+
+        let mut fb = Framebuffer::new();
+        fb.flip(Size { width: 16, height: 1 });
+        fb.set_tab_size(4);
+        fb.replace_text(0, 1, 16, "a\tb\t\tc");
+        assert_eq!(fb.buffers[fb.frame_counter & 1].text.lines[0], " a   b       c  ");
+    }
+
+    #[test]
+    fn test_reorder_row() {
+        stdext::arena::init(16 * MEBI).unwrap();
+        let scratch = scratch_arena(None);
+
+        let mut fb = Framebuffer::new();
+        fb.flip(Size { width: 10, height: 1 });
+        let text = "ab (אבג)";
+        fb.replace_text(0, 1, 10, text);
+        let red = StraightRgba::from_be(0xff0000ff);
+        fb.blend_bg(Rect { left: 5, top: 0, right: 6, bottom: 1 }, red);
+
+        let bytes = text.as_bytes();
+        let cfg = MeasurementConfig::new(&bytes);
+        let clusters = bidi::reorder(&scratch, bytes, cfg, Default::default(), true);
+        fb.reorder_row(0, 1, &clusters);
+
+        let back = &fb.buffers[fb.frame_counter & 1];
+        assert_eq!(back.text.lines[0], " (גבא) ab ");
+        // The "א" moved from column 5 to 4 and took its color along.
+        assert!(back.bg_bitmap.data[4] == red);
+        assert!(back.bg_bitmap.data[5] != red);
+    }
+
+    #[test]
+    fn test_links() {
+        let arena = Arena::new(64 * 1024).unwrap();
+        let mut fb = Framebuffer::new();
+        let size = Size { width: 10, height: 1 };
+        let draw = |fb: &mut Framebuffer, url: &str| {
+            fb.flip(size);
+            fb.replace_text(0, 0, size.width, "see here");
+            fb.add_link(Rect { left: 4, top: 0, right: 8, bottom: 1 }, url);
+            fb.render(&arena).to_string()
+        };
+
+        let output = draw(&mut fb, "https://a.b");
+        assert!(output.contains("see \x1b]8;;https://a.b\x1b\\here\x1b]8;;\x1b\\"));
+        // The row is drawn again if only the link changed.
+        let output = draw(&mut fb, "https://c.d");
+        assert!(output.contains("\x1b]8;;https://c.d\x1b\\here"));
+        assert!(!draw(&mut fb, "https://c.d").contains("here"));
     }
 }
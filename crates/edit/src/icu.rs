@@ -11,7 +11,7 @@ use std::ops::Range;
 use stdext::arena::{Arena, ArenaString};
 
 use crate::buffer::TextBuffer;
-use crate::apperr;
+use crate::{apperr, simd};
 
 #[derive(Clone, Copy)]
 pub struct Encoding {
@@ -28,10 +28,16 @@ static ENCODINGS: Encodings = Encodings {
     preferred: &[
         Encoding { label: "UTF-8", canonical: "UTF-8" },
         Encoding { label: "UTF-8 BOM", canonical: "UTF-8 BOM" },
+        Encoding { label: "UTF-16LE", canonical: "UTF-16LE" },
+        Encoding { label: "UTF-16BE", canonical: "UTF-16BE" },
     ],
     all: &[
         Encoding { label: "UTF-8", canonical: "UTF-8" },
         Encoding { label: "UTF-8 BOM", canonical: "UTF-8 BOM" },
+        Encoding { label: "UTF-16LE", canonical: "UTF-16LE" },
+        Encoding { label: "UTF-16BE", canonical: "UTF-16BE" },
+        Encoding { label: "UTF-32LE", canonical: "UTF-32LE" },
+        Encoding { label: "UTF-32BE", canonical: "UTF-32BE" },
     ],
 };
 
@@ -47,7 +53,107 @@ pub fn init() -> apperr::Result<()> {
     Ok(())
 }
 
+/// The Unicode encodings [`Converter`] translates between.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Unicode {
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+    Utf32Le,
+    Utf32Be,
+}
+
+impl Unicode {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "UTF-8" | "UTF-8 BOM" => Some(Self::Utf8),
+            "UTF-16LE" => Some(Self::Utf16Le),
+            "UTF-16BE" => Some(Self::Utf16Be),
+            "UTF-32LE" => Some(Self::Utf32Le),
+            "UTF-32BE" => Some(Self::Utf32Be),
+            _ => None,
+        }
+    }
+
+    /// Decodes the char at the start of `input` and returns it with its length in bytes,
+    /// or `None` if `input` ends in the middle of it. Invalid input becomes U+FFFD.
+    fn decode(self, input: &[u8]) -> Option<(char, usize)> {
+        match self {
+            Self::Utf8 => {
+                let len = match *input.first()? {
+                    0x00..=0x7f => 1,
+                    0xc2..=0xdf => 2,
+                    0xe0..=0xef => 3,
+                    0xf0..=0xf4 => 4,
+                    _ => return Some((char::REPLACEMENT_CHARACTER, 1)),
+                };
+                if input.len() < len && input[1..].iter().all(|&b| b & 0xc0 == 0x80) {
+                    return None;
+                }
+                match input.get(..len).map(str::from_utf8) {
+                    Some(Ok(s)) => Some((s.chars().next().unwrap(), len)),
+                    _ => Some((char::REPLACEMENT_CHARACTER, 1)),
+                }
+            }
+            Self::Utf16Le | Self::Utf16Be => {
+                let unit = |i: usize| {
+                    let b = input.get(i..i + 2)?;
+                    let b = [b[0], b[1]];
+                    Some(match self {
+                        Self::Utf16Le => u16::from_le_bytes(b),
+                        _ => u16::from_be_bytes(b),
+                    })
+                };
+                let hi = unit(0)? as u32;
+                if !(0xd800..0xdc00).contains(&hi) {
+                    return Some((char::from_u32(hi).unwrap_or(char::REPLACEMENT_CHARACTER), 2));
+                }
+                let lo = unit(2)? as u32;
+                if !(0xdc00..0xe000).contains(&lo) {
+                    return Some((char::REPLACEMENT_CHARACTER, 2));
+                }
+                let c = 0x10000 + ((hi - 0xd800) << 10) + (lo - 0xdc00);
+                Some((char::from_u32(c).unwrap_or(char::REPLACEMENT_CHARACTER), 4))
+            }
+            Self::Utf32Le | Self::Utf32Be => {
+                let b: [u8; 4] = input.get(..4)?.try_into().unwrap();
+                let c = match self {
+                    Self::Utf32Le => u32::from_le_bytes(b),
+                    _ => u32::from_be_bytes(b),
+                };
+                Some((char::from_u32(c).unwrap_or(char::REPLACEMENT_CHARACTER), 4))
+            }
+        }
+    }
+
+    /// Encodes `c` into `out` and returns how many bytes it took up.
+    fn encode(self, c: char, out: &mut [u8; 4]) -> usize {
+        match self {
+            Self::Utf8 => c.encode_utf8(out).len(),
+            Self::Utf16Le | Self::Utf16Be => {
+                let mut units = [0; 2];
+                let units = c.encode_utf16(&mut units);
+                for (i, u) in units.iter().enumerate() {
+                    let b = if self == Self::Utf16Le { u.to_le_bytes() } else { u.to_be_bytes() };
+                    out[i * 2..i * 2 + 2].copy_from_slice(&b);
+                }
+                units.len() * 2
+            }
+            Self::Utf32Le => {
+                *out = (c as u32).to_le_bytes();
+                4
+            }
+            Self::Utf32Be => {
+                *out = (c as u32).to_be_bytes();
+                4
+            }
+        }
+    }
+}
+
 pub struct Converter<'pivot> {
+    source: Unicode,
+    target: Unicode,
     _marker: std::marker::PhantomData<&'pivot mut [MaybeUninit<u16>]>,
 }
 
@@ -57,24 +163,62 @@ impl<'pivot> Converter<'pivot> {
         source_encoding: &str,
         target_encoding: &str,
     ) -> apperr::Result<Self> {
-        if (source_encoding == "UTF-8" || source_encoding == "UTF-8 BOM") &&
-           (target_encoding == "UTF-8" || target_encoding == "UTF-8 BOM") {
-            Ok(Self { _marker: std::marker::PhantomData })
-        } else {
-            Err(apperr::Error::new_icu(16))
+        match (Unicode::from_name(source_encoding), Unicode::from_name(target_encoding)) {
+            (Some(source), Some(target)) => {
+                Ok(Self { source, target, _marker: std::marker::PhantomData })
+            }
+            _ => Err(apperr::Error::new_icu(16)),
         }
     }
 
+    /// Converts as much of `input` as fits into `output` and returns how many bytes
+    /// of each were used. A char cut off at the end of `input` is left for the next call.
     pub fn convert(
         &mut self,
         input: &[u8],
         output: &mut [MaybeUninit<u8>],
     ) -> apperr::Result<(usize, usize)> {
-        let len = input.len().min(output.len());
-        unsafe {
-            std::ptr::copy_nonoverlapping(input.as_ptr(), output.as_mut_ptr() as *mut u8, len);
+        if self.source == self.target {
+            let len = input.len().min(output.len());
+            unsafe {
+                std::ptr::copy_nonoverlapping(input.as_ptr(), output.as_mut_ptr() as *mut u8, len);
+            }
+            return Ok((len, len));
+        }
+
+        let mut input_advance = 0;
+        let mut output_advance = 0;
+        let mut buf = [0; 4];
+        while let Some((c, len)) = self.source.decode(&input[input_advance..]) {
+            let n = self.target.encode(c, &mut buf);
+            let Some(dst) = output.get_mut(output_advance..output_advance + n) else {
+                break;
+            };
+            for (d, &b) in dst.iter_mut().zip(&buf[..n]) {
+                d.write(b);
+            }
+            input_advance += len;
+            output_advance += n;
         }
-        Ok((len, len))
+        Ok((input_advance, output_advance))
+    }
+
+    /// Finishes the conversion once the input ended. If [`Converter::convert`] left a cut off
+    /// char over, it can't be completed anymore and becomes U+FFFD, so that it doesn't vanish
+    /// without a trace. Returns how many bytes of `output` were used.
+    pub fn flush(&mut self, input: &[u8], output: &mut [MaybeUninit<u8>]) -> usize {
+        if input.is_empty() {
+            return 0;
+        }
+        let mut buf = [0; 4];
+        let n = self.target.encode(char::REPLACEMENT_CHARACTER, &mut buf);
+        let Some(dst) = output.get_mut(..n) else {
+            return 0;
+        };
+        for (d, &b) in dst.iter_mut().zip(&buf[..n]) {
+            d.write(b);
+        }
+        n
     }
 }
 
@@ -87,6 +231,16 @@ pub fn fold_case<'a>(arena: &'a Arena, input: &str) -> ArenaString<'a> {
     ArenaString::from_str(arena, &folded)
 }
 
+/// Full Unicode uppercase mapping, e.g. "ß" becomes "SS".
+pub fn to_upper(input: &str) -> String {
+    input.to_uppercase()
+}
+
+/// Full Unicode lowercase mapping, including the final form of the Greek sigma.
+pub fn to_lower(input: &str) -> String {
+    input.to_lowercase()
+}
+
 // -----------------------------------------------------------------------------------------
 // Regex and Text implementation (Shared Logic)
 // -----------------------------------------------------------------------------------------
@@ -101,17 +255,27 @@ impl Drop for Text {
 }
 
 impl Text {
+    /// Creates a snapshot of the given text buffer's contents.
+    ///
+    /// # Safety
+    ///
+    /// The caller must ensure that `tb` outlives the returned instance.
     pub unsafe fn new(tb: &TextBuffer) -> apperr::Result<Self> {
         let mut t = Self { 
             content: String::new(), 
             tb_ptr: tb as *const _ 
         };
-        t.refresh();
+        unsafe { t.refresh() };
         Ok(t)
     }
 
+    /// Re-reads the contents of the text buffer.
+    ///
+    /// # Safety
+    ///
+    /// The text buffer passed to [`Text::new()`] must still be alive.
     pub unsafe fn refresh(&mut self) {
-        let tb = &*self.tb_ptr;
+        let tb = unsafe { &*self.tb_ptr };
         self.content.clear();
         self.content.reserve(tb.text_length());
         
@@ -121,7 +285,10 @@ impl Text {
             if chunk.is_empty() {
                 break;
             }
-            self.content.push_str(&String::from_utf8_lossy(chunk));
+            // Almost all of it is valid and copied as is. The rest gets replacement characters.
+            let valid = simd::utf8_valid_up_to(chunk);
+            self.content.push_str(unsafe { str::from_utf8_unchecked(&chunk[..valid]) });
+            self.content.push_str(&String::from_utf8_lossy(&chunk[valid..]));
             offset += chunk.len();
         }
     }
@@ -144,7 +311,21 @@ impl Regex {
     pub const MULTILINE: i32 = 2;
     pub const LITERAL: i32 = 4;
 
+    /// Compiles `pattern` and binds it to a snapshot of `text`.
+    ///
+    /// # Safety
+    ///
+    /// Kept `unsafe` for parity with the ICU bindings this replaces.
     pub unsafe fn new(pattern: &str, flags: i32, text: &Text) -> apperr::Result<Self> {
+        Ok(Self {
+            inner: Self::build(pattern, flags)?,
+            text: text.content.clone(),
+            last_idx: 0,
+            captures: None,
+        })
+    }
+
+    fn build(pattern: &str, flags: i32) -> apperr::Result<regex::Regex> {
         let pattern_string;
         let final_pattern = if (flags & Self::LITERAL) != 0 {
             pattern_string = regex::escape(pattern);
@@ -162,19 +343,16 @@ impl Regex {
             builder.multi_line(true);
         }
         
-        match builder.build() {
-            Ok(inner) => Ok(Self {
-                inner,
-                text: text.content.clone(),
-                last_idx: 0,
-                captures: None,
-            }),
-            Err(_) => Err(apperr::Error::new_icu(1)),
-        }
+        builder.build().map_err(|_| apperr::Error::new_icu(1))
     }
 
+    /// Refreshes `text` and rebinds the regex to it.
+    ///
+    /// # Safety
+    ///
+    /// See [`Text::refresh()`].
     pub unsafe fn set_text(&mut self, text: &mut Text, offset: usize) {
-        text.refresh();
+        unsafe { text.refresh() };
         self.text = text.content.clone();
         self.reset(offset);
     }
@@ -238,6 +416,22 @@ impl Iterator for Regex {
     }
 }
 
+/// A compiled [`Regex`] that only tells whether a text contains a match.
+/// It isn't bound to a [`TextBuffer`], so it can be shared between threads.
+#[cfg(feature = "regex")]
+pub struct Matcher(regex::Regex);
+
+#[cfg(feature = "regex")]
+impl Matcher {
+    pub fn new(pattern: &str, flags: i32) -> apperr::Result<Self> {
+        Regex::build(pattern, flags).map(Self)
+    }
+
+    pub fn is_match(&self, text: &str) -> bool {
+        self.0.is_match(text)
+    }
+}
+
 // -----------------------------------------------------------------------------------------
 // Implementation 2: LITE MODE (Using std string search)
 // -----------------------------------------------------------------------------------------
@@ -256,7 +450,16 @@ impl Regex {
     pub const MULTILINE: i32 = 2; // Ignored in lite
     pub const LITERAL: i32 = 4;   // Always literal in lite
 
+    /// Compiles `pattern` and binds it to a snapshot of `text`.
+    ///
+    /// # Safety
+    ///
+    /// Kept `unsafe` for parity with the ICU bindings this replaces.
     pub unsafe fn new(pattern: &str, flags: i32, text: &Text) -> apperr::Result<Self> {
+        Ok(Self::with_text(pattern, flags, text.content.clone()))
+    }
+
+    fn with_text(pattern: &str, flags: i32, text: String) -> Self {
         let mut p = pattern;
         let mut whole_word = false;
 
@@ -267,17 +470,22 @@ impl Regex {
              whole_word = true;
         }
 
-        Ok(Self {
+        Self {
             pattern: p.to_string(),
-            text: text.content.clone(),
+            text,
             last_idx: 0,
             case_insensitive: (flags & Self::CASE_INSENSITIVE) != 0,
             whole_word,
-        })
+        }
     }
 
+    /// Refreshes `text` and rebinds the regex to it.
+    ///
+    /// # Safety
+    ///
+    /// See [`Text::refresh()`].
     pub unsafe fn set_text(&mut self, text: &mut Text, offset: usize) {
-        text.refresh();
+        unsafe { text.refresh() };
         self.text = text.content.clone();
         self.reset(offset);
     }
@@ -316,8 +524,27 @@ impl Iterator for Regex {
                 return Some(self.last_idx..self.last_idx);
             }
 
-            // 2. Scan text
-            for (offset, _) in slice.char_indices() {
+            // 2. Scan text. Only where the first character may match, which for ASCII is found
+            // with `memchr2`. Non-ASCII is always checked, since it may lowercase to ASCII, like "K" (Kelvin).
+            let bytes = slice.as_bytes();
+            let first = pat_lower[0];
+            let mut next_first = 0;
+            let mut next = 0;
+
+            while next < bytes.len() {
+                if first.is_ascii() {
+                    if next_first < next {
+                        let b = first as u8;
+                        next_first = simd::memchr2(b, b.to_ascii_uppercase(), bytes, next);
+                    }
+                    next = next_first.min(simd::ascii_prefix(bytes, next));
+                    if next >= bytes.len() {
+                        break;
+                    }
+                }
+                let offset = next;
+                next += slice[offset..].chars().next().map_or(1, char::len_utf8);
+
                 let mut sub_iter = slice[offset..].chars();
                 let mut pat_iter = pat_lower.iter();
                 let mut current_match_len = 0;
@@ -404,3 +631,76 @@ impl Iterator for Regex {
         }
     }
 }
+
+/// A [`Regex`] that only tells whether a text contains a match.
+/// It isn't bound to a [`TextBuffer`], so it can be shared between threads.
+#[cfg(not(feature = "regex"))]
+pub struct Matcher {
+    pattern: String,
+    flags: i32,
+}
+
+#[cfg(not(feature = "regex"))]
+impl Matcher {
+    pub fn new(pattern: &str, flags: i32) -> apperr::Result<Self> {
+        Ok(Self { pattern: pattern.to_string(), flags })
+    }
+
+    pub fn is_match(&self, text: &str) -> bool {
+        Regex::with_text(&self.pattern, self.flags, text.to_string()).next().is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn convert(source: &str, target: &str, input: &[u8], output_len: usize) -> (usize, Vec<u8>) {
+        let mut pivot = [];
+        let mut conv = Converter::new(&mut pivot, source, target).unwrap();
+        let mut output = vec![MaybeUninit::uninit(); output_len];
+        let (input_advance, output_advance) = conv.convert(input, &mut output).unwrap();
+        let output = output[..output_advance].iter().map(|b| unsafe { b.assume_init() }).collect();
+        (input_advance, output)
+    }
+
+    #[test]
+    fn test_convert_utf16_utf32() {
+        let text = "a\u{e9}\u{1f600}";
+        let utf16le: Vec<u8> = text.encode_utf16().flat_map(u16::to_le_bytes).collect();
+        let utf32be: Vec<u8> = text.chars().flat_map(|c| (c as u32).to_be_bytes()).collect();
+
+        assert_eq!(convert("UTF-16LE", "UTF-8", &utf16le, 64), (8, text.as_bytes().to_vec()));
+        assert_eq!(convert("UTF-8", "UTF-16LE", text.as_bytes(), 64), (7, utf16le.clone()));
+        assert_eq!(convert("UTF-32BE", "UTF-8", &utf32be, 64), (12, text.as_bytes().to_vec()));
+        assert_eq!(convert("UTF-8", "UTF-32BE", text.as_bytes(), 64), (7, utf32be));
+
+        // A char cut off by the end of the input or output is left for later.
+        assert_eq!(convert("UTF-16LE", "UTF-8", &utf16le[..6], 64), (4, "a\u{e9}".into()));
+        assert_eq!(convert("UTF-8", "UTF-16LE", text.as_bytes(), 7), (3, utf16le[..4].to_vec()));
+        let cut = &text.as_bytes()[..5];
+        assert_eq!(convert("UTF-8", "UTF-16BE", cut, 64), (3, vec![0, b'a', 0, 0xe9]));
+
+        // Lone surrogates and invalid UTF-8 turn into U+FFFD.
+        let lone = [0xdc, 0x00, 0, b'b'];
+        assert_eq!(convert("UTF-16BE", "UTF-8", &lone, 64), (4, "\u{fffd}b".into()));
+        assert_eq!(convert("UTF-8", "UTF-16BE", b"\xffb", 64), (2, vec![0xff, 0xfd, 0, b'b']));
+        assert!(Converter::new(&mut [], "UTF-8", "Shift_JIS").is_err());
+    }
+
+    #[test]
+    fn test_flush() {
+        let mut pivot = [];
+        let mut output = [MaybeUninit::uninit(); 4];
+        let mut flush = |source: &str, target: &str, input: &[u8]| {
+            let mut conv = Converter::new(&mut pivot, source, target).unwrap();
+            let n = conv.flush(input, &mut output);
+            output[..n].iter().map(|b| unsafe { b.assume_init() }).collect::<Vec<u8>>()
+        };
+
+        assert_eq!(flush("UTF-16LE", "UTF-8", b""), b"");
+        assert_eq!(flush("UTF-16LE", "UTF-8", b"a"), "\u{fffd}".as_bytes());
+        assert_eq!(flush("UTF-32BE", "UTF-8", b"\0\0\0"), "\u{fffd}".as_bytes());
+        assert_eq!(flush("UTF-8", "UTF-16BE", b"\xe2\x82"), [0xff, 0xfd]);
+    }
+}
@@ -9,7 +9,7 @@
 use std::mem;
 
 use crate::helpers::{CoordType, Point, Size};
-use crate::vt;
+use crate::{base64, vt};
 
 /// Represents a key/modifier combination.
 ///
@@ -26,7 +26,7 @@ impl InputKey {
         Self(v)
     }
 
-    pub(crate) const fn from_ascii(ch: char) -> Option<Self> {
+    pub const fn from_ascii(ch: char) -> Option<Self> {
         if ch == ' ' || (ch >= '0' && ch <= '9') {
             Some(Self(ch as u32))
         } else if ch >= 'a' && ch <= 'z' {
@@ -42,7 +42,7 @@ impl InputKey {
         self.0
     }
 
-    pub(crate) const fn key(&self) -> Self {
+    pub const fn key(&self) -> Self {
         Self(self.0 & 0x00FFFFFF)
     }
 
@@ -54,7 +54,7 @@ impl InputKey {
         (self.0 & modifier.0) != 0
     }
 
-    pub(crate) const fn with_modifiers(&self, modifiers: InputKeyMod) -> Self {
+    pub const fn with_modifiers(&self, modifiers: InputKeyMod) -> Self {
         Self(self.0 | modifiers.0)
     }
 }
@@ -160,6 +160,8 @@ pub mod vk {
     pub const X: InputKey = InputKey::new('X' as u32);
     pub const Y: InputKey = InputKey::new('Y' as u32);
     pub const Z: InputKey = InputKey::new('Z' as u32);
+    /// The Menu key, next to the right Windows key.
+    pub const APPS: InputKey = InputKey::new(0x5D);
 
     pub const NUMPAD0: InputKey = InputKey::new(0x60);
     pub const NUMPAD1: InputKey = InputKey::new(0x61);
@@ -202,6 +204,10 @@ pub mod vk {
     pub const F22: InputKey = InputKey::new(0x85);
     pub const F23: InputKey = InputKey::new(0x86);
     pub const F24: InputKey = InputKey::new(0x87);
+
+    pub const OEM_2: InputKey = InputKey::new(0xBF); // "/?" on US keyboards
+    pub const OEM_5: InputKey = InputKey::new(0xDC); // "\\|" on US keyboards
+    pub const OEM_6: InputKey = InputKey::new(0xDD); // "]}" on US keyboards
 }
 
 /// Keyboard modifiers.
@@ -261,6 +267,11 @@ pub enum Input<'input> {
     Keyboard(InputKey),
     /// Mouse input.
     Mouse(InputMouse),
+    /// The terminal window gained (`true`) or lost (`false`) focus.
+    /// Requires focus reporting (mode 1004) to be enabled.
+    Focus(bool),
+    /// The contents of the terminal's clipboard, in response to an OSC 52 query.
+    Clipboard(Vec<u8>),
 }
 
 /// Parses VT sequences into input events.
@@ -270,6 +281,8 @@ pub struct Parser {
     x10_mouse_want: bool,
     x10_mouse_buf: [char; 3],
     x10_mouse_len: usize,
+    osc_buf: String,
+    osc_ignore: bool,
 }
 
 impl Parser {
@@ -283,6 +296,8 @@ impl Parser {
             x10_mouse_want: false,
             x10_mouse_buf: ['\0'; 3],
             x10_mouse_len: 0,
+            osc_buf: String::new(),
+            osc_ignore: false,
         }
     }
 
@@ -330,21 +345,23 @@ impl<'input> Iterator for Stream<'_, '_, 'input> {
                 vt::Token::Text(text) => {
                     return Some(Input::Text(text));
                 }
-                vt::Token::Ctrl(ch) => match ch {
-                    '\0' | '\t' | '\r' => return Some(Input::Keyboard(InputKey::new(ch as u32))),
-                    '\n' => return Some(Input::Keyboard(kbmod::CTRL | vk::RETURN)),
-                    ..='\x1a' => {
-                        // Shift control code to A-Z
-                        let key = ch as u32 | 0x40;
-                        return Some(Input::Keyboard(kbmod::CTRL | InputKey::new(key)));
+                vt::Token::Ctrl(ch) => {
+                    if let Some(key) = Self::parse_ctrl(ch) {
+                        return Some(Input::Keyboard(key));
                     }
-                    '\x7f' => return Some(Input::Keyboard(vk::BACK)),
-                    _ => {}
-                },
+                }
                 vt::Token::Esc(ch) => {
                     match ch {
                         '\0' => return Some(Input::Keyboard(vk::ESCAPE)),
                         '\n' => return Some(Input::Keyboard(kbmod::CTRL_ALT | vk::RETURN)),
+                        '\r' => return Some(Input::Keyboard(kbmod::ALT | vk::RETURN)),
+                        '\x7f' => return Some(Input::Keyboard(kbmod::ALT | vk::BACK)),
+                        // Termux sends Ctrl+Alt+key (e.g. Volume-Down+Alt+key) as ESC + control code.
+                        '\x01'..'\x20' => {
+                            if let Some(key) = Self::parse_ctrl(ch) {
+                                return Some(Input::Keyboard(kbmod::ALT | key));
+                            }
+                        }
                         ' '..='~' => {
                             let ch = ch as u32;
                             let key = ch & !0x20; // Shift a-z to A-Z
@@ -355,6 +372,11 @@ impl<'input> Iterator for Stream<'_, '_, 'input> {
                         _ => {}
                     }
                 }
+                vt::Token::Osc { data, partial } => {
+                    if let Some(input) = self.handle_osc(data, partial) {
+                        return Some(input);
+                    }
+                }
                 vt::Token::SS3(ch) => match ch {
                     'A'..='H' => {
                         let vk = KEYPAD_LUT[ch as usize - 'A' as usize];
@@ -378,7 +400,17 @@ impl<'input> Iterator for Stream<'_, '_, 'input> {
                                 ));
                             }
                         }
+                        // F1-F4 with modifiers, e.g. ESC [1;2P for Shift+F1.
+                        'P'..='S' => {
+                            let key = vk::F1.value() + csi.final_byte as u32 - 'P' as u32;
+                            return Some(Input::Keyboard(
+                                InputKey::new(key) | Self::parse_modifiers(csi),
+                            ));
+                        }
                         'Z' => return Some(Input::Keyboard(kbmod::SHIFT | vk::TAB)),
+                        'I' | 'O' if csi.param_count == 0 && csi.private_byte == '\0' => {
+                            return Some(Input::Focus(csi.final_byte == 'I'));
+                        }
                         '~' => {
                             const LUT: [u8; 35] = [
                                 0,
@@ -388,14 +420,14 @@ impl<'input> Iterator for Stream<'_, '_, 'input> {
                                 vk::END.value() as u8,    // 4
                                 vk::PRIOR.value() as u8,  // 5
                                 vk::NEXT.value() as u8,   // 6
+                                vk::HOME.value() as u8,   // 7 (rxvt)
+                                vk::END.value() as u8,    // 8 (rxvt)
                                 0,
                                 0,
-                                0,
-                                0,
-                                0,
-                                0,
-                                0,
-                                0,
+                                vk::F1.value() as u8, // 11 (rxvt)
+                                vk::F2.value() as u8, // 12 (rxvt)
+                                vk::F3.value() as u8, // 13 (rxvt)
+                                vk::F4.value() as u8, // 14 (rxvt)
                                 vk::F5.value() as u8, // 15
                                 0,
                                 vk::F6.value() as u8,  // 17
@@ -409,8 +441,8 @@ impl<'input> Iterator for Stream<'_, '_, 'input> {
                                 vk::F13.value() as u8, // 25
                                 vk::F14.value() as u8, // 26
                                 0,
-                                vk::F15.value() as u8, // 28
-                                vk::F16.value() as u8, // 29
+                                vk::F15.value() as u8,  // 28
+                                vk::APPS.value() as u8, // 29 (F16 on a VT220)
                                 0,
                                 vk::F17.value() as u8, // 31
                                 vk::F18.value() as u8, // 32
@@ -443,8 +475,15 @@ impl<'input> Iterator for Stream<'_, '_, 'input> {
 
                             mouse.state = InputMouseState::None;
                             if (btn & 0x40) != 0 {
+                                // Buttons 4/5 are the vertical wheel and 6/7 the horizontal one.
+                                // Shift turns the vertical wheel into a horizontal one, like in most GUI apps.
+                                let delta = if (btn & 0x01) != 0 { 3 } else { -3 };
                                 mouse.state = InputMouseState::Scroll;
-                                mouse.scroll.y += if (btn & 0x01) != 0 { 3 } else { -3 };
+                                if (btn & 0x02) != 0 || (btn & 0x04) != 0 {
+                                    mouse.scroll.x += delta;
+                                } else {
+                                    mouse.scroll.y += delta;
+                                }
                             } else if csi.final_byte == 'M' {
                                 const STATES: [InputMouseState; 4] = [
                                     InputMouseState::Left,
@@ -520,12 +559,52 @@ impl<'input> Stream<'_, '_, 'input> {
         }
 
         if !self.parser.bracketed_paste {
-            Some(Input::Paste(mem::take(&mut self.parser.bracketed_paste_buf)))
+            let mut paste = mem::take(&mut self.parser.bracketed_paste_buf);
+            sanitize_paste(&mut paste);
+            Some(Input::Paste(paste))
         } else {
             None
         }
     }
 
+    /// Handles OSC responses from the terminal. Only OSC 52 (clipboard) is of interest.
+    /// Large responses arrive in several parts, which get buffered up in `osc_buf`.
+    #[cold]
+    fn handle_osc(&mut self, data: &str, partial: bool) -> Option<Input<'input>> {
+        let parser = &mut *self.parser;
+
+        if parser.osc_ignore {
+            parser.osc_ignore = partial;
+            return None;
+        }
+
+        if partial || !parser.osc_buf.is_empty() {
+            parser.osc_buf.push_str(data);
+            // Don't buffer up sequences we aren't interested in.
+            let buf = parser.osc_buf.as_str();
+            if !buf.starts_with("52;") && !"52;".starts_with(buf) {
+                parser.osc_buf.clear();
+                parser.osc_ignore = partial;
+                return None;
+            }
+            if partial {
+                return None;
+            }
+        }
+
+        let buf = mem::take(&mut parser.osc_buf);
+        let data = if buf.is_empty() { data } else { buf.as_str() };
+
+        // The response is `52;<selection>;<base64>`. A "?" is a query, not a response.
+        let (_, payload) = data.strip_prefix("52;")?.split_once(';')?;
+        if payload == "?" {
+            return None;
+        }
+        let mut text = base64::decode(payload.as_bytes())?;
+        sanitize_paste(&mut text);
+        Some(Input::Clipboard(text))
+    }
+
     /// Implements the X10 mouse protocol via `CSI M CbCxCy`.
     ///
     /// You want to send numeric mouse coordinates.
@@ -577,6 +656,24 @@ impl<'input> Stream<'_, '_, 'input> {
         }))
     }
 
+    /// Translates a C0 control code to the key combination that typically produces it.
+    /// In Termux, Volume-Down acts as Ctrl, which makes these the only way to type Ctrl+key.
+    fn parse_ctrl(ch: char) -> Option<InputKey> {
+        Some(match ch {
+            '\0' => kbmod::CTRL | vk::SPACE,
+            '\t' | '\r' => InputKey::new(ch as u32),
+            '\n' => kbmod::CTRL | vk::RETURN,
+            // Shift control code to A-Z
+            ..='\x1a' => kbmod::CTRL | InputKey::new(ch as u32 | 0x40),
+            '\x1c' => kbmod::CTRL | vk::OEM_5,
+            '\x1d' => kbmod::CTRL | vk::OEM_6,
+            '\x1e' => kbmod::CTRL_SHIFT | vk::N6,
+            '\x1f' => kbmod::CTRL | vk::OEM_2,
+            '\x7f' => vk::BACK,
+            _ => return None,
+        })
+    }
+
     fn parse_modifiers(csi: &vt::Csi) -> InputKeyMod {
         let mut modifiers = kbmod::NONE;
         let p1 = csi.params[1].saturating_sub(1);
@@ -592,3 +689,106 @@ impl<'input> Stream<'_, '_, 'input> {
         modifiers
     }
 }
+
+/// Removes control characters from pasted text, except for tabs and newlines.
+///
+/// The contents of a bracketed paste are meant to be taken literally, but
+/// stray escape sequences or C1 controls would otherwise end up in the document
+/// (or worse, in the terminal when we render them).
+fn sanitize_paste(text: &mut Vec<u8>) {
+    let mut w = 0;
+    let mut r = 0;
+
+    while r < text.len() {
+        let b = text[r];
+        let len = match b {
+            b'\t' | b'\n' | b'\r' => 1,
+            0x00..0x20 | 0x7f => {
+                r += 1;
+                continue;
+            }
+            // C1 controls are encoded as U+0080..U+009F, i.e. 0xC2 0x80..0x9F.
+            0xc2 if matches!(text.get(r + 1), Some(0x80..0xa0)) => {
+                r += 2;
+                continue;
+            }
+            _ => 1,
+        };
+        text.copy_within(r..r + len, w);
+        w += len;
+        r += len;
+    }
+
+    text.truncate(w);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sanitize_paste() {
+        let mut text = b"a\tb\r\nc\x1b[31md\x07\x7f\xc2\x9b\xc2\xa0\xe2\x80\xa6".to_vec();
+        sanitize_paste(&mut text);
+        assert_eq!(text, b"a\tb\r\nc[31md\xc2\xa0\xe2\x80\xa6");
+    }
+
+    #[test]
+    fn test_focus() {
+        let mut vt = vt::Parser::new();
+        let mut parser = Parser::new();
+        let mut inputs = parser.parse(vt.parse("\x1b[O\x1b[I"));
+        assert!(matches!(inputs.next(), Some(Input::Focus(false))));
+        assert!(matches!(inputs.next(), Some(Input::Focus(true))));
+        assert!(inputs.next().is_none());
+    }
+
+    #[test]
+    fn test_termux_keys() {
+        let mut vt = vt::Parser::new();
+        let mut parser = Parser::new();
+        let keys: Vec<_> = parser
+            .parse(vt.parse("\x00\x1c\x1f\x1b\x7f\x1b\x01\x1b[1;2P\x1b[7~\x1b[8~\x1b[11~\x1bb"))
+            .map(|input| match input {
+                Input::Keyboard(key) => key.value(),
+                _ => 0,
+            })
+            .collect();
+        let expected = [
+            kbmod::CTRL | vk::SPACE,
+            kbmod::CTRL | vk::OEM_5,
+            kbmod::CTRL | vk::OEM_2,
+            kbmod::ALT | vk::BACK,
+            kbmod::CTRL_ALT | vk::A,
+            kbmod::SHIFT | vk::F1,
+            vk::HOME,
+            vk::END,
+            vk::F1,
+            kbmod::ALT | vk::B,
+        ];
+        assert_eq!(keys, expected.map(|key| key.value()));
+    }
+
+    #[test]
+    fn test_osc52() {
+        let mut vt = vt::Parser::new();
+        let mut parser = Parser::new();
+
+        let mut inputs = parser.parse(vt.parse("\x1b]52;c;aGVsbG8=\x1b\\"));
+        assert!(matches!(inputs.next(), Some(Input::Clipboard(d)) if d == b"hello"));
+        assert!(inputs.next().is_none());
+
+        // Split across reads, terminated with BEL.
+        assert!(parser.parse(vt.parse("\x1b]5")).next().is_none());
+        assert!(parser.parse(vt.parse("2;c;aGVs")).next().is_none());
+        let mut inputs = parser.parse(vt.parse("bG8=\x07"));
+        assert!(matches!(inputs.next(), Some(Input::Clipboard(d)) if d == b"hello"));
+
+        // Other OSC sequences and queries are ignored.
+        assert!(parser.parse(vt.parse("\x1b]11;rgb:")).next().is_none());
+        assert!(parser.parse(vt.parse("0000/0000/0000\x1b\\")).next().is_none());
+        assert!(parser.parse(vt.parse("\x1b]52;c;?\x07")).next().is_none());
+        let mut inputs = parser.parse(vt.parse("\x1b[I"));
+        assert!(matches!(inputs.next(), Some(Input::Focus(true))));
+    }
+}
@@ -7,11 +7,13 @@ pub mod lines_bwd;
 pub mod lines_fwd;
 mod memchr2;
 mod memset;
+mod utf8;
 
 pub use lines_bwd::*;
 pub use lines_fwd::*;
 pub use memchr2::*;
 pub use memset::*;
+pub use utf8::*;
 
 #[cfg(test)]
 mod test {
@@ -0,0 +1,211 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Classifying and validating UTF-8, with a fast path for ASCII.
+
+use std::ptr;
+
+/// Returns the index of the first non-ASCII byte in `haystack`,
+/// starting the search at `offset`. If there's none, `haystack.len()` is returned.
+pub fn ascii_prefix(haystack: &[u8], offset: usize) -> usize {
+    unsafe {
+        let beg = haystack.as_ptr();
+        let end = beg.add(haystack.len());
+        let it = beg.add(offset.min(haystack.len()));
+        let it = ascii_prefix_raw(it, end);
+        it.offset_from_unsigned(beg)
+    }
+}
+
+/// Like [`std::str::Utf8Error::valid_up_to`]: Returns how many bytes at the start of
+/// `haystack` are valid UTF-8. A sequence that's cut off at the end doesn't count.
+pub fn utf8_valid_up_to(haystack: &[u8]) -> usize {
+    let mut off = 0;
+
+    loop {
+        off = ascii_prefix(haystack, off);
+        if off >= haystack.len() {
+            return off;
+        }
+
+        // Only the non-ASCII sequences are left to the standard library, one at a time.
+        let len = match haystack[off] {
+            0xC2..=0xDF => 2,
+            0xE0..=0xEF => 3,
+            0xF0..=0xF4 => 4,
+            _ => return off,
+        };
+        let Some(seq) = haystack.get(off..off + len) else {
+            return off;
+        };
+        if str::from_utf8(seq).is_err() {
+            return off;
+        }
+        off += len;
+    }
+}
+
+unsafe fn ascii_prefix_raw(beg: *const u8, end: *const u8) -> *const u8 {
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    return unsafe { ASCII_PREFIX_DISPATCH(beg, end) };
+
+    #[cfg(target_arch = "aarch64")]
+    return unsafe { ascii_prefix_neon(beg, end) };
+
+    #[allow(unreachable_code)]
+    return unsafe { ascii_prefix_fallback(beg, end) };
+}
+
+unsafe fn ascii_prefix_fallback(mut beg: *const u8, end: *const u8) -> *const u8 {
+    unsafe {
+        while !ptr::eq(beg, end) && *beg < 0x80 {
+            beg = beg.add(1);
+        }
+        beg
+    }
+}
+
+// See `MEMCHR2_DISPATCH` for why this is a self-updating function pointer.
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+static mut ASCII_PREFIX_DISPATCH: unsafe fn(beg: *const u8, end: *const u8) -> *const u8 =
+    ascii_prefix_dispatch;
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+unsafe fn ascii_prefix_dispatch(beg: *const u8, end: *const u8) -> *const u8 {
+    let func =
+        if is_x86_feature_detected!("avx2") { ascii_prefix_avx2 } else { ascii_prefix_fallback };
+    unsafe { ASCII_PREFIX_DISPATCH = func };
+    unsafe { func(beg, end) }
+}
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+#[target_feature(enable = "avx2")]
+unsafe fn ascii_prefix_avx2(mut beg: *const u8, end: *const u8) -> *const u8 {
+    unsafe {
+        #[cfg(target_arch = "x86")]
+        use std::arch::x86::*;
+        #[cfg(target_arch = "x86_64")]
+        use std::arch::x86_64::*;
+
+        let mut remaining = end.offset_from_unsigned(beg);
+
+        while remaining >= 32 {
+            let v = _mm256_loadu_si256(beg as *const _);
+            // The high bit of each byte is set for anything but ASCII.
+            let m = _mm256_movemask_epi8(v) as u32;
+
+            if m != 0 {
+                return beg.add(m.trailing_zeros() as usize);
+            }
+
+            beg = beg.add(32);
+            remaining -= 32;
+        }
+
+        ascii_prefix_fallback(beg, end)
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+unsafe fn ascii_prefix_neon(mut beg: *const u8, end: *const u8) -> *const u8 {
+    unsafe {
+        use std::arch::aarch64::*;
+
+        if end.offset_from_unsigned(beg) >= 16 {
+            let high = vdupq_n_u8(0x80);
+
+            loop {
+                let v = vld1q_u8(beg as *const _);
+                let c = vcgeq_u8(v, high);
+
+                // See `memchr2_neon` for how this turns the comparison into a bitmask.
+                let m = vreinterpretq_u16_u8(c);
+                let m = vshrn_n_u16(m, 4);
+                let m = vreinterpret_u64_u8(m);
+                let m = vget_lane_u64(m, 0);
+
+                if m != 0 {
+                    return beg.add(m.trailing_zeros() as usize >> 2);
+                }
+
+                beg = beg.add(16);
+                if end.offset_from_unsigned(beg) < 16 {
+                    break;
+                }
+            }
+        }
+
+        ascii_prefix_fallback(beg, end)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::slice;
+
+    use stdext::sys::{virtual_commit, virtual_reserve};
+
+    use super::*;
+    use crate::simd::test::make_rng;
+
+    #[test]
+    fn test_ascii_prefix() {
+        assert_eq!(ascii_prefix(b"", 0), 0);
+        assert_eq!(ascii_prefix(b"hello", 0), 5);
+
+        let text = "0123456789abcdef0123456789abcdef0123456789äbc".as_bytes();
+        assert_eq!(ascii_prefix(text, 0), 42);
+        assert_eq!(ascii_prefix(text, 42), 42);
+        assert_eq!(ascii_prefix(text, 43), 43);
+        assert_eq!(ascii_prefix(text, 44), text.len());
+        assert_eq!(ascii_prefix(text, 100), text.len());
+    }
+
+    #[test]
+    fn test_utf8_valid_up_to() {
+        let mut rng = make_rng();
+        let alphabet: [&[u8]; 8] = [
+            b"a",
+            b"\n",
+            "ä".as_bytes(),
+            "€".as_bytes(),
+            "😀".as_bytes(),
+            b"\xFF",
+            b"\xE2\x82",
+            b"\xED\xA0\x80",
+        ];
+
+        for len in 0..200 {
+            let mut text = Vec::new();
+            for _ in 0..len {
+                // Mostly valid text, so that the errors are found somewhere in the middle.
+                let i = rng() % 64;
+                text.extend_from_slice(alphabet[if i < 61 { i % 5 } else { i - 56 }]);
+            }
+
+            let expected = str::from_utf8(&text).map_or_else(|err| err.valid_up_to(), |s| s.len());
+            assert_eq!(utf8_valid_up_to(&text), expected);
+        }
+    }
+
+    // Test memory access safety at page boundaries.
+    // The test is a success if it doesn't segfault.
+    #[test]
+    fn test_page_boundary() {
+        let page = unsafe {
+            const PAGE_SIZE: usize = 64 * 1024; // 64 KiB to cover many architectures.
+
+            // 3 pages: uncommitted, committed, uncommitted
+            let ptr = virtual_reserve(PAGE_SIZE * 3).unwrap();
+            virtual_commit(ptr.add(PAGE_SIZE), PAGE_SIZE).unwrap();
+            slice::from_raw_parts_mut(ptr.add(PAGE_SIZE).as_ptr(), PAGE_SIZE)
+        };
+
+        page.fill(b'a');
+
+        // Test if it seeks beyond the page boundary.
+        assert_eq!(ascii_prefix(&page[page.len() - 40..], 0), 40);
+        // Test if it seeks before the page boundary for the masked/partial load.
+        assert_eq!(ascii_prefix(&page[..10], 0), 10);
+    }
+}
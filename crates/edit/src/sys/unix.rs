@@ -6,13 +6,15 @@
 //! Read the `windows` module for reference.
 //! TODO: This reminds me that the sys API should probably be a trait.
 
-use std::ffi::{CStr, c_char, c_int, c_void};
+use std::ffi::{CStr, OsStr, c_char, c_int, c_void};
 use std::fs::File;
+use std::io::{Read as _, Write as _};
 use std::mem::{self, ManuallyDrop, MaybeUninit};
-use std::os::fd::{AsRawFd as _, FromRawFd as _};
+use std::os::fd::{AsRawFd as _, FromRawFd as _, OwnedFd};
+use std::os::unix::process::{CommandExt as _, ExitStatusExt as _};
 use std::path::Path;
 use std::ptr::{NonNull, null_mut};
-use std::{thread, time};
+use std::{process, thread, time};
 
 use stdext::arena::{Arena, ArenaString, scratch_arena};
 use stdext::arena_format;
@@ -57,6 +59,10 @@ pub fn switch_modes() -> apperr::Result<()> {
         if libc::isatty(STATE.stdin) == 0 {
             STATE.stdin = check_int_return(libc::open(c"/dev/tty".as_ptr(), libc::O_RDONLY))?;
         }
+        // Same for stdout (e.g. `edit - > out.txt`). The original one is left for the document.
+        if libc::isatty(STATE.stdout) == 0 {
+            STATE.stdout = check_int_return(libc::open(c"/dev/tty".as_ptr(), libc::O_WRONLY))?;
+        }
 
         // Store the stdin flags so we can more easily toggle `O_NONBLOCK` later on.
         STATE.stdin_flags = check_int_return(libc::fcntl(STATE.stdin, libc::F_GETFL))?;
@@ -210,7 +216,15 @@ pub fn read_stdin(arena: &Arena, mut timeout: time::Duration) -> Option<ArenaStr
                     ret = libc::poll(&mut pollfd, 1, timeout.as_millis() as libc::c_int);
                 }
                 if ret < 0 {
-                    return None; // Error? Let's assume it's an EOF.
+                    match errno() {
+                        // Interrupted by a signal, e.g. a SIGWINCH while a resize is held back.
+                        libc::EINTR if STATE.inject_resize => break,
+                        libc::EINTR => {
+                            timeout = timeout.saturating_sub(beg.elapsed());
+                            continue;
+                        }
+                        _ => return None, // Error? Let's assume it's an EOF.
+                    }
                 }
                 if ret == 0 {
                     break; // Timeout? We can stop reading.
@@ -296,16 +310,12 @@ pub fn write_stdout(text: &str) {
         return;
     }
 
-    // If we don't set the TTY to blocking mode,
-    // the write will potentially fail with EAGAIN.
-    set_tty_nonblocking(false);
-
     let buf = text.as_bytes();
     let mut written = 0;
 
     while written < buf.len() {
         let w = &buf[written..];
-        let w = &buf[..w.len().min(GIBI)];
+        let w = &w[..w.len().min(GIBI)];
         let n = unsafe { libc::write(STATE.stdout, w.as_ptr() as *const _, w.len()) };
 
         if n >= 0 {
@@ -313,9 +323,16 @@ pub fn write_stdout(text: &str) {
             continue;
         }
 
-        let err = errno();
-        if err != libc::EINTR {
-            return;
+        match errno() {
+            libc::EINTR => {}
+            // `read_stdin` may have put the TTY into `O_NONBLOCK` mode. This may run on the
+            // render thread, so instead of changing the flag back, we wait until it's writable.
+            libc::EAGAIN => unsafe {
+                let mut pollfd =
+                    libc::pollfd { fd: STATE.stdout, events: libc::POLLOUT, revents: 0 };
+                libc::poll(&mut pollfd, 1, -1);
+            },
+            _ => return,
         }
     }
 }
@@ -334,14 +351,16 @@ fn set_tty_nonblocking(nonblock: bool) {
     }
 }
 
+/// Returns the original stdin, if it's redirected. This works before and after
+/// [`switch_modes`], which reopens stdin for the UI.
 pub fn open_stdin_if_redirected() -> Option<File> {
     unsafe {
-        // Did we reopen stdin during `init()`?
-        if STATE.stdin != libc::STDIN_FILENO {
-            Some(File::from_raw_fd(libc::STDIN_FILENO))
-        } else {
-            None
+        if STATE.stdin == libc::STDIN_FILENO && libc::isatty(libc::STDIN_FILENO) != 0 {
+            return None;
         }
+        // A copy, so that dropping the file doesn't close stdin.
+        let fd = libc::dup(libc::STDIN_FILENO);
+        if fd < 0 { None } else { Some(File::from_raw_fd(fd)) }
     }
 }
 
@@ -366,6 +385,133 @@ pub fn file_id(file: Option<&File>, path: &Path) -> apperr::Result<FileId> {
     }
 }
 
+/// A read-only mapping of a whole file into memory.
+///
+/// If another process truncates the file while it's mapped,
+/// reading the missing part crashes with SIGBUS.
+pub struct FileMap {
+    ptr: NonNull<u8>,
+    len: usize,
+}
+
+// It's read-only.
+unsafe impl Send for FileMap {}
+
+impl FileMap {
+    pub fn new(file: &File) -> apperr::Result<Self> {
+        let len = file.metadata()?.len() as usize;
+        if len == 0 {
+            // mmap() rejects empty mappings.
+            return Err(errno_to_apperr(libc::EINVAL));
+        }
+
+        unsafe {
+            let ptr = libc::mmap(
+                null_mut(),
+                len,
+                libc::PROT_READ,
+                libc::MAP_PRIVATE,
+                file.as_raw_fd(),
+                0,
+            );
+            if ptr == libc::MAP_FAILED {
+                return Err(get_last_error());
+            }
+            Ok(Self { ptr: NonNull::new_unchecked(ptr as *mut u8), len })
+        }
+    }
+}
+
+impl std::ops::Deref for FileMap {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self.ptr.as_ptr(), self.len) }
+    }
+}
+
+impl Drop for FileMap {
+    fn drop(&mut self) {
+        unsafe { libc::munmap(self.ptr.as_ptr() as *mut c_void, self.len) };
+    }
+}
+
+/// Copies the permissions, ownership and extended attributes (which
+/// includes the SELinux context on Android) from `src` to `dst`.
+///
+/// All of this is best-effort: Changing the owner usually requires privileges
+/// we don't have, many filesystems don't support xattrs, and some (like the FUSE
+/// mounts under `/storage` on Android) reject permission changes with `EPERM`.
+/// Only unexpected errors from changing the permissions are returned.
+pub fn copy_file_attributes(src: &File, dst: &File) -> apperr::Result<()> {
+    unsafe {
+        let src_fd = src.as_raw_fd();
+        let dst_fd = dst.as_raw_fd();
+
+        let mut stat = MaybeUninit::<libc::stat>::uninit();
+        check_int_return(libc::fstat(src_fd, stat.as_mut_ptr()))?;
+        let stat = stat.assume_init();
+
+        // Change the owner first, because it may reset the setuid/setgid bits.
+        if libc::fchown(dst_fd, stat.st_uid, stat.st_gid) != 0 {
+            // We may still be able to retain the group if we're a member of it.
+            libc::fchown(dst_fd, u32::MAX as libc::uid_t, stat.st_gid);
+        }
+
+        if libc::fchmod(dst_fd, stat.st_mode & 0o7777) != 0 {
+            let err = errno();
+            if err != libc::EPERM && err != libc::ENOTSUP {
+                return Err(errno_to_apperr(err));
+            }
+        }
+
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        copy_xattrs(src_fd, dst_fd);
+    }
+
+    Ok(())
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+unsafe fn copy_xattrs(src_fd: c_int, dst_fd: c_int) {
+    unsafe {
+        let scratch = scratch_arena(None);
+
+        let len = libc::flistxattr(src_fd, null_mut(), 0);
+        if len <= 0 {
+            return;
+        }
+
+        let names: &mut [MaybeUninit<u8>] = scratch.alloc_uninit_slice(len as usize);
+        let len = libc::flistxattr(src_fd, names.as_mut_ptr() as *mut c_char, names.len());
+        if len <= 0 {
+            return;
+        }
+        let names = names[..len as usize].assume_init_ref();
+
+        // The list is a sequence of NUL-terminated names.
+        for name in names.split(|&b| b == 0).filter(|n| !n.is_empty()) {
+            let name = arena_format!(&*scratch, "{}\0", String::from_utf8_lossy(name));
+            let name = name.as_ptr() as *const c_char;
+
+            let len = libc::fgetxattr(src_fd, name, null_mut(), 0);
+            if len < 0 {
+                continue;
+            }
+
+            let value: &mut [MaybeUninit<u8>] = scratch.alloc_uninit_slice(len as usize);
+            let len = libc::fgetxattr(src_fd, name, value.as_mut_ptr() as *mut c_void, value.len());
+            if len < 0 {
+                continue;
+            }
+
+            // Failures are expected (e.g. "security.*" without privileges) and ignored.
+            libc::fsetxattr(dst_fd, name, value.as_ptr() as *const c_void, len as usize, 0);
+        }
+    }
+}
+
+#[allow(dead_code, reason = "ICU is not loaded dynamically on Unix")]
 unsafe fn load_library(name: *const c_char) -> apperr::Result<NonNull<c_void>> {
     unsafe {
         NonNull::new(libc::dlopen(name, libc::RTLD_LAZY))
@@ -395,6 +541,114 @@ pub unsafe fn get_proc_address<T>(
     }
 }
 
+/// A program that runs in a pseudo terminal, like the shell in a terminal panel.
+pub struct Pty {
+    master: File,
+    child: process::Child,
+}
+
+impl Pty {
+    /// Runs `program` with `args` in `cwd`, with a terminal of the given size as its stdin/stdout/stderr.
+    pub fn spawn(program: &OsStr, args: &[&OsStr], cwd: &Path, size: Size) -> apperr::Result<Self> {
+        unsafe {
+            let flags = libc::O_RDWR | libc::O_NOCTTY | libc::O_CLOEXEC;
+            let master = File::from_raw_fd(check_int_return(libc::posix_openpt(flags))?);
+            let fd = master.as_raw_fd();
+            check_int_return(libc::grantpt(fd))?;
+            check_int_return(libc::unlockpt(fd))?;
+
+            // ptsname() isn't thread-safe, but ptsname_r() doesn't exist everywhere.
+            let name = libc::ptsname(fd);
+            if name.is_null() {
+                return Err(get_last_error());
+            }
+            let slave = OwnedFd::from_raw_fd(check_int_return(libc::open(name, flags))?);
+
+            // Reads shouldn't block the UI. The output is polled instead.
+            let fl = libc::fcntl(fd, libc::F_GETFL);
+            check_int_return(libc::fcntl(fd, libc::F_SETFL, fl | libc::O_NONBLOCK))?;
+
+            let mut pty = Self {
+                child: process::Command::new(program)
+                    .args(args)
+                    .current_dir(cwd)
+                    .env("TERM", "xterm-256color")
+                    .stdin(slave.try_clone()?)
+                    .stdout(slave.try_clone()?)
+                    .stderr(slave)
+                    // A new session, with the terminal as its controlling terminal,
+                    // is what makes Ctrl+C and job control work.
+                    .pre_exec(|| {
+                        if libc::setsid() < 0 || libc::ioctl(0, libc::TIOCSCTTY, 0) < 0 {
+                            return Err(std::io::Error::last_os_error());
+                        }
+                        Ok(())
+                    })
+                    .spawn()?,
+                master,
+            };
+            pty.resize(size);
+            Ok(pty)
+        }
+    }
+
+    /// Reads what the program wrote, without waiting for it.
+    /// Returns `Some(0)` if there's nothing new and `None` once it exited.
+    pub fn read(&mut self, buf: &mut [u8]) -> Option<usize> {
+        match (&self.master).read(buf) {
+            Ok(0) => None,
+            Ok(n) => Some(n),
+            Err(err) if err.kind() == std::io::ErrorKind::WouldBlock => Some(0),
+            Err(err) if err.kind() == std::io::ErrorKind::Interrupted => Some(0),
+            // Linux reports EIO once the other side is closed.
+            Err(_) => None,
+        }
+    }
+
+    /// Writes input for the program, as if it was typed.
+    pub fn write(&mut self, data: &[u8]) {
+        let mut data = data;
+        while !data.is_empty() {
+            match (&self.master).write(data) {
+                Ok(n) => data = &data[n..],
+                // The program isn't reading fast enough. Its buffer is usually 4KiB or more,
+                // so this only happens for large pastes into a busy program.
+                Err(err) if err.kind() == std::io::ErrorKind::WouldBlock => {
+                    thread::sleep(time::Duration::from_millis(1))
+                }
+                Err(_) => break,
+            }
+        }
+    }
+
+    /// Waits for the program to exit and returns its exit code, 128 plus the signal
+    /// if one killed it, like shells do. Call this once `read` returned `None`.
+    pub fn wait(&mut self) -> Option<i32> {
+        let status = self.child.wait().ok()?;
+        status.code().or_else(|| status.signal().map(|signal| 128 + signal))
+    }
+
+    /// Tells the program that the terminal changed its size.
+    pub fn resize(&mut self, size: Size) {
+        let winsize = libc::winsize {
+            ws_row: size.height.clamp(1, u16::MAX as CoordType) as u16,
+            ws_col: size.width.clamp(1, u16::MAX as CoordType) as u16,
+            ws_xpixel: 0,
+            ws_ypixel: 0,
+        };
+        unsafe {
+            libc::ioctl(self.master.as_raw_fd(), libc::TIOCSWINSZ, &winsize);
+        }
+    }
+}
+
+impl Drop for Pty {
+    fn drop(&mut self) {
+        _ = self.child.kill();
+        _ = self.child.wait();
+    }
+}
+
 pub fn preferred_languages(arena: &Arena) -> Vec<ArenaString<'_>, &Arena> {
     let mut locales = Vec::new_in(arena);
 
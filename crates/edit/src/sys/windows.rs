@@ -1,7 +1,7 @@
 // Copyright (c) Microsoft Corporation.
 // Licensed under the MIT License.
 
-use std::ffi::{OsString, c_char, c_void};
+use std::ffi::{OsStr, OsString, c_char, c_void};
 use std::fmt::Write as _;
 use std::fs::{self, File};
 use std::mem::MaybeUninit;
@@ -158,6 +158,20 @@ pub fn switch_modes() -> apperr::Result<()> {
                 null_mut(),
             );
         }
+        // Same for stdout (e.g. `edit - > out.txt`). The original one is left for the document.
+        if ptr::eq(STATE.stdout, Foundation::INVALID_HANDLE_VALUE)
+            || !matches!(FileSystem::GetFileType(STATE.stdout), FileSystem::FILE_TYPE_CHAR)
+        {
+            STATE.stdout = FileSystem::CreateFileW(
+                w!("CONOUT$"),
+                Foundation::GENERIC_READ | Foundation::GENERIC_WRITE,
+                FileSystem::FILE_SHARE_READ | FileSystem::FILE_SHARE_WRITE,
+                null_mut(),
+                FileSystem::OPEN_EXISTING,
+                0,
+                null_mut(),
+            );
+        }
         if ptr::eq(STATE.stdin, Foundation::INVALID_HANDLE_VALUE)
             || ptr::eq(STATE.stdout, Foundation::INVALID_HANDLE_VALUE)
         {
@@ -421,17 +435,17 @@ pub fn write_stdout(text: &str) {
     }
 }
 
-/// Check if the stdin handle is redirected to a file, etc.
-///
-/// # Returns
-///
-/// * `Some(file)` if stdin is redirected.
-/// * Otherwise, `None`.
+/// Returns the original stdin, if it's redirected. This works before and after
+/// [`switch_modes`], which reopens stdin for the UI.
 pub fn open_stdin_if_redirected() -> Option<File> {
     unsafe {
         let handle = Console::GetStdHandle(Console::STD_INPUT_HANDLE);
-        // Did we reopen stdin during `init()`?
-        if !std::ptr::eq(STATE.stdin, handle) { Some(File::from_raw_handle(handle)) } else { None }
+        if handle.is_null() || ptr::eq(handle, Foundation::INVALID_HANDLE_VALUE) {
+            return None;
+        }
+        let redirected = !ptr::eq(STATE.stdin, handle)
+            || !matches!(FileSystem::GetFileType(handle), FileSystem::FILE_TYPE_CHAR);
+        if redirected { Some(File::from_raw_handle(handle)) } else { None }
     }
 }
 
@@ -516,6 +530,13 @@ pub fn canonicalize(path: &Path) -> std::io::Result<PathBuf> {
     Ok(path)
 }
 
+/// Copies the file permissions from `src` to `dst`.
+/// Windows ACLs are inherited from the parent directory.
+pub fn copy_file_attributes(src: &File, dst: &File) -> apperr::Result<()> {
+    dst.set_permissions(src.metadata()?.permissions())?;
+    Ok(())
+}
+
 unsafe fn get_module(name: *const u16) -> apperr::Result<NonNull<c_void>> {
     unsafe { check_ptr_return(LibraryLoader::GetModuleHandleW(name)) }
 }
@@ -548,6 +569,53 @@ pub unsafe fn get_proc_address<T>(
     }
 }
 
+/// A read-only mapping of a whole file into memory.
+///
+/// TODO: Implement this with MapViewOfFile. For now, mapping always fails.
+pub struct FileMap;
+
+impl FileMap {
+    pub fn new(_file: &File) -> apperr::Result<Self> {
+        Err(gle_to_apperr(Foundation::ERROR_CALL_NOT_IMPLEMENTED))
+    }
+}
+
+impl std::ops::Deref for FileMap {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &[]
+    }
+}
+
+/// A program that runs in a pseudo terminal, like the shell in a terminal panel.
+///
+/// TODO: Implement this with ConPTY. For now, spawning always fails.
+pub struct Pty;
+
+impl Pty {
+    pub fn spawn(
+        _program: &OsStr,
+        _args: &[&OsStr],
+        _cwd: &Path,
+        _size: Size,
+    ) -> apperr::Result<Self> {
+        Err(gle_to_apperr(Foundation::ERROR_CALL_NOT_IMPLEMENTED))
+    }
+
+    pub fn read(&mut self, _buf: &mut [u8]) -> Option<usize> {
+        None
+    }
+
+    pub fn write(&mut self, _data: &[u8]) {}
+
+    pub fn wait(&mut self) -> Option<i32> {
+        None
+    }
+
+    pub fn resize(&mut self, _size: Size) {}
+}
+
 /// Returns a list of preferred languages for the current user.
 pub fn preferred_languages(arena: &Arena) -> Vec<ArenaString<'_>, &Arena> {
     // If the GetUserPreferredUILanguages() don't fit into 512 characters,
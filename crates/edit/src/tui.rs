@@ -147,16 +147,22 @@ use std::arch::breakpoint;
 #[cfg(debug_assertions)]
 use std::collections::HashSet;
 use std::fmt::Write as _;
+use std::ops::Range;
 use std::{iter, mem, ptr, time};
 
 use stdext::arena::{Arena, ArenaString, scratch_arena};
 use stdext::arena_format;
 
-use crate::buffer::{CursorMovement, MoveLineDirection, RcTextBuffer, TextBuffer, TextBufferCell};
+use crate::buffer::{
+    CursorMovement, MoveLineDirection, RcTextBuffer, SelectionUnit, TextBuffer, TextBufferCell,
+};
 use crate::cell::*;
 use crate::clipboard::Clipboard;
 use crate::document::WriteableDocument;
-use crate::framebuffer::{Attributes, Framebuffer, INDEXED_COLORS_COUNT, IndexedColor};
+use crate::framebuffer::{
+    Attributes, ColorMode, Framebuffer, INDEXED_COLORS_COUNT, IndexedColor, NAMED_COLORS_COUNT,
+    NamedColor,
+};
 use crate::hash::*;
 use crate::helpers::*;
 use crate::input::{InputKeyMod, kbmod, vk};
@@ -168,10 +174,45 @@ const SHIFT_TAB: InputKey = vk::TAB.with_modifiers(kbmod::SHIFT);
 const KBMOD_FOR_WORD_NAV: InputKeyMod =
     if cfg!(target_os = "macos") { kbmod::ALT } else { kbmod::CTRL };
 
+/// How long [`Context::flash`] keeps the screen inverted.
+const FLASH_DURATION: time::Duration = time::Duration::from_millis(100);
+
+// Touch mode: See `Tui::set_touch_mode`.
+const LONG_PRESS_DURATION: time::Duration = time::Duration::from_millis(500);
+const FLING_FRAME_DURATION: time::Duration = time::Duration::from_millis(16);
+// How long after the last drag event a release still counts as a flick.
+const FLING_RELEASE_WINDOW: time::Duration = time::Duration::from_millis(80);
+// The speed in lines per frame that a flick needs to start momentum scrolling,
+// and how much of it is left after each frame.
+const FLING_MIN_VELOCITY: f32 = 2.0;
+const FLING_FRICTION: f32 = 0.9;
+
 type Input<'input> = input::Input<'input>;
 type InputKey = input::InputKey;
 type InputMouseState = input::InputMouseState;
 
+/// Where the user dragged a dialog to and what size they gave it.
+/// It's kept per dialog type for as long as the [`Tui`] lives. See [`Context::modal_end()`].
+struct ModalGeometry {
+    classname: &'static str,
+    /// The top-left corner. `None` while it's centered.
+    position: Option<Point>,
+    /// The intrinsic size. `None` while it's up to the dialog.
+    size: Option<Size>,
+}
+
+/// A dialog that's being dragged by its title or resized from its grip.
+struct ModalDrag {
+    classname: &'static str,
+    resize: bool,
+    /// The top-left corner and intrinsic size when the drag started.
+    position: Point,
+    size: Size,
+}
+
+/// Dialogs can't be resized any smaller than this.
+const MODAL_MIN_SIZE: Size = Size { width: 16, height: 3 };
+
 /// Since [`TextBuffer`] creation and management is expensive,
 /// we cache instances of them for reuse between frames.
 /// This is used for [`Context::editline()`].
@@ -233,6 +274,137 @@ pub enum ListSelection {
     Activated,
 }
 
+/// The state of a list made with [`Context::virtual_list_begin`], which the caller
+/// keeps between frames. Only the visible rows get drawn, so that it can hold tens of
+/// thousands of them, and rows can be appended while it's shown, e.g. while a search
+/// streams in its results, without moving the view.
+///
+/// Several rows can be selected: Shift extends the selection from the last row that
+/// was picked, Ctrl+click and Ctrl+Space toggle a row and Ctrl+A selects all of them.
+#[derive(Default)]
+pub struct VirtualList {
+    /// Whether each row is selected. Its length is the number of rows.
+    selected: Vec<bool>,
+    selected_count: usize,
+    /// The row that the arrow keys move from.
+    cursor: usize,
+    /// Where Shift extends the selection from.
+    anchor: usize,
+    /// The first visible row.
+    scroll: usize,
+    /// The number of visible rows, as of the last layout.
+    height: usize,
+}
+
+impl VirtualList {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.selected.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.selected.is_empty()
+    }
+
+    /// Sets the number of rows. The ones beyond the old length are appended unselected.
+    /// Unless the list was empty: Then the first one gets selected, so that
+    /// there's always something to act on.
+    pub fn set_len(&mut self, len: usize) {
+        if len < self.selected.len() {
+            self.selected_count -= self.selected[len..].iter().filter(|&&s| s).count();
+        }
+        self.selected.resize(len, false);
+
+        let last = len.saturating_sub(1);
+        self.cursor = self.cursor.min(last);
+        self.anchor = self.anchor.min(last);
+        self.scroll = self.scroll.min(last);
+
+        if len != 0 && self.selected_count == 0 {
+            self.selected[self.cursor] = true;
+            self.selected_count = 1;
+        }
+    }
+
+    /// Removes all rows.
+    pub fn clear(&mut self) {
+        *self = Self { height: self.height, ..Self::default() };
+    }
+
+    /// The row that has the keyboard focus, if there are any.
+    pub fn cursor(&self) -> Option<usize> {
+        (!self.is_empty()).then_some(self.cursor)
+    }
+
+    pub fn is_selected(&self, row: usize) -> bool {
+        self.selected.get(row).copied().unwrap_or(false)
+    }
+
+    pub fn selected_count(&self) -> usize {
+        self.selected_count
+    }
+
+    /// The selected rows in ascending order.
+    pub fn selection(&self) -> impl Iterator<Item = usize> + '_ {
+        self.selected.iter().enumerate().filter(|&(_, &s)| s).map(|(row, _)| row)
+    }
+
+    pub fn select_all(&mut self) {
+        self.selected.fill(true);
+        self.selected_count = self.len();
+    }
+
+    /// Moves the cursor to `row` and scrolls it into view. With `extend` the selection
+    /// then spans from the anchor to `row`, and otherwise it's just `row`.
+    pub fn select(&mut self, row: usize, extend: bool) {
+        if row >= self.len() {
+            return;
+        }
+        if !extend {
+            self.anchor = row;
+        }
+        let range = self.anchor.min(row)..=self.anchor.max(row);
+        self.selected.fill(false);
+        self.selected[range.clone()].fill(true);
+        self.selected_count = range.count();
+        self.cursor = row;
+        self.scroll_to_cursor();
+    }
+
+    /// Moves the cursor to `row` and adds it to the selection or removes it.
+    pub fn toggle(&mut self, row: usize) {
+        let Some(selected) = self.selected.get_mut(row) else {
+            return;
+        };
+        *selected = !*selected;
+        if *selected {
+            self.selected_count += 1;
+        } else {
+            self.selected_count -= 1;
+        }
+        self.cursor = row;
+        self.anchor = row;
+        self.scroll_to_cursor();
+    }
+
+    fn scroll_to_cursor(&mut self) {
+        let height = self.height.max(1);
+        if self.cursor < self.scroll {
+            self.scroll = self.cursor;
+        } else if self.cursor >= self.scroll + height {
+            self.scroll = self.cursor + 1 - height;
+        }
+    }
+
+    fn scroll_by(&mut self, delta: CoordType) {
+        let max = self.len().saturating_sub(self.height);
+        self.scroll = self.scroll.saturating_add_signed(delta).min(max);
+    }
+}
+
 /// Controls the position of a node relative to its parent.
 #[derive(Default)]
 pub enum Position {
@@ -355,6 +527,17 @@ pub struct Tui {
     /// in a double/triple click series.
     first_click_target: u64,
 
+    /// Whether the mouse input comes from a touchscreen. See [`Tui::set_touch_mode`].
+    touch_mode: bool,
+    /// Timestamp of the last mouse down event. Used for detecting long presses.
+    mouse_down_timestamp: std::time::Instant,
+    /// Whether the current mouse press was reported as a long press already.
+    mouse_long_pressed: bool,
+    /// How far the mouse moved during this frame's drag event, if any.
+    mouse_drag_delta: Point,
+    /// Timestamp of the last drag event. Used for detecting flicks.
+    mouse_drag_timestamp: std::time::Instant,
+
     /// Path to the currently focused node.
     focused_node_path: Vec<u64>,
     /// Contains the last element in [`Tui::focused_node_path`].
@@ -368,9 +551,20 @@ pub struct Tui {
     /// The clipboard contents.
     clipboard: Clipboard,
 
+    /// Whether the terminal window has focus. Assumed to be true
+    /// unless the terminal tells us otherwise via focus reporting.
+    window_focused: bool,
+
     settling_have: i32,
     settling_want: i32,
     read_timeout: time::Duration,
+    /// Set by [`Context::flash`] until the next frame was rendered inverted.
+    flash: bool,
+    /// Drives the animation of indeterminate progress bars.
+    epoch: time::Instant,
+
+    modal_geometry: Vec<ModalGeometry>,
+    modal_drag: Option<ModalDrag>,
 }
 
 impl Tui {
@@ -411,6 +605,12 @@ impl Tui {
             first_click_position: Point::MIN,
             first_click_target: 0,
 
+            touch_mode: false,
+            mouse_down_timestamp: std::time::Instant::now(),
+            mouse_long_pressed: false,
+            mouse_drag_delta: Point::default(),
+            mouse_drag_timestamp: std::time::Instant::now(),
+
             focused_node_path: Vec::with_capacity(16),
             focused_node_for_scrolling: ROOT_ID,
 
@@ -420,7 +620,13 @@ impl Tui {
 
             settling_have: 0,
             settling_want: 0,
+            window_focused: true,
             read_timeout: time::Duration::MAX,
+            flash: false,
+            epoch: time::Instant::now(),
+
+            modal_geometry: Vec::new(),
+            modal_drag: None,
         };
         Self::clean_node_path(&mut tui.mouse_down_node_path);
         Self::clean_node_path(&mut tui.focused_node_path);
@@ -432,10 +638,36 @@ impl Tui {
         self.framebuffer.set_indexed_colors(colors);
     }
 
-    /// Disable true color (24-bit RGB) and use 256-color mode instead.
-    /// Useful for compatibility with terminals that have limited true color support.
-    pub fn set_disable_true_color(&mut self, disable: bool) {
-        self.framebuffer.set_disable_true_color(disable);
+    /// Remaps a single color of the framebuffer's color palette.
+    /// See [`Framebuffer::set_indexed_color()`].
+    pub fn set_indexed_color(&mut self, index: IndexedColor, color: StraightRgba) {
+        self.framebuffer.set_indexed_color(index, color);
+    }
+
+    /// Sets up the framebuffer's extended color palette.
+    /// See [`Framebuffer::set_named_colors()`].
+    pub fn setup_named_colors(&mut self, colors: [Option<StraightRgba>; NAMED_COLORS_COUNT]) {
+        self.framebuffer.set_named_colors(colors);
+    }
+
+    /// See [`Framebuffer::fill_default_colors()`].
+    pub fn fill_default_colors(&mut self) {
+        self.framebuffer.fill_default_colors();
+    }
+
+    /// Sets how RGB colors are sent to the terminal.
+    pub fn set_color_mode(&mut self, mode: ColorMode) {
+        self.framebuffer.set_color_mode(mode);
+    }
+
+    /// Sets how wide tabs in labels are, see [`Framebuffer::set_tab_size()`].
+    pub fn set_tab_size(&mut self, tab_size: CoordType) {
+        self.framebuffer.set_tab_size(tab_size);
+    }
+
+    /// See [`Framebuffer::invalidate()`].
+    pub fn invalidate(&mut self) {
+        self.framebuffer.invalidate();
     }
 
     /// Set up translations for Ctrl/Alt/Shift modifiers.
@@ -466,7 +698,28 @@ impl Tui {
     /// If the TUI is currently running animations, etc.,
     /// this will return a timeout smaller than [`time::Duration::MAX`].
     pub fn read_timeout(&mut self) -> time::Duration {
-        mem::replace(&mut self.read_timeout, time::Duration::MAX)
+        let timeout = mem::replace(&mut self.read_timeout, time::Duration::MAX);
+        // Animations (e.g. scrolling while drag-selecting) aren't worth it
+        // if the user isn't looking. They resume with the next input.
+        if self.window_focused { timeout } else { time::Duration::MAX }
+    }
+
+    /// Returns whether the terminal window has focus.
+    pub fn is_window_focused(&self) -> bool {
+        self.window_focused
+    }
+
+    /// Enables touch-friendly mouse handling, for terminals like Termux that turn
+    /// touchscreen input into mouse events. In textareas, dragging scrolls the text
+    /// (with momentum when flicked), a long press starts a selection, and the ends of
+    /// the selection get handles that can be dragged. Scrollbars are easier to hit.
+    pub fn set_touch_mode(&mut self, enabled: bool) {
+        self.touch_mode = enabled;
+    }
+
+    /// Widens scrollbar hit targets in touch mode, since fingers are imprecise.
+    fn scrollbar_hit_slop(&self) -> CoordType {
+        if self.touch_mode { 1 } else { 0 }
     }
 
     /// Returns the viewport size.
@@ -482,6 +735,12 @@ impl Tui {
         self.framebuffer.indexed(index)
     }
 
+    /// Returns a color from the framebuffer's extended palette, if it's set.
+    #[inline]
+    pub fn named(&self, color: NamedColor) -> Option<StraightRgba> {
+        self.framebuffer.named(color)
+    }
+
     /// Returns an indexed color from the framebuffer with the given alpha.
     /// See [`Framebuffer::indexed_alpha()`].
     #[inline]
@@ -529,7 +788,9 @@ impl Tui {
             self.left_mouse_down_target = 0;
             self.mouse_state = InputMouseState::None;
             self.mouse_is_drag = false;
+            self.mouse_long_pressed = false;
         }
+        self.mouse_drag_delta = Point::default();
 
         let now = std::time::Instant::now();
         let mut input_text = None;
@@ -537,6 +798,9 @@ impl Tui {
         let mut input_mouse_modifiers = kbmod::NONE;
         let mut input_mouse_click = 0;
         let mut input_scroll_delta = Point { x: 0, y: 0 };
+        let mut input_window_focus = None;
+        let mut input_long_press = false;
+        let mut input_right_click = false;
         // `input_consumed` should be `true` if we're in the settling phase which is indicated by
         // `self.needs_settling() == true`. However, there's a possibility for it being true from
         // a previous frame, and we do have fresh new input. In that case want `input_consumed`
@@ -554,6 +818,11 @@ impl Tui {
                 assert!(resize.width < 32768 && resize.height < 32768);
                 self.size = resize;
             }
+            Some(Input::Focus(focused)) => {
+                self.window_focused = focused;
+                self.framebuffer.set_cursor_blink(focused);
+                input_window_focus = Some(focused);
+            }
             Some(Input::Text(text)) => {
                 input_text = Some(text);
                 // TODO: the .len()==1 check causes us to ignore keyboard inputs that are faster than we process them.
@@ -566,12 +835,24 @@ impl Tui {
                     input_keyboard = InputKey::from_ascii(ch as char)
                 }
             }
-            Some(Input::Paste(paste)) => {
+            // Pastes go through the clipboard, so that they get inserted verbatim and as a single
+            // undo step, without auto-indentation or overtype. Nothing is left if the paste only
+            // contained control characters, in which case we must not paste the previous contents.
+            Some(Input::Paste(paste)) if !paste.is_empty() => {
                 let clipboard = self.clipboard_mut();
                 clipboard.write(paste);
                 clipboard.mark_as_synchronized();
                 input_keyboard = Some(kbmod::CTRL | vk::V);
             }
+            Some(Input::Paste(_)) => {}
+            // The terminal told us what's on its clipboard, in response to an OSC 52 query.
+            Some(Input::Clipboard(data)) => {
+                let clipboard = self.clipboard_mut();
+                if !data.is_empty() && data != clipboard.read() {
+                    clipboard.write(data);
+                    clipboard.mark_as_synchronized();
+                }
+            }
             Some(Input::Keyboard(keyboard)) => {
                 input_keyboard = Some(keyboard);
             }
@@ -590,6 +871,7 @@ impl Tui {
 
                 let mut hovered_node = None; // Needed for `mouse_down`
                 let mut focused_node = None; // Needed for `mouse_down` and `is_click`
+                let mut keep_focus = false;
                 if mouse_down || mouse_up {
                     // Roots (aka windows) are ordered in Z order, so we iterate
                     // them in reverse order, from topmost to bottommost.
@@ -602,8 +884,10 @@ impl Tui {
                                 return VisitControl::SkipChildren;
                             }
                             hovered_node = Some(node);
-                            if n.attributes.focusable {
+                            if n.attributes.focusable || n.attributes.focus_keep {
+                                // A `focus_keep` node still serves as the click target.
                                 focused_node = Some(node);
+                                keep_focus = n.attributes.focus_keep;
                             }
                             VisitControl::Continue
                         });
@@ -626,6 +910,11 @@ impl Tui {
                     next_state = self.mouse_state;
                 } else if is_drag {
                     self.mouse_is_drag = true;
+                    self.mouse_drag_delta = Point {
+                        x: next_position.x - self.mouse_position.x,
+                        y: next_position.y - self.mouse_position.y,
+                    };
+                    self.mouse_drag_timestamp = now;
                 } else if mouse_down {
                     // Transition from no mouse input to some mouse input --> Record the mouse down position.
                     Self::build_node_path(hovered_node, &mut self.mouse_down_node_path);
@@ -634,8 +923,10 @@ impl Tui {
                     let mut target = 0;
                     if next_state == InputMouseState::Left {
                         target = focused_node.map_or(0, |n| n.borrow().id);
-                        Self::build_node_path(focused_node, &mut self.focused_node_path);
-                        self.needs_more_settling(); // See `needs_more_settling()`.
+                        if !keep_focus {
+                            Self::build_node_path(focused_node, &mut self.focused_node_path);
+                            self.needs_more_settling(); // See `needs_more_settling()`.
+                        }
                     }
 
                     // Double-/Triple-/Etc.-clicks are triggered on mouse-down,
@@ -657,9 +948,17 @@ impl Tui {
                         };
                     }
 
+                    input_right_click = next_state == InputMouseState::Right;
+
                     // Gets reset at the start of this function.
                     self.left_mouse_down_target = target;
                     self.mouse_down_position = next_position;
+                    self.mouse_down_timestamp = now;
+
+                    // Wake up in time to detect a long press, even if no further input arrives.
+                    if self.touch_mode && next_state == InputMouseState::Left {
+                        self.read_timeout = self.read_timeout.min(LONG_PRESS_DURATION);
+                    }
                 } else if mouse_up {
                     // Transition from some mouse input to no mouse input --> The mouse button was released.
                     next_state = InputMouseState::Release;
@@ -690,6 +989,16 @@ impl Tui {
             }
         }
 
+        if self.touch_mode
+            && self.mouse_state == InputMouseState::Left
+            && !self.mouse_is_drag
+            && !self.mouse_long_pressed
+            && now - self.mouse_down_timestamp >= LONG_PRESS_DURATION
+        {
+            self.mouse_long_pressed = true;
+            input_long_press = true;
+        }
+
         if !input_consumed {
             // Every time there's input, we naturally need to re-render at least once.
             self.settling_have = 0;
@@ -709,6 +1018,9 @@ impl Tui {
             input_mouse_modifiers,
             input_mouse_click,
             input_scroll_delta,
+            input_window_focus,
+            input_long_press,
+            input_right_click,
             input_consumed,
 
             tree,
@@ -859,12 +1171,41 @@ impl Tui {
 
     /// Renders the last frame into the framebuffer and returns the VT output.
     pub fn render<'a>(&mut self, arena: &'a Arena) -> ArenaString<'a> {
+        self.render_frame();
+        self.framebuffer.render(arena)
+    }
+
+    /// Like [`Tui::render()`], but appends the VT output to `output`,
+    /// which can be reused across frames, see [`Framebuffer::render_into()`].
+    pub fn render_into(&mut self, output: &mut String) {
+        self.render_frame();
+        self.framebuffer.render_into(output);
+    }
+
+    /// Renders the last frame into the framebuffer.
+    fn render_frame(&mut self) {
         self.framebuffer.flip(self.size);
         for child in self.prev_tree.iterate_roots() {
             let mut child = child.borrow_mut();
             self.render_node(&mut child);
         }
-        self.framebuffer.render(arena)
+        // While unfocused, nothing would undo it until the next input.
+        if mem::take(&mut self.flash) && self.window_focused {
+            self.framebuffer.reverse(self.size.as_rect());
+            // Render again soon, which undoes the inversion.
+            self.read_timeout = self.read_timeout.min(FLASH_DURATION);
+        }
+    }
+
+    /// See [`Framebuffer::spawn_render_thread()`].
+    pub fn spawn_render_thread(&mut self) {
+        self.framebuffer.spawn_render_thread();
+    }
+
+    /// Writes the output of [`Tui::render_into()`] to the terminal and clears it,
+    /// see [`Framebuffer::present()`].
+    pub fn present(&mut self, output: &mut String) {
+        self.framebuffer.present(output);
     }
 
     /// Recursively renders each node and its children.
@@ -948,6 +1289,26 @@ impl Tui {
                 self.framebuffer.blend_bg(rect, dim);
                 self.framebuffer.blend_fg(rect, dim);
             }
+
+            if node.attributes.shadow {
+                // One column to the right and one row below, offset by one like a drop shadow.
+                let o = node.outer;
+                let viewport = self.size.as_rect();
+                let dim = self.indexed_alpha(IndexedColor::Black, 1, 2);
+                for rect in [
+                    Rect { left: o.right, top: o.top + 1, right: o.right + 1, bottom: o.bottom },
+                    Rect {
+                        left: o.left + 1,
+                        top: o.bottom,
+                        right: o.right + 1,
+                        bottom: o.bottom + 1,
+                    },
+                ] {
+                    let rect = rect.intersect(viewport);
+                    self.framebuffer.blend_bg(rect, dim);
+                    self.framebuffer.blend_fg(rect, dim);
+                }
+            }
         }
 
         self.framebuffer.blend_bg(outer_clipped, node.attributes.bg);
@@ -973,12 +1334,22 @@ impl Tui {
                         title,
                     );
                 }
+                // The grip to resize it with, see `Context::modal_end`.
+                if node.outer == node.outer_clipped {
+                    self.framebuffer.replace_text(
+                        node.outer.bottom - 1,
+                        node.outer.right - 1,
+                        node.outer.right,
+                        "◢",
+                    );
+                }
             }
             NodeContent::Text(content) => self.render_styled_text(
                 inner,
                 node.intrinsic_size.width,
                 &content.text,
                 &content.chunks,
+                &content.links,
                 content.overflow,
             ),
             NodeContent::Textarea(tc) => {
@@ -1001,6 +1372,21 @@ impl Tui {
                     tc.scroll_offset_x_max = res.visual_pos_x_max;
                 }
 
+                if self.touch_mode
+                    && tc.has_focus
+                    && !tc.single_line
+                    && let Some((beg, end)) = tb.selection_range()
+                {
+                    for c in [beg, end] {
+                        let pos = Point {
+                            x: destination.left + tb.margin_width() + c.visual_pos.x
+                                - tc.scroll_offset.x,
+                            y: destination.top + c.visual_pos.y - tc.scroll_offset.y,
+                        };
+                        self.framebuffer.draw_selection_handle(destination, pos);
+                    }
+                }
+
                 if !tc.single_line {
                     // Render the scrollbar.
                     let track = Rect {
@@ -1032,6 +1418,16 @@ impl Tui {
                     content.intrinsic_size.height,
                 );
             }
+            NodeContent::VirtualList(vc) => {
+                let track = Rect {
+                    left: inner.right,
+                    top: inner.top,
+                    right: inner.right + 1,
+                    bottom: inner.bottom,
+                };
+                vc.thumb_height =
+                    self.framebuffer.draw_scrollbar(outer_clipped, track, vc.scroll, vc.rows);
+            }
             _ => {}
         }
 
@@ -1047,6 +1443,7 @@ impl Tui {
         actual_width: CoordType,
         text: &str,
         chunks: &[StyledTextChunk],
+        links: &[(Range<usize>, &str)],
         overflow: Overflow,
     ) {
         let target_width = target.width();
@@ -1059,7 +1456,8 @@ impl Tui {
             self.framebuffer.replace_text(target.top, target.left, target.right, text);
         } else {
             let bytes = text.as_bytes();
-            let mut cfg = unicode::MeasurementConfig::new(&bytes);
+            let mut cfg =
+                unicode::MeasurementConfig::new(&bytes).with_tab_size(self.framebuffer.tab_size());
 
             match overflow {
                 Overflow::Clip => unreachable!(),
@@ -1096,10 +1494,12 @@ impl Tui {
 
         if !chunks.is_empty() {
             let bytes = text.as_bytes();
-            let mut cfg = unicode::MeasurementConfig::new(&bytes).with_cursor(unicode::Cursor {
-                visual_pos: Point { x: target.left, y: 0 },
-                ..Default::default()
-            });
+            let mut cfg = unicode::MeasurementConfig::new(&bytes)
+                .with_tab_size(self.framebuffer.tab_size())
+                .with_cursor(unicode::Cursor {
+                    visual_pos: Point { x: target.left, y: 0 },
+                    ..Default::default()
+                });
 
             let mut iter = chunks.iter().peekable();
 
@@ -1118,6 +1518,7 @@ impl Tui {
                     let end = cfg.goto_offset(end.min(skipped.start)).visual_pos.x;
                     let rect =
                         Rect { left: beg, top: target.top, right: end, bottom: target.bottom };
+                    self.framebuffer.blend_bg(rect, chunk.bg);
                     self.framebuffer.blend_fg(rect, chunk.fg);
                     self.framebuffer.replace_attr(rect, chunk.attr, chunk.attr);
                 }
@@ -1127,11 +1528,44 @@ impl Tui {
                     let end = cfg.goto_offset(end).visual_pos.x - skipped_cols;
                     let rect =
                         Rect { left: beg, top: target.top, right: end, bottom: target.bottom };
+                    self.framebuffer.blend_bg(rect, chunk.bg);
                     self.framebuffer.blend_fg(rect, chunk.fg);
                     self.framebuffer.replace_attr(rect, chunk.attr, chunk.attr);
                 }
             }
         }
+
+        if !links.is_empty() {
+            let bytes = text.as_bytes();
+            let mut cfg = unicode::MeasurementConfig::new(&bytes)
+                .with_tab_size(self.framebuffer.tab_size())
+                .with_cursor(unicode::Cursor {
+                    visual_pos: Point { x: target.left, y: 0 },
+                    ..Default::default()
+                });
+
+            for (range, url) in links {
+                let beg = range.start;
+                let end = range.end.min(text.len());
+
+                // Like the chunks above, around the text skipped by the ellipsis.
+                if beg < skipped.start {
+                    let beg = cfg.goto_offset(beg).visual_pos.x;
+                    let end = cfg.goto_offset(end.min(skipped.start)).visual_pos.x;
+                    let rect =
+                        Rect { left: beg, top: target.top, right: end, bottom: target.bottom };
+                    self.framebuffer.add_link(rect, url);
+                }
+
+                if end > skipped.end {
+                    let beg = cfg.goto_offset(beg.max(skipped.end)).visual_pos.x - skipped_cols;
+                    let end = cfg.goto_offset(end).visual_pos.x - skipped_cols;
+                    let rect =
+                        Rect { left: beg, top: target.top, right: end, bottom: target.bottom };
+                    self.framebuffer.add_link(rect, url);
+                }
+            }
+        }
     }
 
     /// Outputs a debug string of the layout and focus tree.
@@ -1207,7 +1641,7 @@ impl Tui {
                 match &node.content {
                     NodeContent::Text(content) => {
                         result.push_repeat(' ', depth * 2);
-                        _ = write!(result, "  text:         \"{}\"\r\n", &content.text);
+                        _ = write!(result, "  text:         \"{}\"\r\n", content.text);
                     }
                     NodeContent::Textarea(content) => {
                         let tb = content.buffer.borrow();
@@ -1338,6 +1772,12 @@ pub struct Context<'a, 'input> {
     input_mouse_click: CoordType,
     /// By how much the mouse wheel was scrolled since the last frame.
     input_scroll_delta: Point,
+    /// Whether the terminal window gained or lost focus, if it changed.
+    input_window_focus: Option<bool>,
+    /// Whether the mouse was held down without moving for [`LONG_PRESS_DURATION`] in touch mode.
+    input_long_press: bool,
+    /// Whether the right mouse button was pressed down this frame.
+    input_right_click: bool,
     input_consumed: bool,
 
     tree: Tree<'a>,
@@ -1357,7 +1797,7 @@ impl<'a> Drop for Context<'a, '_> {
     }
 }
 
-impl<'a> Context<'a, '_> {
+impl<'a, 'input> Context<'a, 'input> {
     /// Get an arena for temporary allocations such as for [`arena_format`].
     pub fn arena(&self) -> &'a Arena {
         // TODO:
@@ -1374,12 +1814,34 @@ impl<'a> Context<'a, '_> {
         self.tui.size()
     }
 
+    /// Returns `Some(true)` if the terminal window just gained focus,
+    /// `Some(false)` if it just lost it, and `None` otherwise.
+    pub fn window_focus_changed(&self) -> Option<bool> {
+        self.input_window_focus
+    }
+
+    /// Returns whether the terminal window has focus.
+    pub fn is_window_focused(&self) -> bool {
+        self.tui.is_window_focused()
+    }
+
     /// Returns an indexed color from the framebuffer.
     #[inline]
     pub fn indexed(&self, index: IndexedColor) -> StraightRgba {
         self.tui.framebuffer.indexed(index)
     }
 
+    /// Returns a color from the framebuffer's extended palette, if it's set.
+    #[inline]
+    pub fn named(&self, color: NamedColor) -> Option<StraightRgba> {
+        self.tui.framebuffer.named(color)
+    }
+
+    /// The background of focused list items.
+    fn focus_color(&self) -> StraightRgba {
+        self.named(NamedColor::Focus).unwrap_or_else(|| self.indexed(IndexedColor::Green))
+    }
+
     /// Returns an indexed color from the framebuffer with the given alpha.
     /// See [`Framebuffer::indexed_alpha()`].
     #[inline]
@@ -1415,6 +1877,16 @@ impl<'a> Context<'a, '_> {
         self.needs_settling = true;
     }
 
+    /// Sets how wide tabs in labels are, see [`Tui::set_tab_size()`].
+    pub fn set_tab_size(&mut self, tab_size: CoordType) {
+        self.tui.set_tab_size(tab_size);
+    }
+
+    /// Inverts the whole screen for a moment, as a visual bell.
+    pub fn flash(&mut self) {
+        self.tui.flash = true;
+    }
+
     /// Begins a generic UI block (container) with a unique ID derived from the given `classname`.
     pub fn block_begin(&mut self, classname: &'static str) {
         let parent = self.tree.current_node;
@@ -1533,7 +2005,8 @@ impl<'a> Context<'a, '_> {
         self.next_block_id_mixin = id;
     }
 
-    fn attr_focusable(&mut self) {
+    /// Lets the current node take the keyboard focus, for blocks that handle their own input.
+    pub fn attr_focusable(&mut self) {
         let mut last_node = self.tree.last_node.borrow_mut();
         last_node.attributes.focusable = true;
     }
@@ -1598,6 +2071,13 @@ impl<'a> Context<'a, '_> {
         last_node.attributes.focus_well = true;
     }
 
+    /// Clicks on this node and its children leave the keyboard focus where it is.
+    /// Use [`Context::was_clicked`] to find out whether a child was clicked.
+    pub fn attr_focus_keep(&mut self) {
+        let mut last_node = self.tree.last_node.borrow_mut();
+        last_node.attributes.focus_keep = true;
+    }
+
     /// Explicitly sets the intrinsic size of the current node.
     /// The intrinsic size is the size the node ideally wants to be.
     pub fn attr_intrinsic_size(&mut self, size: Size) {
@@ -1642,6 +2122,12 @@ impl<'a> Context<'a, '_> {
         last_node.attributes.bordered = true;
     }
 
+    /// Gives the current floater a shadow, which darkens what's below it.
+    pub fn attr_shadow(&mut self) {
+        let mut last_node = self.tree.last_node.borrow_mut();
+        last_node.attributes.shadow = true;
+    }
+
     /// Sets the current node's position inside the parent.
     pub fn attr_position(&mut self, align: Position) {
         let mut last_node = self.tree.last_node.borrow_mut();
@@ -1699,6 +2185,23 @@ impl<'a> Context<'a, '_> {
         if self.input_consumed { None } else { self.input_keyboard }
     }
 
+    /// Returns current text input, if any.
+    /// Returns None if the input was already consumed.
+    pub fn text_input(&self) -> Option<&'input str> {
+        if self.input_consumed { None } else { self.input_text }
+    }
+
+    /// Returns whether the node that `block_begin(classname)` would create next
+    /// was focused during the last frame. This allows you to intercept input,
+    /// before a child like a [`Context::textarea`] gets to process it.
+    pub fn is_next_block_focused(&self, classname: &'static str) -> bool {
+        let mut id = hash_str(self.tree.current_node.borrow().id, classname);
+        if self.next_block_id_mixin != 0 {
+            id = hash(id, &self.next_block_id_mixin.to_ne_bytes());
+        }
+        self.tui.is_node_focused(id)
+    }
+
     #[inline]
     pub fn set_input_consumed(&mut self) {
         debug_assert!(!self.input_consumed);
@@ -1716,12 +2219,74 @@ impl<'a> Context<'a, '_> {
         self.tui.was_mouse_down_on_node(last_node.id)
     }
 
+    /// Returns how far the mouse wheel scrolled while over the current node, if it did.
+    pub fn scroll_input(&mut self) -> Option<Point> {
+        if self.input_consumed || self.input_scroll_delta == Point::default() {
+            return None;
+        }
+        let last_node = self.tree.last_node.borrow();
+        let prev = self.tui.prev_node_map.get(last_node.id)?.borrow();
+        prev.outer_clipped.contains(self.tui.mouse_position).then_some(self.input_scroll_delta)
+    }
+
+    /// Returns where a context menu was asked for on the current node, if it was:
+    /// At the mouse for a right-click or a long press, or, for the Menu key or Shift+F10
+    /// while the node is focused, at its text cursor if it's a textarea.
+    ///
+    /// A long press is reported even if the textarea consumed it to select a word,
+    /// so that the menu can act on that word.
+    pub fn context_menu_input(&mut self) -> Option<Point> {
+        let mouse = if self.input_long_press {
+            self.contains_mouse_down()
+        } else {
+            !self.input_consumed && self.input_right_click && self.contains_mouse_down()
+        };
+        if mouse {
+            self.set_input_consumed();
+            return Some(self.tui.mouse_position);
+        }
+
+        if self.input_consumed
+            || !matches!(self.input_keyboard, Some(key) if key == vk::APPS || key == kbmod::SHIFT | vk::F10)
+            || !self.is_focused()
+        {
+            return None;
+        }
+        self.set_input_consumed();
+
+        let last_node = self.tree.last_node.borrow();
+        let prev = self.tui.prev_node_map.get(last_node.id)?.borrow();
+        let pos = match &prev.content {
+            NodeContent::Textarea(tc) => {
+                let tb = tc.buffer.borrow();
+                let cursor = tb.cursor_visual_pos();
+                Point {
+                    x: prev.inner.left + tb.margin_width() + cursor.x - tc.scroll_offset.x,
+                    y: prev.inner.top + cursor.y - tc.scroll_offset.y,
+                }
+            }
+            _ => Point { x: prev.outer.left, y: prev.outer.top },
+        };
+        Some(pos)
+    }
+
     /// Returns whether the mouse was pressed down on the current node's subtree.
     pub fn contains_mouse_down(&mut self) -> bool {
         let last_node = self.tree.last_node.borrow();
         self.tui.was_mouse_down_on_subtree(&last_node)
     }
 
+    /// Returns whether the current node was clicked, without requiring it to be focused.
+    /// This is meant for the children of a node with [`Context::attr_focus_keep`].
+    pub fn was_clicked(&mut self) -> bool {
+        if !self.input_consumed && self.input_mouse_click != 0 && self.contains_mouse_down() {
+            self.set_input_consumed();
+            true
+        } else {
+            false
+        }
+    }
+
     /// Returns whether the current node is focused.
     pub fn is_focused(&mut self) -> bool {
         let last_node = self.tree.last_node.borrow();
@@ -1762,8 +2327,13 @@ impl<'a> Context<'a, '_> {
 
     /// Ends the current modal window block.
     /// Returns true if the user pressed Escape (a request to close).
+    ///
+    /// Dialogs can be dragged by their title and resized from the grip in the
+    /// bottom right corner. Both stick for all dialogs with the same classname.
     pub fn modal_end(&mut self) -> bool {
         self.block_end();
+        self.modal_drag();
+        self.modal_apply_geometry();
 
         // Consume the input unconditionally, so that the root (the "main window")
         // doesn't accidentally receive any input via `consume_shortcut()`.
@@ -1776,6 +2346,120 @@ impl<'a> Context<'a, '_> {
         }
     }
 
+    /// Moves or resizes the modal that just ended, if its title or grip is being dragged.
+    fn modal_drag(&mut self) {
+        let node = self.tree.last_node.borrow();
+        let classname = node.classname;
+        let dragging = self.tui.modal_drag.as_ref().is_some_and(|d| d.classname == classname);
+
+        if self.tui.mouse_state != InputMouseState::Left || !self.tui.mouse_is_drag {
+            if dragging {
+                self.tui.modal_drag = None;
+            }
+            return;
+        }
+        if self.input_consumed {
+            return;
+        }
+
+        let Some(prev) = self.tui.prev_node_map.get(node.id) else {
+            return;
+        };
+        let prev = prev.borrow();
+        let outer = prev.outer;
+        let down = self.tui.mouse_down_position;
+
+        if !dragging {
+            if self.tui.modal_drag.is_some() || !outer.contains(down) {
+                return;
+            }
+            let resize = down == Point { x: outer.right - 1, y: outer.bottom - 1 };
+            if !resize && down.y != outer.top {
+                return;
+            }
+            self.tui.modal_drag = Some(ModalDrag {
+                classname,
+                resize,
+                position: Point { x: outer.left, y: outer.top },
+                size: prev.intrinsic_size,
+            });
+        }
+
+        let drag = self.tui.modal_drag.as_ref().unwrap();
+        let dx = self.tui.mouse_position.x - down.x;
+        let dy = self.tui.mouse_position.y - down.y;
+        let (position, size) = if drag.resize {
+            let size = Size {
+                width: (drag.size.width + dx).max(MODAL_MIN_SIZE.width),
+                height: (drag.size.height + dy).max(MODAL_MIN_SIZE.height),
+            };
+            (drag.position, Some(size))
+        } else {
+            (Point { x: drag.position.x + dx, y: drag.position.y + dy }, None)
+        };
+
+        let geometry = match self.tui.modal_geometry.iter().position(|g| g.classname == classname) {
+            Some(i) => &mut self.tui.modal_geometry[i],
+            None => {
+                self.tui.modal_geometry.push(ModalGeometry {
+                    classname,
+                    position: None,
+                    size: None,
+                });
+                self.tui.modal_geometry.last_mut().unwrap()
+            }
+        };
+        geometry.position = Some(position);
+        if size.is_some() {
+            geometry.size = size;
+        }
+
+        drop(prev);
+        drop(node);
+        self.set_input_consumed();
+        self.needs_rerender();
+    }
+
+    /// Gives the modal that just ended the position and size the user dragged it to.
+    fn modal_apply_geometry(&mut self) {
+        let mut node = self.tree.last_node.borrow_mut();
+        let Some(geometry) = self.tui.modal_geometry.iter().find(|g| g.classname == node.classname)
+        else {
+            return;
+        };
+        let viewport = self.tui.size;
+
+        if let Some(size) = geometry.size {
+            // Leave room for the border.
+            node.intrinsic_size = Size {
+                width: size.width.min(viewport.width - 2).max(1),
+                height: size.height.min(viewport.height - 2).max(1),
+            };
+            node.intrinsic_size_set = true;
+        }
+
+        if let Some(position) = geometry.position {
+            // Dialogs that size themselves only know their size after the layout.
+            let size = if node.intrinsic_size_set {
+                node.intrinsic_to_outer()
+            } else {
+                self.tui.prev_node_map.get(node.id).map_or(Size::default(), |prev| {
+                    let outer = prev.borrow().outer;
+                    Size { width: outer.width(), height: outer.height() }
+                })
+            };
+            // Keep it on screen, even if the terminal got smaller since.
+            let x = position.x.min(viewport.width - size.width).max(0);
+            let y = position.y.min(viewport.height - size.height).max(0);
+            node.attributes.float = Some(FloatAttributes {
+                gravity_x: 0.0,
+                gravity_y: 0.0,
+                offset_x: x as f32,
+                offset_y: y as f32,
+            });
+        }
+    }
+
     /// Begins a table block. Call [`Context::table_end()`].
     /// Tables are the primary way to create a grid layout,
     /// and to layout controls on a single row (= a table with 1 row).
@@ -1786,9 +2470,23 @@ impl<'a> Context<'a, '_> {
         last_node.content = NodeContent::Table(TableContent {
             columns: Vec::new_in(self.arena()),
             cell_gap: Default::default(),
+            stacked: false,
         });
     }
 
+    /// Stacks the cells of each row vertically, centered, if the viewport is narrower than `width`.
+    /// Use it for rows that would otherwise get clipped on narrow screens, like a dialog's buttons.
+    /// The focus then moves between the cells with Up/Down instead of Left/Right.
+    pub fn table_stack_below(&mut self, width: CoordType) {
+        let stacked = self.tui.size.width < width;
+        let mut last_node = self.tree.last_node.borrow_mut();
+        if let NodeContent::Table(spec) = &mut last_node.content {
+            spec.stacked = stacked;
+        } else {
+            debug_assert!(false);
+        }
+    }
+
     /// Assigns widths to the columns of the current table.
     /// By default, the table will left-align all columns.
     pub fn table_set_columns(&mut self, columns: &[CoordType]) {
@@ -1839,7 +2537,14 @@ impl<'a> Context<'a, '_> {
     }
 
     fn table_end_row(&mut self) {
-        self.table_move_focus(vk::LEFT, vk::RIGHT);
+        let stacked = self.tree.last_node.borrow().parent.is_some_and(
+            |table| matches!(&table.borrow().content, NodeContent::Table(spec) if spec.stacked),
+        );
+        if stacked {
+            self.table_move_focus(vk::UP, vk::DOWN);
+        } else {
+            self.table_move_focus(vk::LEFT, vk::RIGHT);
+        }
     }
 
     /// Ends the current table block.
@@ -1944,37 +2649,63 @@ impl<'a> Context<'a, '_> {
         self.tree.last_node.borrow_mut().content = NodeContent::Text(TextContent {
             text: ArenaString::new_in(self.arena()),
             chunks: Vec::with_capacity_in(4, self.arena()),
+            links: Vec::new_in(self.arena()),
             overflow: Overflow::Clip,
         });
     }
 
     /// Changes the active pencil color of the current label.
     pub fn styled_label_set_foreground(&mut self, fg: StraightRgba) {
+        self.styled_label_update_chunk(|chunk| chunk.fg = fg);
+    }
+
+    /// Changes the active background color of the current label.
+    pub fn styled_label_set_background(&mut self, bg: StraightRgba) {
+        self.styled_label_update_chunk(|chunk| chunk.bg = bg);
+    }
+
+    /// Changes the active pencil attributes of the current label.
+    pub fn styled_label_set_attributes(&mut self, attr: Attributes) {
+        self.styled_label_update_chunk(|chunk| chunk.attr = attr);
+    }
+
+    /// Makes the text added from now on a link to `url`, until it's called with `None`.
+    /// See [`Framebuffer::add_link`].
+    pub fn styled_label_set_link(&mut self, url: Option<&str>) {
+        let arena = self.arena();
         let mut node = self.tree.last_node.borrow_mut();
         let NodeContent::Text(content) = &mut node.content else {
             unreachable!();
         };
 
-        let last = content.chunks.last().unwrap_or(&INVALID_STYLED_TEXT_CHUNK);
-        if last.offset != content.text.len() && last.fg != fg {
-            content.chunks.push(StyledTextChunk {
-                offset: content.text.len(),
-                fg,
-                attr: last.attr,
-            });
+        let offset = content.text.len();
+        if let Some((range, _)) = content.links.last_mut()
+            && range.end == usize::MAX
+        {
+            range.end = offset;
+        }
+        if let Some(url) = url {
+            content.links.push((offset..usize::MAX, ArenaString::from_str(arena, url).leak()));
         }
     }
 
-    /// Changes the active pencil attributes of the current label.
-    pub fn styled_label_set_attributes(&mut self, attr: Attributes) {
+    fn styled_label_update_chunk(&mut self, update: impl FnOnce(&mut StyledTextChunk)) {
         let mut node = self.tree.last_node.borrow_mut();
         let NodeContent::Text(content) = &mut node.content else {
             unreachable!();
         };
 
-        let last = content.chunks.last().unwrap_or(&INVALID_STYLED_TEXT_CHUNK);
-        if last.offset != content.text.len() && last.attr != attr {
-            content.chunks.push(StyledTextChunk { offset: content.text.len(), fg: last.fg, attr });
+        let offset = content.text.len();
+        let last = content.chunks.last().copied().unwrap_or(INVALID_STYLED_TEXT_CHUNK);
+        let unchanged = StyledTextChunk { offset, ..last };
+        let mut chunk = unchanged;
+        update(&mut chunk);
+
+        if last.offset == offset {
+            // No text was added since the last change, so it can be amended.
+            *content.chunks.last_mut().unwrap() = chunk;
+        } else if chunk != unchanged {
+            content.chunks.push(chunk);
         }
     }
 
@@ -1997,23 +2728,90 @@ impl<'a> Context<'a, '_> {
             };
 
             let cursor = unicode::MeasurementConfig::new(&content.text.as_bytes())
+                .with_tab_size(self.tui.framebuffer.tab_size())
                 .goto_visual(Point { x: CoordType::MAX, y: 0 });
             last_node.intrinsic_size.width = cursor.visual_pos.x;
             last_node.intrinsic_size.height = 1;
             last_node.intrinsic_size_set = true;
         }
-
+
+        self.block_end();
+    }
+
+    /// Sets the overflow behavior of the current label.
+    pub fn attr_overflow(&mut self, overflow: Overflow) {
+        let mut last_node = self.tree.last_node.borrow_mut();
+        let NodeContent::Text(content) = &mut last_node.content else {
+            return;
+        };
+
+        content.overflow = overflow;
+    }
+
+    /// Shows `text` in a tooltip next to the cell at `anchor`, below it if there's room
+    /// and above it otherwise. The text wraps at word boundaries to fit the screen.
+    ///
+    /// The tooltip takes no input and clicks on it leave the focus where it is.
+    /// It's up to the caller to stop drawing it, e.g. once the cursor moved.
+    pub fn tooltip(&mut self, classname: &'static str, anchor: Point, text: &str) {
+        let screen = self.tui.size;
+        // Leave room for the padding, the shadow, and a bit of the screen around it.
+        let max_width = (screen.width - 4).clamp(1, 60);
+        let lines = wrap_text(text, max_width, self.tui.framebuffer.tab_size());
+        let width = lines.iter().map(|&(_, width)| width).max().unwrap_or(0) + 2;
+        let height = lines.len() as CoordType;
+
+        let below = anchor.y + 1 + height < screen.height;
+        let x = anchor.x.min(screen.width - width - 1).max(0);
+        let y = if below { anchor.y + 1 } else { (anchor.y - height).max(0) };
+
+        self.block_begin(classname);
+        self.attr_float(FloatSpec {
+            anchor: Anchor::Root,
+            gravity_x: 0.0,
+            gravity_y: 0.0,
+            offset_x: x as f32,
+            offset_y: y as f32,
+        });
+        self.attr_intrinsic_size(Size { width, height });
+        self.attr_padding(Rect::two(0, 1));
+        self.attr_shadow();
+        self.attr_focus_keep();
+        {
+            for (i, &(line, _)) in lines.iter().enumerate() {
+                self.next_block_id_mixin(i as u64);
+                self.label("line", line);
+            }
+        }
         self.block_end();
     }
 
-    /// Sets the overflow behavior of the current label.
-    pub fn attr_overflow(&mut self, overflow: Overflow) {
-        let mut last_node = self.tree.last_node.borrow_mut();
-        let NodeContent::Text(content) = &mut last_node.content else {
-            return;
+    /// Creates a progress bar that's `width` columns wide, filled according to `progress`,
+    /// which goes from 0 to 1. If it's `None`, a block slides back and forth instead.
+    /// Since that's an animation, the caller needs to keep drawing frames meanwhile.
+    pub fn progress_bar(
+        &mut self,
+        classname: &'static str,
+        progress: Option<f32>,
+        width: CoordType,
+    ) {
+        let width = width.max(1);
+        let (beg, end) = match progress {
+            Some(progress) => (0, (progress.clamp(0.0, 1.0) * width as f32).round() as CoordType),
+            None => {
+                let len = (width / 4).max(1);
+                let steps = ((width - len) * 2).max(1);
+                let step = (self.tui.epoch.elapsed().as_millis() / 50) as CoordType % steps;
+                let beg = if step <= width - len { step } else { steps - step };
+                (beg, beg + len)
+            }
         };
 
-        content.overflow = overflow;
+        self.styled_label_begin(classname);
+        for x in 0..width {
+            self.styled_label_add_text(if (beg..end).contains(&x) { "█" } else { "░" });
+        }
+        self.styled_label_end();
     }
 
     /// Creates a button with the given text.
@@ -2121,6 +2919,9 @@ impl<'a> Context<'a, '_> {
             scroll_offset_x_max: 0,
             thumb_height: 0,
             preferred_column: 0,
+            drag_anchor: None,
+            touch_drag: TouchDrag::None,
+            fling_velocity: 0.0,
             single_line,
             has_focus: self.tui.is_node_focused(node.id),
         });
@@ -2142,6 +2943,9 @@ impl<'a> Context<'a, '_> {
                 content.scroll_offset_x_max = content_prev.scroll_offset_x_max;
                 content.thumb_height = content_prev.thumb_height;
                 content.preferred_column = content_prev.preferred_column;
+                content.drag_anchor = content_prev.drag_anchor.clone();
+                content.touch_drag = content_prev.touch_drag;
+                content.fling_velocity = content_prev.fling_velocity;
 
                 let mut text_width = node_prev.inner.width();
                 if !single_line {
@@ -2156,6 +2960,7 @@ impl<'a> Context<'a, '_> {
                     make_cursor_visible |= tb.set_width(text_width);
                 }
 
+                self.textarea_apply_fling(content);
                 make_cursor_visible |= self.textarea_handle_input(content, &node_prev, single_line);
 
                 if make_cursor_visible {
@@ -2193,6 +2998,109 @@ impl<'a> Context<'a, '_> {
         dirty
     }
 
+    /// Continues momentum scrolling after a flick in touch mode.
+    fn textarea_apply_fling(&mut self, tc: &mut TextareaContent) {
+        if tc.fling_velocity == 0.0 || self.tui.mouse_state == InputMouseState::Left {
+            return;
+        }
+
+        tc.scroll_offset.y += tc.fling_velocity.round() as CoordType;
+        tc.fling_velocity *= FLING_FRICTION;
+        if tc.fling_velocity.abs() < 0.5 {
+            tc.fling_velocity = 0.0;
+        } else {
+            self.tui.read_timeout = self.tui.read_timeout.min(FLING_FRAME_DURATION);
+            self.needs_rerender();
+        }
+    }
+
+    /// Handles mouse input in the text area in touch mode. See [`Tui::set_touch_mode`].
+    fn textarea_handle_touch(
+        &mut self,
+        tc: &mut TextareaContent,
+        tb: &mut TextBuffer,
+        pos: Point,
+    ) -> bool {
+        match self.tui.mouse_state {
+            InputMouseState::Left if self.input_long_press => {
+                // A long press selects the word under the finger, and dragging extends it.
+                tb.cursor_move_to_visual(pos);
+                tb.select_word();
+                tc.touch_drag = TouchDrag::Select;
+                true
+            }
+            InputMouseState::Left if !self.tui.mouse_is_drag => {
+                tc.fling_velocity = 0.0;
+                if tc.touch_drag != TouchDrag::None {
+                    return false;
+                }
+
+                tc.touch_drag = TouchDrag::Scroll;
+                if self.input_mouse_click >= 2 {
+                    tb.select_word();
+                    return true;
+                }
+
+                // Pressing a selection handle lets you drag that end of the selection.
+                // The handles are hit within one column, since fingers are imprecise.
+                if let Some((beg, end)) = tb.selection_range() {
+                    let hit = |c: Point| c.y == pos.y && (c.x - pos.x).abs() <= 1;
+                    let (anchor, moving) = if hit(end.visual_pos) {
+                        (beg, end)
+                    } else if hit(beg.visual_pos) {
+                        (end, beg)
+                    } else {
+                        return false;
+                    };
+                    tb.cursor_move_to_offset(anchor.offset);
+                    tb.selection_update_offset(moving.offset);
+                    tc.touch_drag = TouchDrag::Select;
+                }
+                false
+            }
+            InputMouseState::Left => {
+                if tc.touch_drag == TouchDrag::Select {
+                    tb.selection_update_visual(pos);
+                    tc.preferred_column = tb.cursor_visual_pos().x;
+                    return true;
+                }
+
+                // Dragging moves the text along with the finger.
+                let delta = self.tui.mouse_drag_delta;
+                tc.scroll_offset.x -= delta.x;
+                tc.scroll_offset.y -= delta.y;
+                if delta.y != 0 {
+                    tc.fling_velocity = -delta.y as f32;
+                }
+                false
+            }
+            InputMouseState::Release => {
+                let touch_drag = mem::replace(&mut tc.touch_drag, TouchDrag::None);
+                if touch_drag != TouchDrag::Scroll {
+                    return false;
+                }
+
+                if !self.tui.mouse_is_drag {
+                    // A tap places the cursor, unless it was a double tap.
+                    tc.fling_velocity = 0.0;
+                    if self.input_mouse_click == 1 {
+                        tb.cursor_move_to_visual(pos);
+                        tc.preferred_column = tb.cursor_visual_pos().x;
+                        return true;
+                    }
+                } else if tc.fling_velocity.abs() < FLING_MIN_VELOCITY
+                    || self.tui.mouse_drag_timestamp.elapsed() > FLING_RELEASE_WINDOW
+                {
+                    tc.fling_velocity = 0.0;
+                } else {
+                    self.tui.read_timeout = self.tui.read_timeout.min(FLING_FRAME_DURATION);
+                }
+                false
+            }
+            _ => false,
+        }
+    }
+
     fn textarea_handle_input(
         &mut self,
         tc: &mut TextareaContent,
@@ -2221,10 +3129,11 @@ impl<'a> Context<'a, '_> {
         {
             let mouse = self.tui.mouse_position;
             let inner = node_prev.inner;
+            let track_width = if single_line { 0 } else { 1 + self.tui.scrollbar_hit_slop() };
             let text_rect = Rect {
                 left: inner.left + tb.margin_width(),
                 top: inner.top,
-                right: inner.right - !single_line as CoordType,
+                right: inner.right - track_width,
                 bottom: inner.bottom,
             };
             let track_rect = Rect {
@@ -2237,10 +3146,37 @@ impl<'a> Context<'a, '_> {
                 x: mouse.x - inner.left - tb.margin_width() + tc.scroll_offset.x,
                 y: mouse.y - inner.top + tc.scroll_offset.y,
             };
-
-            if text_rect.contains(self.tui.mouse_down_position) {
+            // Same as the width `render_node` passes to `TextBuffer::render`.
+            let text_width = inner.width() - tb.margin_width() - !single_line as CoordType;
+            let pos = tb.screen_to_visual_pos(pos, tc.scroll_offset.x, text_width);
+
+            if self.input_right_click {
+                // A right-click moves the cursor, unless it hit the selection, so that the
+                // context menu acts on what was clicked. The click is left for `context_menu_input`.
+                if !text_rect.contains(self.tui.mouse_down_position) {
+                    return false;
+                }
+                let on_selection = tb
+                    .selection_range()
+                    .is_some_and(|(beg, end)| beg.visual_pos <= pos && pos < end.visual_pos);
+                if !on_selection {
+                    tb.cursor_move_to_visual(pos);
+                    tc.preferred_column = tb.cursor_visual_pos().x;
+                }
+                return !on_selection;
+            } else if self.tui.touch_mode
+                && !single_line
+                && text_rect.contains(self.tui.mouse_down_position)
+            {
+                make_cursor_visible = self.textarea_handle_touch(tc, tb, pos);
+            } else if text_rect.contains(self.tui.mouse_down_position) {
                 if self.tui.mouse_is_drag {
-                    tb.selection_update_visual(pos);
+                    match &tc.drag_anchor {
+                        Some((unit, anchor)) => {
+                            tb.selection_update_visual_by(pos, *unit, anchor.clone())
+                        }
+                        None => tb.selection_update_visual(pos),
+                    }
                     tc.preferred_column = tb.cursor_visual_pos().x;
 
                     let height = inner.height();
@@ -2279,11 +3215,22 @@ impl<'a> Context<'a, '_> {
                         }
                     }
                 } else {
+                    tc.drag_anchor = None;
                     match self.input_mouse_click {
                         5.. => {}
                         4 => tb.select_all(),
-                        3 => tb.select_line(),
-                        2 => tb.select_word(),
+                        3 | 2 => {
+                            let unit = if self.input_mouse_click == 3 {
+                                tb.select_line();
+                                SelectionUnit::Line
+                            } else {
+                                tb.select_word();
+                                SelectionUnit::Word
+                            };
+                            if let Some((beg, end)) = tb.selection_range() {
+                                tc.drag_anchor = Some((unit, beg.offset..end.offset));
+                            }
+                        }
                         _ => match self.tui.mouse_state {
                             InputMouseState::Left => {
                                 if self.input_mouse_modifiers.contains(kbmod::SHIFT) {
@@ -2302,6 +3249,17 @@ impl<'a> Context<'a, '_> {
             } else if track_rect.contains(self.tui.mouse_down_position) {
                 if self.tui.mouse_state == InputMouseState::Release {
                     tc.scroll_offset_y_drag_start = CoordType::MIN;
+
+                    // A click on the track (not a drag) pages towards the click.
+                    if !self.tui.mouse_is_drag && track_rect.contains(mouse) {
+                        tc.scroll_offset.y += scrollbar_track_click_delta(
+                            track_rect,
+                            mouse.y,
+                            tc.scroll_offset.y,
+                            tb.visual_line_count() - 1,
+                            tc.thumb_height,
+                        );
+                    }
                 } else if self.tui.mouse_is_drag {
                     if tc.scroll_offset_y_drag_start == CoordType::MIN {
                         tc.scroll_offset_y_drag_start = tc.scroll_offset.y;
@@ -2341,7 +3299,7 @@ impl<'a> Context<'a, '_> {
 
             match key {
                 vk::BACK => {
-                    let granularity = if modifiers == kbmod::CTRL {
+                    let granularity = if modifiers == kbmod::CTRL || modifiers == kbmod::ALT {
                         CursorMovement::Word
                     } else {
                         CursorMovement::Grapheme
@@ -2424,6 +3382,7 @@ impl<'a> Context<'a, '_> {
                 }
                 vk::END => {
                     let logical_before = tb.cursor_logical_pos();
+                    let visual_before = tb.cursor_visual_pos();
                     let destination = if modifiers.contains(kbmod::CTRL) {
                         Point::MAX
                     } else {
@@ -2439,6 +3398,32 @@ impl<'a> Context<'a, '_> {
                     if !modifiers.contains(kbmod::CTRL) {
                         let logical_after = tb.cursor_logical_pos();
 
+                        // Without word-wrap, if the line goes on beyond the right edge,
+                        // End first stops at the last column in view and only the second
+                        // time moves to the end of the line. The last column is the one
+                        // `textarea_make_cursor_visible` doesn't scroll for. A wide glyph
+                        // that straddles it is left for the second time, since `goto_visual`
+                        // stops in front of it.
+                        let last_column = tc.scroll_offset.x + tb.text_width() - 10;
+                        let end = tb.cursor_visual_pos();
+                        if !tb.is_word_wrap_enabled()
+                            && !single_line
+                            && last_column > tc.scroll_offset.x
+                            && end.x > last_column
+                        {
+                            let move_to = |tb: &mut TextBuffer, destination| {
+                                if modifiers.contains(kbmod::SHIFT) {
+                                    tb.selection_update_visual(destination);
+                                } else {
+                                    tb.cursor_move_to_visual(destination);
+                                }
+                            };
+                            move_to(tb, Point { x: last_column, y: end.y });
+                            if tb.cursor_visual_pos().x <= visual_before.x {
+                                move_to(tb, end);
+                            }
+                        }
+
                         // If word-wrap is enabled and the user presses End the first time,
                         // it moves to the start of the visual line. The second time they
                         // press it, it moves to the start of the logical line.
@@ -2655,20 +3640,24 @@ impl<'a> Context<'a, '_> {
                 },
                 vk::A => match modifiers {
                     kbmod::CTRL => tb.select_all(),
+                    kbmod::CTRL_ALT => tb.add_to_number(1, false),
+                    kbmod::CTRL_ALT_SHIFT => tb.add_to_number(1, true),
                     _ => return false,
                 },
                 vk::B => match modifiers {
-                    kbmod::ALT if cfg!(target_os = "macos") => {
+                    kbmod::ALT if cfg!(any(target_os = "macos", target_os = "android")) => {
                         // On macOS, terminals commonly emit the Emacs style
                         // Alt+B (ESC b) sequence for Alt+Left.
+                        // In Termux, it's what Volume-Up+B sends.
                         tb.cursor_move_delta(CursorMovement::Word, -1);
                     }
                     _ => return false,
                 },
                 vk::F => match modifiers {
-                    kbmod::ALT if cfg!(target_os = "macos") => {
+                    kbmod::ALT if cfg!(any(target_os = "macos", target_os = "android")) => {
                         // On macOS, terminals commonly emit the Emacs style
                         // Alt+F (ESC f) sequence for Alt+Right.
+                        // In Termux, it's what Volume-Up+F sends.
                         tb.cursor_move_delta(CursorMovement::Word, 1);
                     }
                     _ => return false,
@@ -2681,8 +3670,20 @@ impl<'a> Context<'a, '_> {
                     kbmod::CTRL => tb.select_line(),
                     _ => return false,
                 },
+                vk::D => match modifiers {
+                    kbmod::CTRL if !single_line => tb.duplicate_selected_lines(),
+                    _ => return false,
+                },
+                vk::K => match modifiers {
+                    // Most terminals send Ctrl+K for it, unless they support
+                    // an extended keyboard protocol like Windows Terminal does.
+                    kbmod::CTRL_SHIFT if !single_line => tb.delete_selected_lines(),
+                    _ => return false,
+                },
                 vk::X => match modifiers {
                     kbmod::CTRL => tb.cut(self.clipboard_mut()),
+                    kbmod::CTRL_ALT => tb.add_to_number(-1, false),
+                    kbmod::CTRL_ALT_SHIFT => tb.add_to_number(-1, true),
                     _ => return false,
                 },
                 vk::C => match modifiers {
@@ -2693,8 +3694,13 @@ impl<'a> Context<'a, '_> {
                     kbmod::CTRL => tb.paste(self.clipboard_ref()),
                     _ => return false,
                 },
+                vk::Q => match modifiers {
+                    kbmod::ALT => tb.rewrap(),
+                    _ => return false,
+                },
                 vk::Y => match modifiers {
                     kbmod::CTRL => tb.redo(),
+                    kbmod::ALT => tb.paste_rotate(self.clipboard_mut()),
                     _ => return false,
                 },
                 vk::Z => match modifiers {
@@ -2716,7 +3722,7 @@ impl<'a> Context<'a, '_> {
             write = unicode::strip_newline(&write[..end]);
         }
         if !write.is_empty() {
-            tb.write_canon(write);
+            tb.write_typed(write);
             change_preferred_column = true;
             make_cursor_visible = true;
         }
@@ -2837,44 +3843,61 @@ impl<'a> Context<'a, '_> {
                 sc.scroll_offset.y += self.input_scroll_delta.y;
                 self.set_input_consumed();
             } else if self.tui.mouse_state != InputMouseState::None {
+                // We don't need to look up the previous track node,
+                // since it has a fixed size based on the container size.
+                let track_rect = Rect {
+                    left: container_rect.right - self.tui.scrollbar_hit_slop(),
+                    top: container_rect.top,
+                    right: container_rect.right + 1,
+                    bottom: container_rect.bottom,
+                };
+
                 match self.tui.mouse_state {
                     InputMouseState::Left => {
-                        if self.tui.mouse_is_drag {
-                            // We don't need to look up the previous track node,
-                            // since it has a fixed size based on the container size.
-                            let track_rect = Rect {
-                                left: container_rect.right,
-                                top: container_rect.top,
-                                right: container_rect.right + 1,
-                                bottom: container_rect.bottom,
-                            };
-                            if track_rect.contains(self.tui.mouse_down_position) {
-                                if sc.scroll_offset_y_drag_start == CoordType::MIN {
-                                    sc.scroll_offset_y_drag_start = sc.scroll_offset.y;
-                                }
-
-                                let content = prev_container.children.first.unwrap().borrow();
-                                let content_rect = content.inner;
-                                let content_height = content_rect.height();
-                                let track_height = track_rect.height();
-                                let scrollable_height = content_height - track_height;
-
-                                if scrollable_height > 0 {
-                                    let trackable = track_height - sc.thumb_height;
-                                    let delta_y =
-                                        self.tui.mouse_position.y - self.tui.mouse_down_position.y;
-                                    sc.scroll_offset.y = sc.scroll_offset_y_drag_start
-                                        + (delta_y as i64 * scrollable_height as i64
-                                            / trackable as i64)
-                                            as CoordType;
-                                }
+                        if self.tui.mouse_is_drag
+                            && track_rect.contains(self.tui.mouse_down_position)
+                        {
+                            if sc.scroll_offset_y_drag_start == CoordType::MIN {
+                                sc.scroll_offset_y_drag_start = sc.scroll_offset.y;
+                            }
 
-                                self.set_input_consumed();
+                            let content = prev_container.children.first.unwrap().borrow();
+                            let content_rect = content.inner;
+                            let content_height = content_rect.height();
+                            let track_height = track_rect.height();
+                            let scrollable_height = content_height - track_height;
+
+                            if scrollable_height > 0 {
+                                let trackable = track_height - sc.thumb_height;
+                                let delta_y =
+                                    self.tui.mouse_position.y - self.tui.mouse_down_position.y;
+                                sc.scroll_offset.y = sc.scroll_offset_y_drag_start
+                                    + (delta_y as i64 * scrollable_height as i64 / trackable as i64)
+                                        as CoordType;
                             }
+
+                            self.set_input_consumed();
                         }
                     }
                     InputMouseState::Release => {
                         sc.scroll_offset_y_drag_start = CoordType::MIN;
+
+                        // A click on the track (not a drag) pages towards the click.
+                        if !self.tui.mouse_is_drag
+                            && track_rect.contains(self.tui.mouse_down_position)
+                            && track_rect.contains(self.tui.mouse_position)
+                        {
+                            let content = prev_container.children.first.unwrap().borrow();
+                            let scrollable_height = content.inner.height() - track_rect.height();
+                            sc.scroll_offset.y += scrollbar_track_click_delta(
+                                track_rect,
+                                self.tui.mouse_position.y,
+                                sc.scroll_offset.y,
+                                scrollable_height,
+                                sc.thumb_height,
+                            );
+                            self.set_input_consumed();
+                        }
                     }
                     _ => {}
                 }
@@ -3108,13 +4131,165 @@ impl<'a> Context<'a, '_> {
         if contains_focus {
             {
                 let mut node = selected_next.borrow_mut();
-                node.attributes.bg = self.indexed(IndexedColor::Green);
-                node.attributes.fg = self.contrasted(self.indexed(IndexedColor::Green));
+                node.attributes.bg = self.focus_color();
+                node.attributes.fg = self.contrasted(self.focus_color());
             }
             self.steal_focus_for(selected_next);
         }
     }
 
+    /// Creates a list that only draws the rows that are visible, see [`VirtualList`].
+    /// `height` is the number of rows it shows, until the layout says otherwise.
+    ///
+    /// Returns the rows to draw, each between [`Context::virtual_list_item_begin`]
+    /// and [`Context::virtual_list_item_end`]. Finish with [`Context::virtual_list_end`].
+    pub fn virtual_list_begin(
+        &mut self,
+        classname: &'static str,
+        list: &mut VirtualList,
+        height: CoordType,
+    ) -> Range<usize> {
+        self.block_begin(classname);
+        self.attr_focusable();
+
+        let mut node = self.tree.last_node.borrow_mut();
+        list.height = match self.tui.prev_node_map.get(node.id) {
+            Some(prev) => prev.borrow().inner.height(),
+            None => height,
+        }
+        .max(1) as usize;
+        list.scroll = list.scroll.min(list.len().saturating_sub(list.height));
+
+        node.content = NodeContent::VirtualList(VirtualListContent {
+            scroll: list.scroll as CoordType,
+            rows: list.len() as CoordType,
+            thumb_height: 0,
+        });
+        node.intrinsic_size.height = height.max(1);
+        node.intrinsic_size_set = true;
+
+        list.scroll..list.len().min(list.scroll + list.height)
+    }
+
+    /// Begins a row of the current virtual list. It's a styled label, see
+    /// [`Context::styled_label_begin`], with the selection already marked.
+    pub fn virtual_list_item_begin(&mut self, list: &VirtualList, row: usize) {
+        let focused = self.tui.is_subtree_focused(&self.tree.current_node.borrow());
+        let is_cursor = focused && list.cursor == row;
+
+        self.next_block_id_mixin(row as u64);
+        self.styled_label_begin("item");
+        self.styled_label_add_text(if is_cursor { "> " } else { "  " });
+
+        if is_cursor {
+            self.attr_background_rgba(self.focus_color());
+            self.attr_foreground_rgba(self.contrasted(self.focus_color()));
+        } else if list.is_selected(row) {
+            let bg = self.tui.named(NamedColor::Selection).unwrap_or_else(|| {
+                self.indexed(IndexedColor::Foreground).oklab_blend(self.indexed_alpha(
+                    IndexedColor::BrightBlue,
+                    1,
+                    2,
+                ))
+            });
+            self.attr_background_rgba(bg);
+        }
+    }
+
+    /// Ends the current row of the virtual list.
+    pub fn virtual_list_item_end(&mut self) {
+        self.styled_label_end();
+    }
+
+    /// Ends the current virtual list and handles its input: The arrow keys, Page Up/Down,
+    /// Home and End, each with Shift to extend the selection, clicks and the mouse wheel.
+    ///
+    /// Returns the row that was activated with Enter or a double-click, if any.
+    pub fn virtual_list_end(&mut self, list: &mut VirtualList) -> Option<usize> {
+        self.block_end();
+
+        if self.input_consumed || list.is_empty() {
+            return None;
+        }
+
+        let node = self.tree.last_node.borrow();
+        let prev = self.tui.prev_node_map.get(node.id)?.borrow();
+        let rect = prev.inner;
+        let thumb_height = match &prev.content {
+            NodeContent::VirtualList(vc) => vc.thumb_height,
+            _ => 0,
+        };
+        let mouse = self.tui.mouse_position;
+        let last = list.len() - 1;
+        let mut activated = None;
+
+        if self.input_scroll_delta.y != 0 && rect.contains(mouse) {
+            list.scroll_by(self.input_scroll_delta.y);
+        } else if self.input_mouse_click != 0 && self.tui.was_mouse_down_on_subtree(&node) {
+            let track = Rect {
+                left: rect.right - self.tui.scrollbar_hit_slop(),
+                top: rect.top,
+                right: rect.right + 1,
+                bottom: rect.bottom,
+            };
+            if track.contains(mouse) {
+                let scrollable = list.len().saturating_sub(list.height) as CoordType;
+                let delta = scrollbar_track_click_delta(
+                    track,
+                    mouse.y,
+                    list.scroll as CoordType,
+                    scrollable,
+                    thumb_height,
+                );
+                list.scroll_by(delta);
+            } else {
+                let row = list.scroll + (mouse.y - rect.top).max(0) as usize;
+                if row > last {
+                    return None;
+                }
+                if self.input_mouse_modifiers.contains(kbmod::CTRL) {
+                    list.toggle(row);
+                } else {
+                    list.select(row, self.input_mouse_modifiers.contains(kbmod::SHIFT));
+                }
+                if self.input_mouse_click == 2 {
+                    activated = Some(row);
+                }
+            }
+        } else if self.tui.is_subtree_focused(&node)
+            && let Some(key) = self.input_keyboard
+        {
+            let page = list.height.saturating_sub(1).max(1);
+            let extend = key.modifiers() == kbmod::SHIFT;
+            if key.modifiers() != kbmod::NONE && !extend {
+                match key {
+                    _ if key == kbmod::CTRL | vk::A => list.select_all(),
+                    _ if key == kbmod::CTRL | vk::SPACE => list.toggle(list.cursor),
+                    _ => return None,
+                }
+            } else {
+                match key.key() {
+                    vk::UP => list.select(list.cursor.saturating_sub(1), extend),
+                    vk::DOWN => list.select((list.cursor + 1).min(last), extend),
+                    vk::PRIOR => list.select(list.cursor.saturating_sub(page), extend),
+                    vk::NEXT => list.select((list.cursor + page).min(last), extend),
+                    vk::HOME => list.select(0, extend),
+                    vk::END => list.select(last, extend),
+                    vk::RETURN if !extend => activated = Some(list.cursor),
+                    _ => return None,
+                }
+            }
+        } else {
+            return None;
+        }
+
+        drop(prev);
+        drop(node);
+        self.set_input_consumed();
+        self.needs_rerender();
+        activated
+    }
+
     /// Creates a menubar, to be shown at the top of the screen.
     pub fn menubar_begin(&mut self) {
         self.table_begin("menubar");
@@ -3148,8 +4323,8 @@ impl<'a> Context<'a, '_> {
             self.attr_foreground_rgba(self.tui.floater_default_fg);
 
             if self.is_focused() {
-                self.attr_background_rgba(self.indexed(IndexedColor::Green));
-                self.attr_foreground_rgba(self.contrasted(self.indexed(IndexedColor::Green)));
+                self.attr_background_rgba(self.focus_color());
+                self.attr_foreground_rgba(self.contrasted(self.focus_color()));
             }
 
             self.next_block_id_mixin(mixin);
@@ -3181,7 +4356,8 @@ impl<'a> Context<'a, '_> {
         accelerator: char,
         shortcut: InputKey,
     ) -> bool {
-        self.menubar_menu_checkbox(text, accelerator, shortcut, false)
+        let hint = self.shortcut_text(shortcut);
+        self.menubar_menu_item(text, accelerator, &hint, None)
     }
 
     /// Appends a checkbox to the current menu.
@@ -3192,6 +4368,34 @@ impl<'a> Context<'a, '_> {
         accelerator: char,
         shortcut: InputKey,
         checked: bool,
+    ) -> bool {
+        let hint = self.shortcut_text(shortcut);
+        self.menubar_menu_item(text, accelerator, &hint, Some(checked))
+    }
+
+    /// Like [`Context::menubar_menu_button`], for commands whose shortcut isn't a single key,
+    /// like a chord. `hint` is shown in its place.
+    pub fn menubar_menu_button_hint(&mut self, text: &str, accelerator: char, hint: &str) -> bool {
+        self.menubar_menu_item(text, accelerator, hint, None)
+    }
+
+    /// Like [`Context::menubar_menu_checkbox`], with a `hint` instead of the shortcut.
+    pub fn menubar_menu_checkbox_hint(
+        &mut self,
+        text: &str,
+        accelerator: char,
+        hint: &str,
+        checked: bool,
+    ) -> bool {
+        self.menubar_menu_item(text, accelerator, hint, Some(checked))
+    }
+
+    fn menubar_menu_item(
+        &mut self,
+        text: &str,
+        accelerator: char,
+        hint: &str,
+        checked: Option<bool>,
     ) -> bool {
         self.table_next_row();
         self.attr_focusable();
@@ -3202,19 +4406,26 @@ impl<'a> Context<'a, '_> {
         }
 
         if self.is_focused() {
-            self.attr_background_rgba(self.indexed(IndexedColor::Green));
-            self.attr_foreground_rgba(self.contrasted(self.indexed(IndexedColor::Green)));
+            self.attr_background_rgba(self.focus_color());
+            self.attr_foreground_rgba(self.contrasted(self.focus_color()));
         }
 
         let clicked =
             self.button_activated() || self.consume_shortcut(InputKey::new(accelerator as u32));
 
-        self.button_label(
-            "menu_checkbox",
-            text,
-            ButtonStyle::default().bracketed(false).checked(checked).accelerator(accelerator),
-        );
-        self.menubar_shortcut(shortcut);
+        let mut style = ButtonStyle::default().bracketed(false).accelerator(accelerator);
+        if let Some(checked) = checked {
+            style = style.checked(checked);
+        }
+        self.button_label("menu_checkbox", text, style);
+
+        if hint.is_empty() {
+            self.block_begin("shortcut");
+            self.block_end();
+        } else {
+            self.label("shortcut", hint);
+        }
+        self.attr_padding(Rect { left: 2, top: 0, right: 2, bottom: 0 });
 
         if clicked {
             // TODO: This should reassign the previous focused path.
@@ -3318,30 +4529,89 @@ impl<'a> Context<'a, '_> {
         self.styled_label_end();
     }
 
-    fn menubar_shortcut(&mut self, shortcut: InputKey) {
-        let shortcut_letter = shortcut.value() as u8 as char;
-        if shortcut_letter.is_ascii_uppercase() {
-            let mut shortcut_text = ArenaString::new_in(self.arena());
-            if shortcut.modifiers_contains(kbmod::CTRL) {
-                shortcut_text.push_str(self.tui.modifier_translations.ctrl);
-                shortcut_text.push('+');
-            }
-            if shortcut.modifiers_contains(kbmod::ALT) {
-                shortcut_text.push_str(self.tui.modifier_translations.alt);
-                shortcut_text.push('+');
-            }
-            if shortcut.modifiers_contains(kbmod::SHIFT) {
-                shortcut_text.push_str(self.tui.modifier_translations.shift);
-                shortcut_text.push('+');
-            }
-            shortcut_text.push(shortcut_letter);
+    /// Spells out a shortcut like "Ctrl+Shift+F" or "Shift+F2". Empty for [`vk::NULL`].
+    fn shortcut_text(&self, shortcut: InputKey) -> ArenaString<'a> {
+        let mut text = ArenaString::new_in(self.arena());
+        let key = shortcut.key();
+        let letter = key.value() as u8 as char;
+        let function = (vk::F1.value()..=vk::F12.value()).contains(&key.value());
+        if !letter.is_ascii_uppercase() && !function {
+            return text;
+        }
 
-            self.label("shortcut", &shortcut_text);
+        if shortcut.modifiers_contains(kbmod::CTRL) {
+            text.push_str(self.tui.modifier_translations.ctrl);
+            text.push('+');
+        }
+        if shortcut.modifiers_contains(kbmod::ALT) {
+            text.push_str(self.tui.modifier_translations.alt);
+            text.push('+');
+        }
+        if shortcut.modifiers_contains(kbmod::SHIFT) {
+            text.push_str(self.tui.modifier_translations.shift);
+            text.push('+');
+        }
+        if function {
+            _ = write!(text, "F{}", key.value() - vk::F1.value() + 1);
         } else {
-            self.block_begin("shortcut");
-            self.block_end();
+            text.push(letter);
         }
-        self.attr_padding(Rect { left: 2, top: 0, right: 2, bottom: 0 });
+        text
+    }
+}
+
+/// Splits `text` into lines of at most `width` columns, breaking between words where possible.
+/// Returns each line along with its width.
+fn wrap_text(text: &str, width: CoordType, tab_size: CoordType) -> Vec<(&str, CoordType)> {
+    let bytes = text.as_bytes();
+    let mut cfg = unicode::MeasurementConfig::new(&bytes)
+        .with_word_wrap_column(width)
+        .with_tab_size(tab_size);
+    let mut lines = Vec::new();
+    let mut beg = 0;
+
+    for y in 1.. {
+        let end = cfg.goto_visual(Point { x: 0, y });
+        let last = end.visual_pos.y < y;
+        let line = text[beg..if last { text.len() } else { end.offset }].trim_end();
+        let line_width = unicode::MeasurementConfig::new(&line.as_bytes())
+            .with_tab_size(tab_size)
+            .goto_visual(Point { x: CoordType::MAX, y: 0 })
+            .visual_pos
+            .x;
+        lines.push((line, line_width.min(width)));
+        if last {
+            break;
+        }
+        beg = end.offset;
+    }
+
+    lines
+}
+
+/// Returns how far to scroll when the scrollbar `track` gets clicked at `y`:
+/// One page up if the click was above the thumb, one page down if below.
+fn scrollbar_track_click_delta(
+    track: Rect,
+    y: CoordType,
+    scroll_offset: CoordType,
+    scrollable_height: CoordType,
+    thumb_height: CoordType,
+) -> CoordType {
+    if scrollable_height <= 0 {
+        return 0;
+    }
+
+    let trackable = (track.height() - thumb_height).max(0) as i64;
+    let offset = scroll_offset.clamp(0, scrollable_height) as i64;
+    let thumb_top = track.top + (offset * trackable / scrollable_height as i64) as CoordType;
+
+    if y < thumb_top {
+        -track.height()
+    } else if y >= thumb_top + thumb_height {
+        track.height()
+    } else {
+        0
     }
 }
 
@@ -3644,6 +4914,8 @@ struct NodeAttributes {
     focusable: bool,
     focus_well: bool, // Prevents focus from leaving via Tab
     focus_void: bool, // Prevents focus from entering via Tab
+    focus_keep: bool, // Prevents clicks from moving the focus
+    shadow: bool,     // Casts a shadow to the bottom right
 }
 
 /// NOTE: Must not contain items that require drop().
@@ -3657,22 +4929,32 @@ struct ListContent<'a> {
 struct TableContent<'a> {
     columns: Vec<CoordType, &'a Arena>,
     cell_gap: Size,
+    /// See [`Context::table_stack_below`].
+    stacked: bool,
 }
 
 /// NOTE: Must not contain items that require drop().
+#[derive(Clone, Copy, PartialEq)]
 struct StyledTextChunk {
     offset: usize,
     fg: StraightRgba,
+    bg: StraightRgba,
     attr: Attributes,
 }
 
-const INVALID_STYLED_TEXT_CHUNK: StyledTextChunk =
-    StyledTextChunk { offset: usize::MAX, fg: StraightRgba::zero(), attr: Attributes::None };
+const INVALID_STYLED_TEXT_CHUNK: StyledTextChunk = StyledTextChunk {
+    offset: usize::MAX,
+    fg: StraightRgba::zero(),
+    bg: StraightRgba::zero(),
+    attr: Attributes::None,
+};
 
 /// NOTE: Must not contain items that require drop().
 struct TextContent<'a> {
     text: ArenaString<'a>,
     chunks: Vec<StyledTextChunk, &'a Arena>,
+    /// The ranges of `text` that are links, see [`Context::styled_label_set_link`].
+    links: Vec<(Range<usize>, &'a str), &'a Arena>,
     overflow: Overflow,
 }
 
@@ -3686,11 +4968,25 @@ struct TextareaContent<'a> {
     scroll_offset_x_max: CoordType,
     thumb_height: CoordType,
     preferred_column: CoordType,
+    /// The word or line that a double or triple click selected.
+    /// Dragging afterwards extends the selection in these units.
+    drag_anchor: Option<(SelectionUnit, Range<usize>)>,
+    /// In touch mode: What the current drag does.
+    touch_drag: TouchDrag,
+    /// In touch mode: The remaining momentum of a flick in lines per frame.
+    fling_velocity: f32,
 
     single_line: bool,
     has_focus: bool,
 }
 
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum TouchDrag {
+    None,
+    Scroll,
+    Select,
+}
+
 /// NOTE: Must not contain items that require drop().
 #[derive(Clone)]
 struct ScrollareaContent {
@@ -3699,6 +4995,14 @@ struct ScrollareaContent {
     thumb_height: CoordType,
 }
 
+/// NOTE: Must not contain items that require drop().
+#[derive(Clone)]
+struct VirtualListContent {
+    scroll: CoordType,
+    rows: CoordType,
+    thumb_height: CoordType,
+}
+
 /// NOTE: Must not contain items that require drop().
 #[derive(Default)]
 enum NodeContent<'a> {
@@ -3710,6 +5014,7 @@ enum NodeContent<'a> {
     Text(TextContent<'a>),
     Textarea(TextareaContent<'a>),
     Scrollarea(ScrollareaContent),
+    VirtualList(VirtualListContent),
 }
 
 /// NOTE: Must not contain items that require drop().
@@ -3788,7 +5093,8 @@ impl Node<'_> {
     fn outer_to_inner(&self, mut outer: Rect) -> Rect {
         let l = self.attributes.bordered;
         let t = self.attributes.bordered;
-        let r = self.attributes.bordered || matches!(self.content, NodeContent::Scrollarea(..));
+        let r = self.attributes.bordered
+            || matches!(self.content, NodeContent::Scrollarea(..) | NodeContent::VirtualList(..));
         let b = self.attributes.bordered;
 
         outer.left += self.attributes.padding.left + l as CoordType;
@@ -3803,7 +5109,8 @@ impl Node<'_> {
     fn intrinsic_to_outer(&self) -> Size {
         let l = self.attributes.bordered;
         let t = self.attributes.bordered;
-        let r = self.attributes.bordered || matches!(self.content, NodeContent::Scrollarea(..));
+        let r = self.attributes.bordered
+            || matches!(self.content, NodeContent::Scrollarea(..) | NodeContent::VirtualList(..));
         let b = self.attributes.bordered;
 
         let mut size = self.intrinsic_size;
@@ -3821,6 +5128,44 @@ impl Node<'_> {
     /// Computes the intrinsic size of this node and its children.
     fn compute_intrinsic_size(&mut self) {
         match &mut self.content {
+            NodeContent::Table(spec) if spec.stacked => {
+                // Each row is as wide as its widest cell and as tall as all of them together.
+                let mut total_width = 0;
+                let mut total_height = 0;
+
+                for row in Tree::iterate_siblings(self.children.first) {
+                    let mut row = row.borrow_mut();
+                    let mut row_width = 0;
+                    let mut row_height = 0;
+
+                    for cell in Tree::iterate_siblings(row.children.first) {
+                        let mut cell = cell.borrow_mut();
+                        cell.compute_intrinsic_size();
+
+                        let size = cell.intrinsic_to_outer();
+                        row_width = row_width.max(size.width);
+                        row_height += size.height;
+                    }
+
+                    row_height +=
+                        spec.cell_gap.height * row.child_count.saturating_sub(1) as CoordType;
+                    row.intrinsic_size = Size { width: row_width, height: row_height };
+                    row.intrinsic_size_set = true;
+
+                    let size = row.intrinsic_to_outer();
+                    total_width = total_width.max(size.width);
+                    total_height += size.height;
+                }
+
+                total_height +=
+                    spec.cell_gap.height * self.child_count.saturating_sub(1) as CoordType;
+
+                if !self.intrinsic_size_set {
+                    self.intrinsic_size.width = total_width;
+                    self.intrinsic_size.height = total_height;
+                    self.intrinsic_size_set = true;
+                }
+            }
             NodeContent::Table(spec) => {
                 // Calculate each row's height and the maximum width of each of its columns.
                 for row in Tree::iterate_siblings(self.children.first) {
@@ -3913,6 +5258,45 @@ impl Node<'_> {
         }
 
         match &mut self.content {
+            NodeContent::Table(spec) if spec.stacked => {
+                let width = self.inner.right - self.inner.left;
+                let x = self.inner.left;
+                let mut y = self.inner.top;
+
+                for row in Tree::iterate_siblings(self.children.first) {
+                    let mut row = row.borrow_mut();
+                    let size = row.intrinsic_to_outer();
+                    row.outer = Rect { left: x, top: y, right: x + width, bottom: y + size.height };
+                    row.outer = row.outer.intersect(self.inner);
+                    row.inner = row.outer_to_inner(row.outer);
+                    row.outer_clipped = row.outer.intersect(clip);
+                    row.inner_clipped = row.inner.intersect(clip);
+
+                    for cell in Tree::iterate_siblings(row.children.first) {
+                        let mut cell = cell.borrow_mut();
+                        let size = cell.intrinsic_to_outer();
+                        let left = x + (width - size.width).max(0) / 2;
+                        cell.outer = Rect {
+                            left,
+                            top: y,
+                            right: left + size.width,
+                            bottom: y + size.height,
+                        };
+                        cell.outer = cell.outer.intersect(self.inner);
+                        cell.inner = cell.outer_to_inner(cell.outer);
+                        cell.outer_clipped = cell.outer.intersect(clip);
+                        cell.inner_clipped = cell.inner.intersect(clip);
+                        cell.layout_children(clip);
+
+                        y += size.height + spec.cell_gap.height;
+                    }
+
+                    // The gap after the last cell doubles as the one between the rows.
+                    if row.child_count == 0 {
+                        y += spec.cell_gap.height;
+                    }
+                }
+            }
             NodeContent::Table(spec) => {
                 let width = self.inner.right - self.inner.left;
                 let mut x = self.inner.left;
@@ -0,0 +1,481 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Enough of "UAX #9: Unicode Bidirectional Algorithm" to show Arabic and Hebrew
+//! in the right order. Each line is its own paragraph and each row is reordered
+//! on its own. Explicit embeddings and isolates are ignored like other format
+//! characters, and the character classes come from a table of ranges, which
+//! leaves out a few of the less common scripts.
+
+use stdext::arena::Arena;
+
+use super::{Cursor, MeasurementConfig, Utf8Chars};
+use crate::helpers::{CoordType, Point};
+
+/// The direction of a paragraph.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    /// The direction of the first strong character, as per rules P2 and P3.
+    #[default]
+    Auto,
+    Ltr,
+    Rtl,
+}
+
+/// One grapheme cluster of a row, see [`reorder`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VisualCluster {
+    /// Where it starts when laid out in logical order.
+    pub x: CoordType,
+    pub width: CoordType,
+    /// Whether it's in a right-to-left run, where brackets are mirrored.
+    pub rtl: bool,
+}
+
+/// The bidi character types, "Bidi_Class" in the UCD.
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Class {
+    L,
+    R,
+    AL,
+    EN,
+    ES,
+    ET,
+    AN,
+    CS,
+    NSM,
+    BN,
+    B,
+    S,
+    WS,
+    ON,
+}
+
+use Class::*;
+
+/// The classes of the non-ASCII characters. The ones in between are `L`.
+#[rustfmt::skip]
+const CLASSES: &[(u32, u32, Class)] = &[
+    (0x80, 0x84, BN), (0x85, 0x85, B), (0x86, 0x9F, BN), (0xA0, 0xA0, CS), (0xA1, 0xA1, ON),
+    (0xA2, 0xA5, ET), (0xA6, 0xA9, ON), (0xAB, 0xAC, ON), (0xAD, 0xAD, BN), (0xAE, 0xAF, ON),
+    (0xB0, 0xB1, ET), (0xB2, 0xB3, EN), (0xB4, 0xB4, ON), (0xB6, 0xB8, ON), (0xB9, 0xB9, EN),
+    (0xBB, 0xBF, ON), (0xD7, 0xD7, ON), (0xF7, 0xF7, ON),
+    (0x300, 0x36F, NSM), (0x483, 0x489, NSM),
+    // Hebrew
+    (0x590, 0x590, R), (0x591, 0x5BD, NSM), (0x5BE, 0x5BE, R), (0x5BF, 0x5BF, NSM),
+    (0x5C0, 0x5C0, R), (0x5C1, 0x5C2, NSM), (0x5C3, 0x5C3, R), (0x5C4, 0x5C5, NSM),
+    (0x5C6, 0x5C6, R), (0x5C7, 0x5C7, NSM), (0x5C8, 0x5FF, R),
+    // Arabic, Syriac, Thaana
+    (0x600, 0x605, AN), (0x606, 0x607, ON), (0x608, 0x608, AL), (0x609, 0x60A, ET),
+    (0x60B, 0x60B, AL), (0x60C, 0x60C, CS), (0x60D, 0x60D, AL), (0x60E, 0x60F, ON),
+    (0x610, 0x61A, NSM), (0x61B, 0x64A, AL), (0x64B, 0x65F, NSM), (0x660, 0x669, AN),
+    (0x66A, 0x66A, ET), (0x66B, 0x66C, AN), (0x66D, 0x66F, AL), (0x670, 0x670, NSM),
+    (0x671, 0x6D5, AL), (0x6D6, 0x6DC, NSM), (0x6DD, 0x6DD, AN), (0x6DE, 0x6DE, ON),
+    (0x6DF, 0x6E4, NSM), (0x6E5, 0x6E6, AL), (0x6E7, 0x6E8, NSM), (0x6E9, 0x6E9, ON),
+    (0x6EA, 0x6ED, NSM), (0x6EE, 0x6EF, AL), (0x6F0, 0x6F9, EN), (0x6FA, 0x710, AL),
+    (0x711, 0x711, NSM), (0x712, 0x72F, AL), (0x730, 0x74A, NSM), (0x74B, 0x7A5, AL),
+    (0x7A6, 0x7B0, NSM), (0x7B1, 0x7BF, AL),
+    // NKo, Samaritan, Mandaic
+    (0x7C0, 0x7EA, R), (0x7EB, 0x7F3, NSM), (0x7F4, 0x7F5, R), (0x7F6, 0x7F9, ON),
+    (0x7FA, 0x7FC, R), (0x7FD, 0x7FD, NSM), (0x7FE, 0x815, R), (0x816, 0x819, NSM),
+    (0x81A, 0x81A, R), (0x81B, 0x823, NSM), (0x824, 0x824, R), (0x825, 0x827, NSM),
+    (0x828, 0x828, R), (0x829, 0x82D, NSM), (0x82E, 0x858, R), (0x859, 0x85B, NSM),
+    (0x85C, 0x85F, R),
+    // Syriac Supplement, Arabic Extended
+    (0x860, 0x88F, AL), (0x890, 0x891, AN), (0x892, 0x896, AL), (0x897, 0x89F, NSM),
+    (0x8A0, 0x8C9, AL), (0x8CA, 0x8E1, NSM), (0x8E2, 0x8E2, AN), (0x8E3, 0x8FF, NSM),
+    // Punctuation, symbols and format characters
+    (0x1680, 0x1680, WS), (0x2000, 0x200A, WS), (0x200B, 0x200D, BN), (0x200F, 0x200F, R),
+    (0x2010, 0x2027, ON), (0x2028, 0x2028, WS), (0x2029, 0x2029, B), (0x202A, 0x202E, BN),
+    (0x202F, 0x202F, CS), (0x2030, 0x2034, ET), (0x2035, 0x2043, ON), (0x2044, 0x2044, CS),
+    (0x2045, 0x205E, ON), (0x205F, 0x205F, WS), (0x2060, 0x206F, BN), (0x2070, 0x2070, EN),
+    (0x2074, 0x2079, EN), (0x207A, 0x207B, ES), (0x207C, 0x207E, ON), (0x2080, 0x2089, EN),
+    (0x208A, 0x208B, ES), (0x208C, 0x208E, ON), (0x20A0, 0x20CF, ET), (0x20D0, 0x20F0, NSM),
+    (0x2100, 0x2101, ON), (0x2103, 0x2106, ON), (0x2108, 0x2109, ON), (0x2114, 0x2114, ON),
+    (0x2116, 0x2118, ON), (0x211E, 0x2123, ON), (0x2125, 0x2125, ON), (0x2127, 0x2127, ON),
+    (0x2129, 0x2129, ON), (0x212E, 0x212E, ET), (0x2140, 0x2144, ON), (0x214A, 0x214D, ON),
+    (0x2150, 0x215F, ON), (0x2189, 0x218B, ON), (0x2190, 0x2211, ON), (0x2212, 0x2212, ES),
+    (0x2213, 0x2213, ET), (0x2214, 0x2487, ON), (0x2488, 0x249B, EN), (0x24EA, 0x2BFF, ON),
+    (0x2CE5, 0x2CEA, ON), (0x2CF9, 0x2CFF, ON), (0x2E00, 0x2E7F, ON), (0x2E80, 0x2FFF, ON),
+    (0x3000, 0x3000, WS), (0x3001, 0x3004, ON), (0x3008, 0x3020, ON), (0x302A, 0x302D, NSM),
+    (0x3030, 0x3030, ON), (0x3099, 0x309A, NSM), (0x30A0, 0x30A0, ON), (0x30FB, 0x30FB, ON),
+    (0xA490, 0xA4C6, ON), (0xA60D, 0xA60F, ON), (0xA66F, 0xA67F, NSM), (0xA700, 0xA721, ON),
+    (0xA788, 0xA788, ON),
+    // Hebrew and Arabic presentation forms
+    (0xFB1D, 0xFB1D, R), (0xFB1E, 0xFB1E, NSM), (0xFB1F, 0xFB28, R), (0xFB29, 0xFB29, ES),
+    (0xFB2A, 0xFB4F, R), (0xFB50, 0xFD3D, AL), (0xFD3E, 0xFD4F, ON), (0xFD50, 0xFDCF, AL),
+    (0xFDF0, 0xFDFC, AL), (0xFDFD, 0xFDFF, ON), (0xFE00, 0xFE0F, NSM), (0xFE10, 0xFE19, ON),
+    (0xFE20, 0xFE2F, NSM), (0xFE30, 0xFE4F, ON), (0xFE50, 0xFE50, CS), (0xFE51, 0xFE51, ON),
+    (0xFE52, 0xFE52, CS), (0xFE54, 0xFE54, ON), (0xFE55, 0xFE55, CS), (0xFE56, 0xFE5E, ON),
+    (0xFE5F, 0xFE5F, ET), (0xFE60, 0xFE61, ON), (0xFE62, 0xFE63, ES), (0xFE64, 0xFE68, ON),
+    (0xFE69, 0xFE6A, ET), (0xFE6B, 0xFE6B, ON), (0xFE70, 0xFEFE, AL), (0xFEFF, 0xFEFF, BN),
+    // Fullwidth forms
+    (0xFF01, 0xFF02, ON), (0xFF03, 0xFF05, ET), (0xFF06, 0xFF0A, ON), (0xFF0B, 0xFF0B, ES),
+    (0xFF0C, 0xFF0C, CS), (0xFF0D, 0xFF0D, ES), (0xFF0E, 0xFF0F, CS), (0xFF10, 0xFF19, EN),
+    (0xFF1A, 0xFF1A, CS), (0xFF1B, 0xFF20, ON), (0xFF3B, 0xFF40, ON), (0xFF5B, 0xFF65, ON),
+    (0xFFE0, 0xFFE1, ET), (0xFFE2, 0xFFE4, ON), (0xFFE5, 0xFFE6, ET), (0xFFE8, 0xFFEE, ON),
+    (0xFFF9, 0xFFFD, ON),
+    // Right-to-left scripts of the SMP
+    (0x10800, 0x10CFF, R), (0x10D00, 0x10D23, AL), (0x10D24, 0x10D27, NSM),
+    (0x10D30, 0x10D39, AN), (0x10D40, 0x10E5F, R), (0x10E60, 0x10E7E, AN),
+    (0x10E80, 0x10EFF, R), (0x10F00, 0x10F2F, R), (0x10F30, 0x10F45, AL),
+    (0x10F46, 0x10F50, NSM), (0x10F51, 0x10F6F, AL), (0x10F70, 0x10FFF, R),
+    (0x1E800, 0x1EC6F, R), (0x1EC70, 0x1ECBF, AL), (0x1ECC0, 0x1ECFF, R),
+    (0x1ED00, 0x1ED4F, AL), (0x1ED50, 0x1EDFF, R), (0x1EE00, 0x1EEEF, AL),
+    (0x1EEF0, 0x1EEF1, ON), (0x1EEF2, 0x1EEFF, AL), (0x1EF00, 0x1EFFF, R),
+    // Emoji and other symbols, tags and variation selectors
+    (0x1F000, 0x1FAFF, ON), (0xE0001, 0xE007F, BN), (0xE0100, 0xE01EF, NSM),
+];
+
+fn class(ch: char) -> Class {
+    let cp = ch as u32;
+    if cp < 0x80 {
+        return match ch {
+            '\t' | '\x0b' | '\x1f' => S,
+            '\n' | '\r' | '\x1c'..='\x1e' => B,
+            '\x0c' | ' ' => WS,
+            '\0'..='\x1b' | '\x7f' => BN,
+            '0'..='9' => EN,
+            '+' | '-' => ES,
+            '#' | '$' | '%' => ET,
+            ',' | '.' | '/' | ':' => CS,
+            'A'..='Z' | 'a'..='z' => L,
+            _ => ON,
+        };
+    }
+    match CLASSES.binary_search_by(|&(beg, end, _)| {
+        if end < cp {
+            std::cmp::Ordering::Less
+        } else if beg > cp {
+            std::cmp::Ordering::Greater
+        } else {
+            std::cmp::Ordering::Equal
+        }
+    }) {
+        Ok(i) => CLASSES[i].2,
+        Err(_) => L,
+    }
+}
+
+/// Whether `text` contains right-to-left characters, i.e. whether it needs to be reordered.
+pub fn has_rtl(text: &[u8]) -> bool {
+    // Hebrew is the first right-to-left script, at U+0590 = D6 90.
+    text.iter().any(|&b| b >= 0xD6)
+        && Utf8Chars::new(text, 0).any(|ch| matches!(class(ch), R | AL | AN))
+}
+
+/// Whether the first strong character in `text` is right-to-left (rules P2 and P3).
+/// `None` if there's none.
+pub fn first_strong_rtl(text: &[u8]) -> Option<bool> {
+    Utf8Chars::new(text, 0).find_map(|ch| match class(ch) {
+        L => Some(false),
+        R | AL => Some(true),
+        _ => None,
+    })
+}
+
+/// Returns the mirrored counterpart of a bracket, which right-to-left runs show instead.
+pub fn mirror(ch: char) -> char {
+    match ch {
+        '(' => ')',
+        ')' => '(',
+        '<' => '>',
+        '>' => '<',
+        '[' => ']',
+        ']' => '[',
+        '{' => '}',
+        '}' => '{',
+        '«' => '»',
+        '»' => '«',
+        '‹' => '›',
+        '›' => '‹',
+        _ => ch,
+    }
+}
+
+/// Lays out a row of `text`, measured by `cfg` starting at `start`, and returns its
+/// grapheme clusters in visual order. `rtl` is the direction of its paragraph.
+pub fn reorder<'a>(
+    arena: &'a Arena,
+    text: &[u8],
+    cfg: MeasurementConfig<'_>,
+    start: Cursor,
+    rtl: bool,
+) -> Vec<VisualCluster, &'a Arena> {
+    let mut cfg = cfg.with_cursor(start);
+    let mut clusters = Vec::new_in(arena);
+    let mut classes = Vec::new_in(arena);
+    let mut cursor = start;
+
+    loop {
+        let next = cfg.goto_logical(Point { x: cursor.logical_pos.x + 1, y: 0 });
+        if next.offset == cursor.offset {
+            break;
+        }
+        let ch = Utf8Chars::new(text, cursor.offset).next().unwrap_or(' ');
+        let width = next.visual_pos.x - cursor.visual_pos.x;
+        clusters.push(VisualCluster { x: cursor.visual_pos.x, width, rtl: false });
+        classes.push(class(ch));
+        cursor = next;
+    }
+
+    let levels = resolve_levels(arena, &mut classes, rtl);
+    for (cluster, &level) in clusters.iter_mut().zip(&levels) {
+        cluster.rtl = level & 1 != 0;
+    }
+
+    // L2: From the highest level to the lowest odd one, reverse every
+    // sequence of clusters that are at that level or higher.
+    let highest = levels.iter().copied().max().unwrap_or(0);
+    let lowest_odd = levels.iter().copied().min().unwrap_or(0) | 1;
+    let mut levels = levels;
+    for level in (lowest_odd..=highest).rev() {
+        let mut i = 0;
+        while i < levels.len() {
+            if levels[i] < level {
+                i += 1;
+                continue;
+            }
+            let beg = i;
+            while i < levels.len() && levels[i] >= level {
+                i += 1;
+            }
+            clusters[beg..i].reverse();
+            levels[beg..i].reverse();
+        }
+    }
+
+    clusters
+}
+
+/// Returns where the cluster at column `x` of the row in logical order is shown.
+/// Columns past the end of the row stay where they are.
+pub fn logical_to_visual(clusters: &[VisualCluster], x: CoordType) -> CoordType {
+    let mut visual = clusters.iter().map(|c| c.x).min().unwrap_or(x);
+    for c in clusters {
+        if (c.x..c.x + c.width).contains(&x) {
+            return visual;
+        }
+        visual += c.width;
+    }
+    x
+}
+
+/// The opposite of [`logical_to_visual`]: Returns the logical column of the cluster
+/// that is shown at column `x`.
+pub fn visual_to_logical(clusters: &[VisualCluster], x: CoordType) -> CoordType {
+    let mut visual = clusters.iter().map(|c| c.x).min().unwrap_or(x);
+    for c in clusters {
+        if (visual..visual + c.width).contains(&x) {
+            return c.x;
+        }
+        visual += c.width;
+    }
+    x
+}
+
+/// Applies the rules W1 to I2 and L1 and returns the embedding level of each cluster.
+/// `classes` are changed along the way.
+fn resolve_levels<'a>(arena: &'a Arena, classes: &mut [Class], rtl: bool) -> Vec<u8, &'a Arena> {
+    let n = classes.len();
+    let base = rtl as u8;
+    // The type of the start and end of the row ("sos" and "eos").
+    let edge = if rtl { R } else { L };
+    let mut original = Vec::with_capacity_in(n, arena);
+    original.extend_from_slice(classes);
+
+    // X9 removes the format characters. Here they take on the type of what's before them.
+    // W1: Nonspacing marks do the same.
+    let mut prev = edge;
+    for c in classes.iter_mut() {
+        if matches!(*c, NSM | BN) {
+            *c = prev;
+        }
+        prev = *c;
+    }
+
+    // W2: European numbers after Arabic letters are Arabic numbers.
+    // W3: Arabic letters are right-to-left.
+    let mut strong = edge;
+    for c in classes.iter_mut() {
+        match *c {
+            L | R | AL => strong = *c,
+            EN if strong == AL => *c = AN,
+            _ => {}
+        }
+        if *c == AL {
+            *c = R;
+        }
+    }
+
+    // W4: A single separator between two numbers of the same type takes on their type.
+    for i in 1..n.saturating_sub(1) {
+        let (before, after) = (classes[i - 1], classes[i + 1]);
+        if before == after
+            && (classes[i] == ES && before == EN || classes[i] == CS && matches!(before, EN | AN))
+        {
+            classes[i] = before;
+        }
+    }
+
+    // W5: Terminators next to European numbers become European numbers.
+    let mut i = 0;
+    while i < n {
+        if classes[i] != ET {
+            i += 1;
+            continue;
+        }
+        let beg = i;
+        while i < n && classes[i] == ET {
+            i += 1;
+        }
+        if (beg > 0 && classes[beg - 1] == EN) || (i < n && classes[i] == EN) {
+            classes[beg..i].fill(EN);
+        }
+    }
+
+    // W6: The remaining separators and terminators are neutral.
+    // W7: European numbers in left-to-right text are left-to-right.
+    let mut strong = edge;
+    for c in classes.iter_mut() {
+        match *c {
+            ES | ET | CS => *c = ON,
+            L | R => strong = *c,
+            EN if strong == L => *c = L,
+            _ => {}
+        }
+    }
+
+    // N1 and N2: Neutrals take on the direction of the text around them
+    // if it's the same on both sides, and the paragraph's otherwise.
+    let direction = |c: Class| if c == L { L } else { R };
+    let mut i = 0;
+    while i < n {
+        if !matches!(classes[i], B | S | WS | ON) {
+            i += 1;
+            continue;
+        }
+        let beg = i;
+        while i < n && matches!(classes[i], B | S | WS | ON) {
+            i += 1;
+        }
+        let before = if beg == 0 { edge } else { direction(classes[beg - 1]) };
+        let after = if i == n { edge } else { direction(classes[i]) };
+        classes[beg..i].fill(if before == after { before } else { edge });
+    }
+
+    // I1 and I2
+    let mut levels = Vec::with_capacity_in(n, arena);
+    levels.extend(classes.iter().map(|&c| match (rtl, c) {
+        (false, R) => 1,
+        (false, AN | EN) => 2,
+        (true, L | EN | AN) => 2,
+        _ => base,
+    }));
+
+    // L1: Separators and the whitespace before them and at the end of the row
+    // are at the paragraph's level.
+    let mut trailing = true;
+    for i in (0..n).rev() {
+        match original[i] {
+            B | S => {
+                levels[i] = base;
+                trailing = true;
+            }
+            WS | BN if trailing => levels[i] = base,
+            _ => trailing = false,
+        }
+    }
+
+    levels
+}
+
+#[cfg(test)]
+mod tests {
+    use stdext::arena::scratch_arena;
+
+    use super::*;
+    use crate::helpers::MEBI;
+
+    /// Returns the row the way it's shown.
+    fn visual(text: &str, rtl: bool) -> String {
+        let scratch = scratch_arena(None);
+        let bytes = text.as_bytes();
+        let cfg = MeasurementConfig::new(&bytes);
+        let clusters = reorder(&scratch, bytes, cfg, Cursor::default(), rtl);
+        let mut offsets = Vec::new();
+        let mut cfg = MeasurementConfig::new(&bytes);
+        for c in &clusters {
+            let beg = cfg.goto_visual(Point { x: c.x, y: 0 }).offset;
+            let end = cfg.goto_visual(Point { x: c.x + c.width, y: 0 }).offset;
+            offsets.push((beg, end, c.rtl));
+            cfg = MeasurementConfig::new(&bytes);
+        }
+        offsets
+            .into_iter()
+            .map(|(beg, end, rtl)| {
+                text[beg..end]
+                    .chars()
+                    .map(|ch| if rtl { mirror(ch) } else { ch })
+                    .collect::<String>()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_reorder() {
+        stdext::arena::init(16 * MEBI).unwrap();
+
+        assert_eq!(visual("abc def", false), "abc def");
+        assert_eq!(visual("abc אבג def", false), "abc גבא def");
+        assert_eq!(visual("אבג abc", true), "abc גבא");
+        assert_eq!(visual("אבג abc", false), "גבא abc");
+        // Numbers keep their order, even in right-to-left text.
+        assert_eq!(visual("אבג 123 דה", false), "הד 123 גבא");
+        assert_eq!(visual("عدد 12", false), "12 ددع");
+        // In right-to-left text, the sign of a number goes to its right.
+        assert_eq!(visual("-12% אבג", true), "גבא 12%-");
+        // Brackets are mirrored, so that they still face the text inside them.
+        assert_eq!(visual("א(ב)", true), "(ב)א");
+        assert_eq!(visual("a (ב) c", false), "a (ב) c");
+        // Whitespace at the end stays at the end, as far as the paragraph is concerned.
+        assert_eq!(visual("abc ", true), " abc");
+        // Marks stay with their letter.
+        assert_eq!(
+            visual("\u{5E9}\u{5B8}\u{5C1}\u{5DC}\u{5D5}\u{5B9}\u{5DD}", false),
+            "\u{5DD}\u{5D5}\u{5B9}\u{5DC}\u{5E9}\u{5B8}\u{5C1}"
+        );
+    }
+
+    #[test]
+    fn test_logical_to_visual() {
+        stdext::arena::init(16 * MEBI).unwrap();
+        let scratch = scratch_arena(None);
+
+        // |a\tאב|, with tabs 4 wide.
+        let bytes = "a\tאב".as_bytes();
+        let cfg = MeasurementConfig::new(&bytes).with_tab_size(4);
+        let clusters = reorder(&scratch, bytes, cfg, Cursor::default(), false);
+        assert_eq!(
+            &clusters[..],
+            [
+                VisualCluster { x: 0, width: 1, rtl: false },
+                VisualCluster { x: 1, width: 3, rtl: false },
+                VisualCluster { x: 5, width: 1, rtl: true },
+                VisualCluster { x: 4, width: 1, rtl: true },
+            ]
+        );
+        assert_eq!(logical_to_visual(&clusters, 4), 5);
+        assert_eq!(logical_to_visual(&clusters, 5), 4);
+        assert_eq!(logical_to_visual(&clusters, 6), 6);
+        assert_eq!(visual_to_logical(&clusters, 4), 5);
+        assert_eq!(visual_to_logical(&clusters, 2), 1);
+        assert_eq!(visual_to_logical(&clusters, 9), 9);
+
+        assert!(has_rtl("a אב".as_bytes()));
+        assert!(!has_rtl("äöü €".as_bytes()));
+        assert_eq!(first_strong_rtl("12 אב a".as_bytes()), Some(true));
+        assert_eq!(first_strong_rtl("- 1".as_bytes()), None);
+    }
+}
@@ -0,0 +1,141 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Case conversions for Selection > Uppercase and friends.
+
+use crate::icu;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Case {
+    Upper,
+    Lower,
+    /// The first letter of every word uppercase, the rest lowercase.
+    Title,
+    Snake,
+    Camel,
+    Kebab,
+}
+
+/// Converts `text` to the given case.
+///
+/// Snake, camel and kebab case are meant for identifiers: Every line is split into words
+/// at anything but letters and digits, and where the case changes, as in `parseHTTPRequest`.
+/// The words are then joined up again. Whitespace around them is left as is.
+pub fn convert_case(text: &str, case: Case) -> String {
+    match case {
+        Case::Upper => icu::to_upper(text),
+        Case::Lower => icu::to_lower(text),
+        Case::Title => title_case(text),
+        Case::Snake | Case::Camel | Case::Kebab => {
+            let mut out = String::with_capacity(text.len());
+            for line in text.split_inclusive('\n') {
+                let content = line.trim_end_matches(['\r', '\n']);
+                let lead = content.len() - content.trim_start().len();
+                let trimmed = content.trim();
+                out.push_str(&content[..lead]);
+                out.push_str(&join_words(trimmed, case));
+                out.push_str(&line[lead + trimmed.len()..]);
+            }
+            out
+        }
+    }
+}
+
+fn title_case(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut in_word = false;
+    for (i, c) in text.char_indices() {
+        let s = &text[i..i + c.len_utf8()];
+        if c.is_alphanumeric() {
+            out.push_str(&if in_word { icu::to_lower(s) } else { icu::to_upper(s) });
+            in_word = true;
+        } else {
+            out.push(c);
+            // "don't" is a single word.
+            in_word &= c == '\'' || c == '’';
+        }
+    }
+    out
+}
+
+fn join_words(text: &str, case: Case) -> String {
+    let mut out = String::with_capacity(text.len() + 8);
+    for (i, word) in words(text).into_iter().enumerate() {
+        match case {
+            Case::Camel if i > 0 => {
+                let mut chars = word.chars();
+                if let Some(first) = chars.next() {
+                    out.push_str(&icu::to_upper(&first.to_string()));
+                    out.push_str(&icu::to_lower(chars.as_str()));
+                }
+            }
+            _ => {
+                if i > 0 {
+                    out.push(if case == Case::Kebab { '-' } else { '_' });
+                }
+                out.push_str(&icu::to_lower(word));
+            }
+        }
+    }
+    out
+}
+
+/// Splits `text` into words, see [`convert_case`].
+fn words(text: &str) -> Vec<&str> {
+    let chars: Vec<(usize, char)> = text.char_indices().collect();
+    let mut words = Vec::new();
+    let mut beg = None;
+
+    for (i, &(off, c)) in chars.iter().enumerate() {
+        if !c.is_alphanumeric() {
+            if let Some(beg) = beg.take() {
+                words.push(&text[beg..off]);
+            }
+            continue;
+        }
+
+        if let Some(b) = beg
+            && c.is_uppercase()
+        {
+            let prev = chars[i - 1].1;
+            let next_lower = chars.get(i + 1).is_some_and(|&(_, c)| c.is_lowercase());
+            // "fooBar" and "v2Beta", or the "R" that ends the acronym in "HTTPRequest".
+            if !prev.is_uppercase() || next_lower {
+                words.push(&text[b..off]);
+                beg = Some(off);
+            }
+        }
+        beg.get_or_insert(off);
+    }
+
+    if let Some(beg) = beg {
+        words.push(&text[beg..]);
+    }
+    words
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_words() {
+        assert_eq!(words("parseHTTPRequest"), ["parse", "HTTP", "Request"]);
+        assert_eq!(words("Foo_bar-baz qux"), ["Foo", "bar", "baz", "qux"]);
+        assert_eq!(words("utf8Decoder v2Beta"), ["utf8", "Decoder", "v2", "Beta"]);
+        assert_eq!(words("SCREAMING_CASE"), ["SCREAMING", "CASE"]);
+        assert_eq!(words("__"), [] as [&str; 0]);
+    }
+
+    #[test]
+    fn test_convert_case() {
+        assert_eq!(convert_case("Straße", Case::Upper), "STRASSE");
+        assert_eq!(convert_case("ΟΔΟΣ", Case::Lower), "οδο\u{3c2}");
+        assert_eq!(convert_case("élan VITAL, don't", Case::Title), "Élan Vital, Don't");
+        assert_eq!(convert_case("parseHTTPRequest", Case::Snake), "parse_http_request");
+        assert_eq!(convert_case("parse_http_request", Case::Camel), "parseHttpRequest");
+        assert_eq!(convert_case("ParseHttp request", Case::Kebab), "parse-http-request");
+        assert_eq!(convert_case("ÜberGröße", Case::Snake), "über_größe");
+        assert_eq!(convert_case("  fooBar\r\n\tbazQux\n", Case::Snake), "  foo_bar\r\n\tbaz_qux\n");
+    }
+}
@@ -2,6 +2,7 @@
 // Licensed under the MIT License.
 
 use std::hint::cold_path;
+use std::sync::atomic::{AtomicU8, AtomicUsize, Ordering};
 
 use super::Utf8Chars;
 use super::tables::*;
@@ -10,21 +11,85 @@ use crate::helpers::{CoordType, Point};
 
 // On one hand it's disgusting that I wrote this as a global variable, but on the
 // other hand, this isn't a public library API, and it makes the code a lot cleaner,
-// because we don't need to inject this once-per-process value everywhere.
-static mut AMBIGUOUS_WIDTH: usize = 1;
+// because we don't need to inject this value everywhere. It's atomic, because the
+// user can change it at runtime and the render thread measures text, too.
+static AMBIGUOUS_WIDTH: AtomicUsize = AtomicUsize::new(1);
 
 /// Sets the width of "ambiguous" width characters as per "UAX #11: East Asian Width".
 ///
-/// Defaults to 1.
+/// Defaults to 1. Text measured before the change keeps its old layout,
+/// so callers must reflow their buffers afterwards.
 pub fn setup_ambiguous_width(ambiguous_width: CoordType) {
-    unsafe { AMBIGUOUS_WIDTH = ambiguous_width as usize };
+    AMBIGUOUS_WIDTH.store(ambiguous_width.clamp(1, 2) as usize, Ordering::Relaxed);
 }
 
 #[inline]
 fn ambiguous_width() -> usize {
-    // SAFETY: This is a global variable that is set once per process.
-    // It is never changed after that, so this is safe to call.
-    unsafe { AMBIGUOUS_WIDTH }
+    AMBIGUOUS_WIDTH.load(Ordering::Relaxed)
+}
+
+// Same as above. 0 draws every emoji sequence as a single glyph. See `EmojiWidths::pack`.
+static EMOJI_WIDTHS: AtomicU8 = AtomicU8::new(0);
+
+/// How the terminal draws emoji sequences. Unicode says that each of them is
+/// a single glyph, 2 columns wide, but many terminals draw their parts one by one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EmojiWidths {
+    /// Whether ZWJ sequences like "👨‍👩‍👧" are a single glyph.
+    pub zwj_sequences: bool,
+    /// Whether skin tone modifiers merge with the emoji before them, like in "👍🏽".
+    pub modifiers: bool,
+    /// The width of a flag like "🇩🇪", made of 2 regional indicators. 2 to 4.
+    pub flags: CoordType,
+}
+
+impl Default for EmojiWidths {
+    fn default() -> Self {
+        Self { zwj_sequences: true, modifiers: true, flags: 2 }
+    }
+}
+
+impl EmojiWidths {
+    /// Derives the widths from how many columns the terminal used
+    /// to draw "👨‍👩‍👧", "👍🏽" and "🇩🇪", respectively.
+    pub fn from_probes(family: CoordType, modifier: CoordType, flag: CoordType) -> Self {
+        Self { zwj_sequences: family <= 2, modifiers: modifier <= 2, flags: flag.clamp(2, 4) }
+    }
+
+    fn pack(self) -> u8 {
+        !self.zwj_sequences as u8
+            | (!self.modifiers as u8) << 1
+            | ((self.flags.clamp(2, 4) - 2) as u8) << 2
+    }
+}
+
+/// Sets how the terminal draws emoji sequences.
+///
+/// Defaults to [`EmojiWidths::default`]. Like with [`setup_ambiguous_width`],
+/// callers must reflow their buffers afterwards.
+pub fn setup_emoji_widths(widths: EmojiWidths) {
+    EMOJI_WIDTHS.store(widths.pack(), Ordering::Relaxed);
+}
+
+/// Called for every character `ch` that joins a grapheme cluster, unless `emoji` is 0.
+/// If the terminal draws the cluster in parts and `ch` begins a new one,
+/// the `width` of the part so far is moved into `parts_width`.
+#[cold]
+fn split_emoji_cluster(emoji: u8, ch: char, width: &mut CoordType, parts_width: &mut CoordType) {
+    let split = match ch {
+        '\u{200D}' => emoji & 1 != 0,
+        '\u{1F3FB}'..='\u{1F3FF}' => emoji & 2 != 0,
+        // The second indicator of a flag. The first one gets the rest of the flag's width.
+        '\u{1F1E6}'..='\u{1F1FF}' if emoji >> 2 != 0 => {
+            *parts_width += (emoji >> 2) as CoordType;
+            true
+        }
+        _ => false,
+    };
+    if split {
+        *parts_width += (*width).min(2);
+        *width = 0;
+    }
 }
 
 /// Stores a position inside a [`ReadableDocument`].
@@ -61,13 +126,20 @@ pub struct MeasurementConfig<'doc> {
     cursor: Cursor,
     tab_size: CoordType,
     word_wrap_column: CoordType,
+    emoji: u8,
     buffer: &'doc dyn ReadableDocument,
 }
 
 impl<'doc> MeasurementConfig<'doc> {
     /// Creates a new [`MeasurementConfig`] for the given document.
     pub fn new(buffer: &'doc dyn ReadableDocument) -> Self {
-        Self { cursor: Default::default(), tab_size: 8, word_wrap_column: 0, buffer }
+        Self {
+            cursor: Default::default(),
+            tab_size: 8,
+            word_wrap_column: 0,
+            emoji: EMOJI_WIDTHS.load(Ordering::Relaxed),
+            buffer,
+        }
     }
 
     /// Sets the initial cursor to the given position.
@@ -95,6 +167,14 @@ impl<'doc> MeasurementConfig<'doc> {
         self
     }
 
+    /// Sets how the terminal draws emoji sequences.
+    ///
+    /// Defaults to what was given to [`setup_emoji_widths`].
+    pub fn with_emoji_widths(mut self, widths: EmojiWidths) -> Self {
+        self.emoji = widths.pack();
+        self
+    }
+
     /// Navigates **forward** to the given absolute offset.
     ///
     /// # Returns
@@ -189,6 +269,7 @@ impl<'doc> MeasurementConfig<'doc> {
             let mut offset_next_cluster;
             let mut state = 0;
             let mut width = 0;
+            let mut parts_width = 0;
 
             // Since we want to measure the width of the current cluster,
             // by necessity we need to seek to the next cluster.
@@ -225,6 +306,9 @@ impl<'doc> MeasurementConfig<'doc> {
                 if ucd_grapheme_cluster_joins_done(state) {
                     break;
                 }
+                if self.emoji != 0 {
+                    split_emoji_cluster(self.emoji, ch, &mut width, &mut parts_width);
+                }
             }
 
             if offset_next_cluster == offset {
@@ -236,8 +320,8 @@ impl<'doc> MeasurementConfig<'doc> {
                 continue;
             }
 
-            // The max. width of a terminal cell is 2.
-            width = width.min(2);
+            // The max. width of a terminal cell is 2, but a cluster can span several.
+            width = parts_width + width.min(2);
 
             // Tabs require special handling because they can have a variable width.
             if props_last_char == ucd_tab_properties() {
@@ -367,6 +451,7 @@ impl<'doc> MeasurementConfig<'doc> {
                     let mut offset_next_cluster;
                     let mut state = 0;
                     let mut width = 0;
+                    let mut parts_width = 0;
 
                     // Since we want to measure the width of the current cluster,
                     // by necessity we need to seek to the next cluster.
@@ -407,6 +492,9 @@ impl<'doc> MeasurementConfig<'doc> {
                         if ucd_grapheme_cluster_joins_done(state) {
                             break;
                         }
+                        if self.emoji != 0 {
+                            split_emoji_cluster(self.emoji, ch, &mut width, &mut parts_width);
+                        }
                     }
 
                     if offset_next_cluster == offset {
@@ -418,8 +506,8 @@ impl<'doc> MeasurementConfig<'doc> {
                         continue;
                     }
 
-                    // The max. width of a terminal cell is 2.
-                    width = width.min(2);
+                    // The max. width of a terminal cell is 2, but a cluster can span several.
+                    width = parts_width + width.min(2);
 
                     // Tabs require special handling because they can have a variable width.
                     if props_last_char == ucd_tab_properties() {
@@ -568,6 +656,38 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_measure_forward_emoji_widths() {
+        let measure = |text: &str, widths: EmojiWidths| {
+            let bytes = text.as_bytes();
+            let mut cfg = MeasurementConfig::new(&bytes).with_emoji_widths(widths);
+            let cursor = cfg.goto_offset(text.len());
+            (cursor.logical_pos.x, cursor.visual_pos.x)
+        };
+        let family = "a👨‍👩‍👧b";
+        let thumbs = "a👍🏽b";
+        let flag = "a🇩🇪b";
+
+        let joined = EmojiWidths::from_probes(2, 2, 2);
+        assert_eq!(joined, EmojiWidths::default());
+        assert_eq!(measure(family, joined), (3, 4));
+        assert_eq!(measure(thumbs, joined), (3, 4));
+        assert_eq!(measure(flag, joined), (3, 4));
+
+        // Each part is drawn on its own, but the clusters still move the cursor as one.
+        let split = EmojiWidths::from_probes(6, 4, 4);
+        assert_eq!(measure(family, split), (3, 8));
+        assert_eq!(measure(thumbs, split), (3, 6));
+        assert_eq!(measure(flag, split), (3, 6));
+        assert_eq!(measure("👩🏽‍💻", split), (1, 6));
+        assert_eq!(measure("🇩🇪", EmojiWidths::from_probes(2, 2, 3)), (1, 3));
+
+        let cursor = MeasurementConfig::new(&family.as_bytes())
+            .with_emoji_widths(split)
+            .goto_visual(Point { x: 4, y: 0 });
+        assert_eq!((cursor.offset, cursor.visual_pos.x), (1, 1));
+    }
+
     #[test]
     fn test_measure_forward_word_wrap() {
         //   |foo␣  |
@@ -3,9 +3,15 @@
 
 //! Everything related to Unicode lives here.
 
+pub mod bidi;
+mod case;
 mod measurement;
+mod name_tables;
+mod names;
 mod tables;
 mod utf8;
 
+pub use case::*;
 pub use measurement::*;
+pub use names::*;
 pub use utf8::*;
@@ -0,0 +1,216 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Character names, for Edit > Insert Character.
+//!
+//! See `unicode-gen/src/names.rs` for how [`NAMES`] is encoded.
+
+use super::name_tables::*;
+
+const HANGUL_BASE: u32 = 0xAC00;
+const HANGUL_LEADS: [&str; 19] = [
+    "G", "GG", "N", "D", "DD", "R", "M", "B", "BB", "S", "SS", "", "J", "JJ", "C", "K", "T", "P",
+    "H",
+];
+const HANGUL_VOWELS: [&str; 21] = [
+    "A", "AE", "YA", "YAE", "EO", "E", "YEO", "YE", "O", "WA", "WAE", "OE", "YO", "U", "WEO", "WE",
+    "WI", "YU", "EU", "YI", "I",
+];
+const HANGUL_TRAILS: [&str; 28] = [
+    "", "G", "GG", "GS", "N", "NJ", "NH", "D", "L", "LG", "LM", "LB", "LS", "LT", "LP", "LH", "M",
+    "B", "BS", "S", "SS", "NG", "J", "C", "K", "T", "P", "H",
+];
+
+/// The name of `c`, like "LATIN SMALL LETTER A", or `None` for unnamed ones like controls.
+pub fn char_name(c: char) -> Option<String> {
+    let cp = c as u32;
+
+    if let Some(s) = cp.checked_sub(HANGUL_BASE)
+        && s < 11172
+    {
+        let (l, v, t) = (s / 588, s % 588 / 28, s % 28);
+        return Some(format!(
+            "HANGUL SYLLABLE {}{}{}",
+            HANGUL_LEADS[l as usize], HANGUL_VOWELS[v as usize], HANGUL_TRAILS[t as usize]
+        ));
+    }
+
+    if let Some(&(_, _, prefix)) = NAME_PATTERNS.iter().find(|p| (p.0..=p.1).contains(&cp)) {
+        return Some(format!("{prefix}{cp:04X}"));
+    }
+
+    let run = NAME_RUNS.partition_point(|r| r.0 <= cp).checked_sub(1)?;
+    let (first, count, pos) = NAME_RUNS[run];
+    if cp - first >= count {
+        return None;
+    }
+
+    let mut pos = pos as usize;
+    let mut words = Vec::new();
+    for _ in first..=cp {
+        decode_name(&mut pos, &mut words);
+    }
+
+    let mut name = String::new();
+    for (i, &w) in words.iter().enumerate() {
+        if i > 0 {
+            name.push(' ');
+        }
+        name.push_str(word(w));
+    }
+    Some(name)
+}
+
+/// Finds up to `limit` characters whose name contains all the words in `query`,
+/// best matches first. A query that's a codepoint, like `U+00E9` or `e9`, finds that one, too.
+///
+/// Names spelled out with their codepoint, like those of CJK ideographs and Hangul syllables,
+/// are only found by their codepoint.
+pub fn search_char_names(query: &str, limit: usize) -> Vec<char> {
+    let mut results = Vec::new();
+    if let Some(c) = parse_codepoint(query) {
+        results.push(c);
+    }
+
+    let query = query.to_ascii_uppercase();
+    let terms: Vec<&str> = query.split_whitespace().collect();
+    if terms.is_empty() {
+        return results;
+    }
+
+    // How well each of the terms matches each word: 0 = not at all, 1 = within it, 2 = fully.
+    let word_count = NAME_WORD_OFFSETS.len() - 1;
+    let matches: Vec<Vec<u8>> = terms
+        .iter()
+        .map(|&term| {
+            (0..word_count)
+                .map(|w| {
+                    let word = word(w);
+                    if word == term {
+                        2
+                    } else if word.contains(term) {
+                        1
+                    } else {
+                        0
+                    }
+                })
+                .collect()
+        })
+        .collect();
+
+    let mut found = Vec::new();
+    let mut words = Vec::new();
+    for &(first, count, pos) in &NAME_RUNS {
+        let mut pos = pos as usize;
+        for cp in first..first + count {
+            decode_name(&mut pos, &mut words);
+
+            let mut whole = true;
+            let mut all = true;
+            for m in &matches {
+                let best = words.iter().map(|&w| m[w]).max().unwrap_or(0);
+                whole &= best == 2;
+                all &= best != 0;
+            }
+            if !all {
+                continue;
+            }
+
+            // Exact names first, then those that contain the terms as whole words.
+            // Among those, the shorter the name, the closer it is to what was asked for.
+            let score = match (whole, words.len() == terms.len()) {
+                (true, true) => 0,
+                (true, false) => 1,
+                _ => 2,
+            };
+            found.push((score, words.len(), cp));
+        }
+    }
+
+    found.sort_unstable();
+    for (_, _, cp) in found {
+        if results.len() >= limit {
+            break;
+        }
+        if let Some(c) = char::from_u32(cp)
+            && !results.contains(&c)
+        {
+            results.push(c);
+        }
+    }
+    results.truncate(limit);
+    results
+}
+
+/// Parses a codepoint written as `U+1F600`, `0x1F600` or just `1F600`.
+pub fn parse_codepoint(text: &str) -> Option<char> {
+    let text = text.trim();
+    let hex = ["U+", "u+", "0x", "0X"].iter().find_map(|p| text.strip_prefix(p)).unwrap_or(text);
+    if hex.is_empty() || hex.len() > 6 || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    char::from_u32(u32::from_str_radix(hex, 16).ok()?)
+}
+
+/// Formats `c` as `U+XXXX`.
+pub fn format_codepoint(c: char) -> String {
+    format!("U+{:04X}", c as u32)
+}
+
+fn word(i: usize) -> &'static str {
+    &NAME_WORDS[NAME_WORD_OFFSETS[i] as usize..NAME_WORD_OFFSETS[i + 1] as usize]
+}
+
+/// Decodes the word indices of the name at `pos` into `words` and moves `pos` past it.
+fn decode_name(pos: &mut usize, words: &mut Vec<usize>) {
+    words.clear();
+    let count = NAMES[*pos];
+    *pos += 1;
+    for _ in 0..count {
+        let mut w = NAMES[*pos] as usize;
+        *pos += 1;
+        if w >= 0x80 {
+            w = (w & 0x7f) << 8 | NAMES[*pos] as usize;
+            *pos += 1;
+        }
+        words.push(w);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_char_name() {
+        assert_eq!(char_name('A').as_deref(), Some("LATIN CAPITAL LETTER A"));
+        assert_eq!(char_name('é').as_deref(), Some("LATIN SMALL LETTER E WITH ACUTE"));
+        assert_eq!(char_name('😀').as_deref(), Some("GRINNING FACE"));
+        assert_eq!(char_name('中').as_deref(), Some("CJK UNIFIED IDEOGRAPH-4E2D"));
+        assert_eq!(char_name('한').as_deref(), Some("HANGUL SYLLABLE HAN"));
+        assert_eq!(char_name('가').as_deref(), Some("HANGUL SYLLABLE GA"));
+        assert_eq!(char_name('\n'), None);
+        assert_eq!(char_name('\u{0378}'), None);
+    }
+
+    #[test]
+    fn test_search_char_names() {
+        assert_eq!(search_char_names("grinning face", 1), ['😀']);
+        assert_eq!(search_char_names("e acute small", 1), ['é']);
+        assert_eq!(search_char_names("u+2603", 1), ['☃']);
+        assert!(search_char_names("arrow", 100).len() == 100);
+        assert!(search_char_names("xyzzy", 10).is_empty());
+        assert!(search_char_names("  ", 10).is_empty());
+    }
+
+    #[test]
+    fn test_parse_codepoint() {
+        assert_eq!(parse_codepoint("U+00E9"), Some('é'));
+        assert_eq!(parse_codepoint("0x1f600"), Some('😀'));
+        assert_eq!(parse_codepoint(" 41 "), Some('A'));
+        assert_eq!(parse_codepoint("D800"), None);
+        assert_eq!(parse_codepoint("110000"), None);
+        assert_eq!(parse_codepoint("face"), Some('\u{face}'));
+        assert_eq!(format_codepoint('😀'), "U+1F600");
+    }
+}
@@ -1,6 +1,7 @@
 // Copyright (c) Microsoft Corporation.
 // Licensed under the MIT License.
 
+mod names;
 mod rules;
 
 use std::collections::HashMap;
@@ -247,6 +248,7 @@ Usage: grapheme-table-gen [options...] <ucd.nounihan.grouped.xml>
                         Expose tab and linefeed as grapheme cluster properties
   --no-ambiguous        Treat all ambiguous characters as narrow
   --line-breaks         Store and expose line break information
+  --names               Output the character name index instead (Rust only)
 
 Download ucd.nounihan.grouped.xml at:
   https://www.unicode.org/Public/UCD/latest/ucdxml/ucd.nounihan.grouped.zip
@@ -270,6 +272,7 @@ fn main() -> anyhow::Result<()> {
         arg_line_breaks: args.contains("--line-breaks"),
         ..Default::default()
     };
+    let arg_names = args.contains("--names");
     let arg_input = args.free_from_os_str(|s| -> Result<PathBuf, &'static str> { Ok(s.into()) })?;
     let arg_remaining = args.finish();
     if !arg_remaining.is_empty() {
@@ -278,6 +281,16 @@ fn main() -> anyhow::Result<()> {
 
     let input = std::fs::read_to_string(arg_input)?;
     let doc = roxmltree::Document::parse(&input)?;
+
+    if arg_names {
+        if !matches!(out.arg_lang, Language::Rust) {
+            bail!("--names is only supported with --lang=rust");
+        }
+        let buf = names::generate_names(&doc)?;
+        std::io::stdout().write_all(buf.as_bytes())?;
+        return Ok(());
+    }
+
     out.ucd = extract_values_from_ucd(&doc, &out)?;
 
     // Find the best trie configuration over the given block sizes (2^2 - 2^8) and stages (4).
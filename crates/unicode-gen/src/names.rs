@@ -0,0 +1,183 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! `--names`: A compact index of the character names, for searching them by name.
+//!
+//! Names are split into words, which are stored once each, sorted by how often they're used.
+//! Each name is then a word count followed by the word indices, where indices below 128
+//! take up 1 byte and all others 2, with the high bit set on the first one.
+
+use std::collections::HashMap;
+use std::fmt::Write as FmtWrite;
+
+use anyhow::{Context, bail};
+
+const HANGUL_SYLLABLES: std::ops::RangeInclusive<usize> = 0xAC00..=0xD7A3;
+// Limits how many names need to be skipped over when looking one up.
+const MAX_RUN_LEN: usize = 64;
+
+pub fn generate_names(doc: &roxmltree::Document) -> anyhow::Result<String> {
+    let ns = "http://www.unicode.org/ns/2003/ucd/1.0";
+    let root = doc.root_element();
+    let description = root
+        .children()
+        .find(|n| n.has_tag_name((ns, "description")))
+        .context("missing ucd description")?;
+    let repertoire = root
+        .children()
+        .find(|n| n.has_tag_name((ns, "repertoire")))
+        .context("missing ucd repertoire")?;
+    let description = description.text().unwrap_or_default();
+
+    let mut names: Vec<(usize, &str)> = Vec::new();
+    let mut patterns: Vec<(usize, usize, &str)> = Vec::new();
+
+    for group in repertoire.children().filter(|n| n.is_element()) {
+        let group_name = group.attribute("na").unwrap_or("");
+
+        for char in group.children().filter(|n| n.is_element()) {
+            let name = char.attribute("na").unwrap_or(group_name);
+            let range = super::extract_range(&char);
+
+            if name.is_empty() || HANGUL_SYLLABLES.contains(range.start()) {
+                // Hangul syllable names are derived from their jamo at runtime.
+                continue;
+            }
+
+            // Names like "CJK UNIFIED IDEOGRAPH-#" are spelled out with the codepoint.
+            if let Some(prefix) = name.strip_suffix('#') {
+                match patterns.last_mut() {
+                    Some((_, last, p)) if *p == prefix && *last + 1 == *range.start() => {
+                        *last = *range.end();
+                    }
+                    _ => patterns.push((*range.start(), *range.end(), prefix)),
+                }
+                continue;
+            }
+
+            if range.start() != range.end() {
+                bail!("unexpected range with a name: {name}");
+            }
+            names.push((*range.start(), name));
+        }
+    }
+
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    for &(_, name) in &names {
+        for word in name.split(' ') {
+            *counts.entry(word).or_default() += 1;
+        }
+    }
+    let mut words: Vec<&str> = counts.keys().copied().collect();
+    words.sort_by(|a, b| counts[b].cmp(&counts[a]).then(a.cmp(b)));
+    if words.len() > 0x8000 {
+        bail!("too many distinct words: {}", words.len());
+    }
+    let indices: HashMap<&str, usize> = words.iter().enumerate().map(|(i, &w)| (w, i)).collect();
+
+    let mut word_offsets = Vec::with_capacity(words.len() + 1);
+    let mut offset = 0;
+    for w in &words {
+        word_offsets.push(offset);
+        offset += w.len();
+    }
+    word_offsets.push(offset);
+
+    let mut runs: Vec<(usize, usize, usize)> = Vec::new();
+    let mut data = Vec::new();
+    for &(cp, name) in &names {
+        match runs.last_mut() {
+            Some((first, count, _)) if *first + *count == cp && *count < MAX_RUN_LEN => {
+                *count += 1;
+            }
+            _ => runs.push((cp, 1, data.len())),
+        }
+
+        data.push(name.split(' ').count() as u8);
+        for word in name.split(' ') {
+            let i = indices[word];
+            if i < 0x80 {
+                data.push(i as u8);
+            } else {
+                data.push(0x80 | (i >> 8) as u8);
+                data.push(i as u8);
+            }
+        }
+    }
+
+    let total_size = offset
+        + word_offsets.len() * 4
+        + runs.len() * 12
+        + patterns.iter().map(|p| 8 + p.2.len()).sum::<usize>()
+        + data.len();
+
+    let mut buf = String::new();
+    _ = writeln!(
+        buf,
+        "// BEGIN: Generated by grapheme-table-gen on {}, from {}, with --lang=rust --names, {} bytes",
+        chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+        description,
+        total_size,
+    );
+
+    buf.push_str("#[rustfmt::skip]\npub static NAME_WORDS: &str = \"");
+    let mut line_len = usize::MAX;
+    for w in &words {
+        if line_len > 100 {
+            // Line continuations skip the newline and the indentation that follows.
+            buf.push_str("\\\n    ");
+            line_len = 0;
+        }
+        buf.push_str(w);
+        line_len += w.len();
+    }
+    buf.push_str("\";\n");
+
+    _ = write!(
+        buf,
+        "#[rustfmt::skip]\npub static NAME_WORD_OFFSETS: [u32; {}] = [",
+        word_offsets.len()
+    );
+    for (i, o) in word_offsets.iter().enumerate() {
+        if i % 16 == 0 {
+            buf.push_str("\n   ");
+        }
+        _ = write!(buf, " {o},");
+    }
+    buf.push_str("\n];\n");
+
+    _ = write!(
+        buf,
+        "#[rustfmt::skip]\npub static NAME_RUNS: [(u32, u32, u32); {}] = [",
+        runs.len()
+    );
+    for (i, (cp, count, offset)) in runs.iter().enumerate() {
+        if i % 4 == 0 {
+            buf.push_str("\n   ");
+        }
+        _ = write!(buf, " (0x{cp:04x}, {count}, {offset}),");
+    }
+    buf.push_str("\n];\n");
+
+    _ = writeln!(
+        buf,
+        "#[rustfmt::skip]\npub static NAME_PATTERNS: [(u32, u32, &str); {}] = [",
+        patterns.len()
+    );
+    for (first, last, prefix) in &patterns {
+        _ = writeln!(buf, "    (0x{first:04x}, 0x{last:04x}, \"{prefix}\"),");
+    }
+    buf.push_str("];\n");
+
+    _ = write!(buf, "#[rustfmt::skip]\npub static NAMES: [u8; {}] = [", data.len());
+    for (i, b) in data.iter().enumerate() {
+        if i % 32 == 0 {
+            buf.push_str("\n   ");
+        }
+        _ = write!(buf, " 0x{b:02x},");
+    }
+    buf.push_str("\n];\n");
+
+    buf.push_str("// END: Generated by grapheme-table-gen\n");
+    Ok(buf)
+}